@@ -1,14 +1,12 @@
 extern crate rtforth;
 mod vm;
 
-use rtforth::core::Core;
+use rtforth::core::{Core, Termination};
 use rtforth::output::Output;
-use std::process;
 use vm::VM;
 
 fn main() {
     let mut vm = VM::new(0x100);
-    vm.add_primitive("bye", bye);
 
     vm.set_source(
         "
@@ -25,11 +23,5 @@ fn main() {
 
     let main = vm.find("main").unwrap();
     vm.execute_word(main);
-    vm.run();
-}
-
-/// Terminate process.
-fn bye(vm: &mut VM) {
-    vm.flush_output();
-    process::exit(0);
+    assert_eq!(vm.run(), Termination::Bye);
 }
@@ -1,25 +1,30 @@
-extern crate hibitset;
 extern crate rtforth;
 
-use self::hibitset::BitSet;
+use rtforth::builder::VmBuilder;
+#[cfg(feature = "code")]
+use rtforth::code::Code;
 use rtforth::core::{Control, Core, ForwardReferences, Stack, State, Wordlist};
 use rtforth::env::Environment;
 use rtforth::exception::Exception;
-use rtforth::facility::Facility;
-use rtforth::file_access::FileAccess;
+use rtforth::facility::{Alarms, Facility};
+use rtforth::file_access::{FileAccess, FileStorage, Transcript};
 use rtforth::float::Float;
-use rtforth::loader::{HasLoader, Source};
+use rtforth::fsm::Fsm;
+#[cfg(feature = "gcode")]
+use rtforth::gcode::Gcode;
+use rtforth::image::ProcessImage;
+#[cfg(feature = "jit")]
+use rtforth::jit::Jit;
+use rtforth::loader::HasLoader;
 use rtforth::memory::DataSpace;
+#[cfg(feature = "memory-allocation")]
+use rtforth::memory_allocation::{Heap, MemoryAllocation};
 use rtforth::output::Output;
 use rtforth::tools::Tools;
 use rtforth::units::Units;
 use rtforth::NUM_TASKS;
-use std::fs::File;
 use std::time::Instant;
 
-const BUFFER_SIZE: usize = 0x400;
-const LABEL_COUNT: u32 = 1000;
-
 /// Task
 ///
 /// Each task has its own input buffer but shares the
@@ -32,9 +37,7 @@ pub struct Task {
     c_stk: Stack<Control>,
     f_stk: Stack<f64>,
     inbuf: Option<String>,
-    files: Vec<Option<File>>,
-    sources: Vec<Option<Source>>,
-    lines: Vec<Option<String>>,
+    files: FileStorage,
 }
 
 impl Task {
@@ -48,16 +51,14 @@ impl Task {
             c_stk: Stack::new(Control::Canary),
             f_stk: Stack::new(1.234567890),
             inbuf: None,
-            files: Vec::new(),
-            sources: Vec::new(),
-            lines: Vec::new(),
+            files: FileStorage::new(),
         }
     }
 
     /// Create a task with input buffer.
-    pub fn new_terminal() -> Task {
+    pub fn new_terminal(inbuf: String) -> Task {
         let mut task = Task::new_background();
-        task.inbuf = Some(String::with_capacity(BUFFER_SIZE));
+        task.inbuf = Some(inbuf);
         task
     }
 }
@@ -75,21 +76,25 @@ pub struct VM {
     hldbuf: String,
     references: ForwardReferences,
     now: Instant,
-    forward_bitset: BitSet,
-    resolved_bitset: BitSet,
-    labels: Vec<usize>,
+    alarms: Alarms,
+    transcript: Transcript,
+    #[cfg(feature = "memory-allocation")]
+    heap: Heap,
 }
 
 impl VM {
     /// Create a VM with data space size specified by `data_pages`.
     pub fn new(data_pages: usize) -> VM {
-        let mut labels = Vec::with_capacity(LABEL_COUNT as _);
-        labels.resize(LABEL_COUNT as _, 0);
+        VM::with_builder(&VmBuilder::new().data_pages(data_pages))
+    }
+
+    /// Create a VM configured by `builder`.
+    pub fn with_builder(builder: &VmBuilder) -> VM {
         let mut vm = VM {
             current_task: 0,
             tasks: [
                 // Only operator task has its own input buffer.
-                Task::new_terminal(),
+                Task::new_terminal(builder.input_buffer()),
                 Task::new_background(),
                 Task::new_background(),
                 Task::new_background(),
@@ -97,29 +102,19 @@ impl VM {
             ],
             last_error: None,
             handler: 0,
-            wordlist: Wordlist::with_capacity(1000),
-            data_space: DataSpace::new(data_pages),
+            wordlist: builder.wordlist(),
+            data_space: builder.data_space(),
             tkn: Some(String::with_capacity(64)),
-            outbuf: Some(String::with_capacity(128)),
+            outbuf: Some(builder.output_buffer()),
             hldbuf: String::with_capacity(128),
             references: ForwardReferences::new(),
             now: Instant::now(),
-            forward_bitset: BitSet::with_capacity(LABEL_COUNT),
-            resolved_bitset: BitSet::with_capacity(LABEL_COUNT),
-            labels,
+            alarms: Alarms::new(),
+            transcript: Transcript::new(),
+            #[cfg(feature = "memory-allocation")]
+            heap: builder.heap(),
         };
-        vm.add_core();
-        vm.add_output();
-        vm.add_tools();
-        vm.add_environment();
-        vm.add_facility();
-        vm.add_float();
-        vm.add_units();
-        vm.add_file_access();
-        vm.add_loader();
-
-        vm.load_core_fth();
-
+        builder.install(&mut vm);
         vm
     }
 }
@@ -166,24 +161,6 @@ impl Core for VM {
     fn set_input_buffer(&mut self, buffer: String) {
         *self.input_buffer() = Some(buffer);
     }
-    fn files(&self) -> &Vec<Option<File>> {
-        &self.tasks[self.current_task].files
-    }
-    fn files_mut(&mut self) -> &mut Vec<Option<File>> {
-        &mut self.tasks[self.current_task].files
-    }
-    fn sources(&self) -> &Vec<Option<Source>> {
-        &self.tasks[self.current_task].sources
-    }
-    fn sources_mut(&mut self) -> &mut Vec<Option<Source>> {
-        &mut self.tasks[self.current_task].sources
-    }
-    fn lines(&self) -> &Vec<Option<String>> {
-        &self.tasks[self.current_task].lines
-    }
-    fn lines_mut(&mut self) -> &mut Vec<Option<String>> {
-        &mut self.tasks[self.current_task].lines
-    }
     fn last_token(&mut self) -> &mut Option<String> {
         &mut self.tkn
     }
@@ -214,7 +191,13 @@ impl Core for VM {
     fn references(&mut self) -> &mut ForwardReferences {
         &mut self.references
     }
-    fn system_time_ns(&self) -> u64 {
+    fn transcription(&self) -> &Transcript {
+        &self.transcript
+    }
+    fn transcription_mut(&mut self) -> &mut Transcript {
+        &mut self.transcript
+    }
+    fn monotonic_ns(&self) -> u64 {
         let elapsed = self.now.elapsed();
         elapsed.as_nanos() as _
     }
@@ -242,31 +225,47 @@ impl Core for VM {
             // Do nothing.
         }
     }
-    fn forward_bitset(&self) -> &BitSet {
-        &self.forward_bitset
+}
+
+#[cfg(feature = "code")]
+impl Code for VM {}
+impl Environment for VM {}
+impl Facility for VM {
+    fn alarms(&self) -> &Alarms {
+        &self.alarms
     }
-    fn forward_bitset_mut(&mut self) -> &mut BitSet {
-        &mut self.forward_bitset
+    fn alarms_mut(&mut self) -> &mut Alarms {
+        &mut self.alarms
     }
-    fn resolved_bitset(&self) -> &BitSet {
-        &self.resolved_bitset
+}
+impl Float for VM {}
+impl Fsm for VM {}
+impl ProcessImage for VM {}
+#[cfg(feature = "jit")]
+impl Jit for VM {}
+#[cfg(feature = "gcode")]
+impl Gcode for VM {}
+#[cfg(feature = "memory-allocation")]
+impl MemoryAllocation for VM {
+    fn heap(&self) -> &Heap {
+        &self.heap
+    }
+    fn heap_mut(&mut self) -> &mut Heap {
+        &mut self.heap
     }
-    fn resolved_bitset_mut(&mut self) -> &mut BitSet {
-        &mut self.resolved_bitset
+}
+impl Units for VM {}
+impl FileAccess for VM {
+    fn file_storage(&self) -> &FileStorage {
+        &self.tasks[self.current_task].files
     }
-    fn labels(&self) -> &Vec<usize> {
-        &self.labels
+    fn file_storage_mut(&mut self) -> &mut FileStorage {
+        &mut self.tasks[self.current_task].files
     }
-    fn labels_mut(&mut self) -> &mut Vec<usize> {
-        &mut self.labels
+    fn task_file_storage(&self, i: usize) -> Option<&FileStorage> {
+        self.tasks.get(i).map(|task| &task.files)
     }
 }
-
-impl Environment for VM {}
-impl Facility for VM {}
-impl Float for VM {}
-impl Units for VM {}
-impl FileAccess for VM {}
 impl HasLoader for VM {}
 impl Output for VM {}
 impl Tools for VM {}
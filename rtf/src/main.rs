@@ -1,37 +1,52 @@
 extern crate crossterm;
+#[cfg(feature = "gui")]
 extern crate dioxus;
+#[cfg(feature = "gui")]
 extern crate dioxus_tui;
 extern crate directories;
 extern crate getopts;
 extern crate rtforth;
+extern crate signal_hook;
 extern crate unicode_width;
 
+mod session;
 mod term;
+#[cfg(feature = "gui")]
 mod ui;
 
 use getopts::Options;
-use rtforth::core::{Control, Core, ForwardReferences, Stack, State, Wordlist};
+use rtforth::builder::VmBuilder;
+#[cfg(feature = "code")]
+use rtforth::code::Code;
+use rtforth::core::{Control, Core, ForwardReferences, Stack, State, Termination, Wordlist};
 use rtforth::env::Environment;
 use rtforth::exception::Exception;
-use rtforth::facility::Facility;
-use rtforth::file_access::FileAccess;
+use rtforth::facility::{Alarms, Facility};
+use rtforth::file_access::{FileAccess, FileStorage, Transcript};
 use rtforth::float::Float;
-use rtforth::hibitset::BitSet;
-use rtforth::loader::{HasLoader, Source};
-use rtforth::memory::DataSpace;
+use rtforth::fsm::Fsm;
+#[cfg(feature = "gcode")]
+use rtforth::gcode::Gcode;
+use rtforth::image::ProcessImage;
+#[cfg(feature = "jit")]
+use rtforth::jit::Jit;
+use rtforth::loader::HasLoader;
+use rtforth::memory::{DataSpace, DATA_SPACE_RESERVE};
+use rtforth::memory_allocation::{Heap, MemoryAllocation};
 use rtforth::output::Output;
-use rtforth::tools::Tools;
+use rtforth::tools::{diff_digests, parse_digest, Tools, WordChange};
 use rtforth::units::Units;
 use rtforth::NUM_TASKS;
+use session::Session;
+use signal_hook::consts::SIGINT;
 use std::env;
 use std::fmt::Write;
-use std::fs::File;
-use std::time::Instant;
+use std::fs;
+use std::mem;
+use std::process;
+use std::time::{Duration, Instant};
 use term::Term;
 
-const BUFFER_SIZE: usize = 0x400;
-const LABEL_COUNT: u32 = 1000;
-
 /// Task
 ///
 /// Each task has its own input buffer but shares the
@@ -44,9 +59,7 @@ pub struct Task {
     c_stk: Stack<Control>,
     f_stk: Stack<f64>,
     inbuf: Option<String>,
-    files: Vec<Option<File>>,
-    sources: Vec<Option<Source>>,
-    lines: Vec<Option<String>>,
+    files: FileStorage,
 }
 
 impl Task {
@@ -60,16 +73,14 @@ impl Task {
             c_stk: Stack::new(Control::Default),
             f_stk: Stack::new(1.234567890),
             inbuf: None,
-            files: Vec::new(),
-            sources: Vec::new(),
-            lines: Vec::new(),
+            files: FileStorage::new(),
         }
     }
 
     /// Create a task with input buffer.
-    pub fn new_terminal() -> Task {
+    pub fn new_terminal(inbuf: String) -> Task {
         let mut task = Task::new_background();
-        task.inbuf = Some(String::with_capacity(BUFFER_SIZE));
+        task.inbuf = Some(inbuf);
         task
     }
 }
@@ -88,22 +99,33 @@ pub struct VM {
     hldbuf: String,
     references: ForwardReferences,
     now: Instant,
-    forward_bitset: BitSet,
-    resolved_bitset: BitSet,
-    labels: Vec<usize>,
+    alarms: Alarms,
+    transcript: Transcript,
+    heap: Heap,
+    session: Session,
+    #[cfg(feature = "gui")]
+    ui: ui::Ui,
 }
 
 impl VM {
-    /// Create a VM with data space size specified by `data_pages`.
+    /// Create a VM with data space size specified by `data_pages`, able to
+    /// grow up to `DATA_SPACE_RESERVE` bytes.
     pub fn new(data_pages: usize) -> VM {
-        let mut labels = Vec::with_capacity(LABEL_COUNT as _);
-        labels.resize(LABEL_COUNT as _, 0);
+        VM::with_builder(
+            &VmBuilder::new()
+                .data_pages(data_pages)
+                .max_data_capacity(DATA_SPACE_RESERVE),
+        )
+    }
+
+    /// Create a VM configured by `builder`.
+    pub fn with_builder(builder: &VmBuilder) -> VM {
         let mut vm = VM {
             current_task: 0,
             tasks: [
                 // Only the operator task is a terminal task
                 // with its own input buffer.
-                Task::new_terminal(),
+                Task::new_terminal(builder.input_buffer()),
                 Task::new_background(),
                 Task::new_background(),
                 Task::new_background(),
@@ -112,30 +134,35 @@ impl VM {
             term: Term::new(),
             last_error: None,
             handler: 0,
-            wordlist: Wordlist::with_capacity(1000),
-            data_space: DataSpace::new(data_pages),
+            wordlist: builder.wordlist(),
+            data_space: builder.data_space(),
             tkn: Some(String::with_capacity(64)),
-            outbuf: Some(String::with_capacity(128)),
+            outbuf: Some(builder.output_buffer()),
             hldbuf: String::with_capacity(128),
             references: ForwardReferences::new(),
             now: Instant::now(),
-            forward_bitset: BitSet::with_capacity(LABEL_COUNT),
-            resolved_bitset: BitSet::with_capacity(LABEL_COUNT),
-            labels,
+            alarms: Alarms::new(),
+            transcript: Transcript::new(),
+            heap: builder.heap(),
+            session: Session::new(),
+            #[cfg(feature = "gui")]
+            ui: ui::Ui::new(),
         };
-        vm.add_core();
-        vm.add_output();
-        vm.add_tools();
-        vm.add_environment();
-        vm.add_facility();
-        vm.add_float();
-        vm.add_units();
-        vm.add_file_access();
-        vm.add_loader();
-        vm.add_primitive("receive", receive);
-        vm.add_primitive("ui", launch_ui);
-
-        vm.load_core_fth();
+        builder.add_word_sets(&mut vm);
+        vm.wordlist_mut().set_terminal(Box::new(term::KeyInput));
+        // Ctrl-C interrupts the running program instead of rtf.
+        let _ = signal_hook::flag::register(SIGINT, vm.wordlist().interrupt_flag());
+        vm.add_primitive("(receive)", receive);
+        #[cfg(feature = "gui")]
+        {
+            vm.add_primitive("ui-open", ui_open);
+            vm.add_primitive("ui-close", ui_close);
+            vm.add_primitive("ui-open?", ui_open_q);
+            vm.add_primitive("ui-eval", ui_eval);
+            vm.alarms_mut()
+                .set_annunciator(Box::new(ui::BannerAnnunciator));
+        }
+        builder.load(&mut vm);
 
         let rtf_fth = include_str!("../rtf.fth");
         vm.load_str(rtf_fth);
@@ -144,9 +171,22 @@ impl VM {
         }
 
         vm.flush_output();
+        let words = vm.wordlist().len();
+        vm.session.start(words);
 
         vm
     }
+
+    /// True if `session-save` is on.
+    fn session_save(&mut self) -> bool {
+        match self.find("session-save") {
+            Some(xt) => {
+                let dfa = self.wordlist()[xt].dfa();
+                unsafe { *(dfa as *const isize) != 0 }
+            }
+            None => false,
+        }
+    }
 }
 
 impl Core for VM {
@@ -191,24 +231,6 @@ impl Core for VM {
     fn set_input_buffer(&mut self, buffer: String) {
         *self.input_buffer() = Some(buffer);
     }
-    fn files(&self) -> &Vec<Option<File>> {
-        &self.tasks[self.current_task].files
-    }
-    fn files_mut(&mut self) -> &mut Vec<Option<File>> {
-        &mut self.tasks[self.current_task].files
-    }
-    fn sources(&self) -> &Vec<Option<Source>> {
-        &self.tasks[self.current_task].sources
-    }
-    fn sources_mut(&mut self) -> &mut Vec<Option<Source>> {
-        &mut self.tasks[self.current_task].sources
-    }
-    fn lines(&self) -> &Vec<Option<String>> {
-        &self.tasks[self.current_task].lines
-    }
-    fn lines_mut(&mut self) -> &mut Vec<Option<String>> {
-        &mut self.tasks[self.current_task].lines
-    }
     fn last_token(&mut self) -> &mut Option<String> {
         &mut self.tkn
     }
@@ -239,7 +261,13 @@ impl Core for VM {
     fn references(&mut self) -> &mut ForwardReferences {
         &mut self.references
     }
-    fn system_time_ns(&self) -> u64 {
+    fn transcription(&self) -> &Transcript {
+        &self.transcript
+    }
+    fn transcription_mut(&mut self) -> &mut Transcript {
+        &mut self.transcript
+    }
+    fn monotonic_ns(&self) -> u64 {
         let elapsed = self.now.elapsed();
         elapsed.as_nanos() as _
     }
@@ -268,43 +296,75 @@ impl Core for VM {
             // Do nothing.
         }
     }
-    fn forward_bitset(&self) -> &BitSet {
-        &self.forward_bitset
-    }
-    fn forward_bitset_mut(&mut self) -> &mut BitSet {
-        &mut self.forward_bitset
-    }
-    fn resolved_bitset(&self) -> &BitSet {
-        &self.resolved_bitset
-    }
-    fn resolved_bitset_mut(&mut self) -> &mut BitSet {
-        &mut self.resolved_bitset
-    }
-    fn labels(&self) -> &Vec<usize> {
-        &self.labels
-    }
-    fn labels_mut(&mut self) -> &mut Vec<usize> {
-        &mut self.labels
-    }
 }
 
+#[cfg(feature = "code")]
+impl Code for VM {}
 impl Environment for VM {}
-impl Facility for VM {}
+impl Facility for VM {
+    fn alarms(&self) -> &Alarms {
+        &self.alarms
+    }
+    fn alarms_mut(&mut self) -> &mut Alarms {
+        &mut self.alarms
+    }
+}
 impl Float for VM {}
+impl Fsm for VM {}
+impl ProcessImage for VM {}
+#[cfg(feature = "jit")]
+impl Jit for VM {}
+#[cfg(feature = "gcode")]
+impl Gcode for VM {}
+impl MemoryAllocation for VM {
+    fn heap(&self) -> &Heap {
+        &self.heap
+    }
+    fn heap_mut(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+}
 impl Units for VM {}
 impl HasLoader for VM {}
 impl Output for VM {}
 impl Tools for VM {}
-impl FileAccess for VM {}
+impl FileAccess for VM {
+    fn file_storage(&self) -> &FileStorage {
+        &self.tasks[self.current_task].files
+    }
+    fn file_storage_mut(&mut self) -> &mut FileStorage {
+        &mut self.tasks[self.current_task].files
+    }
+    fn task_file_storage(&self, i: usize) -> Option<&FileStorage> {
+        self.tasks.get(i).map(|task| &task.files)
+    }
+}
 
 fn main() {
-    let vm = &mut VM::new(1024);
+    if start() != Termination::Bye {
+        process::exit(1);
+    }
+}
 
+/// Start rtForth, return after the VM is dropped and the terminal restored.
+fn start() -> Termination {
     let args: Vec<_> = env::args().collect();
+    if args.get(1).map(|a| a.as_str()) == Some("image-diff") {
+        return image_diff(&args[2..]);
+    }
     let program = args[0].clone();
     let mut opts = Options::new();
     opts.optflag("h", "help", "print help menu");
     opts.optflag("v", "version", "print version number");
+    opts.optopt(
+        "p",
+        "pages",
+        &format!(
+            "data space size in pages, default ${} or {}",
+            PAGES_VAR, DATA_PAGES
+        ),
+        "N",
+    );
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
@@ -314,9 +374,22 @@ fn main() {
     };
     if matches.opt_present("h") {
         print_usage(&program, opts);
+        return Termination::Bye;
     } else if matches.opt_present("v") {
         print_version();
-    } else if !matches.free.is_empty() {
+        return Termination::Bye;
+    }
+    let pages = match data_pages(matches.opt_str("p")) {
+        Ok(pages) => pages,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            return Termination::Completed;
+        }
+    };
+    let vm = &mut VM::new(pages);
+    vm.set_banner(&version());
+
+    if !matches.free.is_empty() {
         for word in matches.free {
             match vm.input_buffer().take() {
                 Some(mut buf) => {
@@ -327,25 +400,68 @@ fn main() {
                 None => { /* Unreachable */ }
             }
         }
-        repl(vm);
+        repl(vm)
     } else {
-        print_version();
+        vm.evaluate_str(".banner flush-output");
         println!("Type 'bye' or press Ctrl-D to exit.\r");
-        repl(vm);
+        repl(vm)
+    }
+}
+
+/// Default size of data space in pages, see `data_pages`.
+const DATA_PAGES: usize = 1024;
+
+/// Environment variable with the size of data space in pages.
+const PAGES_VAR: &str = "RTF_PAGES";
+
+/// Longest wait of `(receive)` for keys before other tasks run.
+const RECEIVE_POLL: Duration = Duration::from_millis(10);
+
+/// Size of data space in pages, from option `--pages`, else environment
+/// variable `RTF_PAGES`, else `DATA_PAGES`. It can still be extended by
+/// `grow-data-space` at run time.
+fn data_pages(option: Option<String>) -> Result<usize, String> {
+    let (value, origin) = match option {
+        Some(v) => (v, "--pages".to_string()),
+        None => match env::var(PAGES_VAR) {
+            Ok(v) => (v, PAGES_VAR.to_string()),
+            Err(_) => return Ok(DATA_PAGES),
+        },
+    };
+    match value.trim().parse::<usize>() {
+        Ok(pages) if pages > 0 => Ok(pages),
+        _ => Err(format!("Invalid number of pages in {}: {}", origin, value)),
     }
 }
 
+fn version() -> String {
+    format!(
+        "rtForth v{}, Copyright (C) 2022 Mapacode Inc.\r",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
 fn print_version() {
-    let version = env!("CARGO_PKG_VERSION");
-    println!("rtForth v{}, Copyright (C) 2022 Mapacode Inc.\r", version);
+    println!("{}", version());
 }
 
+/// Wait up to `RECEIVE_POLL` for the line being entered at the terminal,
+/// true once it is complete and made the input. `receive` pauses between
+/// calls, so that other tasks run while the operator is typing.
+/// `(receive) ( -- flag )`
 fn receive(vm: &mut VM) {
-    let line = vm.term.read_line();
+    let line = vm.term.poll_line(RECEIVE_POLL);
     match line {
-        Ok(line) => {
+        Ok(Some(line)) => {
+            let words = vm.wordlist().len();
+            let compiling = vm.state().is_compiling;
+            vm.session.record(words, compiling, line.clone());
             vm.set_source(&line);
         }
+        Ok(None) => {
+            vm.s_stack().push(0);
+            return;
+        }
         Err(term::Error::Eof) => {
             vm.bye();
         }
@@ -356,21 +472,88 @@ fn receive(vm: &mut VM) {
             None => {}
         },
     }
+    vm.s_stack().push(-1);
 }
 
-fn launch_ui(vm: &mut VM) {
-    ui::launch_ui();
+/// Open the UI in its own thread, the VM going on with its tasks.
+/// `ui-open ( -- )`
+#[cfg(feature = "gui")]
+fn ui_open(vm: &mut VM) {
+    vm.ui.open();
+}
+
+/// Close the UI and restore the terminal. `ui-close ( -- )`
+#[cfg(feature = "gui")]
+fn ui_close(vm: &mut VM) {
+    vm.ui.close();
+}
+
+/// True if the UI is open. `ui-open? ( -- flag )`
+#[cfg(feature = "gui")]
+fn ui_open_q(vm: &mut VM) {
+    let open = vm.ui.is_open();
+    vm.s_stack().push(if open { -1 } else { 0 });
+}
+
+/// Evaluate the commands sent by the UI, for example in a task looping
+/// `begin ui-eval pause again`. `ui-eval ( -- )`
+#[cfg(feature = "gui")]
+fn ui_eval(vm: &mut VM) {
+    while let Some(command) = vm.ui.next_command() {
+        vm.evaluate_str(&command);
+        if vm.last_error().is_some() {
+            break;
+        }
+    }
 }
 
 #[inline(never)]
-fn repl(vm: &mut VM) {
+fn repl(vm: &mut VM) -> Termination {
     let cold = vm.find("COLD").expect("COlD");
     vm.execute_word(cold);
-    vm.run();
+    let termination = vm.run();
     vm.flush_output();
+    if vm.session_save() {
+        let mut session = mem::replace(&mut vm.session, Session::new());
+        if let Some(path) = session.save(vm) {
+            println!("Session saved to {}\r", path);
+        }
+    }
+    termination
 }
 
 fn print_usage(program: &str, opts: Options) {
-    let brief = format!("Usage: {} [forth words] [options]", program);
+    let brief = format!(
+        "Usage: {0} [forth words] [options]\n       {0} image-diff OLD NEW",
+        program
+    );
     print!("{}", opts.usage(&brief));
 }
+
+/// `rtf image-diff OLD NEW`: print the words removed (`-`), added (`+`)
+/// and changed (`~`) between the dictionary digests in files OLD and NEW,
+/// saved by `save-digest`.
+fn image_diff(args: &[String]) -> Termination {
+    if args.len() != 2 {
+        eprintln!("Usage: rtf image-diff OLD NEW");
+        return Termination::Completed;
+    }
+    let mut digests = Vec::new();
+    for path in args {
+        match fs::read_to_string(path) {
+            Ok(text) => digests.push(parse_digest(&text)),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                return Termination::Completed;
+            }
+        }
+    }
+    for change in diff_digests(&digests[0], &digests[1]) {
+        match change {
+            WordChange::Removed(name) => println!("- {}", name),
+            WordChange::Added(name) => println!("+ {}", name),
+            WordChange::Changed(name) => println!("~ {}", name),
+        }
+    }
+    Termination::Bye
+}
@@ -0,0 +1,146 @@
+//! Interactive definitions of a session, saved on exit by `session-save on`.
+
+use rtforth::core::{Core, Operand};
+use std::fmt::Write;
+use std::fs;
+use std::slice;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Input line which defined words, or was entered while compiling.
+struct Entry {
+    /// Word count before the line was evaluated.
+    start: usize,
+    /// Word count after the line was evaluated.
+    end: usize,
+    text: String,
+}
+
+/// Lines entered at the terminal which defined words.
+pub struct Session {
+    /// Word count when the session started.
+    start: usize,
+    entries: Vec<Entry>,
+    /// Line being evaluated, with the word count and the compilation state
+    /// before.
+    pending: Option<(usize, bool, String)>,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session {
+            start: 0,
+            entries: Vec::new(),
+            pending: None,
+        }
+    }
+
+    /// Start the session with `words` words defined.
+    pub fn start(&mut self, words: usize) {
+        self.start = words;
+        self.entries.clear();
+        self.pending = None;
+    }
+
+    /// Keep the line evaluated last if it defined words or took part in a
+    /// definition, forget the lines whose words have been forgotten, then
+    /// remember `line` about to be evaluated.
+    pub fn record(&mut self, words: usize, compiling: bool, line: String) {
+        self.settle(words, compiling);
+        self.pending = Some((words, compiling, line));
+    }
+
+    fn settle(&mut self, words: usize, compiling: bool) {
+        if let Some((start, was_compiling, text)) = self.pending.take() {
+            if words > start || was_compiling || compiling {
+                self.entries.push(Entry {
+                    start,
+                    end: words,
+                    text,
+                });
+            }
+        }
+        // Lines entered while compiling a word still existing are kept
+        // with the line beginning the definition.
+        self.entries
+            .retain(|e| e.start < words || (e.start == words && e.end == words));
+        for e in &mut self.entries {
+            e.end = e.end.min(words);
+        }
+    }
+
+    /// Source of the words defined in the session and still existing.
+    ///
+    /// The lines which defined them are reproduced as entered. Words not
+    /// defined at the terminal, such as by other tasks, are reconstructed
+    /// from their compiled code where it has no control flow, and listed
+    /// in comments otherwise.
+    pub fn source<T: Core>(&mut self, vm: &mut T) -> String {
+        let words = vm.wordlist().len();
+        self.settle(words, false);
+        let mut source = String::new();
+        for e in &self.entries {
+            source.push_str(&e.text);
+            source.push('\n');
+        }
+        for xt in self.start..words {
+            let entered = self.entries.iter().any(|e| e.start <= xt && xt < e.end);
+            if !entered && !vm.wordlist()[xt].is_hidden() {
+                source.push_str(&decompile(vm, xt));
+            }
+        }
+        source
+    }
+
+    /// Write the source of the session to a timestamped file in the
+    /// current directory, returning its path. Nothing is written if no
+    /// word has been defined.
+    pub fn save<T: Core>(&mut self, vm: &mut T) -> Option<String> {
+        let source = self.source(vm);
+        if source.is_empty() {
+            return None;
+        }
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let path = format!("rtf-session-{}.fth", secs);
+        match fs::write(&path, source) {
+            Ok(_) => Some(path),
+            Err(_) => None,
+        }
+    }
+}
+
+/// Source of colon definition `xt` from its compiled code, or a comment
+/// if it cannot be reconstructed.
+fn decompile<T: Core>(vm: &mut T, xt: usize) -> String {
+    let name = vm.word_name(xt);
+    let code = match vm.decode(xt) {
+        Some(code) => code,
+        None => return format!("\\ {} not restored: not a colon definition\n", name),
+    };
+    let mut source = format!(": {}", name);
+    let body = match code.last() {
+        Some(i) if i.name == "exit" => &code[..code.len() - 1],
+        _ => &code[..],
+    };
+    for i in body {
+        match i.operand {
+            Operand::None => write!(source, " {}", i.name).unwrap(),
+            Operand::Literal(n) => write!(source, " {}", n).unwrap(),
+            Operand::FLiteral(f) => write!(source, " {:e}", f).unwrap(),
+            Operand::Str(addr, len) => {
+                let bytes = unsafe { slice::from_raw_parts(addr as *const u8, len) };
+                write!(source, " s\" {}\"", String::from_utf8_lossy(bytes)).unwrap();
+            }
+            Operand::Branch(_) | Operand::JumpTable(_) => {
+                return format!("\\ {} not restored: control flow\n", name);
+            }
+        }
+    }
+    source.push_str(" ;\n");
+    if vm.wordlist()[xt].is_immediate() {
+        source.push_str("immediate\n");
+    }
+    source
+}
@@ -1,13 +1,15 @@
 use crossterm::{
     cursor::{self, MoveTo},
-    event::{read, Event, KeyCode, KeyModifiers},
+    event::{self, poll, read, Event, KeyCode, KeyModifiers},
     queue,
     terminal::{self, Clear, ClearType},
 };
 use directories::ProjectDirs;
+use rtforth::facility::{Key, KeyEvent, Terminal};
 use std::{
     fs,
     io::{self, stdout, BufRead, LineWriter, Write},
+    time::Duration,
 };
 use unicode_width::UnicodeWidthStr;
 
@@ -20,6 +22,9 @@ pub enum Error {
 pub struct Term {
     history: Vec<String>,
     buffer: String,
+    /// Index in the history and cursor of the line being edited, `None`
+    /// if no line is begun.
+    editing: Option<(usize, usize)>,
 }
 
 impl Term {
@@ -51,17 +56,44 @@ impl Term {
         };
         let _ = history.drain(0..to_drain);
         let buffer = String::with_capacity(128);
-        Term { history, buffer }
+        Term {
+            history,
+            buffer,
+            editing: None,
+        }
     }
 
-    pub fn read_line(&mut self) -> Result<String, Error> {
+    /// Line entered at the terminal, `None` if not complete after waiting
+    /// up to `timeout` for keys. The line being edited is kept until the
+    /// next call, so that other tasks can run in between.
+    pub fn poll_line(&mut self, timeout: Duration) -> Result<Option<String>, Error> {
+        // Raw mode is off between calls and may have been turned off by
+        // `raw-mode-off`.
+        let _ = terminal::enable_raw_mode();
         let mut done = false;
         let mut stdout = stdout();
-        self.history.push(String::new());
-        self.buffer.clear();
-        let mut h = self.history.len() - 1;
-        let mut x = 0;
+        let (mut h, mut x) = match self.editing.take() {
+            Some(editing) => editing,
+            None => {
+                self.history.push(String::new());
+                self.buffer.clear();
+                (self.history.len() - 1, 0)
+            }
+        };
+        let mut wait = timeout;
         while !done {
+            match poll(wait) {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.editing = Some((h, x));
+                    // Let Ctrl-C raise SIGINT while other tasks run.
+                    let _ = terminal::disable_raw_mode();
+                    return Ok(None);
+                }
+                Err(_) => return Err(Error::Other),
+            }
+            // Only wait for the first key, then take those pending.
+            wait = Duration::from_secs(0);
             match read() {
                 Ok(ev) => match ev {
                     Event::Key(key) => {
@@ -148,6 +180,8 @@ impl Term {
             queue!(stdout, MoveTo(width as u16, p.1)).unwrap();
             stdout.flush().unwrap();
         }
+        // Let Ctrl-C raise SIGINT while the line is evaluated.
+        let _ = terminal::disable_raw_mode();
         if self.buffer.len() == 0 {
             // Do not keep empty history.
             let _ = self.history.pop();
@@ -156,7 +190,7 @@ impl Term {
             self.history[h].clear();
             self.history[h].push_str(&self.buffer);
         }
-        Ok(self.buffer.clone())
+        Ok(Some(self.buffer.clone()))
     }
 }
 
@@ -193,3 +227,76 @@ impl Drop for Term {
         }
     }
 }
+
+/// Raw key input of the console for `raw-mode-on` and `key-event?`.
+pub struct KeyInput;
+
+impl Terminal for KeyInput {
+    fn set_raw_mode(&mut self, raw: bool) -> bool {
+        if raw {
+            terminal::enable_raw_mode().is_ok()
+        } else {
+            terminal::disable_raw_mode().is_ok()
+        }
+    }
+
+    fn poll_key(&mut self) -> Option<KeyEvent> {
+        while let Ok(true) = poll(Duration::from_secs(0)) {
+            match read() {
+                Ok(Event::Key(key)) => {
+                    if let Some(event) = decode(key) {
+                        return Some(event);
+                    }
+                }
+                Ok(Event::Resize(_, _)) => {
+                    return Some(KeyEvent {
+                        key: Key::Resize,
+                        shift: false,
+                        ctrl: false,
+                        alt: false,
+                    });
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn size(&mut self) -> Option<(usize, usize)> {
+        terminal::size()
+            .ok()
+            .map(|(cols, rows)| (cols as usize, rows as usize))
+    }
+}
+
+/// Key event of rtForth from key event `key` of crossterm.
+fn decode(key: event::KeyEvent) -> Option<KeyEvent> {
+    let (code, shift) = match key.code {
+        KeyCode::Char(c) => (Key::Char(c), false),
+        KeyCode::Left => (Key::Left, false),
+        KeyCode::Right => (Key::Right, false),
+        KeyCode::Up => (Key::Up, false),
+        KeyCode::Down => (Key::Down, false),
+        KeyCode::Home => (Key::Home, false),
+        KeyCode::End => (Key::End, false),
+        KeyCode::PageUp => (Key::PageUp, false),
+        KeyCode::PageDown => (Key::PageDown, false),
+        KeyCode::Insert => (Key::Insert, false),
+        KeyCode::Delete => (Key::Delete, false),
+        KeyCode::Backspace => (Key::Backspace, false),
+        KeyCode::Enter => (Key::Enter, false),
+        KeyCode::Tab => (Key::Tab, false),
+        KeyCode::BackTab => (Key::Tab, true),
+        KeyCode::Esc => (Key::Escape, false),
+        KeyCode::F(n) if (1..=12).contains(&n) => (Key::F(n), false),
+        _ => return None,
+    };
+    Some(KeyEvent {
+        key: code,
+        // Shifted characters are already upper case.
+        shift: shift
+            || (key.modifiers.contains(KeyModifiers::SHIFT) && !matches!(code, Key::Char(_))),
+        ctrl: key.modifiers.contains(KeyModifiers::CONTROL),
+        alt: key.modifiers.contains(KeyModifiers::ALT),
+    })
+}
@@ -1,11 +1,148 @@
+//! Terminal UI running in its own thread, opened by `ui-open` and closed
+//! by `ui-close`, while the VM keeps scheduling its tasks.
+//!
+//! The UI sends Forth commands to the VM over a channel, evaluated by
+//! `ui-eval`. The UI takes over the terminal while it is open.
+//!
+//! `alarm` is shown as a banner in the UI while it is open, see
+//! `BannerAnnunciator`.
+
 use dioxus::prelude::*;
+use dioxus_tui::TuiContext;
+use rtforth::facility::Annunciator;
+use std::io::{self, Write};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Context of the UI running, to close it from the VM.
+static CONTEXT: Mutex<Option<TuiContext>> = Mutex::new(None);
+
+/// Sender of the commands of the UI running.
+static COMMANDS: Mutex<Option<Sender<String>>> = Mutex::new(None);
+
+/// Rerendering of the UI running, for its banner.
+static UPDATE: Mutex<Option<Arc<dyn Fn() + Send + Sync>>> = Mutex::new(None);
+
+/// Last alarm shown by the UI.
+static BANNER: Mutex<Option<String>> = Mutex::new(None);
+
+/// Command sent when the UI is clicked.
+const CLICK_COMMAND: &str = "1 ui-clicks +!";
+
+/// Handle of the UI thread owned by the VM.
+pub struct Ui {
+    thread: Option<JoinHandle<()>>,
+    commands: Option<Receiver<String>>,
+}
+
+impl Ui {
+    pub fn new() -> Ui {
+        Ui {
+            thread: None,
+            commands: None,
+        }
+    }
+
+    /// True if the UI is running, not closed by `close` or by the user
+    /// with Ctrl-C.
+    pub fn is_open(&self) -> bool {
+        match self.thread {
+            Some(ref thread) => !thread.is_finished(),
+            None => false,
+        }
+    }
+
+    /// Start the UI in a new thread, false if it is already open.
+    pub fn open(&mut self) -> bool {
+        if self.is_open() {
+            return false;
+        }
+        self.close();
+        let (tx, rx) = channel();
+        *COMMANDS.lock().unwrap_or_else(|e| e.into_inner()) = Some(tx);
+        self.commands = Some(rx);
+        self.thread = Some(thread::spawn(|| {
+            dioxus_tui::launch(app);
+            *CONTEXT.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            *UPDATE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        }));
+        true
+    }
+
+    /// Close the UI and wait for its thread to end, restoring the
+    /// terminal. Nothing is done if the UI is not open.
+    pub fn close(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            // The context is provided once the UI has rendered.
+            while !thread.is_finished() {
+                if let Some(ref tui) = *CONTEXT.lock().unwrap_or_else(|e| e.into_inner()) {
+                    tui.quit();
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            let _ = thread.join();
+        }
+        *COMMANDS.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Next command sent by the UI, also after it is closed.
+    pub fn next_command(&self) -> Option<String> {
+        self.commands.as_ref().and_then(|rx| rx.try_recv().ok())
+    }
+}
+
+impl Drop for Ui {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
 
-pub fn launch_ui() {
-    dioxus_tui::launch(app);
+/// Show `text` in the banner of the UI, false if the UI is not open.
+fn show_banner(text: String) -> bool {
+    match *UPDATE.lock().unwrap_or_else(|e| e.into_inner()) {
+        Some(ref update) => {
+            *BANNER.lock().unwrap_or_else(|e| e.into_inner()) = Some(text);
+            update();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Annunciator showing alarms in the banner of the UI while it is open,
+/// sounding the console bell otherwise.
+pub struct BannerAnnunciator;
+
+impl Annunciator for BannerAnnunciator {
+    fn beep(&mut self) {
+        print!("\x07");
+        let _ = io::stdout().flush();
+    }
+
+    fn announce(&mut self, level: isize, message: &str) {
+        let text = format!("ALARM {}: {}", level, message);
+        if !show_banner(text.clone()) {
+            print!("\x07{}\r\n", text);
+            let _ = io::stdout().flush();
+        }
+    }
 }
 
 fn app(cx: Scope) -> Element {
+    cx.use_hook(|_| {
+        let tui = cx.consume_context::<TuiContext>();
+        *CONTEXT.lock().unwrap_or_else(|e| e.into_inner()) = tui;
+        *UPDATE.lock().unwrap_or_else(|e| e.into_inner()) = Some(cx.schedule_update());
+    });
     let radius = 0;
+    let banner = BANNER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_default();
 
     cx.render(rsx! {
         div {
@@ -18,8 +155,13 @@ fn app(cx: Scope) -> Element {
             border_width: "thick",
             border_radius: "{radius}px",
             border_color: "#FFFFFF #FFFFFF #FFFFFF #FFFFFF",
+            onclick: move |_| {
+                if let Some(ref tx) = *COMMANDS.lock().unwrap_or_else(|e| e.into_inner()) {
+                    let _ = tx.send(CLICK_COMMAND.to_string());
+                }
+            },
 
-            "{radius}"
+            "{radius} {banner}"
         }
     })
 }
@@ -0,0 +1,613 @@
+//! Configurable construction of virtual machines
+//!
+//! The virtual machine is defined by the embedder. `VmBuilder` creates the
+//! configured parts for the constructor of the virtual machine, then
+//! registers the selected word sets and loads `core.fth`.
+//!
+//! ```text
+//! let builder = VmBuilder::new().data_pages(16).without(WordSet::FileAccess);
+//! let mut vm = VM {
+//!     wordlist: builder.wordlist(),
+//!     data_space: builder.data_space(),
+//!     ...
+//! };
+//! builder.install(&mut vm);
+//! ```
+//!
+//! Note that stack sizes and the number of tasks are fixed at compile time
+//! by `Stack` and `NUM_TASKS`.
+//!
+//! Word sets disabled by cargo features are neither available nor required
+//! from the virtual machine.
+
+#[cfg(feature = "code")]
+use code::Code;
+use core::{OutputOverflow, Wordlist, ERROR_HISTORY_CAPACITY, MAX_FILES, MAX_NESTING};
+use env::Environment;
+#[cfg(feature = "facility")]
+use facility::Facility;
+#[cfg(feature = "file-access")]
+use file_access::FileAccess;
+use float::Float;
+use fsm::Fsm;
+#[cfg(feature = "gcode")]
+use gcode::Gcode;
+use image::ProcessImage;
+#[cfg(feature = "jit")]
+use jit::Jit;
+#[cfg(feature = "loader")]
+use loader::HasLoader;
+use memory::DataSpace;
+#[cfg(feature = "memory-allocation")]
+use memory_allocation::{Heap, MemoryAllocation, HEAP_SIZE};
+use output::Output;
+use std::any;
+use std::sync::Mutex;
+use std::time::Duration;
+use tools::Tools;
+#[cfg(feature = "units")]
+use units::Units;
+
+/// Define trait `$name` which is the word set `$word_set` if `$feature` is
+/// enabled, and is implemented by all types otherwise.
+macro_rules! optional_word_set {
+    ($feature:literal, $name:ident, $word_set:ident) => {
+        #[cfg(feature = $feature)]
+        pub trait $name: $word_set {}
+        #[cfg(feature = $feature)]
+        impl<T: $word_set> $name for T {}
+        #[cfg(not(feature = $feature))]
+        pub trait $name {}
+        #[cfg(not(feature = $feature))]
+        impl<T> $name for T {}
+    };
+}
+
+optional_word_set!("facility", OptionalFacility, Facility);
+optional_word_set!("file-access", OptionalFileAccess, FileAccess);
+optional_word_set!("loader", OptionalLoader, HasLoader);
+optional_word_set!(
+    "memory-allocation",
+    OptionalMemoryAllocation,
+    MemoryAllocation
+);
+optional_word_set!("units", OptionalUnits, Units);
+optional_word_set!("code", OptionalCode, Code);
+optional_word_set!("jit", OptionalJit, Jit);
+optional_word_set!("gcode", OptionalGcode, Gcode);
+
+/// Word sets enabled by cargo features.
+pub trait WordSets:
+    Output
+    + Tools
+    + Environment
+    + Float
+    + Fsm
+    + ProcessImage
+    + OptionalFacility
+    + OptionalFileAccess
+    + OptionalLoader
+    + OptionalMemoryAllocation
+    + OptionalUnits
+    + OptionalCode
+    + OptionalJit
+    + OptionalGcode
+{
+}
+
+impl<T> WordSets for T where
+    T: Output
+        + Tools
+        + Environment
+        + Float
+        + Fsm
+        + ProcessImage
+        + OptionalFacility
+        + OptionalFileAccess
+        + OptionalLoader
+        + OptionalMemoryAllocation
+        + OptionalUnits
+        + OptionalCode
+        + OptionalJit
+        + OptionalGcode
+{
+}
+
+/// Optional word sets.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WordSet {
+    Output,
+    Tools,
+    Environment,
+    #[cfg(feature = "facility")]
+    Facility,
+    Float,
+    Fsm,
+    ProcessImage,
+    #[cfg(feature = "units")]
+    Units,
+    #[cfg(feature = "file-access")]
+    FileAccess,
+    #[cfg(feature = "loader")]
+    Loader,
+    #[cfg(feature = "memory-allocation")]
+    MemoryAllocation,
+    #[cfg(feature = "code")]
+    Code,
+    #[cfg(feature = "jit")]
+    Jit,
+    #[cfg(feature = "gcode")]
+    Gcode,
+}
+
+pub(crate) const ALL_WORD_SETS: &[WordSet] = &[
+    WordSet::Output,
+    WordSet::Tools,
+    WordSet::Environment,
+    #[cfg(feature = "facility")]
+    WordSet::Facility,
+    WordSet::Float,
+    WordSet::Fsm,
+    WordSet::ProcessImage,
+    #[cfg(feature = "units")]
+    WordSet::Units,
+    #[cfg(feature = "file-access")]
+    WordSet::FileAccess,
+    #[cfg(feature = "loader")]
+    WordSet::Loader,
+    #[cfg(feature = "memory-allocation")]
+    WordSet::MemoryAllocation,
+    #[cfg(feature = "code")]
+    WordSet::Code,
+    #[cfg(feature = "jit")]
+    WordSet::Jit,
+    #[cfg(feature = "gcode")]
+    WordSet::Gcode,
+];
+
+impl WordSet {
+    /// True if the word set can be registered upon the first lookup of one
+    /// of its words: it has no Forth part, and `core.fth` does not use it.
+    fn can_be_lazy(self) -> bool {
+        match self {
+            WordSet::Environment | WordSet::Fsm | WordSet::ProcessImage => true,
+            #[cfg(feature = "units")]
+            WordSet::Units => true,
+            #[cfg(feature = "memory-allocation")]
+            WordSet::MemoryAllocation => true,
+            #[cfg(feature = "gcode")]
+            WordSet::Gcode => true,
+            _ => false,
+        }
+    }
+}
+
+/// Names of the words of the word sets which can be registered lazily, by
+/// type of virtual machine, in lower case and sorted. Recorded when a
+/// word set is registered for the first time.
+static LAZY_NAMES: Mutex<Vec<(&'static str, WordSet, Vec<String>)>> = Mutex::new(Vec::new());
+
+/// Configuration of a virtual machine.
+pub struct VmBuilder {
+    data_capacity: usize,
+    max_data_capacity: usize,
+    wordlist_capacity: usize,
+    input_buffer_size: usize,
+    output_buffer_size: usize,
+    output_limit: Option<(usize, OutputOverflow)>,
+    error_history: usize,
+    max_files: usize,
+    max_nesting: usize,
+    source_timeout: Option<Duration>,
+    close_sources_on_reset: bool,
+    floored: bool,
+    strict_tick: bool,
+    strict_math: bool,
+    stack_guards: bool,
+    checked_xts: bool,
+    unlock_pin: Option<String>,
+    #[cfg(feature = "memory-allocation")]
+    heap_size: usize,
+    word_sets: Vec<WordSet>,
+    load_core_fth: bool,
+    core_image: bool,
+    lazy: bool,
+    prompt: Option<String>,
+    banner: Option<String>,
+}
+
+impl VmBuilder {
+    /// Default configuration: 64 KiB data space, all word sets, and
+    /// `core.fth` loaded.
+    pub fn new() -> VmBuilder {
+        VmBuilder {
+            data_capacity: 64 * 1024,
+            max_data_capacity: 0,
+            wordlist_capacity: 1000,
+            input_buffer_size: 0x400,
+            output_buffer_size: 128,
+            output_limit: None,
+            error_history: ERROR_HISTORY_CAPACITY,
+            max_files: MAX_FILES,
+            max_nesting: MAX_NESTING,
+            source_timeout: None,
+            close_sources_on_reset: true,
+            floored: false,
+            strict_tick: false,
+            strict_math: false,
+            stack_guards: true,
+            checked_xts: false,
+            unlock_pin: None,
+            #[cfg(feature = "memory-allocation")]
+            heap_size: HEAP_SIZE,
+            word_sets: ALL_WORD_SETS.to_vec(),
+            load_core_fth: true,
+            core_image: true,
+            lazy: false,
+            prompt: None,
+            banner: None,
+        }
+    }
+
+    /// Size of data space in bytes.
+    pub fn data_capacity(mut self, bytes: usize) -> Self {
+        self.data_capacity = bytes;
+        self
+    }
+
+    /// Size of data space in pages.
+    pub fn data_pages(mut self, pages: usize) -> Self {
+        self.data_capacity = pages * page_size::get();
+        self
+    }
+
+    /// Size in bytes up to which `grow-data-space` can extend data space,
+    /// such as `memory::DATA_SPACE_RESERVE`. On unix this is reserved as
+    /// address space only, elsewhere it is allocated up front. By default
+    /// data space cannot grow.
+    pub fn max_data_capacity(mut self, bytes: usize) -> Self {
+        self.max_data_capacity = bytes;
+        self
+    }
+
+    /// Size in pages up to which `grow-data-space` can extend data space.
+    pub fn max_data_pages(mut self, pages: usize) -> Self {
+        self.max_data_capacity = pages * page_size::get();
+        self
+    }
+
+    /// Initial capacity of word list.
+    pub fn wordlist_capacity(mut self, words: usize) -> Self {
+        self.wordlist_capacity = words;
+        self
+    }
+
+    /// Initial capacity of input buffers.
+    pub fn input_buffer_size(mut self, bytes: usize) -> Self {
+        self.input_buffer_size = bytes;
+        self
+    }
+
+    /// Initial capacity of output buffer.
+    pub fn output_buffer_size(mut self, bytes: usize) -> Self {
+        self.output_buffer_size = bytes;
+        self
+    }
+
+    /// Maximum length of output buffer and what to do with output beyond
+    /// it. No limit by default.
+    pub fn output_limit(mut self, bytes: usize, policy: OutputOverflow) -> Self {
+        self.output_limit = Some((bytes, policy));
+        self
+    }
+
+    /// Number of errors kept in the error history, 0 to disable it.
+    /// `ERROR_HISTORY_CAPACITY` by default.
+    pub fn error_history(mut self, errors: usize) -> Self {
+        self.error_history = errors;
+        self
+    }
+
+    /// Maximum number of files and input sources open by a task.
+    /// `MAX_FILES` by default.
+    pub fn max_files(mut self, files: usize) -> Self {
+        self.max_files = files;
+        self
+    }
+
+    /// Maximum nesting of `execute_xt` and `evaluate` by primitives of a
+    /// task. `MAX_NESTING` by default.
+    pub fn max_nesting(mut self, depth: usize) -> Self {
+        self.max_nesting = depth;
+        self
+    }
+
+    /// Timeout of reading a line from input sources opened from files.
+    /// They wait indefinitely by default. See `source-timeout!`.
+    pub fn source_timeout(mut self, timeout: Duration) -> Self {
+        self.source_timeout = Some(timeout);
+        self
+    }
+
+    /// Close all input sources of a task when it is reset, as after an
+    /// error aborts nested `include`s. On by default.
+    pub fn close_sources_on_reset(mut self, close: bool) -> Self {
+        self.close_sources_on_reset = close;
+        self
+    }
+
+    /// Let `/`, `mod` and `/mod` use floored division instead of symmetric
+    /// division. Off by default.
+    pub fn floored(mut self, floored: bool) -> Self {
+        self.floored = floored;
+        self
+    }
+
+    /// Let `'` and `[']` reject compile-only words, see `strict-tick-on`.
+    /// Off by default.
+    pub fn strict_tick(mut self, strict: bool) -> Self {
+        self.strict_tick = strict;
+        self
+    }
+
+    /// Let `+`, `-` and `*` abort on overflow, see `strict-math-on`. Off by
+    /// default.
+    pub fn strict_math(mut self, strict: bool) -> Self {
+        self.strict_math = strict;
+        self
+    }
+
+    /// Compile `need` and `needs-f` into definitions, see `guards-off`. On
+    /// by default.
+    pub fn stack_guards(mut self, guards: bool) -> Self {
+        self.stack_guards = guards;
+        self
+    }
+
+    /// Let `execute` reject execution tokens of forgotten words, see
+    /// `checked-xts-on`. Off by default.
+    pub fn checked_xts(mut self, checked: bool) -> Self {
+        self.checked_xts = checked;
+        self
+    }
+
+    /// PIN letting `unlock` allow privileged words, see
+    /// `Word::set_privileged`. None by default, privileged words staying
+    /// locked.
+    pub fn unlock_pin(mut self, pin: &str) -> Self {
+        self.unlock_pin = Some(pin.to_string());
+        self
+    }
+
+    /// Size in bytes of the heap of `allocate`, allotted in data space.
+    /// `HEAP_SIZE` by default.
+    #[cfg(feature = "memory-allocation")]
+    pub fn heap_size(mut self, bytes: usize) -> Self {
+        self.heap_size = bytes;
+        self
+    }
+
+    /// Register word set `word_set`.
+    pub fn with(mut self, word_set: WordSet) -> Self {
+        if !self.word_sets.contains(&word_set) {
+            self.word_sets.push(word_set);
+        }
+        self
+    }
+
+    /// Do not register word set `word_set`.
+    ///
+    /// `core.fth` uses words from all word sets, so it should not be loaded
+    /// without them.
+    pub fn without(mut self, word_set: WordSet) -> Self {
+        self.word_sets.retain(|&w| w != word_set);
+        self
+    }
+
+    /// Load `core.fth` or not.
+    pub fn load_core_fth(mut self, load: bool) -> Self {
+        self.load_core_fth = load;
+        self
+    }
+
+    /// Copy the dictionary of `core.fth` loaded by earlier virtual machines
+    /// of the same type instead of evaluating it again, see
+    /// `Core::load_core_image`. On by default.
+    pub fn core_image(mut self, copy: bool) -> Self {
+        self.core_image = copy;
+        self
+    }
+
+    /// Text printed by `.prompt`. Requires `core.fth`.
+    pub fn prompt(mut self, prompt: &str) -> Self {
+        self.prompt = Some(prompt.to_string());
+        self
+    }
+
+    /// Text printed by `.banner`. Requires `core.fth`.
+    pub fn banner(mut self, banner: &str) -> Self {
+        self.banner = Some(banner.to_string());
+        self
+    }
+
+    /// Register the word sets without a Forth part upon the first lookup
+    /// of one of their words instead of when the VM is built, see
+    /// `Wordlist::lazy_word_sets`. Off by default.
+    ///
+    /// Their words are known once a VM of the same type registered them,
+    /// so the first VM of a process still registers them all. Until then,
+    /// `words` does not list them.
+    pub fn lazy(mut self, lazy: bool) -> Self {
+        self.lazy = lazy;
+        self
+    }
+
+    /// True if word set `word_set` is registered.
+    pub fn has(&self, word_set: WordSet) -> bool {
+        self.word_sets.contains(&word_set)
+    }
+
+    pub fn data_space(&self) -> DataSpace {
+        DataSpace::with_reserve(self.data_capacity, self.max_data_capacity)
+    }
+
+    pub fn wordlist<Target>(&self) -> Wordlist<Target> {
+        let mut wordlist = Wordlist::with_capacity(self.wordlist_capacity);
+        wordlist.errors_mut().set_capacity(self.error_history);
+        wordlist.set_max_files(self.max_files);
+        wordlist.set_max_nesting(self.max_nesting);
+        wordlist.set_source_timeout(self.source_timeout);
+        wordlist.set_close_sources_on_reset(self.close_sources_on_reset);
+        wordlist.set_floored(self.floored);
+        wordlist.set_strict_tick(self.strict_tick);
+        wordlist.set_strict_math(self.strict_math);
+        wordlist.set_stack_guards(self.stack_guards);
+        wordlist.set_checked_xts(self.checked_xts);
+        wordlist.set_unlock_pin(self.unlock_pin.clone());
+        wordlist
+    }
+
+    /// Heap of `allocate` and `free` of `heap_size` bytes.
+    #[cfg(feature = "memory-allocation")]
+    pub fn heap(&self) -> Heap {
+        Heap::new(self.heap_size)
+    }
+
+    pub fn input_buffer(&self) -> String {
+        String::with_capacity(self.input_buffer_size)
+    }
+
+    pub fn output_buffer(&self) -> String {
+        String::with_capacity(self.output_buffer_size)
+    }
+
+    /// Register core words and the selected word sets to `vm`, then load
+    /// `core.fth` and set prompt and banner if configured.
+    ///
+    /// Panics if `core.fth` cannot be loaded.
+    pub fn install<T: WordSets>(&self, vm: &mut T) {
+        self.add_word_sets(vm);
+        self.load(vm);
+    }
+
+    /// Register core words and the selected word sets to `vm`.
+    ///
+    /// Embedders with their own primitives used by `core.fth` add them
+    /// between `add_word_sets` and `load`.
+    pub fn add_word_sets<T: WordSets>(&self, vm: &mut T) {
+        vm.add_core();
+        if let Some((bytes, policy)) = self.output_limit {
+            vm.set_output_limit(bytes, policy);
+        }
+        let mut lazy = Vec::new();
+        {
+            let mut names = LAZY_NAMES.lock().unwrap_or_else(|e| e.into_inner());
+            for &word_set in ALL_WORD_SETS.iter().filter(|w| self.has(**w)) {
+                if !word_set.can_be_lazy() {
+                    add_word_set(vm, word_set);
+                    continue;
+                }
+                let known = names
+                    .iter()
+                    .any(|&(t, w, _)| t == any::type_name::<T>() && w == word_set);
+                if self.lazy && known {
+                    lazy.push(word_set);
+                    continue;
+                }
+                let start = vm.wordlist().len();
+                add_word_set(vm, word_set);
+                if !known {
+                    let mut words: Vec<String> = (start..vm.wordlist().len())
+                        .map(|xt| vm.word_name(xt).to_ascii_lowercase())
+                        .collect();
+                    words.sort();
+                    names.push((any::type_name::<T>(), word_set, words));
+                }
+            }
+        }
+        if !lazy.is_empty() {
+            vm.wordlist_mut()
+                .set_lazy_word_sets(lazy, register_lazily::<T>);
+        }
+    }
+
+    /// Load `core.fth` and set prompt and banner if configured.
+    ///
+    /// Panics if `core.fth` cannot be loaded.
+    pub fn load<T: Output>(&self, vm: &mut T) {
+        if self.load_core_fth {
+            if self.core_image {
+                vm.load_core_image();
+            } else {
+                vm.load_core_fth();
+            }
+            if let Some(ref prompt) = self.prompt {
+                vm.set_prompt(prompt);
+            }
+            if let Some(ref banner) = self.banner {
+                vm.set_banner(banner);
+            }
+        }
+    }
+}
+
+/// Register `word_set` to `vm`.
+fn add_word_set<T: WordSets>(vm: &mut T, word_set: WordSet) {
+    match word_set {
+        WordSet::Output => vm.add_output(),
+        WordSet::Tools => vm.add_tools(),
+        WordSet::Environment => vm.add_environment(),
+        #[cfg(feature = "facility")]
+        WordSet::Facility => vm.add_facility(),
+        WordSet::Float => vm.add_float(),
+        WordSet::Fsm => vm.add_fsm(),
+        WordSet::ProcessImage => vm.add_process_image(),
+        #[cfg(feature = "units")]
+        WordSet::Units => vm.add_units(),
+        #[cfg(feature = "file-access")]
+        WordSet::FileAccess => vm.add_file_access(),
+        #[cfg(feature = "loader")]
+        WordSet::Loader => vm.add_loader(),
+        #[cfg(feature = "memory-allocation")]
+        WordSet::MemoryAllocation => vm.add_memory_allocation(),
+        #[cfg(feature = "code")]
+        WordSet::Code => vm.add_code(),
+        #[cfg(feature = "jit")]
+        WordSet::Jit => vm.add_jit(),
+        #[cfg(feature = "gcode")]
+        WordSet::Gcode => vm.add_gcode(),
+    }
+}
+
+/// Register the word set of `vm` not registered yet which has a word named
+/// `name`, false if there is none.
+///
+/// The word set stays in `Wordlist::lazy_word_sets`, so that it is
+/// registered again if its words are forgotten by a marker.
+fn register_lazily<T: WordSets>(vm: &mut T, name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    let word_set = {
+        let names = LAZY_NAMES.lock().unwrap_or_else(|e| e.into_inner());
+        names
+            .iter()
+            .find(|&&(t, w, ref words)| {
+                t == any::type_name::<T>()
+                    && vm.wordlist().lazy_word_sets().contains(&w)
+                    && words.binary_search(&name).is_ok()
+            })
+            .map(|&(_, w, _)| w)
+    };
+    match word_set {
+        Some(word_set) => {
+            add_word_set(vm, word_set);
+            true
+        }
+        None => false,
+    }
+}
+
+impl Default for VmBuilder {
+    fn default() -> Self {
+        VmBuilder::new()
+    }
+}
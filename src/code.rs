@@ -0,0 +1,810 @@
+//! Machine code words
+//!
+//! `code <name> ... end-code` defines a word whose body is machine code
+//! assembled into a code space outside the data space. The code space is
+//! mapped writable while a definition is being assembled and executable
+//! otherwise, never both, so hosts which do not allow executable mappings
+//! fail `end-code` with `UNSUPPORTED_OPERATION`.
+//!
+//! ```text
+//! code 2+  rax pop,  rcx 2 mov#,  rax rcx add,  rax push,  next,  end-code
+//! ```
+//!
+//! Machine code is called as `extern "C" fn(stack, depth) -> depth`, where
+//! `stack` is the address of the cells of the data stack, the bottom first,
+//! and `depth` the number of items on it. The code returns the new depth.
+//! Pseudo-instructions `pop,`, `push,` and `next,` of the assemblers follow
+//! this convention:
+//!
+//! | Architecture | stack | depth | returned depth | scratch registers |
+//! |--------------|-------|-------|----------------|-------------------|
+//! | x86-64       | rdi   | rsi   | rax            | rax rcx rdx r8-r11 |
+//! | AArch64      | x0    | x1    | x0             | x2-x15            |
+//!
+//! Bytes are assembled with `code-c,`, or with the assembler of cargo
+//! feature `asm-x86-64` or `asm-aarch64`. Enable only the one of the target,
+//! since both use the same mnemonics. Machine code is trusted like Rust
+//! primitives: nothing but the stack depth returned is checked.
+//!
+//! The code space is not reclaimed by markers.
+
+use core::Core;
+use exception::{
+    CONTROL_STRUCTURE_MISMATCH, DICTIONARY_OVERFLOW, INVALID_NUMERIC_ARGUMENT,
+    UNSUPPORTED_OPERATION,
+};
+use memory::Memory;
+use std::mem;
+use std::ptr;
+
+/// Size of the code space in bytes.
+pub const CODE_SPACE_CAPACITY: usize = 64 * 1024;
+
+/// Signature of machine code words.
+pub type CodeFn = extern "C" fn(*mut isize, usize) -> usize;
+
+/// Memory holding the machine code of `code` definitions, mapped upon the
+/// first definition.
+pub struct CodeSpace {
+    inner: *mut u8,
+    cap: usize,
+    len: usize,
+    executable: bool,
+    /// Word being assembled by `code`.
+    assembling: Option<usize>,
+}
+
+impl CodeSpace {
+    pub fn new() -> CodeSpace {
+        CodeSpace {
+            inner: ptr::null_mut(),
+            cap: CODE_SPACE_CAPACITY,
+            len: 0,
+            executable: false,
+            assembling: None,
+        }
+    }
+
+    /// Number of bytes assembled.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// True if the code can be executed, that is, no definition is being
+    /// assembled.
+    pub fn is_executable(&self) -> bool {
+        self.executable
+    }
+
+    /// Machine code assembled, empty if not mapped.
+    pub fn as_slice(&self) -> &[u8] {
+        if self.inner.is_null() {
+            &[]
+        } else {
+            unsafe { std::slice::from_raw_parts(self.inner, self.len) }
+        }
+    }
+
+    /// Entry of machine code at `offset`, `None` if not executable.
+    pub fn entry(&self, offset: usize) -> Option<CodeFn> {
+        if self.executable && offset < self.len {
+            Some(unsafe { mem::transmute::<*mut u8, CodeFn>(self.inner.add(offset)) })
+        } else {
+            None
+        }
+    }
+
+    /// Map the code space writable. Returns false if it cannot be mapped.
+    fn make_writable(&mut self) -> bool {
+        if self.inner.is_null() {
+            self.inner = map(self.cap);
+            self.executable = false;
+            !self.inner.is_null()
+        } else {
+            self.executable = false;
+            protect(self.inner, self.cap, false)
+        }
+    }
+
+    /// Map the code space executable. Returns false if the host does not
+    /// allow it.
+    fn make_executable(&mut self) -> bool {
+        if self.inner.is_null() || !protect(self.inner, self.cap, true) {
+            return false;
+        }
+        flush_instruction_cache(self.inner, self.len);
+        self.executable = true;
+        true
+    }
+
+    /// Add function `bytes` outside of `code` definitions, returning its
+    /// offset. `None` if a definition is being assembled or the code space
+    /// cannot be mapped executable.
+    pub(crate) fn add_function(&mut self, bytes: &[u8]) -> Option<usize> {
+        if self.assembling.is_some() || self.len + bytes.len() > self.cap {
+            return None;
+        }
+        if !self.make_writable() {
+            return None;
+        }
+        let offset = self.len;
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), self.inner.add(offset), bytes.len());
+        }
+        self.len += bytes.len();
+        if self.make_executable() {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    /// Append `bytes` to the definition being assembled.
+    fn append(&mut self, bytes: &[u8]) -> Result<(), ::exception::Exception> {
+        if self.assembling.is_none() || self.executable {
+            Err(CONTROL_STRUCTURE_MISMATCH)
+        } else if self.len + bytes.len() > self.cap {
+            Err(DICTIONARY_OVERFLOW)
+        } else {
+            unsafe {
+                ptr::copy_nonoverlapping(bytes.as_ptr(), self.inner.add(self.len), bytes.len());
+            }
+            self.len += bytes.len();
+            Ok(())
+        }
+    }
+}
+
+impl Default for CodeSpace {
+    fn default() -> Self {
+        CodeSpace::new()
+    }
+}
+
+impl Drop for CodeSpace {
+    fn drop(&mut self) {
+        if !self.inner.is_null() {
+            unmap(self.inner, self.cap);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn map(cap: usize) -> *mut u8 {
+    let p = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            cap,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANON,
+            -1,
+            0,
+        )
+    };
+    if p == libc::MAP_FAILED {
+        ptr::null_mut()
+    } else {
+        p as *mut u8
+    }
+}
+
+#[cfg(unix)]
+fn protect(p: *mut u8, cap: usize, executable: bool) -> bool {
+    let prot = if executable {
+        libc::PROT_READ | libc::PROT_EXEC
+    } else {
+        libc::PROT_READ | libc::PROT_WRITE
+    };
+    unsafe { libc::mprotect(p as *mut libc::c_void, cap, prot) == 0 }
+}
+
+#[cfg(unix)]
+fn unmap(p: *mut u8, cap: usize) {
+    unsafe {
+        libc::munmap(p as *mut libc::c_void, cap);
+    }
+}
+
+#[cfg(not(unix))]
+fn map(_: usize) -> *mut u8 {
+    ptr::null_mut()
+}
+
+#[cfg(not(unix))]
+fn protect(_: *mut u8, _: usize, _: bool) -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+fn unmap(_: *mut u8, _: usize) {}
+
+#[cfg(target_arch = "aarch64")]
+fn flush_instruction_cache(p: *mut u8, len: usize) {
+    extern "C" {
+        fn __clear_cache(start: *mut libc::c_char, end: *mut libc::c_char);
+    }
+    unsafe {
+        __clear_cache(p as *mut libc::c_char, p.add(len) as *mut libc::c_char);
+    }
+}
+
+#[cfg(not(target_arch = "aarch64"))]
+fn flush_instruction_cache(_: *mut u8, _: usize) {}
+
+/// Encoding of x86-64 instructions, 64-bit operands.
+#[cfg(feature = "asm-x86-64")]
+pub mod x86_64 {
+    pub const RAX: u8 = 0;
+    pub const RCX: u8 = 1;
+    pub const RDX: u8 = 2;
+    pub const RSI: u8 = 6;
+    pub const RDI: u8 = 7;
+
+    /// Opcodes of `op r/m64, r64`.
+    pub const MOV: u8 = 0x89;
+    pub const ADD: u8 = 0x01;
+    pub const SUB: u8 = 0x29;
+    pub const AND: u8 = 0x21;
+    pub const OR: u8 = 0x09;
+    pub const XOR: u8 = 0x31;
+
+    fn rex(reg: u8, rm: u8) -> u8 {
+        0x48 | (reg >> 3) << 2 | rm >> 3
+    }
+
+    /// `op dst, src` with opcode `op`.
+    pub fn alu(op: u8, dst: u8, src: u8) -> Vec<u8> {
+        vec![rex(src, dst), op, 0xc0 | (src & 7) << 3 | dst & 7]
+    }
+
+    /// `mov dst, imm64`.
+    pub fn mov_imm(dst: u8, imm: i64) -> Vec<u8> {
+        let mut code = vec![rex(0, dst), 0xb8 | dst & 7];
+        code.extend_from_slice(&imm.to_le_bytes());
+        code
+    }
+
+    /// `neg reg`.
+    pub fn neg(reg: u8) -> Vec<u8> {
+        vec![rex(0, reg), 0xf7, 0xd8 | reg & 7]
+    }
+
+    /// `mov reg, [rdi+rsi*8-8]` then `dec rsi`.
+    pub fn pop(reg: u8) -> Vec<u8> {
+        vec![
+            rex(reg, 0),
+            0x8b,
+            0x44 | (reg & 7) << 3,
+            0xf7,
+            0xf8,
+            0x48,
+            0xff,
+            0xce,
+        ]
+    }
+
+    /// `inc rsi` then `mov [rdi+rsi*8-8], reg`.
+    pub fn push(reg: u8) -> Vec<u8> {
+        vec![
+            0x48,
+            0xff,
+            0xc6,
+            rex(reg, 0),
+            0x89,
+            0x44 | (reg & 7) << 3,
+            0xf7,
+            0xf8,
+        ]
+    }
+
+    /// `mov rax, rsi` then `ret`.
+    pub fn next() -> Vec<u8> {
+        let mut code = alu(MOV, RAX, RSI);
+        code.push(0xc3);
+        code
+    }
+}
+
+/// Encoding of AArch64 instructions, 64-bit operands.
+#[cfg(feature = "asm-aarch64")]
+pub mod aarch64 {
+    /// Opcodes of `op xd, xn, xm` with shifted register.
+    pub const ADD: u32 = 0x8b00_0000;
+    pub const SUB: u32 = 0xcb00_0000;
+    pub const AND: u32 = 0x8a00_0000;
+    pub const ORR: u32 = 0xaa00_0000;
+    pub const EOR: u32 = 0xca00_0000;
+    pub const RET: u32 = 0xd65f_03c0;
+
+    const XZR: u32 = 31;
+
+    /// `op dst, dst, src` with opcode `op`.
+    pub fn alu(op: u32, dst: u8, src: u8) -> u32 {
+        op | (src as u32) << 16 | (dst as u32) << 5 | dst as u32
+    }
+
+    /// `mov dst, src`, alias of `orr dst, xzr, src`.
+    pub fn mov(dst: u8, src: u8) -> u32 {
+        ORR | (src as u32) << 16 | XZR << 5 | dst as u32
+    }
+
+    /// `movz` and three `movk` loading `imm` into `dst`.
+    pub fn mov_imm(dst: u8, imm: i64) -> [u32; 4] {
+        let half = |hw: u32| ((imm as u64 >> (16 * hw)) & 0xffff) as u32;
+        let d = dst as u32;
+        [
+            0xd280_0000 | half(0) << 5 | d,
+            0xf2a0_0000 | half(1) << 5 | d,
+            0xf2c0_0000 | half(2) << 5 | d,
+            0xf2e0_0000 | half(3) << 5 | d,
+        ]
+    }
+
+    /// `neg reg`, alias of `sub reg, xzr, reg`.
+    pub fn neg(reg: u8) -> u32 {
+        SUB | (reg as u32) << 16 | XZR << 5 | reg as u32
+    }
+
+    /// `sub x1, x1, #1` then `ldr reg, [x0, x1, lsl #3]`.
+    pub fn pop(reg: u8) -> [u32; 2] {
+        [0xd100_0421, 0xf861_7800 | reg as u32]
+    }
+
+    /// `str reg, [x0, x1, lsl #3]` then `add x1, x1, #1`.
+    pub fn push(reg: u8) -> [u32; 2] {
+        [0xf821_7800 | reg as u32, 0x9100_0421]
+    }
+
+    /// `mov x0, x1` then `ret`.
+    pub fn next() -> [u32; 2] {
+        [mov(0, 1), RET]
+    }
+
+    /// Little-endian bytes of `code`.
+    pub fn bytes(code: &[u32]) -> Vec<u8> {
+        code.iter().flat_map(|i| i.to_le_bytes()).collect()
+    }
+}
+
+/// Stack effects of the primitives of `Code`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("code", "( \"name\" -- )"),
+    ("end-code", "( -- )"),
+    ("code-c,", "( char -- )"),
+    ("code-here", "( -- u )"),
+    ("mov,", "( dst src -- )"),
+    ("add,", "( dst src -- )"),
+    ("sub,", "( dst src -- )"),
+    ("and,", "( dst src -- )"),
+    ("or,", "( dst src -- )"),
+    ("xor,", "( dst src -- )"),
+    ("mov#,", "( reg n -- )"),
+    ("neg,", "( reg -- )"),
+    ("pop,", "( reg -- )"),
+    ("push,", "( reg -- )"),
+    ("next,", "( -- )"),
+    ("ret,", "( -- )"),
+];
+
+pub trait Code: Core {
+    /// Add machine code words, and the assemblers enabled by cargo
+    /// features.
+    fn add_code(&mut self) {
+        self.add_primitive("code", Code::code);
+        self.add_primitive("end-code", Code::end_code);
+        self.add_primitive("code-c,", Code::code_c_comma);
+        self.add_primitive("code-here", Code::code_here);
+
+        #[cfg(feature = "asm-x86-64")]
+        {
+            for &(name, reg) in &[
+                ("rax", 0),
+                ("rcx", 1),
+                ("rdx", 2),
+                ("r8", 8),
+                ("r9", 9),
+                ("r10", 10),
+                ("r11", 11),
+            ] {
+                self.add_register(name, reg);
+            }
+            self.add_primitive("mov,", Code::x86_mov);
+            self.add_primitive("add,", Code::x86_add);
+            self.add_primitive("sub,", Code::x86_sub);
+            self.add_primitive("and,", Code::x86_and);
+            self.add_primitive("or,", Code::x86_or);
+            self.add_primitive("xor,", Code::x86_xor);
+            self.add_primitive("mov#,", Code::x86_mov_imm);
+            self.add_primitive("neg,", Code::x86_neg);
+            self.add_primitive("pop,", Code::x86_pop);
+            self.add_primitive("push,", Code::x86_push);
+            self.add_primitive("next,", Code::x86_next);
+            self.add_primitive("ret,", Code::x86_ret);
+        }
+
+        #[cfg(feature = "asm-aarch64")]
+        {
+            for reg in 2..16 {
+                self.add_register(&format!("x{}", reg), reg);
+            }
+            self.add_primitive("mov,", Code::a64_mov);
+            self.add_primitive("add,", Code::a64_add);
+            self.add_primitive("sub,", Code::a64_sub);
+            self.add_primitive("and,", Code::a64_and);
+            self.add_primitive("or,", Code::a64_or);
+            self.add_primitive("xor,", Code::a64_xor);
+            self.add_primitive("mov#,", Code::a64_mov_imm);
+            self.add_primitive("neg,", Code::a64_neg);
+            self.add_primitive("pop,", Code::a64_pop);
+            self.add_primitive("push,", Code::a64_push);
+            self.add_primitive("next,", Code::a64_next);
+            self.add_primitive("ret,", Code::a64_ret);
+        }
+        self.declare_effects(STACK_EFFECTS);
+    }
+
+    /// Add constant `name` for register `reg`.
+    fn add_register(&mut self, name: &str, reg: isize) {
+        self.add_primitive_with(name, Core::p_const, Core::compile_const);
+        self.data_space().compile_isize(reg);
+    }
+
+    /// Run-time: ( i*x -- j*x )
+    ///
+    /// Call the machine code of the word being executed.
+    fn p_code(&mut self) {
+        let wp = self.state().word_pointer();
+        let dfa = self.wordlist()[wp].dfa();
+        let offset = unsafe { self.data_space().get_usize(dfa) };
+        match self.wordlist().code_space().entry(offset) {
+            Some(f) => {
+                let stack = self.s_stack();
+                let depth = f(stack.inner.as_mut_ptr(), stack.len() as usize);
+                stack.set_len(depth as u8);
+            }
+            None => self.abort_with(UNSUPPORTED_OPERATION),
+        }
+    }
+
+    /// ( "name" -- )
+    ///
+    /// Begin the machine code definition `name`, ended by `end-code`.
+    fn code(&mut self) {
+        if self.wordlist().code_space().assembling.is_some() {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+            return;
+        }
+        if !self.wordlist_mut().code_space_mut().make_writable() {
+            self.abort_with(UNSUPPORTED_OPERATION);
+            return;
+        }
+        self.define(Code::p_code, Core::compile_word);
+        if self.last_error().is_none() {
+            let offset = self.wordlist().code_space().len();
+            self.data_space().compile_usize(offset);
+            let def = self.wordlist().len() - 1;
+            self.wordlist_mut()[def].set_hidden(true);
+            self.wordlist_mut().code_space_mut().assembling = Some(def);
+        } else {
+            self.wordlist_mut().code_space_mut().make_executable();
+        }
+    }
+
+    /// ( -- )
+    ///
+    /// End the machine code definition begun by `code`.
+    fn end_code(&mut self) {
+        let def = match self.wordlist_mut().code_space_mut().assembling.take() {
+            Some(def) => def,
+            None => {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                return;
+            }
+        };
+        if self.wordlist_mut().code_space_mut().make_executable() {
+            self.wordlist_mut()[def].set_hidden(false);
+        } else {
+            self.abort_with(UNSUPPORTED_OPERATION);
+        }
+    }
+
+    /// Append `bytes` to the machine code definition being assembled.
+    fn assemble(&mut self, bytes: &[u8]) {
+        if let Err(e) = self.wordlist_mut().code_space_mut().append(bytes) {
+            self.abort_with(e);
+        }
+    }
+
+    /// ( char -- )
+    ///
+    /// Append byte `char` to the machine code definition being assembled.
+    fn code_c_comma(&mut self) {
+        let c = self.s_stack().pop() as u8;
+        self.assemble(&[c]);
+    }
+
+    /// ( -- u )
+    ///
+    /// Number of bytes assembled in the code space.
+    fn code_here(&mut self) {
+        let len = self.wordlist().code_space().len();
+        self.s_stack().push(len as isize);
+    }
+
+    /// Register `reg` popped from the data stack, aborting with
+    /// `INVALID_NUMERIC_ARGUMENT` if not below `count`.
+    fn pop_register(&mut self, count: u8) -> Option<u8> {
+        let reg = self.s_stack().pop();
+        if 0 <= reg && reg < count as isize {
+            Some(reg as u8)
+        } else {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+            None
+        }
+    }
+
+    /// Registers `dst` and `src` popped from the data stack.
+    fn pop_registers(&mut self, count: u8) -> Option<(u8, u8)> {
+        let src = self.pop_register(count)?;
+        let dst = self.pop_register(count)?;
+        Some((dst, src))
+    }
+
+    #[cfg(feature = "asm-x86-64")]
+    fn x86_alu(&mut self, op: u8) {
+        if let Some((dst, src)) = self.pop_registers(16) {
+            self.assemble(&x86_64::alu(op, dst, src));
+        }
+    }
+
+    /// ( dst src -- )
+    #[cfg(feature = "asm-x86-64")]
+    fn x86_mov(&mut self) {
+        self.x86_alu(x86_64::MOV);
+    }
+
+    /// ( dst src -- )
+    #[cfg(feature = "asm-x86-64")]
+    fn x86_add(&mut self) {
+        self.x86_alu(x86_64::ADD);
+    }
+
+    /// ( dst src -- )
+    #[cfg(feature = "asm-x86-64")]
+    fn x86_sub(&mut self) {
+        self.x86_alu(x86_64::SUB);
+    }
+
+    /// ( dst src -- )
+    #[cfg(feature = "asm-x86-64")]
+    fn x86_and(&mut self) {
+        self.x86_alu(x86_64::AND);
+    }
+
+    /// ( dst src -- )
+    #[cfg(feature = "asm-x86-64")]
+    fn x86_or(&mut self) {
+        self.x86_alu(x86_64::OR);
+    }
+
+    /// ( dst src -- )
+    #[cfg(feature = "asm-x86-64")]
+    fn x86_xor(&mut self) {
+        self.x86_alu(x86_64::XOR);
+    }
+
+    /// ( reg n -- )
+    #[cfg(feature = "asm-x86-64")]
+    fn x86_mov_imm(&mut self) {
+        let n = self.s_stack().pop();
+        if let Some(reg) = self.pop_register(16) {
+            self.assemble(&x86_64::mov_imm(reg, n as i64));
+        }
+    }
+
+    /// ( reg -- )
+    #[cfg(feature = "asm-x86-64")]
+    fn x86_neg(&mut self) {
+        if let Some(reg) = self.pop_register(16) {
+            self.assemble(&x86_64::neg(reg));
+        }
+    }
+
+    /// ( reg -- )
+    ///
+    /// Pop the top of the data stack into `reg`.
+    #[cfg(feature = "asm-x86-64")]
+    fn x86_pop(&mut self) {
+        if let Some(reg) = self.pop_register(16) {
+            self.assemble(&x86_64::pop(reg));
+        }
+    }
+
+    /// ( reg -- )
+    ///
+    /// Push `reg` onto the data stack.
+    #[cfg(feature = "asm-x86-64")]
+    fn x86_push(&mut self) {
+        if let Some(reg) = self.pop_register(16) {
+            self.assemble(&x86_64::push(reg));
+        }
+    }
+
+    /// ( -- )
+    ///
+    /// Return the depth of the data stack to the caller.
+    #[cfg(feature = "asm-x86-64")]
+    fn x86_next(&mut self) {
+        self.assemble(&x86_64::next());
+    }
+
+    /// ( -- )
+    #[cfg(feature = "asm-x86-64")]
+    fn x86_ret(&mut self) {
+        self.assemble(&[0xc3]);
+    }
+
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_assemble(&mut self, code: &[u32]) {
+        self.assemble(&aarch64::bytes(code));
+    }
+
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_alu(&mut self, op: u32) {
+        if let Some((dst, src)) = self.pop_registers(31) {
+            self.a64_assemble(&[aarch64::alu(op, dst, src)]);
+        }
+    }
+
+    /// ( dst src -- )
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_mov(&mut self) {
+        if let Some((dst, src)) = self.pop_registers(31) {
+            self.a64_assemble(&[aarch64::mov(dst, src)]);
+        }
+    }
+
+    /// ( dst src -- )
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_add(&mut self) {
+        self.a64_alu(aarch64::ADD);
+    }
+
+    /// ( dst src -- )
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_sub(&mut self) {
+        self.a64_alu(aarch64::SUB);
+    }
+
+    /// ( dst src -- )
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_and(&mut self) {
+        self.a64_alu(aarch64::AND);
+    }
+
+    /// ( dst src -- )
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_or(&mut self) {
+        self.a64_alu(aarch64::ORR);
+    }
+
+    /// ( dst src -- )
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_xor(&mut self) {
+        self.a64_alu(aarch64::EOR);
+    }
+
+    /// ( reg n -- )
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_mov_imm(&mut self) {
+        let n = self.s_stack().pop();
+        if let Some(reg) = self.pop_register(31) {
+            self.a64_assemble(&aarch64::mov_imm(reg, n as i64));
+        }
+    }
+
+    /// ( reg -- )
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_neg(&mut self) {
+        if let Some(reg) = self.pop_register(31) {
+            self.a64_assemble(&[aarch64::neg(reg)]);
+        }
+    }
+
+    /// ( reg -- )
+    ///
+    /// Pop the top of the data stack into `reg`.
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_pop(&mut self) {
+        if let Some(reg) = self.pop_register(31) {
+            self.a64_assemble(&aarch64::pop(reg));
+        }
+    }
+
+    /// ( reg -- )
+    ///
+    /// Push `reg` onto the data stack.
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_push(&mut self) {
+        if let Some(reg) = self.pop_register(31) {
+            self.a64_assemble(&aarch64::push(reg));
+        }
+    }
+
+    /// ( -- )
+    ///
+    /// Return the depth of the data stack to the caller.
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_next(&mut self) {
+        self.a64_assemble(&aarch64::next());
+    }
+
+    /// ( -- )
+    #[cfg(feature = "asm-aarch64")]
+    fn a64_ret(&mut self) {
+        self.a64_assemble(&[aarch64::RET]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::Core;
+    use exception::CONTROL_STRUCTURE_MISMATCH;
+    use mock_vm::VM;
+
+    #[test]
+    fn test_code_space() {
+        let vm = &mut VM::new();
+        vm.set_source("195 code-c,");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        vm.clear_error();
+        vm.set_source("code-here  code nothing 195 code-c, end-code  code-here swap -");
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().as_slice(), [1]);
+        vm.set_source("end-code");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        assert!(vm.find("nothing").is_some());
+    }
+
+    #[cfg(all(
+        feature = "asm-x86-64",
+        not(feature = "asm-aarch64"),
+        target_arch = "x86_64",
+        unix
+    ))]
+    #[test]
+    fn test_x86_64_code() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "
+            code 2+  rax pop,  rcx 2 mov#,  rax rcx add,  rax push,  next,  end-code
+            code swap-  rax pop,  rdx pop,  rdx rax sub,  rdx push,  next,  end-code
+            40 2+  10 3 swap-
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [42, 7]);
+        vm.set_source(": x 5 2+ ;  x");
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().as_slice(), [42, 7, 7]);
+    }
+
+    #[cfg(feature = "asm-aarch64")]
+    #[test]
+    fn test_aarch64_encoding() {
+        use super::aarch64;
+        assert_eq!(aarch64::alu(aarch64::ADD, 2, 3), 0x8b03_0042);
+        assert_eq!(aarch64::mov(2, 3), 0xaa03_03e2);
+        assert_eq!(aarch64::neg(4), 0xcb04_03e4);
+        assert_eq!(aarch64::pop(2), [0xd100_0421, 0xf861_7802]);
+        assert_eq!(aarch64::next(), [0xaa01_03e0, 0xd65f_03c0]);
+        assert_eq!(aarch64::mov_imm(5, 0x1234)[0], 0xd282_4685);
+    }
+}
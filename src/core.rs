@@ -3,41 +3,264 @@
 //! This module contains rtForth core words.
 
 extern crate libc;
+use builder::WordSet;
+#[cfg(feature = "code")]
+use code::CodeSpace;
 use exception::{
-    self, Exception, ABORT, CONTROL_STRUCTURE_MISMATCH, DIVISION_BY_ZERO,
-    FLOATING_POINT_STACK_OVERFLOW, FLOATING_POINT_STACK_UNDERFLOW,
-    INTERPRETING_A_COMPILE_ONLY_WORD, INVALID_MEMORY_ADDRESS, INVALID_NUMERIC_ARGUMENT,
-    RETURN_STACK_OVERFLOW, RETURN_STACK_UNDERFLOW, STACK_OVERFLOW, STACK_UNDERFLOW, UNDEFINED_WORD,
-    UNEXPECTED_END_OF_FILE, UNSUPPORTED_OPERATION,
+    self, Exception, ABORT, ABORT_QUOTE, ALLOCATE, CONTROL_STRUCTURE_MISMATCH, DEADLOCK,
+    DIVISION_BY_ZERO, EXCEPTION_IN_SENDING_OR_RECEIVING_A_CHARACTER, FLOATING_POINT_STACK_OVERFLOW,
+    FLOATING_POINT_STACK_UNDERFLOW, INTERPRETING_A_COMPILE_ONLY_WORD, INVALID_FORGET,
+    INVALID_MEMORY_ADDRESS, INVALID_NAME_ARGUMENT, INVALID_NUMERIC_ARGUMENT, INVALID_XT,
+    NESTING_LIMIT, PERMISSION_DENIED, PRIVILEGED_WORD, RESULT_OUT_OF_RANGE, RETURN_STACK_OVERFLOW,
+    RETURN_STACK_UNDERFLOW, STACK_OVERFLOW, STACK_UNDERFLOW, TIMEOUT, UNDEFINED_WORD,
+    UNEXPECTED_END_OF_FILE, UNSUPPORTED_OPERATION, USER_INTERRUPT, WATCHDOG_EXPIRED,
 };
-use hibitset::{BitSet, BitSetLike};
-use loader::Source;
-use memory::{DataSpace, Memory};
+#[cfg(feature = "facility")]
+use facility::Terminal;
+#[cfg(feature = "file-access")]
+use file_access::Transcript;
+use fsm::FsmDefinition;
+use image::{ImageDefinition, ImageSchema};
+#[cfg(feature = "jit")]
+use jit::{self, JitState, NativeCode};
+#[cfg(feature = "loader")]
+use loader::SourceProvider;
+use memory::{DataSpace, Memory, Wait, HIGH_PRIORITY, NORMAL_PRIORITY, SCHEDULE_CAPACITY};
 use parser;
+use poll::PollHandle;
+use std::any;
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, VecDeque};
 use std::fmt::Write;
 use std::fmt::{self, Display};
-use std::fs::File;
 use std::mem;
 use std::ops::{Index, IndexMut};
+use std::ptr;
+use std::slice;
 use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tools::{Memo, TraceRing};
 use {FALSE, NUM_TASKS, TRUE};
 
+/// Names and stack effects of primitives, shared by all virtual machines of
+/// the process, and their addresses.
+static SHARED_NAMES: Mutex<BTreeMap<&'static str, usize>> = Mutex::new(BTreeMap::new());
+
+/// Address of `name` kept in `SHARED_NAMES` as a counted string, like the
+/// names in data space, added if new.
+fn shared_name(name: &str) -> usize {
+    let mut names = SHARED_NAMES.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(&addr) = names.get(name) {
+        return addr;
+    }
+    let cell = mem::size_of::<usize>();
+    let len = name.len();
+    let block = Box::leak(vec![0usize; 1 + len.div_ceil(cell)].into_boxed_slice());
+    block[0] = len;
+    let p = block[1..].as_mut_ptr() as *mut u8;
+    let key = unsafe {
+        ptr::copy_nonoverlapping(name.as_ptr(), p, len);
+        str::from_utf8_unchecked(slice::from_raw_parts(p, len))
+    };
+    let addr = block.as_ptr() as usize;
+    names.insert(key, addr);
+    addr
+}
+
+/// Dictionaries added by `load_core_fth`, kept for the process so that
+/// later virtual machines copy them instead of evaluating `core.fth`
+/// again.
+///
+/// The cells where the compiler stored addresses within the data space
+/// while loading, recorded by `DataSpace::record_addresses`, and the
+/// fields of words holding such addresses are moved to the data space
+/// copied to. Other cells are copied as they are. The image is not used
+/// if loading changed the data space below where it started, or left a
+/// value which could be an address in a cell not recorded, such as one
+/// stored by `,`, since it could not be told from an integer.
+///
+/// Images hold cells in the byte order and width of the host and the
+/// addresses of its actions, so they are kept in memory only and never
+/// written to files.
+static CORE_IMAGES: Mutex<Vec<CoreImage>> = Mutex::new(Vec::new());
+
+/// What `core.fth` is loaded upon: the type of the virtual machine, its
+/// words and data space before loading, and the settings affecting
+/// compilation.
+#[derive(PartialEq, Eq)]
+struct ImageKey {
+    vm: &'static str,
+    words: usize,
+    actions: usize,
+    offset: usize,
+    fold_constants: bool,
+}
+
+/// Word of a `CoreImage`, with its actions as addresses.
+struct ImageWord {
+    is_immediate: bool,
+    is_compile_only: bool,
+    privileged: bool,
+    hidden: bool,
+    shared_name: bool,
+    link: usize,
+    hash: u32,
+    nfa: usize,
+    dfa: usize,
+    doer: usize,
+    effect: usize,
+    action: usize,
+    compilation_semantics: usize,
+    position: SourcePosition,
+}
+
+struct CoreImage {
+    key: ImageKey,
+    /// Start and limit of the data space loaded, `None` if the image is
+    /// not usable.
+    space: Option<(usize, usize)>,
+    /// Data space from where loading started.
+    bytes: Vec<u8>,
+    /// Offsets in `bytes` of the cells holding addresses, recorded while
+    /// loading.
+    cells: Vec<usize>,
+    words: Vec<ImageWord>,
+    buckets: [usize; BUCKET_SIZE],
+    last: usize,
+    /// Messages of the exceptions allocated by `new-exception`.
+    user_exceptions: Vec<String>,
+}
+
+/// Key of the core image for `vm` as it is now.
+fn core_image_key<T: Core>(vm: &T) -> ImageKey {
+    let wordlist = vm.wordlist();
+    ImageKey {
+        vm: any::type_name::<T>(),
+        words: wordlist.len(),
+        actions: wordlist.words.iter().fold(0usize, |sum, w| {
+            sum.wrapping_add(w.action as usize)
+                .wrapping_add(w.compilation_semantics as usize)
+        }),
+        offset: vm.data_space_const().here() - vm.data_space_const().start(),
+        fold_constants: wordlist.fold_constants(),
+    }
+}
+
+/// Image of what `vm` loaded since it was at `key`, with `below` the data
+/// space before loading and `addresses` the cells where addresses were
+/// stored while loading.
+fn sample_core_image<T: Core>(
+    vm: &T,
+    key: ImageKey,
+    below: &[u8],
+    addresses: &[usize],
+) -> CoreImage {
+    let data_space = vm.data_space_const();
+    let start = data_space.start();
+    let limit = data_space.limit();
+    let len = data_space.here() - start - key.offset;
+    let (mut usable, bytes) = unsafe {
+        (
+            slice::from_raw_parts(start as *const u8, key.offset) == below,
+            slice::from_raw_parts((start + key.offset) as *const u8, len).to_vec(),
+        )
+    };
+    let cell = mem::size_of::<usize>();
+    let base = start + key.offset;
+    let mut cells: Vec<usize> = addresses
+        .iter()
+        .filter(|&&pos| base <= pos && pos + cell <= base + len)
+        .map(|&pos| pos - base)
+        .collect();
+    cells.sort_unstable();
+    cells.dedup();
+    for i in 0..len.saturating_sub(cell - 1) {
+        let x = unsafe { ptr::read_unaligned(bytes[i..].as_ptr() as *const usize) };
+        let recorded = cells.binary_search(&i).is_ok();
+        if recorded != (start <= x && x <= limit) {
+            usable = false;
+        }
+    }
+    let wordlist = vm.wordlist();
+    CoreImage {
+        space: if usable { Some((start, limit)) } else { None },
+        bytes,
+        cells,
+        words: wordlist.words[key.words..]
+            .iter()
+            .map(|w| w.to_image())
+            .collect(),
+        buckets: wordlist.buckets,
+        last: wordlist.last,
+        user_exceptions: wordlist.user_exceptions.clone(),
+        key,
+    }
+}
+
+/// Copy `image` to `vm`, false if it is not usable or the data space is
+/// too small.
+fn copy_core_image<T: Core>(vm: &mut T, image: &CoreImage) -> bool {
+    let (start, limit) = match image.space {
+        Some(space) => space,
+        None => return false,
+    };
+    let len = image.bytes.len();
+    let at = vm.data_space().here();
+    if at + len > vm.data_space().limit() {
+        return false;
+    }
+    let delta = vm.data_space().start().wrapping_sub(start);
+    vm.data_space().allot(len as isize);
+    unsafe {
+        ptr::copy_nonoverlapping(image.bytes.as_ptr(), at as *mut u8, len);
+        for &i in &image.cells {
+            let p = (at + i) as *mut usize;
+            *p = (*p).wrapping_add(delta);
+        }
+    }
+    let wordlist = vm.wordlist_mut();
+    for w in &image.words {
+        let mut w = unsafe { Word::from_image(w, start, limit, delta) };
+        w.generation = wordlist.generation;
+        wordlist.words.push(w);
+    }
+    wordlist.buckets = image.buckets;
+    wordlist.last = image.last;
+    wordlist.user_exceptions = image.user_exceptions.clone();
+    true
+}
+
 // Word
 pub struct Word<Target> {
     is_immediate: bool,
     is_compile_only: bool,
+    /// True if only executed or compiled from the input after `unlock`.
+    privileged: bool,
     hidden: bool,
+    /// True if the name is in `SHARED_NAMES` instead of data space.
+    shared_name: bool,
     link: usize,
     hash: u32,
     nfa: usize,
     dfa: usize,
     doer: usize,
+    /// Address of the declared stack effect as a counted string, 0 if none.
+    effect: usize,
+    /// `Wordlist::generation` when the word was defined.
+    generation: usize,
     action: fn(&mut Target),
     pub(crate) compilation_semantics: fn(&mut Target, usize),
+    position: SourcePosition,
     // Minimum execution time in [ns]
     pub(crate) min_execution_time: usize,
     // Maximum execution time in [ns]
     pub(crate) max_execution_time: usize,
+    /// Machine code compiled upon the first call of a colon definition.
+    #[cfg(feature = "jit")]
+    pub(crate) native: Option<Box<NativeCode>>,
 }
 
 impl<Target> Word<Target> {
@@ -50,16 +273,23 @@ impl<Target> Word<Target> {
         Word {
             is_immediate: false,
             is_compile_only: false,
+            privileged: false,
             hidden: false,
+            shared_name: false,
             link: 0,
             hash: 0,
             nfa: nfa,
             dfa: dfa,
             doer: 0,
+            effect: 0,
+            generation: 0,
             action: action,
             compilation_semantics: compilation_semantics,
+            position: SourcePosition::default(),
             min_execution_time: 0,
             max_execution_time: 0,
+            #[cfg(feature = "jit")]
+            native: None,
         }
     }
 
@@ -79,6 +309,19 @@ impl<Target> Word<Target> {
         self.is_compile_only = flag;
     }
 
+    /// True if interpreting or compiling the word from the input, or
+    /// performing it by `execute`, `postpone` or `compile,`, aborts with
+    /// `PRIVILEGED_WORD` unless the session is unlocked, see
+    /// `Wordlist::is_unlocked`. Definitions compiled while unlocked still
+    /// call it.
+    pub fn is_privileged(&self) -> bool {
+        self.privileged
+    }
+
+    pub fn set_privileged(&mut self, flag: bool) {
+        self.privileged = flag;
+    }
+
     pub fn is_hidden(&self) -> bool {
         self.hidden
     }
@@ -95,19 +338,245 @@ impl<Target> Word<Target> {
         self.dfa
     }
 
+    /// Address where the word begins in data space: its name, or its data
+    /// field if the name is shared.
+    pub fn start(&self) -> usize {
+        if self.shared_name {
+            self.dfa
+        } else {
+            self.nfa
+        }
+    }
+
     pub fn action(&self) -> fn(&mut Target) {
         self.action
     }
+
+    pub(crate) fn set_action(&mut self, action: fn(&mut Target)) {
+        self.action = action;
+    }
+
+    /// Where the word was defined.
+    pub fn position(&self) -> SourcePosition {
+        self.position
+    }
+
+    fn to_image(&self) -> ImageWord {
+        ImageWord {
+            is_immediate: self.is_immediate,
+            is_compile_only: self.is_compile_only,
+            privileged: self.privileged,
+            hidden: self.hidden,
+            shared_name: self.shared_name,
+            link: self.link,
+            hash: self.hash,
+            nfa: self.nfa,
+            dfa: self.dfa,
+            doer: self.doer,
+            effect: self.effect,
+            action: self.action as usize,
+            compilation_semantics: self.compilation_semantics as usize,
+            position: self.position,
+        }
+    }
+
+    /// Word of `image` taken from the data space from `start` to `limit`,
+    /// with the addresses within it moved by `delta`.
+    ///
+    /// Unsafe because `image` must be taken from a word of `Target`.
+    unsafe fn from_image(
+        image: &ImageWord,
+        start: usize,
+        limit: usize,
+        delta: usize,
+    ) -> Word<Target> {
+        let moved = |addr: usize| {
+            if start <= addr && addr <= limit {
+                addr.wrapping_add(delta)
+            } else {
+                addr
+            }
+        };
+        let mut w = Word::new(
+            mem::transmute::<usize, fn(&mut Target)>(image.action),
+            mem::transmute::<usize, fn(&mut Target, usize)>(image.compilation_semantics),
+            moved(image.nfa),
+            moved(image.dfa),
+        );
+        w.is_immediate = image.is_immediate;
+        w.is_compile_only = image.is_compile_only;
+        w.privileged = image.privileged;
+        w.hidden = image.hidden;
+        w.shared_name = image.shared_name;
+        w.link = image.link;
+        w.hash = image.hash;
+        w.doer = moved(image.doer);
+        w.effect = moved(image.effect);
+        w.position = image.position;
+        w
+    }
+}
+
+/// Line in a source file, see `Wordlist::source_file`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SourcePosition {
+    /// Index of the file from 1, 0 if not from a file.
+    pub file: usize,
+    /// Line number from 1.
+    pub line: usize,
 }
 
 const BUCKET_SIZE: usize = 64;
 
+/// Default maximum number of files and input sources open by a task.
+pub const MAX_FILES: usize = 64;
+
+/// Default maximum nesting of interpretation by primitives, see
+/// `Core::enter_activation`.
+pub const MAX_NESTING: usize = 32;
+
+/// Bits of the word index in an execution token tagged with its
+/// generation, see `Wordlist::tag_xt`.
+const XT_INDEX_BITS: usize = 4 * mem::size_of::<usize>();
+
+/// Number of generations distinguished by tagged execution tokens.
+const XT_GENERATIONS: usize = usize::MAX >> XT_INDEX_BITS;
+
+/// Breakpoint set by `break-if` or `break-count`.
+struct Breakpoint {
+    xt: usize,
+    /// Predicate `( -- flag )`, 0 if unconditional.
+    predicate: usize,
+    /// Number of hits to skip.
+    skip: usize,
+    hits: usize,
+}
+
 /// Wordlist
 pub struct Wordlist<Target> {
     words: Vec<Word<Target>>,
     buckets: [usize; BUCKET_SIZE],
     temp_buckets: [usize; BUCKET_SIZE],
     last: usize,
+    /// True if there are breakpoints, checked before each word executed.
+    has_breakpoints: bool,
+    breakpoints: Vec<Breakpoint>,
+    /// Words executed by `bye`, registered by `at-exit`.
+    at_exit: Vec<usize>,
+    /// Words executed by `reset`.
+    at_reset: Vec<usize>,
+    /// True if `reset` closes the input sources of the task.
+    close_sources_on_reset: bool,
+    /// True if constants are compiled as literals, see `fold-on`.
+    fold_constants: bool,
+    /// True if `'` and `[']` reject compile-only words, see
+    /// `strict-tick-on`.
+    strict_tick: bool,
+    /// True if `+`, `-` and `*` abort on overflow, see `strict-math-on`.
+    strict_math: bool,
+    /// True if `need` and `needs-f` are compiled, see `guards-off`.
+    stack_guards: bool,
+    /// True if execution tokens given to programs are tagged with the
+    /// generation of their words, see `checked-xts-on`.
+    checked_xts: bool,
+    /// PIN of `unlock`, `None` if privileged words cannot be unlocked.
+    unlock_pin: Option<String>,
+    /// True if privileged words may be interpreted and compiled.
+    unlocked: bool,
+    /// Incremented whenever words are forgotten, from 1.
+    generation: usize,
+    /// True if `/`, `mod` and `/mod` round towards negative infinity.
+    floored: bool,
+    /// Word sets registered upon the first lookup of one of their words,
+    /// and the function registering them, see `VmBuilder::lazy`.
+    lazy_word_sets: Vec<WordSet>,
+    register_lazily: Option<fn(&mut Target, &str) -> bool>,
+    /// Alignment of loop starts in compiled code, 0 if not aligned.
+    code_align: usize,
+    /// Canonical paths of loaded source files and the word count when
+    /// loaded, so that files loaded before a marker are forgotten with it.
+    loaded_files: Vec<(String, usize)>,
+    /// Events recorded by `trace!`.
+    trace: TraceRing,
+    /// Hash of the dictionary recorded by `seal-dict`.
+    dict_seal: Option<u64>,
+    /// Word count at the latest `Core::fork` not discarded yet. Markers
+    /// may not forget words below it.
+    forget_floor: usize,
+    /// Messages of the exceptions allocated by `new-exception`, the first
+    /// one of code `USER_EXCEPTIONS`.
+    user_exceptions: Vec<String>,
+    /// Words memoized by `memoize` and `fmemoize`.
+    memos: Vec<Memo<Target>>,
+    output_limit: OutputLimit,
+    /// Raw bytes written by `bemit` and `btype`, followed by the output
+    /// buffer.
+    raw_output: Vec<u8>,
+    errors: ErrorHistory,
+    build_log: BuildLog,
+    /// Canonical paths of all source files ever opened, kept across
+    /// markers so that `SourcePosition`s stay valid.
+    source_files: Vec<String>,
+    /// Maximum number of files and input sources open by a task.
+    max_files: usize,
+    /// Maximum number of activations of a task.
+    max_nesting: usize,
+    /// Timeout of reading a line from the input sources opened from files,
+    /// see `source-timeout!`.
+    source_timeout: Option<Duration>,
+    /// Task, starting from 0, and source id of the file loaded by
+    /// `include-async`.
+    background_load: Option<(usize, usize)>,
+    /// Words declared by `forward` and not yet defined.
+    forwards: Vec<usize>,
+    /// Tasks waiting when the last deadlock was detected.
+    deadlock: Vec<WaitFor>,
+    /// Completions of I/O operations posted by the host.
+    poll: PollHandle,
+    /// Set by the host to interrupt `Core::run`.
+    interrupt: Arc<AtomicBool>,
+    /// System time when `Core::evaluate_with_timeout` times out.
+    deadline: Option<u64>,
+    /// Process images defined by `image:`.
+    images: Vec<ImageSchema>,
+    /// Words executed by `Core::scan`, registered by `scan-phase`.
+    scan_phases: Vec<usize>,
+    /// Period of scans in nanoseconds, 0 if overruns are not detected.
+    scan_period: u64,
+    scan_overruns: usize,
+    /// One-shot timers set by `after`, the earliest deadline on top.
+    timers: BinaryHeap<Reverse<Timer>>,
+    last_timer: usize,
+    timers_overdue: usize,
+    /// Latest dispatch of a timer after its deadline, in nanoseconds.
+    timer_lateness: u64,
+    /// True during `Core::scan`, so that `pause` ends the slice of the
+    /// task instead of switching to another one.
+    scanning: bool,
+    slice_ended: bool,
+    /// Cells of the `retain` variables.
+    #[cfg(feature = "file-access")]
+    retain_region: usize,
+    /// `retain` variables, in the order of their cells in the region.
+    #[cfg(feature = "file-access")]
+    retained: Vec<usize>,
+    /// File set by `retain-file`.
+    #[cfg(feature = "file-access")]
+    retain_file: Option<String>,
+    /// Providers of input sources, in the order of registration.
+    #[cfg(feature = "loader")]
+    source_providers: Vec<Box<dyn SourceProvider>>,
+    /// Machine code of `code` definitions.
+    #[cfg(feature = "code")]
+    code_space: CodeSpace,
+    #[cfg(feature = "jit")]
+    jit: JitState,
+    /// Address of the tables of the G-code word set.
+    #[cfg(feature = "gcode")]
+    gcode_table: usize,
+    /// Terminal of the host for raw key input.
+    #[cfg(feature = "facility")]
+    terminal: Option<Box<dyn Terminal>>,
 }
 
 impl<Target> Wordlist<Target> {
@@ -118,6 +587,69 @@ impl<Target> Wordlist<Target> {
             buckets: [0; BUCKET_SIZE],
             temp_buckets: [0; BUCKET_SIZE],
             last: 0,
+            has_breakpoints: false,
+            breakpoints: Vec::new(),
+            at_exit: Vec::new(),
+            at_reset: Vec::new(),
+            close_sources_on_reset: true,
+            fold_constants: false,
+            strict_tick: false,
+            strict_math: false,
+            stack_guards: true,
+            checked_xts: false,
+            unlock_pin: None,
+            unlocked: false,
+            generation: 1,
+            floored: false,
+            lazy_word_sets: Vec::new(),
+            register_lazily: None,
+            code_align: 0,
+            loaded_files: Vec::new(),
+            trace: TraceRing::new(),
+            dict_seal: None,
+            forget_floor: 0,
+            user_exceptions: Vec::new(),
+            memos: Vec::new(),
+            output_limit: OutputLimit::new(),
+            raw_output: Vec::new(),
+            errors: ErrorHistory::new(),
+            build_log: BuildLog::new(),
+            source_files: Vec::new(),
+            max_files: MAX_FILES,
+            max_nesting: MAX_NESTING,
+            source_timeout: None,
+            background_load: None,
+            forwards: Vec::new(),
+            deadlock: Vec::new(),
+            poll: PollHandle::new(),
+            interrupt: Arc::new(AtomicBool::new(false)),
+            deadline: None,
+            images: Vec::new(),
+            scan_phases: Vec::new(),
+            scan_period: 0,
+            scan_overruns: 0,
+            timers: BinaryHeap::new(),
+            last_timer: 0,
+            timers_overdue: 0,
+            timer_lateness: 0,
+            scanning: false,
+            slice_ended: false,
+            #[cfg(feature = "file-access")]
+            retain_region: 0,
+            #[cfg(feature = "file-access")]
+            retained: Vec::new(),
+            #[cfg(feature = "file-access")]
+            retain_file: None,
+            #[cfg(feature = "loader")]
+            source_providers: Vec::new(),
+            #[cfg(feature = "code")]
+            code_space: CodeSpace::new(),
+            #[cfg(feature = "jit")]
+            jit: JitState::new(),
+            #[cfg(feature = "gcode")]
+            gcode_table: 0,
+            #[cfg(feature = "facility")]
+            terminal: None,
         }
     }
 
@@ -143,6 +675,7 @@ impl<Target> Wordlist<Target> {
     /// Push word `w` into list.
     fn push(&mut self, name: &str, mut w: Word<Target>) {
         w.hash = Self::hash(name);
+        w.generation = self.generation;
         let b = w.hash as usize % BUCKET_SIZE;
         w.link = self.buckets[b];
         self.last = self.words.len();
@@ -150,446 +683,1809 @@ impl<Target> Wordlist<Target> {
         self.words.push(w);
     }
 
+    /// Number of words linked in each hash bucket.
+    pub fn bucket_lengths(&self) -> Vec<usize> {
+        self.buckets
+            .iter()
+            .map(|&first| {
+                let mut len = 0;
+                let mut w = first;
+                while w != 0 && len < self.words.len() {
+                    len += 1;
+                    w = self.words[w].link;
+                }
+                len
+            })
+            .collect()
+    }
+
+    /// Link all words into the buckets of their hashes again, the latest
+    /// defined first.
+    fn relink(&mut self) {
+        self.buckets = [0; BUCKET_SIZE];
+        for w in 1..self.words.len() {
+            let b = self.words[w].hash as usize % BUCKET_SIZE;
+            self.words[w].link = self.buckets[b];
+            self.buckets[b] = w;
+        }
+    }
+
     /// Remove the `i`th word and all words behind it.
     fn truncate(&mut self, i: usize) {
+        if i < self.words.len() {
+            self.generation = self.generation % XT_GENERATIONS + 1;
+        }
         self.words.truncate(i);
         self.last = self.words.len() - 1;
+        self.breakpoints.retain(|b| b.xt < i && b.predicate < i);
+        self.has_breakpoints = !self.breakpoints.is_empty();
+        self.at_exit.retain(|&xt| xt < i);
+        self.at_reset.retain(|&xt| xt < i);
+        self.images.retain(|image| image.xt < i);
+        self.scan_phases.retain(|&xt| xt < i);
+        self.timers.retain(|t| t.0.xt < i);
+        self.memos.retain(|memo| memo.xt < i);
+        #[cfg(feature = "file-access")]
+        self.retained.retain(|&xt| xt < i);
+        self.loaded_files.retain(|&(_, len)| len <= i);
+    }
+
+    /// Release the capacity of the word headers and of the per-word tables
+    /// not used any more, as after markers forgot many words.
+    ///
+    /// Data space is not compacted: it is reclaimed by the markers
+    /// themselves, and the remaining words cannot move because cells hold
+    /// absolute addresses.
+    pub fn shrink_to_fit(&mut self) {
+        self.words.shrink_to_fit();
+        self.breakpoints.shrink_to_fit();
+        self.at_exit.shrink_to_fit();
+        self.at_reset.shrink_to_fit();
+        self.images.shrink_to_fit();
+        self.scan_phases.shrink_to_fit();
+        self.timers.shrink_to_fit();
+        self.memos.shrink_to_fit();
+        #[cfg(feature = "file-access")]
+        self.retained.shrink_to_fit();
+        self.loaded_files.shrink_to_fit();
+    }
+
+    /// Number of word headers allocated, see `shrink_to_fit`.
+    pub fn capacity(&self) -> usize {
+        self.words.capacity()
+    }
+
+    /// Record source file with canonical path `path` as loaded.
+    pub fn add_loaded_file(&mut self, path: &str) {
+        if !self.is_loaded_file(path) {
+            let len = self.words.len();
+            self.loaded_files.push((path.to_string(), len));
+        }
     }
 
-    /// Find execution token of the word to whom the address may belong to.
-    pub fn find_xt(&self, addr: usize) -> Option<usize> {
-        let result = self.words.binary_search_by(|w| w.nfa().cmp(&addr));
-        match result {
-            Ok(xt) => Some(xt),
-            Err(xt) => {
-                if xt == 0 {
-                    None
-                } else {
-                    Some(xt - 1)
-                }
+    /// True if source file with canonical path `path` has been loaded.
+    pub fn is_loaded_file(&self, path: &str) -> bool {
+        self.loaded_files.iter().any(|(p, _)| p == path)
+    }
+
+    /// Index of source file with canonical path `path` for
+    /// `SourcePosition`, registered if new.
+    pub fn source_file_index(&mut self, path: &str) -> usize {
+        match self.source_files.iter().position(|p| p == path) {
+            Some(i) => i + 1,
+            None => {
+                self.source_files.push(path.to_string());
+                self.source_files.len()
             }
         }
     }
-}
 
-impl<Target> Index<usize> for Wordlist<Target> {
-    type Output = Word<Target>;
-    #[inline(always)]
-    fn index(&self, index: usize) -> &Word<Target> {
-        &self.words[index]
+    /// Canonical path of source file `file` of a `SourcePosition`.
+    pub fn source_file(&self, file: usize) -> Option<&str> {
+        if file > 0 {
+            self.source_files.get(file - 1).map(|p| p.as_str())
+        } else {
+            None
+        }
     }
-}
 
-impl<Target> IndexMut<usize> for Wordlist<Target> {
-    #[inline(always)]
-    fn index_mut(&mut self, index: usize) -> &mut Word<Target> {
-        &mut self.words[index]
+    /// Register word `xt` to be executed by `bye`.
+    pub fn at_exit(&mut self, xt: usize) {
+        self.at_exit.push(xt);
     }
-}
 
-pub struct Stack<T: Default> {
-    pub inner: [T; 256],
-    pub len: u8,
-    pub canary: T,
-}
+    /// Register word `xt` to be executed by `reset`, in the task being
+    /// reset.
+    pub fn at_reset(&mut self, xt: usize) {
+        self.at_reset.push(xt);
+    }
 
-impl<T: Default + Copy + PartialEq + Display> Stack<T> {
-    pub fn new(canary: T) -> Self {
-        let mut result = Stack {
-            inner: [T::default(); 256],
-            len: 0,
-            canary: canary,
-        };
-        result.reset();
-        result
+    /// True if `reset`, thus also `quit` after an error, closes all input
+    /// sources of the task, such as those of nested `include`s aborted by
+    /// the error. On by default, embedders managing input sources
+    /// themselves turn it off.
+    pub fn close_sources_on_reset(&self) -> bool {
+        self.close_sources_on_reset
     }
 
-    pub fn reset(&mut self) {
-        self.len = 0;
-        for i in 0..256 {
-            self.inner[i] = self.canary;
-        }
+    pub fn set_close_sources_on_reset(&mut self, close: bool) {
+        self.close_sources_on_reset = close;
     }
 
-    pub fn underflow(&self) -> bool {
-        (self.inner[255] != self.canary) || (self.len > 128)
+    /// True if `/`, `mod` and `/mod` use floored division like `fm/mod`,
+    /// false if they use symmetric division like `sm/rem`, the default.
+    /// Set when the VM is built, see `VmBuilder::floored`.
+    pub fn floored(&self) -> bool {
+        self.floored
     }
 
-    pub fn overflow(&self) -> bool {
-        (self.inner[64] != self.canary) || (self.len > 64 && self.len <= 128)
+    pub fn set_floored(&mut self, floored: bool) {
+        self.floored = floored;
     }
 
-    pub fn push(&mut self, v: T) {
-        let len = self.len.wrapping_add(1);
-        self.len = len;
-        self.inner[len.wrapping_sub(1) as usize] = v;
+    /// Word sets not registered yet, registered by `Core::find` upon the
+    /// first lookup of one of their words.
+    pub fn lazy_word_sets(&self) -> &[WordSet] {
+        &self.lazy_word_sets
     }
 
-    pub fn pop(&mut self) -> T {
-        let result = self.inner[self.len.wrapping_sub(1) as usize];
-        self.len = self.len.wrapping_sub(1);
-        result
+    pub(crate) fn set_lazy_word_sets(
+        &mut self,
+        word_sets: Vec<WordSet>,
+        register: fn(&mut Target, &str) -> bool,
+    ) {
+        self.lazy_word_sets = word_sets;
+        self.register_lazily = Some(register);
     }
 
-    pub fn push2(&mut self, v1: T, v2: T) {
-        let len = self.len.wrapping_add(2);
-        self.len = len;
-        self.inner[self.len.wrapping_sub(2) as usize] = v1;
-        self.inner[self.len.wrapping_sub(1) as usize] = v2;
+    /// True if constants and float constants are compiled into colon
+    /// definitions as literals of their values.
+    pub fn fold_constants(&self) -> bool {
+        self.fold_constants
     }
 
-    pub fn push3(&mut self, v1: T, v2: T, v3: T) {
-        let len = self.len.wrapping_add(3);
-        self.len = len;
-        self.inner[self.len.wrapping_sub(3) as usize] = v1;
-        self.inner[self.len.wrapping_sub(2) as usize] = v2;
-        self.inner[self.len.wrapping_sub(1) as usize] = v3;
+    pub fn set_fold_constants(&mut self, fold: bool) {
+        self.fold_constants = fold;
     }
 
-    pub fn pop2(&mut self) -> (T, T) {
-        let result = (
-            self.inner[self.len.wrapping_sub(2) as usize],
-            self.inner[self.len.wrapping_sub(1) as usize],
-        );
-        self.len = self.len.wrapping_sub(2);
-        result
+    /// True if `'` and `[']` abort with `INTERPRETING_A_COMPILE_ONLY_WORD`
+    /// upon compile-only words instead of returning their execution
+    /// tokens. Off by default.
+    pub fn strict_tick(&self) -> bool {
+        self.strict_tick
     }
 
-    pub fn pop3(&mut self) -> (T, T, T) {
-        let result = (
-            self.inner[self.len.wrapping_sub(3) as usize],
-            self.inner[self.len.wrapping_sub(2) as usize],
-            self.inner[self.len.wrapping_sub(1) as usize],
-        );
-        self.len = self.len.wrapping_sub(3);
-        result
+    pub fn set_strict_tick(&mut self, strict: bool) {
+        self.strict_tick = strict;
     }
 
-    pub fn last(&self) -> Option<T> {
-        Some(self.inner[self.len.wrapping_sub(1) as usize])
+    /// True if `+`, `-` and `*` abort with `RESULT_OUT_OF_RANGE` on
+    /// overflow like `+|`, `-|` and `*|` instead of wrapping around. Off by
+    /// default.
+    pub fn strict_math(&self) -> bool {
+        self.strict_math
     }
 
-    pub fn get(&self, pos: u8) -> Option<T> {
-        Some(self.inner[pos as usize])
+    pub fn set_strict_math(&mut self, strict: bool) {
+        self.strict_math = strict;
     }
 
-    pub fn len(&self) -> u8 {
-        self.len
+    /// True if `need` and `needs-f` are compiled into definitions, false
+    /// to leave them out of release builds. On by default.
+    pub fn stack_guards(&self) -> bool {
+        self.stack_guards
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.len == 0
+    pub fn set_stack_guards(&mut self, guards: bool) {
+        self.stack_guards = guards;
     }
 
-    /// # Safety
-    /// Because the implementer (me) is still learning Rust, it is uncertain if as_slice is safe.
-    pub fn as_slice(&self) -> &[T] {
-        &self.inner[..self.len as usize]
+    /// True if `'` and `[']` tag the execution tokens they return with
+    /// the generation of the word, so that executing one
+    /// whose word has been forgotten by a marker aborts with `INVALID_XT`
+    /// instead of executing the word defined in its place. Off by default.
+    pub fn checked_xts(&self) -> bool {
+        self.checked_xts
     }
-}
 
-impl Index<u8> for Stack<isize> {
-    type Output = isize;
-    #[inline(always)]
-    fn index(&self, index: u8) -> &isize {
-        &self.inner[index as usize]
+    pub fn set_checked_xts(&mut self, checked: bool) {
+        self.checked_xts = checked;
     }
-}
 
-impl IndexMut<u8> for Stack<isize> {
-    #[inline(always)]
-    fn index_mut(&mut self, index: u8) -> &mut isize {
-        &mut self.inner[index as usize]
+    /// PIN which `unlock` expects, `None`, never unlocking, by default.
+    pub fn unlock_pin(&self) -> Option<&str> {
+        self.unlock_pin.as_deref()
     }
-}
 
-impl Index<u8> for Stack<f64> {
-    type Output = f64;
-    #[inline(always)]
-    fn index(&self, index: u8) -> &f64 {
-        &self.inner[index as usize]
+    pub fn set_unlock_pin(&mut self, pin: Option<String>) {
+        self.unlock_pin = pin;
     }
-}
 
-impl IndexMut<u8> for Stack<f64> {
-    #[inline(always)]
-    fn index_mut(&mut self, index: u8) -> &mut f64 {
-        &mut self.inner[index as usize]
+    /// True if words marked by `Word::set_privileged` may be interpreted
+    /// and compiled from the input, set by `unlock` and cleared by `lock`.
+    pub fn is_unlocked(&self) -> bool {
+        self.unlocked
     }
-}
 
-impl Index<u8> for Stack<Control> {
-    type Output = Control;
-    #[inline(always)]
-    fn index(&self, index: u8) -> &Control {
-        &self.inner[index as usize]
+    pub fn set_unlocked(&mut self, unlocked: bool) {
+        self.unlocked = unlocked;
     }
-}
 
-impl fmt::Debug for Stack<isize> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.len == 0 {
+    /// Execution token of word `xt` as given to programs, tagged with the
+    /// generation of the word in the upper bits if `checked_xts`.
+    pub fn tag_xt(&self, xt: usize) -> isize {
+        if self.checked_xts && xt < self.words.len() {
+            (xt | self.words[xt].generation << XT_INDEX_BITS) as isize
         } else {
-            for i in 0..self.len {
-                let v = self[i];
-                match write!(f, "{} ", v) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                }
-            }
+            xt as isize
         }
-        Ok(())
     }
-}
 
-impl fmt::Debug for Stack<f64> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.len == 0 {
-        } else {
-            for i in 0..self.len {
-                let v = self[i];
-                match write!(f, "{:.7} ", v) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                }
-            }
+    /// Word of execution token `xt` given by a program, tagged or not,
+    /// `None` if there is no such word or it is not the word tagged.
+    pub fn untag_xt(&self, xt: isize) -> Option<usize> {
+        let xt = xt as usize;
+        let index = xt & ((1 << XT_INDEX_BITS) - 1);
+        let generation = xt >> XT_INDEX_BITS;
+        match self.words.get(index) {
+            Some(w) if generation == 0 || generation == w.generation => Some(index),
+            _ => None,
         }
-        Ok(())
     }
-}
 
-#[allow(non_snake_case)]
-pub struct ForwardReferences {
-    pub idx_lit: usize,
-    pub idx_flit: usize,
-    pub idx_exit: usize,
-    pub idx_zero_branch: usize,
-    pub idx_branch: usize,
-    pub idx_do: usize,
-    pub idx_qdo: usize,
-    pub idx_loop: usize,
-    pub idx_plus_loop: usize,
-    pub idx_s_quote: usize,
-    pub idx_type: usize,
-    pub idx_over: usize,
-    pub idx_equal: usize,
-    pub idx_drop: usize,
-    pub idx__postpone: usize,
-    pub idx_to_r: usize,
-    pub idx__does: usize,
-}
+    /// Alignment in bytes of the targets of `begin`, `do` and `?do` in
+    /// compiled code, 0 if not aligned. See `code-align`.
+    pub fn code_align(&self) -> usize {
+        self.code_align
+    }
 
-impl ForwardReferences {
-    pub fn new() -> ForwardReferences {
-        ForwardReferences {
-            idx_lit: 0,
-            idx_flit: 0,
-            idx_exit: 0,
-            idx_zero_branch: 0,
-            idx_branch: 0,
-            idx_do: 0,
-            idx_qdo: 0,
-            idx_loop: 0,
-            idx_plus_loop: 0,
-            idx_s_quote: 0,
-            idx_type: 0,
-            idx_over: 0,
-            idx_equal: 0,
-            idx_drop: 0,
-            idx__postpone: 0,
-            idx_to_r: 0,
-            idx__does: 0,
-        }
+    /// Events recorded by `trace!`.
+    pub fn trace(&self) -> &TraceRing {
+        &self.trace
     }
-}
 
-pub struct State {
-    pub is_compiling: bool,
-    pub instruction_pointer: usize,
-    word_pointer: usize,
-    pub aborted_word_pointer: usize,
-    pub source_index: usize,
-    pub source_id: isize,
-}
+    pub fn trace_mut(&mut self) -> &mut TraceRing {
+        &mut self.trace
+    }
 
-impl State {
-    pub fn new() -> State {
-        State {
-            is_compiling: false,
-            instruction_pointer: 0,
-            word_pointer: 0,
-            aborted_word_pointer: 0,
-            source_index: 0,
-            source_id: 0,
+    /// Hash of the dictionary recorded by `seal-dict` and checked by
+    /// `verify-dict`, `None` if never sealed.
+    pub fn dict_seal(&self) -> Option<u64> {
+        self.dict_seal
+    }
+
+    pub fn set_dict_seal(&mut self, hash: Option<u64>) {
+        self.dict_seal = hash;
+    }
+
+    /// Messages of the exceptions allocated by `new-exception`, in the
+    /// order of allocation.
+    pub fn user_exceptions(&self) -> &[String] {
+        &self.user_exceptions
+    }
+
+    /// Allocate an exception with `message`, `None` if no code is left.
+    pub fn new_exception(&mut self, message: &str) -> Option<Exception> {
+        let e = Exception::user(self.user_exceptions.len())?;
+        self.user_exceptions.push(message.to_string());
+        Some(e)
+    }
+
+    /// Description of `e`, the message registered by `new-exception` for
+    /// exceptions in the user range.
+    pub fn exception_description(&self, e: Exception) -> &str {
+        match e.user_index().and_then(|i| self.user_exceptions.get(i)) {
+            Some(message) => message,
+            None => exception::description(e),
         }
     }
 
-    pub fn word_pointer(&self) -> usize {
-        self.word_pointer
+    /// Latest errors, see `errors.`.
+    pub fn errors(&self) -> &ErrorHistory {
+        &self.errors
     }
-}
 
-#[derive(Clone, Copy, PartialEq)]
-pub enum Control {
-    Default,
-    Canary,
-    If(usize),
-    Else(usize),
-    Begin(usize),
-    While(usize),
-    Do(usize, usize),
-    Case,
-    Of(usize),
-    Endof(usize),
+    pub fn errors_mut(&mut self) -> &mut ErrorHistory {
+        &mut self.errors
+    }
+
+    /// Words memoized by `memoize` and `fmemoize`.
+    pub fn memos(&self) -> &[Memo<Target>] {
+        &self.memos
+    }
+
+    pub(crate) fn memos_mut(&mut self) -> &mut Vec<Memo<Target>> {
+        &mut self.memos
+    }
+
+    /// Definitions compiled so far, see `build-manifest`.
+    pub fn build_log(&self) -> &BuildLog {
+        &self.build_log
+    }
+
+    pub fn build_log_mut(&mut self) -> &mut BuildLog {
+        &mut self.build_log
+    }
+
+    /// Maximum number of files and input sources open by a task,
+    /// `MAX_FILES` by default. Beyond it `open-file` and `create-file`
+    /// fail.
+    pub fn max_files(&self) -> usize {
+        self.max_files
+    }
+
+    pub fn set_max_files(&mut self, n: usize) {
+        self.max_files = n;
+    }
+
+    /// Maximum number of nested `execute_xt` and `evaluate` of a task,
+    /// `MAX_NESTING` by default. Beyond it they abort with
+    /// `NESTING_LIMIT`.
+    pub fn max_nesting(&self) -> usize {
+        self.max_nesting
+    }
+
+    pub fn set_max_nesting(&mut self, n: usize) {
+        self.max_nesting = n;
+    }
+
+    /// Timeout of reading a line from the input sources opened from files
+    /// afterwards, `None`, waiting indefinitely, by default. A read timing
+    /// out aborts with `FILE_IO_TIMEOUT`.
+    pub fn source_timeout(&self) -> Option<Duration> {
+        self.source_timeout
+    }
+
+    pub fn set_source_timeout(&mut self, timeout: Option<Duration>) {
+        self.source_timeout = timeout;
+    }
+
+    /// Task, starting from 0, and source id of the file being loaded by
+    /// `include-async`, see `loading?`.
+    pub fn background_load(&self) -> Option<(usize, usize)> {
+        self.background_load
+    }
+
+    pub fn set_background_load(&mut self, load: Option<(usize, usize)>) {
+        self.background_load = load;
+    }
+
+    /// Tasks waiting for semaphores when the last deadlock was detected,
+    /// see `Core::pause`.
+    pub fn deadlock(&self) -> &[WaitFor] {
+        &self.deadlock
+    }
+
+    /// Handle for the host to post completions of I/O operations waited
+    /// for by `io-wait`.
+    pub fn poll_handle(&self) -> PollHandle {
+        self.poll.clone()
+    }
+
+    /// Flag to interrupt a running program, which can be set from another
+    /// thread or a signal handler, for example upon Ctrl-C.
+    ///
+    /// `Core::run` checks it every `INTERRUPT_PERIOD` words, clears it and
+    /// aborts with `USER_INTERRUPT`. Code compiled by `jit` is not
+    /// interrupted.
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    /// Words executed by `Core::scan`, in order.
+    pub fn scan_phases(&self) -> &[usize] {
+        &self.scan_phases
+    }
+
+    /// Number of scans which took longer than the period set by
+    /// `scan-period!`.
+    pub fn scan_overruns(&self) -> usize {
+        self.scan_overruns
+    }
+
+    /// Pending timers set by `after`, the earliest deadline first.
+    pub fn timers(&self) -> Vec<Timer> {
+        let mut timers: Vec<Timer> = self.timers.iter().map(|t| t.0).collect();
+        timers.sort();
+        timers
+    }
+
+    /// Number of timers dispatched more than `TIMER_SLACK` nanoseconds
+    /// after their deadlines.
+    pub fn timers_overdue(&self) -> usize {
+        self.timers_overdue
+    }
+
+    /// Latest dispatch of a timer after its deadline, in nanoseconds.
+    pub fn timer_lateness(&self) -> u64 {
+        self.timer_lateness
+    }
+
+    /// Address of the retention region of `retain` variables.
+    #[cfg(feature = "file-access")]
+    pub fn retain_region(&self) -> usize {
+        self.retain_region
+    }
+
+    #[cfg(feature = "file-access")]
+    pub(crate) fn set_retain_region(&mut self, region: usize) {
+        self.retain_region = region;
+    }
+
+    /// Words defined by `retain`, the `i`th one using the `i`th cell of the
+    /// retention region.
+    #[cfg(feature = "file-access")]
+    pub fn retained(&self) -> &[usize] {
+        &self.retained
+    }
+
+    #[cfg(feature = "file-access")]
+    pub(crate) fn add_retained(&mut self, xt: usize) {
+        self.retained.push(xt);
+    }
+
+    /// File in which the `retain` variables are kept, see `retain-file`.
+    #[cfg(feature = "file-access")]
+    pub fn retain_file(&self) -> Option<&str> {
+        self.retain_file.as_deref()
+    }
+
+    #[cfg(feature = "file-access")]
+    pub fn set_retain_file(&mut self, path: Option<String>) {
+        self.retain_file = path;
+    }
+
+    /// Machine code of the words defined by `code`.
+    #[cfg(feature = "code")]
+    pub fn code_space(&self) -> &CodeSpace {
+        &self.code_space
+    }
+
+    #[cfg(feature = "code")]
+    pub(crate) fn code_space_mut(&mut self) -> &mut CodeSpace {
+        &mut self.code_space
+    }
+
+    /// State of the compiler of colon definitions to machine code.
+    #[cfg(feature = "jit")]
+    pub fn jit(&self) -> &JitState {
+        &self.jit
+    }
+
+    #[cfg(feature = "jit")]
+    pub(crate) fn jit_mut(&mut self) -> &mut JitState {
+        &mut self.jit
+    }
+
+    /// Address of the tables of the G-code word set.
+    #[cfg(feature = "gcode")]
+    pub fn gcode_table(&self) -> usize {
+        self.gcode_table
+    }
+
+    #[cfg(feature = "gcode")]
+    pub(crate) fn set_gcode_table(&mut self, table: usize) {
+        self.gcode_table = table;
+    }
+
+    /// Provide the terminal used by `raw-mode-on` and `key-event?`.
+    #[cfg(feature = "facility")]
+    pub fn set_terminal(&mut self, terminal: Box<dyn Terminal>) {
+        self.terminal = Some(terminal);
+    }
+
+    #[cfg(feature = "facility")]
+    pub(crate) fn terminal_mut(&mut self) -> Option<&mut Box<dyn Terminal>> {
+        self.terminal.as_mut()
+    }
+
+    /// True if breakpoints are set.
+    pub fn has_breakpoints(&self) -> bool {
+        self.has_breakpoints
+    }
+
+    /// Schemas of the process images, in the order of definition.
+    pub fn images(&self) -> &[ImageSchema] {
+        &self.images
+    }
+
+    /// Schema of process image `name`.
+    pub fn image(&self, name: &str) -> Option<&ImageSchema> {
+        self.images
+            .iter()
+            .find(|image| image.name.eq_ignore_ascii_case(name))
+    }
+
+    pub(crate) fn add_image(&mut self, image: ImageSchema) {
+        self.images.push(image);
+    }
+
+    /// Providers of input sources, consulted in order by `included` before
+    /// the file system.
+    #[cfg(feature = "loader")]
+    pub fn source_providers(&self) -> &[Box<dyn SourceProvider>] {
+        &self.source_providers
+    }
+
+    #[cfg(feature = "loader")]
+    pub fn add_source_provider(&mut self, provider: Box<dyn SourceProvider>) {
+        self.source_providers.push(provider);
+    }
+
+    /// Raw bytes written by `bemit` and `btype`, to be written before
+    /// the output buffer.
+    pub fn raw_output(&self) -> &Vec<u8> {
+        &self.raw_output
+    }
+
+    pub fn raw_output_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.raw_output
+    }
+
+    /// Breakpoint on word `xt`, created if not existing.
+    fn breakpoint_mut(&mut self, xt: usize) -> &mut Breakpoint {
+        self.has_breakpoints = true;
+        match self.breakpoints.iter().position(|b| b.xt == xt) {
+            Some(i) => &mut self.breakpoints[i],
+            None => {
+                self.breakpoints.push(Breakpoint {
+                    xt,
+                    predicate: 0,
+                    skip: 0,
+                    hits: 0,
+                });
+                self.breakpoints.last_mut().expect("breakpoint")
+            }
+        }
+    }
+
+    /// Find execution token of the word to whom the address may belong to.
+    pub fn find_xt(&self, addr: usize) -> Option<usize> {
+        let result = self.words.binary_search_by(|w| w.start().cmp(&addr));
+        match result {
+            Ok(xt) => Some(xt),
+            Err(xt) => {
+                if xt == 0 {
+                    None
+                } else {
+                    Some(xt - 1)
+                }
+            }
+        }
+    }
 }
 
-impl Default for Control {
-    fn default() -> Self {
-        Control::Default
+impl<Target> Index<usize> for Wordlist<Target> {
+    type Output = Word<Target>;
+    #[inline(always)]
+    fn index(&self, index: usize) -> &Word<Target> {
+        &self.words[index]
     }
 }
 
-impl Display for Control {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let s = match *self {
-            Control::Default => "Default",
-            Control::Canary => "Canary",
-            Control::If(_) => "If",
-            Control::Else(_) => "Else",
-            Control::Begin(_) => "Begin",
-            Control::While(_) => "While",
-            Control::Do(_, _) => "Do",
-            Control::Case => "Case",
-            Control::Of(_) => "Of",
-            Control::Endof(_) => "Endof",
-        };
-        write!(f, "{}", s)
+impl<Target> IndexMut<usize> for Wordlist<Target> {
+    #[inline(always)]
+    fn index_mut(&mut self, index: usize) -> &mut Word<Target> {
+        &mut self.words[index]
     }
 }
 
-pub trait Core: Sized {
-    // Functions to access VM.
-    fn last_error(&self) -> Option<Exception>;
-    fn set_error(&mut self, e: Option<Exception>);
-    fn handler(&self) -> usize;
-    fn set_handler(&mut self, h: usize);
-    fn data_space(&mut self) -> &mut DataSpace;
-    fn data_space_const(&self) -> &DataSpace;
-    /// Numeric output buffer
-    fn hold_buffer(&mut self) -> &mut String;
-    /// Get `output_buffer`.
-    fn output_buffer(&mut self) -> &mut Option<String>;
-    /// Set `output_buffer` to `Some(buffer)`.
-    fn set_output_buffer(&mut self, buffer: String);
-    /// Input source identifier
-    ///
-    /// > 0: input from source at `self.sources[source_id] and input buffer
-    /// `self.lines\[source_id\]`.
-    /// = 0: input from the default user input buffer.
-    fn source_id(&self) -> isize;
-    /// Get `input_buffer`.
-    fn input_buffer(&mut self) -> &mut Option<String>;
-    /// Set `input_buffer` to `Some(buffer)`.
-    fn set_input_buffer(&mut self, buffer: String);
-    fn files(&self) -> &Vec<Option<File>>;
-    fn files_mut(&mut self) -> &mut Vec<Option<File>>;
-    fn sources(&self) -> &Vec<Option<Source>>;
-    fn sources_mut(&mut self) -> &mut Vec<Option<Source>>;
-    fn lines(&self) -> &Vec<Option<String>>;
-    fn lines_mut(&mut self) -> &mut Vec<Option<String>>;
-    fn last_token(&mut self) -> &mut Option<String>;
-    fn set_last_token(&mut self, buffer: String);
-    fn s_stack(&mut self) -> &mut Stack<isize>;
-    fn r_stack(&mut self) -> &mut Stack<isize>;
-    fn c_stack(&mut self) -> &mut Stack<Control>;
-    fn f_stack(&mut self) -> &mut Stack<f64>;
-    /// Last definition, 0 if last define fails.
-    fn wordlist_mut(&mut self) -> &mut Wordlist<Self>;
-    fn wordlist(&self) -> &Wordlist<Self>;
-    fn state(&mut self) -> &mut State;
-    fn references(&mut self) -> &mut ForwardReferences;
-    fn system_time_ns(&self) -> u64;
-    /// Current task
-    fn current_task(&self) -> usize;
-    /// Set curretn task.
-    ///
-    /// No operation if there is no task `i`.
-    fn set_current_task(&mut self, i: usize);
-    /// Is task `i` awake?
+/// Default number of cells usable in a stack.
+pub const STACK_LIMIT: u8 = 64;
+
+/// Stack of 256 cells indexed with wrapping `u8` arithmetic.
+///
+/// Cells 0 to 127 are the usable part of the stack, and cells 128 to 255
+/// are the guard zone below the bottom, where an underflowing primitive
+/// reads or writes. Depth of the stack and indexes into it are considered
+/// signed, so that cell 255 is at depth -1.
+///
+/// The lowest and the highest depths reached by `push`, `pop` and
+/// indexing are kept as watermarks until `reset`. The stack underflows if
+/// the low watermark is below 0, and overflows if the high watermark is
+/// above `limit`.
+#[derive(Clone)]
+pub struct Stack<T: Default> {
+    pub inner: [T; 256],
+    pub len: u8,
+    low: Cell<i8>,
+    high: Cell<i8>,
+    limit: u8,
+}
+
+impl<T: Default + Copy + PartialEq + Display> Stack<T> {
+    /// Create a stack with all cells set to `fill`.
+    pub fn new(fill: T) -> Self {
+        Stack {
+            inner: [fill; 256],
+            len: 0,
+            low: Cell::new(0),
+            high: Cell::new(0),
+            limit: STACK_LIMIT,
+        }
+    }
+
+    /// Empty the stack and clear the watermarks.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.low.set(0);
+        self.high.set(0);
+    }
+
+    /// Number of usable cells, `STACK_LIMIT` by default.
+    pub fn limit(&self) -> u8 {
+        self.limit
+    }
+
+    /// Set the number of usable cells to `limit`, at most 127.
     ///
-    /// False if there is no task `i`.
-    fn awake(&self, i: usize) -> bool;
-    /// Set awake to `v`.
+    /// Memory-tight targets may lower the limit so that programs are
+    /// checked against the stack depth they can afford.
+    pub fn set_limit(&mut self, limit: u8) {
+        self.limit = limit.min(i8::MAX as u8);
+    }
+
+    /// Record depth `depth` in the watermarks.
+    #[inline(always)]
+    fn mark(&self, depth: u8) {
+        let depth = depth as i8;
+        if depth < self.low.get() {
+            self.low.set(depth);
+        } else if depth > self.high.get() {
+            self.high.set(depth);
+        }
+    }
+
+    /// Lowest and highest depths reached since `reset`.
     ///
-    /// No operation if there is no task `i`.
-    fn set_awake(&mut self, i: usize, v: bool);
-    /// Bitset to check forward declaration of labels.
-    fn forward_bitset(&self) -> &BitSet;
-    /// Mutable bitset to check forward declaration of labels.
-    fn forward_bitset_mut(&mut self) -> &mut BitSet;
-    /// Bitset to check resolved labels.
-    fn resolved_bitset(&self) -> &BitSet;
-    /// Mutable bitset to check resolved labels.
-    fn resolved_bitset_mut(&mut self) -> &mut BitSet;
-    /// Labels to support BASIC-like goto, label, call.
-    fn labels(&self) -> &Vec<usize>;
-    /// Labels to support BASIC-like goto, label, call.
-    fn labels_mut(&mut self) -> &mut Vec<usize>;
+    /// Accessing the cell at `index` counts as reaching depth `index`, so
+    /// that reading the second item of a stack with one item reaches
+    /// depth -1.
+    pub fn watermarks(&self) -> (isize, isize) {
+        (self.low.get() as isize, self.high.get() as isize)
+    }
+
+    /// Number of cells below the bottom reached since `reset`, 0 if none.
+    pub fn underflow_depth(&self) -> usize {
+        let depth = self.low.get().min(self.len as i8);
+        if depth < 0 {
+            -(depth as isize) as usize
+        } else {
+            0
+        }
+    }
 
-    /// Add core primitives to self.
-    fn add_core(&mut self) {
-        self.add_primitive("", Core::noop);
-        self.add_primitive("noop", Core::noop);
-        self.add_compile_only("exit", Core::exit);
-        self.add_compile_only("lit", Core::lit);
-        self.add_compile_only("flit", Core::flit);
-        self.add_compile_only("_s\"", Core::p_s_quote);
-        self.add_compile_only("branch", Core::branch);
-        self.add_compile_only("0branch", Core::zero_branch);
-        self.add_compile_only("_do", Core::_do);
-        self.add_compile_only("_qdo", Core::_qdo);
-        self.add_compile_only("_loop", Core::_loop);
-        self.add_compile_only("_+loop", Core::_plus_loop);
-        self.add_compile_only("unloop", Core::unloop);
-        self.add_compile_only("leave", Core::leave);
-        self.add_compile_only("i", Core::p_i);
-        self.add_compile_only("j", Core::p_j);
-        self.add_compile_only(">r", Core::p_to_r);
-        self.add_compile_only("r>", Core::r_from);
-        self.add_compile_only("r@", Core::r_fetch);
-        self.add_compile_only("2>r", Core::two_to_r);
-        self.add_compile_only("2r>", Core::two_r_from);
-        self.add_compile_only("2r@", Core::two_r_fetch);
-        self.add_compile_only("compile,", Core::compile_comma);
-        self.add_compile_only("_postpone", Core::_postpone);
-        self.add_compile_only("_does", Core::_does);
+    /// Number of cells above `limit` reached since `reset`, 0 if none.
+    pub fn overflow_depth(&self) -> usize {
+        let depth = self.high.get().max(self.len as i8) as isize;
+        if depth > self.limit as isize {
+            (depth - self.limit as isize) as usize
+        } else {
+            0
+        }
+    }
 
-        self.add_primitive("execute", Core::execute);
-        self.add_primitive("dup", Core::dup);
-        self.add_primitive("drop", Core::p_drop);
-        self.add_primitive("swap", Core::swap);
-        self.add_primitive("over", Core::over);
-        self.add_primitive("nip", Core::nip);
-        self.add_primitive("depth", Core::depth);
-        self.add_primitive("?stacks", Core::check_stacks);
-        self.add_primitive("0<", Core::zero_less);
-        self.add_primitive("=", Core::equals);
-        self.add_primitive("<", Core::less_than);
-        self.add_primitive("invert", Core::invert);
-        self.add_primitive("and", Core::and);
-        self.add_primitive("or", Core::or);
-        self.add_primitive("xor", Core::xor);
-        self.add_primitive("lshift", Core::lshift);
-        self.add_primitive("rshift", Core::rshift);
+    pub fn underflow(&self) -> bool {
+        self.underflow_depth() > 0
+    }
+
+    pub fn overflow(&self) -> bool {
+        self.overflow_depth() > 0
+    }
+
+    pub fn push(&mut self, v: T) {
+        let len = self.len.wrapping_add(1);
+        self.len = len;
+        self.mark(len);
+        self.inner[len.wrapping_sub(1) as usize] = v;
+    }
+
+    pub fn pop(&mut self) -> T {
+        let len = self.len.wrapping_sub(1);
+        self.mark(len);
+        let result = self.inner[len as usize];
+        self.len = len;
+        result
+    }
+
+    pub fn push2(&mut self, v1: T, v2: T) {
+        let len = self.len.wrapping_add(2);
+        self.len = len;
+        self.mark(len);
+        self.inner[self.len.wrapping_sub(2) as usize] = v1;
+        self.inner[self.len.wrapping_sub(1) as usize] = v2;
+    }
+
+    pub fn push3(&mut self, v1: T, v2: T, v3: T) {
+        let len = self.len.wrapping_add(3);
+        self.len = len;
+        self.mark(len);
+        self.inner[self.len.wrapping_sub(3) as usize] = v1;
+        self.inner[self.len.wrapping_sub(2) as usize] = v2;
+        self.inner[self.len.wrapping_sub(1) as usize] = v3;
+    }
+
+    pub fn pop2(&mut self) -> (T, T) {
+        let len = self.len.wrapping_sub(2);
+        self.mark(len);
+        let result = (
+            self.inner[len as usize],
+            self.inner[len.wrapping_add(1) as usize],
+        );
+        self.len = len;
+        result
+    }
+
+    pub fn pop3(&mut self) -> (T, T, T) {
+        let len = self.len.wrapping_sub(3);
+        self.mark(len);
+        let result = (
+            self.inner[len as usize],
+            self.inner[len.wrapping_add(1) as usize],
+            self.inner[len.wrapping_add(2) as usize],
+        );
+        self.len = len;
+        result
+    }
+
+    pub fn last(&self) -> Option<T> {
+        Some(self.inner[self.len.wrapping_sub(1) as usize])
+    }
+
+    pub fn get(&self, pos: u8) -> Option<T> {
+        Some(self.inner[pos as usize])
+    }
+
+    pub fn len(&self) -> u8 {
+        self.len
+    }
+
+    /// Set the depth to `len`, as left by machine code operating on
+    /// `inner` directly.
+    pub fn set_len(&mut self, len: u8) {
+        self.len = len;
+        self.mark(len);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of items in the usable part of the stack, 0 if the stack
+    /// has underflowed.
+    fn usable_len(&self) -> usize {
+        if (self.len as i8) < 0 {
+            0
+        } else {
+            self.len as usize
+        }
+    }
+
+    /// Items on the stack, the bottom first.
+    ///
+    /// Empty if the stack has underflowed, because the length of an
+    /// underflowed stack wraps around into the guard zone.
+    pub fn as_slice(&self) -> &[T] {
+        &self.inner[..self.usable_len()]
+    }
+
+    /// Iterate over items on the stack, the bottom first.
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+}
+
+impl<T: Default + Copy + PartialEq + Display> Index<u8> for Stack<T> {
+    type Output = T;
+    #[inline(always)]
+    fn index(&self, index: u8) -> &T {
+        self.mark(index);
+        &self.inner[index as usize]
+    }
+}
+
+impl<T: Default + Copy + PartialEq + Display> IndexMut<u8> for Stack<T> {
+    #[inline(always)]
+    fn index_mut(&mut self, index: u8) -> &mut T {
+        self.mark(index);
+        &mut self.inner[index as usize]
+    }
+}
+
+impl fmt::Debug for Stack<isize> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for v in self.iter() {
+            write!(f, "{} ", v)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Stack<f64> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for v in self.iter() {
+            write!(f, "{:.7} ", v)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Stack<Control> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for Stack<Control> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.iter() {
+            write!(f, "{} ", c)?;
+        }
+        Ok(())
+    }
+}
+
+#[allow(non_snake_case)]
+pub struct ForwardReferences {
+    pub idx_lit: usize,
+    pub idx_flit: usize,
+    pub idx_exit: usize,
+    pub idx_zero_branch: usize,
+    pub idx_branch: usize,
+    pub idx_do: usize,
+    pub idx_qdo: usize,
+    pub idx_loop: usize,
+    pub idx_plus_loop: usize,
+    pub idx_s_quote: usize,
+    pub idx_type: usize,
+    pub idx_over: usize,
+    pub idx_equal: usize,
+    pub idx_drop: usize,
+    pub idx_two_drop: usize,
+    pub idx__of_quote: usize,
+    pub idx__postpone: usize,
+    pub idx_to_r: usize,
+    pub idx__does: usize,
+    pub idx__jump_table: usize,
+    pub idx_noop: usize,
+    pub idx_store: usize,
+    pub idx_f_store: usize,
+}
+
+impl ForwardReferences {
+    pub fn new() -> ForwardReferences {
+        ForwardReferences {
+            idx_lit: 0,
+            idx_flit: 0,
+            idx_exit: 0,
+            idx_zero_branch: 0,
+            idx_branch: 0,
+            idx_do: 0,
+            idx_qdo: 0,
+            idx_loop: 0,
+            idx_plus_loop: 0,
+            idx_s_quote: 0,
+            idx_type: 0,
+            idx_over: 0,
+            idx_equal: 0,
+            idx_drop: 0,
+            idx_two_drop: 0,
+            idx__of_quote: 0,
+            idx__postpone: 0,
+            idx_to_r: 0,
+            idx__does: 0,
+            idx__jump_table: 0,
+            idx_noop: 0,
+            idx_store: 0,
+            idx_f_store: 0,
+        }
+    }
+}
+
+/// Reason why the inner interpreter returns.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Termination {
+    /// The instruction pointer left the data space, for example at the end
+    /// of the code executed.
+    Completed,
+    /// `bye` was executed.
+    Bye,
+    /// The code executed was aborted with the exception, not cleared by
+    /// the error handler, see `handler!`.
+    Faulted(Exception),
+    /// `Core::forth` executed a word, more are to be executed.
+    Yielded,
+}
+
+/// Outcome of `Core::scan`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ScanReport {
+    /// Duration of the scan in nanoseconds.
+    pub elapsed: u64,
+    /// True if the scan took longer than the period set by `scan-period!`.
+    pub overrun: bool,
+}
+
+/// State of a task, see `task-status`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TaskStatus {
+    /// The current task.
+    Running = 0,
+    /// Awake and to be resumed by `pause`.
+    Ready = 1,
+    /// Awake but waiting for a semaphore, see `get`.
+    BlockedOnSemaphore = 2,
+    /// Awake but waiting for a time, see `ms`.
+    BlockedOnTime = 3,
+    /// Not awake, see `suspend` and `stop`.
+    Suspended = 4,
+    /// Awake but waiting for an I/O operation, see `io-wait`.
+    BlockedOnIo = 5,
+}
+
+/// Task waiting for a semaphore when a deadlock was detected.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WaitFor {
+    /// Waiting task, 0-based task id.
+    pub task: usize,
+    /// Address of the semaphore.
+    pub semaphore: usize,
+    /// Task holding the semaphore, 0-based task id, if valid.
+    pub holder: Option<usize>,
+}
+
+/// Nanoseconds after its deadline within which a timer is dispatched in
+/// time, above which it is counted as overdue.
+pub const TIMER_SLACK: u64 = 1_000_000;
+
+/// One-shot timer set by `after`, ordered by deadline.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timer {
+    /// System time in nanoseconds when the timer is due.
+    pub deadline: u64,
+    /// Timer number returned by `after`.
+    pub id: usize,
+    /// Word executed when the timer is due.
+    pub xt: usize,
+}
+
+/// Input specification saved by `evaluate`.
+struct InputSpec {
+    source_id: isize,
+    source_index: usize,
+    /// Input buffer of source id -1 and 0, which is shared by both.
+    buffer: Option<String>,
+}
+
+/// Interpreter state saved by a primitive nesting interpretation, see
+/// `Core::enter_activation`.
+struct Activation {
+    instruction_pointer: usize,
+    word_pointer: usize,
+    input: Option<InputSpec>,
+}
+
+/// Maximum nesting of break levels.
+const MAX_BREAK_DEPTH: usize = 8;
+
+/// Interpreter state saved when entering a break level.
+struct BreakFrame {
+    error: Exception,
+    is_compiling: bool,
+    instruction_pointer: usize,
+    word_pointer: usize,
+    r_len: u8,
+    source_index: usize,
+    source_id: isize,
+    input: Option<String>,
+    /// Word interrupted by a breakpoint, executed by `continue`.
+    pending: Option<usize>,
+}
+
+pub struct State {
+    pub is_compiling: bool,
+    pub instruction_pointer: usize,
+    word_pointer: usize,
+    pub aborted_word_pointer: usize,
+    pub source_index: usize,
+    pub source_id: isize,
+    /// Line of the source file being loaded, set by the loader.
+    pub source_position: SourcePosition,
+    /// Enter a break level instead of clearing stacks upon errors.
+    pub break_enabled: bool,
+    break_frames: Vec<BreakFrame>,
+    /// Watchdog timeout in nanoseconds, 0 if disabled.
+    watchdog_timeout: u64,
+    /// System time when the watchdog was fed.
+    watchdog_fed: u64,
+    /// Recovery word executed by `check-watchdogs`, 0 if none.
+    watchdog_xt: usize,
+    /// Set by `bye`, cleared when `run` returns.
+    bye: bool,
+    /// Interpretations nested by primitives, the innermost last.
+    activations: Vec<Activation>,
+    /// Labels of the definition being compiled.
+    pub labels: Labels,
+    /// Finite state machine being defined.
+    pub fsm: Option<FsmDefinition>,
+    /// Process image being defined.
+    pub image: Option<ImageDefinition>,
+    /// Addresses of the literals compiled in the current definition since
+    /// the last branch target, see `lit?last`.
+    literals: Vec<usize>,
+    /// Initial data stack of the next `activate`, see `pass`.
+    passed: Vec<isize>,
+}
+
+impl State {
+    pub fn new() -> State {
+        State {
+            is_compiling: false,
+            instruction_pointer: 0,
+            word_pointer: 0,
+            aborted_word_pointer: 0,
+            source_index: 0,
+            source_id: 0,
+            source_position: SourcePosition::default(),
+            break_enabled: false,
+            break_frames: Vec::new(),
+            watchdog_timeout: 0,
+            watchdog_fed: 0,
+            watchdog_xt: 0,
+            bye: false,
+            activations: Vec::new(),
+            labels: Labels::new(),
+            fsm: None,
+            image: None,
+            literals: Vec::new(),
+            passed: Vec::new(),
+        }
+    }
+
+    pub fn word_pointer(&self) -> usize {
+        self.word_pointer
+    }
+
+    /// Number of nested break levels.
+    pub fn break_depth(&self) -> usize {
+        self.break_frames.len()
+    }
+
+    /// Number of nested `evaluate`.
+    pub fn evaluate_depth(&self) -> usize {
+        self.activations
+            .iter()
+            .filter(|a| a.input.is_some())
+            .count()
+    }
+
+    /// Number of nested `execute_xt` and `evaluate`.
+    pub fn nesting_depth(&self) -> usize {
+        self.activations.len()
+    }
+}
+
+/// Data space, dictionary and stacks saved by `Core::fork`.
+pub struct Fork {
+    data: Vec<u8>,
+    words: usize,
+    last: usize,
+    buckets: [usize; BUCKET_SIZE],
+    forget_floor: usize,
+    user_exceptions: usize,
+    s_stack: Stack<isize>,
+    r_stack: Stack<isize>,
+    f_stack: Stack<f64>,
+    is_compiling: bool,
+    instruction_pointer: usize,
+    error: Option<Exception>,
+}
+
+impl Fork {
+    /// Number of bytes of data space saved.
+    pub fn data_len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// Number of words executed by `Core::run` between checks of
+/// `Wordlist::interrupt_flag`, a power of two.
+pub const INTERRUPT_PERIOD: u32 = 1024;
+
+/// Upper limit of label numbers for `label`, `goto` and `call`.
+///
+/// Labels are stored sparsely, so that machine-generated code may number
+/// them freely within this limit.
+pub const MAX_LABELS: usize = 0x100_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Label {
+    Undefined,
+    /// Address of the last branch waiting for the label, branches waiting
+    /// for the same label are chained through their offsets.
+    Forward(usize),
+    /// Address of the label.
+    Resolved(usize),
+}
+
+/// What to do when the output buffer exceeds its maximum length.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputOverflow {
+    /// Discard the oldest output to make room for the new output.
+    DropOldest,
+    /// Discard the new output beyond the maximum length.
+    DropNewest,
+    /// Discard the new output beyond the maximum length and abort with
+    /// `EXCEPTION_IN_SENDING_OR_RECEIVING_A_CHARACTER`.
+    Error,
+}
+
+/// Maximum length of the output buffer, set by `Core::set_output_limit`.
+pub struct OutputLimit {
+    max_len: usize,
+    policy: OutputOverflow,
+    overflowed: bool,
+}
+
+impl OutputLimit {
+    /// No limit.
+    pub fn new() -> OutputLimit {
+        OutputLimit {
+            max_len: usize::MAX,
+            policy: OutputOverflow::DropNewest,
+            overflowed: false,
+        }
+    }
+}
+
+impl Default for OutputLimit {
+    fn default() -> Self {
+        OutputLimit::new()
+    }
+}
+
+/// Default number of errors kept by `ErrorHistory`.
+pub const ERROR_HISTORY_CAPACITY: usize = 16;
+
+/// Error recorded in `ErrorHistory`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorRecord {
+    pub error: Exception,
+    /// Wall-clock time in nanoseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub task: usize,
+    /// Name of the word being executed.
+    pub word: String,
+    /// Last token parsed.
+    pub token: String,
+    /// Source of the input, see `source-id`.
+    pub source_id: isize,
+    /// Offset into the input.
+    pub source_index: usize,
+}
+
+/// Ring of the latest errors, so that faults can be diagnosed after the
+/// fact.
+pub struct ErrorHistory {
+    records: VecDeque<ErrorRecord>,
+    capacity: usize,
+}
+
+impl ErrorHistory {
+    /// Empty history keeping `ERROR_HISTORY_CAPACITY` errors.
+    pub fn new() -> ErrorHistory {
+        ErrorHistory {
+            records: VecDeque::new(),
+            capacity: ERROR_HISTORY_CAPACITY,
+        }
+    }
+
+    /// Record `record`, dropping the oldest one if the history is full.
+    pub fn record(&mut self, record: ErrorRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Keep the latest `capacity` errors, 0 to disable the history.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        while self.records.len() > capacity {
+            self.records.pop_front();
+        }
+        self.capacity = capacity;
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Recorded errors, the oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &ErrorRecord> {
+        self.records.iter()
+    }
+}
+
+impl Default for ErrorHistory {
+    fn default() -> Self {
+        ErrorHistory::new()
+    }
+}
+
+/// Definition recorded in `BuildLog`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildEvent {
+    /// Name of the word, empty if defined by `:noname`.
+    pub name: String,
+    pub position: SourcePosition,
+    /// Data space from the header of the word up to the next definition.
+    pub start: usize,
+    pub end: usize,
+    /// Wall-clock time in nanoseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// Append-only log of the definitions compiled after the words of rtForth
+/// itself, so that a dictionary can be traced back to its sources.
+pub struct BuildLog {
+    events: Vec<BuildEvent>,
+}
+
+impl BuildLog {
+    pub fn new() -> BuildLog {
+        BuildLog { events: Vec::new() }
+    }
+
+    /// Record `event`, ending the data space of the previous definition
+    /// where `event` starts.
+    pub fn record(&mut self, event: BuildEvent) {
+        if let Some(last) = self.events.last_mut() {
+            last.end = event.start.max(last.start);
+        }
+        self.events.push(event);
+    }
+
+    /// End the data space of the last definition at `here`.
+    pub fn close(&mut self, here: usize) {
+        if let Some(last) = self.events.last_mut() {
+            last.end = here.max(last.start);
+        }
+    }
+
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.events.truncate(len);
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Recorded definitions, the oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &BuildEvent> {
+        self.events.iter()
+    }
+}
+
+impl Default for BuildLog {
+    fn default() -> Self {
+        BuildLog::new()
+    }
+}
+
+/// Labels for BASIC-like `label`, `goto` and `call`, scoped to the
+/// definition being compiled.
+///
+/// Only the labels in use are stored, whatever their numbers.
+pub struct Labels {
+    table: BTreeMap<usize, Label>,
+}
+
+impl Labels {
+    pub fn new() -> Labels {
+        Labels {
+            table: BTreeMap::new(),
+        }
+    }
+
+    fn get(&self, n: usize) -> Label {
+        self.table.get(&n).cloned().unwrap_or(Label::Undefined)
+    }
+
+    fn set(&mut self, n: usize, label: Label) {
+        self.table.insert(n, label);
+    }
+
+    /// Forget all labels.
+    pub fn clear(&mut self) {
+        self.table.clear();
+    }
+
+    /// Numbers of labels used by `goto` or `call` but not defined yet.
+    pub fn unresolved(&self) -> Vec<usize> {
+        self.table
+            .iter()
+            .filter_map(|(&n, label)| match *label {
+                Label::Forward(_) => Some(n),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Labels::new()
+    }
+}
+
+/// Data compiled inline after a word in a colon definition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    None,
+    /// Integer compiled by `literal`.
+    Literal(isize),
+    /// Floating-point number compiled by `fliteral`.
+    FLiteral(f64),
+    /// Address and length of a string compiled by `s"`.
+    Str(usize, usize),
+    /// Destination of `branch`, `0branch`, `_loop` and `_+loop`, or the
+    /// address after the loop for `_do` and `_qdo`.
+    Branch(usize),
+    /// Destinations of `_jump-table`.
+    JumpTable(Vec<usize>),
+}
+
+/// Word compiled in a colon definition, see `Core::decode`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    /// Address of the instruction.
+    pub addr: usize,
+    pub xt: usize,
+    pub name: String,
+    pub operand: Operand,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Control {
+    Default,
+    Canary,
+    If(usize),
+    Else(usize),
+    Begin(usize),
+    While(usize),
+    Do(usize, usize),
+    Case,
+    /// `case` whose selector is a string, made so by `of"`.
+    StringCase,
+    Of(usize),
+    Endof(usize),
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Control::Default
+    }
+}
+
+impl Display for Control {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Control::Default => "Default",
+            Control::Canary => "Canary",
+            Control::If(_) => "If",
+            Control::Else(_) => "Else",
+            Control::Begin(_) => "Begin",
+            Control::While(_) => "While",
+            Control::Do(_, _) => "Do",
+            Control::Case => "Case",
+            Control::StringCase => "StringCase",
+            Control::Of(_) => "Of",
+            Control::Endof(_) => "Endof",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Remainder and quotient of `n / d`, `d` not 0, rounded towards negative
+/// infinity if `floored`, towards zero otherwise.
+fn divide(n: isize, d: isize, floored: bool) -> (isize, isize) {
+    let (r, q) = (n.wrapping_rem(d), n.wrapping_div(d));
+    if floored && r != 0 && (r < 0) != (d < 0) {
+        (r.wrapping_add(d), q.wrapping_sub(1))
+    } else {
+        (r, q)
+    }
+}
+
+/// Stack effects of the core primitives, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("noop", "( -- )"),
+    ("exit", "( -- ) ( R: nest-sys -- )"),
+    ("lit", "( -- x )"),
+    ("flit", "( F: -- r )"),
+    ("literal", "( n -- )"),
+    ("fliteral", "( F: r -- )"),
+    ("lit?last", "( -- x flag )"),
+    ("flit?last", "( -- flag ) ( F: -- r )"),
+    ("uncompile", "( -- )"),
+    ("_s\"", "( -- c-addr u )"),
+    ("_of\"", "( c-addr u -- c-addr u flag )"),
+    ("branch", "( -- )"),
+    ("0branch", "( flag -- )"),
+    ("_do", "( n1|u1 n2|u2 -- ) ( R: -- loop-sys )"),
+    ("_qdo", "( n1|u1 n2|u2 -- ) ( R: -- loop-sys )"),
+    ("_loop", "( -- ) ( R: loop-sys1 -- | loop-sys2 )"),
+    ("_+loop", "( n -- ) ( R: loop-sys1 -- | loop-sys2 )"),
+    ("unloop", "( -- ) ( R: loop-sys -- )"),
+    ("i", "( -- n ) ( R: loop-sys -- loop-sys )"),
+    (
+        "j",
+        "( -- n ) ( R: loop-sys1 loop-sys2 -- loop-sys1 loop-sys2 )",
+    ),
+    (">r", "( x -- ) ( R: -- x )"),
+    ("r>", "( -- x ) ( R: x -- )"),
+    ("r@", "( -- x ) ( R: x -- x )"),
+    ("2>r", "( x1 x2 -- ) ( R: -- x1 x2 )"),
+    ("2r>", "( -- x1 x2 ) ( R: x1 x2 -- )"),
+    ("2r@", "( -- x1 x2 ) ( R: x1 x2 -- x1 x2 )"),
+    ("compile,", "( xt -- )"),
+    ("_postpone", "( xt -- )"),
+    ("_does", "( -- )"),
+    ("_jump-table", "( i -- )"),
+    ("execute", "( i*x xt -- j*x )"),
+    ("jump", "( i*x i table -- j*x )"),
+    ("dup", "( x -- x x )"),
+    ("drop", "( x -- )"),
+    ("swap", "( x1 x2 -- x2 x1 )"),
+    ("over", "( x1 x2 -- x1 x2 x1 )"),
+    ("nip", "( x1 x2 -- x2 )"),
+    ("depth", "( -- +n )"),
+    ("need", "( n -- )"),
+    ("needs-f", "( n -- )"),
+    ("?stacks", "( -- )"),
+    ("0<", "( n -- flag )"),
+    ("=", "( x1 x2 -- flag )"),
+    ("<", "( n1 n2 -- flag )"),
+    ("invert", "( x1 -- x2 )"),
+    ("and", "( x1 x2 -- x3 )"),
+    ("or", "( x1 x2 -- x3 )"),
+    ("xor", "( x1 x2 -- x3 )"),
+    ("lshift", "( x1 u -- x2 )"),
+    ("rshift", "( x1 u -- x2 )"),
+    ("1+", "( n1 -- n2 )"),
+    ("1-", "( n1 -- n2 )"),
+    ("-", "( n1 n2 -- n3 )"),
+    ("+", "( n1 n2 -- n3 )"),
+    ("*", "( n1 n2 -- n3 )"),
+    ("+|", "( n1 n2 -- n3 )"),
+    ("-|", "( n1 n2 -- n3 )"),
+    ("*|", "( n1 n2 -- n3 )"),
+    ("/mod", "( n1 n2 -- n3 n4 )"),
+    ("cell+", "( a-addr1 -- a-addr2 )"),
+    ("cells", "( n1 -- n2 )"),
+    ("@", "( a-addr -- x )"),
+    ("!", "( x a-addr -- )"),
+    ("char+", "( c-addr1 -- c-addr2 )"),
+    ("here", "( -- addr )"),
+    ("unused", "( -- u )"),
+    ("limit", "( -- addr )"),
+    ("grow-data-space", "( pages -- ior )"),
+    ("low-water", "( -- a-addr )"),
+    ("(.low-water)", "( u -- )"),
+    ("allot", "( n -- )"),
+    ("aligned", "( addr -- a-addr )"),
+    ("align", "( -- )"),
+    ("c@", "( c-addr -- char )"),
+    ("c!", "( char c-addr -- )"),
+    ("move", "( addr1 addr2 u -- )"),
+    ("base", "( -- a-addr )"),
+    ("immediate", "( -- )"),
+    ("compile-only", "( -- )"),
+    ("(", "( \"ccc<paren>\" -- )"),
+    ("\\", "( \"ccc<eol>\" -- )"),
+    ("[", "( -- )"),
+    ("[']", "( \"<spaces>name\" -- )"),
+    ("[char]", "( \"<spaces>name\" -- )"),
+    (";", "( -- )"),
+    ("if", "( flag -- )"),
+    ("else", "( -- )"),
+    ("then", "( -- )"),
+    ("case", "( -- )"),
+    ("of", "( x1 x2 -- | x1 )"),
+    ("of\"", "( c-addr u \"ccc<quote>\" -- | c-addr u )"),
+    ("endof", "( -- )"),
+    ("endcase", "( x -- )"),
+    ("begin", "( -- )"),
+    ("while", "( flag -- )"),
+    ("repeat", "( -- )"),
+    ("until", "( flag -- )"),
+    ("again", "( -- )"),
+    ("0labels", "( -- )"),
+    ("label", "( n -- )"),
+    ("goto", "( n -- )"),
+    ("jump-table", "( n \"label1\" ... \"labeln\" -- )"),
+    ("call", "( n -- )"),
+    ("recurse", "( -- )"),
+    ("do", "( n1|u1 n2|u2 -- ) ( R: -- loop-sys )"),
+    ("?do", "( n1|u1 n2|u2 -- ) ( R: -- loop-sys )"),
+    ("loop", "( a-addr -- )"),
+    ("+loop", "( a-addr -- )"),
+    ("postpone", "( \"<spaces>name\" -- )"),
+    ("does>", "( -- )"),
+    ("true", "( -- true )"),
+    ("false", "( -- false )"),
+    ("on", "( a-addr -- )"),
+    ("off", "( a-addr -- )"),
+    ("not", "( x -- flag )"),
+    ("0=", "( x -- flag )"),
+    ("0>", "( n -- flag )"),
+    ("0<>", "( x -- flag )"),
+    (">", "( n1 n2 -- flag )"),
+    ("<>", "( x1 x2 -- flag )"),
+    ("within", "( n1 n2 n3 -- flag )"),
+    ("rot", "( x1 x2 x3 -- x2 x3 x1 )"),
+    ("-rot", "( x1 x2 x3 -- x3 x1 x2 )"),
+    ("pick", "( ... n -- x )"),
+    ("2dup", "( x1 x2 -- x1 x2 x1 x2 )"),
+    ("2drop", "( x1 x2 -- )"),
+    ("2swap", "( x1 x2 x3 x4 -- x3 x4 x1 x2 )"),
+    ("2over", "( x1 x2 x3 x4 -- x1 x2 x3 x4 x1 x2 )"),
+    ("/", "( n1 n2 -- n3 )"),
+    ("mod", "( n1 n2 -- n3 )"),
+    ("s>d", "( n -- d )"),
+    ("fm/mod", "( d n1 -- n2 n3 )"),
+    ("sm/rem", "( d n1 -- n2 n3 )"),
+    ("abs", "( n -- u )"),
+    ("negate", "( n1 -- n2 )"),
+    ("parse-word", "( \"ccc\" -- )"),
+    ("char", "( \"<spaces>name\" -- char )"),
+    ("_skip", "( char \"ccc\" -- )"),
+    ("parse", "( char \"ccc<char>\" -- )"),
+    (":", "( \"<spaces>name\" -- )"),
+    ("forward", "( \"<spaces>name\" -- )"),
+    ("constant", "( x \"<spaces>name\" -- )"),
+    ("value", "( x \"<spaces>name\" -- )"),
+    ("to", "( i*x \"<spaces>name\" -- )"),
+    ("create", "( \"<spaces>name\" -- )"),
+    ("xt-table", "( n \"<spaces>name\" -- )"),
+    ("xt!", "( xt i table -- )"),
+    ("xt@", "( i table -- xt )"),
+    ("dispatch", "( i*x i table -- j*x )"),
+    ("'", "( \"<spaces>name\" -- xt )"),
+    (">body", "( xt -- a-addr )"),
+    (">name", "( xt -- a-addr )"),
+    ("]", "( -- )"),
+    (",", "( x -- )"),
+    ("marker", "( \"<spaces>name\" -- )"),
+    ("handler!", "( a-addr -- )"),
+    ("error", "( -- n )"),
+    (".error", "( -- )"),
+    ("0error", "( -- )"),
+    ("0stacks", "( -- )"),
+    ("reset", "( -- )"),
+    ("abort", "( i*x -- )"),
+    ("break-on", "( -- )"),
+    ("break-off", "( -- )"),
+    ("fold-on", "( -- )"),
+    ("fold-off", "( -- )"),
+    ("strict-tick-on", "( -- )"),
+    ("strict-tick-off", "( -- )"),
+    ("strict-math-on", "( -- )"),
+    ("strict-math-off", "( -- )"),
+    ("guards-on", "( -- )"),
+    ("guards-off", "( -- )"),
+    ("checked-xts-on", "( -- )"),
+    ("checked-xts-off", "( -- )"),
+    ("unlock", "( \"pin\" -- )"),
+    ("lock", "( -- )"),
+    ("code-align", "( n -- )"),
+    ("break-depth", "( -- n )"),
+    ("break-error", "( -- n )"),
+    ("continue", "( -- )"),
+    ("break-if", "( xt -- )"),
+    ("break-count", "( n -- )"),
+    ("unbreak", "( -- )"),
+    ("0breaks", "( -- )"),
+    ("compiling?", "( -- flag )"),
+    ("token-empty?", "( -- f )"),
+    (".token", "( -- )"),
+    ("!token", "( c-addr -- )"),
+    ("compile-token", "( -- )"),
+    ("interpret-token", "( -- )"),
+    ("source-id", "( -- source-id )"),
+    ("source-id!", "( source-id -- )"),
+    ("source-idx", "( -- source-idx )"),
+    ("source-idx!", "( source-idx -- )"),
+    ("evaluate", "( i*x c-addr u -- j*x )"),
+    ("sandbox-eval", "( i*x c-addr u -- i*x ok? )"),
+    ("bye", "( -- )"),
+    ("at-exit", "( xt -- )"),
+    ("pause", "( -- )"),
+    ("activate", "( i -- )"),
+    ("pass", "( x i -- )"),
+    ("solo", "( -- a-addr )"),
+    ("schedule\"", "( \"ccc<quote>\" -- )"),
+    ("task-eval", "( c-addr u i -- )"),
+    ("me", "( -- i )"),
+    ("suspend", "( i -- )"),
+    ("resume", "( i -- )"),
+    ("priority!", "( n i -- )"),
+    ("priority@", "( i -- n )"),
+    ("task-status", "( i -- n )"),
+    ("(wait-semaphore)", "( a -- )"),
+    ("io-token", "( -- token )"),
+    ("io-done?", "( token -- flag )"),
+    ("io-result", "( token -- x )"),
+    ("(wait-io)", "( token -- )"),
+    ("scan-phase", "( xt -- )"),
+    ("scan-tasks", "( -- )"),
+    ("scan-period!", "( n -- )"),
+    ("scan-overruns", "( -- n )"),
+    ("after", "( ns xt -- timer# )"),
+    ("cancel", "( timer# -- )"),
+    ("timers-overdue", "( -- n )"),
+    ("feed-watchdog", "( -- )"),
+    ("watchdog!", "( n i -- )"),
+    ("watchdog-xt!", "( i -- )"),
+    ("watchdog-expired?", "( i -- flag )"),
+    ("check-watchdogs", "( -- )"),
+    ("exception-name", "( n -- c-addr u )"),
+    ("new-exception", "( c-addr u -- n )"),
+    ("throw", "( n -- )"),
+    ("leave", "( -- ) ( R: loop-sys -- )"),
+];
+
+pub trait Core: Sized {
+    // Functions to access VM.
+    fn last_error(&self) -> Option<Exception>;
+    fn set_error(&mut self, e: Option<Exception>);
+    fn handler(&self) -> usize;
+    fn set_handler(&mut self, h: usize);
+    fn data_space(&mut self) -> &mut DataSpace;
+    fn data_space_const(&self) -> &DataSpace;
+    /// Numeric output buffer
+    fn hold_buffer(&mut self) -> &mut String;
+    /// Get `output_buffer`.
+    fn output_buffer(&mut self) -> &mut Option<String>;
+    /// Set `output_buffer` to `Some(buffer)`.
+    fn set_output_buffer(&mut self, buffer: String);
+    /// Input source identifier
+    ///
+    /// > 0: input from source at `self.sources[source_id] and input buffer
+    /// `self.lines\[source_id\]`, see `HasLoader`.
+    /// = 0: input from the default user input buffer.
+    /// = -1: input from a string by `evaluate`.
+    fn source_id(&self) -> isize;
+    /// Get `input_buffer`.
+    fn input_buffer(&mut self) -> &mut Option<String>;
+    /// Set `input_buffer` to `Some(buffer)`.
+    fn set_input_buffer(&mut self, buffer: String);
+    fn last_token(&mut self) -> &mut Option<String>;
+    fn set_last_token(&mut self, buffer: String);
+    fn s_stack(&mut self) -> &mut Stack<isize>;
+    fn r_stack(&mut self) -> &mut Stack<isize>;
+    fn c_stack(&mut self) -> &mut Stack<Control>;
+    fn f_stack(&mut self) -> &mut Stack<f64>;
+    /// Last definition, 0 if last define fails.
+    fn wordlist_mut(&mut self) -> &mut Wordlist<Self>;
+    fn wordlist(&self) -> &Wordlist<Self>;
+    fn state(&mut self) -> &mut State;
+    fn references(&mut self) -> &mut ForwardReferences;
+    /// Transcript of the host input lines and the output, see
+    /// `FileAccess::transcript`.
+    #[cfg(feature = "file-access")]
+    fn transcription(&self) -> &Transcript;
+    #[cfg(feature = "file-access")]
+    fn transcription_mut(&mut self) -> &mut Transcript;
+    /// Nanoseconds since an arbitrary origin, never going backwards. Used
+    /// for scheduling, timeouts and measurements.
+    fn monotonic_ns(&self) -> u64;
+    /// Nanoseconds since the Unix epoch, which may jump when the host clock
+    /// is set. Used for timestamps of records, never for scheduling.
+    fn wallclock_ns(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+    }
+    /// Current task
+    fn current_task(&self) -> usize;
+    /// Set curretn task.
+    ///
+    /// No operation if there is no task `i`.
+    fn set_current_task(&mut self, i: usize);
+    /// Is task `i` awake?
+    ///
+    /// False if there is no task `i`.
+    fn awake(&self, i: usize) -> bool;
+    /// Set awake to `v`.
+    ///
+    /// No operation if there is no task `i`.
+    fn set_awake(&mut self, i: usize, v: bool);
+
+    /// Add core primitives to self.
+    fn add_core(&mut self) {
+        self.add_primitive("", Core::noop);
+        self.add_primitive("noop", Core::noop);
+        self.add_compile_only("exit", Core::exit);
+        self.add_compile_only("lit", Core::lit);
+        self.add_compile_only("flit", Core::flit);
+        self.add_immediate_and_compile_only("literal", Core::imm_literal);
+        self.add_immediate_and_compile_only("fliteral", Core::imm_fliteral);
+        self.add_primitive("lit?last", Core::lit_q_last);
+        self.add_primitive("flit?last", Core::flit_q_last);
+        self.add_primitive("uncompile", Core::uncompile);
+        self.add_compile_only("_s\"", Core::p_s_quote);
+        self.add_compile_only("_of\"", Core::p_of_quote);
+        self.add_compile_only("branch", Core::branch);
+        self.add_compile_only("0branch", Core::zero_branch);
+        self.add_compile_only("_do", Core::_do);
+        self.add_compile_only("_qdo", Core::_qdo);
+        self.add_compile_only("_loop", Core::_loop);
+        self.add_compile_only("_+loop", Core::_plus_loop);
+        self.add_compile_only("unloop", Core::unloop);
+        self.add_primitive_with("leave", Core::leave, Core::compile_leave);
+        self.compile_only();
+        self.add_compile_only("i", Core::p_i);
+        self.add_compile_only("j", Core::p_j);
+        self.add_compile_only(">r", Core::p_to_r);
+        self.add_compile_only("r>", Core::r_from);
+        self.add_compile_only("r@", Core::r_fetch);
+        self.add_compile_only("2>r", Core::two_to_r);
+        self.add_compile_only("2r>", Core::two_r_from);
+        self.add_compile_only("2r@", Core::two_r_fetch);
+        self.add_compile_only("compile,", Core::compile_comma);
+        self.add_compile_only("_postpone", Core::_postpone);
+        self.add_compile_only("_does", Core::_does);
+        self.add_compile_only("_jump-table", Core::_jump_table);
+
+        self.add_primitive("execute", Core::execute);
+        self.add_primitive("jump", Core::jump);
+        self.add_primitive("dup", Core::dup);
+        self.add_primitive("drop", Core::p_drop);
+        self.add_primitive("swap", Core::swap);
+        self.add_primitive("over", Core::over);
+        self.add_primitive("nip", Core::nip);
+        self.add_primitive("depth", Core::depth);
+        self.add_primitive_with("need", Core::need, Core::compile_guard);
+        self.add_primitive_with("needs-f", Core::needs_f, Core::compile_guard);
+        self.add_primitive("?stacks", Core::check_stacks);
+        self.add_primitive("0<", Core::zero_less);
+        self.add_primitive("=", Core::equals);
+        self.add_primitive("<", Core::less_than);
+        self.add_primitive("invert", Core::invert);
+        self.add_primitive("and", Core::and);
+        self.add_primitive("or", Core::or);
+        self.add_primitive("xor", Core::xor);
+        self.add_primitive("lshift", Core::lshift);
+        self.add_primitive("rshift", Core::rshift);
         self.add_primitive("1+", Core::one_plus);
         self.add_primitive("1-", Core::one_minus);
         self.add_primitive("-", Core::minus);
         self.add_primitive("+", Core::plus);
         self.add_primitive("*", Core::star);
+        self.add_primitive("+|", Core::checked_plus);
+        self.add_primitive("-|", Core::checked_minus);
+        self.add_primitive("*|", Core::checked_star);
         self.add_primitive("/mod", Core::slash_mod);
         self.add_primitive("cell+", Core::cell_plus);
         self.add_primitive("cells", Core::cells);
@@ -597,6 +2493,11 @@ pub trait Core: Sized {
         self.add_primitive("!", Core::store);
         self.add_primitive("char+", Core::char_plus);
         self.add_primitive("here", Core::here);
+        self.add_primitive("unused", Core::unused);
+        self.add_primitive("limit", Core::p_limit);
+        self.add_primitive("grow-data-space", Core::grow_data_space);
+        self.add_primitive("low-water", Core::low_water);
+        self.add_primitive("(.low-water)", Core::default_low_water);
         self.add_primitive("allot", Core::allot);
         self.add_primitive("aligned", Core::aligned);
         self.add_primitive("align", Core::align);
@@ -607,4361 +2508,9305 @@ pub trait Core: Sized {
         self.add_primitive("immediate", Core::immediate);
         self.add_primitive("compile-only", Core::compile_only);
 
-        // Immediate words
-        self.add_immediate("(", Core::imm_paren);
-        self.add_immediate("\\", Core::imm_backslash);
-        self.add_immediate("[", Core::left_bracket);
-        self.add_immediate_and_compile_only("[']", Core::bracket_tick);
-        self.add_immediate_and_compile_only("[char]", Core::bracket_char);
-        self.add_immediate_and_compile_only(";", Core::semicolon);
-        self.add_immediate_and_compile_only("if", Core::imm_if);
-        self.add_immediate_and_compile_only("else", Core::imm_else);
-        self.add_immediate_and_compile_only("then", Core::imm_then);
-        self.add_immediate_and_compile_only("case", Core::imm_case);
-        self.add_immediate_and_compile_only("of", Core::imm_of);
-        self.add_immediate_and_compile_only("endof", Core::imm_endof);
-        self.add_immediate_and_compile_only("endcase", Core::imm_endcase);
-        self.add_immediate_and_compile_only("begin", Core::imm_begin);
-        self.add_immediate_and_compile_only("while", Core::imm_while);
-        self.add_immediate_and_compile_only("repeat", Core::imm_repeat);
-        self.add_immediate_and_compile_only("until", Core::imm_until);
-        self.add_immediate_and_compile_only("again", Core::imm_again);
-        self.add_immediate("0labels", Core::imm_clear_labels);
-        self.add_immediate_and_compile_only("label", Core::imm_label);
-        self.add_immediate_and_compile_only("goto", Core::imm_goto);
-        self.add_immediate_and_compile_only("call", Core::imm_call);
-        self.add_immediate_and_compile_only("recurse", Core::imm_recurse);
-        self.add_immediate_and_compile_only("do", Core::imm_do);
-        self.add_immediate_and_compile_only("?do", Core::imm_qdo);
-        self.add_immediate_and_compile_only("loop", Core::imm_loop);
-        self.add_immediate_and_compile_only("+loop", Core::imm_plus_loop);
-        self.add_immediate_and_compile_only("postpone", Core::postpone);
-        self.add_immediate_and_compile_only("does>", Core::does);
+        // Immediate words
+        self.add_immediate("(", Core::imm_paren);
+        self.add_immediate("\\", Core::imm_backslash);
+        self.add_immediate("[", Core::left_bracket);
+        self.add_immediate_and_compile_only("[']", Core::bracket_tick);
+        self.add_immediate_and_compile_only("[char]", Core::bracket_char);
+        self.add_immediate_and_compile_only(";", Core::semicolon);
+        self.add_immediate_and_compile_only("if", Core::imm_if);
+        self.add_immediate_and_compile_only("else", Core::imm_else);
+        self.add_immediate_and_compile_only("then", Core::imm_then);
+        self.add_immediate_and_compile_only("case", Core::imm_case);
+        self.add_immediate_and_compile_only("of", Core::imm_of);
+        self.add_immediate_and_compile_only("of\"", Core::imm_of_quote);
+        self.add_immediate_and_compile_only("endof", Core::imm_endof);
+        self.add_immediate_and_compile_only("endcase", Core::imm_endcase);
+        self.add_immediate_and_compile_only("begin", Core::imm_begin);
+        self.add_immediate_and_compile_only("while", Core::imm_while);
+        self.add_immediate_and_compile_only("repeat", Core::imm_repeat);
+        self.add_immediate_and_compile_only("until", Core::imm_until);
+        self.add_immediate_and_compile_only("again", Core::imm_again);
+        self.add_immediate("0labels", Core::imm_clear_labels);
+        self.add_immediate_and_compile_only("label", Core::imm_label);
+        self.add_immediate_and_compile_only("goto", Core::imm_goto);
+        self.add_immediate("jump-table", Core::imm_jump_table);
+        self.add_immediate_and_compile_only("call", Core::imm_call);
+        self.add_immediate_and_compile_only("recurse", Core::imm_recurse);
+        self.add_immediate_and_compile_only("do", Core::imm_do);
+        self.add_immediate_and_compile_only("?do", Core::imm_qdo);
+        self.add_immediate_and_compile_only("loop", Core::imm_loop);
+        self.add_immediate_and_compile_only("+loop", Core::imm_plus_loop);
+        self.add_immediate_and_compile_only("postpone", Core::postpone);
+        self.add_immediate_and_compile_only("does>", Core::does);
+
+        // More Primitives
+        self.add_primitive("true", Core::p_true);
+        self.add_primitive("false", Core::p_false);
+        self.add_primitive("on", Core::p_on);
+        self.add_primitive("off", Core::p_off);
+        self.add_primitive("not", Core::zero_equals);
+        self.add_primitive("0=", Core::zero_equals);
+        self.add_primitive("0>", Core::zero_greater);
+        self.add_primitive("0<>", Core::zero_not_equals);
+        self.add_primitive(">", Core::greater_than);
+        self.add_primitive("<>", Core::not_equals);
+        self.add_primitive("within", Core::within);
+        self.add_primitive("rot", Core::rot);
+        self.add_primitive("-rot", Core::minus_rot);
+        self.add_primitive("pick", Core::pick);
+        self.add_primitive("2dup", Core::two_dup);
+        self.add_primitive("2drop", Core::two_drop);
+        self.add_primitive("2swap", Core::two_swap);
+        self.add_primitive("2over", Core::two_over);
+        self.add_primitive("/", Core::slash);
+        self.add_primitive("mod", Core::p_mod);
+        self.add_primitive("s>d", Core::s_to_d);
+        self.add_primitive("fm/mod", Core::fm_slash_mod);
+        self.add_primitive("sm/rem", Core::sm_slash_rem);
+        self.add_primitive("abs", Core::abs);
+        self.add_primitive("negate", Core::negate);
+        self.add_primitive("parse-word", Core::parse_word);
+        self.add_primitive("char", Core::char);
+        self.add_primitive("_skip", Core::_skip);
+        self.add_primitive("parse", Core::parse);
+        self.add_primitive(":", Core::colon);
+        self.add_primitive("forward", Core::forward);
+        self.add_primitive("constant", Core::constant);
+        self.add_primitive("value", Core::value);
+        self.add_immediate("to", Core::to);
+        self.add_primitive("create", Core::create);
+        self.add_primitive("xt-table", Core::xt_table);
+        self.add_primitive("xt!", Core::xt_store);
+        self.add_primitive("xt@", Core::xt_fetch);
+        self.add_primitive("dispatch", Core::dispatch);
+        self.add_primitive("'", Core::tick);
+        self.add_primitive(">body", Core::to_body);
+        self.add_primitive(">name", Core::to_name);
+        self.add_primitive("]", Core::right_bracket);
+        self.add_primitive(",", Core::comma);
+        self.add_primitive("marker", Core::marker);
+        self.add_primitive("handler!", Core::handler_store);
+        self.add_primitive("error", Core::error);
+        self.add_primitive(".error", Core::dot_error);
+        self.add_primitive("0error", Core::clear_error);
+        self.add_primitive("0stacks", Core::clear_stacks);
+        self.add_primitive("reset", Core::reset);
+        self.add_primitive("abort", Core::abort);
+        self.add_primitive("break-on", Core::break_on);
+        self.add_primitive("break-off", Core::break_off);
+        self.add_primitive("fold-on", Core::fold_on);
+        self.add_primitive("fold-off", Core::fold_off);
+        self.add_primitive("strict-tick-on", Core::strict_tick_on);
+        self.add_primitive("strict-tick-off", Core::strict_tick_off);
+        self.add_primitive("strict-math-on", Core::strict_math_on);
+        self.add_primitive("strict-math-off", Core::strict_math_off);
+        self.add_primitive("guards-on", Core::guards_on);
+        self.add_primitive("guards-off", Core::guards_off);
+        self.add_primitive("checked-xts-on", Core::checked_xts_on);
+        self.add_primitive("checked-xts-off", Core::checked_xts_off);
+        self.add_primitive("unlock", Core::unlock);
+        self.add_primitive("lock", Core::lock);
+        self.add_primitive("code-align", Core::p_code_align);
+        self.add_primitive("break-depth", Core::p_break_depth);
+        self.add_primitive("break-error", Core::break_error);
+        self.add_primitive("continue", Core::p_continue);
+        self.add_primitive("break-if", Core::break_if);
+        self.add_primitive("break-count", Core::break_count);
+        self.add_primitive("unbreak", Core::unbreak);
+        self.add_primitive("0breaks", Core::clear_breakpoints);
+        self.add_primitive("compiling?", Core::p_compiling);
+        self.add_primitive("token-empty?", Core::token_empty);
+        self.add_primitive(".token", Core::dot_token);
+        self.add_primitive("!token", Core::store_token);
+        self.add_primitive("compile-token", Core::compile_token);
+        self.add_primitive("interpret-token", Core::interpret_token);
+        self.add_primitive("source-id", Core::p_source_id);
+        self.add_primitive("source-id!", Core::p_set_source_id);
+        self.add_primitive("source-idx", Core::p_source_idx);
+        self.add_primitive("source-idx!", Core::p_set_source_idx);
+        self.add_primitive("evaluate", Core::evaluate);
+        self.add_primitive("sandbox-eval", Core::sandbox_evaluate);
+        self.add_primitive("bye", Core::bye);
+        self.add_primitive("at-exit", Core::p_at_exit);
+
+        self.references().idx_lit = self.find("lit").expect("lit undefined");
+        self.references().idx_flit = self.find("flit").expect("flit undefined");
+        self.references().idx_exit = self.find("exit").expect("exit undefined");
+        self.references().idx_zero_branch = self.find("0branch").expect("0branch undefined");
+        self.references().idx_branch = self.find("branch").expect("branch undefined");
+        self.references().idx_do = self.find("_do").expect("_do undefined");
+        self.references().idx_qdo = self.find("_qdo").expect("_qdo undefined");
+        self.references().idx_loop = self.find("_loop").expect("_loop undefined");
+        self.references().idx_plus_loop = self.find("_+loop").expect("_+loop undefined");
+        self.references().idx_over = self.find("over").expect("over undefined");
+        self.references().idx_equal = self.find("=").expect("= undefined");
+        self.references().idx_drop = self.find("drop").expect("drop undefined");
+        self.references().idx_two_drop = self.find("2drop").expect("2drop undefined");
+        self.references().idx__of_quote = self.find("_of\"").expect("_of\" undefined");
+        self.references().idx_noop = self.find("noop").expect("noop undefined");
+        self.references().idx_store = self.find("!").expect("! undefined");
+        self.references().idx__postpone = self.find("_postpone").expect("_postpone undefined");
+        self.references().idx_to_r = self.find(">r").expect(">r");
+        self.references().idx__does = self.find("_does").expect("_does");
+        self.references().idx__jump_table = self.find("_jump-table").expect("_jump-table");
+
+        {
+            // Multitasker
+            self.add_compile_only("pause", Core::pause);
+            self.add_compile_only("activate", Core::activate);
+            self.add_primitive("pass", Core::pass);
+            self.add_primitive("solo", Core::solo);
+            self.add_primitive("schedule\"", Core::schedule_quote);
+            self.add_primitive("task-eval", Core::task_eval);
+            self.add_primitive("me", Core::me);
+            self.add_primitive("suspend", Core::suspend);
+            self.add_primitive("resume", Core::resume);
+            self.add_primitive("priority!", Core::priority_store);
+            self.add_primitive("priority@", Core::priority_fetch);
+            self.add_primitive("task-status", Core::p_task_status);
+            self.add_primitive("(wait-semaphore)", Core::wait_semaphore);
+            self.add_primitive("io-token", Core::io_token);
+            self.add_primitive("io-done?", Core::io_done_q);
+            self.add_primitive("io-result", Core::io_result);
+            self.add_primitive("(wait-io)", Core::wait_io);
+        }
+        {
+            // Cyclic executive
+            self.add_primitive("scan-phase", Core::scan_phase);
+            self.add_primitive("scan-tasks", Core::scan_tasks);
+            self.add_primitive("scan-period!", Core::scan_period_store);
+            self.add_primitive("scan-overruns", Core::p_scan_overruns);
+            self.add_primitive("after", Core::after);
+            self.add_primitive("cancel", Core::cancel);
+            self.add_primitive("timers-overdue", Core::p_timers_overdue);
+        }
+        {
+            // Watchdog
+            self.add_primitive("feed-watchdog", Core::p_feed_watchdog);
+            self.add_primitive("watchdog!", Core::watchdog_store);
+            self.add_primitive("watchdog-xt!", Core::watchdog_xt_store);
+            self.add_primitive("watchdog-expired?", Core::p_watchdog_expired);
+            self.add_primitive("check-watchdogs", Core::check_watchdogs);
+        }
+        {
+            // Exception codes
+            self.add_primitive("exception-name", Core::exception_name);
+            self.add_primitive("new-exception", Core::new_exception);
+            self.add_primitive("throw", Core::throw);
+            for &(name, e) in exception::NAMES {
+                self.add_primitive_with(name, Core::p_const, Core::compile_const);
+                self.data_space().compile_isize(isize::from(e));
+            }
+        }
+        self.set_awake(0, true);
+        self.declare_effects(STACK_EFFECTS);
+    }
+
+    /// Add a primitive word to word list.
+    fn add_primitive(&mut self, name: &str, action: fn(&mut Self)) {
+        self.add_primitive_with(name, action, Core::compile_word);
+    }
+
+    /// Add a primitive word with compilation semantics
+    /// `compilation_semantics` to word list.
+    ///
+    /// `compilation_semantics` is called with the execution token of the
+    /// word when the word is compiled, instead of compiling the execution
+    /// token.
+    fn add_primitive_with(
+        &mut self,
+        name: &str,
+        action: fn(&mut Self),
+        compilation_semantics: fn(&mut Self, usize),
+    ) {
+        self.data_space().align();
+        let mut word = Word::new(
+            action,
+            compilation_semantics,
+            shared_name(name),
+            self.data_space().here(),
+        );
+        word.shared_name = true;
+        self.wordlist_mut().push(name, word);
+    }
+
+    /// Replace the compilation semantics of word `xt`.
+    ///
+    /// No operation if there is no word `xt`.
+    fn set_compilation_semantics(
+        &mut self,
+        xt: usize,
+        compilation_semantics: fn(&mut Self, usize),
+    ) {
+        if xt < self.wordlist().len() {
+            self.wordlist_mut()[xt].compilation_semantics = compilation_semantics;
+        }
+    }
+
+    /// Set the last definition immediate.
+    fn immediate(&mut self) {
+        let def = self.wordlist().last;
+        self.wordlist_mut()[def].set_immediate(true);
+    }
+
+    /// Add an immediate word to word list.
+    fn add_immediate(&mut self, name: &str, action: fn(&mut Self)) {
+        self.add_primitive(name, action);
+        self.immediate();
+    }
+
+    /// Set the last definition compile-only.
+    fn compile_only(&mut self) {
+        let def = self.wordlist().last;
+        self.wordlist_mut()[def].set_compile_only(true);
+    }
+
+    /// Add a compile-only word to word list.
+    fn add_compile_only(&mut self, name: &str, action: fn(&mut Self)) {
+        self.add_primitive(name, action);
+        self.compile_only();
+    }
+
+    /// Add an immediate and compile-only word to word list.
+    fn add_immediate_and_compile_only(&mut self, name: &str, action: fn(&mut Self)) {
+        self.add_primitive(name, action);
+        self.immediate();
+        self.compile_only();
+    }
+
+    /// Execute word at position `i`.
+    fn execute_word(&mut self, i: usize) {
+        self.state().word_pointer = i;
+        if self.wordlist().has_breakpoints && self.hit_breakpoint(i) {
+            return;
+        }
+        if i < self.wordlist().len() {
+            (self.wordlist()[i].action())(self);
+            if self.wordlist().output_limit.max_len != usize::MAX {
+                self.limit_output();
+            }
+        } else {
+            self.abort_with(INVALID_XT);
+        }
+    }
+
+    /// Execute word `xt` to completion and return to the caller.
+    ///
+    /// Used by primitives which need the result of an execution token.
+    /// Upon error, the instruction pointer is left to the error handler.
+    fn execute_xt(&mut self, xt: usize) {
+        if !self.enter_activation(false) {
+            return;
+        }
+        self.state().instruction_pointer = 0;
+        self.execute_word(xt);
+        self.run();
+        self.leave_activation();
+    }
+
+    /// Save the instruction and word pointers, and the input specification
+    /// if `input`, so that a primitive can interpret further words and
+    /// then return to its caller with `leave_activation`.
+    ///
+    /// Primitives nest interpretation through `execute_xt` and
+    /// `evaluate_str`, which pair both. Return false after aborting with
+    /// `NESTING_LIMIT` if `Wordlist::max_nesting` activations are nested
+    /// already.
+    fn enter_activation(&mut self, input: bool) -> bool {
+        if self.state().activations.len() >= self.wordlist().max_nesting {
+            self.abort_with(NESTING_LIMIT);
+            return false;
+        }
+        let input = if input {
+            let source_id = self.source_id();
+            let source_index = self.state().source_index;
+            self.state().source_id = -1;
+            let buffer = self.input_buffer().take();
+            Some(InputSpec {
+                source_id,
+                source_index,
+                buffer,
+            })
+        } else {
+            None
+        };
+        let activation = Activation {
+            instruction_pointer: self.state().instruction_pointer,
+            word_pointer: self.state().word_pointer,
+            input,
+        };
+        self.state().activations.push(activation);
+        true
+    }
+
+    /// Restore what `enter_activation` saved. Upon error, the instruction
+    /// pointer is left to the error handler, and after `bye` to 0, so that
+    /// the callers stop too.
+    fn leave_activation(&mut self) {
+        let activation = match self.state().activations.pop() {
+            Some(activation) => activation,
+            None => return,
+        };
+        if self.last_error().is_none() && !self.state().bye {
+            self.state().instruction_pointer = activation.instruction_pointer;
+        }
+        self.state().word_pointer = activation.word_pointer;
+        if let Some(spec) = activation.input {
+            self.state().source_id = -1;
+            *self.input_buffer() = spec.buffer;
+            self.state().source_id = spec.source_id;
+            self.state().source_index = spec.source_index;
+        }
+    }
+
+    /// Find the word with name `name`.
+    /// If not found returns zero.
+    ///
+    /// A word of a word set not registered yet registers it, see
+    /// `Wordlist::lazy_word_sets`.
+    fn find(&mut self, name: &str) -> Option<usize> {
+        let found = self.find_registered(name);
+        if found.is_none() {
+            if let Some(register) = self.wordlist_mut().register_lazily.take() {
+                let registered = register(self, name);
+                self.wordlist_mut().register_lazily = Some(register);
+                if registered {
+                    return self.find_registered(name);
+                }
+            }
+        }
+        found
+    }
+
+    /// Find the word with name `name` among the words registered.
+    fn find_registered(&mut self, name: &str) -> Option<usize> {
+        let hash = Wordlist::<Self>::hash(name);
+        let mut w = self.wordlist().buckets[hash as usize % BUCKET_SIZE];
+        while w != 0 {
+            if !self.wordlist()[w].is_hidden() {
+                {
+                    if self.wordlist()[w].hash == hash {
+                        let nfa = self.wordlist()[w].nfa();
+                        let w_name = unsafe { self.data_space().get_str(nfa) };
+                        if w_name.eq_ignore_ascii_case(name) {
+                            return Some(w);
+                        }
+                    }
+                }
+            }
+            w = self.wordlist()[w].link;
+        }
+        None
+    }
+
+    /// True if every word is linked in the bucket of the hash of its name,
+    /// with the hash matching the name, so that `find` can reach it.
+    fn dictionary_intact(&mut self) -> bool {
+        let len = self.wordlist().len();
+        let mut linked = 0;
+        for b in 0..BUCKET_SIZE {
+            let mut w = self.wordlist().buckets[b];
+            while w != 0 {
+                if w >= len || linked >= len {
+                    return false;
+                }
+                let nfa = self.wordlist()[w].nfa();
+                let hash = Wordlist::<Self>::hash(unsafe { self.data_space().get_str(nfa) });
+                let link = self.wordlist()[w].link;
+                if self.wordlist()[w].hash != hash || hash as usize % BUCKET_SIZE != b || link >= w
+                {
+                    return false;
+                }
+                linked += 1;
+                w = link;
+            }
+        }
+        linked + 1 == len
+    }
+
+    /// Recompute the hashes of all words from their names and rebuild the
+    /// hash buckets, so that every word is reachable by `find` again.
+    fn rehash(&mut self) {
+        for w in 1..self.wordlist().len() {
+            let nfa = self.wordlist()[w].nfa();
+            let hash = Wordlist::<Self>::hash(unsafe { self.data_space().get_str(nfa) });
+            self.wordlist_mut()[w].hash = hash;
+        }
+        self.wordlist_mut().relink();
+    }
+
+    /// Name of word `xt`, empty if none.
+    fn word_name(&mut self, xt: usize) -> String {
+        if xt < self.wordlist().len() {
+            let nfa = self.wordlist()[xt].nfa();
+            unsafe { self.data_space().get_str(nfa) }.to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    /// Stack effect declared for word `xt`, such as `( x -- x x )`, `None`
+    /// if there is none.
+    ///
+    /// Primitives declare theirs by `declare_effects`, definitions by a
+    /// comment with `--` following the name, as in
+    /// `: sq ( n -- n*n ) dup * ;`.
+    fn word_effect(&mut self, xt: usize) -> Option<String> {
+        if xt >= self.wordlist().len() {
+            return None;
+        }
+        match self.wordlist()[xt].effect {
+            0 => None,
+            effect => Some(unsafe { self.data_space().get_str(effect) }.to_string()),
+        }
+    }
+
+    /// Declare the stack effects of primitives, given as pairs of names
+    /// and effects.
+    ///
+    /// Called by each word set once its primitives are added. Names not
+    /// found or found to be definitions are skipped.
+    fn declare_effects(&mut self, effects: &[(&str, &str)]) {
+        for &(name, effect) in effects {
+            if let Some(xt) = self.find_registered(name) {
+                if self.wordlist()[xt].shared_name {
+                    self.wordlist_mut()[xt].effect = shared_name(effect);
+                }
+            }
+        }
+    }
+
+    /// Instructions of colon definition `xt`, up to the next definition or
+    /// `here`, `None` if `xt` is not a colon definition.
+    ///
+    /// Decoding stops at a cell which is not a valid execution token.
+    fn decode(&mut self, xt: usize) -> Option<Vec<Instruction>> {
+        if xt >= self.wordlist().len() {
+            return None;
+        }
+        let action = self.wordlist()[xt].action();
+        #[cfg(feature = "jit")]
+        let is_colon =
+            action as usize == Self::nest as fn(&mut Self) as usize || jit::is_colon_action(action);
+        #[cfg(not(feature = "jit"))]
+        let is_colon = action as usize == Self::nest as fn(&mut Self) as usize;
+        if !is_colon {
+            return None;
+        }
+        let start = self.wordlist()[xt].dfa();
+        let end = (xt + 1..self.wordlist().len())
+            .map(|w| self.wordlist()[w].start())
+            .find(|&a| a >= start)
+            .unwrap_or_else(|| self.data_space().here());
+        let r = self.references();
+        let (lit, flit, s_quote, of_quote, jump_table) = (
+            r.idx_lit,
+            r.idx_flit,
+            r.idx_s_quote,
+            r.idx__of_quote,
+            r.idx__jump_table,
+        );
+        let branches = [
+            r.idx_branch,
+            r.idx_zero_branch,
+            r.idx_do,
+            r.idx_qdo,
+            r.idx_loop,
+            r.idx_plus_loop,
+        ];
+        let cell = mem::size_of::<isize>();
+        let mut code = Vec::new();
+        let mut addr = start;
+        while addr + cell <= end {
+            let w = unsafe { self.data_space().get_usize(addr) };
+            if w == 0 || w >= self.wordlist().len() {
+                break;
+            }
+            let mut next = addr + cell;
+            let operand = if w == lit && next + cell <= end {
+                next += cell;
+                Operand::Literal(unsafe { self.data_space().get_isize(addr + cell) })
+            } else if w == flit && next + mem::size_of::<f64>() <= end {
+                let p = next;
+                next = p + mem::size_of::<f64>();
+                Operand::FLiteral(unsafe { self.data_space().get_f64(p) })
+            } else if (w == s_quote || w == of_quote) && next + cell <= end {
+                let len = unsafe { self.data_space().get_usize(next) };
+                let p = next + cell;
+                next = DataSpace::aligned(p + len);
+                Operand::Str(p, len)
+            } else if branches.contains(&w) && next + cell <= end {
+                next += cell;
+                Operand::Branch(unsafe { self.data_space().get_usize(addr + cell) })
+            } else if w == jump_table && next + cell <= end {
+                let table = next + cell;
+                let n = unsafe { self.data_space().get_usize(next) }.min((end - table) / cell);
+                next = table + n * cell;
+                let dests = (0..n)
+                    .map(|i| unsafe { self.data_space().get_usize(table + i * cell) })
+                    .collect();
+                Operand::JumpTable(dests)
+            } else {
+                Operand::None
+            };
+            let name = self.word_name(w);
+            code.push(Instruction {
+                addr,
+                xt: w,
+                name,
+                operand,
+            });
+            addr = next;
+        }
+        Some(code)
+    }
+
+    // -------------------------------
+    // Token threaded code
+    // -------------------------------
+
+    /// Evaluate a compiled program following self.state().instruction_pointer.
+    /// Any exception causes termination of inner loop.
+    ///
+    /// Returns `Termination::Bye` if terminated by `bye`,
+    /// `Termination::Faulted` if an error is left, otherwise
+    /// `Termination::Completed`.
+    ///
+    /// Every `INTERRUPT_PERIOD` words, the loop aborts with `USER_INTERRUPT`
+    /// if the host has set `Wordlist::interrupt_flag`, and stops with
+    /// `TIMEOUT` once the budget of `evaluate_with_timeout` is spent.
+    #[inline(never)]
+    fn run(&mut self) -> Termination {
+        let mut ip = self.state().instruction_pointer;
+        let mut ticks = 0u32;
+        while self.data_space().start() <= ip
+            && ip + mem::size_of::<isize>() <= self.data_space().limit()
+        {
+            let w = unsafe { self.data_space().get_isize(ip) as usize };
+            self.state().instruction_pointer += mem::size_of::<isize>();
+            self.execute_word(w);
+            ticks = ticks.wrapping_add(1);
+            if ticks & (INTERRUPT_PERIOD - 1) == 0 && self.poll_interrupt() {
+                break;
+            }
+            ip = self.state().instruction_pointer;
+        }
+        self.termination()
+    }
+
+    /// Why `run` or `forth` stopped with the instruction pointer out of
+    /// the data space.
+    fn termination(&mut self) -> Termination {
+        if self.state().bye {
+            // Nested runs leave `bye` to the outermost one.
+            if self.state().activations.is_empty() {
+                self.state().bye = false;
+            }
+            Termination::Bye
+        } else {
+            match self.last_error() {
+                Some(e) => Termination::Faulted(e),
+                None => Termination::Completed,
+            }
+        }
+    }
+
+    /// True if no task is ready to run: all awake tasks wait for a
+    /// semaphore, a time or I/O, and no timer is due. An embedding loop
+    /// may then sleep until its next event instead of calling `run` or
+    /// `scan`.
+    fn is_idle(&self) -> bool {
+        let timer_due = match self.wordlist().timers.peek() {
+            Some(&Reverse(t)) => t.deadline <= self.monotonic_ns(),
+            None => false,
+        };
+        !timer_due && (0..NUM_TASKS).all(|i| !self.awake(i) || self.blocked(i))
+    }
+
+    /// Check the interrupt flag and the deadline at a safe point of `run`.
+    /// Return true if `run` is to stop.
+    #[cold]
+    fn poll_interrupt(&mut self) -> bool {
+        if self.wordlist().interrupt.swap(false, Ordering::Relaxed) {
+            self.abort_with(USER_INTERRUPT);
+        }
+        match self.wordlist().deadline {
+            Some(deadline) if self.monotonic_ns() >= deadline => {
+                if self.last_error() != Some(TIMEOUT) {
+                    self.abort_with(TIMEOUT);
+                }
+                self.state().instruction_pointer = 0;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Run one scan of the cyclic executive, for example upon each tick of
+    /// a hardware timer instead of a free-running `run`.
+    ///
+    /// The words registered by `scan-phase` are executed once in order in
+    /// the current task, typically reading inputs, `scan-tasks` and writing
+    /// outputs. Without registered phases, only `scan-tasks` is executed.
+    /// The scan stops at the first error, left in `last_error`.
+    fn scan(&mut self) -> ScanReport {
+        let start = self.monotonic_ns();
+        let scanning = mem::replace(&mut self.wordlist_mut().scanning, true);
+        self.run_timers();
+        let phases = self.wordlist().scan_phases.clone();
+        if phases.is_empty() {
+            self.scan_tasks();
+        }
+        for xt in phases {
+            self.execute_xt(xt);
+            if self.last_error().is_some() {
+                break;
+            }
+        }
+        self.wordlist_mut().scanning = scanning;
+        let elapsed = self.monotonic_ns().saturating_sub(start);
+        let period = self.wordlist().scan_period;
+        let overrun = period != 0 && elapsed > period;
+        if overrun {
+            self.wordlist_mut().scan_overruns += 1;
+        }
+        ScanReport { elapsed, overrun }
+    }
+
+    /// Execute one step of the inner interpreter.
+    ///
+    /// Returns `Termination::Yielded` if a word was executed, otherwise why
+    /// there is nothing to execute, as `run` does.
+    fn forth(&mut self) -> Termination {
+        let ip = self.state().instruction_pointer;
+        if self.data_space().start() <= ip
+            && ip + mem::size_of::<isize>() <= self.data_space().limit()
+        {
+            let w = unsafe { self.data_space().get_isize(ip) as usize };
+            self.state().instruction_pointer += mem::size_of::<isize>();
+            self.execute_word(w);
+            Termination::Yielded
+        } else {
+            self.termination()
+        }
+    }
+
+    fn compile_word(&mut self, word_index: usize) {
+        self.data_space().compile_usize(word_index as usize);
+    }
+
+    fn compile_nest(&mut self, word_index: usize) {
+        self.compile_word(word_index);
+    }
+
+    fn compile_nest_code(&mut self, _: usize) {
+        // Do nothing.
+    }
+
+    fn compile_var(&mut self, word_index: usize) {
+        self.compile_word(word_index);
+    }
+
+    /// Compile the value of the constant as a literal if constants are
+    /// folded, see `fold-on`.
+    fn compile_const(&mut self, word_index: usize) {
+        if self.wordlist().fold_constants() {
+            let dfa = self.wordlist()[word_index].dfa();
+            let value = unsafe { self.data_space().get_isize(dfa) };
+            self.compile_integer(value);
+        } else {
+            self.compile_word(word_index);
+        }
+    }
+
+    fn compile_unmark(&mut self, word_index: usize) {
+        self.compile_word(word_index);
+    }
+
+    /// Compile the value of the float constant as a literal if constants
+    /// are folded, see `fold-on`.
+    fn compile_fconst(&mut self, word_index: usize) {
+        if self.wordlist().fold_constants() {
+            let dfa = DataSpace::aligned_f64(self.wordlist()[word_index].dfa());
+            let value = unsafe { self.data_space().get_f64(dfa) };
+            self.compile_float(value);
+        } else {
+            self.compile_word(word_index);
+        }
+    }
+
+    fn lit(&mut self) {
+        let ip = self.state().instruction_pointer;
+        let v = unsafe { self.data_space().get_isize(ip) as isize };
+        let slen = self.s_stack().len.wrapping_add(1);
+        self.s_stack().len = slen;
+        self.s_stack()[slen.wrapping_sub(1)] = v;
+        self.state().instruction_pointer += mem::size_of::<isize>();
+    }
+
+    /// Compile integer `i`.
+    fn compile_integer(&mut self, i: isize) {
+        let start = self.data_space().here();
+        let idx = self.references().idx_lit;
+        self.compile_word(idx);
+        self.data_space().compile_isize(i as isize);
+        self.state().literals.push(start);
+    }
+
+    fn flit(&mut self) {
+        let ip = self.state().instruction_pointer;
+        let v = unsafe { self.data_space().get_f64(ip) };
+        let flen = self.f_stack().len.wrapping_add(1);
+        self.f_stack().len = flen;
+        self.f_stack()[flen.wrapping_sub(1)] = v;
+        self.state().instruction_pointer = ip + mem::size_of::<f64>();
+    }
+
+    /// Compile float 'f'.
+    ///
+    /// The float follows `flit` without alignment padding, so that the
+    /// cells of a definition stay contiguous where floats are aligned more
+    /// strictly than cells.
+    fn compile_float(&mut self, f: f64) {
+        let start = self.data_space().here();
+        let idx_flit = self.references().idx_flit;
+        self.compile_word(idx_flit);
+        self.data_space().compile_f64(f);
+        self.state().literals.push(start);
+    }
+
+    /// Compilation: ( n -- )
+    ///
+    /// Append the run-time semantics `( -- n )` to the current definition.
+    fn imm_literal(&mut self) {
+        let n = self.s_stack().pop();
+        self.compile_integer(n);
+    }
+
+    /// Compilation: ( F: r -- )
+    ///
+    /// Append the run-time semantics `( F: -- r )` to the current
+    /// definition.
+    fn imm_fliteral(&mut self) {
+        let r = self.f_stack().pop();
+        self.compile_float(r);
+    }
+
+    /// Address where the most recently compiled cells are a literal, and
+    /// the execution token of `lit` or `flit` there.
+    ///
+    /// `None` if something else has been compiled since, or a branch
+    /// target has been set after the literal.
+    fn last_literal(&mut self) -> Option<(usize, usize)> {
+        let start = match self.state().literals.last() {
+            Some(&start) => start,
+            None => return None,
+        };
+        let here = self.data_space().here();
+        if start < self.data_space().start() || start >= here {
+            return None;
+        }
+        let idx_lit = self.references().idx_lit;
+        let idx_flit = self.references().idx_flit;
+        let xt = unsafe { self.data_space().get_usize(start) };
+        let end = if xt == idx_lit {
+            start + 2 * mem::size_of::<isize>()
+        } else if xt == idx_flit {
+            start + mem::size_of::<isize>() + mem::size_of::<f64>()
+        } else {
+            return None;
+        };
+        if end == here {
+            Some((start, xt))
+        } else {
+            None
+        }
+    }
+
+    /// Compilation: ( -- x flag )
+    ///
+    /// If the most recently compiled cells in the current definition are
+    /// the integer literal `x`, return `x` and true. Otherwise return 0
+    /// and false.
+    ///
+    /// With `uncompile`, immediate words can fold literals:
+    ///
+    /// ```forth
+    /// : cells ( n -- n*cell )
+    ///   lit?last if uncompile cells postpone literal
+    ///   else drop postpone cells then ; immediate
+    /// ```
+    fn lit_q_last(&mut self) {
+        let idx_lit = self.references().idx_lit;
+        match self.last_literal() {
+            Some((start, xt)) if xt == idx_lit => {
+                let x = unsafe { self.data_space().get_isize(start + mem::size_of::<isize>()) };
+                self.s_stack().push2(x, TRUE);
+            }
+            _ => self.s_stack().push2(0, FALSE),
+        }
+    }
+
+    /// Compilation: ( -- flag ) ( F: -- r )
+    ///
+    /// If the most recently compiled cells in the current definition are
+    /// the floating-point literal `r`, return `r` and true. Otherwise
+    /// return 0 and false.
+    fn flit_q_last(&mut self) {
+        let idx_flit = self.references().idx_flit;
+        match self.last_literal() {
+            Some((start, xt)) if xt == idx_flit => {
+                let r = unsafe { self.data_space().get_f64(start + mem::size_of::<isize>()) };
+                self.f_stack().push(r);
+                self.s_stack().push(TRUE);
+            }
+            _ => {
+                self.f_stack().push(0.0);
+                self.s_stack().push(FALSE);
+            }
+        }
+    }
+
+    /// Compilation: ( -- )
+    ///
+    /// Remove the literal most recently compiled in the current
+    /// definition. The literal compiled before it, if adjacent, becomes
+    /// the most recent one.
+    ///
+    /// Aborts with `UNSUPPORTED_OPERATION` if the most recently compiled
+    /// cells are not a literal, see `lit?last`.
+    fn uncompile(&mut self) {
+        match self.last_literal() {
+            Some((start, _)) => {
+                self.state().literals.pop();
+                let _ = self.data_space().set_here(start);
+            }
+            None => self.abort_with(UNSUPPORTED_OPERATION),
+        }
+    }
+
+    /// Runtime of S"
+    fn p_s_quote(&mut self) {
+        let ip = self.state().instruction_pointer;
+        let (addr, cnt) = {
+            let s = unsafe { self.data_space().get_str(ip) };
+            (s.as_ptr() as isize, s.len() as isize)
+        };
+        let slen = self.s_stack().len.wrapping_add(2);
+        self.s_stack().len = slen;
+        self.s_stack()[slen.wrapping_sub(1)] = cnt;
+        self.s_stack()[slen.wrapping_sub(2)] = addr;
+        self.state().instruction_pointer = DataSpace::aligned(
+            self.state().instruction_pointer + mem::size_of::<isize>() + cnt as usize,
+        );
+    }
+
+    /// Runtime of `of"`: ( c-addr u -- c-addr u flag )
+    ///
+    /// `flag` is true if the string `c-addr u` is the same as the string
+    /// compiled after `_of"`, which is skipped.
+    fn p_of_quote(&mut self) {
+        let ip = self.state().instruction_pointer;
+        let (addr, cnt) = self.s_stack().pop2();
+        let (equal, len) = {
+            let data_space = self.data_space_const();
+            let s = unsafe { data_space.get_str(ip) };
+            let equal = cnt as usize == s.len()
+                && unsafe { data_space.buffer_from_raw_parts(addr as usize, cnt as usize) }
+                    == s.as_bytes();
+            (equal, s.len())
+        };
+        self.s_stack()
+            .push3(addr, cnt, if equal { TRUE } else { FALSE });
+        self.state().instruction_pointer = DataSpace::aligned(ip + mem::size_of::<isize>() + len);
+    }
+
+    fn branch(&mut self) {
+        let ip = self.state().instruction_pointer;
+        self.state().instruction_pointer = unsafe { self.data_space().get_isize(ip) as usize };
+    }
+
+    fn compile_branch(&mut self, destination: usize) -> usize {
+        let idx = self.references().idx_branch;
+        self.compile_word(idx);
+        self.data_space().compile_address(destination);
+        self.data_space().here()
+    }
+
+    fn zero_branch(&mut self) {
+        let v = self.s_stack().pop();
+        if v == 0 {
+            self.branch();
+        } else {
+            self.state().instruction_pointer += mem::size_of::<isize>();
+        }
+    }
+
+    /// Run-time: ( i -- )
+    ///
+    /// Branch to the `i`-th destination of the table following `_jump-table`,
+    /// or continue after the table if `i` is out of range.
+    ///
+    /// ```text
+    /// +-------------+---+-------+-----+-------+--
+    /// | _jump-table | n | dest0 | ... | destn-1 |
+    /// +-------------+---+-------+-----+-------+--
+    ///                 ^
+    ///                 |
+    ///                 ip
+    /// ```
+    fn _jump_table(&mut self) {
+        let i = self.s_stack().pop();
+        let ip = self.state().instruction_pointer;
+        let n = unsafe { self.data_space().get_isize(ip) };
+        self.state().instruction_pointer = if 0 <= i && i < n {
+            unsafe {
+                self.data_space()
+                    .get_usize(ip + (i as usize + 1) * mem::size_of::<isize>())
+            }
+        } else {
+            ip + (n as usize + 1) * mem::size_of::<isize>()
+        };
+    }
+
+    fn compile_zero_branch(&mut self, destination: usize) -> usize {
+        let idx = self.references().idx_zero_branch;
+        self.compile_word(idx);
+        self.data_space().compile_address(destination);
+        self.data_space().here()
+    }
+
+    /// ( n1|u1 n2|u2 -- ) ( R: -- loop-sys )
+    ///
+    /// Set up loop control parameters with index `n2`|`u2` and limit `n1`|`u1`. An
+    /// ambiguous condition exists if `n1`|`u1` and `n2`|`u2` are not both the same
+    /// type.  Anything already on the return stack becomes unavailable until
+    /// the loop-control parameters are discarded.
+    ///
+    /// ```
+    ///         +--------------------------+
+    ///         |                          |
+    ///         |                          v
+    /// +-----+-+-+-----------+-------+---+--
+    /// | _do | x | loop body | _loop | x |
+    /// +-----+---+-----------+-------+-+-+--
+    ///         ^
+    ///         |
+    ///         ip
+    /// ```
+    ///
+    fn _do(&mut self) {
+        let ip = self.state().instruction_pointer as isize;
+        self.r_stack().push(ip);
+        self.state().instruction_pointer += mem::size_of::<isize>();
+        let (n, t) = self.s_stack().pop2();
+        let rt = isize::min_value().wrapping_add(t).wrapping_sub(n);
+        let rn = t.wrapping_sub(rt);
+        self.r_stack().push2(rn, rt);
+    }
+
+    /// ( n1|u1 n2|u2 -- ) ( R: -- loop-sys )
+    ///
+    /// If n1|u1 is equal to n2|u2, continue execution at the location given by
+    /// the consumer of do-sys. Otherwise set up loop control parameters with
+    /// index n2|u2 and limit n1|u1 and continue executing immediately
+    /// following ?DO. Anything already on the return stack becomes unavailable
+    /// until the loop control parameters are discarded. An ambiguous condition
+    /// exists if n1|u1 and n2|u2 are not both of the same type.
+    ///
+    /// ```
+    ///          +--------------------------+
+    ///          |                          |
+    ///          |                          v
+    /// +------+-+-+-----------+-------+---+--
+    /// | _qdo | x | loop body | _loop | x |
+    /// +------+---+-----------+-------+-+-+--
+    ///          ^
+    ///          |
+    ///          ip
+    /// ```
+    ///
+    fn _qdo(&mut self) {
+        let (n, t) = self.s_stack().pop2();
+        if n == t {
+            self.branch();
+        } else {
+            let ip = self.state().instruction_pointer as isize;
+            self.r_stack().push(ip);
+            self.state().instruction_pointer += mem::size_of::<isize>();
+            let rt = isize::min_value().wrapping_add(t).wrapping_sub(n);
+            let rn = t.wrapping_sub(rt);
+            self.r_stack().push2(rn, rt);
+        }
+    }
+
+    /// Run-time: ( -- ) ( R:  loop-sys1 --  | loop-sys2 )
+    ///
+    /// An ambiguous condition exists if the loop control parameters are
+    /// unavailable. Add one to the loop index. If the loop index is then equal
+    /// to the loop limit, discard the loop parameters and continue execution
+    /// immediately following the loop. Otherwise continue execution at the
+    /// beginning of the loop.
+    fn _loop(&mut self) {
+        let rt = self.r_stack().pop();
+        match rt.checked_add(1) {
+            Some(sum) => {
+                self.r_stack().push(sum);
+                self.branch();
+            }
+            None => {
+                let _ = self.r_stack().pop2();
+                self.state().instruction_pointer += mem::size_of::<isize>();
+            }
+        }
+    }
+
+    /// Run-time: ( n -- ) ( R: loop-sys1 -- | loop-sys2 )
+    ///
+    /// An ambiguous condition exists if the loop control parameters are
+    /// unavailable. Add `n` to the loop index. If the loop index did not cross
+    /// the boundary between the loop limit minus one and the loop limit,
+    /// continue execution at the beginning of the loop. Otherwise, discard the
+    /// current loop control parameters and continue execution immediately
+    /// following the loop.
+    fn _plus_loop(&mut self) {
+        let rt = self.r_stack().pop();
+        let t = self.s_stack().pop();
+        match rt.checked_add(t) {
+            Some(sum) => {
+                self.r_stack().push(sum);
+                self.branch();
+            }
+            None => {
+                let _ = self.r_stack().pop2();
+                self.state().instruction_pointer += mem::size_of::<isize>();
+            }
+        }
+    }
+
+    /// Run-time: ( -- ) ( R: loop-sys -- )
+    ///
+    /// Discard the loop-control parameters for the current nesting level. An
+    /// `UNLOOP` is required for each nesting level before the definition may be
+    /// `EXIT`ed. An ambiguous condition exists if the loop-control parameters
+    /// are unavailable.
+    fn unloop(&mut self) {
+        let _ = self.r_stack().pop3();
+    }
+
+    fn leave(&mut self) {
+        let (third, _, _) = self.r_stack().pop3();
+        if self.r_stack().underflow() {
+            self.abort_with(RETURN_STACK_UNDERFLOW);
+            return;
+        }
+        self.state().instruction_pointer =
+            unsafe { self.data_space().get_isize(third as usize) as usize };
+    }
+
+    fn compile_leave(&mut self, word_idx: usize) {
+        match self.leave_part() {
+            Some(leave_part) => {
+                self.compile_word(word_idx);
+            }
+            _ => {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                return;
+            }
+        };
+    }
+
+    fn p_j(&mut self) {
+        let pos = self.r_stack().len() - 4;
+        match self.r_stack().get(pos) {
+            Some(jt) => match self.r_stack().get(pos - 1) {
+                Some(jn) => {
+                    self.s_stack().push(jt.wrapping_add(jn));
+                }
+                None => self.abort_with(RETURN_STACK_UNDERFLOW),
+            },
+            None => self.abort_with(RETURN_STACK_UNDERFLOW),
+        }
+    }
+
+    fn leave_part(&mut self) -> Option<usize> {
+        let position = self.c_stack().iter().rposition(|&c| match c {
+            Control::Do(_, _) => true,
+            _ => false,
+        });
+        match position {
+            Some(p) => match self.c_stack()[p as u8] {
+                Control::Do(_, leave_part) => Some(leave_part),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// ```
+    /// IF A THEN
+    ///
+    ///         +------+
+    ///         |      |
+    ///         |      v
+    /// +-----+---+---+--
+    /// | _if | x | A |
+    /// +-----+---+---+--
+    ///         ^
+    ///         |
+    ///         ip
+    /// ```
+    fn imm_if(&mut self) {
+        let here = self.compile_zero_branch(0);
+        self.c_stack().push(Control::If(here));
+    }
+
+    /// ```
+    /// IF A ELSE B THEN
+    ///
+    ///             +--------------------+
+    ///             |                    |
+    ///             |                    v
+    /// +---------+---+---+--------+---+---+--
+    /// | ?branch | x | A | branch | x | B |
+    /// +---------+---+---+--------+---+---+--
+    ///             ^                |       ^
+    ///             |                |       |
+    ///             ip               +-------+
+    ///
+    /// ```
+    fn imm_else(&mut self) {
+        let if_part = match self.c_stack().pop() {
+            Control::If(if_part) => if_part,
+            _ => {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                return;
+            }
+        };
+        if self.c_stack().underflow() {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        } else {
+            let here = self.compile_branch(0);
+            self.c_stack().push(Control::Else(here));
+            unsafe {
+                self.data_space()
+                    .put_address(here, if_part - mem::size_of::<isize>());
+            }
+        }
+    }
+
+    fn imm_then(&mut self) {
+        let branch_part = match self.c_stack().pop() {
+            Control::If(branch_part) => branch_part,
+            Control::Else(branch_part) => branch_part,
+            _ => {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                return;
+            }
+        };
+        if self.c_stack().underflow() {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        } else {
+            let here = self.data_space().here();
+            unsafe {
+                self.data_space()
+                    .put_address(here, branch_part - mem::size_of::<isize>());
+            }
+            self.state().literals.clear();
+        }
+    }
+
+    /// ```text
+    /// n1 CASE
+    ///   n2 OF A ENDOF
+    ///   n3 OF B ENDOF
+    ///   C
+    /// ENDCASE
+    /// D
+    ///
+    /// +-----+----+------+---+---------+---+------+---+--------+---+
+    /// | lit | n2 | over | = | 0branch | x | drop | A | branch | x |
+    /// +-----+----+------+---+---------+---+------+---+--------+---+
+    ///                                   |                       |
+    ///   +-------------------------------+                       +--------------+
+    ///   |                                                       |              |
+    ///   v                                                       |              v
+    /// +-----+----+------+---+---------+---+------+---+--------+---+---+------+---+
+    /// | lit | n3 | over | = | 0branch | x | drop | B | branch | x | C | drop | D |
+    /// +-----+----+------+---+---------+---+------+---+--------+---+---+------+---+
+    ///                                   |                           ^
+    ///                                   |                           |
+    ///                                   +---------------------------+
+    ///
+    /// ```
+    fn imm_case(&mut self) {
+        self.c_stack().push(Control::Case);
+    }
+
+    /// Position in the control-flow stack of the `case` being compiled,
+    /// below the `endof`s so far, `None` if there is none.
+    fn case_position(&mut self) -> Option<u8> {
+        for (i, c) in self.c_stack().as_slice().iter().enumerate().rev() {
+            match *c {
+                Control::Endof(_) => {}
+                Control::Case | Control::StringCase => return Some(i as u8),
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    fn imm_of(&mut self) {
+        match self.case_position().map(|i| self.c_stack()[i]) {
+            Some(Control::Case) => {
+                let idx = self.references().idx_over;
+                self.compile_word(idx);
+                let idx = self.references().idx_equal;
+                self.compile_word(idx);
+                let here = self.compile_zero_branch(0);
+                self.c_stack().push(Control::Of(here));
+                let idx = self.references().idx_drop;
+                self.compile_word(idx);
+            }
+            _ => self.abort_with(CONTROL_STRUCTURE_MISMATCH),
+        }
+    }
+
+    /// Compilation: ( "ccc&lt;quote&gt;" -- )
+    ///
+    /// Like `of`, for a `case` selecting on a string `( c-addr u )`. Parse
+    /// ccc delimited by `"`, compiled to be compared with the string. If
+    /// equal, the string is dropped and the words up to `endof` are
+    /// executed. `endcase` drops the string if no `of"` matches, so a
+    /// `case` cannot mix `of` and `of"`.
+    ///
+    /// ```text
+    /// : command ( c-addr u -- )
+    ///   case
+    ///     of" start" start endof
+    ///     of" stop" stop endof
+    ///     2dup type ."  unknown"
+    ///   endcase ;
+    /// ```
+    fn imm_of_quote(&mut self) {
+        let i = match self.case_position() {
+            Some(i) => i,
+            None => return self.abort_with(CONTROL_STRUCTURE_MISMATCH),
+        };
+        if self.c_stack()[i] == Control::Case && self.c_stack().len() > i + 1 {
+            // Numeric `of`s already compiled.
+            return self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        }
+        self.c_stack()[i] = Control::StringCase;
+        if self.state().source_index < self.input_buffer().as_ref().map_or(0, |b| b.len()) {
+            self.state().source_index += 1;
+        }
+        self.s_stack().push('"' as isize);
+        self.parse();
+        let text = self.last_token().take().expect("token");
+        let idx = self.references().idx__of_quote;
+        self.compile_word(idx);
+        self.data_space().compile_str(&text);
+        self.data_space().align();
+        self.set_last_token(text);
+        let here = self.compile_zero_branch(0);
+        self.c_stack().push(Control::Of(here));
+        let idx = self.references().idx_two_drop;
+        self.compile_word(idx);
+    }
+
+    fn imm_endof(&mut self) {
+        let of_part = match self.c_stack().pop() {
+            Control::Of(of_part) => of_part,
+            _ => {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                return;
+            }
+        };
+        if self.c_stack().underflow() {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        } else {
+            let here = self.compile_branch(0);
+            self.c_stack().push(Control::Endof(here));
+            unsafe {
+                self.data_space()
+                    .put_address(here, of_part - mem::size_of::<isize>());
+            }
+        }
+    }
+
+    fn imm_endcase(&mut self) {
+        let idx = match self.case_position().map(|i| self.c_stack()[i]) {
+            Some(Control::StringCase) => self.references().idx_two_drop,
+            _ => self.references().idx_drop,
+        };
+        self.compile_word(idx);
+        loop {
+            let endof_part = match self.c_stack().pop() {
+                Control::Case | Control::StringCase => {
+                    break;
+                }
+                Control::Endof(endof_part) => endof_part,
+                _ => {
+                    self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                    return;
+                }
+            };
+            if self.c_stack().underflow() {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+            } else {
+                let here = self.data_space().here();
+                unsafe {
+                    self.data_space()
+                        .put_address(here, endof_part - mem::size_of::<isize>());
+                }
+            }
+        }
+        if self.c_stack().underflow() {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        }
+    }
+
+    /// Begin a structure that is terminated by `repeat`, `until`, or `again`. `begin ( -- )`.
+    fn imm_begin(&mut self) {
+        self.align_code(0);
+        let here = self.data_space().here();
+        self.c_stack().push(Control::Begin(here));
+        self.state().literals.clear();
+    }
+
+    /// Begin the conditional part of a `begin ... while ... repeat` structure. `while ( flag -- )`.
+    ///
+    /// If all bits of `flag` are zero, continue execution at the location following `repeat`.
+    ///
+    /// ```text
+    /// begin A while B repeat C
+    ///
+    ///                +--------------------+
+    ///                |                    |
+    ///                |                    v
+    /// +---+---------+---+---+--------+---+---+
+    /// | A | 0branch | x | B | branch | x | C |
+    /// +---+---------+---+---+--------+---+---+
+    ///   ^                              |
+    ///   |                              |
+    ///   +------------------------------+
+    ///
+    /// ```
+    fn imm_while(&mut self) {
+        let here = self.compile_zero_branch(0);
+        self.c_stack().push(Control::While(here));
+    }
+
+    /// Terminate a `begin ... while ... repeat` structure. `repeat ( -- )`.
+    ///
+    /// Continue execution at the location following `begin`.
+    fn imm_repeat(&mut self) {
+        let (begin_part, while_part) = match self.c_stack().pop2() {
+            (Control::Begin(begin_part), Control::While(while_part)) => (begin_part, while_part),
+            _ => {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                return;
+            }
+        };
+        if self.c_stack().underflow() {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        } else {
+            let here = self.compile_branch(begin_part);
+            unsafe {
+                self.data_space()
+                    .put_address(here, while_part - mem::size_of::<isize>());
+            }
+        }
+    }
+
+    /// Terminate a `begin ... until` structure. `until ( flag -- )`.
+    ///
+    /// If all bits of `flag` are zero, continue execution at the location following `begin`.
+    ///
+    /// ```text
+    /// begin A until C
+    ///
+    /// +---+---------+---+---+
+    /// | A | 0branch | x | C |
+    /// +---+---------+---+---+
+    ///   ^             |
+    ///   |             |
+    ///   +-------------+
+    ///
+    /// ```
+    fn imm_until(&mut self) {
+        let begin_part = match self.c_stack().pop() {
+            Control::Begin(begin_part) => begin_part,
+            _ => {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                return;
+            }
+        };
+        if self.c_stack().underflow() {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        } else {
+            self.compile_zero_branch(begin_part);
+        }
+    }
+
+    /// Terminate a `begin ... again` structure. `again ( -- )`.
+    ///
+    /// Continue execution at the location following `begin`.
+    ///
+    /// ```
+    /// begin A again C
+    ///
+    /// +---+--------+---+---+
+    /// | A | branch | x | C |
+    /// +---+--------+---+---+
+    ///   ^            |
+    ///   |            |
+    ///   +------------+
+    ///
+    /// ```
+    fn imm_again(&mut self) {
+        let begin_part = match self.c_stack().pop() {
+            Control::Begin(begin_part) => begin_part,
+            _ => {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                return;
+            }
+        };
+        if self.c_stack().underflow() {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        } else {
+            self.compile_branch(begin_part);
+        }
+    }
+
+    /// Clear labels, `0labels ( -- )`
+    ///
+    /// Labels are also cleared at the start of each colon definition.
+    fn imm_clear_labels(&mut self) {
+        self.state().labels.clear();
+    }
+
+    /// Create a label `n`, `label ( n -- )`
+    ///
+    /// Valid `n`: `0 < n < MAX_LABELS`.
+    fn imm_label(&mut self) {
+        let n = self.s_stack().pop() as usize;
+        if 0 < n && n < MAX_LABELS {
+            let here = self.data_space().here();
+            self.state().literals.clear();
+            match self.state().labels.get(n) {
+                Label::Forward(mut p) => {
+                    // Resolve forward references.
+                    loop {
+                        let last = unsafe { self.data_space().get_usize(p) };
+                        unsafe {
+                            self.data_space().put_address(here, p as usize);
+                        }
+                        if last == 0 {
+                            break;
+                        }
+                        p = last;
+                    }
+                    self.state().labels.set(n, Label::Resolved(here));
+                }
+                Label::Resolved(_) => {
+                    self.abort_with(INVALID_NUMERIC_ARGUMENT);
+                }
+                Label::Undefined => {
+                    self.state().labels.set(n, Label::Resolved(here));
+                }
+            }
+        } else {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
+    }
+
+    /// goto ( n -- )
+    ///
+    /// Go to label `n`.
+    ///
+    /// ```
+    /// +--------+---------------------+-----+-------
+    /// | BRANCH | data ptr of label n | ... |  addr at label n
+    /// +--------+---------------------+-----+-------
+    ///             |                             ^
+    ///             +-----------------------------+
+    ///
+    /// [ n ] goto ... [ n ] label ...
+    /// [ n ] label ... [ n ]  goto
+    /// ```
+    fn imm_goto(&mut self) {
+        let n = self.s_stack().pop() as usize;
+        if 0 < n && n < MAX_LABELS {
+            match self.state().labels.get(n) {
+                Label::Forward(p) => {
+                    let to_patch = self.compile_branch(p) - mem::size_of::<isize>();
+                    self.state().labels.set(n, Label::Forward(to_patch));
+                }
+                Label::Resolved(p) => {
+                    let _ = self.compile_branch(p);
+                }
+                Label::Undefined => {
+                    let to_patch = self.compile_branch(0) - mem::size_of::<isize>();
+                    self.state().labels.set(n, Label::Forward(to_patch));
+                }
+            }
+        } else {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
+    }
+
+    /// call ( n -- )
+    ///
+    /// Call subroutine at label `n`.
+    ///
+    /// ```
+    /// +----+-----------------+----+------------+-------------------------+--+------+
+    /// | LIT | return_addr | >R |  BRANCH | data ptr of label n | ... | EXIT |
+    /// +----+-----------------+----+------------+-------------------------+--+------+
+    ///                       |                                                                                       ^
+    ///                       +-------------------------------------------------------+
+    ///
+    /// Usage:
+    ///
+    /// [ n ] call ... [ n ] label ... exit ...
+    /// [ n ] label .. exit ... [ n ] call ...
+    /// ```
+    fn imm_call(&mut self) {
+        let return_addr = self.data_space().here() + 5 * mem::size_of::<isize>();
+        self.compile_integer(return_addr as _);
+        let idx_to_r = self.references().idx_to_r;
+        self.compile_word(idx_to_r);
+        self.imm_goto();
+    }
+
+    /// Compilation: ( n "label1" ... "labeln" -- )
+    ///
+    /// Parse `n` label numbers and append the run-time semantics below.
+    ///
+    /// Run-time: ( i -- )
+    ///
+    /// Go to the `i`-th label, 0-based, in constant time. Continue after
+    /// the table if `i` is out of range.
+    ///
+    /// Interpretation: ( n "name" "word1" ... "wordn" -- )
+    ///
+    /// Define `name ( -- table )` with a table of `n` words, to be
+    /// executed by `jump`.
+    ///
+    /// Usage:
+    ///
+    /// ```text
+    /// : step ( i -- )   [ 2 ] jump-table 10 20  exit
+    ///     [ 10 ] label  ... exit
+    ///     [ 20 ] label  ... ;
+    /// 2 jump-table actions start stop
+    /// 1 actions jump
+    /// ```
+    fn imm_jump_table(&mut self) {
+        let n = self.s_stack().pop();
+        if n < 0 {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+            return;
+        }
+        if self.state().is_compiling {
+            let idx = self.references().idx__jump_table;
+            self.compile_word(idx);
+            self.data_space().compile_isize(n);
+            for _ in 0..n {
+                self.parse_word();
+                let label = match self.last_token().as_ref().map(|t| t.parse::<usize>()) {
+                    Some(Ok(label)) if 0 < label && label < MAX_LABELS => label,
+                    _ => {
+                        self.abort_with(INVALID_NUMERIC_ARGUMENT);
+                        return;
+                    }
+                };
+                let slot = self.data_space().here();
+                match self.state().labels.get(label) {
+                    Label::Forward(p) => {
+                        self.data_space().compile_address(p);
+                        self.state().labels.set(label, Label::Forward(slot));
+                    }
+                    Label::Resolved(p) => {
+                        self.data_space().compile_address(p);
+                    }
+                    Label::Undefined => {
+                        self.data_space().compile_usize(0);
+                        self.state().labels.set(label, Label::Forward(slot));
+                    }
+                }
+            }
+        } else {
+            self.define(Core::p_var, Core::compile_var);
+            if self.last_error().is_some() {
+                return;
+            }
+            self.data_space().compile_isize(n);
+            for _ in 0..n {
+                self.parse_word();
+                let last_token = self.last_token().take().expect("last token");
+                let found = self.find(&last_token);
+                self.set_last_token(last_token);
+                match found {
+                    Some(xt) => self.data_space().compile_usize(xt),
+                    None => {
+                        self.abort_with(UNDEFINED_WORD);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Compile `noop`s until `offset` bytes after `here` is aligned to
+    /// `code-align`.
+    fn align_code(&mut self, offset: usize) {
+        let align = self.wordlist().code_align();
+        if align > mem::size_of::<isize>() {
+            let noop = self.references().idx_noop;
+            while !(self.data_space().here() + offset).is_multiple_of(align) {
+                self.compile_word(noop);
+            }
+        }
+    }
+
+    /// ( n -- )
+    ///
+    /// Align the starts of loops compiled from now on to `n` bytes, for
+    /// example the size of cache lines, so that a tight loop spans as few
+    /// cache lines as possible. `n` is 0 to stop aligning or a power of two
+    /// up to 4096. The `noop`s compiled as padding are executed once upon
+    /// entering the loop.
+    fn p_code_align(&mut self) {
+        let n = self.s_stack().pop();
+        if n == 0 || (n > 0 && n <= 4096 && (n & (n - 1)) == 0) {
+            self.wordlist_mut().code_align = n as usize;
+        } else {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
+    }
+
+    /// Execution: ( -- a-ddr )
+    ///
+    /// Append the run-time semantics of `_do` to the current definition.
+    /// The semantics are incomplete until resolved by `LOOP` or `+LOOP`.
+    ///
+    /// ```text
+    /// +-----+---+--
+    /// | _do | 0 |
+    /// +-----+---+--
+    ///            ^
+    ///            |
+    ///            ++-----+
+    ///             |     |
+    /// Control::Do(here, here)
+    /// ```
+    fn imm_do(&mut self) {
+        self.align_code(2 * mem::size_of::<isize>());
+        let idx = self.references().idx_do;
+        self.compile_word(idx);
+        self.data_space().compile_isize(0);
+        let here = self.data_space().here();
+        self.c_stack().push(Control::Do(here, here));
+    }
+
+    fn imm_recurse(&mut self) {
+        let last = self.wordlist().len() - 1;
+        self.compile_nest(last);
+    }
+
+    /// Execution: ( -- a-ddr )
+    ///
+    /// Append the run-time semantics of `_qdo` to the current definition.
+    /// The semantics are incomplete until resolved by `LOOP` or `+LOOP`.
+    ///
+    /// ```
+    /// +------+---+--
+    /// | _qdo | 0 |
+    /// +------+---+--
+    ///             ^
+    ///             |
+    ///             ++-----+
+    ///              |     |
+    /// Control::Do(here, here)
+    /// ```
+    fn imm_qdo(&mut self) {
+        self.align_code(2 * mem::size_of::<isize>());
+        let idx = self.references().idx_qdo;
+        self.compile_word(idx);
+        self.data_space().compile_isize(0);
+        let here = self.data_space().here();
+        self.c_stack().push(Control::Do(here, here));
+    }
+
+    /// Run-time: ( a-addr -- )
+    ///
+    /// Append the run-time semantics of `_LOOP` to the current definition.
+    /// Resolve the destination of all unresolved occurrences of `LEAVE` between
+    /// the location given by do-sys and the next location for a transfer of
+    /// control, to execute the words following the `LOOP`.
+    ///
+    /// ```
+    /// For DO ... LOOP,
+    ///
+    ///         +--------------------------+
+    ///         |                          |
+    ///         |                          v
+    /// +-----+-+-+-----------+-------+---+--
+    /// | _do | x | loop body | _loop | x |
+    /// +-----+---+-----------+-------+-+-+--
+    ///            ^                    |
+    ///            |                    |
+    ///            ++-------------------+
+    ///             |
+    /// Control::Do(do_part, _)
+    ///
+    /// For ?DO ... LOOP,
+    ///
+    ///          +--------------------------+
+    ///          |                          |
+    ///          |                          v
+    /// +------+-+-+-----------+-------+---+--
+    /// | _qdo | x | loop body | _loop | x |
+    /// +------+---+-----------+-------+-+-+--
+    ///             ^                    |
+    ///             |                    |
+    ///             +--------------------+
+    ///             |
+    /// Control::Do(do_part, _)
+    /// ```
+    fn imm_loop(&mut self) {
+        let do_part = match self.c_stack().pop() {
+            Control::Do(do_part, _) => do_part,
+            _ => {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                return;
+            }
+        };
+        if self.c_stack().underflow() {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        } else {
+            let idx = self.references().idx_loop;
+            self.compile_word(idx);
+            self.data_space().compile_address(do_part);
+            let here = self.data_space().here();
+            unsafe {
+                self.data_space()
+                    .put_address(here, (do_part - mem::size_of::<isize>()) as usize);
+            }
+        }
+    }
+
+    /// Run-time: ( a-addr -- )
+    ///
+    /// Append the run-time semantics of `_+LOOP` to the current definition.
+    /// Resolve the destination of all unresolved occurrences of `LEAVE` between
+    /// the location given by do-sys and the next location for a transfer of
+    /// control, to execute the words following `+LOOP`.
+    fn imm_plus_loop(&mut self) {
+        let do_part = match self.c_stack().pop() {
+            Control::Do(do_part, _) => do_part,
+            _ => {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                return;
+            }
+        };
+        if self.c_stack().underflow() {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        } else {
+            let idx = self.references().idx_plus_loop;
+            self.compile_word(idx);
+            self.data_space().compile_address(do_part);
+            let here = self.data_space().here();
+            unsafe {
+                self.data_space()
+                    .put_address(here, (do_part - mem::size_of::<isize>()) as usize);
+            }
+        }
+    }
+
+    /// Run-time: ( i -- )
+    ///
+    /// Wake and reset task `i`, which then runs the code following
+    /// `activate`. The data stack of task `i` is replaced by the values
+    /// handed over by `pass` since the last activation, the first passed
+    /// at the bottom.
+    fn activate(&mut self) {
+        let i = (self.s_stack().pop() - 1) as usize;
+        if i < NUM_TASKS {
+            // Wake task `i`.
+            self.set_awake(i, true);
+            self.data_space()
+                .system_variables_mut()
+                .set_wait(i, Wait::None);
+            // Reset task `i` and Assign the code following ACTIVATE to task `i`
+            let current_task = self.current_task();
+            let ip = self.state().instruction_pointer;
+            self.set_current_task(i);
+            self.reset();
+            self.s_stack().reset();
+            let passed = mem::take(&mut self.state().passed);
+            for &x in &passed {
+                self.s_stack().push(x);
+            }
+            self.state().instruction_pointer = ip;
+            self.set_current_task(current_task);
+            // Return to caller.
+            let ip = self.r_stack().pop() as usize;
+            self.state().instruction_pointer = ip;
+        } else {
+            let ip = self.r_stack().pop() as usize;
+            self.state().instruction_pointer = ip;
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
+    }
+
+    fn p_i(&mut self) {
+        match self.r_stack().last() {
+            Some(it) => {
+                let next = self.r_stack().len - 2;
+                match self.r_stack().get(next) {
+                    Some(inext) => {
+                        self.s_stack().push(it.wrapping_add(inext));
+                    }
+                    None => self.abort_with(RETURN_STACK_UNDERFLOW),
+                }
+            }
+            None => self.abort_with(RETURN_STACK_UNDERFLOW),
+        }
+    }
+
+    // -----------
+    // Evaluation
+    // -----------
+
+    fn left_bracket(&mut self) {
+        self.state().is_compiling = false;
+    }
+
+    fn right_bracket(&mut self) {
+        self.state().is_compiling = true;
+    }
+
+    /// Copy content of `s` to `input_buffer` and set `source_index` to 0.
+    ///
+    /// `s` is also appended to the transcript, if any, as the input line
+    /// of a host.
+    fn set_source(&mut self, s: &str) {
+        self.transcribe(s.as_bytes());
+        self.transcribe(b"\n");
+        let mut buffer = self.input_buffer().take().expect("input buffer");
+        buffer.clear();
+        buffer.push_str(s);
+        self.state().source_index = 0;
+        self.set_input_buffer(buffer);
+    }
+
+    /// Append `bytes` to the transcript, if any.
+    fn transcribe(&mut self, bytes: &[u8]) {
+        #[cfg(feature = "file-access")]
+        self.transcription_mut().write(bytes);
+        #[cfg(not(feature = "file-access"))]
+        let _ = bytes;
+    }
+
+    /// Push content of `s` to `input_buffer`.
+    fn push_source(&mut self, s: &str) {
+        let mut buffer = self.input_buffer().take().expect("input buffer");
+        buffer.push_str(s);
+        self.set_input_buffer(buffer);
+    }
+
+    /// Run-time: ( "ccc" -- )
+    ///
+    /// Parse word delimited by white space, skipping leading white spaces.
+    fn parse_word(&mut self) {
+        let mut last_token = self.last_token().take().expect("token");
+        last_token.clear();
+        if let Some(input_buffer) = self.input_buffer().take() {
+            if self.state().source_index < input_buffer.len() {
+                let source = &input_buffer[self.state().source_index..];
+                let mut cnt = source.len();
+                let mut char_indices = source.char_indices();
+                loop {
+                    match char_indices.next() {
+                        Some((idx, ch)) => {
+                            match ch {
+                                '\t' | '\n' | '\r' | ' ' => {
+                                    if !last_token.is_empty() {
+                                        cnt = idx;
+                                        break;
+                                    }
+                                }
+                                _ => last_token.push(ch),
+                            };
+                        }
+                        None => {
+                            break;
+                        }
+                    }
+                }
+                self.state().source_index = self.state().source_index + cnt;
+            }
+            self.set_input_buffer(input_buffer);
+        }
+        self.set_last_token(last_token);
+    }
+
+    /// Run-time: ( "&lt;spaces&gt;name" -- char)
+    ///
+    /// Skip leading space delimiters. Parse name delimited by a space.
+    /// Put the value of its first character onto the stack.
+    fn char(&mut self) {
+        self.parse_word();
+        let last_token = self.last_token().take().expect("token");
+        match last_token.chars().nth(0) {
+            Some(c) => {
+                self.set_last_token(last_token);
+                self.s_stack().push(c as isize);
+            }
+            None => {
+                self.set_last_token(last_token);
+                self.abort_with(UNEXPECTED_END_OF_FILE);
+            }
+        }
+    }
+
+    /// Compilation: ( "&lt;spaces&gt;name" -- )
+    ///
+    /// Skip leading space delimiters. Parse name delimited by a space.
+    /// Append the run-time semantics given below to the current definition.
+    ///
+    /// Run-time: ( -- char )
+    ///
+    /// Place `char`, the value of the first character of name, on the stack.
+    fn bracket_char(&mut self) {
+        self.char();
+        if self.last_error().is_some() {
+            return;
+        }
+        let ch = self.s_stack().pop();
+        self.compile_integer(ch);
+    }
+
+    /// Run-time: ( char "ccc&lt;char&gt;" -- )
+    ///
+    /// Parse ccc delimited by the delimiter char.
+    fn parse(&mut self) {
+        let input_buffer = self.input_buffer().take().expect("input buffer");
+        let v = self.s_stack().pop();
+        let mut last_token = self.last_token().take().expect("token");
+        last_token.clear();
+        {
+            let source = &input_buffer[self.state().source_index..];
+            let mut cnt = source.len();
+            let mut char_indices = source.char_indices();
+            loop {
+                match char_indices.next() {
+                    Some((idx, ch)) => {
+                        if ch as isize == v {
+                            match char_indices.next() {
+                                Some((idx, _)) => {
+                                    cnt = idx;
+                                }
+                                None => {}
+                            }
+                            break;
+                        } else {
+                            last_token.push(ch);
+                        }
+                    }
+                    None => {
+                        break;
+                    }
+                }
+            }
+            self.state().source_index = self.state().source_index + cnt;
+        }
+        self.set_last_token(last_token);
+        self.set_input_buffer(input_buffer);
+    }
+
+    /// Run-time: ( char "ccc" -- )
+    ///
+    /// Skip all of the delimiter char.
+    fn _skip(&mut self) {
+        let input_buffer = self.input_buffer().take().expect("input buffer");
+        let v = self.s_stack().pop();
+        {
+            let source = &input_buffer[self.state().source_index..];
+            let mut cnt = 0;
+            let mut char_indices = source.char_indices();
+            loop {
+                match char_indices.next() {
+                    Some((idx, ch)) => {
+                        if ch as isize == v {
+                            cnt += 1;
+                        } else {
+                            break;
+                        }
+                    }
+                    None => {
+                        break;
+                    }
+                }
+            }
+            self.state().source_index = self.state().source_index + cnt;
+        }
+        self.set_input_buffer(input_buffer);
+    }
+
+    fn imm_paren(&mut self) {
+        self.s_stack().push(')' as isize);
+        self.parse();
+    }
+
+    /// Begin a comment that includes the entire remainder of the current line.
+    fn imm_backslash(&mut self) {
+        self.s_stack().push('\n' as isize);
+        self.parse();
+    }
+
+    /// postpone ( "<spaces>name" -- )
+    ///
+    /// Skip leading space delimiters. Parse name delimited by a space.
+    /// Find name. Append the compilation semantics of name to the current definition.
+    ///
+    /// For example:
+    ///
+    /// ```
+    /// : does>   postpone _does  postpone exit ;
+    ///
+    /// +------+-------------+-----------+------+------------+-----------+
+    /// | _lit | xt of _does | _postpone | _lit | xt of exit | _postpone |
+    /// +--------------------+-----------+------+------------+-----------+
+    /// ```
+    fn postpone(&mut self) {
+        self.parse_word();
+        let last_token = self.last_token().take().expect("token");
+        if last_token.is_empty() {
+            self.set_last_token(last_token);
+            self.abort_with(UNEXPECTED_END_OF_FILE);
+        } else {
+            match self.find(&last_token) {
+                Some(xt) => {
+                    self.set_last_token(last_token);
+                    if !self.check_privilege(xt) {
+                        return;
+                    }
+                    self.compile_integer(xt as isize);
+                    let idx = self.references().idx__postpone;
+                    self.compile_word(idx);
+                }
+                None => {
+                    self.set_last_token(last_token);
+                    self.abort_with(UNDEFINED_WORD);
+                }
+            }
+        }
+    }
+
+    /// _POSTPONE ( xt -- )
+    ///
+    /// Execute the compilation semantics of an xt on stack.
+    ///
+    /// _POSTPONE is a hidden word which is only compiled by POSTPONE.
+    fn _postpone(&mut self) {
+        // : A ;
+        // : B   POSTPONE A ;
+        // which generate
+        // --+------+---------+-----------+--
+        //   | _lit | xt of A | _postpone |
+        // --+------+---------+-----------+--
+        // Because B comes after A, the xt of A is valid during execution of B.
+        let xt = self.s_stack().pop() as usize;
+        if self.wordlist()[xt].is_immediate() {
+            self.perform(xt);
+        } else {
+            let compilation_semantics = self.wordlist()[xt].compilation_semantics;
+            compilation_semantics(self, xt);
+        }
+    }
+
+    fn compile_token(&mut self) {
+        let last_token = self.last_token().take().expect("token");
+        match self.find(&last_token) {
+            Some(found_index) => {
+                self.set_last_token(last_token);
+                if !self.check_privilege(found_index) {
+                    return;
+                }
+                let compilation_semantics = self.wordlist()[found_index].compilation_semantics;
+                if !self.wordlist()[found_index].is_immediate() {
+                    compilation_semantics(self, found_index);
+                } else {
+                    self.perform(found_index);
+                }
+            }
+            None => {
+                let mut done = false;
+                self.set_error(None);
+                self.evaluate_integer(&last_token);
+                match self.last_error() {
+                    None => done = true,
+                    Some(_) => {
+                        self.set_error(None);
+                        self.evaluate_float(&last_token);
+                        if self.last_error().is_none() {
+                            done = true;
+                        }
+                    }
+                }
+                self.set_last_token(last_token);
+                if done {
+                    /* Do nothing. */
+                } else {
+                    self.abort_with(UNDEFINED_WORD);
+                }
+            }
+        }
+    }
+
+    fn interpret_token(&mut self) {
+        let last_token = self.last_token().take().expect("last token");
+        match self.find(&last_token) {
+            Some(found_index) => {
+                self.set_last_token(last_token);
+                self.perform(found_index);
+            }
+            None => {
+                let mut done = false;
+                self.set_error(None);
+                self.evaluate_integer(&last_token);
+                match self.last_error() {
+                    None => done = true,
+                    Some(_) => {
+                        self.set_error(None);
+                        self.evaluate_float(&last_token);
+                        if self.last_error().is_none() {
+                            done = true;
+                        }
+                    }
+                }
+                self.set_last_token(last_token);
+                if done {
+                    /* Do nothing. */
+                } else {
+                    self.abort_with(UNDEFINED_WORD);
+                }
+            }
+        }
+    }
+
+    fn p_compiling(&mut self) {
+        let value = if self.state().is_compiling {
+            TRUE
+        } else {
+            FALSE
+        };
+        self.s_stack().push(value);
+    }
+
+    /// Is token empty? `token-empty? ( -- f )
+    fn token_empty(&mut self) {
+        let value = match self.last_token().as_ref() {
+            Some(ref t) => {
+                if t.is_empty() {
+                    TRUE
+                } else {
+                    FALSE
+                }
+            }
+            None => TRUE,
+        };
+        self.s_stack().push(value);
+    }
+
+    /// Print token. `.token ( -- )`
+    fn dot_token(&mut self) {
+        match self.last_token().take() {
+            Some(t) => {
+                match self.output_buffer().as_mut() {
+                    Some(buf) => {
+                        write!(buf, "{}", t).expect("write token");
+                    }
+                    None => {}
+                }
+                self.set_last_token(t);
+            }
+            None => {}
+        }
+    }
+
+    /// Store token. `!token ( c-addr -- )
+    ///
+    /// Store token in counted string at `c-addr`.`
+    fn store_token(&mut self) {
+        let c_addr = self.s_stack().pop() as usize;
+        if self.data_space().start() <= c_addr {
+            match self.last_token().take() {
+                Some(mut t) => {
+                    self.data_space().put_cstr(&t, c_addr);
+                    t.clear();
+                    self.set_last_token(t);
+                }
+                None => unsafe {
+                    if c_addr < self.data_space().limit() {
+                        self.data_space().put_u8(0, c_addr);
+                    } else {
+                        panic!("Error: store_token while space is full.");
+                    }
+                },
+            }
+        } else {
+            self.abort_with(INVALID_MEMORY_ADDRESS);
+        }
+    }
+
+    fn evaluate_input(&mut self) {
+        loop {
+            self.parse_word();
+            match self.last_token().as_ref() {
+                Some(t) => {
+                    if t.is_empty() {
+                        return;
+                    }
+                }
+                None => {}
+            }
+            if self.state().is_compiling {
+                self.compile_token();
+                if self.last_error().is_some() {
+                    break;
+                }
+            } else {
+                self.interpret_token();
+                if self.last_error().is_some() {
+                    break;
+                }
+            }
+            if self.run() == Termination::Bye {
+                break;
+            }
+            self.check_stacks();
+            if self.last_error().is_some() {
+                break;
+            }
+            self.check_low_water();
+        }
+    }
+
+    /// Interpret `script` from the terminal input buffer.
+    fn load_str(&mut self, script: &str) {
+        let mut input_buffer = self.input_buffer().take().unwrap();
+        input_buffer.clear();
+        input_buffer.push_str(script);
+        self.state().source_index = 0;
+        self.set_input_buffer(input_buffer);
+        self.evaluate_input();
+    }
+
+    /// Interpret `src` as `load_str`, but abort with `TIMEOUT` once it has
+    /// run for `budget`, so that snippets from users cannot hang the host.
+    ///
+    /// The budget is checked every `INTERRUPT_PERIOD` words, and upon
+    /// timeout the inner loops are left whatever the error handler does.
+    /// Errors are also left in `last_error`, to be cleared by `reset`.
+    fn evaluate_with_timeout(&mut self, src: &str, budget: Duration) -> Result<(), Exception> {
+        let budget = budget.as_nanos().min(u64::MAX as u128) as u64;
+        let deadline = self.monotonic_ns().saturating_add(budget);
+        let outer = self.wordlist().deadline;
+        self.wordlist_mut().deadline = Some(outer.map_or(deadline, |d| d.min(deadline)));
+        self.load_str(src);
+        self.wordlist_mut().deadline = outer;
+        match self.last_error() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Load `core.fth`, then the Forth part of the optional word sets
+    /// registered to the VM, and finally mark the start of user words
+    /// with `marker -work`. Their definitions are left out of the build
+    /// log.
+    ///
+    /// Panics if any of them cannot be loaded.
+    fn load_core_fth(&mut self) {
+        // Word sets are not registered lazily while loading, which
+        // `load_core_image` would not repeat.
+        let register = self.wordlist_mut().register_lazily.take();
+        let logged = self.wordlist().build_log.len();
+        self.load_fth(include_str!("../core.fth"));
+        if self.find("mtime").is_some() {
+            self.load_fth(include_str!("../facility.fth"));
+        }
+        if self.find("open-file").is_some() {
+            self.load_fth(include_str!("../file-access.fth"));
+        }
+        if self.find("open-source").is_some() {
+            self.load_fth(include_str!("../loader.fth"));
+        }
+        self.load_fth("marker -work");
+        self.wordlist_mut().build_log.truncate(logged);
+        self.wordlist_mut().register_lazily = register;
+    }
+
+    /// Like `load_core_fth`, but copy the dictionary loaded by earlier
+    /// virtual machines of the same type and words, see `CORE_IMAGES`.
+    fn load_core_image(&mut self) {
+        let key = core_image_key(self);
+        let sample = {
+            let images = CORE_IMAGES.lock().unwrap_or_else(|e| e.into_inner());
+            match images.iter().find(|image| image.key == key) {
+                Some(image) => {
+                    if copy_core_image(self, image) {
+                        return;
+                    }
+                    false
+                }
+                None => true,
+            }
+        };
+        if !sample {
+            self.load_core_fth();
+            return;
+        }
+        let below = unsafe {
+            slice::from_raw_parts(self.data_space().start() as *const u8, key.offset).to_vec()
+        };
+        self.data_space().record_addresses();
+        self.load_core_fth();
+        let addresses = self.data_space().take_addresses();
+        let image = sample_core_image(self, key, &below, &addresses);
+        let mut images = CORE_IMAGES.lock().unwrap_or_else(|e| e.into_inner());
+        if !images.iter().any(|sampled| sampled.key == image.key) {
+            images.push(image);
+        }
+    }
+
+    /// Load `script` and panic on error.
+    fn load_fth(&mut self, script: &str) {
+        self.load_str(script);
+        if self.last_error().is_some() {
+            panic!(
+                "Error {:?} {:?}",
+                self.last_error().unwrap(),
+                self.last_token()
+            );
+        }
+    }
+
+    /// Interpret `text` with source id -1, then restore the input
+    /// specification. Upon error, the instruction pointer is left to the
+    /// error handler.
+    ///
+    /// Source id -1 shares the input buffer with the terminal, so the
+    /// content of the buffer is saved too.
+    fn evaluate_str(&mut self, text: &str) {
+        if !self.enter_activation(true) {
+            return;
+        }
+        self.set_input_buffer(text.to_string());
+        self.state().source_index = 0;
+        self.state().instruction_pointer = 0;
+        self.evaluate_input();
+        self.leave_activation();
+    }
+
+    /// Run-time: ( i*x c-addr u -- j*x )
+    ///
+    /// Save the current input source specification, make the string
+    /// described by `c-addr` and `u` the input source with source id -1,
+    /// interpret it. When the string is exhausted, restore the prior input
+    /// source specification.
+    fn evaluate(&mut self) {
+        let (caddr, u) = self.s_stack().pop2();
+        if caddr < 0 || u < 0 {
+            self.abort_with(INVALID_MEMORY_ADDRESS);
+            return;
+        }
+        let text = String::from(unsafe {
+            self.data_space()
+                .str_from_raw_parts(caddr as usize, u as usize)
+        });
+        self.evaluate_str(&text);
+    }
+
+    /// Save the used part of the data space, the dictionary, and the
+    /// stacks of the current task, so that a candidate definition can be
+    /// evaluated and inspected, then thrown away by `discard_fork`.
+    ///
+    /// Compiled code and variables refer to absolute addresses in the data
+    /// space, so the VM is not cloned elsewhere. Its data space is copied
+    /// instead and restored in place. Changes to words defined before the
+    /// fork, such as by `memoize`, and to other tasks are kept. Words
+    /// defined before the fork cannot be forgotten until it is discarded:
+    /// their markers abort with `INVALID_FORGET`.
+    fn fork(&mut self) -> Fork {
+        let start = self.data_space().start();
+        let len = self.data_space().here() - start;
+        let data = unsafe { self.data_space().buffer_from_raw_parts(start, len).to_vec() };
+        let words = self.wordlist().len();
+        let forget_floor = mem::replace(&mut self.wordlist_mut().forget_floor, words);
+        Fork {
+            data,
+            words,
+            last: self.wordlist().last,
+            buckets: self.wordlist().buckets,
+            forget_floor,
+            user_exceptions: self.wordlist().user_exceptions.len(),
+            s_stack: self.s_stack().clone(),
+            r_stack: self.r_stack().clone(),
+            f_stack: self.f_stack().clone(),
+            is_compiling: self.state().is_compiling,
+            instruction_pointer: self.state().instruction_pointer,
+            error: self.last_error(),
+        }
+    }
+
+    /// Restore the VM as saved by `fork`.
+    fn discard_fork(&mut self, fork: Fork) {
+        let start = self.data_space().start();
+        unsafe {
+            self.data_space()
+                .buffer_from_raw_parts_mut(start, fork.data.len())
+                .copy_from_slice(&fork.data);
+        }
+        self.data_space().truncate(start + fork.data.len());
+        self.wordlist_mut().truncate(fork.words);
+        self.wordlist_mut().last = fork.last;
+        self.wordlist_mut().buckets = fork.buckets;
+        self.wordlist_mut().forget_floor = fork.forget_floor;
+        self.wordlist_mut()
+            .user_exceptions
+            .truncate(fork.user_exceptions);
+        *self.s_stack() = fork.s_stack;
+        *self.r_stack() = fork.r_stack;
+        *self.f_stack() = fork.f_stack;
+        self.c_stack().reset();
+        self.state().is_compiling = fork.is_compiling;
+        self.state().instruction_pointer = fork.instruction_pointer;
+        self.set_error(fork.error);
+    }
+
+    /// Run-time: ( i*x c-addr u -- i*x ok? )
+    ///
+    /// Evaluate the string described by `c-addr` and `u` in a fork of the
+    /// VM, see `Core::fork`, then discard all its effects. `ok?` is true if
+    /// the evaluation completed without error.
+    fn sandbox_evaluate(&mut self) {
+        let (caddr, u) = self.s_stack().pop2();
+        if caddr < 0 || u < 0 {
+            self.abort_with(INVALID_MEMORY_ADDRESS);
+            return;
+        }
+        let text = String::from(unsafe {
+            self.data_space()
+                .str_from_raw_parts(caddr as usize, u as usize)
+        });
+        let fork = self.fork();
+        self.evaluate_str(&text);
+        let ok = self.last_error().is_none();
+        self.discard_fork(fork);
+        self.s_stack().push(if ok { TRUE } else { FALSE });
+    }
+
+    fn base(&mut self) {
+        let base_addr = self.data_space().system_variables().base_addr();
+        self.s_stack().push(base_addr as isize);
+    }
+
+    fn evaluate_integer(&mut self, token: &str) {
+        let base_addr = self.data_space().system_variables().base_addr();
+        let default_base = unsafe { self.data_space().get_isize(base_addr) };
+        match parser::quoted_char(&token.as_bytes()) {
+            parser::IResult::Done(_bytes, c) => {
+                if self.state().is_compiling {
+                    self.compile_integer(c);
+                } else {
+                    self.s_stack().push(c);
+                }
+                return;
+            }
+            parser::IResult::Err(_) => {
+                // Do nothing.
+            }
+        }
+        match parser::base(&token.as_bytes(), default_base) {
+            parser::IResult::Done(bytes, base) => match parser::sign(&bytes) {
+                parser::IResult::Done(bytes, sign) => match parser::uint_in_base(&bytes, base) {
+                    parser::IResult::Done(bytes, value) => {
+                        if bytes.len() != 0 {
+                            self.set_error(Some(UNSUPPORTED_OPERATION));
+                        } else {
+                            if self.state().is_compiling {
+                                self.compile_integer(sign.wrapping_mul(value));
+                            } else {
+                                self.s_stack().push(sign.wrapping_mul(value));
+                            }
+                        }
+                    }
+                    parser::IResult::Err(e) => self.set_error(Some(e)),
+                },
+                parser::IResult::Err(e) => {
+                    self.set_error(Some(e));
+                }
+            },
+            parser::IResult::Err(e) => {
+                self.set_error(Some(e));
+            }
+        }
+    }
+
+    /// Evaluate float.
+    fn evaluate_float(&mut self, token: &str) {
+        let significand_sign;
+        let integer_part;
+        let mut fraction_part = 0.0;
+        let mut exponent_sign: isize = 0;
+        let mut exponent_part: isize = 0;
+        let mut failed = false;
+        let mut bytes = token.as_bytes();
+
+        match parser::sign(bytes) {
+            parser::IResult::Done(input, value) => {
+                significand_sign = value;
+                bytes = input;
+            }
+            parser::IResult::Err(e) => {
+                self.set_error(Some(e));
+                return;
+            }
+        }
+
+        let len_before = bytes.len();
+        match parser::uint(bytes) {
+            parser::IResult::Done(input, value) => {
+                integer_part = value;
+                bytes = input;
+            }
+            parser::IResult::Err(e) => {
+                self.set_error(Some(e));
+                return;
+            }
+        }
+        if bytes.len() != len_before {
+            match parser::fraction(bytes) {
+                parser::IResult::Done(input, value) => {
+                    fraction_part = value;
+                    bytes = input;
+                }
+                parser::IResult::Err(e) => {
+                    self.set_error(Some(e));
+                    return;
+                }
+            }
+
+            let len_before = bytes.len();
+            match parser::ascii(bytes, b'E') {
+                parser::IResult::Done(input, value) => {
+                    if value {
+                        match parser::sign(input) {
+                            parser::IResult::Done(input, value) => {
+                                exponent_sign = value;
+                                bytes = input;
+                            }
+                            parser::IResult::Err(e) => {
+                                self.set_error(Some(e));
+                                return;
+                            }
+                        }
+                        match parser::uint(bytes) {
+                            parser::IResult::Done(input, value) => {
+                                exponent_part = value;
+                                bytes = input;
+                            }
+                            parser::IResult::Err(e) => {
+                                self.set_error(Some(e));
+                                return;
+                            }
+                        }
+                    } else {
+                        match parser::ascii(bytes, b'e') {
+                            parser::IResult::Done(input, value) => {
+                                if value {
+                                    match parser::sign(input) {
+                                        parser::IResult::Done(input, value) => {
+                                            exponent_sign = value;
+                                            bytes = input;
+                                        }
+                                        parser::IResult::Err(e) => {
+                                            self.set_error(Some(e));
+                                            return;
+                                        }
+                                    }
+                                    match parser::uint(bytes) {
+                                        parser::IResult::Done(input, value) => {
+                                            exponent_part = value;
+                                            bytes = input;
+                                        }
+                                        parser::IResult::Err(e) => {
+                                            self.set_error(Some(e));
+                                            return;
+                                        }
+                                    }
+                                }
+                            }
+                            parser::IResult::Err(e) => {
+                                self.set_error(Some(e));
+                                return;
+                            }
+                        }
+                    }
+                }
+                parser::IResult::Err(e) => {
+                    self.set_error(Some(e));
+                    return;
+                }
+            }
+
+            if bytes.len() == len_before {
+                failed = true;
+            }
+        } else {
+            failed = true;
+        }
+
+        if bytes.len() != 0 {
+            failed = true;
+        }
+
+        if failed {
+            self.set_error(Some(UNSUPPORTED_OPERATION))
+        } else {
+            if self.references().idx_flit == 0 {
+                self.set_error(Some(UNSUPPORTED_OPERATION));
+            } else {
+                let value = (significand_sign as f64)
+                    * (integer_part as f64 + fraction_part)
+                    * ((10.0f64).powi((exponent_sign.wrapping_mul(exponent_part)) as i32) as f64);
+                if self.state().is_compiling {
+                    self.compile_float(value);
+                } else {
+                    self.f_stack().push(value);
+                }
+            }
+        }
+    }
+
+    // -----------------------
+    // High level definitions
+    // -----------------------
+
+    fn nest(&mut self) {
+        let rlen = self.r_stack().len.wrapping_add(1);
+        self.r_stack().len = rlen;
+        self.r_stack()[rlen.wrapping_sub(1)] = self.state().instruction_pointer as isize;
+        let wp = self.state().word_pointer;
+        self.state().instruction_pointer = self.wordlist()[wp].dfa();
+    }
+
+    fn p_var(&mut self) {
+        let wp = self.state().word_pointer;
+        let dfa = self.wordlist()[wp].dfa() as isize;
+        self.s_stack().push(dfa);
+    }
+
+    fn p_const(&mut self) {
+        let wp = self.state().word_pointer;
+        let dfa = self.wordlist()[wp].dfa();
+        let value = unsafe { self.data_space().get_isize(dfa) as isize };
+        self.s_stack().push(value);
+    }
+
+    /// Action of words defined by `value`, also keeping them from being
+    /// folded like constants.
+    fn p_value(&mut self) {
+        self.p_const();
+    }
+
+    /// Action of words defined by `fvalue`.
+    fn p_fvalue(&mut self) {
+        let wp = self.state().word_pointer;
+        let dfa = DataSpace::aligned_f64(self.wordlist()[wp].dfa());
+        let value = unsafe { self.data_space().get_f64(dfa) };
+        self.f_stack().push(value);
+    }
+
+    /// Define a word named by the next token, with its case preserved for
+    /// display. Lookup by `find` ignores the case.
+    fn define(&mut self, action: fn(&mut Self), compilation_semantics: fn(&mut Self, usize)) {
+        self.parse_word();
+        let last_token = self.last_token().take().expect("last token");
+        let found = self.find(&last_token);
+        let declared = match found {
+            Some(xt) => self.wordlist().forwards.contains(&xt),
+            None => false,
+        };
+        if found.is_some() && !declared {
+            match self.output_buffer().as_mut() {
+                Some(buf) => {
+                    write!(buf, "Redefining {}", last_token).expect("write");
+                }
+                None => {}
+            }
+        }
+        if last_token.is_empty() {
+            self.set_last_token(last_token);
+            self.abort_with(UNEXPECTED_END_OF_FILE);
+        } else {
+            self.log_definition(&last_token);
+            let nfa = self.data_space().compile_str(&last_token);
+            let effect = match self.stack_effect_ahead() {
+                Some(effect) => self.data_space().compile_str(&effect),
+                None => 0,
+            };
+            self.data_space().align();
+            let mut word = Word::new(action, compilation_semantics, nfa, self.data_space().here());
+            word.position = self.source_position();
+            word.effect = effect;
+            self.wordlist_mut().push(&last_token, word);
+            self.set_last_token(last_token);
+            // Colon definitions resolve forward declarations at `;`.
+            if action as usize != Self::nest as fn(&mut Self) as usize {
+                let def = self.wordlist().last;
+                self.resolve_forward(def);
+            }
+        }
+    }
+
+    /// Run-time: ( "<spaces>name" -- )
+    ///
+    /// Declare word `name` to be defined later, so that mutually recursive
+    /// words can refer to each other:
+    ///
+    /// ```forth
+    /// forward odd?
+    /// : even? ( n -- flag )   dup 0= if drop true else 1- odd? then ;
+    /// : odd? ( n -- flag )   dup 0= if drop false else 1- even? then ;
+    /// ```
+    ///
+    /// Until then, executing `name` aborts with `UNDEFINED_WORD`. The next
+    /// definition of `name` resolves it: references compiled in colon
+    /// definitions are patched to the definition, others such as `' name`
+    /// reach it through the declaration.
+    fn forward(&mut self) {
+        self.define(Core::p_forward, Core::compile_word);
+        if self.last_error().is_none() {
+            self.data_space().compile_usize(0);
+            let xt = self.wordlist().last;
+            self.wordlist_mut().forwards.push(xt);
+        }
+    }
+
+    /// Execute the definition resolving the forward declaration, abort with
+    /// `UNDEFINED_WORD` if none yet.
+    fn p_forward(&mut self) {
+        let wp = self.state().word_pointer;
+        let dfa = self.wordlist()[wp].dfa();
+        match unsafe { self.data_space().get_usize(dfa) } {
+            0 => self.abort_with(UNDEFINED_WORD),
+            def => self.execute_word(def),
+        }
+    }
+
+    /// Resolve the pending forward declaration of the name of `def`, if
+    /// any, to `def`, and patch the references to it in the code of colon
+    /// definitions.
+    fn resolve_forward(&mut self, def: usize) {
+        if self.wordlist().forwards.is_empty() {
+            return;
+        }
+        let len = self.wordlist().len();
+        let p_forward = Self::p_forward as fn(&mut Self) as usize;
+        let mut forwards = mem::take(&mut self.wordlist_mut().forwards);
+        // Declarations forgotten by markers.
+        forwards.retain(|&xt| xt < len && self.wordlist()[xt].action() as usize == p_forward);
+        let name = self.word_name(def);
+        let pending = forwards
+            .iter()
+            .position(|&xt| xt != def && self.word_name(xt).eq_ignore_ascii_case(&name));
+        if let Some(i) = pending {
+            let declared = forwards.remove(i);
+            let dfa = self.wordlist()[declared].dfa();
+            unsafe { self.data_space().put_usize(def, dfa) };
+            for xt in 0..len {
+                for i in self.decode(xt).unwrap_or_default() {
+                    if i.xt == declared {
+                        unsafe { self.data_space().put_usize(def, i.addr) };
+                    }
+                }
+            }
+        }
+        self.wordlist_mut().forwards = forwards;
+    }
+
+    /// Stack effect comments following in the input, such as
+    /// `( n -- ) ( F: r -- )`, with spaces collapsed, `None` if the next
+    /// comment has no `--`. The input is left as is.
+    fn stack_effect_ahead(&mut self) -> Option<String> {
+        let input_buffer = self.input_buffer().take()?;
+        let mut effect = String::new();
+        if let Some(mut source) = input_buffer.get(self.state().source_index..) {
+            loop {
+                source = source.trim_start();
+                if !(source.starts_with("( ") || source.starts_with("(\t")) {
+                    break;
+                }
+                let comment = match source.find(')') {
+                    Some(end) if source[..end].contains("--") => &source[..end + 1],
+                    _ => break,
+                };
+                for part in comment.split_whitespace() {
+                    if !effect.is_empty() {
+                        effect.push(' ');
+                    }
+                    effect.push_str(part);
+                }
+                source = &source[comment.len()..];
+            }
+        }
+        self.set_input_buffer(input_buffer);
+        if effect.is_empty() {
+            None
+        } else {
+            Some(effect)
+        }
+    }
+
+    /// Record the definition of `name` beginning at `here` in the build
+    /// log.
+    fn log_definition(&mut self, name: &str) {
+        let event = BuildEvent {
+            name: name.to_string(),
+            position: self.source_position(),
+            start: self.data_space().here(),
+            end: self.data_space().here(),
+            timestamp: self.wallclock_ns(),
+        };
+        self.wordlist_mut().build_log.record(event);
+    }
+
+    /// Position of the input in a source file being loaded, the default if
+    /// the input is not from a file.
+    fn source_position(&mut self) -> SourcePosition {
+        if self.source_id() > 0 {
+            self.state().source_position
+        } else {
+            SourcePosition::default()
+        }
+    }
+
+    fn colon(&mut self) {
+        self.define(Core::nest, Core::compile_nest);
+        if self.last_error().is_none() {
+            let def = self.wordlist().last;
+            self.compile_nest_code(def);
+            self.wordlist_mut()[def].set_hidden(true);
+            self.state().labels.clear();
+            self.state().literals.clear();
+            self.right_bracket();
+        }
+    }
+
+    /// Begin a colon definition without name, ended by `;`. Returns its
+    /// execution token.
+    fn noname(&mut self) -> usize {
+        self.log_definition("");
+        let nfa = self.data_space().compile_str("");
+        self.data_space().align();
+        let mut word = Word::new(
+            Core::nest,
+            Core::compile_nest,
+            nfa,
+            self.data_space().here(),
+        );
+        word.position = self.source_position();
+        self.wordlist_mut().push("", word);
+        let def = self.wordlist().last;
+        self.compile_nest_code(def);
+        self.wordlist_mut()[def].set_hidden(true);
+        self.state().labels.clear();
+        self.state().literals.clear();
+        self.right_bracket();
+        def
+    }
+
+    /// Labels used but not defined in the definition are reported as
+    /// `Unresolved labels: n1 n2 ...` before aborting with
+    /// `CONTROL_STRUCTURE_MISMATCH`.
+    fn semicolon(&mut self) {
+        let unresolved = self.state().labels.unresolved();
+        if self.c_stack().len != 0 {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        } else if !unresolved.is_empty() {
+            if let Some(ref mut buf) = *self.output_buffer() {
+                buf.push_str("Unresolved labels:");
+                for n in unresolved {
+                    write!(buf, " {}", n).unwrap();
+                }
+                buf.push(' ');
+            }
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        } else {
+            let idx = self.references().idx_exit;
+            let compile = self.wordlist()[idx].compilation_semantics;
+            compile(self, idx);
+            let def = self.wordlist().last;
+            self.wordlist_mut()[def].set_hidden(false);
+            self.resolve_forward(def);
+            #[cfg(feature = "jit")]
+            {
+                if self.wordlist().jit().is_enabled() {
+                    self.wordlist_mut()[def].set_action(jit::first_call::<Self>);
+                }
+            }
+        }
+        self.left_bracket();
+    }
+
+    fn create(&mut self) {
+        self.define(Core::p_var, Core::compile_var);
+    }
+
+    fn constant(&mut self) {
+        let v = self.s_stack().pop();
+        self.define(Core::p_const, Core::compile_const);
+        if self.last_error().is_none() {
+            self.data_space().compile_isize(v as isize);
+        }
+    }
+
+    /// Run-time: ( x "<spaces>name" -- )
+    ///
+    /// Define `name ( -- x )` returning `x` until changed by `to`.
+    fn value(&mut self) {
+        let v = self.s_stack().pop();
+        self.define(Core::p_value, Core::compile_word);
+        if self.last_error().is_none() {
+            self.data_space().compile_isize(v);
+        }
+    }
+
+    /// Interpretation: ( x "<spaces>name" -- ) or ( "<spaces>name" -- ) ( F: r -- )
+    ///
+    /// Compilation: ( "<spaces>name" -- )
+    ///
+    /// Store `x` in `name` defined by `value`, or `r` in `name` defined by
+    /// `fvalue`. When compiling, `name` is looked up now and a store to its
+    /// data field is compiled, storing at run time.
+    ///
+    /// Abort with `INVALID_NAME_ARGUMENT` if `name` is not a value.
+    fn to(&mut self) {
+        self.parse_word();
+        let last_token = self.last_token().take().expect("last token");
+        if last_token.is_empty() {
+            self.set_last_token(last_token);
+            return self.abort_with(UNEXPECTED_END_OF_FILE);
+        }
+        let found = self.find(&last_token);
+        self.set_last_token(last_token);
+        let xt = match found {
+            Some(xt) => xt,
+            None => return self.abort_with(UNDEFINED_WORD),
+        };
+        let action = self.wordlist()[xt].action() as usize;
+        let dfa = self.wordlist()[xt].dfa();
+        let (dfa, store) = if action == Self::p_value as fn(&mut Self) as usize {
+            (dfa, self.references().idx_store)
+        } else if action == Self::p_fvalue as fn(&mut Self) as usize {
+            (DataSpace::aligned_f64(dfa), self.references().idx_f_store)
+        } else {
+            return self.abort_with(INVALID_NAME_ARGUMENT);
+        };
+        if self.state().is_compiling {
+            self.compile_integer(dfa as isize);
+            self.compile_word(store);
+        } else if store == self.references().idx_store {
+            let v = self.s_stack().pop();
+            unsafe { self.data_space().put_isize(v, dfa) };
+        } else {
+            let r = self.f_stack().pop();
+            unsafe { self.data_space().put_f64(r, dfa) };
+        }
+    }
+
+    /// Run-time: ( n "<spaces>name" -- )
+    ///
+    /// Define `name ( -- table )` returning a table of `n` execution
+    /// tokens, all `noop` initially, for `xt!`, `xt@` and `dispatch`:
+    ///
+    /// ```forth
+    /// 3 xt-table menu
+    /// ' load 0 menu xt!  ' save 1 menu xt!  ' quit 2 menu xt!
+    /// choice menu dispatch
+    /// ```
+    ///
+    /// The table is the count `n` followed by the execution tokens.
+    fn xt_table(&mut self) {
+        let n = self.s_stack().pop();
+        if n < 0 {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+            return;
+        }
+        self.define(Core::p_var, Core::compile_var);
+        if self.last_error().is_none() {
+            let noop = self.references().idx_noop;
+            self.data_space().compile_isize(n);
+            for _ in 0..n {
+                self.data_space().compile_usize(noop);
+            }
+        }
+    }
+
+    /// Address of entry `i` of the table `table` made by `xt-table`, or
+    /// `INVALID_NUMERIC_ARGUMENT` if `i` is out of the table, or
+    /// `INVALID_MEMORY_ADDRESS` if `table` is not in data space.
+    fn xt_table_entry(&mut self, i: isize, table: usize) -> Result<usize, Exception> {
+        let cell = mem::size_of::<usize>();
+        let (start, here) = (self.data_space().start(), self.data_space().here());
+        if table < start || table.saturating_add(cell) > here {
+            return Err(INVALID_MEMORY_ADDRESS);
+        }
+        let n = unsafe { self.data_space().get_isize(table) };
+        if i < 0 || i >= n {
+            return Err(INVALID_NUMERIC_ARGUMENT);
+        }
+        let entry = table + (i as usize + 1) * cell;
+        if entry + cell > here {
+            return Err(INVALID_MEMORY_ADDRESS);
+        }
+        Ok(entry)
+    }
+
+    /// Run-time: ( xt i table -- )
+    ///
+    /// Store `xt` in entry `i` of `table`. Aborts with
+    /// `INVALID_NUMERIC_ARGUMENT` if `xt` is not an execution token or `i`
+    /// is out of the table.
+    fn xt_store(&mut self) {
+        let (xt, i, table) = self.s_stack().pop3();
+        if self.wordlist().untag_xt(xt).is_none() {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+            return;
+        }
+        match self.xt_table_entry(i, table as usize) {
+            Ok(entry) => unsafe { self.data_space().put_isize(xt, entry) },
+            Err(e) => self.abort_with(e),
+        }
+    }
+
+    /// Run-time: ( i table -- xt )
+    ///
+    /// Execution token in entry `i` of `table`.
+    fn xt_fetch(&mut self) {
+        let (i, table) = self.s_stack().pop2();
+        match self.xt_table_entry(i, table as usize) {
+            Ok(entry) => {
+                let xt = unsafe { self.data_space().get_isize(entry) };
+                self.s_stack().push(xt);
+            }
+            Err(e) => self.abort_with(e),
+        }
+    }
+
+    /// Run-time: ( i*x i table -- j*x )
+    ///
+    /// Execute entry `i` of `table`, checked as by `execute`. Aborts with
+    /// `INVALID_NUMERIC_ARGUMENT` if `i` is out of the table or the word
+    /// stored has been forgotten since.
+    fn dispatch(&mut self) {
+        let (i, table) = self.s_stack().pop2();
+        match self.xt_table_entry(i, table as usize) {
+            Ok(entry) => {
+                let xt = unsafe { self.data_space().get_isize(entry) };
+                match self.wordlist().untag_xt(xt) {
+                    Some(xt) => self.perform(xt),
+                    None => self.abort_with(INVALID_XT),
+                }
+            }
+            Err(e) => self.abort_with(e),
+        }
+    }
+
+    fn unmark(&mut self) {
+        let wp = self.state().word_pointer;
+        if wp < self.wordlist().forget_floor {
+            return self.abort_with(INVALID_FORGET);
+        }
+        let (start, mut dfa) = {
+            let w = &self.wordlist()[wp];
+            (w.start(), w.dfa())
+        };
+        let x = unsafe { self.data_space().get_usize(dfa) };
+        self.wordlist_mut().last = x;
+        for i in 0..BUCKET_SIZE {
+            dfa += mem::size_of::<usize>();
+            let x = unsafe { self.data_space().get_usize(dfa) };
+            self.wordlist_mut().buckets[i] = x;
+        }
+        self.data_space().truncate(start);
+        self.wordlist_mut().truncate(wp);
+    }
+
+    /// Example:
+    /// ```text
+    /// marker -work
+    ///
+    /// DFA of -work
+    /// +------+--------+
+    /// | last | b0-b63 |
+    /// +------+--------+
+    /// ```
+    fn marker(&mut self) {
+        let x = self.wordlist().last;
+        self.wordlist_mut().temp_buckets = self.wordlist().buckets;
+        self.define(Core::unmark, Core::compile_unmark);
+        self.data_space().compile_usize(x);
+        for i in 0..BUCKET_SIZE {
+            let x = self.wordlist().temp_buckets[i];
+            self.data_space().compile_usize(x);
+        }
+    }
+
+    /// Run time behavior of words created by `create` ... `does>`.
+    ///
+    /// Token threaded version.
+    ///
+    /// Example of does>
+    /// ```
+    /// : 2constant   create , , does> 2@ ;
+    /// 4 40 2constant range
+    ///
+    /// 2constant
+    /// +--------+---+---+-------+------+----+------+
+    /// | create | , | , | _does | exit | 2@ | exit |
+    /// +--------+---+---+-------+------+----+------+
+    ///                                   ^
+    /// range                             |
+    ///                                   |
+    ///   action                          |
+    ///   +-------+                       |
+    ///   | xdoes |                       |
+    ///   +-------+                       |
+    ///                                   |
+    ///   doer                            |
+    ///   +------+                        |
+    ///   | x    |------------------------+
+    ///   +------+
+    ///
+    ///   dfa
+    ///   +---+----+
+    ///   | 4 | 40 |
+    ///   +---+----+
+    /// ```
+    fn does(&mut self) {
+        let idx = self.references().idx__does;
+        self.s_stack().push(idx as isize);
+        self.compile_comma();
+        let idx = self.references().idx_exit;
+        self.s_stack().push(idx as isize);
+        self.compile_comma();
+    }
+
+    fn xdoes(&mut self) {
+        // Push DFA.
+        let wp = self.state().word_pointer;
+        let word = &self.wordlist()[wp];
+        let dfa = word.dfa();
+        let doer = word.doer;
+        self.s_stack().push(dfa as isize);
+        // Execute words behind DOES>.
+        let ip = self.state().instruction_pointer as isize;
+        self.r_stack().push(ip);
+        self.state().instruction_pointer = doer;
+    }
+
+    /// Run time behavior of does>.
+    fn _does(&mut self) {
+        let doer = self.state().instruction_pointer + mem::size_of::<isize>();
+        self.data_space().compile_address(doer);
+        let def = self.wordlist().last;
+        let word = &mut self.wordlist_mut()[def];
+        word.action = Core::xdoes;
+        word.doer = doer;
+    }
 
-        // More Primitives
-        self.add_primitive("true", Core::p_true);
-        self.add_primitive("false", Core::p_false);
-        self.add_primitive("not", Core::zero_equals);
-        self.add_primitive("0=", Core::zero_equals);
-        self.add_primitive("0>", Core::zero_greater);
-        self.add_primitive("0<>", Core::zero_not_equals);
-        self.add_primitive(">", Core::greater_than);
-        self.add_primitive("<>", Core::not_equals);
-        self.add_primitive("within", Core::within);
-        self.add_primitive("rot", Core::rot);
-        self.add_primitive("-rot", Core::minus_rot);
-        self.add_primitive("pick", Core::pick);
-        self.add_primitive("2dup", Core::two_dup);
-        self.add_primitive("2drop", Core::two_drop);
-        self.add_primitive("2swap", Core::two_swap);
-        self.add_primitive("2over", Core::two_over);
-        self.add_primitive("/", Core::slash);
-        self.add_primitive("mod", Core::p_mod);
-        self.add_primitive("abs", Core::abs);
-        self.add_primitive("negate", Core::negate);
-        self.add_primitive("parse-word", Core::parse_word);
-        self.add_primitive("char", Core::char);
-        self.add_primitive("_skip", Core::_skip);
-        self.add_primitive("parse", Core::parse);
-        self.add_primitive(":", Core::colon);
-        self.add_primitive("constant", Core::constant);
-        self.add_primitive("create", Core::create);
-        self.add_primitive("'", Core::tick);
-        self.add_primitive(">body", Core::to_body);
-        self.add_primitive(">name", Core::to_name);
-        self.add_primitive("]", Core::right_bracket);
-        self.add_primitive(",", Core::comma);
-        self.add_primitive("marker", Core::marker);
-        self.add_primitive("handler!", Core::handler_store);
-        self.add_primitive("error", Core::error);
-        self.add_primitive(".error", Core::dot_error);
-        self.add_primitive("0error", Core::clear_error);
-        self.add_primitive("0stacks", Core::clear_stacks);
-        self.add_primitive("reset", Core::reset);
-        self.add_primitive("abort", Core::abort);
-        self.add_primitive("compiling?", Core::p_compiling);
-        self.add_primitive("token-empty?", Core::token_empty);
-        self.add_primitive(".token", Core::dot_token);
-        self.add_primitive("!token", Core::store_token);
-        self.add_primitive("compile-token", Core::compile_token);
-        self.add_primitive("interpret-token", Core::interpret_token);
-        self.add_primitive("source-id", Core::p_source_id);
-        self.add_primitive("source-id!", Core::p_set_source_id);
-        self.add_primitive("source-idx", Core::p_source_idx);
-        self.add_primitive("source-idx!", Core::p_set_source_idx);
-        self.add_primitive("bye", Core::bye);
+    // -----------
+    // Primitives
+    // -----------
 
-        self.references().idx_lit = self.find("lit").expect("lit undefined");
-        self.references().idx_flit = self.find("flit").expect("flit undefined");
-        self.references().idx_exit = self.find("exit").expect("exit undefined");
-        self.references().idx_zero_branch = self.find("0branch").expect("0branch undefined");
-        self.references().idx_branch = self.find("branch").expect("branch undefined");
-        self.references().idx_do = self.find("_do").expect("_do undefined");
-        self.references().idx_qdo = self.find("_qdo").expect("_qdo undefined");
-        self.references().idx_loop = self.find("_loop").expect("_loop undefined");
-        self.references().idx_plus_loop = self.find("_+loop").expect("_+loop undefined");
-        self.references().idx_over = self.find("over").expect("over undefined");
-        self.references().idx_equal = self.find("=").expect("= undefined");
-        self.references().idx_drop = self.find("drop").expect("drop undefined");
-        self.references().idx__postpone = self.find("_postpone").expect("_postpone undefined");
-        self.references().idx_to_r = self.find(">r").expect(">r");
-        self.references().idx__does = self.find("_does").expect("_does");
+    /// Run-time: ( -- )
+    ///
+    /// No operation
+    fn noop(&mut self) {
+        // Do nothing
+    }
+
+    /// Run-time: ( -- true )
+    ///
+    /// Return a true flag, a single-cell value with all bits set.
+    fn p_true(&mut self) {
+        self.s_stack().push(TRUE);
+    }
+
+    /// Run-time: ( -- false )
+    ///
+    /// Return a false flag.
+    fn p_false(&mut self) {
+        self.s_stack().push(FALSE);
+    }
+
+    /// Run-time: ( a-addr -- )
+    ///
+    /// Store a true flag at `a-addr`.
+    fn p_on(&mut self) {
+        let a = self.s_stack().pop();
+        self.s_stack().push2(TRUE, a);
+        self.store();
+    }
+
+    /// Run-time: ( a-addr -- )
+    ///
+    /// Store a false flag at `a-addr`.
+    fn p_off(&mut self) {
+        let a = self.s_stack().pop();
+        self.s_stack().push2(FALSE, a);
+        self.store();
+    }
+
+    /// Run-time: ( c-addr1 -- c-addr2 )
+    ///
+    ///Add the size in address units of a character to `c-addr1`, giving `c-addr2`.
+    fn char_plus(&mut self) {
+        let v = self.s_stack().pop();
+        self.s_stack().push(v + mem::size_of::<u8>() as isize);
+    }
+
+    /// Run-time: ( a-addr1 -- a-addr2 )
+    ///
+    /// Add the size in address units of a cell to `a-addr1`, giving `a-addr2`.
+    fn cell_plus(&mut self) {
+        let v = self.s_stack().pop();
+        self.s_stack().push(v + mem::size_of::<isize>() as isize);
+    }
+
+    /// Run-time: ( n1 -- n2 )
+    ///
+    /// `n2` is the size in address units of `n1` cells.
+    fn cells(&mut self) {
+        let v = self.s_stack().pop();
+        self.s_stack().push(v * mem::size_of::<isize>() as isize);
+    }
+
+    fn swap(&mut self) {
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)];
+        let n = self.s_stack()[slen.wrapping_sub(2)];
+        self.s_stack()[slen.wrapping_sub(1)] = n;
+        self.s_stack()[slen.wrapping_sub(2)] = t;
+    }
+
+    fn dup(&mut self) {
+        let slen = self.s_stack().len.wrapping_add(1);
+        self.s_stack().len = slen;
+        self.s_stack()[slen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(2)];
+    }
+
+    fn p_drop(&mut self) {
+        let slen = self.s_stack().len.wrapping_sub(1);
+        self.s_stack().len = slen;
+    }
+
+    fn pop_s_stack(&mut self) -> isize {
+        let slen = self.s_stack().len.wrapping_sub(1);
+        let t = self.s_stack()[slen];
+        self.s_stack().len = slen;
+        t
+    }
+
+    fn nip(&mut self) {
+        let slen = self.s_stack().len.wrapping_sub(1);
+        let t = self.s_stack()[slen];
+        self.s_stack().len = slen;
+        self.s_stack()[slen.wrapping_sub(1)] = t;
+    }
+
+    fn over(&mut self) {
+        let slen = self.s_stack().len.wrapping_add(1);
+        self.s_stack().len = slen;
+        self.s_stack()[slen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(3)];
+    }
+
+    fn rot(&mut self) {
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)];
+        let n = self.s_stack()[slen.wrapping_sub(2)];
+        self.s_stack()[slen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(3)];
+        self.s_stack()[slen.wrapping_sub(2)] = t;
+        self.s_stack()[slen.wrapping_sub(3)] = n;
+    }
+
+    fn minus_rot(&mut self) {
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)];
+        let n = self.s_stack()[slen.wrapping_sub(2)];
+        self.s_stack()[slen.wrapping_sub(2)] = self.s_stack()[slen.wrapping_sub(3)];
+        self.s_stack()[slen.wrapping_sub(3)] = t;
+        self.s_stack()[slen.wrapping_sub(1)] = n;
+    }
+
+    /// Place a copy of the nth stack entry on top of the stack. `pick ( ... n -- x )`
+    ///
+    /// `0 pick` is equivalent to `dup`.
+    fn pick(&mut self) {
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)] as u8;
+        let x = self.s_stack()[slen.wrapping_sub(t.wrapping_add(2))];
+        self.s_stack()[slen.wrapping_sub(1)] = x;
+    }
+
+    fn two_drop(&mut self) {
+        let slen = self.s_stack().len.wrapping_sub(2);
+        self.s_stack().len = slen;
+    }
+
+    fn two_dup(&mut self) {
+        let slen = self.s_stack().len.wrapping_add(2);
+        self.s_stack().len = slen;
+        self.s_stack()[slen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(3)];
+        self.s_stack()[slen.wrapping_sub(2)] = self.s_stack()[slen.wrapping_sub(4)];
+    }
 
-        self.patch_compilation_semanticses();
+    fn two_swap(&mut self) {
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)];
+        let n = self.s_stack()[slen.wrapping_sub(2)];
+        self.s_stack()[slen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(3)];
+        self.s_stack()[slen.wrapping_sub(2)] = self.s_stack()[slen.wrapping_sub(4)];
+        self.s_stack()[slen.wrapping_sub(3)] = t;
+        self.s_stack()[slen.wrapping_sub(4)] = n;
+    }
 
-        {
-            // Multitasker
-            self.add_compile_only("pause", Core::pause);
-            self.add_compile_only("activate", Core::activate);
-            self.add_primitive("me", Core::me);
-            self.add_primitive("suspend", Core::suspend);
-            self.add_primitive("resume", Core::resume);
-        }
-        self.set_awake(0, true);
+    fn two_over(&mut self) {
+        let slen = self.s_stack().len.wrapping_add(2);
+        self.s_stack().len = slen;
+        self.s_stack()[slen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(5)];
+        self.s_stack()[slen.wrapping_sub(2)] = self.s_stack()[slen.wrapping_sub(6)];
     }
 
-    /// Add a primitive word to word list.
-    fn add_primitive(&mut self, name: &str, action: fn(&mut Self)) {
-        let nfa = self.data_space().compile_str(name);
-        self.data_space().align();
-        let word = Word::new(action, Core::compile_word, nfa, self.data_space().here());
-        self.wordlist_mut().push(name, word);
+    fn depth(&mut self) {
+        let len = self.s_stack().len;
+        self.s_stack().push(len as isize);
     }
 
-    /// Set the last definition immediate.
-    fn immediate(&mut self) {
-        let def = self.wordlist().last;
-        self.wordlist_mut()[def].set_immediate(true);
+    /// Abort with `STACK_UNDERFLOW` if fewer than `n` items are on the
+    /// data stack, not counting `n`. `need ( n -- )`
+    ///
+    /// A cheap guard at the start of a word, catching an underflow before
+    /// it corrupts memory.
+    fn need(&mut self) {
+        let n = self.s_stack().pop();
+        if (self.s_stack().len as isize) < n {
+            self.abort_with(STACK_UNDERFLOW);
+        }
     }
 
-    /// Add an immediate word to word list.
-    fn add_immediate(&mut self, name: &str, action: fn(&mut Self)) {
-        self.add_primitive(name, action);
-        self.immediate();
+    /// Abort with `FLOATING_POINT_STACK_UNDERFLOW` if fewer than `n` items
+    /// are on the floating-point stack. `needs-f ( n -- )`
+    fn needs_f(&mut self) {
+        let n = self.s_stack().pop();
+        if (self.f_stack().len as isize) < n {
+            self.abort_with(FLOATING_POINT_STACK_UNDERFLOW);
+        }
     }
 
-    /// Set the last definition compile-only.
-    fn compile_only(&mut self) {
-        let def = self.wordlist().last;
-        self.wordlist_mut()[def].set_compile_only(true);
+    /// Compile `need` or `needs-f`, or nothing but the removal of its
+    /// argument after `guards-off`. A literal argument is not compiled at
+    /// all then.
+    fn compile_guard(&mut self, word_index: usize) {
+        if self.wordlist().stack_guards() {
+            self.compile_word(word_index);
+            return;
+        }
+        let idx_lit = self.references().idx_lit;
+        match self.last_literal() {
+            Some((_, xt)) if xt == idx_lit => self.uncompile(),
+            _ => {
+                let idx_drop = self.references().idx_drop;
+                self.compile_word(idx_drop);
+            }
+        }
     }
 
-    /// Add a compile-only word to word list.
-    fn add_compile_only(&mut self, name: &str, action: fn(&mut Self)) {
-        self.add_primitive(name, action);
-        self.compile_only();
+    fn one_plus(&mut self) {
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)];
+        self.s_stack()[slen.wrapping_sub(1)] = t.wrapping_add(1);
     }
 
-    /// Add an immediate and compile-only word to word list.
-    fn add_immediate_and_compile_only(&mut self, name: &str, action: fn(&mut Self)) {
-        self.add_primitive(name, action);
-        self.immediate();
-        self.compile_only();
+    fn one_minus(&mut self) {
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)];
+        self.s_stack()[slen.wrapping_sub(1)] = t.wrapping_sub(1);
     }
 
-    /// Execute word at position `i`.
-    fn execute_word(&mut self, i: usize) {
-        self.state().word_pointer = i;
-        if i < self.wordlist().len() {
-            (self.wordlist()[i].action())(self);
-        } else {
-            self.abort_with(UNSUPPORTED_OPERATION);
+    /// Wrapping around on overflow unless `Wordlist::strict_math`.
+    fn plus(&mut self) {
+        if self.wordlist().strict_math() {
+            return self.checked_plus();
         }
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)];
+        let n = self.s_stack()[slen.wrapping_sub(2)];
+        self.s_stack()[slen.wrapping_sub(2)] = n.wrapping_add(t);
+        self.s_stack().len = slen.wrapping_sub(1);
     }
 
-    /// Find the word with name `name`.
-    /// If not found returns zero.
-    fn find(&mut self, name: &str) -> Option<usize> {
-        let hash = Wordlist::<Self>::hash(name);
-        let mut w = self.wordlist().buckets[hash as usize % BUCKET_SIZE];
-        while w != 0 {
-            if !self.wordlist()[w].is_hidden() {
-                {
-                    if self.wordlist()[w].hash == hash {
-                        let nfa = self.wordlist()[w].nfa();
-                        let w_name = unsafe { self.data_space().get_str(nfa) };
-                        if w_name.eq_ignore_ascii_case(name) {
-                            return Some(w);
-                        }
-                    }
-                }
-            }
-            w = self.wordlist()[w].link;
+    /// Wrapping around on overflow unless `Wordlist::strict_math`.
+    fn minus(&mut self) {
+        if self.wordlist().strict_math() {
+            return self.checked_minus();
         }
-        None
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)];
+        let n = self.s_stack()[slen.wrapping_sub(2)];
+        self.s_stack()[slen.wrapping_sub(2)] = n.wrapping_sub(t);
+        self.s_stack().len = slen.wrapping_sub(1);
     }
 
-    // -------------------------------
-    // Token threaded code
-    // -------------------------------
-
-    /// Evaluate a compiled program following self.state().instruction_pointer.
-    /// Any exception causes termination of inner loop.
-    #[inline(never)]
-    fn run(&mut self) {
-        let mut ip = self.state().instruction_pointer;
-        while self.data_space().start() <= ip
-            && ip + mem::size_of::<isize>() <= self.data_space().limit()
-        {
-            let w = unsafe { self.data_space().get_isize(ip) as usize };
-            self.state().instruction_pointer += mem::size_of::<isize>();
-            self.execute_word(w);
-            ip = self.state().instruction_pointer;
+    /// Wrapping around on overflow unless `Wordlist::strict_math`.
+    fn star(&mut self) {
+        if self.wordlist().strict_math() {
+            return self.checked_star();
         }
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)];
+        let n = self.s_stack()[slen.wrapping_sub(2)];
+        self.s_stack()[slen.wrapping_sub(2)] = n.wrapping_mul(t);
+        self.s_stack().len = slen.wrapping_sub(1);
     }
 
-    // Execute one step of vm loop.
-    //
-    // Return true if there are more steps to execute, false if otherwise.
-    fn forth(&mut self) -> bool {
-        let mut ip = self.state().instruction_pointer;
-        if self.data_space().start() <= ip
-            && ip + mem::size_of::<isize>() <= self.data_space().limit()
-        {
-            let w = unsafe { self.data_space().get_isize(ip) as usize };
-            self.state().instruction_pointer += mem::size_of::<isize>();
-            self.execute_word(w);
-            ip = self.state().instruction_pointer;
-            true
-        } else {
-            false
+    /// Replace `n1 n2` with `op(n1, n2)`, aborting with
+    /// `RESULT_OUT_OF_RANGE` if it overflows.
+    fn checked_op(&mut self, op: fn(isize, isize) -> Option<isize>) {
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)];
+        let n = self.s_stack()[slen.wrapping_sub(2)];
+        match op(n, t) {
+            Some(r) => {
+                self.s_stack()[slen.wrapping_sub(2)] = r;
+                self.s_stack().len = slen.wrapping_sub(1);
+            }
+            None => self.abort_with(RESULT_OUT_OF_RANGE),
         }
     }
 
-    fn compile_word(&mut self, word_index: usize) {
-        self.data_space().compile_usize(word_index as usize);
+    /// `+` aborting with `RESULT_OUT_OF_RANGE` on overflow.
+    /// `+| ( n1 n2 -- n3 )`
+    fn checked_plus(&mut self) {
+        self.checked_op(isize::checked_add);
     }
 
-    fn compile_nest(&mut self, word_index: usize) {
-        self.compile_word(word_index);
+    /// `-` aborting with `RESULT_OUT_OF_RANGE` on overflow.
+    /// `-| ( n1 n2 -- n3 )`
+    fn checked_minus(&mut self) {
+        self.checked_op(isize::checked_sub);
     }
 
-    fn compile_nest_code(&mut self, _: usize) {
-        // Do nothing.
+    /// `*` aborting with `RESULT_OUT_OF_RANGE` on overflow.
+    /// `*| ( n1 n2 -- n3 )`
+    fn checked_star(&mut self) {
+        self.checked_op(isize::checked_mul);
     }
 
-    fn compile_var(&mut self, word_index: usize) {
-        self.compile_word(word_index);
+    /// Symmetric or floored division, see `Wordlist::floored`.
+    fn slash(&mut self) {
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)];
+        let n = self.s_stack()[slen.wrapping_sub(2)];
+        if t == 0 {
+            self.abort_with(DIVISION_BY_ZERO);
+        } else {
+            let (_, q) = divide(n, t, self.wordlist().floored());
+            self.s_stack()[slen.wrapping_sub(2)] = q;
+            self.s_stack().len = slen.wrapping_sub(1);
+        }
     }
 
-    fn compile_const(&mut self, word_index: usize) {
-        self.compile_word(word_index);
+    fn p_mod(&mut self) {
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)];
+        let n = self.s_stack()[slen.wrapping_sub(2)];
+        if t == 0 {
+            self.abort_with(DIVISION_BY_ZERO);
+        } else {
+            let (r, _) = divide(n, t, self.wordlist().floored());
+            self.s_stack()[slen.wrapping_sub(2)] = r;
+            self.s_stack().len = slen.wrapping_sub(1);
+        }
     }
 
-    fn compile_unmark(&mut self, word_index: usize) {
-        self.compile_word(word_index);
+    fn slash_mod(&mut self) {
+        let slen = self.s_stack().len;
+        let t = self.s_stack()[slen.wrapping_sub(1)];
+        let n = self.s_stack()[slen.wrapping_sub(2)];
+        if t == 0 {
+            self.abort_with(DIVISION_BY_ZERO);
+        } else {
+            let (r, q) = divide(n, t, self.wordlist().floored());
+            self.s_stack()[slen.wrapping_sub(2)] = r;
+            self.s_stack()[slen.wrapping_sub(1)] = q;
+        }
     }
 
-    fn compile_fconst(&mut self, word_index: usize) {
-        self.compile_word(word_index);
+    /// ( n -- d )
+    ///
+    /// Convert `n` to double-cell `d`.
+    fn s_to_d(&mut self) {
+        let n = self.s_stack().pop();
+        self.s_stack().push2(n, if n < 0 { -1 } else { 0 });
     }
 
-    fn lit(&mut self) {
-        let ip = self.state().instruction_pointer;
-        let v = unsafe { self.data_space().get_isize(ip) as isize };
-        let slen = self.s_stack().len.wrapping_add(1);
-        self.s_stack().len = slen;
-        self.s_stack()[slen.wrapping_sub(1)] = v;
-        self.state().instruction_pointer += mem::size_of::<isize>();
+    /// ( d n1 -- n2 n3 )
+    ///
+    /// Divide `d` by `n1` rounding towards negative infinity, giving the
+    /// remainder `n2` and the quotient `n3`.
+    fn fm_slash_mod(&mut self) {
+        self.double_divide(true);
     }
 
-    /// Compile integer `i`.
-    fn compile_integer(&mut self, i: isize) {
-        let idx = self.references().idx_lit;
-        self.compile_word(idx);
-        self.data_space().compile_isize(i as isize);
+    /// ( d n1 -- n2 n3 )
+    ///
+    /// Divide `d` by `n1` rounding towards zero, giving the remainder `n2`
+    /// and the quotient `n3`.
+    fn sm_slash_rem(&mut self) {
+        self.double_divide(false);
     }
 
-    fn flit(&mut self) {
-        let ip = DataSpace::aligned_f64(self.state().instruction_pointer as usize);
-        let v = unsafe { self.data_space().get_f64(ip) };
-        let flen = self.f_stack().len.wrapping_add(1);
-        self.f_stack().len = flen;
-        self.f_stack()[flen.wrapping_sub(1)] = v;
-        self.state().instruction_pointer = ip + mem::size_of::<f64>();
+    fn double_divide(&mut self, floored: bool) {
+        let n = self.s_stack().pop();
+        let (lo, hi) = self.s_stack().pop2();
+        if n == 0 {
+            self.abort_with(DIVISION_BY_ZERO);
+            return;
+        }
+        let d = (hi as i128) << (mem::size_of::<isize>() * 8) | lo as usize as i128;
+        let n = n as i128;
+        let (mut r, mut q) = (d % n, d / n);
+        if floored && r != 0 && (r < 0) != (n < 0) {
+            r += n;
+            q -= 1;
+        }
+        if q as isize as i128 != q {
+            self.abort_with(RESULT_OUT_OF_RANGE);
+        } else {
+            self.s_stack().push2(r as isize, q as isize);
+        }
     }
 
-    /// Compile float 'f'.
-    fn compile_float(&mut self, f: f64) {
-        let idx_flit = self.references().idx_flit;
-        self.compile_word(idx_flit);
-        self.data_space().align_f64();
-        self.data_space().compile_f64(f);
+    fn abs(&mut self) {
+        let t = self.s_stack().pop();
+        self.s_stack().push(t.wrapping_abs());
     }
 
-    /// Runtime of S"
-    fn p_s_quote(&mut self) {
-        let ip = self.state().instruction_pointer;
-        let (addr, cnt) = {
-            let s = unsafe { self.data_space().get_str(ip) };
-            (s.as_ptr() as isize, s.len() as isize)
-        };
-        let slen = self.s_stack().len.wrapping_add(2);
-        self.s_stack().len = slen;
-        self.s_stack()[slen.wrapping_sub(1)] = cnt;
-        self.s_stack()[slen.wrapping_sub(2)] = addr;
-        self.state().instruction_pointer = DataSpace::aligned(
-            self.state().instruction_pointer + mem::size_of::<isize>() + cnt as usize,
-        );
+    fn negate(&mut self) {
+        let t = self.s_stack().pop();
+        self.s_stack().push(t.wrapping_neg());
     }
 
-    fn patch_compilation_semanticses(&mut self) {
-        let idx_leave = self.find("leave").expect("leave");
-        self.wordlist_mut()[idx_leave].compilation_semantics = Self::compile_leave;
+    fn zero_less(&mut self) {
+        let t = self.s_stack().pop();
+        self.s_stack().push(if t < 0 { TRUE } else { FALSE });
     }
 
-    fn branch(&mut self) {
-        let ip = self.state().instruction_pointer;
-        self.state().instruction_pointer = unsafe { self.data_space().get_isize(ip) as usize };
+    fn zero_equals(&mut self) {
+        let t = self.s_stack().pop();
+        self.s_stack().push(if t == 0 { TRUE } else { FALSE });
     }
 
-    fn compile_branch(&mut self, destination: usize) -> usize {
-        let idx = self.references().idx_branch;
-        self.compile_word(idx);
-        self.data_space().compile_isize(destination as isize);
-        self.data_space().here()
+    fn zero_greater(&mut self) {
+        let t = self.s_stack().pop();
+        self.s_stack().push(if t > 0 { TRUE } else { FALSE });
     }
 
-    fn zero_branch(&mut self) {
-        let v = self.s_stack().pop();
-        if v == 0 {
-            self.branch();
-        } else {
-            self.state().instruction_pointer += mem::size_of::<isize>();
-        }
+    fn zero_not_equals(&mut self) {
+        let t = self.s_stack().pop();
+        self.s_stack().push(if t == 0 { FALSE } else { TRUE });
+    }
+
+    fn equals(&mut self) {
+        let (n, t) = self.s_stack().pop2();
+        self.s_stack().push(if t == n { TRUE } else { FALSE });
     }
 
-    fn compile_zero_branch(&mut self, destination: usize) -> usize {
-        let idx = self.references().idx_zero_branch;
-        self.compile_word(idx);
-        self.data_space().compile_isize(destination as isize);
-        self.data_space().here()
+    fn less_than(&mut self) {
+        let (n, t) = self.s_stack().pop2();
+        self.s_stack().push(if n < t { TRUE } else { FALSE });
     }
 
-    /// ( n1|u1 n2|u2 -- ) ( R: -- loop-sys )
-    ///
-    /// Set up loop control parameters with index `n2`|`u2` and limit `n1`|`u1`. An
-    /// ambiguous condition exists if `n1`|`u1` and `n2`|`u2` are not both the same
-    /// type.  Anything already on the return stack becomes unavailable until
-    /// the loop-control parameters are discarded.
-    ///
-    /// ```
-    ///         +--------------------------+
-    ///         |                          |
-    ///         |                          v
-    /// +-----+-+-+-----------+-------+---+--
-    /// | _do | x | loop body | _loop | x |
-    /// +-----+---+-----------+-------+-+-+--
-    ///         ^
-    ///         |
-    ///         ip
-    /// ```
-    ///
-    fn _do(&mut self) {
-        let ip = self.state().instruction_pointer as isize;
-        self.r_stack().push(ip);
-        self.state().instruction_pointer += mem::size_of::<isize>();
+    fn greater_than(&mut self) {
         let (n, t) = self.s_stack().pop2();
-        let rt = isize::min_value().wrapping_add(t).wrapping_sub(n);
-        let rn = t.wrapping_sub(rt);
-        self.r_stack().push2(rn, rt);
+        self.s_stack().push(if n > t { TRUE } else { FALSE });
     }
 
-    /// ( n1|u1 n2|u2 -- ) ( R: -- loop-sys )
-    ///
-    /// If n1|u1 is equal to n2|u2, continue execution at the location given by
-    /// the consumer of do-sys. Otherwise set up loop control parameters with
-    /// index n2|u2 and limit n1|u1 and continue executing immediately
-    /// following ?DO. Anything already on the return stack becomes unavailable
-    /// until the loop control parameters are discarded. An ambiguous condition
-    /// exists if n1|u1 and n2|u2 are not both of the same type.
-    ///
-    /// ```
-    ///          +--------------------------+
-    ///          |                          |
-    ///          |                          v
-    /// +------+-+-+-----------+-------+---+--
-    /// | _qdo | x | loop body | _loop | x |
-    /// +------+---+-----------+-------+-+-+--
-    ///          ^
-    ///          |
-    ///          ip
-    /// ```
-    ///
-    fn _qdo(&mut self) {
+    fn not_equals(&mut self) {
         let (n, t) = self.s_stack().pop2();
-        if n == t {
-            self.branch();
-        } else {
-            let ip = self.state().instruction_pointer as isize;
-            self.r_stack().push(ip);
-            self.state().instruction_pointer += mem::size_of::<isize>();
-            let rt = isize::min_value().wrapping_add(t).wrapping_sub(n);
-            let rn = t.wrapping_sub(rt);
-            self.r_stack().push2(rn, rt);
-        }
+        self.s_stack().push(if n == t { FALSE } else { TRUE });
     }
 
-    /// Run-time: ( -- ) ( R:  loop-sys1 --  | loop-sys2 )
+    /// `within` ( n1 n2 n3 -- flag )  true if n2 <= n1 and n1 < n3.
     ///
-    /// An ambiguous condition exists if the loop control parameters are
-    /// unavailable. Add one to the loop index. If the loop index is then equal
-    /// to the loop limit, discard the loop parameters and continue execution
-    /// immediately following the loop. Otherwise continue execution at the
-    /// beginning of the loop.
-    fn _loop(&mut self) {
-        let rt = self.r_stack().pop();
-        match rt.checked_add(1) {
-            Some(sum) => {
-                self.r_stack().push(sum);
-                self.branch();
-            }
-            None => {
-                let _ = self.r_stack().pop2();
-                self.state().instruction_pointer += mem::size_of::<isize>();
-            }
-        }
+    /// Note: implmenetation incompatible with Forth 2012 standards
+    /// when n2 > n3.
+    fn within(&mut self) {
+        let (x1, x2, x3) = self.s_stack().pop3();
+        self.s_stack()
+            .push(if x2 <= x1 && x1 < x3 { TRUE } else { FALSE });
     }
 
-    /// Run-time: ( n -- ) ( R: loop-sys1 -- | loop-sys2 )
-    ///
-    /// An ambiguous condition exists if the loop control parameters are
-    /// unavailable. Add `n` to the loop index. If the loop index did not cross
-    /// the boundary between the loop limit minus one and the loop limit,
-    /// continue execution at the beginning of the loop. Otherwise, discard the
-    /// current loop control parameters and continue execution immediately
-    /// following the loop.
-    fn _plus_loop(&mut self) {
-        let rt = self.r_stack().pop();
+    fn invert(&mut self) {
         let t = self.s_stack().pop();
-        match rt.checked_add(t) {
-            Some(sum) => {
-                self.r_stack().push(sum);
-                self.branch();
-            }
-            None => {
-                let _ = self.r_stack().pop2();
-                self.state().instruction_pointer += mem::size_of::<isize>();
-            }
-        }
+        self.s_stack().push(!t);
     }
 
-    /// Run-time: ( -- ) ( R: loop-sys -- )
-    ///
-    /// Discard the loop-control parameters for the current nesting level. An
-    /// `UNLOOP` is required for each nesting level before the definition may be
-    /// `EXIT`ed. An ambiguous condition exists if the loop-control parameters
-    /// are unavailable.
-    fn unloop(&mut self) {
-        let _ = self.r_stack().pop3();
+    fn and(&mut self) {
+        let (n, t) = self.s_stack().pop2();
+        self.s_stack().push(t & n);
     }
 
-    fn leave(&mut self) {
-        let (third, _, _) = self.r_stack().pop3();
-        if self.r_stack().underflow() {
-            self.abort_with(RETURN_STACK_UNDERFLOW);
-            return;
-        }
-        self.state().instruction_pointer =
-            unsafe { self.data_space().get_isize(third as usize) as usize };
+    fn or(&mut self) {
+        let (n, t) = self.s_stack().pop2();
+        self.s_stack().push(t | n);
     }
 
-    fn compile_leave(&mut self, word_idx: usize) {
-        match self.leave_part() {
-            Some(leave_part) => {
-                self.compile_word(word_idx);
-            }
-            _ => {
-                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-                return;
-            }
-        };
+    fn xor(&mut self) {
+        let (n, t) = self.s_stack().pop2();
+        self.s_stack().push(t ^ n);
     }
 
-    fn p_j(&mut self) {
-        let pos = self.r_stack().len() - 4;
-        match self.r_stack().get(pos) {
-            Some(jt) => match self.r_stack().get(pos - 1) {
-                Some(jn) => {
-                    self.s_stack().push(jt.wrapping_add(jn));
-                }
-                None => self.abort_with(RETURN_STACK_UNDERFLOW),
-            },
-            None => self.abort_with(RETURN_STACK_UNDERFLOW),
-        }
+    /// Run-time: ( x1 u -- x2 )
+    ///
+    /// Perform a logical left shift of `u` bit-places on `x1`, giving `x2`. Put
+    /// zeroes into the least significant bits vacated by the shift. An
+    /// ambiguous condition exists if `u` is greater than or equal to the number
+    /// of bits in a cell.
+    fn lshift(&mut self) {
+        let (n, t) = self.s_stack().pop2();
+        self.s_stack().push(n.wrapping_shl(t as u32));
     }
 
-    fn leave_part(&mut self) -> Option<usize> {
-        let position = self.c_stack().as_slice().iter().rposition(|&c| match c {
-            Control::Do(_, _) => true,
-            _ => false,
-        });
-        match position {
-            Some(p) => match self.c_stack()[p as u8] {
-                Control::Do(_, leave_part) => Some(leave_part),
-                _ => None,
-            },
-            _ => None,
-        }
+    /// Run-time: ( x1 u -- x2 )
+    ///
+    /// Perform a logical right shift of `u` bit-places on `x1`, giving `x2`. Put
+    /// zeroes into the most significant bits vacated by the shift. An
+    /// ambiguous condition exists if `u` is greater than or equal to the number
+    /// of bits in a cell.
+    fn rshift(&mut self) {
+        let (n, t) = self.s_stack().pop2();
+        self.s_stack()
+            .push(((n as usize).wrapping_shr(t as u32)) as isize);
     }
 
-    /// ```
-    /// IF A THEN
+    /// Interpretation: Interpretation semantics for this word are undefined.
     ///
-    ///         +------+
-    ///         |      |
-    ///         |      v
-    /// +-----+---+---+--
-    /// | _if | x | A |
-    /// +-----+---+---+--
-    ///         ^
-    ///         |
-    ///         ip
-    /// ```
-    fn imm_if(&mut self) {
-        let here = self.compile_zero_branch(0);
-        self.c_stack().push(Control::If(here));
+    /// Execution: ( -- ) ( R: nest-sys -- )
+    /// Return control to the calling definition specified by `nest-sys`.
+    /// Before executing `EXIT` within a do-loop, a program shall discard the
+    /// loop-control parameters by executing `UNLOOP`.
+    ///
+    fn exit(&mut self) {
+        let rlen = self.r_stack().len.wrapping_sub(1);
+        self.state().instruction_pointer = self.r_stack()[rlen] as usize;
+        self.r_stack().len = rlen;
     }
 
-    /// ```
-    /// IF A ELSE B THEN
+    /// Execution: ( -- )
     ///
-    ///             +--------------------+
-    ///             |                    |
-    ///             |                    v
-    /// +---------+---+---+--------+---+---+--
-    /// | ?branch | x | A | branch | x | B |
-    /// +---------+---+---+--------+---+---+--
-    ///             ^                |       ^
-    ///             |                |       |
-    ///             ip               +-------+
+    /// Execute words registered by `at-exit` in reverse order, reset
+    /// the input source of all tasks to the terminal, and set the
+    /// instruction pointer to zero in order to terminate inner interpreter.
+    /// `run` then returns `Termination::Bye`.
     ///
-    /// ```
-    fn imm_else(&mut self) {
-        let if_part = match self.c_stack().pop() {
-            Control::If(if_part) => if_part,
-            _ => {
-                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-                return;
-            }
-        };
-        if self.c_stack().underflow() {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-        } else {
-            let here = self.compile_branch(0);
-            self.c_stack().push(Control::Else(here));
-            unsafe {
-                self.data_space()
-                    .put_isize(here as isize, if_part - mem::size_of::<isize>());
-            }
+    /// Files and sources are closed by at-exit words registered by the
+    /// file access and loader word sets.
+    fn bye(&mut self) {
+        let at_exit = mem::take(&mut self.wordlist_mut().at_exit);
+        for &xt in at_exit.iter().rev() {
+            self.execute_xt(xt);
+        }
+        let current_task = self.current_task();
+        for i in 0..NUM_TASKS {
+            self.set_current_task(i);
+            self.state().source_id = 0;
+        }
+        self.set_current_task(current_task);
+        self.state().bye = true;
+        self.state().instruction_pointer = 0;
+    }
+
+    /// Execute `xt` when `bye`. `at-exit ( xt -- )`
+    fn p_at_exit(&mut self) {
+        let xt = self.s_stack().pop();
+        match self.wordlist().untag_xt(xt) {
+            Some(xt) => self.wordlist_mut().at_exit(xt),
+            None => self.abort_with(INVALID_NUMERIC_ARGUMENT),
         }
     }
 
-    fn imm_then(&mut self) {
-        let branch_part = match self.c_stack().pop() {
-            Control::If(branch_part) => branch_part,
-            Control::Else(branch_part) => branch_part,
-            _ => {
-                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-                return;
-            }
-        };
-        if self.c_stack().underflow() {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+    /// Run-time: ( a-addr -- x )
+    ///
+    /// `x` is the value stored at `a-addr`.
+    fn fetch(&mut self) {
+        let t = self.s_stack().pop() as usize;
+        if self.data_space().start() < t && t + mem::size_of::<isize>() <= self.data_space().limit()
+        {
+            let value = unsafe { self.data_space().get_isize(t as usize) as isize };
+            self.s_stack().push(value);
         } else {
-            let here = self.data_space().here();
-            unsafe {
-                self.data_space()
-                    .put_isize(here as isize, branch_part - mem::size_of::<isize>());
-            }
+            self.abort_with(INVALID_MEMORY_ADDRESS);
         }
     }
 
-    /// ```text
-    /// n1 CASE
-    ///   n2 OF A ENDOF
-    ///   n3 OF B ENDOF
-    ///   C
-    /// ENDCASE
-    /// D
-    ///
-    /// +-----+----+------+---+---------+---+------+---+--------+---+
-    /// | lit | n2 | over | = | 0branch | x | drop | A | branch | x |
-    /// +-----+----+------+---+---------+---+------+---+--------+---+
-    ///                                   |                       |
-    ///   +-------------------------------+                       +--------------+
-    ///   |                                                       |              |
-    ///   v                                                       |              v
-    /// +-----+----+------+---+---------+---+------+---+--------+---+---+------+---+
-    /// | lit | n3 | over | = | 0branch | x | drop | B | branch | x | C | drop | D |
-    /// +-----+----+------+---+---------+---+------+---+--------+---+---+------+---+
-    ///                                   |                           ^
-    ///                                   |                           |
-    ///                                   +---------------------------+
+    /// Run-time: ( x a-addr -- )
     ///
-    /// ```
-    fn imm_case(&mut self) {
-        self.c_stack().push(Control::Case);
+    /// Store `x` at `a-addr`.
+    fn store(&mut self) {
+        let (n, t) = self.s_stack().pop2();
+        let t = t as usize;
+        if self.data_space().start() < t && t + mem::size_of::<isize>() <= self.data_space().limit()
+        {
+            unsafe { self.data_space().put_isize(n as isize, t as usize) };
+        } else {
+            self.abort_with(INVALID_MEMORY_ADDRESS);
+        }
     }
 
-    fn imm_of(&mut self) {
-        match self.c_stack().pop() {
-            Control::Case => {
-                self.c_stack().push(Control::Case);
-            }
-            Control::Endof(n) => {
-                self.c_stack().push(Control::Endof(n));
-            }
-            _ => {
-                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-                return;
-            }
-        };
-        if self.c_stack().underflow() {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+    /// Run-time: ( c-addr -- char )
+    ///
+    /// Fetch the character stored at `c-addr`. When the cell size is greater than
+    /// character size, the unused high-order bits are all zeroes.
+    fn c_fetch(&mut self) {
+        let t = self.s_stack().pop() as usize;
+        if self.data_space().start() <= t && t < self.data_space().limit() {
+            let value = unsafe { self.data_space().get_u8(t as usize) as isize };
+            self.s_stack().push(value);
         } else {
-            let idx = self.references().idx_over;
-            self.compile_word(idx);
-            let idx = self.references().idx_equal;
-            self.compile_word(idx);
-            let here = self.compile_zero_branch(0);
-            self.c_stack().push(Control::Of(here));
-            let idx = self.references().idx_drop;
-            self.compile_word(idx);
+            self.abort_with(INVALID_MEMORY_ADDRESS);
         }
     }
 
-    fn imm_endof(&mut self) {
-        let of_part = match self.c_stack().pop() {
-            Control::Of(of_part) => of_part,
-            _ => {
-                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-                return;
-            }
-        };
-        if self.c_stack().underflow() {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+    /// Run-time: ( char c-addr -- )
+    ///
+    /// Store `char` at `c-addr`. When character size is smaller than cell size,
+    /// only the number of low-order bits corresponding to character size are
+    /// transferred.
+    fn c_store(&mut self) {
+        let (n, t) = self.s_stack().pop2();
+        let t = t as usize;
+        if self.data_space().start() < t && t < self.data_space().limit() {
+            unsafe { self.data_space().put_u8(n as u8, t as usize) };
         } else {
-            let here = self.compile_branch(0);
-            self.c_stack().push(Control::Endof(here));
-            unsafe {
-                self.data_space()
-                    .put_isize(here as isize, of_part - mem::size_of::<isize>());
-            }
+            self.abort_with(INVALID_MEMORY_ADDRESS);
         }
     }
 
-    fn imm_endcase(&mut self) {
-        let idx = self.references().idx_drop;
-        self.compile_word(idx);
-        loop {
-            let endof_part = match self.c_stack().pop() {
-                Control::Case => {
-                    break;
-                }
-                Control::Endof(endof_part) => endof_part,
-                _ => {
-                    self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-                    return;
-                }
-            };
-            if self.c_stack().underflow() {
-                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-            } else {
-                let here = self.data_space().here();
+    /// Run-time: ( addr1 addr2 u -- )
+    ///
+    /// If u is greater than zero, copy the contents of u consecutive address
+    /// units at addr1 to the u consecutive address units at addr2. After MOVE
+    /// completes, the u consecutive address units at addr2 contain exactly
+    /// what the u consecutive address units at addr1 contained before the
+    /// move.
+    fn p_move(&mut self) {
+        let (addr1, addr2, u) = self.s_stack().pop3();
+        if u > 0 {
+            let u = u as usize;
+            let addr1 = addr1 as usize;
+            let addr2 = addr2 as usize;
+            if self.data_space().start() < addr1
+                && addr1 + u <= self.data_space().limit()
+                && self.data_space().start() < addr2
+                && addr2 + u <= self.data_space().limit()
+            {
                 unsafe {
-                    self.data_space()
-                        .put_isize(here as isize, endof_part - mem::size_of::<isize>());
+                    if addr1 < addr2 {
+                        for p in (addr1..(addr1 + u))
+                            .into_iter()
+                            .zip(addr2..(addr2 + u))
+                            .rev()
+                        {
+                            let value = self.data_space().get_u8(p.0);
+                            self.data_space().put_u8(value, p.1);
+                        }
+                    } else {
+                        for p in (addr1..(addr1 + u)).into_iter().zip(addr2..(addr2 + u)) {
+                            let value = self.data_space().get_u8(p.0);
+                            self.data_space().put_u8(value, p.1);
+                        }
+                    }
                 }
+            } else {
+                self.abort_with(INVALID_MEMORY_ADDRESS);
             }
         }
-        if self.c_stack().underflow() {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-        }
-    }
-
-    /// Begin a structure that is terminated by `repeat`, `until`, or `again`. `begin ( -- )`.
-    fn imm_begin(&mut self) {
-        let here = self.data_space().here();
-        self.c_stack().push(Control::Begin(here));
     }
 
-    /// Begin the conditional part of a `begin ... while ... repeat` structure. `while ( flag -- )`.
-    ///
-    /// If all bits of `flag` are zero, continue execution at the location following `repeat`.
-    ///
-    /// ```text
-    /// begin A while B repeat C
-    ///
-    ///                +--------------------+
-    ///                |                    |
-    ///                |                    v
-    /// +---+---------+---+---+--------+---+---+
-    /// | A | 0branch | x | B | branch | x | C |
-    /// +---+---------+---+---+--------+---+---+
-    ///   ^                              |
-    ///   |                              |
-    ///   +------------------------------+
+    /// Run-time: ( "<spaces>name" -- xt )
     ///
-    /// ```
-    fn imm_while(&mut self) {
-        let here = self.compile_zero_branch(0);
-        self.c_stack().push(Control::While(here));
-    }
-
-    /// Terminate a `begin ... while ... repeat` structure. `repeat ( -- )`.
+    /// Skip leading space delimiters. Parse name delimited by a space. Find
+    /// `name` and return `xt`, the execution token for name. An ambiguous
+    /// condition exists if name is not found.
     ///
-    /// Continue execution at the location following `begin`.
-    fn imm_repeat(&mut self) {
-        let (begin_part, while_part) = match self.c_stack().pop2() {
-            (Control::Begin(begin_part), Control::While(while_part)) => (begin_part, while_part),
-            _ => {
-                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-                return;
-            }
-        };
-        if self.c_stack().underflow() {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+    /// With `strict-tick-on`, compile-only words abort with
+    /// `INTERPRETING_A_COMPILE_ONLY_WORD`.
+    fn tick(&mut self) {
+        self.parse_word();
+        let last_token = self.last_token().take().expect("last token");
+        if last_token.is_empty() {
+            self.set_last_token(last_token);
+            self.abort_with(UNEXPECTED_END_OF_FILE);
         } else {
-            let here = self.compile_branch(begin_part);
-            unsafe {
-                self.data_space()
-                    .put_isize(here as isize, while_part - mem::size_of::<isize>());
+            match self.find(&last_token) {
+                Some(found_index) => {
+                    self.set_last_token(last_token);
+                    if self.tick_rejects(found_index) {
+                        self.abort_with(INTERPRETING_A_COMPILE_ONLY_WORD);
+                    } else {
+                        let xt = self.wordlist().tag_xt(found_index);
+                        self.s_stack().push(xt);
+                    }
+                }
+                None => {
+                    self.set_last_token(last_token);
+                    self.abort_with(UNDEFINED_WORD);
+                }
             }
         }
     }
 
-    /// Terminate a `begin ... until` structure. `until ( flag -- )`.
-    ///
-    /// If all bits of `flag` are zero, continue execution at the location following `begin`.
-    ///
-    /// ```text
-    /// begin A until C
-    ///
-    /// +---+---------+---+---+
-    /// | A | 0branch | x | C |
-    /// +---+---------+---+---+
-    ///   ^             |
-    ///   |             |
-    ///   +-------------+
-    ///
-    /// ```
-    fn imm_until(&mut self) {
-        let begin_part = match self.c_stack().pop() {
-            Control::Begin(begin_part) => begin_part,
-            _ => {
-                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-                return;
+    /// ( xt -- a-addr )
+    /// a-addr is the data-field address corresponding to xt. An ambiguous
+    /// condition exists if xt is not for a word defined via CREATE.
+    fn to_body(&mut self) {
+        let t = self.s_stack().pop();
+        match self.wordlist().untag_xt(t) {
+            Some(xt) => {
+                let dfa = self.wordlist()[xt].dfa() as isize;
+                self.s_stack().push(dfa);
+            }
+            None => self.abort_with(INVALID_NUMERIC_ARGUMENT),
+        }
+    }
+
+    /// ( xt -- a-addr )
+    /// a-addr is the name-field address corresponding to xt.
+    fn to_name(&mut self) {
+        let t = self.s_stack().pop();
+        match self.wordlist().untag_xt(t) {
+            Some(xt) => {
+                let nfa = self.wordlist()[xt].nfa() as isize;
+                self.s_stack().push(nfa);
             }
-        };
-        if self.c_stack().underflow() {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-        } else {
-            self.compile_zero_branch(begin_part);
+            None => self.abort_with(INVALID_NUMERIC_ARGUMENT),
         }
     }
 
-    /// Terminate a `begin ... again` structure. `again ( -- )`.
-    ///
-    /// Continue execution at the location following `begin`.
-    ///
-    /// ```
-    /// begin A again C
+    /// True if `'` and `[']` reject word `xt`, see `strict-tick-on`.
+    fn tick_rejects(&self, xt: usize) -> bool {
+        self.wordlist().strict_tick() && self.wordlist()[xt].is_compile_only()
+    }
+
+    /// Run-time: ( i*x xt -- j*x )
     ///
-    /// +---+--------+---+---+
-    /// | A | branch | x | C |
-    /// +---+--------+---+---+
-    ///   ^            |
-    ///   |            |
-    ///   +------------+
+    /// Remove `xt` from the stack and perform the semantics identified by it.
+    /// Other stack effects are due to the word `EXECUTE`d.
     ///
-    /// ```
-    fn imm_again(&mut self) {
-        let begin_part = match self.c_stack().pop() {
-            Control::Begin(begin_part) => begin_part,
-            _ => {
-                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-                return;
-            }
-        };
-        if self.c_stack().underflow() {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-        } else {
-            self.compile_branch(begin_part);
+    /// Compile-only words abort unless compiling, see `Core::perform`.
+    /// Execution tokens of no word, or of a word forgotten since, abort
+    /// with `INVALID_XT`, see `Wordlist::checked_xts`.
+    fn execute(&mut self) {
+        let t = self.s_stack().pop();
+        match self.wordlist().untag_xt(t) {
+            Some(xt) => self.perform(xt),
+            None => self.abort_with(INVALID_XT),
         }
     }
 
-    /// Clear labels, `0labels ( -- )`
-    fn imm_clear_labels(&mut self) {
-        self.forward_bitset_mut().clear();
-        self.resolved_bitset_mut().clear();
+    /// Execute word `xt` found or given by the program, checking that it
+    /// may be performed in the current state.
+    ///
+    /// Compile-only words, whose interpretation semantics are undefined,
+    /// abort with `INTERPRETING_A_COMPILE_ONLY_WORD` unless compiling, and
+    /// privileged words with `PRIVILEGED_WORD` while the session is
+    /// locked. Used by the text interpreter, `compile-token`, `_postpone`,
+    /// `execute`, `dispatch` and `jump`, so that words are checked alike
+    /// whichever way they are reached.
+    fn perform(&mut self, xt: usize) {
+        if xt < self.wordlist().len() && !self.check_privilege(xt) {
+            return;
+        }
+        if xt < self.wordlist().len()
+            && self.wordlist()[xt].is_compile_only()
+            && !self.state().is_compiling
+        {
+            self.abort_with(INTERPRETING_A_COMPILE_ONLY_WORD);
+        } else {
+            self.execute_word(xt);
+        }
     }
 
-    /// Create a label `n`, `label ( n -- )`
+    /// Run-time: ( i*x i table -- j*x )
     ///
-    /// Valid `n`: `0 < n < labels.capacity()`.
-    fn imm_label(&mut self) {
-        let n = self.s_stack().pop() as usize;
-        if 0 < n && n < self.labels().capacity() {
-            let here = self.data_space().here();
-            if self.forward_bitset().contains(n as u32) {
-                // Resolve forward references.
-                let mut p = self.labels()[n];
-                loop {
-                    let last = unsafe { self.data_space().get_usize(p) };
-                    unsafe {
-                        self.data_space().put_isize(here as isize, p as usize);
-                    }
-                    if last == 0 {
-                        break;
-                    }
-                    p = last;
+    /// Execute the `i`-th word, 0-based, of `table` defined by
+    /// `jump-table`. Aborts with `INVALID_NUMERIC_ARGUMENT` if `i` is out
+    /// of the table, or `INVALID_MEMORY_ADDRESS` if the entry is not in
+    /// data space.
+    fn jump(&mut self) {
+        let (i, table) = self.s_stack().pop2();
+        let table = table as usize;
+        let cell = mem::size_of::<isize>();
+        let (start, here) = (self.data_space().start(), self.data_space().here());
+        let valid_table = start <= table && table.saturating_add(cell) <= here;
+        let n = if valid_table {
+            unsafe { self.data_space().get_isize(table) }
+        } else {
+            0
+        };
+        if 0 <= i && i < n {
+            let entry = (i as usize + 1)
+                .checked_mul(cell)
+                .and_then(|offset| table.checked_add(offset))
+                .filter(|entry| entry.saturating_add(cell) <= here);
+            let entry = match entry {
+                Some(entry) => entry,
+                None => {
+                    self.abort_with(INVALID_MEMORY_ADDRESS);
+                    return;
                 }
-                self.labels_mut()[n] = here;
-                self.forward_bitset_mut().remove(n as u32);
-                self.resolved_bitset_mut().add(n as u32);
-            } else if self.resolved_bitset().contains(n as u32) {
-                self.abort_with(INVALID_NUMERIC_ARGUMENT);
-            } else {
-                self.labels_mut()[n] = here;
-                self.resolved_bitset_mut().add(n as u32);
+            };
+            let xt = unsafe { self.data_space().get_isize(entry) };
+            match self.wordlist().untag_xt(xt) {
+                Some(xt) => self.perform(xt),
+                None => self.abort_with(INVALID_XT),
             }
         } else {
             self.abort_with(INVALID_NUMERIC_ARGUMENT);
         }
     }
 
-    /// goto ( n -- )
-    ///
-    /// Go to label `n`.
-    ///
-    /// ```
-    /// +--------+---------------------+-----+-------
-    /// | BRANCH | data ptr of label n | ... |  addr at label n
-    /// +--------+---------------------+-----+-------
-    ///             |                             ^
-    ///             +-----------------------------+
+    /// Compilation: ( "<spaces>name" -- )
+    /// Run-time: ( -- xt )
     ///
-    /// [ n ] goto ... [ n ] label ...
-    /// [ n ] label ... [ n ]  goto
-    /// ```
-    fn imm_goto(&mut self) {
-        let n = self.s_stack().pop() as usize;
-        if 0 < n && n < self.labels().capacity() {
-            if self.forward_bitset().contains(n as u32) {
-                let p = self.labels()[n];
-                let to_patch = self.compile_branch(p) - mem::size_of::<isize>();
-                self.labels_mut()[n] = to_patch;
-            } else if self.resolved_bitset().contains(n as u32) {
-                let p = self.labels()[n];
-                let _ = self.compile_branch(p);
-            } else {
-                let to_patch = self.compile_branch(0) - mem::size_of::<isize>();
-                self.labels_mut()[n] = to_patch;
-                self.forward_bitset_mut().add(n as u32);
-            }
+    /// Forth 2012 6.1.2510
+    fn bracket_tick(&mut self) {
+        self.parse_word();
+        let last_token = self.last_token().take().expect("last token");
+        if last_token.is_empty() {
+            self.set_last_token(last_token);
+            self.abort_with(UNEXPECTED_END_OF_FILE);
         } else {
-            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+            match self.find(&last_token) {
+                Some(found_index) => {
+                    self.set_last_token(last_token);
+                    if self.tick_rejects(found_index) {
+                        self.abort_with(INTERPRETING_A_COMPILE_ONLY_WORD);
+                    } else {
+                        let xt = self.wordlist().tag_xt(found_index);
+                        self.compile_integer(xt);
+                    }
+                }
+                None => {
+                    self.set_last_token(last_token);
+                    self.abort_with(UNDEFINED_WORD);
+                }
+            }
         }
     }
 
-    /// call ( n -- )
-    ///
-    /// Call subroutine at label `n`.
-    ///
-    /// ```
-    /// +----+-----------------+----+------------+-------------------------+--+------+
-    /// | LIT | return_addr | >R |  BRANCH | data ptr of label n | ... | EXIT |
-    /// +----+-----------------+----+------------+-------------------------+--+------+
-    ///                       |                                                                                       ^
-    ///                       +-------------------------------------------------------+
-    ///
-    /// Usage:
+    /// Execution: ( xt -- )
     ///
-    /// [ n ] call ... [ n ] label ... exit ...
-    /// [ n ] label .. exit ... [ n ] call ...
-    /// ```
-    fn imm_call(&mut self) {
-        let return_addr = self.data_space().here() + 5 * mem::size_of::<isize>();
-        self.compile_integer(return_addr as _);
-        let idx_to_r = self.references().idx_to_r;
-        self.compile_word(idx_to_r);
-        self.imm_goto();
+    /// Forth 2012 6.2.0945
+    /// Append the execution semantics of the definition represented by xt to the execution semantics of the current definition.
+    fn compile_comma(&mut self) {
+        let v = self.s_stack().pop();
+        match self.wordlist().untag_xt(v) {
+            Some(xt) => {
+                if self.check_privilege(xt) {
+                    self.data_space().compile_usize(xt);
+                }
+            }
+            None => self.abort_with(INVALID_XT),
+        }
     }
 
-    /// Execution: ( -- a-ddr )
-    ///
-    /// Append the run-time semantics of `_do` to the current definition.
-    /// The semantics are incomplete until resolved by `LOOP` or `+LOOP`.
+    /// Run-time: ( -- addr )
     ///
-    /// ```
-    /// +-----+---+--
-    /// | _do | 0 |
-    /// +-----+---+--
-    ///            ^
-    ///            |
-    ///            ++-----+
-    ///             |     |
-    /// Control::Do(here, here)
-    /// ```
-    fn imm_do(&mut self) {
-        let idx = self.references().idx_do;
-        self.compile_word(idx);
-        self.data_space().compile_isize(0);
-        let here = self.data_space().here();
-        self.c_stack().push(Control::Do(here, here));
-    }
-
-    fn imm_recurse(&mut self) {
-        let last = self.wordlist().len() - 1;
-        self.compile_nest(last);
+    /// `addr` is the data-space pointer.
+    fn here(&mut self) {
+        let here = self.data_space().here() as isize;
+        self.s_stack().push(here);
     }
 
-    /// Execution: ( -- a-ddr )
-    ///
-    /// Append the run-time semantics of `_qdo` to the current definition.
-    /// The semantics are incomplete until resolved by `LOOP` or `+LOOP`.
+    /// Run-time: ( -- u )
     ///
-    /// ```
-    /// +------+---+--
-    /// | _qdo | 0 |
-    /// +------+---+--
-    ///             ^
-    ///             |
-    ///             ++-----+
-    ///              |     |
-    /// Control::Do(here, here)
-    /// ```
-    fn imm_qdo(&mut self) {
-        let idx = self.references().idx_qdo;
-        self.compile_word(idx);
-        self.data_space().compile_isize(0);
-        let here = self.data_space().here();
-        self.c_stack().push(Control::Do(here, here));
+    /// `u` is the amount of data space remaining in address units.
+    fn unused(&mut self) {
+        let unused = self.data_space().limit() - self.data_space().here();
+        self.s_stack().push(unused as isize);
     }
 
-    /// Run-time: ( a-addr -- )
-    ///
-    /// Append the run-time semantics of `_LOOP` to the current definition.
-    /// Resolve the destination of all unresolved occurrences of `LEAVE` between
-    /// the location given by do-sys and the next location for a transfer of
-    /// control, to execute the words following the `LOOP`.
-    ///
-    /// ```
-    /// For DO ... LOOP,
-    ///
-    ///         +--------------------------+
-    ///         |                          |
-    ///         |                          v
-    /// +-----+-+-+-----------+-------+---+--
-    /// | _do | x | loop body | _loop | x |
-    /// +-----+---+-----------+-------+-+-+--
-    ///            ^                    |
-    ///            |                    |
-    ///            ++-------------------+
-    ///             |
-    /// Control::Do(do_part, _)
-    ///
-    /// For ?DO ... LOOP,
+    /// Run-time: ( -- addr )
     ///
-    ///          +--------------------------+
-    ///          |                          |
-    ///          |                          v
-    /// +------+-+-+-----------+-------+---+--
-    /// | _qdo | x | loop body | _loop | x |
-    /// +------+---+-----------+-------+-+-+--
-    ///             ^                    |
-    ///             |                    |
-    ///             +--------------------+
-    ///             |
-    /// Control::Do(do_part, _)
-    /// ```
-    fn imm_loop(&mut self) {
-        let do_part = match self.c_stack().pop() {
-            Control::Do(do_part, _) => do_part,
-            _ => {
-                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-                return;
-            }
-        };
-        if self.c_stack().underflow() {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-        } else {
-            let idx = self.references().idx_loop;
-            self.compile_word(idx);
-            self.data_space().compile_isize(do_part as isize);
-            let here = self.data_space().here();
-            unsafe {
-                self.data_space()
-                    .put_isize(here as isize, (do_part - mem::size_of::<isize>()) as usize);
-            }
-        }
+    /// `addr` is the upper limit of data space.
+    fn p_limit(&mut self) {
+        let limit = self.data_space().limit() as isize;
+        self.s_stack().push(limit);
     }
 
-    /// Run-time: ( a-addr -- )
+    /// Run-time: ( pages -- ior )
     ///
-    /// Append the run-time semantics of `_+LOOP` to the current definition.
-    /// Resolve the destination of all unresolved occurrences of `LEAVE` between
-    /// the location given by do-sys and the next location for a transfer of
-    /// control, to execute the words following `+LOOP`.
-    fn imm_plus_loop(&mut self) {
-        let do_part = match self.c_stack().pop() {
-            Control::Do(do_part, _) => do_part,
-            _ => {
-                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-                return;
-            }
-        };
-        if self.c_stack().underflow() {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+    /// Extend data space by `pages` pages in place, so that addresses
+    /// already handed out stay valid. `ior` is `ALLOCATE` if it would
+    /// exceed the size set by `VmBuilder::max_data_capacity`.
+    fn grow_data_space(&mut self) {
+        let pages = self.s_stack().pop();
+        let ior = if pages < 0 {
+            isize::from(ALLOCATE)
         } else {
-            let idx = self.references().idx_plus_loop;
-            self.compile_word(idx);
-            self.data_space().compile_isize(do_part as isize);
-            let here = self.data_space().here();
-            unsafe {
-                self.data_space()
-                    .put_isize(here as isize, (do_part - mem::size_of::<isize>()) as usize);
+            match (pages as usize).checked_mul(page_size::get()) {
+                Some(bytes) => match self.data_space().grow(bytes) {
+                    Ok(()) => 0,
+                    Err(e) => isize::from(e),
+                },
+                None => isize::from(ALLOCATE),
             }
-        }
+        };
+        self.s_stack().push(ior);
     }
 
-    fn activate(&mut self) {
-        let i = (self.s_stack().pop() - 1) as usize;
-        if i < NUM_TASKS {
-            // Wake task `i`.
-            self.set_awake(i, true);
-            // Reset task `i` and Assign the code following ACTIVATE to task `i`
-            let current_task = self.current_task();
-            let ip = self.state().instruction_pointer;
-            self.set_current_task(i);
-            self.reset();
-            self.state().instruction_pointer = ip;
-            self.set_current_task(current_task);
-            // Return to caller.
-            let ip = self.r_stack().pop() as usize;
-            self.state().instruction_pointer = ip;
-        } else {
-            let ip = self.r_stack().pop() as usize;
-            self.state().instruction_pointer = ip;
-            self.abort_with(INVALID_NUMERIC_ARGUMENT);
-        }
+    /// Run-time: ( -- a-addr )
+    ///
+    /// `a-addr` is the address of the threshold of unused data space, in
+    /// address units, below which `.low-water ( u -- )` is executed with
+    /// the unused space. 0, the default, disables the check.
+    ///
+    /// ```forth
+    /// 4096 low-water !
+    /// ```
+    fn low_water(&mut self) {
+        let addr = self.data_space().system_variables().low_water_addr();
+        self.s_stack().push(addr as isize);
     }
 
-    fn p_i(&mut self) {
-        match self.r_stack().last() {
-            Some(it) => {
-                let next = self.r_stack().len - 2;
-                match self.r_stack().get(next) {
-                    Some(inext) => {
-                        self.s_stack().push(it.wrapping_add(inext));
-                    }
-                    None => self.abort_with(RETURN_STACK_UNDERFLOW),
-                }
+    /// Run-time: ( u -- )
+    ///
+    /// Default behavior of `.low-water`, print a warning with the unused
+    /// space `u` to standard error output.
+    fn default_low_water(&mut self) {
+        let unused = self.s_stack().pop();
+        eprintln!("Warning: {} bytes of data space unused", unused);
+    }
+
+    /// Execute `.low-water` if unused data space has dropped below the
+    /// threshold in `low-water`. Checked after each word evaluated.
+    fn check_low_water(&mut self) {
+        let unused = self.data_space().limit() - self.data_space().here();
+        if self
+            .data_space()
+            .system_variables_mut()
+            .low_water_reached(unused)
+        {
+            self.s_stack().push(unused as isize);
+            match self.find(".low-water") {
+                Some(xt) => self.execute_xt(xt),
+                None => self.default_low_water(),
             }
-            None => self.abort_with(RETURN_STACK_UNDERFLOW),
         }
     }
 
-    // -----------
-    // Evaluation
-    // -----------
+    /// Run-time: ( n -- )
+    ///
+    /// If `n` is greater than zero, reserve n address units of data space. If `n`
+    /// is less than zero, release `|n|` address units of data space. If `n` is
+    /// zero, leave the data-space pointer unchanged.
+    fn allot(&mut self) {
+        let v = self.s_stack().pop();
+        self.data_space().allot(v);
+    }
 
-    fn left_bracket(&mut self) {
-        self.state().is_compiling = false;
+    /// Run-time: ( addr -- a-addr )
+    ///
+    /// Return `a-addr`, the first aligned address greater than or equal to `addr`.
+    fn aligned(&mut self) {
+        let pos = self.s_stack().pop();
+        let pos = DataSpace::aligned(pos as usize);
+        self.s_stack().push(pos as isize);
     }
 
-    fn right_bracket(&mut self) {
-        self.state().is_compiling = true;
+    /// Run-time: ( -- )
+    ///
+    /// If the data-space pointer is not aligned, reserve enough space to align it.
+    fn align(&mut self) {
+        self.data_space().align();
     }
 
-    /// Copy content of `s` to `input_buffer` and set `source_index` to 0.
-    fn set_source(&mut self, s: &str) {
-        let mut buffer = self.input_buffer().take().expect("input buffer");
-        buffer.clear();
-        buffer.push_str(s);
-        self.state().source_index = 0;
-        self.set_input_buffer(buffer);
+    /// Run-time: ( x -- )
+    ///
+    /// Reserve one cell of data space and store `x` in the cell. If the
+    /// data-space pointer is aligned when `,` begins execution, it will remain
+    /// aligned when `,` finishes execution. An ambiguous condition exists if the
+    /// data-space pointer is not aligned prior to execution of `,`.
+    fn comma(&mut self) {
+        let v = self.s_stack().pop();
+        self.data_space().compile_isize(v as isize);
     }
 
-    /// Push content of `s` to `input_buffer`.
-    fn push_source(&mut self, s: &str) {
-        let mut buffer = self.input_buffer().take().expect("input buffer");
-        buffer.push_str(s);
-        self.set_input_buffer(buffer);
+    fn p_to_r(&mut self) {
+        let slen = self.s_stack().len;
+        let rlen = self.r_stack().len.wrapping_add(1);
+        self.r_stack().len = rlen;
+        self.r_stack()[rlen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(1)];
+        self.s_stack().len = slen.wrapping_sub(1);
     }
 
-    /// Run-time: ( "ccc" -- )
+    fn r_from(&mut self) {
+        let slen = self.s_stack().len.wrapping_add(1);
+        let rlen = self.r_stack().len;
+        self.s_stack().len = slen;
+        self.s_stack()[slen.wrapping_sub(1)] = self.r_stack()[rlen.wrapping_sub(1)];
+        self.r_stack().len = rlen.wrapping_sub(1);
+    }
+
+    fn r_fetch(&mut self) {
+        let slen = self.s_stack().len.wrapping_add(1);
+        let rlen = self.r_stack().len;
+        self.s_stack().len = slen;
+        self.s_stack()[slen.wrapping_sub(1)] = self.r_stack()[rlen.wrapping_sub(1)];
+    }
+
+    fn two_to_r(&mut self) {
+        let slen = self.s_stack().len;
+        let rlen = self.r_stack().len.wrapping_add(2);
+        self.r_stack().len = rlen;
+        self.r_stack()[rlen.wrapping_sub(2)] = self.s_stack()[slen.wrapping_sub(2)];
+        self.r_stack()[rlen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(1)];
+        self.s_stack().len = slen.wrapping_sub(2);
+    }
+
+    fn two_r_from(&mut self) {
+        let slen = self.s_stack().len.wrapping_add(2);
+        let rlen = self.r_stack().len;
+        self.s_stack().len = slen;
+        self.s_stack()[slen.wrapping_sub(2)] = self.r_stack()[rlen.wrapping_sub(2)];
+        self.s_stack()[slen.wrapping_sub(1)] = self.r_stack()[rlen.wrapping_sub(1)];
+        self.r_stack().len = rlen.wrapping_sub(2);
+    }
+
+    fn two_r_fetch(&mut self) {
+        let slen = self.s_stack().len.wrapping_add(2);
+        let rlen = self.r_stack().len;
+        self.s_stack().len = slen;
+        self.s_stack()[slen.wrapping_sub(2)] = self.r_stack()[rlen.wrapping_sub(2)];
+        self.s_stack()[slen.wrapping_sub(1)] = self.r_stack()[rlen.wrapping_sub(1)];
+    }
+
+    // ----------------
+    // Error handlling
+    // ----------------
+
+    /// Abort if a stack has overflowed or underflowed since it was last
+    /// reset.
     ///
-    /// Parse word delimited by white space, skipping leading white spaces.
-    fn parse_word(&mut self) {
-        let mut last_token = self.last_token().take().expect("token");
-        last_token.clear();
-        if let Some(input_buffer) = self.input_buffer().take() {
-            if self.state().source_index < input_buffer.len() {
-                let source = &input_buffer[self.state().source_index..];
-                let mut cnt = source.len();
-                let mut char_indices = source.char_indices();
-                loop {
-                    match char_indices.next() {
-                        Some((idx, ch)) => {
-                            match ch {
-                                '\t' | '\n' | '\r' | ' ' => {
-                                    if !last_token.is_empty() {
-                                        cnt = idx;
-                                        break;
-                                    }
-                                }
-                                _ => last_token.push(ch),
-                            };
-                        }
-                        None => {
-                            break;
-                        }
-                    }
-                }
-                self.state().source_index = self.state().source_index + cnt;
-            }
-            self.set_input_buffer(input_buffer);
+    /// The depth reached beyond the limits is written to the output
+    /// buffer before aborting, for example `Data stack reached depth -2 `.
+    fn check_stacks(&mut self) {
+        let s = (self.s_stack().overflow(), self.s_stack().underflow());
+        let r = (self.r_stack().overflow(), self.r_stack().underflow());
+        let c = (self.c_stack().overflow(), self.c_stack().underflow());
+        let f = (self.f_stack().overflow(), self.f_stack().underflow());
+        let (name, (low, high), error) = match (s, r, c, f) {
+            ((true, _), _, _, _) => ("Data", self.s_stack().watermarks(), STACK_OVERFLOW),
+            ((_, true), _, _, _) => ("Data", self.s_stack().watermarks(), STACK_UNDERFLOW),
+            (_, (true, _), _, _) => ("Return", self.r_stack().watermarks(), RETURN_STACK_OVERFLOW),
+            (_, (_, true), _, _) => (
+                "Return",
+                self.r_stack().watermarks(),
+                RETURN_STACK_UNDERFLOW,
+            ),
+            (_, _, (true, _), _) | (_, _, (_, true), _) => (
+                "Control",
+                self.c_stack().watermarks(),
+                CONTROL_STRUCTURE_MISMATCH,
+            ),
+            (_, _, _, (true, _)) => (
+                "Floating-point",
+                self.f_stack().watermarks(),
+                FLOATING_POINT_STACK_OVERFLOW,
+            ),
+            (_, _, _, (_, true)) => (
+                "Floating-point",
+                self.f_stack().watermarks(),
+                FLOATING_POINT_STACK_UNDERFLOW,
+            ),
+            _ => return,
+        };
+        let depth = match error {
+            STACK_OVERFLOW | RETURN_STACK_OVERFLOW | FLOATING_POINT_STACK_OVERFLOW => high,
+            _ if low < 0 => low,
+            _ => high,
+        };
+        if let Some(buf) = self.output_buffer().as_mut() {
+            write!(buf, "{} stack reached depth {} ", name, depth).unwrap();
         }
-        self.set_last_token(last_token);
+        self.abort_with(error);
     }
 
-    /// Run-time: ( "&lt;spaces&gt;name" -- char)
-    ///
-    /// Skip leading space delimiters. Parse name delimited by a space.
-    /// Put the value of its first character onto the stack.
-    fn char(&mut self) {
-        self.parse_word();
-        let last_token = self.last_token().take().expect("token");
-        match last_token.chars().nth(0) {
-            Some(c) => {
-                self.set_last_token(last_token);
-                self.s_stack().push(c as isize);
+    fn handler_store(&mut self) {
+        let t = self.s_stack().pop();
+        self.set_handler(t as usize);
+    }
+
+    /// Error code `error ( -- n )`
+    fn error(&mut self) {
+        match self.last_error() {
+            Some(e) => {
+                self.s_stack().push(e.into());
             }
             None => {
-                self.set_last_token(last_token);
-                self.abort_with(UNEXPECTED_END_OF_FILE);
+                self.s_stack().push(0);
             }
         }
     }
 
-    /// Compilation: ( "&lt;spaces&gt;name" -- )
-    ///
-    /// Skip leading space delimiters. Parse name delimited by a space.
-    /// Append the run-time semantics given below to the current definition.
-    ///
-    /// Run-time: ( -- char )
-    ///
-    /// Place `char`, the value of the first character of name, on the stack.
-    fn bracket_char(&mut self) {
-        self.char();
-        if self.last_error().is_some() {
-            return;
-        }
-        let ch = self.s_stack().pop();
-        self.compile_integer(ch);
+    /// Clear error. `0error ( -- )`
+    fn clear_error(&mut self) {
+        self.set_error(None);
     }
 
-    /// Run-time: ( char "ccc&lt;char&gt;" -- )
-    ///
-    /// Parse ccc delimited by the delimiter char.
-    fn parse(&mut self) {
-        let input_buffer = self.input_buffer().take().expect("input buffer");
-        let v = self.s_stack().pop();
-        let mut last_token = self.last_token().take().expect("token");
-        last_token.clear();
-        {
-            let source = &input_buffer[self.state().source_index..];
-            let mut cnt = source.len();
-            let mut char_indices = source.char_indices();
-            loop {
-                match char_indices.next() {
-                    Some((idx, ch)) => {
-                        if ch as isize == v {
-                            match char_indices.next() {
-                                Some((idx, _)) => {
-                                    cnt = idx;
-                                }
-                                None => {}
-                            }
-                            break;
-                        } else {
-                            last_token.push(ch);
-                        }
-                    }
-                    None => {
-                        break;
-                    }
+    /// Print error description. `.error ( -- )`
+    fn dot_error(&mut self) {
+        match self.last_error() {
+            Some(e) => match self.output_buffer().take() {
+                Some(mut buf) => {
+                    write!(buf, "{}", self.wordlist().exception_description(e)).expect("write");
+                    self.set_output_buffer(buf);
                 }
-            }
-            self.state().source_index = self.state().source_index + cnt;
+                None => {}
+            },
+            None => {}
         }
-        self.set_last_token(last_token);
-        self.set_input_buffer(input_buffer);
     }
 
-    /// Run-time: ( char "ccc" -- )
+    /// Limit the output buffer to `max_len` bytes, handling output beyond
+    /// it according to `policy`.
+    fn set_output_limit(&mut self, max_len: usize, policy: OutputOverflow) {
+        let limit = &mut self.wordlist_mut().output_limit;
+        limit.max_len = max_len;
+        limit.policy = policy;
+    }
+
+    /// True if output has been discarded because of the limit set by
+    /// `set_output_limit`, until `clear_output_overflowed`.
+    fn output_overflowed(&self) -> bool {
+        self.wordlist().output_limit.overflowed
+    }
+
+    fn clear_output_overflowed(&mut self) {
+        self.wordlist_mut().output_limit.overflowed = false;
+    }
+
+    /// Apply the limit set by `set_output_limit` to the output buffer.
     ///
-    /// Skip all of the delimiter char.
-    fn _skip(&mut self) {
-        let input_buffer = self.input_buffer().take().expect("input buffer");
-        let v = self.s_stack().pop();
-        {
-            let source = &input_buffer[self.state().source_index..];
-            let mut cnt = 0;
-            let mut char_indices = source.char_indices();
-            loop {
-                match char_indices.next() {
-                    Some((idx, ch)) => {
-                        if ch as isize == v {
-                            cnt += 1;
-                        } else {
-                            break;
+    /// Called by `execute_word` after each word while a limit is set, so
+    /// that every word writing to the output buffer is limited. With
+    /// `OutputOverflow::Error`, nothing is aborted while handling another
+    /// error, so that the error handler can still print.
+    fn limit_output(&mut self) {
+        let (max_len, policy) = {
+            let limit = &self.wordlist().output_limit;
+            (limit.max_len, limit.policy)
+        };
+        let overflowed = match self.output_buffer().as_mut() {
+            Some(buf) if buf.len() > max_len => {
+                match policy {
+                    OutputOverflow::DropOldest => {
+                        let mut start = buf.len() - max_len;
+                        while !buf.is_char_boundary(start) {
+                            start += 1;
                         }
+                        buf.drain(..start);
                     }
-                    None => {
-                        break;
+                    OutputOverflow::DropNewest | OutputOverflow::Error => {
+                        let mut end = max_len;
+                        while !buf.is_char_boundary(end) {
+                            end -= 1;
+                        }
+                        buf.truncate(end);
                     }
                 }
+                true
+            }
+            _ => false,
+        };
+        if overflowed {
+            self.wordlist_mut().output_limit.overflowed = true;
+            if policy == OutputOverflow::Error && self.last_error().is_none() {
+                self.abort_with(EXCEPTION_IN_SENDING_OR_RECEIVING_A_CHARACTER);
             }
-            self.state().source_index = self.state().source_index + cnt;
         }
-        self.set_input_buffer(input_buffer);
     }
 
-    fn imm_paren(&mut self) {
-        self.s_stack().push(')' as isize);
-        self.parse();
+    /// Clear data, floating point, and control stacks.
+    /// Called by VM's client upon ABORT.
+    fn clear_stacks(&mut self) {
+        self.s_stack().reset();
+        self.f_stack().reset();
+        self.c_stack().reset();
     }
 
-    /// Begin a comment that includes the entire remainder of the current line.
-    fn imm_backslash(&mut self) {
-        self.s_stack().push('\n' as isize);
-        self.parse();
+    /// ( -- source-id )
+    ///
+    /// Current source id.
+    fn p_source_id(&mut self) {
+        let source_id = self.source_id();
+        self.s_stack().push(source_id);
     }
 
-    /// postpone ( "<spaces>name" -- )
-    ///
-    /// Skip leading space delimiters. Parse name delimited by a space.
-    /// Find name. Append the compilation semantics of name to the current definition.
-    ///
-    /// For example:
+    /// ( source-id -- )
     ///
-    /// ```
-    /// : does>   postpone _does  postpone exit ;
+    /// Set source id.
+    fn p_set_source_id(&mut self) {
+        let id = self.s_stack().pop();
+        self.set_source_id(id);
+    }
+
+    /// Set source id, 0 for the terminal or -1 for string evaluation.
     ///
-    /// +------+-------------+-----------+------+------------+-----------+
-    /// | _lit | xt of _does | _postpone | _lit | xt of exit | _postpone |
-    /// +--------------------+-----------+------+------------+-----------+
-    /// ```
-    fn postpone(&mut self) {
-        self.parse_word();
-        let last_token = self.last_token().take().expect("token");
-        if last_token.is_empty() {
-            self.set_last_token(last_token);
-            self.abort_with(UNEXPECTED_END_OF_FILE);
+    /// Ids of opened input sources are accepted by `source-id!` of the
+    /// loader word set.
+    fn set_source_id(&mut self, id: isize) {
+        if id == 0 || id == -1 {
+            self.state().source_id = id;
         } else {
-            match self.find(&last_token) {
-                Some(xt) => {
-                    self.set_last_token(last_token);
-                    self.compile_integer(xt as isize);
-                    let idx = self.references().idx__postpone;
-                    self.compile_word(idx);
-                }
-                None => {
-                    self.set_last_token(last_token);
-                    self.abort_with(UNDEFINED_WORD);
-                }
-            }
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
         }
     }
 
-    /// _POSTPONE ( xt -- )
+    /// ( -- source-idx )
     ///
-    /// Execute the compilation semantics of an xt on stack.
+    /// Current source index.
+    fn p_source_idx(&mut self) {
+        let source_idx = self.state().source_index as isize;
+        self.s_stack().push(source_idx);
+    }
+
+    /// ( source-idx -- )
     ///
-    /// _POSTPONE is a hidden word which is only compiled by POSTPONE.
-    fn _postpone(&mut self) {
-        // : A ;
-        // : B   POSTPONE A ;
-        // which generate
-        // --+------+---------+-----------+--
-        //   | _lit | xt of A | _postpone |
-        // --+------+---------+-----------+--
-        // Because B comes after A, the xt of A is valid during execution of B.
-        let xt = self.s_stack().pop() as usize;
-        let compilation_semantics = self.wordlist()[xt].compilation_semantics;
-        compilation_semantics(self, xt);
+    /// Set source index.
+    fn p_set_source_idx(&mut self) {
+        let idx = self.s_stack().pop() as usize;
+        self.state().source_index = idx;
     }
 
-    fn compile_token(&mut self) {
-        let last_token = self.last_token().take().expect("token");
-        match self.find(&last_token) {
-            Some(found_index) => {
-                self.set_last_token(last_token);
-                let compilation_semantics = self.wordlist()[found_index].compilation_semantics;
-                if !self.wordlist()[found_index].is_immediate() {
-                    compilation_semantics(self, found_index);
-                } else {
-                    self.execute_word(found_index);
-                }
-            }
-            None => {
-                let mut done = false;
-                self.set_error(None);
-                self.evaluate_integer(&last_token);
-                match self.last_error() {
-                    None => done = true,
-                    Some(_) => {
-                        self.set_error(None);
-                        self.evaluate_float(&last_token);
-                        if self.last_error().is_none() {
-                            done = true;
-                        }
-                    }
-                }
-                self.set_last_token(last_token);
-                if done {
-                    /* Do nothing. */
-                } else {
-                    self.abort_with(UNDEFINED_WORD);
-                }
-            }
+    /// Reset VM, do not clear data stack, floating point and control stack.
+    /// Called by VM's client upon Quit.
+    fn reset(&mut self) {
+        self.r_stack().reset();
+        self.set_source_id(0);
+        if let Some(ref mut buf) = *self.input_buffer() {
+            buf.clear()
+        }
+        self.state().aborted_word_pointer = 0;
+        self.state().source_index = 0;
+        self.state().break_frames.clear();
+        self.state().activations.clear();
+        self.state().fsm = None;
+        self.state().image = None;
+        self.left_bracket();
+        self.set_error(None);
+        for i in 0..self.wordlist().at_reset.len() {
+            let xt = self.wordlist().at_reset[i];
+            self.execute_xt(xt);
         }
     }
 
-    fn interpret_token(&mut self) {
-        let last_token = self.last_token().take().expect("last token");
-        match self.find(&last_token) {
-            Some(found_index) => {
-                self.set_last_token(last_token);
-                if self.wordlist()[found_index].is_compile_only() {
-                    self.abort_with(INTERPRETING_A_COMPILE_ONLY_WORD);
-                } else {
-                    self.execute_word(found_index);
-                }
-            }
-            None => {
-                let mut done = false;
-                self.set_error(None);
-                self.evaluate_integer(&last_token);
-                match self.last_error() {
-                    None => done = true,
-                    Some(_) => {
-                        self.set_error(None);
-                        self.evaluate_float(&last_token);
-                        if self.last_error().is_none() {
-                            done = true;
-                        }
-                    }
-                }
-                self.set_last_token(last_token);
-                if done {
-                    /* Do nothing. */
-                } else {
-                    self.abort_with(UNDEFINED_WORD);
-                }
-            }
-        }
+    /// Enable break levels upon errors. `break-on ( -- )`
+    fn break_on(&mut self) {
+        self.state().break_enabled = true;
     }
 
-    fn p_compiling(&mut self) {
-        let value = if self.state().is_compiling {
-            TRUE
-        } else {
-            FALSE
-        };
-        self.s_stack().push(value);
+    /// Disable break levels upon errors. `break-off ( -- )`
+    fn break_off(&mut self) {
+        self.state().break_enabled = false;
     }
 
-    /// Is token empty? `token-empty? ( -- f )
-    fn token_empty(&mut self) {
-        let value = match self.last_token().as_ref() {
-            Some(ref t) => {
-                if t.is_empty() {
-                    TRUE
-                } else {
-                    FALSE
-                }
-            }
-            None => TRUE,
-        };
-        self.s_stack().push(value);
+    /// Compile constants and float constants defined by `constant` and
+    /// `fconstant` as literals of their values. `fold-on ( -- )`
+    ///
+    /// This saves the fetch of the value from the constant at run time, but
+    /// definitions compiled meanwhile do not see values stored into the
+    /// body of a constant later. Literals are forgotten by markers along
+    /// with the definitions they are compiled in, which are defined after
+    /// the constant, so that forgetting never leaves a stale value.
+    fn fold_on(&mut self) {
+        self.wordlist_mut().set_fold_constants(true);
     }
 
-    /// Print token. `.token ( -- )`
-    fn dot_token(&mut self) {
-        match self.last_token().take() {
-            Some(t) => {
-                match self.output_buffer().as_mut() {
-                    Some(buf) => {
-                        write!(buf, "{}", t).expect("write token");
-                    }
-                    None => {}
-                }
-                self.set_last_token(t);
-            }
-            None => {}
-        }
+    /// Compile references to constants again. `fold-off ( -- )`
+    fn fold_off(&mut self) {
+        self.wordlist_mut().set_fold_constants(false);
     }
 
-    /// Store token. `!token ( c-addr -- )
+    /// Let `'` and `[']` reject compile-only words, whose interpretation
+    /// semantics are undefined. `strict-tick-on ( -- )`
+    fn strict_tick_on(&mut self) {
+        self.wordlist_mut().set_strict_tick(true);
+    }
+
+    /// Let `'` and `[']` return compile-only words again, for `compile,`
+    /// or `execute` while compiling. `strict-tick-off ( -- )`
+    fn strict_tick_off(&mut self) {
+        self.wordlist_mut().set_strict_tick(false);
+    }
+
+    /// Let `+`, `-` and `*` abort with `RESULT_OUT_OF_RANGE` on overflow,
+    /// for code which must not silently wrap around. `strict-math-on ( -- )`
+    fn strict_math_on(&mut self) {
+        self.wordlist_mut().set_strict_math(true);
+    }
+
+    /// Let `+`, `-` and `*` wrap around on overflow again.
+    /// `strict-math-off ( -- )`
+    fn strict_math_off(&mut self) {
+        self.wordlist_mut().set_strict_math(false);
+    }
+
+    /// Compile `need` and `needs-f` into definitions again.
+    /// `guards-on ( -- )`
+    fn guards_on(&mut self) {
+        self.wordlist_mut().set_stack_guards(true);
+    }
+
+    /// Leave `need` and `needs-f` out of the definitions compiled from
+    /// now on, for release builds. `guards-off ( -- )`
+    fn guards_off(&mut self) {
+        self.wordlist_mut().set_stack_guards(false);
+    }
+
+    /// Let `'` and `[']` tag execution tokens with the generation of their
+    /// words, so that `execute` aborts with `INVALID_XT` upon one whose
+    /// word a marker has forgotten. `checked-xts-on ( -- )`
     ///
-    /// Store token in counted string at `c-addr`.`
-    fn store_token(&mut self) {
-        let c_addr = self.s_stack().pop() as usize;
-        if self.data_space().start() <= c_addr {
-            match self.last_token().take() {
-                Some(mut t) => {
-                    self.data_space().put_cstr(&t, c_addr);
-                    t.clear();
-                    self.set_last_token(t);
-                }
-                None => unsafe {
-                    if c_addr < self.data_space().limit() {
-                        self.data_space().put_u8(0, c_addr);
-                    } else {
-                        panic!("Error: store_token while space is full.");
-                    }
-                },
-            }
+    /// ```forth
+    /// checked-xts-on
+    /// marker -work  : a ;  ' a constant xt-a  -work
+    /// : b ;  xt-a execute   \ INVALID_XT instead of executing b
+    /// ```
+    fn checked_xts_on(&mut self) {
+        self.wordlist_mut().set_checked_xts(true);
+    }
+
+    /// Return plain execution tokens again. Those tagged before are still
+    /// checked. `checked-xts-off ( -- )`
+    fn checked_xts_off(&mut self) {
+        self.wordlist_mut().set_checked_xts(false);
+    }
+
+    /// Allow interpreting and compiling privileged words if `pin` is the
+    /// PIN set by `Wordlist::set_unlock_pin`, otherwise abort with
+    /// `PERMISSION_DENIED`. `unlock ( "pin" -- )`
+    fn unlock(&mut self) {
+        self.parse_word();
+        let pin = self.last_token().take().expect("last token");
+        let granted = self.wordlist().unlock_pin() == Some(pin.as_str());
+        self.set_last_token(pin);
+        if granted {
+            self.wordlist_mut().set_unlocked(true);
         } else {
-            self.abort_with(INVALID_MEMORY_ADDRESS);
+            self.abort_with(PERMISSION_DENIED);
         }
     }
 
-    fn evaluate_input(&mut self) {
-        loop {
-            self.parse_word();
-            match self.last_token().as_ref() {
-                Some(t) => {
-                    if t.is_empty() {
-                        return;
-                    }
-                }
-                None => {}
-            }
-            if self.state().is_compiling {
-                self.compile_token();
-                if self.last_error().is_some() {
-                    break;
-                }
-            } else {
-                self.interpret_token();
-                if self.last_error().is_some() {
-                    break;
-                }
-            }
-            self.run();
-            self.check_stacks();
-            if self.last_error().is_some() {
-                break;
-            }
+    /// Reject privileged words from the input again. `lock ( -- )`
+    fn lock(&mut self) {
+        self.wordlist_mut().set_unlocked(false);
+    }
+
+    /// False after aborting with `PRIVILEGED_WORD` if word `xt` is
+    /// privileged and the session is locked.
+    fn check_privilege(&mut self, xt: usize) -> bool {
+        if self.wordlist()[xt].is_privileged() && !self.wordlist().is_unlocked() {
+            self.abort_with(PRIVILEGED_WORD);
+            false
+        } else {
+            true
         }
     }
 
-    fn base(&mut self) {
-        let base_addr = self.data_space().system_variables().base_addr();
-        self.s_stack().push(base_addr as isize);
+    /// Number of nested break levels. `break-depth ( -- n )`
+    fn p_break_depth(&mut self) {
+        let depth = self.state().break_depth();
+        self.s_stack().push(depth as isize);
     }
 
-    fn evaluate_integer(&mut self, token: &str) {
-        let base_addr = self.data_space().system_variables().base_addr();
-        let default_base = unsafe { self.data_space().get_isize(base_addr) };
-        match parser::quoted_char(&token.as_bytes()) {
-            parser::IResult::Done(_bytes, c) => {
-                if self.state().is_compiling {
-                    self.compile_integer(c);
-                } else {
-                    self.s_stack().push(c);
+    /// Leave the innermost break level and resume the interrupted word
+    /// right after the point of error. `continue ( -- )`
+    ///
+    /// The interrupted input is restored, so the rest of the line after
+    /// `continue` is discarded. Named `continue` because `resume` is taken by the multitasker.
+    fn p_continue(&mut self) {
+        match self.state().break_frames.pop() {
+            Some(frame) => {
+                self.r_stack().len = frame.r_len;
+                self.state().instruction_pointer = frame.instruction_pointer;
+                self.state().word_pointer = frame.word_pointer;
+                self.state().is_compiling = frame.is_compiling;
+                self.state().source_id = frame.source_id;
+                if let Some(input) = frame.input {
+                    self.set_input_buffer(input);
                 }
-                return;
-            }
-            parser::IResult::Err(_) => {
-                // Do nothing.
-            }
-        }
-        match parser::base(&token.as_bytes(), default_base) {
-            parser::IResult::Done(bytes, base) => match parser::sign(&bytes) {
-                parser::IResult::Done(bytes, sign) => match parser::uint_in_base(&bytes, base) {
-                    parser::IResult::Done(bytes, value) => {
-                        if bytes.len() != 0 {
-                            self.set_error(Some(UNSUPPORTED_OPERATION));
-                        } else {
-                            if self.state().is_compiling {
-                                self.compile_integer(sign.wrapping_mul(value));
-                            } else {
-                                self.s_stack().push(sign.wrapping_mul(value));
-                            }
-                        }
-                    }
-                    parser::IResult::Err(e) => self.set_error(Some(e)),
-                },
-                parser::IResult::Err(e) => {
-                    self.set_error(Some(e));
+                self.state().source_index = frame.source_index;
+                self.set_error(None);
+                if let Some(xt) = frame.pending {
+                    self.state().word_pointer = xt;
+                    (self.wordlist()[xt].action())(self);
                 }
-            },
-            parser::IResult::Err(e) => {
-                self.set_error(Some(e));
             }
+            None => self.abort_with(UNSUPPORTED_OPERATION),
         }
     }
 
-    /// Evaluate float.
-    fn evaluate_float(&mut self, token: &str) {
-        let significand_sign;
-        let integer_part;
-        let mut fraction_part = 0.0;
-        let mut exponent_sign: isize = 0;
-        let mut exponent_part: isize = 0;
-        let mut failed = false;
-        let mut bytes = token.as_bytes();
-
-        match parser::sign(bytes) {
-            parser::IResult::Done(input, value) => {
-                significand_sign = value;
-                bytes = input;
-            }
-            parser::IResult::Err(e) => {
-                self.set_error(Some(e));
-                return;
+    /// Break before word `name` is executed if predicate `xt ( -- flag )`
+    /// returns true. `break-if <name> ( xt -- )`
+    ///
+    /// Aborts with `INVALID_XT` if `xt` is not a word.
+    fn break_if(&mut self) {
+        let predicate = self.s_stack().pop();
+        self.tick();
+        if self.last_error().is_none() {
+            let xt = self.s_stack().pop();
+            let xt = self.wordlist().untag_xt(xt).expect("xt");
+            match self.wordlist().untag_xt(predicate) {
+                Some(predicate) => self.wordlist_mut().breakpoint_mut(xt).predicate = predicate,
+                None => self.abort_with(INVALID_XT),
             }
         }
+    }
 
-        let len_before = bytes.len();
-        match parser::uint(bytes) {
-            parser::IResult::Done(input, value) => {
-                integer_part = value;
-                bytes = input;
-            }
-            parser::IResult::Err(e) => {
-                self.set_error(Some(e));
-                return;
+    /// Break before word `name` is executed, but skip the first `n` hits.
+    /// `break-count <name> ( n -- )`
+    fn break_count(&mut self) {
+        let n = self.s_stack().pop();
+        self.tick();
+        if self.last_error().is_none() {
+            let xt = self.s_stack().pop();
+            let xt = self.wordlist().untag_xt(xt).expect("xt");
+            if n >= 0 {
+                let b = self.wordlist_mut().breakpoint_mut(xt);
+                b.skip = n as usize;
+                b.hits = 0;
+            } else {
+                self.abort_with(INVALID_NUMERIC_ARGUMENT);
             }
         }
-        if bytes.len() != len_before {
-            match parser::fraction(bytes) {
-                parser::IResult::Done(input, value) => {
-                    fraction_part = value;
-                    bytes = input;
-                }
-                parser::IResult::Err(e) => {
-                    self.set_error(Some(e));
-                    return;
-                }
-            }
+    }
 
-            let len_before = bytes.len();
-            match parser::ascii(bytes, b'E') {
-                parser::IResult::Done(input, value) => {
-                    if value {
-                        match parser::sign(input) {
-                            parser::IResult::Done(input, value) => {
-                                exponent_sign = value;
-                                bytes = input;
-                            }
-                            parser::IResult::Err(e) => {
-                                self.set_error(Some(e));
-                                return;
-                            }
-                        }
-                        match parser::uint(bytes) {
-                            parser::IResult::Done(input, value) => {
-                                exponent_part = value;
-                                bytes = input;
-                            }
-                            parser::IResult::Err(e) => {
-                                self.set_error(Some(e));
-                                return;
-                            }
-                        }
-                    } else {
-                        match parser::ascii(bytes, b'e') {
-                            parser::IResult::Done(input, value) => {
-                                if value {
-                                    match parser::sign(input) {
-                                        parser::IResult::Done(input, value) => {
-                                            exponent_sign = value;
-                                            bytes = input;
-                                        }
-                                        parser::IResult::Err(e) => {
-                                            self.set_error(Some(e));
-                                            return;
-                                        }
-                                    }
-                                    match parser::uint(bytes) {
-                                        parser::IResult::Done(input, value) => {
-                                            exponent_part = value;
-                                            bytes = input;
-                                        }
-                                        parser::IResult::Err(e) => {
-                                            self.set_error(Some(e));
-                                            return;
-                                        }
-                                    }
-                                }
-                            }
-                            parser::IResult::Err(e) => {
-                                self.set_error(Some(e));
-                                return;
-                            }
-                        }
-                    }
-                }
-                parser::IResult::Err(e) => {
-                    self.set_error(Some(e));
-                    return;
-                }
-            }
+    /// Remove breakpoint on word `name`. `unbreak <name> ( -- )`
+    fn unbreak(&mut self) {
+        self.tick();
+        if self.last_error().is_none() {
+            let xt = self.s_stack().pop();
+            let xt = self.wordlist().untag_xt(xt).expect("xt");
+            self.wordlist_mut().breakpoints.retain(|b| b.xt != xt);
+            self.wordlist_mut().has_breakpoints = !self.wordlist().breakpoints.is_empty();
+        }
+    }
 
-            if bytes.len() == len_before {
-                failed = true;
-            }
-        } else {
-            failed = true;
+    /// Remove all breakpoints. `0breaks ( -- )`
+    fn clear_breakpoints(&mut self) {
+        self.wordlist_mut().breakpoints.clear();
+        self.wordlist_mut().has_breakpoints = false;
+    }
+
+    /// Error which entered the innermost break level.
+    /// `break-error ( -- n )`
+    fn break_error(&mut self) {
+        let e = self.state().break_frames.last().map(|f| f.error);
+        match e {
+            Some(e) => self.s_stack().push(e.into()),
+            None => self.s_stack().push(0),
         }
+    }
 
-        if bytes.len() != 0 {
-            failed = true;
+    /// Abort the inner loop with an exception, reset VM and clears stacks.
+    ///
+    /// If break levels are enabled with `break-on`, the stacks are kept and
+    /// the interrupted state is saved so that `continue` can resume it.
+    /// `abort`, `abort"` and stack errors always clear all break levels.
+    fn abort_with(&mut self, e: Exception) {
+        self.record_error(e);
+        let breakable = self.state().break_enabled
+            && self.state().break_frames.len() < MAX_BREAK_DEPTH
+            && match e {
+                ABORT
+                | ABORT_QUOTE
+                | STACK_OVERFLOW
+                | STACK_UNDERFLOW
+                | RETURN_STACK_OVERFLOW
+                | RETURN_STACK_UNDERFLOW
+                | FLOATING_POINT_STACK_OVERFLOW
+                | FLOATING_POINT_STACK_UNDERFLOW => false,
+                _ => !self.c_stack().overflow() && !self.c_stack().underflow(),
+            };
+        if breakable {
+            self.enter_break(e, None);
+        } else {
+            self.state().break_frames.clear();
+            self.clear_stacks();
+            self.set_error(Some(e));
+            let h = self.handler();
+            self.state().aborted_word_pointer = self.state().word_pointer;
+            self.execute_word(h);
         }
+    }
 
-        if failed {
-            self.set_error(Some(UNSUPPORTED_OPERATION))
+    /// Record error `e` in the error history, see `Wordlist::errors`.
+    fn record_error(&mut self, e: Exception) {
+        if self.wordlist().errors.capacity() == 0 {
+            return;
+        }
+        let wp = self.state().word_pointer;
+        let word = if wp < self.wordlist().len() {
+            let nfa = self.wordlist()[wp].nfa();
+            unsafe { self.data_space().get_str(nfa) }.to_string()
         } else {
-            if self.references().idx_flit == 0 {
-                self.set_error(Some(UNSUPPORTED_OPERATION));
-            } else {
-                let value = (significand_sign as f64)
-                    * (integer_part as f64 + fraction_part)
-                    * ((10.0f64).powi((exponent_sign.wrapping_mul(exponent_part)) as i32) as f64);
-                if self.state().is_compiling {
-                    self.compile_float(value);
-                } else {
-                    self.f_stack().push(value);
-                }
+            String::new()
+        };
+        let record = ErrorRecord {
+            error: e,
+            timestamp: self.wallclock_ns(),
+            task: self.current_task(),
+            word,
+            token: self.last_token().clone().unwrap_or_default(),
+            source_id: self.source_id(),
+            source_index: self.state().source_index,
+        };
+        self.wordlist_mut().errors.record(record);
+    }
+
+    /// Save the interrupted state and enter a new break level through the
+    /// error handler.
+    fn enter_break(&mut self, e: Exception, pending: Option<usize>) {
+        let frame = BreakFrame {
+            error: e,
+            is_compiling: self.state().is_compiling,
+            instruction_pointer: self.state().instruction_pointer,
+            word_pointer: self.state().word_pointer,
+            r_len: self.r_stack().len,
+            source_index: self.state().source_index,
+            source_id: self.source_id(),
+            input: self.input_buffer().clone(),
+            pending,
+        };
+        self.state().break_frames.push(frame);
+        self.set_error(Some(e));
+        let h = self.handler();
+        self.state().aborted_word_pointer = self.state().word_pointer;
+        self.execute_word(h);
+    }
+
+    /// Check breakpoint on word `xt`, enter a break level with
+    /// `USER_INTERRUPT` and return true if it triggers.
+    fn hit_breakpoint(&mut self, xt: usize) -> bool {
+        let (predicate, skip, hits) = match self.wordlist().breakpoints.iter().find(|b| b.xt == xt)
+        {
+            Some(b) => (b.predicate, b.skip, b.hits),
+            None => return false,
+        };
+        if self.state().break_depth() >= MAX_BREAK_DEPTH {
+            return false;
+        }
+        if predicate != 0 {
+            // Run the predicate with breakpoints disarmed.
+            self.wordlist_mut().has_breakpoints = false;
+            self.execute_xt(predicate);
+            self.wordlist_mut().has_breakpoints = true;
+            self.state().word_pointer = xt;
+            if self.last_error().is_some() || self.s_stack().pop() == FALSE {
+                return false;
+            }
+        }
+        if let Some(b) = self
+            .wordlist_mut()
+            .breakpoints
+            .iter_mut()
+            .find(|b| b.xt == xt)
+        {
+            b.hits = hits + 1;
+        }
+        if hits < skip {
+            return false;
+        }
+        self.enter_break(USER_INTERRUPT, Some(xt));
+        true
+    }
+
+    /// Abort the inner loop with an exception, reset VM and clears stacks.
+    fn abort(&mut self) {
+        self.abort_with(ABORT);
+    }
+
+    /// Pause the current task and resume the next task which is awake.
+    ///
+    /// Tasks blocked on a semaphore or a time are skipped, see
+    /// `task-status`. Tasks scripted by `schedule"` are resumed first, if
+    /// awake. No operation if `solo` is on.
+    ///
+    /// Otherwise task switches are offered alternately to high-priority
+    /// and normal-priority tasks, see `priority!`. Within a class, the
+    /// awake task following the one which last ran in the class is chosen,
+    /// in the order of task ids, wrapping around. If no task of the class
+    /// is awake, the switch goes to the other class. For example, with
+    /// task 3 of high priority and tasks 1 and 2 of normal priority, all
+    /// awake, task 1 pausing repeatedly gives `3 2 3 1 3 2 ...`. Without
+    /// high-priority tasks, this is the usual round robin.
+    ///
+    /// If no task is to be resumed and all awake tasks are blocked on
+    /// semaphores, none of them can ever continue. The tasks waiting are
+    /// then recorded in `Wordlist::deadlock`, printed by `.deadlock`, and
+    /// the operator task is woken up and aborted with `DEADLOCK`.
+    ///
+    /// Timers due are dispatched before the switch, see `after`.
+    fn pause(&mut self) {
+        if self.wordlist().scanning {
+            self.wordlist_mut().slice_ended = true;
+            return;
+        }
+        self.run_timers();
+        if self.last_error().is_some() {
+            return;
+        }
+        match self.next_task() {
+            Some(i) => self.set_current_task(i),
+            None if self.deadlocked() => {
+                self.abort_deadlock();
+                return;
             }
+            None => {}
         }
+        let current = self.current_task();
+        self.data_space()
+            .system_variables_mut()
+            .set_wait(current, Wait::None);
     }
 
-    // -----------------------
-    // High level definitions
-    // -----------------------
-
-    fn nest(&mut self) {
-        let rlen = self.r_stack().len.wrapping_add(1);
-        self.r_stack().len = rlen;
-        self.r_stack()[rlen.wrapping_sub(1)] = self.state().instruction_pointer as isize;
-        let wp = self.state().word_pointer;
-        self.state().instruction_pointer = self.wordlist()[wp].dfa();
+    /// Task to be resumed by `pause`, `None` to continue the current task.
+    fn next_task(&mut self) -> Option<usize> {
+        while let Some(i) = self.data_space().system_variables_mut().next_scheduled() {
+            if self.awake(i) && !self.blocked(i) {
+                return Some(i);
+            }
+        }
+        if self.data_space().system_variables().solo() {
+            return None;
+        }
+        let turn = self.data_space().system_variables_mut().next_turn();
+        let other = if turn == HIGH_PRIORITY {
+            NORMAL_PRIORITY
+        } else {
+            HIGH_PRIORITY
+        };
+        for &priority in &[turn, other] {
+            if let Some(i) = self.next_awake_task(priority) {
+                self.data_space()
+                    .system_variables_mut()
+                    .set_last_run(priority, i);
+                return Some(i);
+            }
+        }
+        None
     }
 
-    fn p_var(&mut self) {
-        let wp = self.state().word_pointer;
-        let dfa = self.wordlist()[wp].dfa() as isize;
-        self.s_stack().push(dfa);
+    /// True if task `i` waits for a semaphore not yet released or a time
+    /// not yet reached. The wait is cleared when the task is resumed, which
+    /// then checks its condition again.
+    fn blocked(&self, i: usize) -> bool {
+        match self.data_space_const().system_variables().wait(i) {
+            Wait::None => false,
+            Wait::Semaphore(a) => {
+                let data_space = self.data_space_const();
+                data_space.start() <= a
+                    && a + mem::size_of::<isize>() <= data_space.limit()
+                    && unsafe { data_space.get_isize(a) } != 0
+            }
+            Wait::Time(t) => self.monotonic_ns() < t,
+            Wait::Io(token) => !self.wordlist().poll.is_complete(token),
+        }
     }
 
-    fn p_const(&mut self) {
-        let wp = self.state().word_pointer;
-        let dfa = self.wordlist()[wp].dfa();
-        let value = unsafe { self.data_space().get_isize(dfa) as isize };
-        self.s_stack().push(value);
+    /// True if all awake tasks are blocked on semaphores.
+    fn deadlocked(&self) -> bool {
+        (0..NUM_TASKS).filter(|&i| self.awake(i)).all(|i| {
+            match self.data_space_const().system_variables().wait(i) {
+                Wait::Semaphore(_) => self.blocked(i),
+                _ => false,
+            }
+        })
     }
 
-    fn define(&mut self, action: fn(&mut Self), compilation_semantics: fn(&mut Self, usize)) {
-        self.parse_word();
-        let mut last_token = self.last_token().take().expect("last token");
-        last_token.make_ascii_lowercase();
-        if let Some(_) = self.find(&last_token) {
-            match self.output_buffer().as_mut() {
-                Some(buf) => {
-                    write!(buf, "Redefining {}", last_token).expect("write");
-                }
-                None => {}
+    /// Record the tasks waiting for semaphores and abort the operator task
+    /// with `DEADLOCK`.
+    fn abort_deadlock(&mut self) {
+        let mut deadlock = Vec::new();
+        for i in 0..NUM_TASKS {
+            if !self.awake(i) {
+                continue;
+            }
+            if let Wait::Semaphore(a) = self.data_space_const().system_variables().wait(i) {
+                let holder = unsafe { self.data_space_const().get_isize(a) } - 1;
+                deadlock.push(WaitFor {
+                    task: i,
+                    semaphore: a,
+                    holder: if 0 <= holder && (holder as usize) < NUM_TASKS {
+                        Some(holder as usize)
+                    } else {
+                        None
+                    },
+                });
             }
         }
-        if last_token.is_empty() {
-            self.set_last_token(last_token);
-            self.abort_with(UNEXPECTED_END_OF_FILE);
+        self.wordlist_mut().deadlock = deadlock;
+        self.set_current_task(0);
+        self.set_awake(0, true);
+        self.data_space()
+            .system_variables_mut()
+            .set_wait(0, Wait::None);
+        self.abort_with(DEADLOCK);
+    }
+
+    /// State of task `i`, 0-based task id.
+    fn task_status(&self, i: usize) -> TaskStatus {
+        if !self.awake(i) {
+            TaskStatus::Suspended
+        } else if i == self.current_task() {
+            TaskStatus::Running
+        } else if !self.blocked(i) {
+            TaskStatus::Ready
         } else {
-            let nfa = self.data_space().compile_str(&last_token);
-            self.data_space().align();
-            let word = Word::new(action, compilation_semantics, nfa, self.data_space().here());
-            self.wordlist_mut().push(&last_token, word);
-            self.set_last_token(last_token);
-        }
-    }
-
-    fn colon(&mut self) {
-        self.define(Core::nest, Core::compile_nest);
-        if self.last_error().is_none() {
-            let def = self.wordlist().last;
-            self.compile_nest_code(def);
-            self.wordlist_mut()[def].set_hidden(true);
-            self.right_bracket();
+            match self.data_space_const().system_variables().wait(i) {
+                Wait::Time(_) => TaskStatus::BlockedOnTime,
+                Wait::Io(_) => TaskStatus::BlockedOnIo,
+                _ => TaskStatus::BlockedOnSemaphore,
+            }
         }
     }
 
-    fn semicolon(&mut self) {
-        if self.c_stack().len != 0 {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-        } else if self.forward_bitset().layer3() != 0 {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+    /// State of task `i`: 0 running, 1 ready, 2 blocked on a semaphore, 3
+    /// blocked on time, 4 suspended and 5 blocked on I/O.
+    /// `task-status ( i -- n )`
+    fn p_task_status(&mut self) {
+        let i = (self.s_stack().pop() - 1) as usize;
+        if i < NUM_TASKS {
+            let status = self.task_status(i);
+            self.s_stack().push(status as isize);
         } else {
-            let idx = self.references().idx_exit;
-            let compile = self.wordlist()[idx].compilation_semantics;
-            compile(self, idx);
-            let def = self.wordlist().last;
-            self.wordlist_mut()[def].set_hidden(false);
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
         }
-        self.left_bracket();
     }
 
-    fn create(&mut self) {
-        self.define(Core::p_var, Core::compile_var);
+    /// Register word `xt` as the next phase of `Core::scan`.
+    /// `scan-phase ( xt -- )`
+    fn scan_phase(&mut self) {
+        let xt = self.s_stack().pop();
+        match self.wordlist().untag_xt(xt) {
+            Some(xt) => self.wordlist_mut().scan_phases.push(xt),
+            None => self.abort_with(INVALID_NUMERIC_ARGUMENT),
+        }
     }
 
-    fn constant(&mut self) {
-        let v = self.s_stack().pop();
-        self.define(Core::p_const, Core::compile_const);
-        if self.last_error().is_none() {
-            self.data_space().compile_isize(v as isize);
+    /// Phase of `Core::scan` running each other awake task not blocked
+    /// until its next `pause`, in the order of task ids. `scan-tasks ( -- )`
+    fn scan_tasks(&mut self) {
+        let caller = self.current_task();
+        let scanning = mem::replace(&mut self.wordlist_mut().scanning, true);
+        for i in 0..NUM_TASKS {
+            if i == caller || !self.awake(i) || self.blocked(i) {
+                continue;
+            }
+            self.set_current_task(i);
+            self.data_space()
+                .system_variables_mut()
+                .set_wait(i, Wait::None);
+            self.wordlist_mut().slice_ended = false;
+            while !self.wordlist().slice_ended
+                && self.last_error().is_none()
+                && self.forth() == Termination::Yielded
+            {}
+            self.set_current_task(caller);
+            if self.last_error().is_some() {
+                break;
+            }
         }
+        self.wordlist_mut().scanning = scanning;
     }
 
-    fn unmark(&mut self) {
-        let wp = self.state().word_pointer;
-        let (nfa, mut dfa) = {
-            let w = &self.wordlist()[wp];
-            (w.nfa(), w.dfa())
-        };
-        let x = unsafe { self.data_space().get_usize(dfa) };
-        self.wordlist_mut().last = x;
-        for i in 0..BUCKET_SIZE {
-            dfa += mem::size_of::<usize>();
-            let x = unsafe { self.data_space().get_usize(dfa) };
-            self.wordlist_mut().buckets[i] = x;
+    /// Set the period of `Core::scan` to `n` microseconds, above which a
+    /// scan is counted as an overrun. Zero disables the detection.
+    /// `scan-period! ( n -- )`
+    fn scan_period_store(&mut self) {
+        let n = self.s_stack().pop();
+        if n >= 0 {
+            self.wordlist_mut().scan_period = n as u64 * 1000;
+        } else {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
         }
-        self.data_space().truncate(nfa);
-        self.wordlist_mut().truncate(wp);
     }
 
-    /// Example:
-    /// ```text
-    /// marker -work
-    ///
-    /// DFA of -work
-    /// +------+--------+
-    /// | last | b0-b63 |
-    /// +------+--------+
-    /// ```
-    fn marker(&mut self) {
-        let x = self.wordlist().last;
-        self.wordlist_mut().temp_buckets = self.wordlist().buckets;
-        self.define(Core::unmark, Core::compile_unmark);
-        self.data_space().compile_usize(x);
-        for i in 0..BUCKET_SIZE {
-            let x = self.wordlist().temp_buckets[i];
-            self.data_space().compile_usize(x);
-        }
+    /// Number of overruns of `Core::scan`. `scan-overruns ( -- n )`
+    fn p_scan_overruns(&mut self) {
+        let n = self.wordlist().scan_overruns;
+        self.s_stack().push(n as isize);
     }
 
-    /// Run time behavior of words created by `create` ... `does>`.
-    ///
-    /// Token threaded version.
-    ///
-    /// Example of does>
-    /// ```
-    /// : 2constant   create , , does> 2@ ;
-    /// 4 40 2constant range
-    ///
-    /// 2constant
-    /// +--------+---+---+-------+------+----+------+
-    /// | create | , | , | _does | exit | 2@ | exit |
-    /// +--------+---+---+-------+------+----+------+
-    ///                                   ^
-    /// range                             |
-    ///                                   |
-    ///   action                          |
-    ///   +-------+                       |
-    ///   | xdoes |                       |
-    ///   +-------+                       |
-    ///                                   |
-    ///   doer                            |
-    ///   +------+                        |
-    ///   | x    |------------------------+
-    ///   +------+
+    /// Execute word `xt` once in `ns` nanoseconds. `after ( ns xt -- timer# )`
     ///
-    ///   dfa
-    ///   +---+----+
-    ///   | 4 | 40 |
-    ///   +---+----+
-    /// ```
-    fn does(&mut self) {
-        let idx = self.references().idx__does;
-        self.s_stack().push(idx as isize);
-        self.compile_comma();
-        let idx = self.references().idx_exit;
-        self.s_stack().push(idx as isize);
-        self.compile_comma();
+    /// The timer is dispatched in the task calling `pause`, or at the
+    /// start of `Core::scan`, once its deadline has passed. Timers due
+    /// together run in the order of their deadlines.
+    fn after(&mut self) {
+        let xt = self.s_stack().pop();
+        let ns = self.s_stack().pop();
+        let xt = match self.wordlist().untag_xt(xt) {
+            Some(xt) if ns >= 0 && xt > 0 => xt,
+            _ => {
+                self.abort_with(INVALID_NUMERIC_ARGUMENT);
+                return;
+            }
+        };
+        let deadline = self.monotonic_ns().saturating_add(ns as u64);
+        let wordlist = self.wordlist_mut();
+        wordlist.last_timer += 1;
+        let id = wordlist.last_timer;
+        wordlist.timers.push(Reverse(Timer { deadline, id, xt }));
+        self.s_stack().push(id as isize);
     }
 
-    fn xdoes(&mut self) {
-        // Push DFA.
-        let wp = self.state().word_pointer;
-        let word = &self.wordlist()[wp];
-        let dfa = word.dfa();
-        let doer = word.doer;
-        self.s_stack().push(dfa as isize);
-        // Execute words behind DOES>.
-        let ip = self.state().instruction_pointer as isize;
-        self.r_stack().push(ip);
-        self.state().instruction_pointer = doer;
+    /// Cancel timer `timer#` set by `after`. No operation if the timer
+    /// has already run. `cancel ( timer# -- )`
+    fn cancel(&mut self) {
+        let id = self.s_stack().pop() as usize;
+        self.wordlist_mut().timers.retain(|t| t.0.id != id);
     }
 
-    /// Run time behavior of does>.
-    fn _does(&mut self) {
-        let doer = self.state().instruction_pointer + mem::size_of::<isize>();
-        self.data_space().compile_usize(doer);
-        let def = self.wordlist().last;
-        let word = &mut self.wordlist_mut()[def];
-        word.action = Core::xdoes;
-        word.doer = doer;
+    /// Execute the timers whose deadlines have passed, stopping at the
+    /// first error. A timer dispatched more than `TIMER_SLACK`
+    /// nanoseconds late is counted by `timers-overdue`.
+    fn run_timers(&mut self) {
+        loop {
+            let now = self.monotonic_ns();
+            let timer = match self.wordlist().timers.peek() {
+                Some(&Reverse(t)) if t.deadline <= now => t,
+                _ => return,
+            };
+            self.wordlist_mut().timers.pop();
+            let lateness = now - timer.deadline;
+            let wordlist = self.wordlist_mut();
+            wordlist.timer_lateness = lateness;
+            if lateness > TIMER_SLACK {
+                wordlist.timers_overdue += 1;
+            }
+            self.execute_xt(timer.xt);
+            if self.last_error().is_some() {
+                return;
+            }
+        }
     }
 
-    // -----------
-    // Primitives
-    // -----------
-
-    /// Run-time: ( -- )
-    ///
-    /// No operation
-    fn noop(&mut self) {
-        // Do nothing
+    /// Number of timers dispatched late. `timers-overdue ( -- n )`
+    fn p_timers_overdue(&mut self) {
+        let n = self.wordlist().timers_overdue;
+        self.s_stack().push(n as isize);
     }
 
-    /// Run-time: ( -- true )
-    ///
-    /// Return a true flag, a single-cell value with all bits set.
-    fn p_true(&mut self) {
-        self.s_stack().push(TRUE);
+    /// New token for an I/O operation completed by the host through
+    /// `Wordlist::poll_handle`. `io-token ( -- token )`
+    fn io_token(&mut self) {
+        let token = self.wordlist().poll.new_token();
+        self.s_stack().push(token as isize);
     }
 
-    /// Run-time: ( -- false )
-    ///
-    /// Return a false flag.
-    fn p_false(&mut self) {
-        self.s_stack().push(FALSE);
+    /// True if I/O operation `token` has completed. `io-done? ( token -- flag )`
+    fn io_done_q(&mut self) {
+        let token = self.s_stack().pop() as usize;
+        let done = self.wordlist().poll.is_complete(token);
+        self.s_stack().push(if done { TRUE } else { FALSE });
     }
 
-    /// Run-time: ( c-addr1 -- c-addr2 )
-    ///
-    ///Add the size in address units of a character to `c-addr1`, giving `c-addr2`.
-    fn char_plus(&mut self) {
-        let v = self.s_stack().pop();
-        self.s_stack().push(v + mem::size_of::<u8>() as isize);
+    /// Result of the completed I/O operation `token`, aborts with
+    /// `INVALID_NUMERIC_ARGUMENT` if not completed. `io-result ( token -- x )`
+    fn io_result(&mut self) {
+        let token = self.s_stack().pop() as usize;
+        match self.wordlist().poll.take(token) {
+            Some(x) => self.s_stack().push(x),
+            None => self.abort_with(INVALID_NUMERIC_ARGUMENT),
+        }
     }
 
-    /// Run-time: ( a-addr1 -- a-addr2 )
-    ///
-    /// Add the size in address units of a cell to `a-addr1`, giving `a-addr2`.
-    fn cell_plus(&mut self) {
-        let v = self.s_stack().pop();
-        self.s_stack().push(v + mem::size_of::<isize>() as isize);
+    /// Let the current task wait for I/O operation `token` to complete
+    /// until resumed by `pause`. Used by `io-wait`. `(wait-io) ( token -- )`
+    fn wait_io(&mut self) {
+        let token = self.s_stack().pop() as usize;
+        let current = self.current_task();
+        self.data_space()
+            .system_variables_mut()
+            .set_wait(current, Wait::Io(token));
+    }
+
+    /// Let the current task wait for the semaphore at `a` to be released
+    /// until resumed by `pause`. Used by `get`. `(wait-semaphore) ( a -- )`
+    fn wait_semaphore(&mut self) {
+        let a = self.s_stack().pop() as usize;
+        let current = self.current_task();
+        self.data_space()
+            .system_variables_mut()
+            .set_wait(current, Wait::Semaphore(a));
+    }
+
+    /// Next awake task of priority class `priority` to run, 0-based task
+    /// id, see `pause`.
+    fn next_awake_task(&mut self, priority: u8) -> Option<usize> {
+        let current = self.current_task();
+        let start = if self.data_space().system_variables().priority(current) == priority {
+            current
+        } else {
+            self.data_space().system_variables().last_run(priority)
+        };
+        (1..=NUM_TASKS).map(|k| (start + k) % NUM_TASKS).find(|&i| {
+            self.awake(i)
+                && !self.blocked(i)
+                && self.data_space_const().system_variables().priority(i) == priority
+        })
+    }
+
+    /// Set the priority class of task `i` to `n`, 0 for normal and 1 for
+    /// high priority. `priority! ( n i -- )`
+    fn priority_store(&mut self) {
+        let (n, i) = self.s_stack().pop2();
+        let i = (i - 1) as usize;
+        if i < NUM_TASKS && (n == NORMAL_PRIORITY as isize || n == HIGH_PRIORITY as isize) {
+            self.data_space()
+                .system_variables_mut()
+                .set_priority(i, n as u8);
+        } else {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
     }
 
-    /// Run-time: ( n1 -- n2 )
-    ///
-    /// `n2` is the size in address units of `n1` cells.
-    fn cells(&mut self) {
-        let v = self.s_stack().pop();
-        self.s_stack().push(v * mem::size_of::<isize>() as isize);
+    /// Priority class of task `i`. `priority@ ( i -- n )`
+    fn priority_fetch(&mut self) {
+        let i = (self.s_stack().pop() - 1) as usize;
+        if i < NUM_TASKS {
+            let n = self.data_space().system_variables().priority(i);
+            self.s_stack().push(n as isize);
+        } else {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
     }
 
-    fn swap(&mut self) {
-        let slen = self.s_stack().len;
-        let t = self.s_stack()[slen.wrapping_sub(1)];
-        let n = self.s_stack()[slen.wrapping_sub(2)];
-        self.s_stack()[slen.wrapping_sub(1)] = n;
-        self.s_stack()[slen.wrapping_sub(2)] = t;
+    /// Hand `x` over to task `i` for its next `activate`. `pass ( x i -- )`
+    ///
+    /// ```forth
+    /// variable ticks
+    /// : worker   2 activate ( a-addr )  begin 1 over +! pause again ;
+    /// ticks 2 pass  worker
+    /// ```
+    ///
+    /// Aborts with `RESULT_OUT_OF_RANGE` if more values are passed than
+    /// the data stack of task `i` holds.
+    fn pass(&mut self) {
+        let (x, i) = self.s_stack().pop2();
+        let i = (i - 1) as usize;
+        if i >= NUM_TASKS {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+            return;
+        }
+        let current_task = self.current_task();
+        self.set_current_task(i);
+        let full = self.state().passed.len() >= self.s_stack().limit() as usize;
+        if !full {
+            self.state().passed.push(x);
+        }
+        self.set_current_task(current_task);
+        if full {
+            self.abort_with(RESULT_OUT_OF_RANGE);
+        }
     }
 
-    fn dup(&mut self) {
-        let slen = self.s_stack().len.wrapping_add(1);
-        self.s_stack().len = slen;
-        self.s_stack()[slen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(2)];
+    /// Current task ID
+    fn me(&mut self) {
+        let me = self.current_task() + 1;
+        self.s_stack().push(me as isize);
     }
 
-    fn p_drop(&mut self) {
-        let slen = self.s_stack().len.wrapping_sub(1);
-        self.s_stack().len = slen;
+    /// Suspend task `i`. `suspend ( i -- )`
+    fn suspend(&mut self) {
+        let i = (self.s_stack().pop() - 1) as usize;
+        if i < NUM_TASKS {
+            self.set_awake(i as usize, false);
+        } else {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
     }
 
-    fn pop_s_stack(&mut self) -> isize {
-        let slen = self.s_stack().len.wrapping_sub(1);
-        let t = self.s_stack()[slen];
-        self.s_stack().len = slen;
-        t
+    /// Resume task `i`. `resume ( i -- )`
+    fn resume(&mut self) {
+        let i = (self.s_stack().pop() - 1) as usize;
+        if i < NUM_TASKS {
+            self.set_awake(i as usize, true);
+        } else {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
     }
 
-    fn nip(&mut self) {
-        let slen = self.s_stack().len.wrapping_sub(1);
-        let t = self.s_stack()[slen];
-        self.s_stack().len = slen;
-        self.s_stack()[slen.wrapping_sub(1)] = t;
+    /// Evaluate the string `c-addr u` in task `i`. `task-eval ( c-addr u i -- )`
+    ///
+    /// The string is copied to the input buffer of task `i`, which is woken
+    /// up and, when resumed by `pause`, evaluates it with `(task-eval)`
+    /// before continuing where it was paused. Task `i` is expected to be
+    /// paused in its own loop, not interpreting its own input.
+    fn task_eval(&mut self) {
+        let (caddr, u, i) = self.s_stack().pop3();
+        let i = (i - 1) as usize;
+        if i >= NUM_TASKS || i == self.current_task() || caddr < 0 || u < 0 {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+            return;
+        }
+        let xt = match self.find("(task-eval)") {
+            Some(xt) => xt,
+            None => {
+                self.abort_with(UNDEFINED_WORD);
+                return;
+            }
+        };
+        let text = String::from(unsafe {
+            self.data_space()
+                .str_from_raw_parts(caddr as usize, u as usize)
+        });
+        let dfa = self.wordlist()[xt].dfa();
+        let current_task = self.current_task();
+        self.set_current_task(i);
+        self.state().source_id = 0;
+        self.set_input_buffer(text);
+        self.state().source_index = 0;
+        self.state().is_compiling = false;
+        let ip = self.state().instruction_pointer;
+        self.r_stack().push(ip as isize);
+        self.state().instruction_pointer = dfa;
+        self.set_current_task(current_task);
+        self.set_awake(i, true);
+    }
+
+    /// Name of the constant of exception code `n`, such as
+    /// `stack-underflow` for -4, the message of an exception allocated by
+    /// `new-exception`, or an empty string if `n` is not a known code.
+    /// `exception-name ( n -- c-addr u )`
+    fn exception_name(&mut self) {
+        let n = self.s_stack().pop();
+        let user = Exception::from_code(n)
+            .and_then(|e| e.user_index())
+            .and_then(|i| self.wordlist().user_exceptions().get(i));
+        let name = match user {
+            Some(message) => message.as_str(),
+            None => exception::name(n).unwrap_or(""),
+        };
+        let (addr, len) = (shared_name(name) + mem::size_of::<usize>(), name.len());
+        self.s_stack().push2(addr as isize, len as isize);
     }
 
-    fn over(&mut self) {
-        let slen = self.s_stack().len.wrapping_add(1);
-        self.s_stack().len = slen;
-        self.s_stack()[slen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(3)];
+    /// Allocate a new exception code `n` below `USER_EXCEPTIONS`, described
+    /// by the string `c-addr u` in `.error` and `exception-name`.
+    /// `new-exception ( c-addr u -- n )`
+    ///
+    /// Aborts with `RESULT_OUT_OF_RANGE` if no code is left.
+    fn new_exception(&mut self) {
+        let (caddr, u) = self.s_stack().pop2();
+        if caddr < 0 || u < 0 {
+            self.abort_with(INVALID_MEMORY_ADDRESS);
+            return;
+        }
+        let message = String::from(unsafe {
+            self.data_space()
+                .str_from_raw_parts(caddr as usize, u as usize)
+        });
+        match self.wordlist_mut().new_exception(&message) {
+            Some(e) => self.s_stack().push(isize::from(e)),
+            None => self.abort_with(RESULT_OUT_OF_RANGE),
+        }
     }
 
-    fn rot(&mut self) {
-        let slen = self.s_stack().len;
-        let t = self.s_stack()[slen.wrapping_sub(1)];
-        let n = self.s_stack()[slen.wrapping_sub(2)];
-        self.s_stack()[slen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(3)];
-        self.s_stack()[slen.wrapping_sub(2)] = t;
-        self.s_stack()[slen.wrapping_sub(3)] = n;
+    /// Abort with exception `n` if it is not zero.
+    /// `throw ( n -- )`
+    ///
+    /// Codes out of the range of exceptions abort with `RESULT_OUT_OF_RANGE`.
+    fn throw(&mut self) {
+        let n = self.s_stack().pop();
+        if n != 0 {
+            self.abort_with(Exception::from_code(n).unwrap_or(RESULT_OUT_OF_RANGE));
+        }
     }
 
-    fn minus_rot(&mut self) {
-        let slen = self.s_stack().len;
-        let t = self.s_stack()[slen.wrapping_sub(1)];
-        let n = self.s_stack()[slen.wrapping_sub(2)];
-        self.s_stack()[slen.wrapping_sub(2)] = self.s_stack()[slen.wrapping_sub(3)];
-        self.s_stack()[slen.wrapping_sub(3)] = t;
-        self.s_stack()[slen.wrapping_sub(1)] = n;
+    /// Feed the watchdog of the current task.
+    fn feed_watchdog(&mut self) {
+        let now = self.monotonic_ns();
+        self.state().watchdog_fed = now;
     }
 
-    /// Place a copy of the nth stack entry on top of the stack. `pick ( ... n -- x )`
+    /// True if the watchdog of task `i` is enabled and not fed in time.
+    /// Tasks which are not awake never expire.
     ///
-    /// `0 pick` is equivalent to `dup`.
-    fn pick(&mut self) {
-        let slen = self.s_stack().len;
-        let t = self.s_stack()[slen.wrapping_sub(1)] as u8;
-        let x = self.s_stack()[slen.wrapping_sub(t.wrapping_add(2))];
-        self.s_stack()[slen.wrapping_sub(1)] = x;
+    /// Task `i` starts from 0.
+    fn watchdog_expired(&mut self, i: usize) -> bool {
+        if i >= NUM_TASKS || !self.awake(i) {
+            return false;
+        }
+        let now = self.monotonic_ns();
+        let current_task = self.current_task();
+        self.set_current_task(i);
+        let timeout = self.state().watchdog_timeout;
+        let fed = self.state().watchdog_fed;
+        self.set_current_task(current_task);
+        timeout != 0 && now.saturating_sub(fed) > timeout
+    }
+
+    /// Feed the watchdog of the current task. `feed-watchdog ( -- )`
+    fn p_feed_watchdog(&mut self) {
+        self.feed_watchdog();
+    }
+
+    /// Set watchdog timeout of task `i` to `n` milliseconds and feed it.
+    /// Zero disables the watchdog. `watchdog! ( n i -- )`
+    fn watchdog_store(&mut self) {
+        let (n, i) = self.s_stack().pop2();
+        let i = (i - 1) as usize;
+        if i < NUM_TASKS && n >= 0 {
+            let current_task = self.current_task();
+            self.set_current_task(i);
+            self.state().watchdog_timeout = n as u64 * 1_000_000;
+            self.feed_watchdog();
+            self.set_current_task(current_task);
+        } else {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
     }
 
-    fn two_drop(&mut self) {
-        let slen = self.s_stack().len.wrapping_sub(2);
-        self.s_stack().len = slen;
+    /// Set recovery word `xt ( i -- )` of task `i`, 0 for none.
+    /// `watchdog-xt! ( xt i -- )`
+    fn watchdog_xt_store(&mut self) {
+        let (xt, i) = self.s_stack().pop2();
+        let i = (i - 1) as usize;
+        match self.wordlist().untag_xt(xt) {
+            Some(xt) if i < NUM_TASKS => {
+                let current_task = self.current_task();
+                self.set_current_task(i);
+                self.state().watchdog_xt = xt;
+                self.set_current_task(current_task);
+            }
+            _ => self.abort_with(INVALID_NUMERIC_ARGUMENT),
+        }
     }
 
-    fn two_dup(&mut self) {
-        let slen = self.s_stack().len.wrapping_add(2);
-        self.s_stack().len = slen;
-        self.s_stack()[slen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(3)];
-        self.s_stack()[slen.wrapping_sub(2)] = self.s_stack()[slen.wrapping_sub(4)];
+    /// `watchdog-expired? ( i -- flag )`
+    fn p_watchdog_expired(&mut self) {
+        let i = (self.s_stack().pop() - 1) as usize;
+        let expired = self.watchdog_expired(i);
+        self.s_stack().push(if expired { TRUE } else { FALSE });
     }
 
-    fn two_swap(&mut self) {
-        let slen = self.s_stack().len;
-        let t = self.s_stack()[slen.wrapping_sub(1)];
-        let n = self.s_stack()[slen.wrapping_sub(2)];
-        self.s_stack()[slen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(3)];
-        self.s_stack()[slen.wrapping_sub(2)] = self.s_stack()[slen.wrapping_sub(4)];
-        self.s_stack()[slen.wrapping_sub(3)] = t;
-        self.s_stack()[slen.wrapping_sub(4)] = n;
+    /// Check watchdogs of all tasks. `check-watchdogs ( -- )`
+    ///
+    /// For each expired task `i`, execute its recovery word `( i -- )` and
+    /// feed its watchdog. Abort with `WATCHDOG_EXPIRED` if the task has no
+    /// recovery word.
+    fn check_watchdogs(&mut self) {
+        for i in 0..NUM_TASKS {
+            if self.watchdog_expired(i) {
+                let current_task = self.current_task();
+                self.set_current_task(i);
+                let xt = self.state().watchdog_xt;
+                self.feed_watchdog();
+                self.set_current_task(current_task);
+                if xt == 0 {
+                    self.abort_with(WATCHDOG_EXPIRED);
+                    return;
+                }
+                self.s_stack().push(i as isize + 1);
+                self.execute_xt(xt);
+                if self.last_error().is_some() {
+                    return;
+                }
+            }
+        }
     }
 
-    fn two_over(&mut self) {
-        let slen = self.s_stack().len.wrapping_add(2);
-        self.s_stack().len = slen;
-        self.s_stack()[slen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(5)];
-        self.s_stack()[slen.wrapping_sub(2)] = self.s_stack()[slen.wrapping_sub(6)];
+    /// Address of the flag to run only the current task. `solo ( -- a-addr )`
+    ///
+    /// When on, `pause` does not switch tasks, unless scripted by
+    /// `schedule"`.
+    fn solo(&mut self) {
+        let addr = self.data_space().system_variables().solo_addr();
+        self.s_stack().push(addr as isize);
     }
 
-    fn depth(&mut self) {
-        let len = self.s_stack().len;
-        self.s_stack().push(len as isize);
+    /// Script the task switches of the following `pause`s, used to
+    /// reproduce specific interleavings of tasks. `schedule" 1 2 1 3"`
+    ///
+    /// Once the schedule is consumed, `pause` switches tasks as usual.
+    fn schedule_quote(&mut self) {
+        self.s_stack().push('"' as isize);
+        self.parse();
+        let last_token = self.last_token().take().expect("last token");
+        let mut tasks = Vec::new();
+        let mut valid = true;
+        for t in last_token.split_whitespace() {
+            match t.parse::<usize>() {
+                Ok(i) if (1..=NUM_TASKS).contains(&i) => tasks.push((i - 1) as u8),
+                _ => valid = false,
+            }
+        }
+        self.set_last_token(last_token);
+        if !valid {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        } else if tasks.len() > SCHEDULE_CAPACITY {
+            self.abort_with(RESULT_OUT_OF_RANGE);
+        } else {
+            self.data_space()
+                .system_variables_mut()
+                .set_schedule(&tasks);
+        }
     }
+}
 
-    fn one_plus(&mut self) {
-        let slen = self.s_stack().len;
-        let t = self.s_stack()[slen.wrapping_sub(1)];
-        self.s_stack()[slen.wrapping_sub(1)] = t.wrapping_add(1);
+#[cfg(test)]
+mod tests {
+    extern crate test;
+    use self::test::Bencher;
+    use super::{Core, Memory, Termination};
+    use builder::{VmBuilder, WordSet};
+    use exception::{
+        Exception, ABORT, CONTROL_STRUCTURE_MISMATCH, DEADLOCK, DIVISION_BY_ZERO,
+        FLOATING_POINT_STACK_UNDERFLOW, INTERPRETING_A_COMPILE_ONLY_WORD, INVALID_MEMORY_ADDRESS,
+        INVALID_NAME_ARGUMENT, INVALID_NUMERIC_ARGUMENT, INVALID_XT, NESTING_LIMIT,
+        PERMISSION_DENIED, PRIVILEGED_WORD, RESULT_OUT_OF_RANGE, RETURN_STACK_UNDERFLOW,
+        STACK_OVERFLOW, STACK_UNDERFLOW, TIMEOUT, UNDEFINED_WORD, UNEXPECTED_END_OF_FILE,
+        UNSUPPORTED_OPERATION, USER_INTERRUPT, WATCHDOG_EXPIRED,
+    };
+    use mock_vm::VM;
+    use std::mem;
+
+    #[bench]
+    fn bench_vm_new(b: &mut Bencher) {
+        b.iter(|| VM::new());
     }
 
-    fn one_minus(&mut self) {
-        let slen = self.s_stack().len;
-        let t = self.s_stack()[slen.wrapping_sub(1)];
-        self.s_stack()[slen.wrapping_sub(1)] = t.wrapping_sub(1);
+    #[bench]
+    fn bench_vm_new_without_core_image(b: &mut Bencher) {
+        let builder = VmBuilder::new().core_image(false);
+        b.iter(|| VM::with_builder(&builder));
     }
 
-    fn plus(&mut self) {
-        let slen = self.s_stack().len;
-        let t = self.s_stack()[slen.wrapping_sub(1)];
-        let n = self.s_stack()[slen.wrapping_sub(2)];
-        self.s_stack()[slen.wrapping_sub(2)] = n.wrapping_add(t);
-        self.s_stack().len = slen.wrapping_sub(1);
+    #[bench]
+    fn bench_vm_new_lazy(b: &mut Bencher) {
+        let builder = VmBuilder::new().lazy(true);
+        b.iter(|| VM::with_builder(&builder));
     }
 
-    fn minus(&mut self) {
-        let slen = self.s_stack().len;
-        let t = self.s_stack()[slen.wrapping_sub(1)];
-        let n = self.s_stack()[slen.wrapping_sub(2)];
-        self.s_stack()[slen.wrapping_sub(2)] = n.wrapping_sub(t);
-        self.s_stack().len = slen.wrapping_sub(1);
+    #[bench]
+    fn bench_noop(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        b.iter(|| vm.noop());
     }
 
-    fn star(&mut self) {
-        let slen = self.s_stack().len;
-        let t = self.s_stack()[slen.wrapping_sub(1)];
-        let n = self.s_stack()[slen.wrapping_sub(2)];
-        self.s_stack()[slen.wrapping_sub(2)] = n.wrapping_mul(t);
-        self.s_stack().len = slen.wrapping_sub(1);
+    #[test]
+    fn test_find() {
+        let vm = &mut VM::new();
+        assert!(vm.find("").is_none());
+        assert!(vm.find("word-not-exist").is_none());
+        vm.find("noop").expect("noop not found");
     }
 
-    fn slash(&mut self) {
-        let slen = self.s_stack().len;
-        let t = self.s_stack()[slen.wrapping_sub(1)];
-        let n = self.s_stack()[slen.wrapping_sub(2)];
-        if t == 0 {
-            self.abort_with(DIVISION_BY_ZERO);
-        } else {
-            self.s_stack()[slen.wrapping_sub(2)] = n.wrapping_div(t);
-            self.s_stack().len = slen.wrapping_sub(1);
-        }
+    #[test]
+    fn test_name_case() {
+        let vm = &mut VM::new();
+        vm.set_output_buffer(String::new());
+        vm.set_source(": GetAxisPos ( -- n ) 3 ;  getaxispos GETAXISPOS  apropos axis");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [3, 3]);
+        assert_eq!(vm.output_buffer().take().unwrap(), "GetAxisPos ( -- n ) ");
+        let xt = vm.find("GETaxisPOS").expect("GetAxisPos");
+        assert_eq!(vm.word_name(xt), "GetAxisPos");
+        vm.set_output_buffer(String::new());
+        vm.set_source(": getaxispos 4 ;");
+        vm.evaluate_input();
+        assert_eq!(vm.output_buffer().take().unwrap(), "Redefining getaxispos");
     }
 
-    fn p_mod(&mut self) {
-        let slen = self.s_stack().len;
-        let t = self.s_stack()[slen.wrapping_sub(1)];
-        let n = self.s_stack()[slen.wrapping_sub(2)];
-        if t == 0 {
-            self.abort_with(DIVISION_BY_ZERO);
-        } else {
-            self.s_stack()[slen.wrapping_sub(2)] = n.wrapping_rem(t);
-            self.s_stack().len = slen.wrapping_sub(1);
-        }
+    #[test]
+    fn test_forward() {
+        let vm = &mut VM::new();
+        vm.set_output_buffer(String::new());
+        vm.set_source(
+            "forward odd?  forward limit-value
+            : even? ( n -- flag )   dup 0= if drop true else 1- odd? then ;
+            : later   limit-value 1+ ;
+            3 even?",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNDEFINED_WORD));
+        vm.reset();
+        vm.set_source(
+            "' odd? constant odd-xt
+            : odd? ( n -- flag )   dup 0= if drop false else 1- even? then ;
+            41 constant limit-value
+            4 even?  7 even?  7 odd?  later  7 odd-xt execute",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [-1, 0, -1, 42, -1]);
+        assert_eq!(vm.output_buffer().take().unwrap(), "");
+        let even = vm.find("even?").expect("even?");
+        let odd = vm.find("odd?").expect("odd?");
+        let code = vm.decode(even).expect("code");
+        assert!(code.iter().any(|i| i.xt == odd));
     }
 
-    fn slash_mod(&mut self) {
-        let slen = self.s_stack().len;
-        let t = self.s_stack()[slen.wrapping_sub(1)];
-        let n = self.s_stack()[slen.wrapping_sub(2)];
-        if t == 0 {
-            self.abort_with(DIVISION_BY_ZERO);
-        } else {
-            self.s_stack()[slen.wrapping_sub(2)] = n.wrapping_rem(t);
-            self.s_stack()[slen.wrapping_sub(1)] = n.wrapping_div(t);
+    #[test]
+    fn test_xt_table() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "3 xt-table ops
+            ' + 0 ops xt!  ' * 2 ops xt!
+            : apply ( n1 n2 i -- n3 )   ops dispatch ;
+            3 4 0 apply  3 4 2 apply  5 1 ops dispatch  1 ops xt@ ' noop =  2 ops xt@ ' * =",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [7, 12, 5, -1, -1]);
+        for script in &[
+            "3 ops xt@",
+            "-1 ops xt@",
+            "5 3 4 apply",
+            "' + 3 ops xt!",
+            "1000000 0 ops xt!",
+            "-1 0 ops xt!",
+            "-1 xt-table bad",
+        ] {
+            vm.reset();
+            vm.set_source(script);
+            vm.evaluate_input();
+            assert_eq!(
+                vm.last_error(),
+                Some(INVALID_NUMERIC_ARGUMENT),
+                "{}",
+                script
+            );
         }
+        vm.reset();
+        vm.set_source("0 0 xt@");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_MEMORY_ADDRESS));
+        vm.reset();
+        vm.set_source("' if 0 ops xt!  0 ops dispatch");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INTERPRETING_A_COMPILE_ONLY_WORD));
     }
 
-    fn abs(&mut self) {
-        let t = self.s_stack().pop();
-        self.s_stack().push(t.wrapping_abs());
+    #[test]
+    fn test_checked_xts() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "variable xt-a  marker -work  : a 1 ;  ' a xt-a !  -work
+            : b0 ;  : b 2 ;  xt-a @ execute",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [2]);
+        vm.s_stack().reset();
+        vm.set_source(
+            "checked-xts-on
+            marker -work  : a 1 ;  ' a xt-a !  -work
+            : b0 ;  : b 2 ;  ' b execute  ' b >body drop  xt-a @ >body",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_NUMERIC_ARGUMENT));
+        for script in &[
+            "xt-a @ execute",
+            ": c, compile, ; immediate  : c [ xt-a @ ] c, ;",
+            "-1 execute",
+        ] {
+            vm.reset();
+            vm.set_source(script);
+            vm.evaluate_input();
+            assert_eq!(vm.last_error(), Some(INVALID_XT), "{}", script);
+        }
+        vm.reset();
+        vm.set_source("1 xt-table t  ' b 0 t xt!  0 t dispatch  checked-xts-off  ' b execute");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [2, 2]);
     }
 
-    fn negate(&mut self) {
-        let t = self.s_stack().pop();
-        self.s_stack().push(t.wrapping_neg());
+    #[test]
+    fn test_grow_data_space() {
+        let page = page_size::get() as isize;
+        let vm = &mut VM::with_builder(&VmBuilder::new().max_data_capacity(256 * 1024));
+        let start = vm.data_space().start();
+        let limit = vm.data_space().limit() as isize;
+        vm.set_source("variable v  42 v !  unused allot  unused  4 grow-data-space  unused");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [0, 0, 4 * page]);
+        assert_eq!(vm.data_space().start(), start);
+        assert_eq!(vm.data_space().limit() as isize, limit + 4 * page);
+        vm.s_stack().reset();
+        vm.set_source(": t   v @ ;  here limit <  t  1000 grow-data-space  -1 grow-data-space");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [-1, 42, -59, -59]);
+        let vm = &mut VM::new();
+        vm.set_source("1 grow-data-space");
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().as_slice(), [-59]);
     }
 
-    fn zero_less(&mut self) {
-        let t = self.s_stack().pop();
-        self.s_stack().push(if t < 0 { TRUE } else { FALSE });
+    #[bench]
+    fn bench_find_word_not_exist(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        b.iter(|| vm.find("unknown"));
     }
 
-    fn zero_equals(&mut self) {
-        let t = self.s_stack().pop();
-        self.s_stack().push(if t == 0 { TRUE } else { FALSE });
+    #[bench]
+    fn bench_find_word_at_beginning_of_wordlist(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        b.iter(|| vm.find("noop"));
     }
 
-    fn zero_greater(&mut self) {
-        let t = self.s_stack().pop();
-        self.s_stack().push(if t > 0 { TRUE } else { FALSE });
+    #[bench]
+    fn bench_inner_interpreter_without_nest(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        let ip = vm.data_space().here();
+        let idx = vm.find("noop").expect("noop not exists");
+        vm.compile_word(idx);
+        vm.compile_word(idx);
+        vm.compile_word(idx);
+        vm.compile_word(idx);
+        vm.compile_word(idx);
+        vm.compile_word(idx);
+        vm.compile_word(idx);
+        b.iter(|| {
+            vm.state().instruction_pointer = ip;
+            vm.run();
+        });
     }
 
-    fn zero_not_equals(&mut self) {
-        let t = self.s_stack().pop();
-        self.s_stack().push(if t == 0 { FALSE } else { TRUE });
+    #[test]
+    fn test_drop() {
+        let vm = &mut VM::new();
+        vm.p_drop();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.p_drop();
+        vm.check_stacks();
+        assert!(vm.s_stack().is_empty());
+        assert!(vm.last_error().is_none());
     }
 
-    fn equals(&mut self) {
-        let (n, t) = self.s_stack().pop2();
-        self.s_stack().push(if t == n { TRUE } else { FALSE });
+    #[bench]
+    fn bench_drop(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        b.iter(|| {
+            vm.p_drop();
+            vm.s_stack().push(1);
+        });
     }
 
-    fn less_than(&mut self) {
-        let (n, t) = self.s_stack().pop2();
-        self.s_stack().push(if n < t { TRUE } else { FALSE });
+    #[test]
+    fn test_nip() {
+        let vm = &mut VM::new();
+        vm.nip();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.nip();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.nip();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert!(vm.s_stack().len() == 1);
+        assert!(vm.s_stack().last() == Some(2));
     }
 
-    fn greater_than(&mut self) {
-        let (n, t) = self.s_stack().pop2();
-        self.s_stack().push(if n > t { TRUE } else { FALSE });
+    #[bench]
+    fn bench_nip(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        vm.s_stack().push(1);
+        b.iter(|| {
+            vm.nip();
+            vm.s_stack().push(1);
+        });
     }
 
-    fn not_equals(&mut self) {
-        let (n, t) = self.s_stack().pop2();
-        self.s_stack().push(if n == t { FALSE } else { TRUE });
+    #[test]
+    fn test_swap() {
+        let vm = &mut VM::new();
+        vm.swap();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.swap();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.swap();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 2);
+        assert_eq!(vm.s_stack().pop(), 1);
+        assert_eq!(vm.s_stack().pop(), 2);
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
     }
 
-    /// `within` ( n1 n2 n3 -- flag )  true if n2 <= n1 and n1 < n3.
-    ///
-    /// Note: implmenetation incompatible with Forth 2012 standards
-    /// when n2 > n3.
-    fn within(&mut self) {
-        let (x1, x2, x3) = self.s_stack().pop3();
-        self.s_stack()
-            .push(if x2 <= x1 && x1 < x3 { TRUE } else { FALSE });
+    #[test]
+    fn test_stack_watermarks() {
+        let vm = &mut VM::new();
+        vm.set_source("1 swap");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        assert_eq!(
+            vm.output_buffer().clone().unwrap(),
+            "Data stack reached depth -1 "
+        );
+        vm.reset();
+        vm.output_buffer().as_mut().unwrap().clear();
+        vm.s_stack().set_limit(4);
+        vm.set_source("1 2 3 4 5 2drop");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(STACK_OVERFLOW));
+        assert_eq!(
+            vm.output_buffer().clone().unwrap(),
+            "Data stack reached depth 5 "
+        );
+        assert!(vm.s_stack().is_empty());
+        assert_eq!(vm.s_stack().watermarks(), (0, 0));
     }
 
-    fn invert(&mut self) {
-        let t = self.s_stack().pop();
-        self.s_stack().push(!t);
+    #[test]
+    fn test_stack_iter() {
+        let vm = &mut VM::new();
+        vm.set_source(": x   begin 3 0 do");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(format!("{}", vm.c_stack()), "Begin Do ");
+        vm.s_stack().push2(1, 2);
+        assert_eq!(vm.s_stack().iter().sum::<isize>(), 3);
+        vm.s_stack().pop3();
+        assert!(vm.s_stack().underflow());
+        assert_eq!(vm.s_stack().as_slice(), []);
+        assert_eq!(vm.s_stack().iter().count(), 0);
     }
 
-    fn and(&mut self) {
-        let (n, t) = self.s_stack().pop2();
-        self.s_stack().push(t & n);
+    #[test]
+    fn test_low_water() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "
+            unused limit here - =
+            variable fired  : warn ( u -- )   drop 1 fired +! ;
+            ' warn ' .low-water defer!
+            unused 100 - low-water !  fired @
+            200 allot  fired @  10 allot  fired @
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [-1, 0, 1, 1]);
     }
 
-    fn or(&mut self) {
-        let (n, t) = self.s_stack().pop2();
-        self.s_stack().push(t | n);
+    #[test]
+    fn test_compilation_semantics() {
+        fn compile_answer(vm: &mut VM, _: usize) {
+            vm.compile_integer(42);
+        }
+        let vm = &mut VM::new();
+        vm.add_primitive_with("answer", VM::noop, compile_answer);
+        let dup = vm.find("dup").expect("dup");
+        vm.set_compilation_semantics(dup, compile_answer);
+        vm.set_source(": t   answer 1 dup ;  t  answer");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [42, 1, 42]);
     }
 
-    fn xor(&mut self) {
-        let (n, t) = self.s_stack().pop2();
-        self.s_stack().push(t ^ n);
+    #[test]
+    fn test_lit_q_last() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "
+            : cells ( n -- n*cell )
+              lit?last if uncompile cells postpone literal
+              else drop postpone cells then ; immediate
+            : +fold ( n1 n2 -- n3 )
+              lit?last if uncompile lit?last if uncompile + postpone literal exit then
+                drop postpone literal postpone + exit
+              then drop postpone + ; immediate
+            : a   3 cells ;
+            : b   1 2 +fold ;
+            : c   dup if 1 then 2 +fold ;
+            a b 5 c
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(
+            vm.s_stack().as_slice(),
+            [3 * mem::size_of::<isize>() as isize, 3, 5, 3]
+        );
+        vm.set_source(": d   dup [ uncompile ] ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNSUPPORTED_OPERATION));
     }
 
-    /// Run-time: ( x1 u -- x2 )
-    ///
-    /// Perform a logical left shift of `u` bit-places on `x1`, giving `x2`. Put
-    /// zeroes into the least significant bits vacated by the shift. An
-    /// ambiguous condition exists if `u` is greater than or equal to the number
-    /// of bits in a cell.
-    fn lshift(&mut self) {
-        let (n, t) = self.s_stack().pop2();
-        self.s_stack().push(n.wrapping_shl(t as u32));
+    #[test]
+    fn test_fold_constants() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "
+            7 constant k  2.5e fconstant fk
+            : a   k ;
+            fold-on
+            : b   k 1+ fk ;
+            fold-off
+            : c   k ;
+            ' k >body 70 swap !
+            a b c
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [70, 8, 70]);
+        assert_eq!(vm.f_stack().as_slice(), [2.5]);
+        let b = vm.find("b").unwrap();
+        let code = vm.decode(b).unwrap();
+        let names: Vec<&str> = code.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["lit", "1+", "flit", "exit"]);
     }
 
-    /// Run-time: ( x1 u -- x2 )
-    ///
-    /// Perform a logical right shift of `u` bit-places on `x1`, giving `x2`. Put
-    /// zeroes into the most significant bits vacated by the shift. An
-    /// ambiguous condition exists if `u` is greater than or equal to the number
-    /// of bits in a cell.
-    fn rshift(&mut self) {
-        let (n, t) = self.s_stack().pop2();
-        self.s_stack()
-            .push(((n as usize).wrapping_shr(t as u32)) as isize);
+    #[test]
+    fn test_code_align() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "
+            64 code-align
+            : a   0  10 0 do i + loop  begin 1- dup 0= until  2.5e ;
+            0 code-align
+            ' a
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        let a = vm.s_stack().pop() as usize;
+        let code = vm.decode(a).unwrap();
+        let target = |name: &str| {
+            code.iter()
+                .find(|i| i.name == name)
+                .map(|i| match i.operand {
+                    super::Operand::Branch(d) => d,
+                    _ => 0,
+                })
+                .unwrap()
+        };
+        assert_eq!(target("_loop") % 64, 0);
+        assert_eq!(target("0branch") % 64, 0);
+        vm.set_source("a");
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().as_slice(), [0]);
+        assert_eq!(vm.f_stack().as_slice(), [2.5]);
+        vm.set_source("48 code-align");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_NUMERIC_ARGUMENT));
     }
 
-    /// Interpretation: Interpretation semantics for this word are undefined.
-    ///
-    /// Execution: ( -- ) ( R: nest-sys -- )
-    /// Return control to the calling definition specified by `nest-sys`.
-    /// Before executing `EXIT` within a do-loop, a program shall discard the
-    /// loop-control parameters by executing `UNLOOP`.
-    ///
-    fn exit(&mut self) {
-        let rlen = self.r_stack().len.wrapping_sub(1);
-        self.state().instruction_pointer = self.r_stack()[rlen] as usize;
-        self.r_stack().len = rlen;
+    #[bench]
+    fn bench_swap(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        b.iter(|| vm.swap());
     }
 
-    /// Execution: ( -- )
-    ///
-    /// Set the instruction pointer to zero in order to terminate inner interpreter.
-    fn bye(&mut self) {
-        self.state().instruction_pointer = 0;
+    #[test]
+    fn test_dup() {
+        let vm = &mut VM::new();
+        vm.dup();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.dup();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 2);
+        assert_eq!(vm.s_stack().pop(), 1);
+        assert_eq!(vm.s_stack().pop(), 1);
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
     }
 
-    /// Run-time: ( a-addr -- x )
-    ///
-    /// `x` is the value stored at `a-addr`.
-    fn fetch(&mut self) {
-        let t = self.s_stack().pop() as usize;
-        if self.data_space().start() < t && t + mem::size_of::<isize>() <= self.data_space().limit()
-        {
-            let value = unsafe { self.data_space().get_isize(t as usize) as isize };
-            self.s_stack().push(value);
-        } else {
-            self.abort_with(INVALID_MEMORY_ADDRESS);
-        }
+    #[bench]
+    fn bench_dup(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        b.iter(|| {
+            vm.dup();
+            vm.s_stack().pop();
+        });
     }
 
-    /// Run-time: ( x a-addr -- )
-    ///
-    /// Store `x` at `a-addr`.
-    fn store(&mut self) {
-        let (n, t) = self.s_stack().pop2();
-        let t = t as usize;
-        if self.data_space().start() < t && t + mem::size_of::<isize>() <= self.data_space().limit()
-        {
-            unsafe { self.data_space().put_isize(n as isize, t as usize) };
-        } else {
-            self.abort_with(INVALID_MEMORY_ADDRESS);
-        }
+    #[test]
+    fn test_over() {
+        let vm = &mut VM::new();
+        vm.over();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.over();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.over();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 3);
+        assert_eq!(vm.s_stack().pop(), 1);
+        assert_eq!(vm.s_stack().pop(), 2);
+        assert_eq!(vm.s_stack().pop(), 1);
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
     }
 
-    /// Run-time: ( c-addr -- char )
-    ///
-    /// Fetch the character stored at `c-addr`. When the cell size is greater than
-    /// character size, the unused high-order bits are all zeroes.
-    fn c_fetch(&mut self) {
-        let t = self.s_stack().pop() as usize;
-        if self.data_space().start() <= t && t < self.data_space().limit() {
-            let value = unsafe { self.data_space().get_u8(t as usize) as isize };
-            self.s_stack().push(value);
-        } else {
-            self.abort_with(INVALID_MEMORY_ADDRESS);
-        }
+    #[bench]
+    fn bench_over(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        b.iter(|| {
+            vm.over();
+            vm.s_stack().pop();
+        });
     }
 
-    /// Run-time: ( char c-addr -- )
-    ///
-    /// Store `char` at `c-addr`. When character size is smaller than cell size,
-    /// only the number of low-order bits corresponding to character size are
-    /// transferred.
-    fn c_store(&mut self) {
-        let (n, t) = self.s_stack().pop2();
-        let t = t as usize;
-        if self.data_space().start() < t && t < self.data_space().limit() {
-            unsafe { self.data_space().put_u8(n as u8, t as usize) };
-        } else {
-            self.abort_with(INVALID_MEMORY_ADDRESS);
-        }
+    #[test]
+    fn test_rot() {
+        let vm = &mut VM::new();
+        vm.rot();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.rot();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.rot();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.s_stack().push(3);
+        vm.rot();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 3);
+        assert_eq!(vm.s_stack().pop(), 1);
+        assert_eq!(vm.s_stack().pop(), 3);
+        assert_eq!(vm.s_stack().pop(), 2);
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
     }
 
-    /// Run-time: ( addr1 addr2 u -- )
-    ///
-    /// If u is greater than zero, copy the contents of u consecutive address
-    /// units at addr1 to the u consecutive address units at addr2. After MOVE
-    /// completes, the u consecutive address units at addr2 contain exactly
-    /// what the u consecutive address units at addr1 contained before the
-    /// move.
-    fn p_move(&mut self) {
-        let (addr1, addr2, u) = self.s_stack().pop3();
-        if u > 0 {
-            let u = u as usize;
-            let addr1 = addr1 as usize;
-            let addr2 = addr2 as usize;
-            if self.data_space().start() < addr1
-                && addr1 + u <= self.data_space().limit()
-                && self.data_space().start() < addr2
-                && addr2 + u <= self.data_space().limit()
-            {
-                unsafe {
-                    if addr1 < addr2 {
-                        for p in (addr1..(addr1 + u))
-                            .into_iter()
-                            .zip(addr2..(addr2 + u))
-                            .rev()
-                        {
-                            let value = self.data_space().get_u8(p.0);
-                            self.data_space().put_u8(value, p.1);
-                        }
-                    } else {
-                        for p in (addr1..(addr1 + u)).into_iter().zip(addr2..(addr2 + u)) {
-                            let value = self.data_space().get_u8(p.0);
-                            self.data_space().put_u8(value, p.1);
-                        }
-                    }
-                }
-            } else {
-                self.abort_with(INVALID_MEMORY_ADDRESS);
-            }
-        }
+    #[bench]
+    fn bench_rot(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.s_stack().push(3);
+        b.iter(|| vm.rot());
     }
 
-    /// Run-time: ( "<spaces>name" -- xt )
-    ///
-    /// Skip leading space delimiters. Parse name delimited by a space. Find
-    /// `name` and return `xt`, the execution token for name. An ambiguous
-    /// condition exists if name is not found.
-    fn tick(&mut self) {
-        self.parse_word();
-        let last_token = self.last_token().take().expect("last token");
-        if last_token.is_empty() {
-            self.set_last_token(last_token);
-            self.abort_with(UNEXPECTED_END_OF_FILE);
-        } else {
-            match self.find(&last_token) {
-                Some(found_index) => {
-                    self.s_stack().push(found_index as isize);
-                    self.set_last_token(last_token);
-                }
-                None => {
-                    self.set_last_token(last_token);
-                    self.abort_with(UNDEFINED_WORD);
-                }
-            }
-        }
-    }
+    #[test]
+    fn test_pick() {
+        let vm = &mut VM::new();
+        vm.s_stack().push(0);
+        vm.s_stack().push(0);
+        vm.pick();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [0, 0]);
 
-    /// ( xt -- a-addr )
-    /// a-addr is the data-field address corresponding to xt. An ambiguous
-    /// condition exists if xt is not for a word defined via CREATE.
-    fn to_body(&mut self) {
-        let t = self.s_stack().pop() as usize;
-        if t < self.wordlist().len() {
-            let dfa = self.wordlist()[t].dfa() as isize;
-            self.s_stack().push(dfa);
-        } else {
-            self.abort_with(INVALID_NUMERIC_ARGUMENT);
-        }
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        vm.s_stack().push(0);
+        vm.s_stack().push(1);
+        vm.pick();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [1, 0, 1]);
+
+        let vm = &mut VM::new();
+        vm.s_stack().push(2);
+        vm.s_stack().push(1);
+        vm.s_stack().push(0);
+        vm.s_stack().push(2);
+        vm.pick();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [2, 1, 0, 2]);
     }
 
-    /// ( xt -- a-addr )
-    /// a-addr is the name-field address corresponding to xt.
-    fn to_name(&mut self) {
-        let t = self.s_stack().pop() as usize;
-        if t < self.wordlist().len() {
-            let nfa = self.wordlist()[t].nfa() as isize;
-            self.s_stack().push(nfa);
-        } else {
-            self.abort_with(INVALID_NUMERIC_ARGUMENT);
-        }
+    #[test]
+    fn test_2drop() {
+        let vm = &mut VM::new();
+        vm.two_drop();
+        assert!(vm.s_stack().underflow());
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.two_drop();
+        assert!(vm.s_stack().underflow());
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.two_drop();
+        assert!(!vm.s_stack().underflow());
+        assert!(!vm.s_stack().overflow());
+        assert!(vm.last_error().is_none());
+        assert!(vm.s_stack().is_empty());
     }
 
-    /// Run-time: ( i*x xt -- j*x )
-    ///
-    /// Remove `xt` from the stack and perform the semantics identified by it.
-    /// Other stack effects are due to the word `EXECUTE`d.
-    fn execute(&mut self) {
-        let t = self.s_stack().pop();
-        self.execute_word(t as usize);
+    #[bench]
+    fn bench_2drop(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        b.iter(|| {
+            vm.s_stack().push(1);
+            vm.s_stack().push(2);
+            vm.two_drop();
+        });
     }
 
-    /// Compilation: ( "<spaces>name" -- )
-    /// Run-time: ( -- xt )
-    ///
-    /// Forth 2012 6.1.2510
-    fn bracket_tick(&mut self) {
-        self.parse_word();
-        let last_token = self.last_token().take().expect("last token");
-        if last_token.is_empty() {
-            self.set_last_token(last_token);
-            self.abort_with(UNEXPECTED_END_OF_FILE);
-        } else {
-            match self.find(&last_token) {
-                Some(found_index) => {
-                    self.compile_integer(found_index as isize);
-                    self.set_last_token(last_token);
-                }
-                None => {
-                    self.set_last_token(last_token);
-                    self.abort_with(UNDEFINED_WORD);
-                }
-            }
-        }
+    #[test]
+    fn test_2dup() {
+        let vm = &mut VM::new();
+        vm.two_dup();
+        assert!(vm.s_stack().underflow());
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.two_dup();
+        assert!(vm.s_stack().underflow());
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.two_dup();
+        assert!(!vm.s_stack().underflow());
+        assert!(!vm.s_stack().overflow());
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 4);
+        assert_eq!(vm.s_stack().pop(), 2);
+        assert_eq!(vm.s_stack().pop(), 1);
+        assert_eq!(vm.s_stack().pop(), 2);
+        assert_eq!(vm.s_stack().pop(), 1);
     }
 
-    /// Execution: ( xt -- )
-    ///
-    /// Forth 2012 6.2.0945
-    /// Append the execution semantics of the definition represented by xt to the execution semantics of the current definition.
-    fn compile_comma(&mut self) {
-        let v = self.s_stack().pop();
-        self.data_space().compile_isize(v as isize);
+    #[bench]
+    fn bench_2dup(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        b.iter(|| {
+            vm.two_dup();
+            vm.two_drop();
+        });
     }
 
-    /// Run-time: ( -- addr )
-    ///
-    /// `addr` is the data-space pointer.
-    fn here(&mut self) {
-        let here = self.data_space().here() as isize;
-        self.s_stack().push(here);
+    #[test]
+    fn test_2swap() {
+        let vm = &mut VM::new();
+        vm.two_swap();
+        assert!(vm.s_stack().underflow());
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.two_swap();
+        assert!(vm.s_stack().underflow());
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.two_swap();
+        assert!(vm.s_stack().underflow());
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.s_stack().push(3);
+        vm.two_swap();
+        assert!(vm.s_stack().underflow());
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.s_stack().push(3);
+        vm.s_stack().push(4);
+        vm.two_swap();
+        assert!(!vm.s_stack().underflow());
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 4);
+        assert_eq!(vm.s_stack().pop(), 2);
+        assert_eq!(vm.s_stack().pop(), 1);
+        assert_eq!(vm.s_stack().pop(), 4);
+        assert_eq!(vm.s_stack().pop(), 3);
     }
 
-    /// Run-time: ( n -- )
-    ///
-    /// If `n` is greater than zero, reserve n address units of data space. If `n`
-    /// is less than zero, release `|n|` address units of data space. If `n` is
-    /// zero, leave the data-space pointer unchanged.
-    fn allot(&mut self) {
-        let v = self.s_stack().pop();
-        self.data_space().allot(v);
+    #[bench]
+    fn bench_2swap(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.s_stack().push(3);
+        vm.s_stack().push(4);
+        b.iter(|| vm.two_swap());
     }
 
-    /// Run-time: ( addr -- a-addr )
-    ///
-    /// Return `a-addr`, the first aligned address greater than or equal to `addr`.
-    fn aligned(&mut self) {
-        let pos = self.s_stack().pop();
-        let pos = DataSpace::aligned(pos as usize);
-        self.s_stack().push(pos as isize);
+    #[test]
+    fn test_2over() {
+        let vm = &mut VM::new();
+        vm.two_over();
+        assert!(vm.s_stack().underflow());
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.two_over();
+        assert!(vm.s_stack().underflow());
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.two_over();
+        assert!(vm.s_stack().underflow());
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.s_stack().push(3);
+        vm.two_over();
+        assert!(vm.s_stack().underflow());
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.s_stack().push(3);
+        vm.s_stack().push(4);
+        vm.two_over();
+        assert!(!vm.s_stack().underflow());
+        assert!(!vm.s_stack().overflow());
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 6);
+        assert_eq!(vm.s_stack().as_slice(), [1, 2, 3, 4, 1, 2]);
     }
 
-    /// Run-time: ( -- )
-    ///
-    /// If the data-space pointer is not aligned, reserve enough space to align it.
-    fn align(&mut self) {
-        self.data_space().align();
+    #[bench]
+    fn bench_2over(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.s_stack().push(3);
+        vm.s_stack().push(4);
+        b.iter(|| {
+            vm.two_over();
+            vm.two_drop();
+        });
     }
 
-    /// Run-time: ( x -- )
-    ///
-    /// Reserve one cell of data space and store `x` in the cell. If the
-    /// data-space pointer is aligned when `,` begins execution, it will remain
-    /// aligned when `,` finishes execution. An ambiguous condition exists if the
-    /// data-space pointer is not aligned prior to execution of `,`.
-    fn comma(&mut self) {
-        let v = self.s_stack().pop();
-        self.data_space().compile_isize(v as isize);
+    #[test]
+    fn test_depth() {
+        let vm = &mut VM::new();
+        vm.depth();
+        vm.depth();
+        vm.depth();
+        assert_eq!(vm.s_stack().as_slice(), [0, 1, 2]);
     }
 
-    fn p_to_r(&mut self) {
-        let slen = self.s_stack().len;
-        let rlen = self.r_stack().len.wrapping_add(1);
-        self.r_stack().len = rlen;
-        self.r_stack()[rlen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(1)];
-        self.s_stack().len = slen.wrapping_sub(1);
+    #[test]
+    fn test_one_plus() {
+        let vm = &mut VM::new();
+        vm.one_plus();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.one_plus();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 2);
     }
 
-    fn r_from(&mut self) {
-        let slen = self.s_stack().len.wrapping_add(1);
-        let rlen = self.r_stack().len;
-        self.s_stack().len = slen;
-        self.s_stack()[slen.wrapping_sub(1)] = self.r_stack()[rlen.wrapping_sub(1)];
-        self.r_stack().len = rlen.wrapping_sub(1);
+    #[bench]
+    fn bench_one_plus(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(0);
+        b.iter(|| {
+            vm.one_plus();
+        });
     }
 
-    fn r_fetch(&mut self) {
-        let slen = self.s_stack().len.wrapping_add(1);
-        let rlen = self.r_stack().len;
-        self.s_stack().len = slen;
-        self.s_stack()[slen.wrapping_sub(1)] = self.r_stack()[rlen.wrapping_sub(1)];
+    #[test]
+    fn test_one_minus() {
+        let vm = &mut VM::new();
+        vm.one_minus();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(2);
+        vm.one_minus();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 1);
     }
 
-    fn two_to_r(&mut self) {
-        let slen = self.s_stack().len;
-        let rlen = self.r_stack().len.wrapping_add(2);
-        self.r_stack().len = rlen;
-        self.r_stack()[rlen.wrapping_sub(2)] = self.s_stack()[slen.wrapping_sub(2)];
-        self.r_stack()[rlen.wrapping_sub(1)] = self.s_stack()[slen.wrapping_sub(1)];
-        self.s_stack().len = slen.wrapping_sub(2);
+    #[bench]
+    fn bench_one_minus(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(0);
+        b.iter(|| {
+            vm.one_minus();
+        });
     }
 
-    fn two_r_from(&mut self) {
-        let slen = self.s_stack().len.wrapping_add(2);
-        let rlen = self.r_stack().len;
-        self.s_stack().len = slen;
-        self.s_stack()[slen.wrapping_sub(2)] = self.r_stack()[rlen.wrapping_sub(2)];
-        self.s_stack()[slen.wrapping_sub(1)] = self.r_stack()[rlen.wrapping_sub(1)];
-        self.r_stack().len = rlen.wrapping_sub(2);
+    #[test]
+    fn test_minus() {
+        let vm = &mut VM::new();
+        vm.minus();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(5);
+        vm.minus();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(5);
+        vm.s_stack().push(7);
+        vm.minus();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -2);
     }
 
-    fn two_r_fetch(&mut self) {
-        let slen = self.s_stack().len.wrapping_add(2);
-        let rlen = self.r_stack().len;
-        self.s_stack().len = slen;
-        self.s_stack()[slen.wrapping_sub(2)] = self.r_stack()[rlen.wrapping_sub(2)];
-        self.s_stack()[slen.wrapping_sub(1)] = self.r_stack()[rlen.wrapping_sub(1)];
+    #[bench]
+    fn bench_minus(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(0);
+        b.iter(|| {
+            vm.dup();
+            vm.minus();
+        });
     }
 
-    // ----------------
-    // Error handlling
-    // ----------------
-
-    fn check_stacks(&mut self) {
-        if self.s_stack().overflow() {
-            self.abort_with(STACK_OVERFLOW);
-        } else if self.s_stack().underflow() {
-            self.abort_with(STACK_UNDERFLOW);
-        } else if self.r_stack().overflow() {
-            self.abort_with(RETURN_STACK_OVERFLOW);
-        } else if self.r_stack().underflow() {
-            self.abort_with(RETURN_STACK_UNDERFLOW);
-        } else if self.c_stack().overflow() {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-        } else if self.c_stack().underflow() {
-            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
-        } else if self.f_stack().overflow() {
-            self.abort_with(FLOATING_POINT_STACK_OVERFLOW);
-        } else if self.f_stack().underflow() {
-            self.abort_with(FLOATING_POINT_STACK_UNDERFLOW);
-        }
+    #[test]
+    fn test_plus() {
+        let vm = &mut VM::new();
+        vm.plus();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(5);
+        vm.plus();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(5);
+        vm.s_stack().push(7);
+        vm.plus();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 12);
     }
 
-    fn handler_store(&mut self) {
-        let t = self.s_stack().pop();
-        self.set_handler(t as usize);
+    #[bench]
+    fn bench_plus(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        b.iter(|| {
+            vm.dup();
+            vm.plus();
+        });
     }
 
-    /// Error code `error ( -- n )`
-    fn error(&mut self) {
-        match self.last_error() {
-            Some(e) => {
-                self.s_stack().push(e.into());
-            }
-            None => {
-                self.s_stack().push(0);
-            }
-        }
+    #[test]
+    fn test_star() {
+        let vm = &mut VM::new();
+        vm.star();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(5);
+        vm.star();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(5);
+        vm.s_stack().push(7);
+        vm.star();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 35);
     }
 
-    /// Clear error. `0error ( -- )`
-    fn clear_error(&mut self) {
-        self.set_error(None);
+    #[bench]
+    fn bench_star(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        b.iter(|| {
+            vm.dup();
+            vm.star();
+        });
     }
 
-    /// Print error description. `.error ( -- )`
-    fn dot_error(&mut self) {
-        match self.last_error() {
-            Some(e) => match self.output_buffer().as_mut() {
-                Some(buf) => {
-                    write!(buf, "{}", exception::description(e)).expect("write");
-                }
-                None => {}
-            },
-            None => {}
-        }
+    #[test]
+    fn test_slash() {
+        let vm = &mut VM::new();
+        vm.slash();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(30);
+        vm.slash();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(30);
+        vm.s_stack().push(7);
+        vm.slash();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 4);
     }
 
-    /// Clear data, floating point, and control stacks.
-    /// Called by VM's client upon ABORT.
-    fn clear_stacks(&mut self) {
-        self.s_stack().reset();
-        self.f_stack().reset();
-        self.c_stack().reset();
+    #[bench]
+    fn bench_slash(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        b.iter(|| {
+            vm.dup();
+            vm.slash();
+        });
     }
 
-    /// ( -- source-id )
-    ///
-    /// Current source id.
-    fn p_source_id(&mut self) {
-        let source_id = self.source_id();
-        self.s_stack().push(source_id);
+    #[test]
+    fn test_mod() {
+        let vm = &mut VM::new();
+        vm.p_mod();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(30);
+        vm.p_mod();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(30);
+        vm.s_stack().push(7);
+        vm.p_mod();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 2);
     }
 
-    /// ( source-id -- )
-    ///
-    /// Set source id.
-    fn p_set_source_id(&mut self) {
-        let id = self.s_stack().pop();
-        self.set_source_id(id);
+    #[bench]
+    fn bench_mod(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        b.iter(|| {
+            vm.p_mod();
+            vm.s_stack().push(2);
+        });
     }
 
-    /// Set source id.
-    fn set_source_id(&mut self, id: isize) {
-        if id > 0 {
-            // File source
-            if id - 1 < self.sources().len() as isize
-                && self.sources()[id as usize - 1].is_some()
-                && self.lines()[id as usize - 1].is_some()
-            {
-                self.state().source_id = id;
-            } else {
-                self.abort_with(INVALID_NUMERIC_ARGUMENT);
-            }
-        } else if id == 0 {
-            self.state().source_id = id;
-        } else {
-            self.abort_with(INVALID_NUMERIC_ARGUMENT);
-        }
+    #[test]
+    fn test_slash_mod() {
+        let vm = &mut VM::new();
+        vm.slash_mod();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(30);
+        vm.slash_mod();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(30);
+        vm.s_stack().push(7);
+        vm.slash_mod();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 2);
+        assert_eq!(vm.s_stack().pop(), 4);
+        assert_eq!(vm.s_stack().pop(), 2);
     }
 
-    /// ( -- source-idx )
-    ///
-    /// Current source index.
-    fn p_source_idx(&mut self) {
-        let source_idx = self.state().source_index as isize;
-        self.s_stack().push(source_idx);
+    #[bench]
+    fn bench_slash_mod(b: &mut Bencher) {
+        let vm = &mut VM::new();
+        vm.s_stack().push2(1, 2);
+        b.iter(|| {
+            vm.slash_mod();
+            vm.p_drop();
+            vm.s_stack().push(2);
+        });
     }
 
-    /// ( source-idx -- )
-    ///
-    /// Set source index.
-    fn p_set_source_idx(&mut self) {
-        let idx = self.s_stack().pop() as usize;
-        self.state().source_index = idx;
+    #[test]
+    fn test_floored() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "
+            -7 2 /mod  7 -2 mod  -7 s>d 2 fm/mod  -7 s>d 2 sm/rem
+            0 -1 2 fm/mod
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(
+            vm.s_stack().as_slice(),
+            [-1, -3, 1, 1, -4, -1, -3, 0, isize::min_value()]
+        );
+        vm.s_stack().reset();
+        vm.wordlist_mut().set_floored(true);
+        vm.set_source("-7 2 /mod  7 -2 mod  -7 2 /  floored");
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().as_slice(), [1, -4, -1, -4, -1]);
+        vm.set_source("0 1 2 sm/rem");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(RESULT_OUT_OF_RANGE));
     }
 
-    /// Reset VM, do not clear data stack, floating point and control stack.
-    /// Called by VM's client upon Quit.
-    fn reset(&mut self) {
-        self.r_stack().reset();
-        self.set_source_id(0);
-        if let Some(ref mut buf) = *self.input_buffer() {
-            buf.clear()
-        }
-        self.state().aborted_word_pointer = 0;
-        self.state().source_index = 0;
-        self.left_bracket();
-        self.set_error(None);
+    #[test]
+    fn test_abs() {
+        let vm = &mut VM::new();
+        vm.s_stack().push(-30);
+        vm.abs();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 30);
     }
 
-    /// Abort the inner loop with an exception, reset VM and clears stacks.
-    fn abort_with(&mut self, e: Exception) {
-        self.clear_stacks();
-        self.set_error(Some(e));
-        let h = self.handler();
-        self.state().aborted_word_pointer = self.state().word_pointer;
-        self.execute_word(h);
+    #[test]
+    fn test_negate() {
+        let vm = &mut VM::new();
+        vm.negate();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(30);
+        vm.negate();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -30);
     }
 
-    /// Abort the inner loop with an exception, reset VM and clears stacks.
-    fn abort(&mut self) {
-        self.abort_with(ABORT);
+    #[test]
+    fn test_zero_less() {
+        let vm = &mut VM::new();
+        vm.zero_less();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(-1);
+        vm.zero_less();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -1);
+        vm.s_stack().push(0);
+        vm.zero_less();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
     }
 
-    /// Pause the current task and resume the next task which is awake.
-    fn pause(&mut self) {
-        let mut i = self.current_task();
-        loop {
-            i = (i + 1) % NUM_TASKS;
-            if self.awake(i) {
-                self.set_current_task(i);
-                break;
-            }
-        }
+    #[test]
+    fn test_zero_equals() {
+        let vm = &mut VM::new();
+        vm.zero_equals();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(0);
+        vm.zero_equals();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -1);
+        vm.s_stack().push(-1);
+        vm.zero_equals();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
+        vm.s_stack().push(1);
+        vm.zero_equals();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
     }
 
-    /// Current task ID
-    fn me(&mut self) {
-        let me = self.current_task() + 1;
-        self.s_stack().push(me as isize);
+    #[test]
+    fn test_zero_greater() {
+        let vm = &mut VM::new();
+        vm.zero_greater();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.zero_greater();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -1);
+        vm.s_stack().push(0);
+        vm.zero_greater();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
     }
 
-    /// Suspend task `i`. `suspend ( i -- )`
-    fn suspend(&mut self) {
-        let i = (self.s_stack().pop() - 1) as usize;
-        if i < NUM_TASKS {
-            self.set_awake(i as usize, false);
-        } else {
-            self.abort_with(INVALID_NUMERIC_ARGUMENT);
-        }
+    #[test]
+    fn test_zero_not_equals() {
+        let vm = &mut VM::new();
+        vm.zero_not_equals();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(0);
+        vm.zero_not_equals();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
+        vm.s_stack().push(-1);
+        vm.zero_not_equals();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -1);
+        vm.s_stack().push(1);
+        vm.zero_not_equals();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -1);
     }
 
-    /// Resume task `i`. `resume ( i -- )`
-    fn resume(&mut self) {
-        let i = (self.s_stack().pop() - 1) as usize;
-        if i < NUM_TASKS {
-            self.set_awake(i as usize, true);
-        } else {
-            self.abort_with(INVALID_NUMERIC_ARGUMENT);
-        }
+    #[test]
+    fn test_less_than() {
+        let vm = &mut VM::new();
+        vm.less_than();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(-1);
+        vm.less_than();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(-1);
+        vm.s_stack().push(0);
+        vm.less_than();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -1);
+        vm.s_stack().push(0);
+        vm.s_stack().push(0);
+        vm.less_than();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
+    }
+
+    #[test]
+    fn test_equals() {
+        let vm = &mut VM::new();
+        vm.equals();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(0);
+        vm.equals();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(0);
+        vm.s_stack().push(0);
+        vm.equals();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -1);
+        vm.s_stack().push(-1);
+        vm.s_stack().push(0);
+        vm.equals();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
+        vm.s_stack().push(1);
+        vm.s_stack().push(0);
+        vm.equals();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
     }
-}
-
-#[cfg(test)]
-mod tests {
-    extern crate test;
-    use self::test::Bencher;
-    use super::{Core, Memory};
-    use exception::{
-        ABORT, CONTROL_STRUCTURE_MISMATCH, INTERPRETING_A_COMPILE_ONLY_WORD,
-        INVALID_MEMORY_ADDRESS, RETURN_STACK_UNDERFLOW, STACK_UNDERFLOW, UNDEFINED_WORD,
-        UNEXPECTED_END_OF_FILE, UNSUPPORTED_OPERATION,
-    };
-    use loader::HasLoader;
-    use mock_vm::VM;
-    use std::mem;
 
-    #[bench]
-    fn bench_noop(b: &mut Bencher) {
+    #[test]
+    fn test_greater_than() {
         let vm = &mut VM::new();
-        b.iter(|| vm.noop());
+        vm.greater_than();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.greater_than();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(0);
+        vm.greater_than();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -1);
+        vm.s_stack().push(0);
+        vm.s_stack().push(0);
+        vm.greater_than();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
     }
 
     #[test]
-    fn test_find() {
+    fn test_not_equals() {
         let vm = &mut VM::new();
-        assert!(vm.find("").is_none());
-        assert!(vm.find("word-not-exist").is_none());
-        vm.find("noop").expect("noop not found");
+        vm.not_equals();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(0);
+        vm.not_equals();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(0);
+        vm.s_stack().push(0);
+        vm.not_equals();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
+        vm.s_stack().push(-1);
+        vm.s_stack().push(0);
+        vm.not_equals();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -1);
+        vm.s_stack().push(1);
+        vm.s_stack().push(0);
+        vm.not_equals();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -1);
     }
 
-    #[bench]
-    fn bench_find_word_not_exist(b: &mut Bencher) {
+    #[test]
+    fn test_within() {
         let vm = &mut VM::new();
-        b.iter(|| vm.find("unknown"));
+        vm.within();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.within();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(1);
+        vm.within();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(1);
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.within();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -1);
+        vm.s_stack().push(1);
+        vm.s_stack().push(0);
+        vm.s_stack().push(1);
+        vm.within();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
+        vm.s_stack().push(0);
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.within();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
+        vm.s_stack().push(3);
+        vm.s_stack().push(1);
+        vm.s_stack().push(2);
+        vm.within();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
     }
 
-    #[bench]
-    fn bench_find_word_at_beginning_of_wordlist(b: &mut Bencher) {
+    #[test]
+    fn test_invert() {
         let vm = &mut VM::new();
-        b.iter(|| vm.find("noop"));
+        vm.invert();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(707);
+        vm.invert();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -708);
     }
 
-    #[bench]
-    fn bench_inner_interpreter_without_nest(b: &mut Bencher) {
+    #[test]
+    fn test_and() {
         let vm = &mut VM::new();
-        let ip = vm.data_space().here();
-        let idx = vm.find("noop").expect("noop not exists");
-        vm.compile_word(idx);
-        vm.compile_word(idx);
-        vm.compile_word(idx);
-        vm.compile_word(idx);
-        vm.compile_word(idx);
-        vm.compile_word(idx);
-        vm.compile_word(idx);
-        b.iter(|| {
-            vm.state().instruction_pointer = ip;
-            vm.run();
-        });
+        vm.s_stack().push(707);
+        vm.s_stack().push(007);
+        vm.and();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert!(!vm.s_stack().overflow());
+        assert!(!vm.s_stack().underflow());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 3);
     }
 
     #[test]
-    fn test_drop() {
+    fn test_or() {
         let vm = &mut VM::new();
-        vm.p_drop();
+        vm.or();
         vm.check_stacks();
         assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.p_drop();
+        vm.s_stack().push(707);
+        vm.or();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(707);
+        vm.s_stack().push(07);
+        vm.or();
         vm.check_stacks();
-        assert!(vm.s_stack().is_empty());
         assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 711);
     }
 
-    #[bench]
-    fn bench_drop(b: &mut Bencher) {
+    #[test]
+    fn test_xor() {
         let vm = &mut VM::new();
-        vm.s_stack().push(1);
-        b.iter(|| {
-            vm.p_drop();
-            vm.s_stack().push(1);
-        });
+        vm.xor();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(707);
+        vm.xor();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        vm.s_stack().push(707);
+        vm.s_stack().push(07);
+        vm.xor();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 708);
     }
 
     #[test]
-    fn test_nip() {
+    fn test_lshift() {
         let vm = &mut VM::new();
-        vm.nip();
+        vm.lshift();
         vm.check_stacks();
         assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
         vm.reset();
         vm.clear_stacks();
         vm.s_stack().push(1);
-        vm.nip();
+        vm.lshift();
         vm.check_stacks();
         assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
         vm.reset();
         vm.clear_stacks();
         vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.nip();
+        vm.s_stack().push(1);
+        vm.lshift();
         vm.check_stacks();
         assert!(vm.last_error().is_none());
-        assert!(vm.s_stack().len() == 1);
-        assert!(vm.s_stack().last() == Some(2));
-    }
-
-    #[bench]
-    fn bench_nip(b: &mut Bencher) {
-        let vm = &mut VM::new();
-        vm.s_stack().push(1);
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 2);
         vm.s_stack().push(1);
-        b.iter(|| {
-            vm.nip();
-            vm.s_stack().push(1);
-        });
+        vm.s_stack().push(2);
+        vm.lshift();
+        vm.check_stacks();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 4);
     }
 
     #[test]
-    fn test_swap() {
+    fn test_rshift() {
         let vm = &mut VM::new();
-        vm.swap();
+        vm.rshift();
         vm.check_stacks();
-        // check_stacks() cannot detect this kind of underflow.
-        // assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.swap();
+        vm.s_stack().push(8);
+        vm.rshift();
         vm.check_stacks();
         assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
         vm.reset();
         vm.clear_stacks();
+        vm.s_stack().push(8);
         vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.swap();
+        vm.rshift();
         vm.check_stacks();
         assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 2);
-        assert_eq!(vm.s_stack().pop(), 1);
-        assert_eq!(vm.s_stack().pop(), 2);
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 4);
+        vm.s_stack().push(-1);
+        vm.s_stack().push(1);
+        vm.rshift();
         vm.check_stacks();
         assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert!(vm.s_stack().pop() > 0);
     }
 
-    #[bench]
-    fn bench_swap(b: &mut Bencher) {
+    #[test]
+    fn test_parse_word() {
         let vm = &mut VM::new();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        b.iter(|| vm.swap());
+        vm.set_source("hello world\t\r\n\"");
+        vm.parse_word();
+        assert_eq!(vm.last_token().clone().unwrap(), "hello");
+        assert_eq!(vm.state().source_index, 5);
+        vm.parse_word();
+        assert_eq!(vm.last_token().clone().unwrap(), "world");
+        assert_eq!(vm.state().source_index, 11);
+        vm.parse_word();
+        assert_eq!(vm.last_token().clone().unwrap(), "\"");
     }
 
     #[test]
-    fn test_dup() {
+    fn test_evaluate_input() {
         let vm = &mut VM::new();
-        vm.dup();
-        vm.check_stacks();
-        // check_stacks can not detect this underflow();
-        //        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        // >r
+        vm.set_source(">r");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INTERPRETING_A_COMPILE_ONLY_WORD));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.dup();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 2);
-        assert_eq!(vm.s_stack().pop(), 1);
-        assert_eq!(vm.s_stack().pop(), 1);
-        vm.check_stacks();
+        // drop
+        vm.set_source("drop");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        // error in colon definition: 4drop
+        vm.set_source(": 4drop drop drop drop drop ; 4drop");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.clear_stacks();
+        // undefined word
+        vm.set_source("xdrop");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNDEFINED_WORD));
+        vm.reset();
+        vm.clear_stacks();
+        // false true dup 1+ 2 -3
+        vm.set_source("false true dup 1+ 2 -3");
+        vm.evaluate_input();
         assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 5);
+        assert_eq!(vm.s_stack().pop(), -3);
+        assert_eq!(vm.s_stack().pop(), 2);
+        assert_eq!(vm.s_stack().pop(), 0);
+        assert_eq!(vm.s_stack().pop(), -1);
+        assert_eq!(vm.s_stack().pop(), 0);
     }
 
-    #[bench]
-    fn bench_dup(b: &mut Bencher) {
-        let vm = &mut VM::new();
-        vm.s_stack().push(1);
-        b.iter(|| {
-            vm.dup();
-            vm.s_stack().pop();
-        });
+    /*
+        #[bench]
+        fn bench_compile_words_at_beginning_of_wordlist(b: &mut Bencher) {
+            let vm = &mut VM::new();
+            b.iter(|| {
+                vm.set_source("marker empty : main noop noop noop noop noop noop noop noop ; empty");
+                vm.evaluate_input();
+                vm.s_stack().reset();
+            });
+        }
+
+        #[bench]
+        fn bench_compile_words_at_end_of_wordlist(b: &mut Bencher) {
+            let vm = &mut VM::new();
+            b.iter(|| {
+                       vm.set_source("marker empty : main bye bye bye bye bye bye bye bye ; empty");
+                       vm.evaluate_input();
+                       vm.s_stack().reset();
+                   });
+        }
+
+    */
+
+    #[test]
+    fn test_push_source() {
+        let mut vm = VM::new();
+        vm.set_source(": x");
+        vm.push_source(" ");
+        vm.push_source("1");
+        vm.push_source(" ");
+        vm.push_source(";");
+        assert_eq!(vm.input_buffer(), &Some(": x 1 ;".to_owned()));
     }
 
     #[test]
-    fn test_over() {
+    fn test_colon_and_semi_colon() {
         let vm = &mut VM::new();
-        vm.over();
-        vm.check_stacks();
-        // check_stacks() cannot detect stack underflow of over().
-        // assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        // :
+        vm.set_source(":");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNEXPECTED_END_OF_FILE));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.check_stacks();
-        vm.over();
-        // check_stacks() cannot detect stack underflow of over().
+        // : 2+3 2 3 + ; 2+3
+        vm.set_source(": 2+3 2 3 + ; 2+3");
+        vm.evaluate_input();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 5);
+    }
+
+    #[test]
+    fn test_constant() {
+        let vm = &mut VM::new();
+        // constant x
+        vm.set_source("constant");
+        vm.evaluate_input();
+        // Note: cannot detect underflow.
         // assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.over();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 3);
-        assert_eq!(vm.s_stack().pop(), 1);
-        assert_eq!(vm.s_stack().pop(), 2);
-        assert_eq!(vm.s_stack().pop(), 1);
-        vm.check_stacks();
+        // 5 constant x x x
+        vm.set_source("5 constant x x x");
+        vm.evaluate_input();
         assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 2);
+        assert_eq!(vm.s_stack().pop(), 5);
+        assert_eq!(vm.s_stack().pop(), 5);
     }
 
-    #[bench]
-    fn bench_over(b: &mut Bencher) {
+    #[test]
+    fn test_constant_in_colon() {
         let vm = &mut VM::new();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        b.iter(|| {
-            vm.over();
-            vm.s_stack().pop();
-        });
+        // 77 constant x
+        // : 2x  x 2 * ;  2x
+        vm.set_source("77 constant x  : 2x x 2 * ;  2x");
+        vm.evaluate_input();
+        vm.run();
+        assert_eq!(vm.s_stack().pop(), 154);
+        assert_eq!(vm.s_stack().len, 0);
     }
 
     #[test]
-    fn test_rot() {
+    fn test_create_and_store_fetch() {
         let vm = &mut VM::new();
-        vm.rot();
-        vm.check_stacks();
-        // check_stacks() cannot detect this kind of stack underflow of over().
-        // assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        // @
+        vm.set_source("@");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_MEMORY_ADDRESS));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.rot();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        // !
+        vm.set_source("!");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_MEMORY_ADDRESS));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.rot();
-        vm.check_stacks();
+        // create x  1 cells allot  x !
+        vm.set_source("create x  1 cells allot  x !");
+        vm.evaluate_input();
         assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.s_stack().push(3);
-        vm.rot();
-        vm.check_stacks();
+        // create x  1 cells allot  x @  3 x !  x @
+        vm.set_source("create x  1 cells allot  x @  3 x !  x @");
+        vm.evaluate_input();
         assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 3);
-        assert_eq!(vm.s_stack().pop(), 1);
+        assert_eq!(vm.s_stack().len(), 2);
         assert_eq!(vm.s_stack().pop(), 3);
-        assert_eq!(vm.s_stack().pop(), 2);
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().pop(), 0);
     }
 
-    #[bench]
-    fn bench_rot(b: &mut Bencher) {
+    #[test]
+    fn test_create_and_fetch_in_colon() {
         let vm = &mut VM::new();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.s_stack().push(3);
-        b.iter(|| vm.rot());
+        // create x  1 cells allot
+        // 7 x !
+        // : x@ x @ ; x@
+        vm.set_source("create x  1 cells allot  7 x !  : x@ x @ ;  x@");
+        vm.evaluate_input();
+        vm.run();
+        assert_eq!(vm.s_stack().pop(), 7);
+        assert_eq!(vm.s_stack().len, 0);
     }
 
     #[test]
-    fn test_pick() {
+    fn test_create_in_colon() {
         let vm = &mut VM::new();
-        vm.s_stack().push(0);
-        vm.s_stack().push(0);
-        vm.pick();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), None);
-        assert_eq!(vm.s_stack().as_slice(), [0, 0]);
+        // create x 7 ,
+        // : x@ x @ ; x@
+        vm.set_source("create x 7 ,  : x@ x @ ;  x@");
+        vm.evaluate_input();
+        vm.run();
+        assert_eq!(vm.s_stack().pop(), 7);
+        assert_eq!(vm.s_stack().len, 0);
+    }
 
+    #[test]
+    fn test_char_plus() {
         let vm = &mut VM::new();
-        vm.s_stack().push(1);
-        vm.s_stack().push(0);
-        vm.s_stack().push(1);
-        vm.pick();
+        vm.char_plus();
         vm.check_stacks();
-        assert_eq!(vm.last_error(), None);
-        assert_eq!(vm.s_stack().as_slice(), [1, 0, 1]);
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        // 2 char+
+        vm.set_source("2 char+");
+        vm.evaluate_input();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().as_slice(), [3]);
+    }
 
+    #[test]
+    fn test_cell_plus_and_cells() {
         let vm = &mut VM::new();
-        vm.s_stack().push(2);
-        vm.s_stack().push(1);
-        vm.s_stack().push(0);
-        vm.s_stack().push(2);
-        vm.pick();
+        vm.cell_plus();
         vm.check_stacks();
-        assert_eq!(vm.last_error(), None);
-        assert_eq!(vm.s_stack().as_slice(), [2, 1, 0, 2]);
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.cells();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        vm.set_source("2 cell+  9 cells");
+        vm.evaluate_input();
+        assert!(vm.last_error().is_none());
+        assert_eq!(
+            vm.s_stack().as_slice(),
+            [
+                2 + mem::size_of::<isize>() as isize,
+                9 * mem::size_of::<isize>() as isize
+            ]
+        );
     }
 
     #[test]
-    fn test_2drop() {
+    fn test_tick() {
         let vm = &mut VM::new();
-        vm.two_drop();
-        assert!(vm.s_stack().underflow());
+        // '
+        vm.tick();
+        assert_eq!(vm.last_error(), Some(UNEXPECTED_END_OF_FILE));
         vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.two_drop();
-        assert!(vm.s_stack().underflow());
+        // ' xdrop
+        vm.set_source("' xdrop");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNDEFINED_WORD));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.two_drop();
-        assert!(!vm.s_stack().underflow());
-        assert!(!vm.s_stack().overflow());
+        // ' drop
+        vm.set_source("' drop");
+        vm.evaluate_input();
         assert!(vm.last_error().is_none());
-        assert!(vm.s_stack().is_empty());
-    }
-
-    #[bench]
-    fn bench_2drop(b: &mut Bencher) {
-        let vm = &mut VM::new();
-        b.iter(|| {
-            vm.s_stack().push(1);
-            vm.s_stack().push(2);
-            vm.two_drop();
-        });
+        assert_eq!(vm.s_stack().len(), 1);
     }
 
     #[test]
-    fn test_2dup() {
+    fn test_execute() {
         let vm = &mut VM::new();
-        vm.two_dup();
-        assert!(!vm.s_stack().underflow());
+        // execute
+        vm.execute();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(INVALID_XT));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.two_dup();
-        assert!(!vm.s_stack().underflow());
+        // ' drop execute
+        vm.set_source("' drop");
+        vm.evaluate_input();
+        vm.execute();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.two_dup();
-        assert!(!vm.s_stack().underflow());
-        assert!(!vm.s_stack().overflow());
+        // 1 2  ' swap execute
+        vm.set_source("1 2  ' swap execute");
+        vm.evaluate_input();
         assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 4);
-        assert_eq!(vm.s_stack().pop(), 2);
+        assert_eq!(vm.s_stack().len(), 2);
         assert_eq!(vm.s_stack().pop(), 1);
         assert_eq!(vm.s_stack().pop(), 2);
-        assert_eq!(vm.s_stack().pop(), 1);
     }
 
-    #[bench]
-    fn bench_2dup(b: &mut Bencher) {
+    #[test]
+    fn test_here_allot() {
         let vm = &mut VM::new();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        b.iter(|| {
-            vm.two_dup();
-            vm.two_drop();
-        });
+        // allot
+        vm.allot();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.reset();
+        // here 2 cells allot here -
+        vm.set_source("here 2 cells allot here -");
+        vm.evaluate_input();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(
+            vm.s_stack().pop(),
+            -((mem::size_of::<isize>() * 2) as isize)
+        );
     }
 
+    #[cfg(not(feature = "check-alignment"))]
     #[test]
-    fn test_2swap() {
+    fn test_unaligned_store_fetch() {
         let vm = &mut VM::new();
-        vm.two_swap();
-        assert!(!vm.s_stack().underflow());
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.two_swap();
-        assert!(!vm.s_stack().underflow());
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.two_swap();
-        assert!(vm.s_stack().underflow());
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.s_stack().push(3);
-        vm.two_swap();
-        assert!(vm.s_stack().underflow());
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.s_stack().push(3);
-        vm.s_stack().push(4);
-        vm.two_swap();
-        assert!(!vm.s_stack().underflow());
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 4);
-        assert_eq!(vm.s_stack().pop(), 2);
-        assert_eq!(vm.s_stack().pop(), 1);
-        assert_eq!(vm.s_stack().pop(), 4);
-        assert_eq!(vm.s_stack().pop(), 3);
+        vm.set_source("3 cells allot  here 1+  -7 over !  @");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [-7]);
     }
 
-    #[bench]
-    fn bench_2swap(b: &mut Bencher) {
+    #[cfg(all(feature = "check-alignment", debug_assertions))]
+    #[test]
+    #[should_panic(expected = "unaligned cell access")]
+    fn test_unaligned_store_fetch() {
         let vm = &mut VM::new();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.s_stack().push(3);
-        vm.s_stack().push(4);
-        b.iter(|| vm.two_swap());
+        vm.set_source(": s   s\" strings are not cells\" ;  s 2drop");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        vm.set_source("3 cells allot  here 1+  -7 over !  @");
+        vm.evaluate_input();
     }
 
     #[test]
-    fn test_2over() {
+    fn test_to_r_r_fetch_r_from() {
         let vm = &mut VM::new();
-        vm.two_over();
-        assert!(!vm.s_stack().underflow());
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.two_over();
-        assert!(!vm.s_stack().underflow());
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.two_over();
-        assert!(!vm.s_stack().underflow());
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.s_stack().push(3);
-        vm.two_over();
-        assert!(!vm.s_stack().underflow());
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.s_stack().push(3);
-        vm.s_stack().push(4);
-        vm.two_over();
-        assert!(!vm.s_stack().underflow());
-        assert!(!vm.s_stack().overflow());
+        vm.set_source(": t 3 >r 2 r@ + r> + ; t");
+        vm.evaluate_input();
         assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 6);
-        assert_eq!(vm.s_stack().as_slice(), [1, 2, 3, 4, 1, 2]);
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 8);
     }
 
     #[bench]
-    fn bench_2over(b: &mut Bencher) {
+    fn bench_to_r_r_fetch_r_from(b: &mut Bencher) {
         let vm = &mut VM::new();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.s_stack().push(3);
-        vm.s_stack().push(4);
+        vm.set_source(": main 3 >r r@ drop r> drop ;");
+        vm.evaluate_input();
+        vm.set_source("' main");
+        vm.evaluate_input();
         b.iter(|| {
-            vm.two_over();
-            vm.two_drop();
+            vm.dup();
+            vm.execute();
+            vm.run();
         });
     }
 
     #[test]
-    fn test_depth() {
-        let vm = &mut VM::new();
-        vm.depth();
-        vm.depth();
-        vm.depth();
-        assert_eq!(vm.s_stack().as_slice(), [0, 1, 2]);
-    }
-
-    #[test]
-    fn test_one_plus() {
+    fn test_two_to_r_two_r_fetch_two_r_from() {
         let vm = &mut VM::new();
-        vm.one_plus();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.one_plus();
-        vm.check_stacks();
+        vm.set_source(": t 1 2 2>r 2r@ + 2r> - * ; t");
+        vm.evaluate_input();
         assert!(vm.last_error().is_none());
         assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 2);
+        assert_eq!(vm.s_stack().pop(), -3);
     }
 
     #[bench]
-    fn bench_one_plus(b: &mut Bencher) {
+    fn bench_two_to_r_two_r_fetch_two_r_from(b: &mut Bencher) {
         let vm = &mut VM::new();
-        vm.s_stack().push(0);
+        vm.set_source(": main 1 2 2>r 2r@ 2drop 2r> 2drop ;");
+        vm.evaluate_input();
+        vm.set_source("' main");
+        vm.evaluate_input();
         b.iter(|| {
-            vm.one_plus();
+            vm.dup();
+            vm.execute();
+            vm.run();
         });
     }
 
     #[test]
-    fn test_one_minus() {
+    fn test_if_then() {
         let vm = &mut VM::new();
-        vm.one_minus();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        // : t5 if ; t5
+        vm.set_source(": t5 if ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(2);
-        vm.one_minus();
-        vm.check_stacks();
+        // : t4 then ; t4
+        vm.set_source(": t4 then ; t4");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        vm.reset();
+        vm.clear_stacks();
+        // : t1 false dup if drop true then ; t1
+        vm.set_source(": t1 0 dup if drop -1 then ; t1");
+        vm.evaluate_input();
         assert!(vm.last_error().is_none());
         assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
+        vm.reset();
+        vm.clear_stacks();
+        // : t2 true dup if drop false then ; t1
+        vm.set_source(": t1 -1 dup if drop -1 then ; t1");
+        vm.evaluate_input();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -1);
     }
 
-    #[bench]
-    fn bench_one_minus(b: &mut Bencher) {
+    #[test]
+    fn test_if_else_then() {
         let vm = &mut VM::new();
-        vm.s_stack().push(0);
-        b.iter(|| {
-            vm.one_minus();
-        });
+        // : t3 else then ; t3
+        vm.set_source(": t3 else then ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        vm.reset();
+        vm.clear_stacks();
+        vm.set_source(": t1 0 if true else false then ; t1");
+        vm.evaluate_input();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), 0);
+        // : t2 1 if true else false then ; t2
+        vm.set_source(": t2 1 if true else false then ; t2");
+        vm.evaluate_input();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().pop(), -1);
     }
 
     #[test]
-    fn test_minus() {
+    fn test_begin_again() {
         let vm = &mut VM::new();
-        vm.minus();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        // : t3 begin ;
+        vm.set_source(": t3 begin ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(5);
-        vm.minus();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        // : t2 again ;
+        vm.set_source(": t2 again ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(5);
-        vm.s_stack().push(7);
-        vm.minus();
-        vm.check_stacks();
+        // : t1 0 begin 1+ dup 3 = if exit then again ; t1
+        vm.set_source(": t1 0 begin 1+ dup 3 = if exit then again ; t1");
+        vm.evaluate_input();
         assert_eq!(vm.last_error(), None);
         assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -2);
-    }
-
-    #[bench]
-    fn bench_minus(b: &mut Bencher) {
-        let vm = &mut VM::new();
-        vm.s_stack().push(0);
-        b.iter(|| {
-            vm.dup();
-            vm.minus();
-        });
+        assert_eq!(vm.s_stack().pop(), 3);
     }
 
     #[test]
-    fn test_plus() {
+    fn test_begin_while_repeat() {
         let vm = &mut VM::new();
-        vm.plus();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        // : t1 begin ;
+        vm.set_source(": t1 begin ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(5);
-        vm.plus();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        // : t2 while ;
+        vm.set_source(": t2 while ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
         vm.reset();
         vm.clear_stacks();
-        vm.s_stack().push(5);
-        vm.s_stack().push(7);
-        vm.plus();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
+        // : t3 repeat ;
+        vm.set_source(": t3 repeat ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        vm.reset();
+        vm.clear_stacks();
+        // : t4 begin while ;
+        vm.set_source(": t4 begin while ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        vm.reset();
+        vm.clear_stacks();
+        // : t5 begin repeat ;
+        vm.set_source(": t5 begin repeat ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        vm.reset();
+        vm.clear_stacks();
+        // : t6 while repeat ;
+        vm.set_source(": t6 while repeat ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        vm.reset();
+        vm.clear_stacks();
+        // : t7 0 begin 1+ dup 3 <> while repeat ; t1
+        vm.set_source(": t7 0 begin 1+ dup 3 <> while repeat ; t7");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
         assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 12);
+        assert_eq!(vm.s_stack().pop(), 3);
     }
 
-    #[bench]
-    fn bench_plus(b: &mut Bencher) {
+    #[test]
+    fn test_jump_table() {
         let vm = &mut VM::new();
-        vm.s_stack().push(1);
-        b.iter(|| {
-            vm.dup();
-            vm.plus();
-        });
+        vm.set_source(
+            "
+            : t ( i -- n )   [ 3 ] jump-table 10 20 30  0 exit
+                [ 10 ] label 100 exit  [ 20 ] label 200 exit  [ 30 ] label 300 ;
+            0 t  1 t  2 t  3 t  -1 t
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [100, 200, 300, 0, 0]);
+        vm.s_stack().reset();
+        vm.set_source(
+            ": a 1 ;  : b 2 ;  2 jump-table ab a b
+            0 ab jump  1 ab jump  : c   ab jump ;  0 c",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [1, 2, 1]);
+        vm.s_stack().reset();
+        vm.set_source("2 ab jump");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_NUMERIC_ARGUMENT));
+        vm.reset();
+        vm.set_source("variable v  100000000 v !  50000000 v jump");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_MEMORY_ADDRESS));
     }
 
     #[test]
-    fn test_star() {
+    fn test_label_goto_call() {
         let vm = &mut VM::new();
-        vm.star();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
+        // Go backwards.
+        vm.set_source(
+            ": test1   0labels  0  [ 10 ] label 1+ dup 3 > if exit then [ 10 ] goto ; test1",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().pop(), 4);
+        // Go forwards.
         vm.clear_stacks();
-        vm.s_stack().push(5);
-        vm.star();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
+        vm.set_source(": test2   0labels  [ 10 ] goto 1 [ 10 ] label 2 3 ; test2");
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().len(), 2);
+        // Call backwards
         vm.clear_stacks();
-        vm.s_stack().push(5);
-        vm.s_stack().push(7);
-        vm.star();
-        vm.check_stacks();
+        vm.set_source(": test3   0labels  [ 10 ] goto [ 20 ] label 2 3  exit  [ 10 ] label  [ 20 ] call 4 5 ; test3");
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().len(), 4);
+        // Call forwards
+        vm.clear_stacks();
+        vm.set_source(": test4   0labels  [ 10 ] call 1 exit [ 10 ] label 2 3 ; test4");
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().len(), 3);
+        assert_eq!(vm.s_stack().pop(), 1);
+        // Foward reference not resolved.
+        vm.clear_stacks();
+        vm.set_source(": test5   0labels  [ 10 ] call ; test5");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        // 0 goto
+        vm.clear_stacks();
+        vm.clear_error();
+        vm.set_source(": test6   [ 0 ] goto ;");
+        vm.evaluate_input();
+        assert!(vm.last_error() != None);
+        // 0 label
+        vm.clear_stacks();
+        vm.clear_error();
+        vm.set_source(": test7   [ 0 ] label ;");
+        vm.evaluate_input();
+        assert!(vm.last_error() != None);
+        // 0 call
+        vm.clear_stacks();
+        vm.clear_error();
+        vm.set_source(": test8   [ 0 ] call ;");
+        vm.evaluate_input();
+        assert!(vm.last_error() != None);
+        // Labels are scoped to the definition and stored sparsely.
+        let vm = &mut VM::new();
+        vm.set_source(
+            ": test9   [ 5000 ] goto 1 [ 5000 ] label 2 ;  : test10   [ 1000000 ] label 3 ;  test9 test10",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [2, 3]);
+        // Unresolved labels are listed.
+        vm.clear_stacks();
+        vm.set_source(": test11   [ 20 ] goto [ 10 ] call [ 20 ] call ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        assert_eq!(
+            vm.output_buffer().clone().unwrap(),
+            "Unresolved labels: 10 20 "
+        );
+    }
+
+    #[test]
+    fn test_backslash() {
+        let vm = &mut VM::new();
+        vm.set_source("1 2 3 \\ 5 6 7");
+        vm.evaluate_input();
+        assert!(vm.last_error().is_none());
+        assert_eq!(vm.s_stack().len(), 3);
+        assert_eq!(vm.s_stack().pop(), 3);
+        assert_eq!(vm.s_stack().pop(), 2);
+        assert_eq!(vm.s_stack().pop(), 1);
+    }
+
+    #[test]
+    fn test_marker_unmark() {
+        let vm = &mut VM::new();
+        let wordlist_len = vm.wordlist().len();
+        vm.set_source("here marker empty empty here =");
+        vm.evaluate_input();
         assert!(vm.last_error().is_none());
         assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 35);
+        assert_eq!(vm.s_stack().pop(), -1);
+        assert_eq!(vm.wordlist().len(), wordlist_len);
     }
 
-    #[bench]
-    fn bench_star(b: &mut Bencher) {
+    #[test]
+    fn test_abort() {
         let vm = &mut VM::new();
-        vm.s_stack().push(1);
-        b.iter(|| {
-            vm.dup();
-            vm.star();
-        });
+        vm.set_source("1 2 3 abort 5 6 7");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(ABORT));
+        assert_eq!(vm.s_stack().len(), 0);
     }
 
     #[test]
-    fn test_slash() {
+    fn test_break_level() {
         let vm = &mut VM::new();
-        vm.slash();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.set_source("break-on 1 2 xyz");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNDEFINED_WORD));
+        assert_eq!(vm.state().break_depth(), 1);
+        assert_eq!(vm.s_stack().as_slice(), [1, 2]);
+        vm.set_error(None);
+        vm.set_source("break-error continue");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.state().break_depth(), 0);
+        assert_eq!(vm.s_stack().as_slice(), [1, 2, -13]);
+        // abort leaves all break levels.
+        vm.set_source("xyz");
+        vm.evaluate_input();
+        assert_eq!(vm.state().break_depth(), 1);
+        vm.set_error(None);
+        vm.set_source("abort");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(ABORT));
+        assert_eq!(vm.state().break_depth(), 0);
+        assert_eq!(vm.s_stack().len(), 0);
+        // Stack errors are not breakable.
         vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(30);
-        vm.slash();
-        vm.check_stacks();
+        vm.set_source("drop");
+        vm.evaluate_input();
         assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(30);
-        vm.s_stack().push(7);
-        vm.slash();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 4);
+        assert_eq!(vm.state().break_depth(), 0);
     }
 
-    #[bench]
-    fn bench_slash(b: &mut Bencher) {
+    #[test]
+    fn test_schedule() {
         let vm = &mut VM::new();
-        vm.s_stack().push(1);
-        b.iter(|| {
-            vm.dup();
-            vm.slash();
-        });
+        vm.set_source(
+            "
+            variable trace
+            : rec ( n -- )   trace @ 10 * + trace ! ;
+            : t2   2 activate begin 2 rec pause again ;
+            : t3   3 activate begin 3 rec pause again ;
+            : go   t2 t3 pause 1 rec ;
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        vm.set_source("schedule\" 3 2 1\" go trace @");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().pop(), 321);
+        vm.set_source("0 trace !  solo on  go  solo off  trace @");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().pop(), 1);
+        vm.set_source("schedule\" 1 9\"");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_NUMERIC_ARGUMENT));
     }
 
     #[test]
-    fn test_mod() {
+    fn test_cfg_export() {
         let vm = &mut VM::new();
-        vm.p_mod();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(30);
-        vm.p_mod();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(30);
-        vm.s_stack().push(7);
-        vm.p_mod();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 2);
+        vm.set_source(
+            "
+            : f ( n -- )   0 > if 10 0 do i 3 = if leave then loop else exit then 1 ;
+            cfg-export f
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        let dot = vm.output_buffer().take().unwrap();
+        assert!(dot.starts_with("digraph \"f\" {"));
+        assert!(dot.contains("b0 [label=\"lit 0\\l>\\l0branch\\l\"];"));
+        assert!(dot.contains("b0 -> b6 [label=\"0\"];"));
+        assert!(dot.contains("b3 -> b5 [label=\"leave\"];"));
+        assert!(dot.contains("b4 -> b2 [label=\"loop\"];"));
+        assert!(dot.contains("b6 -> exit;"));
+        vm.set_source("cfg-export dup");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_NUMERIC_ARGUMENT));
     }
 
-    #[bench]
-    fn bench_mod(b: &mut Bencher) {
+    #[test]
+    fn test_post() {
         let vm = &mut VM::new();
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        b.iter(|| {
-            vm.p_mod();
-            vm.s_stack().push(2);
-        });
+        vm.set_source("post");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [0]);
+        vm.wordlist_mut()[1].hash ^= 1;
+        vm.set_source("post");
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().pop(), ::tools::POST_DICTIONARY);
+        vm.set_source("rehash post");
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().as_slice(), [0, 0]);
     }
 
     #[test]
-    fn test_slash_mod() {
+    fn test_hash_stats() {
         let vm = &mut VM::new();
-        vm.slash_mod();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(30);
-        vm.slash_mod();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(30);
-        vm.s_stack().push(7);
-        vm.slash_mod();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 2);
-        assert_eq!(vm.s_stack().pop(), 4);
-        assert_eq!(vm.s_stack().pop(), 2);
+        let words = vm.wordlist().len() - 1;
+        let lengths = vm.wordlist().bucket_lengths();
+        assert_eq!(lengths.iter().sum::<usize>(), words);
+        vm.set_source("hash-stats.");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        let out = vm.output_buffer().take().unwrap();
+        assert!(out.starts_with(&format!("words {} buckets {} used ", words, lengths.len())));
     }
 
-    #[bench]
-    fn bench_slash_mod(b: &mut Bencher) {
-        let vm = &mut VM::new();
-        vm.s_stack().push2(1, 2);
-        b.iter(|| {
-            vm.slash_mod();
-            vm.p_drop();
-            vm.s_stack().push(2);
-        });
+    #[test]
+    fn test_shared_names() {
+        let vm1 = &mut VM::new();
+        let vm2 = &mut VM::new();
+        let dup1 = vm1.find("dup").expect("dup");
+        let dup2 = vm2.find("dup").expect("dup");
+        assert_eq!(vm1.wordlist()[dup1].nfa(), vm2.wordlist()[dup2].nfa());
+        let nfa = vm1.wordlist()[dup1].nfa();
+        assert!(!vm1.data_space().has(nfa));
+        vm1.set_source(": double   dup + ;  3 double");
+        vm1.evaluate_input();
+        assert_eq!(vm1.s_stack().as_slice(), [6]);
+        let double = vm1.find("double").expect("double");
+        let nfa = vm1.wordlist()[double].nfa();
+        assert!(vm1.data_space().has(nfa));
+        assert_eq!(vm1.word_name(double), "double");
+        assert_eq!(
+            vm1.wordlist().find_xt(vm1.wordlist()[double].dfa()),
+            Some(double)
+        );
     }
 
     #[test]
-    fn test_abs() {
-        let vm = &mut VM::new();
-        vm.s_stack().push(-30);
-        vm.abs();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 30);
+    fn test_core_image() {
+        let loaded = &mut VM::with_builder(&VmBuilder::new().core_image(false));
+        let _sampled = VM::new();
+        let copied = &mut VM::new();
+        assert!(super::CORE_IMAGES
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|image| image.space.is_some()));
+        assert_eq!(copied.wordlist().len(), loaded.wordlist().len());
+        let offset = |vm: &mut VM| vm.data_space().here() - vm.data_space().start();
+        assert_eq!(offset(copied), offset(loaded));
+        for xt in 1..loaded.wordlist().len() {
+            assert_eq!(copied.word_name(xt), loaded.word_name(xt));
+        }
+        assert!(copied.dictionary_intact());
+        copied.set_output_buffer(String::new());
+        copied.set_source(": t   3 0 do i . loop ;  t  7 >in !  >in @  2 5 max");
+        copied.evaluate_input();
+        assert_eq!(copied.last_error(), None);
+        assert_eq!(copied.s_stack().as_slice(), [7, 5]);
+        assert_eq!(copied.output_buffer().as_ref().unwrap(), "0 1 2 ");
+        copied.s_stack().reset();
+        copied.set_source("-work  t");
+        copied.evaluate_input();
+        assert_eq!(copied.last_error(), Some(UNDEFINED_WORD));
+        // Only addresses stored by the compiler are moved.
+        for &(source, usable) in &[(": t   0 if 1 then ;", true), ("create x  here ,", false)] {
+            let vm = &mut VM::new();
+            let key = super::core_image_key(vm);
+            vm.data_space().record_addresses();
+            vm.set_source(source);
+            vm.evaluate_input();
+            let addresses = vm.data_space().take_addresses();
+            let below = unsafe {
+                ::std::slice::from_raw_parts(vm.data_space().start() as *const u8, key.offset)
+                    .to_vec()
+            };
+            let image = super::sample_core_image(vm, key, &below, &addresses);
+            assert_eq!(image.space.is_some(), usable, "{}", source);
+            assert_eq!(image.cells.len(), if usable { 1 } else { 0 }, "{}", source);
+        }
     }
 
     #[test]
-    fn test_negate() {
-        let vm = &mut VM::new();
-        vm.negate();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(30);
-        vm.negate();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -30);
+    fn test_lazy_word_sets() {
+        let eager = &mut VM::new();
+        let vm = &mut VM::with_builder(&VmBuilder::new().lazy(true));
+        assert!(vm
+            .wordlist()
+            .lazy_word_sets()
+            .contains(&WordSet::Environment));
+        let words = vm.wordlist().len();
+        assert!(words < eager.wordlist().len());
+        assert_eq!(vm.find_registered("floored"), None);
+        vm.set_source("floored  floored");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [0, 0]);
+        let registered = vm.wordlist().len();
+        assert!(registered > words);
+        vm.s_stack().reset();
+        // Forgotten by the marker, which forgets itself too, the word set
+        // is registered again.
+        vm.set_source("-work  floored");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [0]);
+        assert_eq!(vm.wordlist().len(), registered - 1);
     }
 
     #[test]
-    fn test_zero_less() {
+    fn test_word_size() {
         let vm = &mut VM::new();
-        vm.zero_less();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(-1);
-        vm.zero_less();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
-        vm.s_stack().push(0);
-        vm.zero_less();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
+        let header = mem::size_of::<super::Word<VM>>() as isize;
+        vm.set_source(
+            "
+            marker -big  create big 20000 allot  : small ;
+            ' big word-size  ' small word-size
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        // Names take a cell for the length and one for the characters.
+        let cell = mem::size_of::<usize>() as isize;
+        assert_eq!(
+            vm.s_stack().as_slice(),
+            [header + 2 * cell + 20000, header + 3 * cell]
+        );
+        vm.s_stack().reset();
+        vm.set_output_buffer(String::new());
+        vm.set_source("1 .dict");
+        vm.evaluate_input();
+        let out = vm.output_buffer().take().unwrap();
+        assert_eq!(
+            out.lines().next(),
+            Some(&*format!("{} big", header + 2 * cell + 20000))
+        );
+        let capacity = vm.wordlist().capacity();
+        vm.set_source("-big");
+        vm.evaluate_input();
+        vm.wordlist_mut().shrink_to_fit();
+        assert!(vm.wordlist().capacity() < capacity);
+        assert_eq!(vm.wordlist().capacity(), vm.wordlist().len());
     }
 
     #[test]
-    fn test_zero_equals() {
+    fn test_error_history() {
         let vm = &mut VM::new();
-        vm.zero_equals();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(0);
-        vm.zero_equals();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
-        vm.s_stack().push(-1);
-        vm.zero_equals();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
-        vm.s_stack().push(1);
-        vm.zero_equals();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
+        vm.set_source(": f   0 0 / ;  2 error-history");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        for source in &["1 f", "f", "nonsense"] {
+            vm.set_source(source);
+            vm.evaluate_input();
+            vm.reset();
+        }
+        let errors: Vec<_> = vm
+            .wordlist()
+            .errors()
+            .iter()
+            .map(|r| (r.error, r.token.clone()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (DIVISION_BY_ZERO, "f".to_string()),
+                (UNDEFINED_WORD, "nonsense".to_string()),
+            ]
+        );
+        assert_eq!(vm.wordlist().errors().iter().next().unwrap().word, "/");
+        vm.set_source("errors.");
+        vm.evaluate_input();
+        let out = vm.output_buffer().take().unwrap();
+        assert!(out.contains("-10 Division by zero in / at f 0:"));
+        assert_eq!(out.lines().count(), 2);
+        vm.set_source("0errors");
+        vm.evaluate_input();
+        assert!(vm.wordlist().errors().is_empty());
     }
 
     #[test]
-    fn test_zero_greater() {
+    fn test_priority() {
         let vm = &mut VM::new();
-        vm.zero_greater();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.zero_greater();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
-        vm.s_stack().push(0);
-        vm.zero_greater();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
+        vm.set_source(
+            "
+            variable trace
+            : rec ( n -- )   trace @ 10 * + trace ! ;
+            : t2   2 activate begin 2 rec pause again ;
+            : t3   3 activate begin 3 rec pause again ;
+            : go   t2 t3  3 0 do pause 1 rec loop ;
+            1 3 priority!  3 priority@  2 priority@
+            go trace @
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [1, 0, 3231_3231_3231]);
+        vm.set_source("2 3 priority!");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_NUMERIC_ARGUMENT));
     }
 
     #[test]
-    fn test_zero_not_equals() {
+    fn test_task_status() {
         let vm = &mut VM::new();
-        vm.zero_not_equals();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(0);
-        vm.zero_not_equals();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
-        vm.s_stack().push(-1);
-        vm.zero_not_equals();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
-        vm.s_stack().push(1);
-        vm.zero_not_equals();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
+        vm.set_source(
+            "
+            variable sem  1 sem !
+            : t2   2 activate  sem get  begin pause again ;
+            : p   pause ;
+            t2  2 task-status  p 2 task-status  1 task-status
+            0 sem !  p 2 task-status  sem @
+            2 suspend  2 task-status
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [1, 2, 0, 1, 2, 4]);
+        #[cfg(feature = "facility")]
+        {
+            vm.s_stack().reset();
+            vm.set_source(": t3   3 activate  2 ms  begin pause again ;  t3 p 3 task-status");
+            vm.evaluate_input();
+            vm.advance();
+            vm.advance();
+            vm.set_source("3 task-status  p 3 task-status");
+            vm.evaluate_input();
+            assert_eq!(vm.last_error(), None);
+            assert_eq!(vm.s_stack().as_slice(), [3, 1, 1]);
+        }
     }
 
     #[test]
-    fn test_less_than() {
+    fn test_io_wait() {
         let vm = &mut VM::new();
-        vm.less_than();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(-1);
-        vm.less_than();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(-1);
-        vm.s_stack().push(0);
-        vm.less_than();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
-        vm.s_stack().push(0);
-        vm.s_stack().push(0);
-        vm.less_than();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
+        vm.set_source(
+            "
+            variable token  variable result
+            io-token token !
+            : t2   2 activate  token @ io-wait result !  begin pause again ;
+            : p   pause ;
+            t2 p 2 task-status  token @ io-done?
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [5, 0]);
+        vm.s_stack().reset();
+        let handle = vm.wordlist().poll_handle();
+        let t = handle.new_token();
+        handle.complete(t - 1, 42);
+        vm.set_source("token @ io-done?  p 2 task-status  result @  token @ io-done?");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [-1, 1, 42, 0]);
+        vm.set_source("token @ io-result");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_NUMERIC_ARGUMENT));
     }
 
     #[test]
-    fn test_equals() {
+    fn test_scan() {
         let vm = &mut VM::new();
-        vm.equals();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(0);
-        vm.equals();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(0);
-        vm.s_stack().push(0);
-        vm.equals();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
-        vm.s_stack().push(-1);
-        vm.s_stack().push(0);
-        vm.equals();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
-        vm.s_stack().push(1);
-        vm.s_stack().push(0);
-        vm.equals();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
+        vm.add_primitive("advance", VM::advance);
+        vm.set_source(
+            "
+            variable in  variable out  variable a  variable b
+            : read   1 in +! ;
+            : write   a @ b @ + out ! ;
+            : t2   2 activate  begin  1 a +!  pause  again ;
+            : t3   3 activate  begin  10 b +!  pause  again ;
+            t2 t3
+            ' read scan-phase  ' scan-tasks scan-phase  ' write scan-phase
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        let report = vm.scan();
+        assert_eq!(report.elapsed, 0);
+        assert!(!report.overrun);
+        vm.scan();
+        vm.set_source("in @  out @  1 task-status  2 task-status");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [2, 22, 0, 1]);
+        vm.s_stack().reset();
+        vm.set_source(": tick   advance advance ;  ' tick scan-phase  1000 scan-period!");
+        vm.evaluate_input();
+        let report = vm.scan();
+        assert_eq!(report.elapsed, 2_000_000);
+        assert!(report.overrun);
+        vm.set_source("scan-overruns  out @");
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().as_slice(), [1, 33]);
     }
 
     #[test]
-    fn test_greater_than() {
+    fn test_after() {
         let vm = &mut VM::new();
-        vm.greater_than();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.greater_than();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(0);
-        vm.greater_than();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
-        vm.s_stack().push(0);
-        vm.s_stack().push(0);
-        vm.greater_than();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
+        vm.add_primitive("advance", VM::advance);
+        vm.set_source(
+            "
+            variable n
+            : bump   1 n +! ;
+            : late   10 n +! ;
+            : yield   pause ;
+            0 ' bump after  1000000 ' late after  0 ' bump after cancel
+            yield n @
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [1, 2, 1]);
+        assert_eq!(vm.wordlist().timers().len(), 1);
+        vm.s_stack().reset();
+        vm.set_source("advance advance advance yield  n @  timers-overdue");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [11, 1]);
+        assert_eq!(vm.wordlist().timer_lateness(), 2_000_000);
+        assert!(vm.wordlist().timers().is_empty());
+        vm.set_source("-1 ' bump after");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_NUMERIC_ARGUMENT));
     }
 
     #[test]
-    fn test_not_equals() {
+    fn test_deadlock() {
+        use super::WaitFor;
         let vm = &mut VM::new();
-        vm.not_equals();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(0);
-        vm.not_equals();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(0);
-        vm.s_stack().push(0);
-        vm.not_equals();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
-        vm.s_stack().push(-1);
-        vm.s_stack().push(0);
-        vm.not_equals();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
-        vm.s_stack().push(1);
-        vm.s_stack().push(0);
-        vm.not_equals();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
+        vm.set_source(
+            "
+            variable a  variable b
+            : t2   2 activate  a get  pause  b get  begin pause again ;
+            : t3   3 activate  b get  pause  a get  begin pause again ;
+            : go   t2 t3  1 suspend pause ;
+            go
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(DEADLOCK));
+        assert_eq!(vm.current_task(), 0);
+        assert!(vm.awake(0));
+        let (a, b) = (vm.find("a").unwrap(), vm.find("b").unwrap());
+        let (a, b) = (vm.wordlist()[a].dfa(), vm.wordlist()[b].dfa());
+        assert_eq!(
+            vm.wordlist().deadlock(),
+            [
+                WaitFor {
+                    task: 1,
+                    semaphore: b,
+                    holder: Some(2)
+                },
+                WaitFor {
+                    task: 2,
+                    semaphore: a,
+                    holder: Some(1)
+                },
+            ]
+        );
     }
 
     #[test]
-    fn test_within() {
+    fn test_pass() {
         let vm = &mut VM::new();
-        vm.within();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.within();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(1);
-        vm.within();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.within();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
-        vm.s_stack().push(1);
-        vm.s_stack().push(0);
-        vm.s_stack().push(1);
-        vm.within();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
-        vm.s_stack().push(0);
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.within();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
-        vm.s_stack().push(3);
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.within();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
+        vm.set_source(
+            "
+            variable a  variable b
+            : worker   2 activate ( n a-addr )  begin 2dup +! pause again ;
+            : go   pause pause ;
+            3 2 pass  a 2 pass  worker  go  a @
+            10 2 pass  b 2 pass  worker  go  a @  b @
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [6, 6, 20]);
+        vm.set_source("1 6 pass");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_NUMERIC_ARGUMENT));
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "
+            : t1   s\" 1 2 +\" evaluate ;
+            : t2   s\" t1 10 * source-id\" evaluate ;
+            t2 5 source-id
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [30, -1, 5, 0]);
+        vm.s_stack().reset();
+        #[cfg(feature = "loader")]
+        {
+            vm.set_source(": t3   s\" refill\" evaluate ;  t3");
+            vm.evaluate_input();
+            assert_eq!(vm.last_error(), None);
+            assert_eq!(vm.s_stack().as_slice(), [0]);
+            vm.s_stack().reset();
+        }
+        vm.set_source(": t4   s\" xyz\" evaluate ;  t4");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNDEFINED_WORD));
+        assert_eq!(vm.source_id(), 0);
+        assert_eq!(vm.state().evaluate_depth(), 0);
     }
 
     #[test]
-    fn test_invert() {
+    fn test_value() {
         let vm = &mut VM::new();
-        vm.invert();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(707);
-        vm.invert();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -708);
+        vm.set_source(
+            "fold-on  3 value v  1.5e fvalue fv
+            : set   to v  fv 2e f* to fv ;  v  7 set  v  fv  9 to v v  0.5e to fv fv",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [3, 7, 9]);
+        assert_eq!(vm.f_stack().as_slice(), [3.0, 0.5]);
+        for source in &["5 constant k  1 to k", ": t   to dup ;", "1 to nothing"] {
+            vm.set_source(source);
+            vm.evaluate_input();
+            let expected = if source.contains("nothing") {
+                UNDEFINED_WORD
+            } else {
+                INVALID_NAME_ARGUMENT
+            };
+            assert_eq!(vm.last_error(), Some(expected), "{}", source);
+            vm.reset();
+        }
+        for source in &["2 value", "2e fvalue"] {
+            let here = vm.data_space().here();
+            vm.set_source(source);
+            vm.evaluate_input();
+            assert!(vm.last_error().is_some(), "{}", source);
+            assert_eq!(vm.data_space().here(), here, "{}", source);
+            vm.reset();
+        }
     }
 
     #[test]
-    fn test_and() {
-        let vm = &mut VM::new();
-        vm.s_stack().push(707);
-        vm.s_stack().push(007);
-        vm.and();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert!(!vm.s_stack().overflow());
-        assert!(!vm.s_stack().underflow());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 3);
+    fn test_privileged_words() {
+        let vm = &mut VM::with_builder(&VmBuilder::new().unlock_pin("1234"));
+        vm.set_source(": setup   42 ;  : danger   7 ;");
+        vm.evaluate_input();
+        let danger = vm.find("danger").expect("danger");
+        vm.wordlist_mut()[danger].set_privileged(true);
+        for source in &[
+            "danger",
+            ": t   danger ;",
+            "' danger execute",
+            ": t   postpone danger ;",
+            ": m   ['] danger compile, ; immediate  : t   m ;",
+            "1 xt-table tbl  ' danger 0 tbl xt!  0 tbl dispatch",
+            "1 jump-table tbl danger  0 tbl jump",
+        ] {
+            vm.set_source(source);
+            vm.evaluate_input();
+            assert_eq!(vm.last_error(), Some(PRIVILEGED_WORD), "{}", source);
+            vm.reset();
+        }
+        vm.set_source("unlock 4321");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(PERMISSION_DENIED));
+        vm.reset();
+        vm.set_source("unlock 1234  danger  : app   danger 1+ ;  lock  app");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [7, 8]);
+        vm.set_source("danger");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(PRIVILEGED_WORD));
     }
 
     #[test]
-    fn test_or() {
+    fn test_verify_dict() {
         let vm = &mut VM::new();
-        vm.or();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(707);
-        vm.or();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(707);
-        vm.s_stack().push(07);
-        vm.or();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 711);
+        vm.set_source(
+            "5 constant k  : t   1 2 + ;  verify-dict  seal-dict  verify-dict  dict-hash",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        let hash = vm.s_stack().pop();
+        assert_eq!(vm.s_stack().as_slice(), [0, -1]);
+        vm.s_stack().reset();
+        for &(source, intact) in &[
+            ("variable v  7 v !  verify-dict", false),
+            ("seal-dict  8 v !  verify-dict", true),
+            ("3 ' t >body cell+ !  t  verify-dict", false),
+        ] {
+            vm.set_source(source);
+            vm.evaluate_input();
+            assert_eq!(vm.last_error(), None, "{}", source);
+            assert_eq!(
+                vm.s_stack().pop(),
+                if intact { -1 } else { 0 },
+                "{}",
+                source
+            );
+        }
+        vm.set_source("dict-hash");
+        vm.evaluate_input();
+        assert_ne!(vm.s_stack().pop(), hash);
     }
 
     #[test]
-    fn test_xor() {
+    fn test_exception_name() {
         let vm = &mut VM::new();
-        vm.xor();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(707);
-        vm.xor();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(707);
-        vm.s_stack().push(07);
-        vm.xor();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 708);
+        vm.set_output_buffer(String::new());
+        vm.set_source(
+            ": undefined?   undefined-word = ;
+            stack-underflow  invalid-xt  abort-exception  -13 undefined?
+            -4 exception-name type space  -263 exception-name type space  1 exception-name nip",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [-4, -263, -1, -1, 0]);
+        assert_eq!(
+            vm.output_buffer().take().expect("output"),
+            "stack-underflow invalid-xt "
+        );
     }
 
     #[test]
-    fn test_lshift() {
+    fn test_new_exception() {
         let vm = &mut VM::new();
-        vm.lshift();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.lshift();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(1);
-        vm.s_stack().push(1);
-        vm.lshift();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 2);
-        vm.s_stack().push(1);
-        vm.s_stack().push(2);
-        vm.lshift();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 4);
+        vm.set_output_buffer(String::new());
+        vm.set_source(
+            ": e1   s\" Valve stuck\" new-exception ;
+            : e2   s\" Sensor lost\" new-exception ;
+            e1 constant valve-stuck  e2 constant sensor-lost
+            valve-stuck sensor-lost  sensor-lost exception-name type  0 throw",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [-4096, -4097]);
+        let valve_stuck = vm.s_stack().as_slice()[0];
+        assert_eq!(vm.output_buffer().take().expect("output"), "Sensor lost");
+        vm.set_output_buffer(String::new());
+        vm.set_source(": t   s\" e2\" sandbox-eval ;  t drop  valve-stuck throw");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Exception::from_code(valve_stuck));
+        vm.set_source(".error");
+        vm.evaluate_input();
+        assert_eq!(vm.output_buffer().take().expect("output"), "Valve stuck");
+        assert_eq!(vm.wordlist().user_exceptions().len(), 2);
     }
 
     #[test]
-    fn test_rshift() {
+    fn test_need() {
         let vm = &mut VM::new();
-        vm.rshift();
-        vm.check_stacks();
+        vm.set_source(
+            ": add   2 need  + ;  : fadd   2 needs-f  f+ ;  : n   need ;
+            1 2 add  1e 2e fadd  1 0 n",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [3, 1]);
+        assert_eq!(vm.f_stack().as_slice(), [3.0]);
+        vm.set_source("drop add");
+        vm.evaluate_input();
         assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
         vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(8);
-        vm.rshift();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.set_source("1e fadd");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(FLOATING_POINT_STACK_UNDERFLOW));
         vm.reset();
-        vm.clear_stacks();
-        vm.s_stack().push(8);
-        vm.s_stack().push(1);
-        vm.rshift();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 4);
-        vm.s_stack().push(-1);
-        vm.s_stack().push(1);
-        vm.rshift();
-        vm.check_stacks();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert!(vm.s_stack().pop() > 0);
+        vm.set_source("guards-off  : add2   2 need  + ;  : n2   need ;  guards-on  5 3 n2  1 need");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [5]);
+        let names = |vm: &mut VM, name| -> Vec<String> {
+            let xt = vm.find(name).expect("word");
+            let code = vm.decode(xt).expect("code");
+            code.into_iter().map(|i| i.name).collect()
+        };
+        assert_eq!(names(vm, "add2"), ["+", "exit"]);
+        assert_eq!(names(vm, "n2"), ["drop", "exit"]);
+        assert_eq!(names(vm, "add"), ["lit", "need", "+", "exit"]);
     }
 
     #[test]
-    fn test_parse_word() {
+    fn test_strict_math() {
         let vm = &mut VM::new();
-        vm.set_source("hello world\t\r\n\"");
-        vm.parse_word();
-        assert_eq!(vm.last_token().clone().unwrap(), "hello");
-        assert_eq!(vm.state().source_index, 5);
-        vm.parse_word();
-        assert_eq!(vm.last_token().clone().unwrap(), "world");
-        assert_eq!(vm.state().source_index, 11);
-        vm.parse_word();
-        assert_eq!(vm.last_token().clone().unwrap(), "\"");
+        vm.set_source("-1 1 rshift  dup 1 +  2 3 +|  2 3 -|  -4 5 *|");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(
+            vm.s_stack().as_slice(),
+            [isize::MAX, isize::MIN, 5, -1, -20]
+        );
+        for source in &["1 +|", "-2 swap -|", "2 *|"] {
+            vm.s_stack().reset();
+            vm.s_stack().push(isize::MAX);
+            vm.set_source(source);
+            vm.evaluate_input();
+            assert_eq!(vm.last_error(), Some(RESULT_OUT_OF_RANGE), "{}", source);
+            vm.reset();
+        }
+        vm.s_stack().reset();
+        vm.set_source(": t   1 + ;  strict-math-on  2 t");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [3]);
+        vm.set_source("-1 1 rshift t");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(RESULT_OUT_OF_RANGE));
+        vm.reset();
+        vm.set_source("strict-math-off  -1 1 rshift t");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [isize::MIN]);
     }
 
     #[test]
-    fn test_evaluate_input() {
+    fn test_strict_tick() {
         let vm = &mut VM::new();
-        // >r
-        vm.set_source(">r");
+        vm.set_source("' if  ' dup  2 over execute");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().len(), 4);
+        vm.set_source("drop drop drop  execute");
         vm.evaluate_input();
         assert_eq!(vm.last_error(), Some(INTERPRETING_A_COMPILE_ONLY_WORD));
         vm.reset();
-        vm.clear_stacks();
-        // drop
-        vm.set_source("drop");
+        vm.set_source(": t   ['] exit execute ;  t");
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        assert_eq!(vm.last_error(), Some(INTERPRETING_A_COMPILE_ONLY_WORD));
         vm.reset();
-        vm.clear_stacks();
-        // error in colon definition: 4drop
-        vm.set_source(": 4drop drop drop drop drop ; 4drop");
+        vm.set_source("strict-tick-on  ' dup  ' then");
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        assert_eq!(vm.last_error(), Some(INTERPRETING_A_COMPILE_ONLY_WORD));
         vm.reset();
-        vm.clear_stacks();
-        // undefined word
-        vm.set_source("xdrop");
+        vm.set_source(": t2   ['] exit ;");
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(UNDEFINED_WORD));
+        assert_eq!(vm.last_error(), Some(INTERPRETING_A_COMPILE_ONLY_WORD));
         vm.reset();
-        vm.clear_stacks();
-        // false true dup 1+ 2 -3
-        vm.set_source("false true dup 1+ 2 -3");
+        vm.set_source("strict-tick-off  ' then drop");
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 5);
-        assert_eq!(vm.s_stack().pop(), -3);
-        assert_eq!(vm.s_stack().pop(), 2);
-        assert_eq!(vm.s_stack().pop(), 0);
-        assert_eq!(vm.s_stack().pop(), -1);
-        assert_eq!(vm.s_stack().pop(), 0);
+        assert_eq!(vm.last_error(), None);
     }
 
-    /*
-        #[bench]
-        fn bench_compile_words_at_beginning_of_wordlist(b: &mut Bencher) {
-            let vm = &mut VM::new();
-            b.iter(|| {
-                vm.set_source("marker empty : main noop noop noop noop noop noop noop noop ; empty");
+    #[test]
+    fn test_immediate_semantics() {
+        // Every built-in immediate word, its use while compiling, and its use
+        // while interpreting unless compile-only.
+        let immediates: &[(&str, &str, Option<&str>)] = &[
+            ("literal", ": t [ 1 ] literal ;", None),
+            ("fliteral", ": t [ 1e ] fliteral ;", None),
+            ("(", ": t ( c ) ;", Some("( c )")),
+            ("\\", ": t \\ c\n ;", Some("\\ c")),
+            ("[", ": t [ ] ;", Some("[")),
+            ("[']", ": t ['] dup ;", None),
+            ("[char]", ": t [char] a ;", None),
+            (";", ": t ;", None),
+            ("if", ": t if then ;", None),
+            ("else", ": t if else then ;", None),
+            ("then", ": t if then ;", None),
+            ("case", ": t case 1 of endof endcase ;", None),
+            ("of", ": t case 1 of endof endcase ;", None),
+            ("of\"", ": t case of\" a\" endof endcase ;", None),
+            ("endof", ": t case 1 of endof endcase ;", None),
+            ("endcase", ": t case 1 of endof endcase ;", None),
+            ("begin", ": t begin again ;", None),
+            ("while", ": t begin 0 while repeat ;", None),
+            ("repeat", ": t begin 0 while repeat ;", None),
+            ("until", ": t begin 1 until ;", None),
+            ("again", ": t begin again ;", None),
+            ("0labels", ": t 0labels ;", Some("0labels")),
+            ("label", ": t 0labels [ 1 ] label ;", None),
+            ("goto", ": t 0labels [ 1 ] label [ 1 ] goto ;", None),
+            (
+                "jump-table",
+                ": t 0labels [ 1 ] jump-table 10 [ 10 ] label ;",
+                Some("1 jump-table jt dup"),
+            ),
+            ("call", ": t 0labels [ 1 ] call exit [ 1 ] label ;", None),
+            ("recurse", ": t recurse ;", None),
+            ("do", ": t 0 0 do loop ;", None),
+            ("?do", ": t 0 0 ?do 1 +loop ;", None),
+            ("loop", ": t 0 0 do loop ;", None),
+            ("+loop", ": t 0 0 ?do 1 +loop ;", None),
+            ("postpone", ": t postpone dup ;", None),
+            ("does>", ": t create does> ;", None),
+            ("s\"", ": t s\" x\" ;", None),
+            (".\"", ": t .\" x\" ;", None),
+            (".(", ": t .( x) ;", Some(".( x)")),
+            (
+                "text:",
+                ": t text: END\nx\nEND\n;",
+                Some("text: END\nx\nEND\n2drop"),
+            ),
+            ("=>", "fsm: m state: a 0 on => a ;fsm", None),
+            #[cfg(feature = "file-access")]
+            ("path\"", ": t path\" x\" ;", Some("path\" x\" 2drop")),
+            ("chars", ": t 1 chars ;", Some("1 chars drop")),
+            ("2literal", ": t [ 1 2 ] 2literal ;", None),
+            ("to", "0 value v  : t to v ;", Some("1 to v")),
+        ];
+        let vm = &mut VM::new();
+        let mut names = Vec::new();
+        for xt in 1..vm.wordlist().len() {
+            if vm.wordlist()[xt].is_immediate() && !vm.wordlist()[xt].is_hidden() {
+                names.push(vm.word_name(xt));
+            }
+        }
+        names.sort();
+        names.dedup();
+        let mut listed: Vec<&str> = immediates.iter().map(|i| i.0).collect();
+        listed.sort();
+        assert_eq!(names, listed);
+        vm.set_source(": ct   parse-word compile-token ;");
+        vm.evaluate_input();
+        for &(name, compiled, interpreted) in immediates {
+            vm.set_source(compiled);
+            vm.evaluate_input();
+            assert_eq!(vm.last_error(), None, "{}", compiled);
+            assert_eq!(vm.s_stack().as_slice(), [], "{}", compiled);
+            let xt = vm.find(name).expect(name);
+            if vm.wordlist()[xt].is_compile_only() {
+                for &source in &[
+                    name,
+                    &format!("' {} execute", name),
+                    &format!("ct {}", name),
+                ] {
+                    vm.set_source(source);
+                    vm.evaluate_input();
+                    assert_eq!(
+                        vm.last_error(),
+                        Some(INTERPRETING_A_COMPILE_ONLY_WORD),
+                        "{}",
+                        source
+                    );
+                    vm.reset();
+                }
+            } else {
+                let source = interpreted.expect(name);
+                vm.set_source(source);
                 vm.evaluate_input();
-                vm.s_stack().reset();
-            });
+                assert_eq!(vm.last_error(), None, "{}", source);
+                assert_eq!(vm.s_stack().as_slice(), [], "{}", source);
+            }
         }
+    }
 
-        #[bench]
-        fn bench_compile_words_at_end_of_wordlist(b: &mut Bencher) {
-            let vm = &mut VM::new();
-            b.iter(|| {
-                       vm.set_source("marker empty : main bye bye bye bye bye bye bye bye ; empty");
-                       vm.evaluate_input();
-                       vm.s_stack().reset();
-                   });
-        }
+    #[test]
+    fn test_of_quote() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            ": cmd ( c-addr u -- n )
+                case
+                  of\" start\" 1 endof
+                  of\" stop\" 2 endof
+                  dup 100 + -rot
+                endcase ;
+            : t   s\" stop\" cmd  s\" start\" cmd  s\" sta\" cmd  s\" \" cmd ;  t",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [2, 1, 103, 100]);
+        vm.s_stack().reset();
+        vm.set_source(": m   case 1 of endof of\" a\" endof endcase ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        vm.reset();
+        vm.set_source(": m   case of\" a\" endof 1 of endof endcase ;");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+    }
+
+    #[test]
+    fn test_word_effects() {
+        let vm = &mut VM::new();
+        vm.set_output_buffer(String::new());
+        vm.set_source(
+            ": sq ( n -- n*n ) dup * ;  : fsq ( x -- )  ( F: r -- r2 ) fdup f* ;  : nop ( ) ;
+            effect sq  effect fsq  effect nop  effect dup  effect f+  3 sq",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [9]);
+        assert_eq!(
+            vm.output_buffer().take().unwrap(),
+            "( n -- n*n ) ( x -- ) ( F: r -- r2 ) ( ? ) ( x -- x x ) ( F: r1 r2 -- r3 ) "
+        );
+        vm.set_output_buffer(String::new());
+        vm.set_source("apropos sq");
+        vm.evaluate_input();
+        assert_eq!(
+            vm.output_buffer().take().unwrap(),
+            "fsq ( x -- ) ( F: r -- r2 ) sq ( n -- n*n ) fsqrt ( F: r1 -- r2 ) "
+        );
+        vm.set_output_buffer(String::new());
+        vm.set_source("effect nosuchword");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNDEFINED_WORD));
+    }
 
-    */
+    #[test]
+    fn test_nesting_limit() {
+        let vm = &mut VM::with_builder(&VmBuilder::new().max_nesting(4));
+        vm.set_source(": r   1 s\" r\" evaluate ;  r");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(NESTING_LIMIT));
+        assert_eq!(vm.state().nesting_depth(), 0);
+        assert_eq!(vm.source_id(), 0);
+        vm.reset();
+        // `bye` in nested interpretation stops the callers too.
+        vm.set_source(": b   s\" 1 bye 2\" evaluate 3 ;  b 4");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [1]);
+        assert_eq!(vm.state().nesting_depth(), 0);
+    }
 
     #[test]
-    fn test_push_source() {
-        let mut vm = VM::new();
-        vm.set_source(": x");
-        vm.push_source(" ");
-        vm.push_source("1");
-        vm.push_source(" ");
-        vm.push_source(";");
-        assert_eq!(vm.input_buffer(), &Some(": x 1 ;".to_owned()));
+    fn test_sandbox_eval() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "variable x  5 x !
+            : t1   s\" 1 x !  : foo 42 ;  foo 7\" sandbox-eval ;
+            : t2   s\" 1 0 /\" sandbox-eval ;
+            : t3   s\" foo\" sandbox-eval ;
+            t1 x @  1 2 t2  t3",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [-1, 5, 1, 2, 0, 0]);
+        let fork = vm.fork();
+        vm.set_source(": bar 1 ;  bar");
+        vm.evaluate_input();
+        assert!(vm.find("bar").is_some());
+        vm.discard_fork(fork);
+        assert!(vm.find("bar").is_none());
+        assert_eq!(vm.s_stack().len(), 6);
+        vm.s_stack().reset();
+        vm.set_source(
+            ": a 1 ;  marker m  : c 3 ;
+            : t   s\" m\" sandbox-eval ;  t  c
+            : t4   s\" marker m2  : d 4 ;  m2\" sandbox-eval ;  t4",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [0, 3, -1]);
+        vm.set_source("m  c");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNDEFINED_WORD));
     }
 
+    #[cfg(feature = "loader")]
     #[test]
-    fn test_colon_and_semi_colon() {
+    fn test_source_file_q() {
+        let path = ::std::env::temp_dir().join("rtforth-test-source-file.fs");
+        ::std::fs::write(&path, ": from-file 42 ;\n").expect("write source file");
+        let path = path.to_str().expect("path");
         let vm = &mut VM::new();
-        // :
-        vm.set_source(":");
+        vm.set_source(&format!(
+            ": loaded?   s\" {}\" source-file? ;  loaded?  marker -test  include {}  loaded?",
+            path, path
+        ));
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(UNEXPECTED_END_OF_FILE));
-        vm.reset();
-        vm.clear_stacks();
-        // : 2+3 2 3 + ; 2+3
-        vm.set_source(": 2+3 2 3 + ; 2+3");
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [0, -1]);
+        vm.s_stack().reset();
+        vm.set_source(&format!(
+            "-test loaded?  require {}  require {}  loaded?",
+            path, path
+        ));
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 5);
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [0, -1]);
+        assert!(vm.wordlist().is_loaded_file(path));
     }
 
+    #[cfg(feature = "loader")]
     #[test]
-    fn test_constant() {
+    fn test_lint() {
+        use tools::{LintKind, Tools};
+        let path = ::std::env::temp_dir().join("rtforth-test-lint.fs");
+        ::std::fs::write(
+            &path,
+            "\\ lint test
+            : fine   10 0 do i 3 = if unloop exit then loop  begin 1 again ;
+            : early   10 0 do exit loop ;
+            : dead   begin again 1 ;
+            ",
+        )
+        .expect("write source file");
+        let path = path.to_str().expect("path");
         let vm = &mut VM::new();
-        // constant x
-        vm.set_source("constant");
+        vm.set_source(&format!("include {}", path));
         vm.evaluate_input();
-        // Note: cannot detect underflow.
-        // assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        // 5 constant x x x
-        vm.set_source("5 constant x x x");
+        assert_eq!(vm.last_error(), None);
+        let found: Vec<_> = vm
+            .lint()
+            .iter()
+            .map(|w| (vm.word_name(w.xt), w.kind, w.position.line))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("early".to_string(), LintKind::ExitInLoop, 3),
+                ("early".to_string(), LintKind::Unreachable, 3),
+                ("dead".to_string(), LintKind::Unreachable, 4),
+            ]
+        );
+        vm.set_source("lint");
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 2);
-        assert_eq!(vm.s_stack().pop(), 5);
-        assert_eq!(vm.s_stack().pop(), 5);
+        assert_eq!(vm.s_stack().pop(), 3);
+        let out = vm.output_buffer().take().unwrap();
+        assert!(out.contains(":4: dead: unreachable code\n"));
     }
 
+    #[cfg(feature = "loader")]
     #[test]
-    fn test_constant_in_colon() {
+    fn test_source_provider() {
+        use loader::{HasLoader, MemorySources};
+        let mut sources = MemorySources::new();
+        sources.add("lib/a.fs", "include lib/b.fs\n: a   b 1+ ;\n");
+        sources.add("lib/b.fs", ": b   41 ;\n");
         let vm = &mut VM::new();
-        // 77 constant x
-        // : 2x  x 2 * ;  2x
-        vm.set_source("77 constant x  : 2x x 2 * ;  2x");
+        vm.add_source_provider(Box::new(sources));
+        vm.set_source("include lib/a.fs  a  path\" lib/b.fs\" source-file?");
         vm.evaluate_input();
-        vm.run();
-        assert_eq!(vm.s_stack().pop(), 154);
-        assert_eq!(vm.s_stack().len, 0);
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [42, -1]);
+        vm.set_source("include lib/c.fs");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(ABORT));
     }
 
+    #[cfg(feature = "loader")]
     #[test]
-    fn test_create_and_store_fetch() {
+    fn test_close_sources_on_reset() {
+        use loader::HasLoader;
+        let path = ::std::env::temp_dir().join("rtforth-test-reset.fs");
+        ::std::fs::write(&path, "1 2 +\n").expect("write source file");
+        for &close in &[true, false] {
+            let vm = &mut VM::new();
+            vm.wordlist_mut().set_close_sources_on_reset(close);
+            vm.set_source(&format!(
+                "path\" {0}\" 2dup r/o open-file drop open-source",
+                path.display()
+            ));
+            vm.evaluate_input();
+            assert_eq!(vm.last_error(), None);
+            assert_eq!(vm.s_stack().as_slice(), [1]);
+            vm.reset();
+            let open = vm.sources().iter().filter(|s| s.is_some()).count();
+            assert_eq!(open, if close { 0 } else { 1 });
+        }
+    }
+
+    #[cfg(feature = "loader")]
+    #[test]
+    fn test_include_async() {
+        let path = ::std::env::temp_dir().join("rtforth-test-include-async.fs");
+        ::std::fs::write(&path, "1 n +!\n".repeat(10)).expect("write source file");
         let vm = &mut VM::new();
-        // @
-        vm.set_source("@");
+        vm.set_source(&format!(
+            "variable n  : step   pause loading? ;  2 halt  2 loader-task !  3 lines/round !
+            loading?  include-async {}  loading?",
+            path.display()
+        ));
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(INVALID_MEMORY_ADDRESS));
-        vm.reset();
-        vm.clear_stacks();
-        // !
-        vm.set_source("!");
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [100, 0, 0, -1]);
+        let mut progress = Vec::new();
+        loop {
+            vm.s_stack().reset();
+            vm.set_source("step");
+            vm.evaluate_input();
+            assert_eq!(vm.last_error(), None);
+            let (percent, flag) = vm.s_stack().pop2();
+            progress.push(percent);
+            if flag == 0 {
+                break;
+            }
+        }
+        assert_eq!(progress, [30, 60, 90, 100]);
+        vm.set_source("n @");
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(INVALID_MEMORY_ADDRESS));
-        vm.reset();
-        vm.clear_stacks();
-        // create x  1 cells allot  x !
-        vm.set_source("create x  1 cells allot  x !");
+        assert_eq!(vm.s_stack().as_slice(), [10]);
+        // Definitions are loaded without pausing.
+        ::std::fs::write(&path, ": f\n1\n2\n3\n4 ;\n").expect("write source file");
+        vm.s_stack().reset();
+        vm.set_source(&format!(
+            "2 lines/round !  include-async {}",
+            path.display()
+        ));
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        // create x  1 cells allot  x @  3 x !  x @
-        vm.set_source("create x  1 cells allot  x @  3 x !  x @");
+        loop {
+            vm.s_stack().reset();
+            vm.set_source("step  : g   5 ;");
+            vm.evaluate_input();
+            assert_eq!(vm.last_error(), None);
+            if vm.s_stack().pop() == 0 {
+                break;
+            }
+        }
+        vm.s_stack().reset();
+        vm.set_source("f g");
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 2);
-        assert_eq!(vm.s_stack().pop(), 3);
-        assert_eq!(vm.s_stack().pop(), 0);
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [1, 2, 3, 4, 5]);
     }
 
+    #[cfg(all(feature = "loader", unix))]
     #[test]
-    fn test_create_and_fetch_in_colon() {
+    fn test_source_timeout() {
+        use exception::FILE_IO_TIMEOUT;
+        use std::ffi::CString;
+        use std::io::Write;
+        let path = ::std::env::temp_dir().join("rtforth-test-source-timeout.fifo");
+        let _ = ::std::fs::remove_file(&path);
+        let c_path = CString::new(path.to_str().expect("path")).expect("C path");
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
         let vm = &mut VM::new();
-        // create x  1 cells allot
-        // 7 x !
-        // : x@ x @ ; x@
-        vm.set_source("create x  1 cells allot  7 x !  : x@ x @ ;  x@");
+        vm.wordlist_mut().set_close_sources_on_reset(false);
+        vm.set_source(&format!(
+            "path\" {0}\" 2dup r/w open-file drop open-source  dup 50 swap source-timeout!",
+            path.display()
+        ));
         vm.evaluate_input();
-        vm.run();
-        assert_eq!(vm.s_stack().pop(), 7);
-        assert_eq!(vm.s_stack().len, 0);
+        assert_eq!(vm.last_error(), None);
+        let id = vm.s_stack().pop();
+        let mut fifo = ::std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .expect("open FIFO");
+        for &text in &["", "1 2"] {
+            fifo.write_all(text.as_bytes()).unwrap();
+            vm.set_source(&format!("{} load-line", id));
+            vm.evaluate_input();
+            assert_eq!(vm.last_error(), Some(FILE_IO_TIMEOUT));
+            vm.reset();
+        }
+        fifo.write_all(b" 3\n").unwrap();
+        vm.set_source(&format!("{} load-line", id));
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [5, -1]);
+        let _ = ::std::fs::remove_file(&path);
     }
 
+    #[cfg(feature = "file-access")]
     #[test]
-    fn test_create_in_colon() {
+    fn test_path() {
+        use std::path::MAIN_SEPARATOR;
         let vm = &mut VM::new();
-        // create x 7 ,
-        // : x@ x @ ; x@
-        vm.set_source("create x 7 ,  : x@ x @ ;  x@");
+        vm.set_source(
+            "
+            path\" dir\" path\" a b\" path+ type
+            : conf   path\" etc\" ;  conf path\" rtf.fs\" path+ type
+            ",
+        );
         vm.evaluate_input();
-        vm.run();
-        assert_eq!(vm.s_stack().pop(), 7);
-        assert_eq!(vm.s_stack().len, 0);
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(
+            vm.output_buffer().take().unwrap(),
+            format!("dir{0}a betc{0}rtf.fs", MAIN_SEPARATOR)
+        );
     }
 
+    #[cfg(feature = "file-access")]
     #[test]
-    fn test_char_plus() {
+    fn test_read_line() {
+        let path = ::std::env::temp_dir().join("rtforth-test-read-line.txt");
+        ::std::fs::write(&path, "ab\r\ncdefg\n").expect("write file");
+        let path = path.to_str().expect("path");
         let vm = &mut VM::new();
-        vm.char_plus();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        // 2 char+
-        vm.set_source("2 char+");
+        vm.set_source(&format!(
+            "
+            create buf 16 allot  variable f
+            : rl ( n -- u flag )   buf swap f @ read-line drop ;
+            path\" {0}\" r/o open-file drop f !
+            8 rl  3 rl  8 rl  8 rl  f @ close-file drop
+            path\" {0}\" r/o bin open-file drop f !
+            8 rl  f @ close-file drop
+            ",
+            path
+        ));
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().as_slice(), [3]);
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [2, -1, 3, -1, 2, -1, 0, 0, 3, -1]);
     }
 
+    #[cfg(feature = "file-access")]
     #[test]
-    fn test_cell_plus_and_cells() {
+    fn test_retain() {
+        let path = ::std::env::temp_dir().join("rtforth-test-retain.bin");
+        let _ = ::std::fs::remove_file(&path);
+        let path = path.to_str().expect("path");
         let vm = &mut VM::new();
-        vm.cell_plus();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.cells();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.set_source("2 cell+  9 cells");
+        vm.set_source(&format!(
+            "
+            retain setpoint  retain count  count setpoint -
+            42 setpoint !  -7 count !
+            path\" {0}\" save-retained
+            0 setpoint !  0 count !
+            path\" {0}\" load-retained  setpoint @  count @
+            ",
+            path
+        ));
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [8, 0, 0, 42, -7]);
+        let vm = &mut VM::new();
+        vm.set_source(&format!(
+            "
+            retain count  retain other  5 other !
+            path\" {0}\" retain-file  count @  other @
+            100 count !  bye
+            ",
+            path
+        ));
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [-7, 5]);
+        let mut bytes = ::std::fs::read(path).expect("read file");
+        assert_eq!(&bytes[..5], b"RTFR\x01");
+        let vm = &mut VM::new();
+        vm.set_source(&format!(
+            "retain count  path\" {0}\" load-retained  count @",
+            path
+        ));
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().as_slice(), [0, 100]);
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        ::std::fs::write(path, bytes).expect("write file");
+        vm.s_stack().reset();
+        vm.set_source(&format!("path\" {0}\" load-retained  count @", path));
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().as_slice(), [-37, 100]);
+        let mut bytes = ::std::fs::read(path).expect("read file");
+        bytes[last] ^= 1;
+        bytes[4] = 2;
+        ::std::fs::write(path, bytes).expect("write file");
+        vm.s_stack().reset();
+        vm.set_source(&format!("path\" {0}\" load-retained  count @", path));
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().as_slice(), [-265, 100]);
+    }
+
+    #[cfg(feature = "file-access")]
+    #[test]
+    fn test_save_digest() {
+        use tools::{diff_digests, parse_digest, WordChange};
+        let digest = |source: &str, name: &str| {
+            let path = ::std::env::temp_dir().join(name);
+            let vm = &mut VM::new();
+            vm.set_source(&format!(
+                "{} path\" {}\" save-digest",
+                source,
+                path.display()
+            ));
+            vm.evaluate_input();
+            assert_eq!(vm.s_stack().as_slice(), [0]);
+            parse_digest(&::std::fs::read_to_string(path).expect("read file"))
+        };
+        let old = digest(
+            ": a 1 ;  : b 2 ;  : c ;  : e 0 if 1 then ;  3 constant k",
+            "rtforth-test-digest-old.txt",
+        );
+        let new = digest(
+            ": d ;  : a 1 ;  : b 5 ;  : e 0 if 1 then ;  4 constant k",
+            "rtforth-test-digest-new.txt",
+        );
         assert_eq!(
-            vm.s_stack().as_slice(),
+            diff_digests(&old, &new),
             [
-                2 + mem::size_of::<isize>() as isize,
-                9 * mem::size_of::<isize>() as isize
+                WordChange::Removed("c".to_string()),
+                WordChange::Added("d".to_string()),
+                WordChange::Changed("b".to_string()),
+                WordChange::Changed("k".to_string()),
             ]
         );
     }
 
+    #[cfg(feature = "file-access")]
     #[test]
-    fn test_tick() {
+    fn test_build_manifest() {
+        let dir = ::std::env::temp_dir();
+        let source = dir.join("rtforth-test-manifest.fth");
+        let manifest = dir.join("rtforth-test-manifest.txt");
+        ::std::fs::write(&source, "variable x\n: sq   dup * ;\n").expect("write file");
         let vm = &mut VM::new();
-        // '
-        vm.tick();
-        assert_eq!(vm.last_error(), Some(UNEXPECTED_END_OF_FILE));
-        vm.reset();
-        // ' xdrop
-        vm.set_source("' xdrop");
+        vm.set_source(&format!(
+            "path\" {}\" included  : t ;  path\" {}\" build-manifest",
+            source.display(),
+            manifest.display()
+        ));
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(UNDEFINED_WORD));
-        vm.reset();
-        vm.clear_stacks();
-        // ' drop
-        vm.set_source("' drop");
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [0]);
+        let text = ::std::fs::read_to_string(&manifest).expect("read file");
+        let lines: Vec<Vec<&str>> = text.lines().map(|l| l.split(' ').collect()).collect();
+        assert_eq!(lines.len(), 3);
+        let path = ::std::fs::canonicalize(&source).expect("path");
+        assert_eq!(lines[0][3], format!("{}:1", path.display()));
+        assert_eq!(lines[0][4], "x");
+        assert_eq!(lines[1][3], format!("{}:2", path.display()));
+        assert_eq!(lines[1][4], "sq");
+        assert_eq!(lines[2][3..], ["-:0", "t"]);
+        assert_eq!(lines[0][2], lines[1][1]);
+        assert_eq!(lines[1][2], lines[2][1]);
+        assert_eq!(lines[2][2], format!("{:#x}", vm.data_space().here()));
+    }
+
+    #[cfg(feature = "file-access")]
+    #[test]
+    fn test_dot_files() {
+        let path = ::std::env::temp_dir().join("rtforth-test-dot-files.txt");
+        ::std::fs::write(&path, "abc").expect("write file");
+        let path = path.to_str().expect("path");
+        let vm = &mut VM::new();
+        vm.wordlist_mut().set_max_files(1);
+        vm.set_source(&format!(
+            "
+            path\" {0}\" r/o bin open-file drop  pad 2 rot read-file 2drop
+            .files  max-files
+            path\" {0}\" r/o open-file nip
+            ",
+            path
+        ));
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(
+            vm.s_stack().as_slice(),
+            [1, isize::from(RESULT_OUT_OF_RANGE)]
+        );
+        assert_eq!(
+            vm.output_buffer().take().unwrap(),
+            format!("1 2 bin {}\n1 of 1 open\n", path)
+        );
     }
 
+    #[cfg(feature = "file-access")]
     #[test]
-    fn test_execute() {
+    fn test_io_error() {
+        let path = ::std::env::temp_dir().join("rtforth-test-io-error/missing.txt");
+        let path = path.to_str().expect("path");
         let vm = &mut VM::new();
-        // execute
-        vm.execute();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(UNSUPPORTED_OPERATION));
-        vm.reset();
-        vm.clear_stacks();
-        // ' drop execute
-        vm.set_source("' drop");
+        vm.set_output_buffer(String::new());
+        vm.set_source("io-error  .io-error");
         vm.evaluate_input();
-        vm.execute();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        vm.clear_stacks();
-        // 1 2  ' swap execute
-        vm.set_source("1 2  ' swap execute");
+        assert_eq!(vm.s_stack().as_slice(), [0]);
+        assert_eq!(vm.output_buffer().take().unwrap(), "no I/O error\n");
+        vm.s_stack().reset();
+        vm.set_output_buffer(String::new());
+        vm.set_source(&format!(
+            "path\" {}\" r/o open-file nip  io-error  .io-error",
+            path
+        ));
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 2);
-        assert_eq!(vm.s_stack().pop(), 1);
-        assert_eq!(vm.s_stack().pop(), 2);
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(
+            vm.s_stack().as_slice(),
+            [isize::from(::exception::NON_EXISTENT_FILE), 2]
+        );
+        assert!(vm.output_buffer().take().unwrap().starts_with("NotFound: "));
     }
 
+    #[cfg(feature = "file-access")]
     #[test]
-    fn test_here_allot() {
+    fn test_transcript() {
+        use output::Output;
+
+        let path = ::std::env::temp_dir().join("rtforth-test-transcript.txt");
+        ::std::fs::remove_file(&path).ok();
+        let path = path.to_str().expect("path");
         let vm = &mut VM::new();
-        // allot
-        vm.allot();
-        vm.check_stacks();
-        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
-        vm.reset();
-        // here 2 cells allot here -
-        vm.set_source("here 2 cells allot here -");
+        vm.set_output_buffer(String::new());
+        vm.set_source(&format!("path\" {}\" transcript", path));
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
+        assert_eq!(vm.s_stack().as_slice(), [0]);
+        assert!(vm.transcription().is_open());
+        vm.set_source("1 2 + .");
+        vm.evaluate_input();
+        vm.flush_output();
+        vm.set_source("transcript-off");
+        vm.evaluate_input();
+        vm.set_source("4 .");
+        vm.evaluate_input();
+        vm.flush_output();
+        assert!(!vm.transcription().is_open());
         assert_eq!(
-            vm.s_stack().pop(),
-            -((mem::size_of::<isize>() * 2) as isize)
+            ::std::fs::read_to_string(path).expect("transcript"),
+            "1 2 + .\n3 \ntranscript-off\n"
         );
     }
 
+    #[cfg(feature = "facility")]
     #[test]
-    fn test_to_r_r_fetch_r_from() {
+    fn test_clocks() {
         let vm = &mut VM::new();
-        vm.set_source(": t 3 >r 2 r@ + r> + ; t");
+        vm.advance();
+        vm.set_source("mticks  now");
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 8);
+        assert_eq!(vm.last_error(), None);
+        let (mticks, now) = vm.s_stack().pop2();
+        assert_eq!(mticks as u64, vm.monotonic_ns());
+        // Later than 2020-01-01 on any host clock set sensibly.
+        assert!(now > 1_577_836_800_000_000_000);
     }
 
-    #[bench]
-    fn bench_to_r_r_fetch_r_from(b: &mut Bencher) {
+    #[cfg(feature = "facility")]
+    #[test]
+    fn test_key_event() {
+        use facility::{Key, KeyEvent, Terminal};
+
+        struct Keys(Vec<KeyEvent>);
+
+        impl Terminal for Keys {
+            fn set_raw_mode(&mut self, _raw: bool) -> bool {
+                true
+            }
+            fn poll_key(&mut self) -> Option<KeyEvent> {
+                self.0.pop()
+            }
+        }
+
         let vm = &mut VM::new();
-        vm.set_source(": main 3 >r r@ drop r> drop ;");
+        vm.set_source("key-event?  raw-mode-on");
         vm.evaluate_input();
-        vm.set_source("' main");
+        assert_eq!(vm.last_error(), Some(UNSUPPORTED_OPERATION));
+        vm.reset();
+        let key = |key, ctrl| KeyEvent {
+            key,
+            shift: false,
+            ctrl,
+            alt: false,
+        };
+        vm.wordlist_mut().set_terminal(Box::new(Keys(vec![
+            key(Key::Char('a'), true),
+            key(Key::F(12), false),
+            key(Key::Up, false),
+        ])));
+        vm.set_source(
+            "raw-mode-on  key-event? swap k-up =              key-event? swap k-f12 =              key-event? swap 'a' k-ctrl-mask or =              key-event?  raw-mode-off",
+        );
         vm.evaluate_input();
-        b.iter(|| {
-            vm.dup();
-            vm.execute();
-            vm.run();
-        });
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [-1, -1, -1, -1, -1, -1, 0, 0]);
     }
 
+    #[cfg(feature = "facility")]
     #[test]
-    fn test_two_to_r_two_r_fetch_two_r_from() {
+    fn test_alarm() {
+        use facility::{Annunciator, Facility};
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        struct Banner(Rc<RefCell<Vec<String>>>);
+
+        impl Annunciator for Banner {
+            fn beep(&mut self) {
+                self.0.borrow_mut().push("beep".to_string());
+            }
+            fn announce(&mut self, level: isize, message: &str) {
+                self.0.borrow_mut().push(format!("{} {}", level, message));
+            }
+        }
+
         let vm = &mut VM::new();
-        vm.set_source(": t 1 2 2>r 2r@ + 2r> - * ; t");
+        vm.set_output_buffer(String::new());
+        vm.set_source(": hot   2 s\" spindle overheat\" alarm ;  beep hot");
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -3);
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(
+            vm.output_buffer().take().expect("output"),
+            "\x07\x07ALARM 2: spindle overheat\n"
+        );
+        let shown = Rc::new(RefCell::new(Vec::new()));
+        vm.alarms_mut()
+            .set_annunciator(Box::new(Banner(shown.clone())));
+        vm.set_output_buffer(String::new());
+        vm.set_source(": door   1 s\" door open\" alarm ;  beep door  alarms.");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(*shown.borrow(), ["beep", "1 door open"]);
+        let output = vm.output_buffer().take().expect("output");
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" 2 spindle overheat"));
+        assert!(lines[1].ends_with(" 1 door open"));
     }
 
-    #[bench]
-    fn bench_two_to_r_two_r_fetch_two_r_from(b: &mut Bencher) {
+    #[cfg(feature = "facility")]
+    #[test]
+    fn test_term_size() {
+        use facility::{Key, KeyEvent, Terminal};
+
+        struct Screen {
+            size: (usize, usize),
+            resized: bool,
+        }
+
+        impl Terminal for Screen {
+            fn set_raw_mode(&mut self, _raw: bool) -> bool {
+                true
+            }
+            fn poll_key(&mut self) -> Option<KeyEvent> {
+                if self.resized {
+                    self.resized = false;
+                    self.size = (132, 43);
+                    Some(KeyEvent {
+                        key: Key::Resize,
+                        shift: false,
+                        ctrl: false,
+                        alt: false,
+                    })
+                } else {
+                    None
+                }
+            }
+            fn size(&mut self) -> Option<(usize, usize)> {
+                Some(self.size)
+            }
+        }
+
         let vm = &mut VM::new();
-        vm.set_source(": main 1 2 2>r 2r@ 2drop 2r> 2drop ;");
+        vm.set_source("term-size");
         vm.evaluate_input();
-        vm.set_source("' main");
+        assert_eq!(vm.last_error(), Some(UNSUPPORTED_OPERATION));
+        vm.reset();
+        vm.wordlist_mut().set_terminal(Box::new(Screen {
+            size: (80, 24),
+            resized: true,
+        }));
+        vm.set_source("term-size  key-event? swap k-resize =  term-size  key-event? nip");
         vm.evaluate_input();
-        b.iter(|| {
-            vm.dup();
-            vm.execute();
-            vm.run();
-        });
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [80, 24, -1, -1, 132, 43, 0]);
     }
 
     #[test]
-    fn test_if_then() {
+    fn test_memoize() {
         let vm = &mut VM::new();
-        // : t5 if ; t5
-        vm.set_source(": t5 if ;");
+        vm.set_source(
+            "variable calls  : sq ( n -- n*n )  1 calls +!  dup * ;
+            1 1 memoize sq  3 sq  3 sq  4 sq  calls @",
+        );
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
-        vm.reset();
-        vm.clear_stacks();
-        // : t4 then ; t4
-        vm.set_source(": t4 then ; t4");
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [9, 9, 16, 2]);
+        let memo = &vm.wordlist().memos()[0];
+        assert_eq!((memo.hits, memo.misses, memo.len()), (1, 2, 2));
+        vm.s_stack().reset();
+        vm.set_source(
+            ": fsq  1 calls +!  fdup f* ;  1 1 fmemoize fsq  1.5e fsq 1.5e fsq f+ calls @",
+        );
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
-        vm.reset();
-        vm.clear_stacks();
-        // : t1 false dup if drop true then ; t1
-        vm.set_source(": t1 0 dup if drop -1 then ; t1");
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.f_stack().as_slice(), [4.5]);
+        assert_eq!(vm.s_stack().as_slice(), [3]);
+        vm.s_stack().reset();
+        vm.set_source("forget-cache  3 sq drop  calls @");
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
-        vm.reset();
-        vm.clear_stacks();
-        // : t2 true dup if drop false then ; t1
-        vm.set_source(": t1 -1 dup if drop -1 then ; t1");
+        assert_eq!(vm.s_stack().as_slice(), [4]);
+        assert_eq!(vm.wordlist().memos()[0].misses, 1);
+        vm.set_source("sq");
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
+        assert_eq!(vm.last_error(), None);
+        vm.s_stack().reset();
+        vm.set_source("sq");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.set_source("1 1 memoize nothing");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNDEFINED_WORD));
+        vm.set_source("-1 1 memoize sq");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_NUMERIC_ARGUMENT));
     }
 
+    #[cfg(feature = "file-access")]
     #[test]
-    fn test_if_else_then() {
+    fn test_trace() {
+        use tools::{Tools, TRACE_CAPACITY};
+        let path = ::std::env::temp_dir().join("rtforth-test-trace.json");
+        let path = path.to_str().expect("path");
         let vm = &mut VM::new();
-        // : t3 else then ; t3
-        vm.set_source(": t3 else then ;");
-        vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
-        vm.reset();
-        vm.clear_stacks();
-        vm.set_source(": t1 0 if true else false then ; t1");
+        vm.set_source(&format!(
+            "1 trace!  : export   2 trace!  s\" {}\" trace-export ;  export",
+            path
+        ));
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 0);
-        // : t2 1 if true else false then ; t2
-        vm.set_source(": t2 1 if true else false then ; t2");
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [0]);
+        let ids: Vec<isize> = vm.wordlist().trace().iter().map(|e| e.id).collect();
+        assert_eq!(ids, [1, 2]);
+        let json = ::std::fs::read_to_string(path).expect("read trace");
+        assert!(json.starts_with("{\"traceEvents\":["));
+        assert!(json.contains("\"name\":\"2\",\"ph\":\"i\""));
+        for i in 0..TRACE_CAPACITY + 1 {
+            vm.s_stack().push(i as isize);
+            vm.trace_store();
+        }
+        assert_eq!(vm.wordlist().trace().len(), TRACE_CAPACITY);
+        assert_eq!(vm.wordlist().trace().iter().next().map(|e| e.id), Some(1));
+        vm.set_source("0trace");
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
+        assert!(vm.wordlist().trace().is_empty());
     }
 
     #[test]
-    fn test_begin_again() {
+    fn test_termination() {
         let vm = &mut VM::new();
-        // : t3 begin ;
-        vm.set_source(": t3 begin ;");
-        vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
-        vm.reset();
-        vm.clear_stacks();
-        // : t2 again ;
-        vm.set_source(": t2 again ;");
+        vm.set_source(
+            "variable n  : ok   1 n ! ;  : fault   1 0 / ;
+            variable s  4 s !  : waiter   2 activate  s get ;  : p   pause ;",
+        );
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        assert_eq!(vm.last_error(), None);
+        let ok = vm.find("ok").expect("ok");
+        vm.execute_word(ok);
+        assert_eq!(vm.forth(), Termination::Yielded);
+        assert_eq!(vm.run(), Termination::Completed);
+        assert_eq!(vm.forth(), Termination::Completed);
+        let fault = vm.find("fault").expect("fault");
+        vm.execute_word(fault);
+        assert_eq!(vm.run(), Termination::Faulted(DIVISION_BY_ZERO));
         vm.reset();
-        vm.clear_stacks();
-        // : t1 0 begin 1+ dup 3 = if exit then again ; t1
-        vm.set_source(": t1 0 begin 1+ dup 3 = if exit then again ; t1");
+        assert!(!vm.is_idle());
+        vm.set_source("waiter p");
         vm.evaluate_input();
         assert_eq!(vm.last_error(), None);
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 3);
+        assert!(!vm.is_idle());
+        vm.set_awake(0, false);
+        assert!(vm.is_idle());
     }
 
     #[test]
-    fn test_begin_while_repeat() {
+    fn test_bye() {
         let vm = &mut VM::new();
-        // : t1 begin ;
-        vm.set_source(": t1 begin ;");
-        vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
-        vm.reset();
-        vm.clear_stacks();
-        // : t2 while ;
-        vm.set_source(": t2 while ;");
-        vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
-        vm.reset();
-        vm.clear_stacks();
-        // : t3 repeat ;
-        vm.set_source(": t3 repeat ;");
-        vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
-        vm.reset();
-        vm.clear_stacks();
-        // : t4 begin while ;
-        vm.set_source(": t4 begin while ;");
+        vm.set_source(
+            "
+            variable trace
+            : rec ( n -- )   trace @ 10 * + trace ! ;
+            : e1   1 rec ;
+            : e2   2 rec ;
+            ' e1 at-exit  ' e2 at-exit
+            : main   bye 3 rec ;
+            ",
+        );
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
-        vm.reset();
-        vm.clear_stacks();
-        // : t5 begin repeat ;
-        vm.set_source(": t5 begin repeat ;");
+        assert_eq!(vm.last_error(), None);
+        let main = vm.find("main").expect("main");
+        vm.execute_word(main);
+        assert_eq!(vm.run(), Termination::Bye);
+        vm.set_source("trace @");
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        assert_eq!(vm.s_stack().pop(), 21);
+        // At-exit words run only once.
         vm.reset();
-        vm.clear_stacks();
-        // : t6 while repeat ;
-        vm.set_source(": t6 while repeat ;");
+        vm.set_source("bye 3 rec trace @");
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
         vm.reset();
-        vm.clear_stacks();
-        // : t7 0 begin 1+ dup 3 <> while repeat ; t1
-        vm.set_source(": t7 0 begin 1+ dup 3 <> while repeat ; t7");
+        vm.set_source("trace @");
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), None);
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), 3);
+        assert_eq!(vm.s_stack().as_slice(), [21]);
     }
 
     #[test]
-    fn test_label_goto_call() {
+    fn test_task_eval() {
         let vm = &mut VM::new();
-        // Go backwards.
         vm.set_source(
-            ": test1   0labels  0  [ 10 ] label 1+ dup 3 > if exit then [ 10 ] goto ; test1",
+            "
+            variable v
+            : worker   2 activate begin pause again ;
+            : go   s\" 3 4 + v !\" 2 task-eval pause ;
+            worker go v @
+            ",
         );
         vm.evaluate_input();
-        assert_eq!(vm.s_stack().pop(), 4);
-        // Go forwards.
-        vm.clear_stacks();
-        vm.set_source(": test2   0labels  [ 10 ] goto 1 [ 10 ] label 2 3 ; test2");
-        vm.evaluate_input();
-        assert_eq!(vm.s_stack().len(), 2);
-        // Call backwards
-        vm.clear_stacks();
-        vm.set_source(": test3   0labels  [ 10 ] goto [ 20 ] label 2 3  exit  [ 10 ] label  [ 20 ] call 4 5 ; test3");
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [7]);
+        vm.set_source(": go1   s\" 1\" 1 task-eval ; go1");
         vm.evaluate_input();
-        assert_eq!(vm.s_stack().len(), 4);
-        // Call forwards
-        vm.clear_stacks();
-        vm.set_source(": test4   0labels  [ 10 ] call 1 exit [ 10 ] label 2 3 ; test4");
+        assert_eq!(vm.last_error(), Some(INVALID_NUMERIC_ARGUMENT));
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let vm = &mut VM::new();
+        vm.set_source(": t2   2 activate begin pause again ;  t2  10 2 watchdog!");
         vm.evaluate_input();
-        assert_eq!(vm.s_stack().len(), 3);
-        assert_eq!(vm.s_stack().pop(), 1);
-        // Foward reference not resolved.
-        vm.clear_stacks();
-        vm.set_source(": test5   0labels  [ 10 ] call ; test5");
+        assert_eq!(vm.last_error(), None);
+        for _ in 0..10 {
+            vm.advance();
+        }
+        assert!(!vm.watchdog_expired(1));
+        vm.advance();
+        assert!(vm.watchdog_expired(1));
+        vm.set_source("2 watchdog-expired?  1 watchdog-expired?");
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
-        // 0 goto
-        vm.clear_stacks();
-        vm.clear_error();
-        vm.set_source(": test6   [ 0 ] goto ;");
+        assert_eq!(vm.s_stack().as_slice(), [-1, 0]);
+        vm.s_stack().reset();
+        vm.set_source("check-watchdogs");
         vm.evaluate_input();
-        assert!(vm.last_error() != None);
-        // 0 label
-        vm.clear_stacks();
-        vm.clear_error();
-        vm.set_source(": test7   [ 0 ] label ;");
+        assert_eq!(vm.last_error(), Some(WATCHDOG_EXPIRED));
+        assert!(!vm.watchdog_expired(1));
+        vm.reset();
+        vm.set_source("variable hit  : recover ( i -- )   hit ! ;  ' recover 2 watchdog-xt!");
         vm.evaluate_input();
-        assert!(vm.last_error() != None);
-        // 0 call
-        vm.clear_stacks();
-        vm.clear_error();
-        vm.set_source(": test8   [ 0 ] call ;");
+        for _ in 0..11 {
+            vm.advance();
+        }
+        vm.set_source("check-watchdogs  hit @");
         vm.evaluate_input();
-        assert!(vm.last_error() != None);
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [2]);
     }
 
     #[test]
-    fn test_backslash() {
+    fn test_interrupt() {
+        use std::sync::atomic::Ordering;
         let vm = &mut VM::new();
-        vm.set_source("1 2 3 \\ 5 6 7");
+        // Like `quit` of rtf, leave the runaway loop.
+        vm.add_primitive("(stop)", |vm| vm.state().instruction_pointer = 0);
+        vm.set_source("' (stop) handler!  : spin   0 begin 1+ again ;");
         vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 3);
-        assert_eq!(vm.s_stack().pop(), 3);
-        assert_eq!(vm.s_stack().pop(), 2);
-        assert_eq!(vm.s_stack().pop(), 1);
+        vm.wordlist()
+            .interrupt_flag()
+            .store(true, Ordering::Relaxed);
+        vm.set_source("spin");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(USER_INTERRUPT));
+        assert!(!vm.wordlist().interrupt_flag().load(Ordering::Relaxed));
     }
 
     #[test]
-    fn test_marker_unmark() {
+    fn test_evaluate_with_timeout() {
+        use std::time::Duration;
         let vm = &mut VM::new();
-        let wordlist_len = vm.wordlist().len();
-        vm.set_source("here marker empty empty here =");
-        vm.evaluate_input();
-        assert!(vm.last_error().is_none());
-        assert_eq!(vm.s_stack().len(), 1);
-        assert_eq!(vm.s_stack().pop(), -1);
-        assert_eq!(vm.wordlist().len(), wordlist_len);
+        vm.add_primitive("advance", VM::advance);
+        let budget = Duration::from_millis(5);
+        assert_eq!(
+            vm.evaluate_with_timeout(": spin   begin advance again ;  spin", budget),
+            Err(TIMEOUT)
+        );
+        vm.reset();
+        assert_eq!(vm.evaluate_with_timeout("1 2 +", budget), Ok(()));
+        assert_eq!(vm.s_stack().as_slice(), [3]);
     }
 
     #[test]
-    fn test_abort() {
+    fn test_breakpoints() {
         let vm = &mut VM::new();
-        vm.set_source("1 2 3 abort 5 6 7");
+        vm.set_source(": foo 1 + ;  2 break-count foo  0 foo foo foo");
         vm.evaluate_input();
-        assert_eq!(vm.last_error(), Some(ABORT));
-        assert_eq!(vm.s_stack().len(), 0);
+        assert_eq!(vm.last_error(), Some(USER_INTERRUPT));
+        assert_eq!(vm.state().break_depth(), 1);
+        assert_eq!(vm.s_stack().as_slice(), [2]);
+        vm.set_error(None);
+        vm.set_source("continue");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [3]);
+        vm.reset();
+        vm.clear_stacks();
+        vm.set_source("unbreak foo  : big? dup 5 > ;  ' big? break-if foo  3 foo foo foo foo");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(USER_INTERRUPT));
+        assert_eq!(vm.s_stack().as_slice(), [6]);
+        vm.reset();
+        vm.clear_stacks();
+        vm.set_source("0breaks  3 foo foo foo foo");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [7]);
+        vm.clear_stacks();
+        vm.set_source("checked-xts-on  ' big? break-if foo  3 foo foo foo foo");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(USER_INTERRUPT));
+        assert_eq!(vm.s_stack().as_slice(), [6]);
+        vm.reset();
+        vm.clear_stacks();
+        vm.set_source("0breaks  99999 break-if foo");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_XT));
     }
 
     #[test]
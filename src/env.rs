@@ -1,12 +1,116 @@
 //! Environemnt word set
 
 use core::Core;
+use memory::Memory;
+use std::env::consts::{ARCH, OS};
+use std::mem;
+use {FALSE, TRUE};
+
+/// Cargo features of rtForth and whether they are enabled at compile time.
+pub const FEATURES: &[(&str, bool)] = &[
+    ("facility", cfg!(feature = "facility")),
+    ("file-access", cfg!(feature = "file-access")),
+    ("loader", cfg!(feature = "loader")),
+    ("units", cfg!(feature = "units")),
+    ("user-dirs", cfg!(feature = "user-dirs")),
+];
+
+/// Description of the build: crate version, target, profile and enabled
+/// cargo features.
+pub fn build_info() -> String {
+    let mut info = format!(
+        "rtForth {} {}-{} {}",
+        env!("CARGO_PKG_VERSION"),
+        ARCH,
+        OS,
+        if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        }
+    );
+    for &(name, enabled) in FEATURES {
+        if enabled {
+            info.push(' ');
+            info.push_str(name);
+        }
+    }
+    info
+}
+
+/// Stack effects of the primitives of `Environment`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("max-n", "( -- n )"),
+    ("max-u", "( -- u )"),
+    ("cell", "( -- u )"),
+    ("float", "( -- u )"),
+    ("/counted-string", "( -- n )"),
+    ("#s-stack", "( -- n )"),
+    ("#r-stack", "( -- n )"),
+    ("#f-stack", "( -- n )"),
+    ("floored", "( -- flag )"),
+    ("feature?", "( c-addr u -- flag )"),
+];
 
 pub trait Environment: Core {
     /// Add environment queries.
     fn add_environment(&mut self) {
         self.add_primitive("max-n", Environment::max_n);
         self.add_primitive("max-u", Environment::max_u);
+        self.add_primitive("cell", Environment::cell);
+        self.add_primitive("float", Environment::float);
+        self.add_primitive("/counted-string", Environment::slash_counted_string);
+        self.add_primitive("#s-stack", Environment::number_s_stack);
+        self.add_primitive("#r-stack", Environment::number_r_stack);
+        self.add_primitive("#f-stack", Environment::number_f_stack);
+        self.add_primitive("floored", Environment::p_floored);
+        self.add_text("version", env!("CARGO_PKG_VERSION"));
+        self.add_text("build-info", &build_info());
+        self.add_primitive("feature?", Environment::feature_q);
+        self.declare_effects(STACK_EFFECTS);
+    }
+
+    /// Add word `name ( -- c-addr u )` returning `text` kept in its data
+    /// field.
+    fn add_text(&mut self, name: &str, text: &str) {
+        self.add_primitive(name, Environment::p_text);
+        self.data_space().compile_str(text);
+        self.data_space().align();
+    }
+
+    /// Run-time: ( -- c-addr u )
+    ///
+    /// Text in the data field of the word, see `add_text`.
+    fn p_text(&mut self) {
+        let wp = self.state().word_pointer();
+        let dfa = self.wordlist()[wp].dfa();
+        let len = unsafe { self.data_space().get_usize(dfa) };
+        let addr = dfa + mem::size_of::<usize>();
+        self.s_stack().push2(addr as isize, len as isize);
+    }
+
+    /// Run-time: ( c-addr u -- flag )
+    ///
+    /// True if cargo feature named c-addr u, such as `file-access`, was
+    /// enabled when rtForth was compiled. `version ( -- c-addr u )` and
+    /// `build-info ( -- c-addr u )` describe the build further.
+    fn feature_q(&mut self) {
+        let (caddr, u) = self.s_stack().pop2();
+        let enabled = if 0 <= u
+            && self.data_space().start() <= caddr as usize
+            && caddr as usize + u as usize <= self.data_space().limit()
+        {
+            let name = unsafe {
+                self.data_space()
+                    .str_from_raw_parts(caddr as usize, u as usize)
+            };
+            FEATURES
+                .iter()
+                .any(|&(f, enabled)| enabled && f.eq_ignore_ascii_case(name))
+        } else {
+            false
+        };
+        self.s_stack().push(if enabled { -1 } else { 0 });
     }
 
     /// Run-time: ( -- n )
@@ -22,6 +126,60 @@ pub trait Environment: Core {
     fn max_u(&mut self) {
         self.s_stack().push(usize::max_value() as isize);
     }
+
+    /// Run-time: ( -- u )
+    ///
+    /// Size of a cell in address units
+    fn cell(&mut self) {
+        self.s_stack().push(mem::size_of::<isize>() as isize);
+    }
+
+    /// Run-time: ( -- u )
+    ///
+    /// Size of a floating-point number in address units
+    fn float(&mut self) {
+        self.s_stack().push(mem::size_of::<f64>() as isize);
+    }
+
+    /// Run-time: ( -- n )
+    ///
+    /// Maximum length of a counted string in characters
+    fn slash_counted_string(&mut self) {
+        self.s_stack().push(u8::MAX as isize);
+    }
+
+    /// Run-time: ( -- n )
+    ///
+    /// Usable depth of the data stack in cells
+    fn number_s_stack(&mut self) {
+        let limit = self.s_stack().limit();
+        self.s_stack().push(limit as isize);
+    }
+
+    /// Run-time: ( -- n )
+    ///
+    /// Usable depth of the return stack in cells
+    fn number_r_stack(&mut self) {
+        let limit = self.r_stack().limit();
+        self.s_stack().push(limit as isize);
+    }
+
+    /// Run-time: ( -- n )
+    ///
+    /// Usable depth of the floating-point stack in floating-point numbers
+    fn number_f_stack(&mut self) {
+        let limit = self.f_stack().limit();
+        self.s_stack().push(limit as isize);
+    }
+
+    /// Run-time: ( -- flag )
+    ///
+    /// True if `/`, `mod` and `/mod` use floored division, false if
+    /// symmetric.
+    fn p_floored(&mut self) {
+        let floored = self.wordlist().floored();
+        self.s_stack().push(if floored { TRUE } else { FALSE });
+    }
 }
 
 #[cfg(test)]
@@ -50,4 +208,39 @@ mod tests {
             t => assert_eq!(t, 0),
         }
     }
+
+    #[test]
+    fn test_sizes() {
+        let vm = &mut VM::new();
+        vm.s_stack().set_limit(32);
+        vm.set_source("cell  1 cells =  float  /counted-string  #s-stack  #r-stack");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [-1, 8, 255, 32, 64]);
+    }
+
+    #[test]
+    fn test_version() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "
+            version type  space  build-info type
+            : f? ( c-addr u -- )   feature? . ;
+            : fs   s\" file-access\" f?  s\" FILE-ACCESS\" f?  s\" jit\" f? ;  fs
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        let file_access = if cfg!(feature = "file-access") { -1 } else { 0 };
+        assert_eq!(
+            vm.output_buffer().take().unwrap(),
+            format!(
+                "{} {}{} {} 0 ",
+                env!("CARGO_PKG_VERSION"),
+                super::build_info(),
+                file_access,
+                file_access
+            )
+        );
+    }
 }
@@ -11,6 +11,40 @@ impl From<Exception> for isize {
     }
 }
 
+impl Exception {
+    /// Exception of code `n`, `None` if 0 or out of the range of codes.
+    pub fn from_code(n: isize) -> Option<Exception> {
+        if n == 0 || n < i16::MIN as isize || n > i16::MAX as isize {
+            None
+        } else {
+            Some(Exception(n as i16))
+        }
+    }
+
+    /// The `i`th exception allocated by `new-exception`, `None` if out of
+    /// the range of codes.
+    pub fn user(i: usize) -> Option<Exception> {
+        USER_EXCEPTIONS
+            .checked_sub(i as isize)
+            .and_then(Exception::from_code)
+    }
+
+    /// Index of the exception allocated by `new-exception`, `None` if not
+    /// in the user range.
+    pub fn user_index(self) -> Option<usize> {
+        let n = isize::from(self);
+        if n <= USER_EXCEPTIONS {
+            Some((USER_EXCEPTIONS - n) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// Code of the first exception allocated by `new-exception`. ANS Forth
+/// reserves -1 to -255 and systems such as rtForth -256 to -4095.
+pub const USER_EXCEPTIONS: isize = -4096;
+
 /// = -1, ANS Forth
 pub const ABORT: Exception = Exception(-1);
 /// = -2, ANS Forth
@@ -127,6 +161,164 @@ pub const QUIT: Exception = Exception(-56);
 pub const EXCEPTION_IN_SENDING_OR_RECEIVING_A_CHARACTER: Exception = Exception(-57);
 /// = -58, ANS Forth
 pub const BRACKET_IF_ELSE_OR_THEN_EXCEPTION: Exception = Exception(-58);
+/// = -59, Forth 2012
+pub const ALLOCATE: Exception = Exception(-59);
+/// = -60, Forth 2012
+pub const FREE: Exception = Exception(-60);
+/// = -61, Forth 2012
+pub const RESIZE: Exception = Exception(-61);
+/// = -256, rtForth
+pub const WATCHDOG_EXPIRED: Exception = Exception(-256);
+/// = -257, rtForth
+pub const DEADLOCK: Exception = Exception(-257);
+/// = -258, rtForth
+pub const TIMEOUT: Exception = Exception(-258);
+/// = -259, rtForth
+pub const PERMISSION_DENIED: Exception = Exception(-259);
+/// = -260, rtForth
+pub const WOULD_BLOCK: Exception = Exception(-260);
+/// = -261, rtForth
+pub const NESTING_LIMIT: Exception = Exception(-261);
+/// = -262, rtForth
+pub const FILE_IO_TIMEOUT: Exception = Exception(-262);
+/// = -263, rtForth
+pub const INVALID_XT: Exception = Exception(-263);
+/// = -264, rtForth
+pub const PRIVILEGED_WORD: Exception = Exception(-264);
+/// = -265, rtForth
+pub const UNSUPPORTED_FORMAT: Exception = Exception(-265);
+
+/// Forth names of the exceptions, defined as constants by `Core::add_core`.
+/// Those of words such as `abort` and `allocate` end with `-exception`.
+pub const NAMES: &[(&str, Exception)] = &[
+    ("abort-exception", ABORT),
+    ("abort-quote", ABORT_QUOTE),
+    ("stack-overflow", STACK_OVERFLOW),
+    ("stack-underflow", STACK_UNDERFLOW),
+    ("return-stack-overflow", RETURN_STACK_OVERFLOW),
+    ("return-stack-underflow", RETURN_STACK_UNDERFLOW),
+    ("do-loop-nested-too-deeply", DO_LOOP_NESTED_TOO_DEEPLY),
+    ("dictionary-overflow", DICTIONARY_OVERFLOW),
+    ("invalid-memory-address", INVALID_MEMORY_ADDRESS),
+    ("division-by-zero", DIVISION_BY_ZERO),
+    ("result-out-of-range", RESULT_OUT_OF_RANGE),
+    ("argument-type-mismatch", ARGUMENT_TYPE_MISMATCH),
+    ("undefined-word", UNDEFINED_WORD),
+    (
+        "interpreting-a-compile-only-word",
+        INTERPRETING_A_COMPILE_ONLY_WORD,
+    ),
+    ("invalid-forget", INVALID_FORGET),
+    (
+        "attempt-to-use-zero-length-string",
+        ATTEMPT_TO_USE_ZERO_LENGTH_STRING,
+    ),
+    (
+        "pictured-numeric-output-string-overflow",
+        PICTURED_NUMERIC_OUTPUT_STRING_OVERFLOW,
+    ),
+    ("parsed-string-overflow", PARSED_STRING_OVERFLOW),
+    ("definition-name-too-long", DEFINITION_NAME_TOO_LONG),
+    (
+        "write-to-a-read-only-location",
+        WRITE_TO_A_READ_ONLY_LOCATION,
+    ),
+    ("unsupported-operation", UNSUPPORTED_OPERATION),
+    ("control-structure-mismatch", CONTROL_STRUCTURE_MISMATCH),
+    ("address-alignment-exception", ADDRESS_ALIGNMENT_EXCEPTION),
+    ("invalid-numeric-argument", INVALID_NUMERIC_ARGUMENT),
+    ("return-stack-imbalance", RETURN_STACK_IMBALANCE),
+    ("loop-parameters-unavailable", LOOP_PARAMETERS_UNAVAILABLE),
+    ("invalid-recursion", INVALID_RECURSION),
+    ("user-interrupt", USER_INTERRUPT),
+    ("compiler-nesting", COMPILER_NESTING),
+    ("obsolescent-feature", OBSOLESCENT_FEATURE),
+    (
+        "to-body-used-on-non-created-definition",
+        TO_BODY_USED_ON_NON_CREATED_DEFINITION,
+    ),
+    ("invalid-name-argument", INVALID_NAME_ARGUMENT),
+    ("block-read-exception", BLOCK_READ_EXCEPTION),
+    ("block-write-exception", BLOCK_WRITE_EXCEPTION),
+    ("invalid-block-number", INVALID_BLOCK_NUMBER),
+    ("invalid-file-position", INVALID_FILE_POSITION),
+    ("file-io-exception", FILE_IO_EXCEPTION),
+    ("non-existent-file", NON_EXISTENT_FILE),
+    ("unexpected-end-of-file", UNEXPECTED_END_OF_FILE),
+    (
+        "invalid-base-for-floating-point-conversion",
+        INVALID_BASE_FOR_FLOATING_POINT_CONVERSION,
+    ),
+    ("loss-of-precision", LOSS_OF_PRECISION),
+    (
+        "floating-point-divided-by-zero",
+        FLOATING_POINT_DIVIDED_BY_ZERO,
+    ),
+    (
+        "floating-point-result-out-of-range",
+        FLOATING_POINT_RESULT_OUT_OF_RANGE,
+    ),
+    (
+        "floating-point-stack-overflow",
+        FLOATING_POINT_STACK_OVERFLOW,
+    ),
+    (
+        "floating-point-stack-underflow",
+        FLOATING_POINT_STACK_UNDERFLOW,
+    ),
+    (
+        "floating-point-invalid-argument",
+        FLOATING_POINT_INVALID_ARGUMENT,
+    ),
+    (
+        "compilation-word-list-deleted",
+        COMPILATION_WORD_LIST_DELETED,
+    ),
+    ("invalid-postpone", INVALID_POSTPONE),
+    ("search-order-overflow", SEARCH_ORDER_OVERFLOW),
+    ("search-order-underflow", SEARCH_ORDER_UNDERFLOW),
+    (
+        "compilation-word-list-changed",
+        COMPILATION_WORD_LIST_CHANGED,
+    ),
+    ("control-flow-stack-overflow", CONTROL_FLOW_STACK_OVERFLOW),
+    ("exception-stack-overflow", EXCEPTION_STACK_OVERFLOW),
+    ("floating-point-underflow", FLOATING_POINT_UNDERFLOW),
+    (
+        "floating-point-unidentified-fault",
+        FLOATING_POINT_UNIDENTIFIED_FAULT,
+    ),
+    ("quit-exception", QUIT),
+    (
+        "exception-in-sending-or-receiving-a-character",
+        EXCEPTION_IN_SENDING_OR_RECEIVING_A_CHARACTER,
+    ),
+    (
+        "bracket-if-else-or-then-exception",
+        BRACKET_IF_ELSE_OR_THEN_EXCEPTION,
+    ),
+    ("allocate-exception", ALLOCATE),
+    ("free-exception", FREE),
+    ("resize-exception", RESIZE),
+    ("watchdog-expired", WATCHDOG_EXPIRED),
+    ("deadlock", DEADLOCK),
+    ("timeout", TIMEOUT),
+    ("permission-denied", PERMISSION_DENIED),
+    ("would-block", WOULD_BLOCK),
+    ("nesting-limit", NESTING_LIMIT),
+    ("file-io-timeout", FILE_IO_TIMEOUT),
+    ("invalid-xt", INVALID_XT),
+    ("privileged-word", PRIVILEGED_WORD),
+    ("unsupported-format", UNSUPPORTED_FORMAT),
+];
+
+/// Forth name of the exception with code `n`, see `NAMES`.
+pub fn name(n: isize) -> Option<&'static str> {
+    NAMES
+        .iter()
+        .find(|&&(_, e)| isize::from(e) == n)
+        .map(|&(name, _)| name)
+}
 
 /// Description of the exception
 pub fn description(e: Exception) -> &'static str {
@@ -191,6 +383,19 @@ pub fn description(e: Exception) -> &'static str {
             "Exception in sending or receiving a character"
         }
         BRACKET_IF_ELSE_OR_THEN_EXCEPTION => "[IF],[ELSE],[THEN] exception",
+        ALLOCATE => "ALLOCATE",
+        FREE => "FREE",
+        RESIZE => "RESIZE",
+        WATCHDOG_EXPIRED => "Watchdog expired",
+        DEADLOCK => "Deadlock",
+        TIMEOUT => "Timeout",
+        PERMISSION_DENIED => "Permission denied",
+        WOULD_BLOCK => "Operation would block",
+        NESTING_LIMIT => "Nesting limit exceeded",
+        FILE_IO_TIMEOUT => "File I/O timeout",
+        INVALID_XT => "Invalid execution token",
+        PRIVILEGED_WORD => "Privileged word",
+        UNSUPPORTED_FORMAT => "Unsupported file format version",
         _ => "",
     }
 }
@@ -1,25 +1,412 @@
 //! Facility word set
+//!
+//! Raw terminal input is provided by the host through `Terminal`, see
+//! `Wordlist::set_terminal`. Keys are encoded as by `ekey` of Forth 2012:
+//! characters by their code points, other keys by the constants `k-left`
+//! ... `k-f12`, each or-ed with the masks `k-shift-mask`, `k-ctrl-mask` and
+//! `k-alt-mask` of the modifiers held.
+//!
+//! A change of the terminal size is delivered by `key-event?` as key
+//! `k-resize`, upon which a full-screen program reads the new size with
+//! `term-size` and lays out again.
+//!
+//! `beep` and `alarm` alert the operator through the `Annunciator` of the
+//! host, see `Alarms::set_annunciator`, or by the console bell if there
+//! is none. Alarms are also logged, listed by `alarms.`.
 
 use core::Core;
+use exception::{INVALID_MEMORY_ADDRESS, UNSUPPORTED_OPERATION};
+use memory::{Memory, Wait};
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// Key decoded by a `Terminal`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    Backspace,
+    Enter,
+    Tab,
+    Escape,
+    /// Function key `F1` to `F12`.
+    F(u8),
+    /// Terminal resized, see `Terminal::size`.
+    Resize,
+}
+
+/// Key pressed with modifiers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+/// Code of `k-left`, the first key which is not a character. The codes of
+/// `k-right` ... `k-f12` follow.
+pub const K_LEFT: isize = 0x11_0000;
+pub const K_SHIFT_MASK: isize = 0x100_0000;
+pub const K_CTRL_MASK: isize = 0x200_0000;
+pub const K_ALT_MASK: isize = 0x400_0000;
+
+/// Names of the keys which are not characters, in the order of their codes.
+const KEY_NAMES: [&str; 23] = [
+    "k-left", "k-right", "k-up", "k-down", "k-home", "k-end", "k-prior", "k-next", "k-insert",
+    "k-delete", "k-f1", "k-f2", "k-f3", "k-f4", "k-f5", "k-f6", "k-f7", "k-f8", "k-f9", "k-f10",
+    "k-f11", "k-f12", "k-resize",
+];
+
+impl KeyEvent {
+    /// Code of the event as returned by `key-event?`.
+    pub fn code(&self) -> isize {
+        let key = match self.key {
+            Key::Char(c) => c as isize,
+            Key::Backspace => 8,
+            Key::Tab => 9,
+            Key::Enter => 13,
+            Key::Escape => 27,
+            Key::Left => K_LEFT,
+            Key::Right => K_LEFT + 1,
+            Key::Up => K_LEFT + 2,
+            Key::Down => K_LEFT + 3,
+            Key::Home => K_LEFT + 4,
+            Key::End => K_LEFT + 5,
+            Key::PageUp => K_LEFT + 6,
+            Key::PageDown => K_LEFT + 7,
+            Key::Insert => K_LEFT + 8,
+            Key::Delete => K_LEFT + 9,
+            Key::F(n) => K_LEFT + 9 + n as isize,
+            Key::Resize => K_LEFT + 22,
+        };
+        let mut code = key;
+        if self.shift {
+            code |= K_SHIFT_MASK;
+        }
+        if self.ctrl {
+            code |= K_CTRL_MASK;
+        }
+        if self.alt {
+            code |= K_ALT_MASK;
+        }
+        code
+    }
+}
+
+/// Terminal of the host, such as a console in raw mode.
+pub trait Terminal {
+    /// Turn raw mode on or off, false if not supported.
+    fn set_raw_mode(&mut self, raw: bool) -> bool;
+
+    /// Next key event pressed, `None` if there is none pending. Must not
+    /// block. A change of size is reported as `Key::Resize`.
+    fn poll_key(&mut self) -> Option<KeyEvent>;
+
+    /// Size of the terminal in columns and rows, `None` if unknown.
+    fn size(&mut self) -> Option<(usize, usize)> {
+        None
+    }
+}
+
+/// Number of alarms kept by `AlarmLog`.
+pub const ALARM_LOG_CAPACITY: usize = 64;
+
+/// Alarm raised by `alarm`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alarm {
+    /// Monotonic time in nanoseconds.
+    pub timestamp: u64,
+    /// Severity, the higher the more severe.
+    pub level: isize,
+    pub message: String,
+}
+
+/// Latest `ALARM_LOG_CAPACITY` alarms, the oldest first.
+pub struct AlarmLog {
+    alarms: VecDeque<Alarm>,
+}
+
+impl AlarmLog {
+    pub fn new() -> AlarmLog {
+        AlarmLog {
+            alarms: VecDeque::new(),
+        }
+    }
+
+    /// Record `alarm`, dropping the oldest one if the log is full.
+    pub fn record(&mut self, alarm: Alarm) {
+        if self.alarms.len() == ALARM_LOG_CAPACITY {
+            self.alarms.pop_front();
+        }
+        self.alarms.push_back(alarm);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Alarm> {
+        self.alarms.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.alarms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.alarms.is_empty()
+    }
+}
+
+impl Default for AlarmLog {
+    fn default() -> Self {
+        AlarmLog::new()
+    }
+}
+
+/// Alerting of the operator provided by the host, such as a sound or a
+/// banner of a GUI.
+pub trait Annunciator {
+    /// Sound a short beep.
+    fn beep(&mut self);
+
+    /// Announce `message` with severity `level`.
+    fn announce(&mut self, level: isize, message: &str);
+}
+
+/// Annunciator and log of `beep` and `alarm`.
+///
+/// Embedders keep one in the virtual machine and return it from
+/// `Facility::alarms`.
+#[derive(Default)]
+pub struct Alarms {
+    annunciator: Option<Box<dyn Annunciator>>,
+    log: AlarmLog,
+}
+
+impl Alarms {
+    pub fn new() -> Alarms {
+        Alarms::default()
+    }
+
+    /// Provide the annunciator used by `beep` and `alarm` instead of the
+    /// console bell.
+    pub fn set_annunciator(&mut self, annunciator: Box<dyn Annunciator>) {
+        self.annunciator = Some(annunciator);
+    }
+
+    fn annunciator_mut(&mut self) -> Option<&mut Box<dyn Annunciator>> {
+        self.annunciator.as_mut()
+    }
+
+    /// Alarms raised by `alarm`.
+    pub fn log(&self) -> &AlarmLog {
+        &self.log
+    }
+
+    pub fn log_mut(&mut self) -> &mut AlarmLog {
+        &mut self.log
+    }
+}
+
+/// Stack effects of the primitives of `Facility`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("mtime", "( -- milli-seconds )"),
+    ("utime", "( -- micro-seconds )"),
+    ("mticks", "( -- n )"),
+    ("now", "( -- n )"),
+    ("(wait-mtime)", "( t -- )"),
+    ("raw-mode-on", "( -- )"),
+    ("raw-mode-off", "( -- )"),
+    ("key-event?", "( -- k flag )"),
+    ("term-size", "( -- cols rows )"),
+    ("beep", "( -- )"),
+    ("alarm", "( level c-addr u -- )"),
+    ("alarms.", "( -- )"),
+];
 
 pub trait Facility: Core {
+    /// Annunciator and log of alarms.
+    fn alarms(&self) -> &Alarms;
+    fn alarms_mut(&mut self) -> &mut Alarms;
+
     /// Run-time: ( --  )
     ///
     /// Add facility primitives.
     fn add_facility(&mut self) {
         self.add_primitive("mtime", Facility::mtime);
         self.add_primitive("utime", Facility::utime);
+        self.add_primitive("mticks", Facility::mticks);
+        self.add_primitive("now", Facility::now);
+        self.add_primitive("(wait-mtime)", Facility::wait_mtime);
+        self.add_primitive("raw-mode-on", Facility::raw_mode_on);
+        self.add_primitive("raw-mode-off", Facility::raw_mode_off);
+        self.add_primitive("key-event?", Facility::key_event_q);
+        self.add_primitive("term-size", Facility::term_size);
+        self.add_primitive("beep", Facility::beep);
+        self.add_primitive("alarm", Facility::alarm);
+        self.add_primitive("alarms.", Facility::dot_alarms);
+        for (i, name) in KEY_NAMES.iter().enumerate() {
+            self.add_key_constant(name, K_LEFT + i as isize);
+        }
+        self.add_key_constant("k-shift-mask", K_SHIFT_MASK);
+        self.add_key_constant("k-ctrl-mask", K_CTRL_MASK);
+        self.add_key_constant("k-alt-mask", K_ALT_MASK);
+        self.declare_effects(STACK_EFFECTS);
+    }
+
+    fn add_key_constant(&mut self, name: &str, code: isize) {
+        self.add_primitive_with(name, Core::p_const, Core::compile_const);
+        self.data_space().compile_isize(code);
+    }
+
+    /// Let the host terminal deliver keys immediately without echo.
+    /// `raw-mode-on ( -- )`
+    ///
+    /// Aborts with `UNSUPPORTED_OPERATION` if the host provides no
+    /// terminal or no raw mode.
+    fn raw_mode_on(&mut self) {
+        self.set_raw_mode(true);
+    }
+
+    /// Restore line input of the host terminal. `raw-mode-off ( -- )`
+    fn raw_mode_off(&mut self) {
+        self.set_raw_mode(false);
+    }
+
+    fn set_raw_mode(&mut self, raw: bool) {
+        let done = match self.wordlist_mut().terminal_mut() {
+            Some(terminal) => terminal.set_raw_mode(raw),
+            None => false,
+        };
+        if !done {
+            self.abort_with(UNSUPPORTED_OPERATION);
+        }
     }
 
-    /// System time in milli-seconds. `mtime ( -- milli-seconds )`
+    /// Next key event from the host terminal, `k` 0 and `flag` false if
+    /// none is pending. `key-event? ( -- k flag )`
+    ///
+    /// Always false if the host provides no terminal.
+    fn key_event_q(&mut self) {
+        let event = match self.wordlist_mut().terminal_mut() {
+            Some(terminal) => terminal.poll_key(),
+            None => None,
+        };
+        match event {
+            Some(event) => self.s_stack().push2(event.code(), -1),
+            None => self.s_stack().push2(0, 0),
+        }
+    }
+
+    /// Size of the host terminal in columns and rows.
+    /// `term-size ( -- cols rows )`
+    ///
+    /// Aborts with `UNSUPPORTED_OPERATION` if the host provides no
+    /// terminal or cannot tell its size.
+    fn term_size(&mut self) {
+        let size = match self.wordlist_mut().terminal_mut() {
+            Some(terminal) => terminal.size(),
+            None => None,
+        };
+        match size {
+            Some((cols, rows)) => self.s_stack().push2(cols as isize, rows as isize),
+            None => self.abort_with(UNSUPPORTED_OPERATION),
+        }
+    }
+
+    /// Sound the annunciator of the host, or the console bell.
+    /// `beep ( -- )`
+    fn beep(&mut self) {
+        match self.alarms_mut().annunciator_mut() {
+            Some(annunciator) => annunciator.beep(),
+            None => {
+                if let Some(buf) = self.output_buffer().as_mut() {
+                    buf.push('\x07');
+                }
+            }
+        }
+    }
+
+    /// Log alarm `c-addr u` of severity `level` and announce it through
+    /// the annunciator of the host, or print it after the console bell.
+    /// `alarm ( level c-addr u -- )`
+    fn alarm(&mut self) {
+        let (level, caddr, u) = self.s_stack().pop3();
+        if caddr < 0 || u < 0 {
+            return self.abort_with(INVALID_MEMORY_ADDRESS);
+        }
+        let message = String::from(unsafe {
+            self.data_space()
+                .str_from_raw_parts(caddr as usize, u as usize)
+        });
+        let timestamp = self.monotonic_ns();
+        match self.alarms_mut().annunciator_mut() {
+            Some(annunciator) => annunciator.announce(level, &message),
+            None => {
+                if let Some(buf) = self.output_buffer().as_mut() {
+                    writeln!(buf, "\x07ALARM {}: {}", level, message).unwrap();
+                }
+            }
+        }
+        self.alarms_mut().log_mut().record(Alarm {
+            timestamp,
+            level,
+            message,
+        });
+    }
+
+    /// Print the logged alarms, the oldest first, one per line with the
+    /// time in milli-seconds and the level. `alarms. ( -- )`
+    fn dot_alarms(&mut self) {
+        if let Some(mut buf) = self.output_buffer().take() {
+            for a in self.alarms().log().iter() {
+                writeln!(buf, "{} {} {}", a.timestamp / 1_000_000, a.level, a.message).unwrap();
+            }
+            self.set_output_buffer(buf);
+        }
+    }
+
+    /// Monotonic time in milli-seconds. `mtime ( -- milli-seconds )`
     fn mtime(&mut self) {
-        let now = self.system_time_ns() / 1_000_000;
+        let now = self.monotonic_ns() / 1_000_000;
         self.s_stack().push(now as isize);
     }
 
-    /// System time in micro-seconds. `utime ( -- micro-seconds )`
+    /// Monotonic time in micro-seconds. `utime ( -- micro-seconds )`
     fn utime(&mut self) {
-        let now = self.system_time_ns() / 1_000;
+        let now = self.monotonic_ns() / 1_000;
+        self.s_stack().push(now as isize);
+    }
+
+    /// Monotonic time in nanoseconds, for measuring and scheduling.
+    /// `mticks ( -- n )`
+    fn mticks(&mut self) {
+        let now = self.monotonic_ns();
         self.s_stack().push(now as isize);
     }
+
+    /// Wall-clock time in nanoseconds since the Unix epoch, for
+    /// timestamps. `now ( -- n )`
+    fn now(&mut self) {
+        let now = self.wallclock_ns();
+        self.s_stack().push(now as isize);
+    }
+
+    /// Let the current task wait until the system time in milli-seconds
+    /// reaches `t` until resumed by `pause`. Used by `ms`.
+    /// `(wait-mtime) ( t -- )`
+    fn wait_mtime(&mut self) {
+        let t = self.s_stack().pop() as u64 * 1_000_000;
+        let current = self.current_task();
+        self.data_space()
+            .system_variables_mut()
+            .set_wait(current, Wait::Time(t));
+    }
 }
@@ -1,16 +1,265 @@
 //! File access word set
 
+use core::Core;
+#[cfg(feature = "user-dirs")]
+use directories::BaseDirs;
 use exception::{
-    FILE_IO_EXCEPTION, INVALID_MEMORY_ADDRESS, INVALID_NUMERIC_ARGUMENT, RESULT_OUT_OF_RANGE,
+    Exception, DICTIONARY_OVERFLOW, FILE_IO_EXCEPTION, FILE_IO_TIMEOUT, INVALID_MEMORY_ADDRESS,
+    INVALID_NUMERIC_ARGUMENT, NON_EXISTENT_FILE, PARSED_STRING_OVERFLOW, PERMISSION_DENIED,
+    RESULT_OUT_OF_RANGE, UNDEFINED_WORD, UNEXPECTED_END_OF_FILE, UNSUPPORTED_FORMAT, WOULD_BLOCK,
 };
-use std::fs::{self, OpenOptions};
+#[cfg(feature = "loader")]
+use loader::Source;
+use memory::{DataSpace, Memory};
+use std::convert::TryFrom;
+use std::fmt::Write as FmtWrite;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind};
 use std::io::{Read, Seek, SeekFrom, Write};
-use Core;
-use Memory;
+use std::mem;
+use std::path::Path;
+use tools::{dictionary_digest, format_digest, format_manifest, ControlFlowGraph};
+use NUM_TASKS;
 
 const PATH_NAME_MAX_LEN: usize = 256;
 
+/// Number of transient buffers used in rotation by `path"` and `path+`.
+const PATH_BUFFERS: usize = 2;
+
+/// Offset of the first transient path buffer from `here`, beyond `pad`.
+const PATH_BUFFER_OFFSET: usize = 1024;
+
+/// Bit of file access method set by `bin`.
+const BIN: isize = 4;
+
+/// Number of cells in the retention region of `retain` variables.
+pub const RETAIN_CELLS: usize = 256;
+
+/// First bytes of files written by `save-retained`.
+const RETAIN_MAGIC: &[u8] = b"RTFR";
+
+/// Version of the format of files written by `save-retained`, the byte
+/// after `RETAIN_MAGIC`.
+const RETAIN_VERSION: u8 = 1;
+
+/// Line terminator written by `write-line` in text mode.
+#[cfg(windows)]
+const TEXT_LINE_TERMINATOR: &[u8] = b"\r\n";
+#[cfg(not(windows))]
+const TEXT_LINE_TERMINATOR: &[u8] = b"\n";
+
+/// Files and input sources owned by a task.
+///
+/// Embedders keep one in each task and return it from
+/// `FileAccess::file_storage`.
+#[derive(Default)]
+pub struct FileStorage {
+    /// Opened files, file id `i` at index `i - 1`.
+    pub files: Vec<Option<File>>,
+    /// Input sources, source id `i` at index `i - 1`.
+    #[cfg(feature = "loader")]
+    pub sources: Vec<Option<Source>>,
+    /// Input buffers of the input sources.
+    #[cfg(feature = "loader")]
+    pub lines: Vec<Option<String>>,
+    /// Transient buffer to be used next by `path"` and `path+`.
+    path_buffer: usize,
+    /// Path and mode of the opened files, at the index of `files`.
+    info: Vec<FileInfo>,
+    /// Last error of an I/O operation, see `io-error`.
+    last_io_error: Option<io::Error>,
+}
+
+/// Path and mode of an opened file.
+#[derive(Default, Clone)]
+struct FileInfo {
+    /// Path as given when opened, empty if unknown.
+    path: String,
+    binary: bool,
+}
+
+/// ior of an I/O error of kind `kind`, `FILE_IO_EXCEPTION` for the kinds
+/// without a code of their own.
+pub fn io_ior(kind: ErrorKind) -> Exception {
+    match kind {
+        ErrorKind::NotFound => NON_EXISTENT_FILE,
+        ErrorKind::PermissionDenied => PERMISSION_DENIED,
+        ErrorKind::WouldBlock => WOULD_BLOCK,
+        ErrorKind::TimedOut => FILE_IO_TIMEOUT,
+        _ => FILE_IO_EXCEPTION,
+    }
+}
+
+/// CRC-32 (IEEE 802.3) of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+impl FileStorage {
+    pub fn new() -> FileStorage {
+        FileStorage::default()
+    }
+
+    /// True if the file at index `i` of `files` was opened with `bin`.
+    pub fn is_binary(&self, i: usize) -> bool {
+        match self.info.get(i) {
+            Some(info) => info.binary,
+            None => false,
+        }
+    }
+
+    /// Path of the file at index `i` of `files` as given when opened, if
+    /// known.
+    pub fn path(&self, i: usize) -> Option<&str> {
+        match self.info.get(i) {
+            Some(info) if !info.path.is_empty() => Some(&info.path),
+            _ => None,
+        }
+    }
+
+    /// Last error of an I/O operation of the task.
+    pub fn last_io_error(&self) -> Option<&io::Error> {
+        self.last_io_error.as_ref()
+    }
+
+    /// Number of opened files and input sources.
+    pub fn open_count(&self) -> usize {
+        let files = self.files.iter().filter(|f| f.is_some()).count();
+        #[cfg(feature = "loader")]
+        let files = files + self.sources.iter().filter(|s| s.is_some()).count();
+        files
+    }
+
+    /// Put `file` in the first free slot of `files` and return its file id.
+    fn insert(&mut self, file: File, path: &str, binary: bool) -> usize {
+        let i = match self.files.iter().position(|x| x.is_none()) {
+            Some(i) => i,
+            None => {
+                self.files.push(None);
+                self.files.len() - 1
+            }
+        };
+        self.files[i] = Some(file);
+        if self.info.len() <= i {
+            self.info.resize(i + 1, FileInfo::default());
+        }
+        self.info[i] = FileInfo {
+            path: path.to_string(),
+            binary,
+        };
+        i + 1
+    }
+}
+
+/// File mirroring the input lines of the host and the flushed output,
+/// opened by `transcript`.
+///
+/// Embedders keep one in the virtual machine and return it from
+/// `Core::transcription`.
+#[derive(Default)]
+pub struct Transcript {
+    file: Option<File>,
+}
+
+impl Transcript {
+    pub fn new() -> Transcript {
+        Transcript::default()
+    }
+
+    /// True if a transcript file is open.
+    pub fn is_open(&self) -> bool {
+        self.file.is_some()
+    }
+
+    fn set_file(&mut self, file: Option<File>) {
+        self.file = file;
+    }
+
+    /// Append `bytes` to the transcript file if any. A failed write closes
+    /// the transcript.
+    pub fn write(&mut self, bytes: &[u8]) {
+        let failed = match self.file {
+            Some(ref mut file) => file.write_all(bytes).is_err(),
+            None => false,
+        };
+        if failed {
+            self.file = None;
+        }
+    }
+}
+
+/// Stack effects of the primitives of `FileAccess`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("file-size", "( fileid -- ud ior )"),
+    ("file-position", "( fileid -- ud ior )"),
+    ("close-file", "( fileid -- ior )"),
+    ("create-file", "( c-addr u fam -- fileid ior )"),
+    ("delete-file", "( c-addr u -- ior )"),
+    ("open-file", "( c-addr u fam -- fileid ior )"),
+    ("read-file", "( c-addr u1 fileid -- u2 ior )"),
+    ("write-file", "( c-addr u fileid -- ior )"),
+    ("read-line", "( c-addr u1 fileid -- u2 flag ior )"),
+    ("write-line", "( c-addr u fileid -- ior )"),
+    ("resize-file", "( ud fileid -- ior )"),
+    ("reposition-file", "( ud fileid -- ior )"),
+    ("(close-files)", "( -- )"),
+    ("trace-export", "( c-addr u -- ior )"),
+    ("transcript", "( c-addr u -- ior )"),
+    ("transcript-off", "( -- )"),
+    ("cfg-export-file", "( c-addr u \"name\" -- ior )"),
+    (".files", "( -- )"),
+    ("io-error", "( -- n )"),
+    (".io-error", "( -- )"),
+    (".sources", "( -- )"),
+    ("max-files", "( -- n )"),
+    ("save-digest", "( c-addr u -- ior )"),
+    ("build-manifest", "( c-addr u -- ior )"),
+    ("retain", "( \"name\" -- )"),
+    ("save-retained", "( c-addr u -- ior )"),
+    ("load-retained", "( c-addr u -- ior )"),
+    ("retain-file", "( c-addr u -- )"),
+    ("(save-retain-file)", "( -- )"),
+    ("path\"", "( \"ccc<quote>\" -- )"),
+    ("path+", "( c-addr1 u1 c-addr2 u2 -- c-addr3 u3 )"),
+    ("home-path", "( -- c-addr u )"),
+    ("config-path", "( -- c-addr u )"),
+];
+
 pub trait FileAccess: Core {
+    /// Files and input sources of the current task.
+    fn file_storage(&self) -> &FileStorage;
+    fn file_storage_mut(&mut self) -> &mut FileStorage;
+    /// Files and input sources of task `i`.
+    ///
+    /// None if there is no task `i`.
+    fn task_file_storage(&self, i: usize) -> Option<&FileStorage>;
+
+    /// Files opened by the current task, file id `i` at index `i - 1`.
+    fn files(&self) -> &Vec<Option<File>> {
+        &self.file_storage().files
+    }
+    fn files_mut(&mut self) -> &mut Vec<Option<File>> {
+        &mut self.file_storage_mut().files
+    }
+
+    /// Record `e` as the last I/O error of the task and return its ior.
+    fn io_exception(&mut self, e: io::Error) -> Exception {
+        let ior = io_ior(e.kind());
+        self.file_storage_mut().last_io_error = Some(e);
+        ior
+    }
+
     fn add_file_access(&mut self) {
         self.add_primitive("file-size", FileAccess::file_size);
         self.add_primitive("file-position", FileAccess::file_position);
@@ -20,8 +269,57 @@ pub trait FileAccess: Core {
         self.add_primitive("open-file", FileAccess::open_file);
         self.add_primitive("read-file", FileAccess::read_file);
         self.add_primitive("write-file", FileAccess::write_file);
+        self.add_primitive("read-line", FileAccess::read_line);
+        self.add_primitive("write-line", FileAccess::write_line);
         self.add_primitive("resize-file", FileAccess::resize_file);
         self.add_primitive("reposition-file", FileAccess::reposition_file);
+        self.add_primitive("(close-files)", FileAccess::close_files);
+        self.add_primitive("trace-export", FileAccess::trace_export);
+        self.add_primitive("transcript", FileAccess::transcript);
+        self.add_primitive("transcript-off", FileAccess::transcript_off);
+        self.add_primitive("cfg-export-file", FileAccess::cfg_export_file);
+        self.add_primitive(".files", FileAccess::dot_files);
+        self.add_primitive("io-error", FileAccess::io_error);
+        self.add_primitive(".io-error", FileAccess::dot_io_error);
+        #[cfg(feature = "loader")]
+        self.add_primitive(".sources", FileAccess::dot_sources);
+        self.add_primitive("max-files", FileAccess::max_files);
+        self.add_primitive("save-digest", FileAccess::save_digest);
+        self.add_primitive("build-manifest", FileAccess::build_manifest);
+        self.add_primitive("retain", FileAccess::retain);
+        self.add_primitive("save-retained", FileAccess::save_retained);
+        self.add_primitive("load-retained", FileAccess::load_retained);
+        self.add_primitive("retain-file", FileAccess::retain_file);
+        self.add_primitive("(save-retain-file)", FileAccess::save_retain_file);
+        self.add_immediate("path\"", FileAccess::path_quote);
+        self.add_primitive("path+", FileAccess::path_plus);
+        #[cfg(feature = "user-dirs")]
+        self.add_primitive("home-path", FileAccess::home_path);
+        #[cfg(feature = "user-dirs")]
+        self.add_primitive("config-path", FileAccess::config_path);
+        self.references().idx_s_quote = self.find("_s\"").expect("_s\" undefined");
+        let close_files = self.find("(close-files)").expect("(close-files) undefined");
+        self.wordlist_mut().at_exit(close_files);
+        self.data_space().align();
+        let region = self.data_space().here();
+        self.data_space()
+            .allot((RETAIN_CELLS * mem::size_of::<isize>()) as isize);
+        self.wordlist_mut().set_retain_region(region);
+        self.declare_effects(STACK_EFFECTS);
+    }
+
+    /// ( -- )
+    ///
+    /// Close files of all tasks. Executed by `bye`.
+    fn close_files(&mut self) {
+        let current_task = self.current_task();
+        for i in 0..NUM_TASKS {
+            self.set_current_task(i);
+            for file in self.files_mut().iter_mut() {
+                *file = None;
+            }
+        }
+        self.set_current_task(current_task);
     }
 
     /// ( fileid -- ud ior )
@@ -45,19 +343,18 @@ pub trait FileAccess: Core {
         }
         let fileid = fileid as usize - 1;
         if fileid < self.files().len() {
-            let ud = match &self.files()[fileid] {
-                &Some(ref f) => match f.metadata() {
-                    Ok(m) => {
-                        let ud = m.len();
-                        if ud <= isize::max_value() as u64 {
-                            Ok(ud)
-                        } else {
-                            Err(RESULT_OUT_OF_RANGE)
-                        }
+            let metadata = self.files()[fileid].as_ref().map(|f| f.metadata());
+            let ud = match metadata {
+                Some(Ok(m)) => {
+                    let ud = m.len();
+                    if ud <= isize::max_value() as u64 {
+                        Ok(ud)
+                    } else {
+                        Err(RESULT_OUT_OF_RANGE)
                     }
-                    Err(_) => Err(FILE_IO_EXCEPTION),
-                },
-                &None => Err(INVALID_NUMERIC_ARGUMENT),
+                }
+                Some(Err(e)) => Err(self.io_exception(e)),
+                None => Err(INVALID_NUMERIC_ARGUMENT),
             };
             match ud {
                 Ok(ud) => {
@@ -93,18 +390,19 @@ pub trait FileAccess: Core {
         }
         let fileid = fileid as usize - 1;
         if fileid < self.files().len() {
-            let ud = match &mut self.files_mut()[fileid] {
-                &mut Some(ref mut f) => match f.seek(SeekFrom::Current(0)) {
-                    Ok(ud) => {
-                        if ud <= isize::max_value() as u64 {
-                            Ok(ud)
-                        } else {
-                            Err(RESULT_OUT_OF_RANGE)
-                        }
+            let position = self.files_mut()[fileid]
+                .as_mut()
+                .map(|f| f.seek(SeekFrom::Current(0)));
+            let ud = match position {
+                Some(Ok(ud)) => {
+                    if ud <= isize::max_value() as u64 {
+                        Ok(ud)
+                    } else {
+                        Err(RESULT_OUT_OF_RANGE)
                     }
-                    Err(_) => Err(FILE_IO_EXCEPTION),
-                },
-                &mut None => Err(INVALID_NUMERIC_ARGUMENT),
+                }
+                Some(Err(e)) => Err(self.io_exception(e)),
+                None => Err(INVALID_NUMERIC_ARGUMENT),
             };
             match ud {
                 Ok(ud) => {
@@ -142,7 +440,8 @@ pub trait FileAccess: Core {
     /// Create the file named in the character string specified by c-addr and
     /// u, and open it with file access method fam. The meaning of values of
     /// fam is implementation defined. If a file with the same name already
-    /// exists, recreate it as an empty file.
+    /// exists, recreate it as an empty file. A fam modified by `bin` opens
+    /// the file in binary mode.
     ///
     /// If the file was successfully created and opened, ior is zero, fileid
     /// is its identifier, and the file has been positioned to the start of
@@ -159,7 +458,7 @@ pub trait FileAccess: Core {
             return;
         }
         let mut options = OpenOptions::new();
-        match fam {
+        match fam & !BIN {
             0 => {
                 // Impossible to create a read-only file.
                 self.s_stack().push2(-1, INVALID_NUMERIC_ARGUMENT.into());
@@ -177,11 +476,13 @@ pub trait FileAccess: Core {
             }
         };
         let file = {
-            if self.data_space().start() <= caddr && caddr + u <= self.data_space().limit() {
+            if self.file_storage().open_count() >= self.wordlist().max_files() {
+                Err(RESULT_OUT_OF_RANGE)
+            } else if self.data_space().start() <= caddr && caddr + u <= self.data_space().limit() {
                 let path_name = unsafe { self.data_space().str_from_raw_parts(caddr, u) };
                 match options.open(&path_name) {
-                    Err(_) => Err(FILE_IO_EXCEPTION),
-                    Ok(file) => Ok(file),
+                    Err(e) => Err(self.io_exception(e)),
+                    Ok(file) => Ok((file, path_name.to_string())),
                 }
             } else {
                 Err(INVALID_MEMORY_ADDRESS)
@@ -191,19 +492,9 @@ pub trait FileAccess: Core {
             Err(e) => {
                 self.s_stack().push2(-1, e.into());
             }
-            Ok(file) => {
-                let position = self.files().iter().position(|x| x.is_none());
-                match position {
-                    Some(p) => {
-                        self.files_mut()[p] = Some(file);
-                        self.s_stack().push2(p as isize + 1, 0);
-                    }
-                    None => {
-                        let fileid = self.files().len() as isize;
-                        self.s_stack().push2(fileid + 1, 0);
-                        self.files_mut().push(Some(file));
-                    }
-                }
+            Ok((file, path)) => {
+                let fileid = self.file_storage_mut().insert(file, &path, fam & BIN != 0);
+                self.s_stack().push2(fileid as isize, 0);
             }
         }
     }
@@ -223,7 +514,7 @@ pub trait FileAccess: Core {
                 if self.data_space().start() <= caddr && caddr + u <= self.data_space().limit() {
                     let path_name = unsafe { self.data_space().str_from_raw_parts(caddr, u) };
                     match fs::remove_file(path_name) {
-                        Err(_) => FILE_IO_EXCEPTION.into(),
+                        Err(e) => self.io_exception(e).into(),
                         Ok(_) => 0,
                     }
                 } else {
@@ -234,10 +525,522 @@ pub trait FileAccess: Core {
         }
     }
 
+    /// ( c-addr u -- ior )
+    ///
+    /// Write the events in the trace ring to the file named in the
+    /// character string specified by c-addr u, in Chrome trace-event JSON
+    /// format which can be viewed in `chrome://tracing`.
+    fn trace_export(&mut self) {
+        let (caddr, u) = self.s_stack().pop2();
+        let caddr = caddr as usize;
+        let u = u as usize;
+        if u > PATH_NAME_MAX_LEN {
+            self.s_stack().push(INVALID_NUMERIC_ARGUMENT.into());
+        } else {
+            let result = {
+                if self.data_space().start() <= caddr && caddr + u <= self.data_space().limit() {
+                    let json = self.wordlist().trace().to_chrome_json();
+                    let path_name = unsafe { self.data_space().str_from_raw_parts(caddr, u) };
+                    match fs::write(path_name, json) {
+                        Err(e) => self.io_exception(e).into(),
+                        Ok(_) => 0,
+                    }
+                } else {
+                    INVALID_MEMORY_ADDRESS.into()
+                }
+            };
+            self.s_stack().push(result);
+        }
+    }
+
+    /// ( c-addr u -- ior )
+    ///
+    /// Append the input lines set by the host and all output flushed by
+    /// `flush-output` to the file named in the character string specified
+    /// by c-addr u, until `transcript-off`. For audit trails of
+    /// interactive sessions. A transcript already open is closed first.
+    fn transcript(&mut self) {
+        let (caddr, u) = self.s_stack().pop2();
+        let caddr = caddr as usize;
+        let u = u as usize;
+        if u > PATH_NAME_MAX_LEN {
+            self.s_stack().push(INVALID_NUMERIC_ARGUMENT.into());
+        } else if self.data_space().start() <= caddr && caddr + u <= self.data_space().limit() {
+            self.transcription_mut().set_file(None);
+            let path_name = unsafe { self.data_space().str_from_raw_parts(caddr, u) };
+            let file = OpenOptions::new().create(true).append(true).open(path_name);
+            let ior = match file {
+                Ok(file) => {
+                    self.transcription_mut().set_file(Some(file));
+                    0
+                }
+                Err(e) => self.io_exception(e).into(),
+            };
+            self.s_stack().push(ior);
+        } else {
+            self.s_stack().push(INVALID_MEMORY_ADDRESS.into());
+        }
+    }
+
+    /// ( -- )
+    ///
+    /// Close the file opened by `transcript`.
+    fn transcript_off(&mut self) {
+        self.transcription_mut().set_file(None);
+    }
+
+    /// ( c-addr u -- ior )
+    ///
+    /// Write the digest of the dictionary, see `tools::dictionary_digest`,
+    /// to the file named in the character string specified by c-addr u.
+    /// Digests of two builds are compared by `rtf image-diff`.
+    fn save_digest(&mut self) {
+        let result = match self.pop_path_name() {
+            Ok(path) => {
+                let text = format_digest(&dictionary_digest(self));
+                match fs::write(path, text) {
+                    Err(e) => self.io_exception(e).into(),
+                    Ok(_) => 0,
+                }
+            }
+            Err(e) => e.into(),
+        };
+        self.s_stack().push(result);
+    }
+
+    /// ( c-addr u -- ior )
+    ///
+    /// Write the build log, see `tools::format_manifest`, to the file
+    /// named in the character string specified by c-addr u, so that the
+    /// dictionary can be traced back to the sources it was compiled from.
+    fn build_manifest(&mut self) {
+        let result = match self.pop_path_name() {
+            Ok(path) => {
+                let text = format_manifest(self);
+                match fs::write(path, text) {
+                    Err(e) => self.io_exception(e).into(),
+                    Ok(_) => 0,
+                }
+            }
+            Err(e) => e.into(),
+        };
+        self.s_stack().push(result);
+    }
+
+    /// ( "name" -- )
+    ///
+    /// Define `name ( -- a-addr )`, a cell in the retention region kept
+    /// across restarts by `save-retained` and `load-retained`. Aborts with
+    /// `DICTIONARY_OVERFLOW` if all `RETAIN_CELLS` are used.
+    fn retain(&mut self) {
+        let n = self.wordlist().retained().len();
+        if n >= RETAIN_CELLS {
+            self.abort_with(DICTIONARY_OVERFLOW);
+            return;
+        }
+        let a = self.wordlist().retain_region() + n * mem::size_of::<isize>();
+        self.s_stack().push(a as isize);
+        self.constant();
+        if self.last_error().is_none() {
+            let xt = self.wordlist().len() - 1;
+            self.wordlist_mut().add_retained(xt);
+        }
+    }
+
+    /// Path name in the character string specified by `c-addr u` on the data
+    /// stack.
+    fn pop_path_name(&mut self) -> Result<String, Exception> {
+        let (caddr, u) = self.s_stack().pop2();
+        if u < 0 || u as usize > PATH_NAME_MAX_LEN {
+            Err(INVALID_NUMERIC_ARGUMENT)
+        } else if self.data_space().start() <= caddr as usize
+            && caddr as usize + u as usize <= self.data_space().limit()
+        {
+            Ok(unsafe {
+                self.data_space()
+                    .str_from_raw_parts(caddr as usize, u as usize)
+            }
+            .to_string())
+        } else {
+            Err(INVALID_MEMORY_ADDRESS)
+        }
+    }
+
+    /// Write the `retain` variables to file `path`.
+    ///
+    /// The file begins with `RTFR`, the version `RETAIN_VERSION` in a byte,
+    /// the CRC-32 and the length of the entries, both little-endian 32-bit. Each entry is the length of the
+    /// name of the variable in a byte, the name and the value as a
+    /// little-endian 64-bit integer, so that files are read by hosts of
+    /// any byte order and cell width.
+    fn write_retained(&mut self, path: &str) -> isize {
+        let mut entries = Vec::new();
+        let region = self.wordlist().retain_region();
+        let retained = self.wordlist().retained().to_vec();
+        for (i, xt) in retained.into_iter().enumerate() {
+            let name = self.word_name(xt);
+            let value = unsafe {
+                self.data_space()
+                    .get_isize(region + i * mem::size_of::<isize>())
+            };
+            entries.push(name.len() as u8);
+            entries.extend_from_slice(name.as_bytes());
+            entries.extend_from_slice(&(value as i64).to_le_bytes());
+        }
+        let mut bytes = RETAIN_MAGIC.to_vec();
+        bytes.push(RETAIN_VERSION);
+        bytes.extend_from_slice(&crc32(&entries).to_le_bytes());
+        bytes.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&entries);
+        match fs::write(path, bytes) {
+            Err(e) => self.io_exception(e).into(),
+            Ok(_) => 0,
+        }
+    }
+
+    /// Set the `retain` variables from file `path` written by
+    /// `write_retained`, matching them by name. Variables not in the file
+    /// or whose values do not fit in a cell of this host are left
+    /// unchanged, and all of them if the file is corrupted or of another
+    /// version, returning `FILE_IO_EXCEPTION` or `UNSUPPORTED_FORMAT`.
+    fn read_retained(&mut self, path: &str) -> isize {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => return self.io_exception(e).into(),
+        };
+        let word = |i: usize| {
+            let mut w = [0; 4];
+            w.copy_from_slice(&bytes[i..i + 4]);
+            u32::from_le_bytes(w)
+        };
+        if bytes.len() < 13 || &bytes[..4] != RETAIN_MAGIC {
+            return FILE_IO_EXCEPTION.into();
+        }
+        if bytes[4] != RETAIN_VERSION {
+            return UNSUPPORTED_FORMAT.into();
+        }
+        let entries = &bytes[13..];
+        if entries.len() != word(9) as usize || crc32(entries) != word(5) {
+            return FILE_IO_EXCEPTION.into();
+        }
+        let mut values = Vec::new();
+        let mut i = 0;
+        while i < entries.len() {
+            let len = entries[i] as usize;
+            if i + 1 + len + 8 > entries.len() {
+                return FILE_IO_EXCEPTION.into();
+            }
+            let name = String::from_utf8_lossy(&entries[i + 1..i + 1 + len]).into_owned();
+            let mut value = [0; 8];
+            value.copy_from_slice(&entries[i + 1 + len..i + 1 + len + 8]);
+            if let Ok(value) = isize::try_from(i64::from_le_bytes(value)) {
+                values.push((name, value));
+            }
+            i += 1 + len + 8;
+        }
+        let region = self.wordlist().retain_region();
+        let retained = self.wordlist().retained().to_vec();
+        for (i, xt) in retained.into_iter().enumerate() {
+            let name = self.word_name(xt);
+            if let Some(&(_, value)) = values.iter().find(|(n, _)| *n == name) {
+                unsafe {
+                    self.data_space()
+                        .put_isize(value, region + i * mem::size_of::<isize>());
+                }
+            }
+        }
+        0
+    }
+
+    /// ( c-addr u -- ior )
+    ///
+    /// Write the `retain` variables to the file named in the character
+    /// string specified by c-addr u.
+    fn save_retained(&mut self) {
+        let result = match self.pop_path_name() {
+            Ok(path) => self.write_retained(&path),
+            Err(e) => e.into(),
+        };
+        self.s_stack().push(result);
+    }
+
+    /// ( c-addr u -- ior )
+    ///
+    /// Set the `retain` variables from the file named in the character
+    /// string specified by c-addr u, written by `save-retained`.
+    fn load_retained(&mut self) {
+        let result = match self.pop_path_name() {
+            Ok(path) => self.read_retained(&path),
+            Err(e) => e.into(),
+        };
+        self.s_stack().push(result);
+    }
+
+    /// ( c-addr u -- )
+    ///
+    /// Keep the `retain` variables in the file named in the character string
+    /// specified by c-addr u: load them from the file if it exists, and
+    /// save them to it at `bye`.
+    fn retain_file(&mut self) {
+        match self.pop_path_name() {
+            Ok(path) => {
+                self.read_retained(&path);
+                if self.wordlist().retain_file().is_none() {
+                    let xt = self
+                        .find("(save-retain-file)")
+                        .expect("(save-retain-file) undefined");
+                    self.wordlist_mut().at_exit(xt);
+                }
+                self.wordlist_mut().set_retain_file(Some(path));
+            }
+            Err(e) => self.abort_with(e),
+        }
+    }
+
+    /// ( -- )
+    ///
+    /// Save the `retain` variables to the file set by `retain-file`.
+    /// Executed by `bye`.
+    fn save_retain_file(&mut self) {
+        if let Some(path) = self.wordlist().retain_file().map(|p| p.to_string()) {
+            self.write_retained(&path);
+        }
+    }
+
+    /// ( c-addr u "name" -- ior )
+    ///
+    /// Write the control flow graph of colon definition `name` in Graphviz
+    /// dot format to the file named in the character string specified by
+    /// c-addr u, see `cfg-export`.
+    fn cfg_export_file(&mut self) {
+        let (caddr, u) = self.s_stack().pop2();
+        let caddr = caddr as usize;
+        let u = u as usize;
+        self.parse_word();
+        let name = self.last_token().clone().unwrap_or_default();
+        if name.is_empty() {
+            self.abort_with(UNEXPECTED_END_OF_FILE);
+            return;
+        }
+        let code = match self.find(&name) {
+            Some(xt) => self.decode(xt),
+            None => {
+                self.abort_with(UNDEFINED_WORD);
+                return;
+            }
+        };
+        let code = match code {
+            Some(code) => code,
+            None => {
+                self.abort_with(INVALID_NUMERIC_ARGUMENT);
+                return;
+            }
+        };
+        let result = if u > PATH_NAME_MAX_LEN {
+            INVALID_NUMERIC_ARGUMENT.into()
+        } else if self.data_space().start() <= caddr && caddr + u <= self.data_space().limit() {
+            let dot = ControlFlowGraph::new(&name, &code).to_dot();
+            let path_name = unsafe { self.data_space().str_from_raw_parts(caddr, u) };
+            match fs::write(path_name, dot) {
+                Err(e) => self.io_exception(e).into(),
+                Ok(_) => 0,
+            }
+        } else {
+            INVALID_MEMORY_ADDRESS.into()
+        };
+        self.s_stack().push(result);
+    }
+
+    /// ( -- )
+    ///
+    /// List the files opened by the current task, one per line with file
+    /// id, position, mode and path if known, followed by the number of
+    /// opened files and input sources and `max-files`.
+    fn dot_files(&mut self) {
+        if let Some(mut buf) = self.output_buffer().take() {
+            let storage = self.file_storage();
+            for (i, file) in storage.files.iter().enumerate() {
+                if let Some(mut file) = file.as_ref() {
+                    let position = match file.stream_position() {
+                        Ok(position) => position.to_string(),
+                        Err(_) => "?".to_string(),
+                    };
+                    let mode = if storage.is_binary(i) { "bin" } else { "txt" };
+                    let path = storage.path(i).unwrap_or("?");
+                    writeln!(buf, "{} {} {} {}", i + 1, position, mode, path).unwrap();
+                }
+            }
+            writeln!(
+                buf,
+                "{} of {} open",
+                storage.open_count(),
+                self.wordlist().max_files()
+            )
+            .unwrap();
+            self.set_output_buffer(buf);
+        }
+    }
+
+    /// ( -- n )
+    ///
+    /// OS error number of the last I/O error of the current task, 0 if there
+    /// is none or it has no number.
+    fn io_error(&mut self) {
+        let n = self
+            .file_storage()
+            .last_io_error()
+            .and_then(|e| e.raw_os_error())
+            .unwrap_or(0);
+        self.s_stack().push(n as isize);
+    }
+
+    /// ( -- )
+    ///
+    /// Print the kind and the message of the last I/O error of the current
+    /// task.
+    fn dot_io_error(&mut self) {
+        if let Some(mut buf) = self.output_buffer().take() {
+            match self.file_storage().last_io_error() {
+                Some(e) => writeln!(buf, "{:?}: {}", e.kind(), e).unwrap(),
+                None => writeln!(buf, "no I/O error").unwrap(),
+            }
+            self.set_output_buffer(buf);
+        }
+    }
+
+    /// ( -- )
+    ///
+    /// List the input sources opened by the current task, one per line with
+    /// source id, number of lines loaded and path.
+    #[cfg(feature = "loader")]
+    fn dot_sources(&mut self) {
+        if let Some(mut buf) = self.output_buffer().take() {
+            for (i, source) in self.file_storage().sources.iter().enumerate() {
+                if let Some(source) = source.as_ref() {
+                    writeln!(buf, "{} {} {}", i + 1, source.line(), source.path()).unwrap();
+                }
+            }
+            self.set_output_buffer(buf);
+        }
+    }
+
+    /// ( -- n )
+    ///
+    /// Maximum number of files and input sources open by a task. Beyond it
+    /// `open-file` and `create-file` return ior `RESULT_OUT_OF_RANGE`.
+    fn max_files(&mut self) {
+        let n = self.wordlist().max_files();
+        self.s_stack().push(n as isize);
+    }
+
+    /// Copy `path` into the next transient path buffer and push its address
+    /// and length. Aborts with `PARSED_STRING_OVERFLOW` if `path` is longer
+    /// than a buffer, or `DICTIONARY_OVERFLOW` if there is no room for the
+    /// buffers in the data space.
+    ///
+    /// The buffers are beyond `pad` and used in rotation, so that the
+    /// result is valid until the data space changes or two more paths are
+    /// placed.
+    fn push_transient_path(&mut self, path: &str) {
+        if path.len() > PATH_NAME_MAX_LEN {
+            self.abort_with(PARSED_STRING_OVERFLOW);
+            return;
+        }
+        let start = DataSpace::aligned(self.data_space().here()) + PATH_BUFFER_OFFSET;
+        if start + PATH_BUFFERS * PATH_NAME_MAX_LEN > self.data_space().limit() {
+            self.abort_with(DICTIONARY_OVERFLOW);
+            return;
+        }
+        let i = self.file_storage().path_buffer;
+        self.file_storage_mut().path_buffer = (i + 1) % PATH_BUFFERS;
+        let addr = start + i * PATH_NAME_MAX_LEN;
+        unsafe {
+            self.data_space()
+                .buffer_from_raw_parts_mut(addr, path.len())
+                .copy_from_slice(path.as_bytes());
+        }
+        self.s_stack().push2(addr as isize, path.len() as isize);
+    }
+
+    /// Compilation: ( "ccc<quote>" -- )
+    ///
+    /// Parse ccc delimited by " (double-quote) and compile it like `s"`.
+    ///
+    /// Interpretation: ( "ccc<quote>" -- c-addr u )
+    ///
+    /// Parse ccc delimited by " (double-quote) and place it in a transient
+    /// path buffer, see `path+`.
+    fn path_quote(&mut self) {
+        // Ignore the space following path"
+        let len = self.input_buffer().as_ref().map_or(0, |b| b.len());
+        if self.state().source_index < len {
+            self.state().source_index += 1;
+        }
+        self.s_stack().push('"' as isize);
+        self.parse();
+        let path = self.last_token().clone().unwrap_or_default();
+        if self.state().is_compiling {
+            let idx = self.references().idx_s_quote;
+            let compilation_semantics = self.wordlist()[idx].compilation_semantics;
+            compilation_semantics(self, idx);
+            self.data_space().compile_str(&path);
+            self.data_space().align();
+        } else {
+            self.push_transient_path(&path);
+        }
+    }
+
+    /// ( c-addr1 u1 c-addr2 u2 -- c-addr3 u3 )
+    ///
+    /// Join path c-addr2 u2 to path c-addr1 u1 with the separator of the
+    /// platform, placing the result c-addr3 u3 in a transient path buffer.
+    /// Same as `Path::join`, an absolute c-addr2 u2 replaces c-addr1 u1.
+    fn path_plus(&mut self) {
+        let (caddr2, u2) = self.s_stack().pop2();
+        let (caddr1, u1) = self.s_stack().pop2();
+        let valid = |vm: &mut Self, caddr: isize, u: isize| {
+            0 <= u
+                && vm.data_space().start() <= caddr as usize
+                && caddr as usize + u as usize <= vm.data_space().limit()
+        };
+        if !valid(self, caddr1, u1) || !valid(self, caddr2, u2) {
+            self.abort_with(INVALID_MEMORY_ADDRESS);
+            return;
+        }
+        let path = unsafe {
+            let data_space = self.data_space_const();
+            let base = data_space.str_from_raw_parts(caddr1 as usize, u1 as usize);
+            let name = data_space.str_from_raw_parts(caddr2 as usize, u2 as usize);
+            Path::new(base).join(name)
+        };
+        self.push_transient_path(&path.to_string_lossy());
+    }
+
+    /// ( -- c-addr u )
+    ///
+    /// Home directory of the user in a transient path buffer, empty if
+    /// unknown.
+    #[cfg(feature = "user-dirs")]
+    fn home_path(&mut self) {
+        let path = BaseDirs::new().map(|dirs| dirs.home_dir().to_string_lossy().into_owned());
+        self.push_transient_path(&path.unwrap_or_default());
+    }
+
+    /// ( -- c-addr u )
+    ///
+    /// Configuration directory of the user in a transient path buffer,
+    /// empty if unknown. `~/.config` on Linux.
+    #[cfg(feature = "user-dirs")]
+    fn config_path(&mut self) {
+        let path = BaseDirs::new().map(|dirs| dirs.config_dir().to_string_lossy().into_owned());
+        self.push_transient_path(&path.unwrap_or_default());
+    }
+
     /// ( c-addr u fam -- fileid ior )
     /// Open the file named in the character string specified by c-addr u,
     /// with file access method indicated by fam. The meaning of values of fam
-    /// is implementation defined.
+    /// is implementation defined. A fam modified by `bin` opens the file in
+    /// binary mode, see `read-line` and `write-line`.
     ///
     /// If the file is successfully opened, ior is zero, fileid is its
     /// identifier, and the file has been positioned to the start of the file.
@@ -253,7 +1056,7 @@ pub trait FileAccess: Core {
             return;
         }
         let mut options = OpenOptions::new();
-        match fam {
+        match fam & !BIN {
             0 => {
                 options.read(true);
             }
@@ -269,11 +1072,13 @@ pub trait FileAccess: Core {
             }
         };
         let file = {
-            if self.data_space().start() <= caddr && caddr + u <= self.data_space().limit() {
+            if self.file_storage().open_count() >= self.wordlist().max_files() {
+                Err(RESULT_OUT_OF_RANGE)
+            } else if self.data_space().start() <= caddr && caddr + u <= self.data_space().limit() {
                 let path_name = unsafe { self.data_space().str_from_raw_parts(caddr, u) };
                 match options.open(&path_name) {
-                    Err(_) => Err(FILE_IO_EXCEPTION),
-                    Ok(file) => Ok(file),
+                    Err(e) => Err(self.io_exception(e)),
+                    Ok(file) => Ok((file, path_name.to_string())),
                 }
             } else {
                 Err(INVALID_MEMORY_ADDRESS)
@@ -283,19 +1088,9 @@ pub trait FileAccess: Core {
             Err(e) => {
                 self.s_stack().push2(-1, e.into());
             }
-            Ok(file) => {
-                let position = self.files_mut().iter().position(|x| x.is_none());
-                match position {
-                    Some(p) => {
-                        self.files_mut()[p] = Some(file);
-                        self.s_stack().push2(p as isize + 1, 0);
-                    }
-                    None => {
-                        let fileid = self.files().len() as isize;
-                        self.s_stack().push2(fileid + 1, 0);
-                        self.files_mut().push(Some(file));
-                    }
-                }
+            Ok((file, path)) => {
+                let fileid = self.file_storage_mut().insert(file, &path, fam & BIN != 0);
+                self.s_stack().push2(fileid as isize, 0);
             }
         }
     }
@@ -343,7 +1138,10 @@ pub trait FileAccess: Core {
             let result = {
                 if self.data_space().start() <= caddr && caddr + u1 <= self.data_space().limit() {
                     let mut buf = unsafe { self.data_space().buffer_from_raw_parts_mut(caddr, u1) };
-                    file.read(&mut buf).or(Err(FILE_IO_EXCEPTION.into()))
+                    match file.read(&mut buf) {
+                        Ok(u2) => Ok(u2),
+                        Err(e) => Err(self.io_exception(e).into()),
+                    }
                 } else {
                     Err(INVALID_MEMORY_ADDRESS.into())
                 }
@@ -389,14 +1187,17 @@ pub trait FileAccess: Core {
                             let buf = unsafe {
                                 self.data_space().buffer_from_raw_parts(caddr as _, u as _)
                             };
-                            f.write_all(buf).or(Err(FILE_IO_EXCEPTION))
+                            match f.write_all(buf) {
+                                Ok(_) => Ok(()),
+                                Err(e) => Err(self.io_exception(e)),
+                            }
                         } else {
                             Err(INVALID_MEMORY_ADDRESS)
                         }
                     };
                     match result {
                         Ok(_) => self.s_stack().push(0),
-                        Err(_) => self.s_stack().push(FILE_IO_EXCEPTION.into()),
+                        Err(e) => self.s_stack().push(e.into()),
                     }
                     self.files_mut()[fileid] = Some(f);
                 }
@@ -409,6 +1210,123 @@ pub trait FileAccess: Core {
         }
     }
 
+    /// ( c-addr u1 fileid -- u2 flag ior )
+    ///
+    /// Read the next line from the file specified by fileid into memory at
+    /// the address c-addr. At most u1 characters are read, not including the
+    /// line terminator. If the line is longer than u1 characters, the rest
+    /// is read by the next `read-line`.
+    ///
+    /// If the operation succeeded, flag is true and ior is zero. u2 is the
+    /// number of characters read, excluding the line terminator. If the file
+    /// position was at the end of the file before the read, u2 is zero and
+    /// flag is false.
+    ///
+    /// In text mode, both LF and CR LF terminate a line. In binary mode, only
+    /// LF does, and a CR before it is part of the line.
+    fn read_line(&mut self) {
+        let (caddr, u1, fileid) = self.s_stack().pop3();
+        let caddr = caddr as usize;
+        let u1 = u1 as usize;
+        if fileid <= 0 || fileid as usize > self.files().len() {
+            self.s_stack().push3(0, 0, INVALID_NUMERIC_ARGUMENT.into());
+            return;
+        }
+        let fileid = fileid as usize - 1;
+        let binary = self.file_storage().is_binary(fileid);
+        let mut file = match self.files_mut()[fileid].take() {
+            Some(file) => file,
+            None => {
+                self.s_stack().push3(0, 0, INVALID_NUMERIC_ARGUMENT.into());
+                return;
+            }
+        };
+        let result = if self.data_space().start() <= caddr
+            && caddr.wrapping_add(u1) <= self.data_space().limit()
+        {
+            // Read enough to see the terminator following u1 characters.
+            let mut buf = Vec::with_capacity(u1 + 2);
+            match (&mut file).take(u1 as u64 + 2).read_to_end(&mut buf) {
+                Ok(0) => Ok((0, false)),
+                Ok(n) => {
+                    let (len, consumed) = match buf.iter().position(|&b| b == b'\n') {
+                        Some(lf) if !binary && lf > 0 && buf[lf - 1] == b'\r' && lf <= u1 + 1 => {
+                            (lf - 1, lf + 1)
+                        }
+                        Some(lf) if lf <= u1 => (lf, lf + 1),
+                        _ => (n.min(u1), n.min(u1)),
+                    };
+                    unsafe {
+                        self.data_space()
+                            .buffer_from_raw_parts_mut(caddr, len)
+                            .copy_from_slice(&buf[..len]);
+                    }
+                    match file.seek(SeekFrom::Current(consumed as i64 - n as i64)) {
+                        Ok(_) => Ok((len, true)),
+                        Err(e) => Err(self.io_exception(e)),
+                    }
+                }
+                Err(e) => Err(self.io_exception(e)),
+            }
+        } else {
+            Err(INVALID_MEMORY_ADDRESS)
+        };
+        match result {
+            Ok((u2, flag)) => self
+                .s_stack()
+                .push3(u2 as isize, if flag { -1 } else { 0 }, 0),
+            Err(e) => self.s_stack().push3(0, 0, e.into()),
+        }
+        self.files_mut()[fileid] = Some(file);
+    }
+
+    /// ( c-addr u fileid -- ior )
+    ///
+    /// Write u characters from c-addr followed by the line terminator to the
+    /// file identified by fileid starting at its current position. ior is
+    /// the implementation-defined I/O result code.
+    ///
+    /// The line terminator is CR LF on Windows and LF elsewhere in text mode,
+    /// and always LF in binary mode.
+    fn write_line(&mut self) {
+        let (caddr, u, fileid) = self.s_stack().pop3();
+        let caddr = caddr as usize;
+        let u = u as usize;
+        if fileid <= 0 || fileid as usize > self.files().len() {
+            self.s_stack().push(INVALID_NUMERIC_ARGUMENT.into());
+            return;
+        }
+        let fileid = fileid as usize - 1;
+        let terminator = if self.file_storage().is_binary(fileid) {
+            b"\n"
+        } else {
+            TEXT_LINE_TERMINATOR
+        };
+        match self.files_mut()[fileid].take() {
+            Some(mut f) => {
+                let result = if self.data_space().start() <= caddr
+                    && caddr.wrapping_add(u) <= self.data_space().limit()
+                {
+                    let buf = unsafe { self.data_space().buffer_from_raw_parts(caddr, u) };
+                    match f.write_all(buf).and_then(|_| f.write_all(terminator)) {
+                        Ok(_) => Ok(()),
+                        Err(e) => Err(self.io_exception(e)),
+                    }
+                } else {
+                    Err(INVALID_MEMORY_ADDRESS)
+                };
+                match result {
+                    Ok(_) => self.s_stack().push(0),
+                    Err(e) => self.s_stack().push(e.into()),
+                }
+                self.files_mut()[fileid] = Some(f);
+            }
+            None => {
+                self.s_stack().push(INVALID_NUMERIC_ARGUMENT.into());
+            }
+        }
+    }
+
     /// ( ud fileid -- ior )
     ///
     /// Set the size of the file identified by fileid to ud. ior is the
@@ -447,8 +1365,9 @@ pub trait FileAccess: Core {
                         Ok(_) => {
                             self.s_stack().push(0);
                         }
-                        Err(_) => {
-                            self.s_stack().push(FILE_IO_EXCEPTION.into());
+                        Err(e) => {
+                            let ior = self.io_exception(e);
+                            self.s_stack().push(ior.into());
                         }
                     }
                     self.files_mut()[fileid] = Some(f);
@@ -494,8 +1413,9 @@ pub trait FileAccess: Core {
                         Ok(_) => {
                             self.s_stack().push(0);
                         }
-                        Err(_) => {
-                            self.s_stack().push(FILE_IO_EXCEPTION.into());
+                        Err(e) => {
+                            let ior = self.io_exception(e);
+                            self.s_stack().push(ior.into());
                         }
                     }
                     self.files_mut()[fileid] = Some(f);
@@ -7,9 +7,57 @@ use std::f64::consts::PI;
 use std::mem;
 use {FALSE, TRUE};
 
+/// Stack effects of the primitives of `Float`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("fconstant", "( \"<spaces>name\" -- ) ( F: r -- )"),
+    ("fvalue", "( \"<spaces>name\" -- ) ( F: r -- )"),
+    ("float+", "( a-addr1 -- a-addr2 )"),
+    ("floats", "( n1 -- n2 )"),
+    ("faligned", "( addr -- a-addr )"),
+    ("falign", "( -- )"),
+    ("pi", "( F: -- r )"),
+    ("f!", "( f-addr -- ) ( F: r -- )"),
+    ("f@", "( f-addr -- ) ( F: -- r )"),
+    ("fabs", "( F: r1 -- r2 )"),
+    ("fsin", "( F: r1 -- r2 )"),
+    ("fcos", "( F: r1 -- r2 )"),
+    ("ftan", "( F: r1 -- r2 )"),
+    ("fsincos", "( F: r1 -- r2 r3 )"),
+    ("fasin", "( F: r1 -- r2 )"),
+    ("facos", "( F: r1 -- r2 )"),
+    ("fatan", "( F: r1 -- r2 )"),
+    ("fatan2", "( F: r1 r2 -- r3 )"),
+    ("fsqrt", "( F: r1 -- r2 )"),
+    ("fdrop", "( F: r -- )"),
+    ("fdup", "( F: r -- r r )"),
+    ("fswap", "( F: r1 r2 -- r2 r1 )"),
+    ("fnip", "( F: r1 r2 -- r2 )"),
+    ("fover", "( F: r1 r2 -- r1 r2 r1 )"),
+    ("frot", "( F: r1 r2 r3 -- r2 r3 r1 )"),
+    ("fpick", "( n -- ) ( F: -- r )"),
+    ("s>f", "( n -- ) ( F: -- r )"),
+    ("f>s", "( -- n ) ( F: r -- )"),
+    ("f+", "( F: r1 r2 -- r3 )"),
+    ("f-", "( F: r1 r2 -- r3 )"),
+    ("f*", "( F: r1 r2 -- r3 )"),
+    ("f/", "( F: r1 r2 -- r3 )"),
+    ("f**", "( F: r1 r2 -- r3 )"),
+    ("f~", "( -- flag ) ( F: r1 r2 r3 -- )"),
+    ("f0<", "( -- flag ) ( F: r -- )"),
+    ("f0=", "( -- flag ) ( F: r -- )"),
+    ("f<", "( -- flag ) ( F: r1 r2 -- )"),
+    ("fmin", "( F: r1 r2 -- r3 )"),
+    ("fmax", "( F: r1 r2 -- r3 )"),
+    ("floor", "( F: r1 -- r2 )"),
+    ("fround", "( F: r1 -- r2 )"),
+    ("fceil", "( F: r1 -- r2 )"),
+    ("fnegate", "( F: r1 -- r2 )"),
+];
+
 pub trait Float: Core {
     fn add_float(&mut self) {
         self.add_primitive("fconstant", Float::fconstant);
+        self.add_primitive("fvalue", Float::fvalue);
         self.add_primitive("float+", Float::float_plus);
         self.add_primitive("floats", Float::floats);
         self.add_primitive("faligned", Float::faligned);
@@ -51,6 +99,8 @@ pub trait Float: Core {
         self.add_primitive("fround", Float::fround);
         self.add_primitive("fceil", Float::fceil);
         self.add_primitive("fnegate", Float::fnegate);
+        self.references().idx_f_store = self.find("f!").expect("f! undefined");
+        self.declare_effects(STACK_EFFECTS);
     }
 
     // Defining words
@@ -69,6 +119,18 @@ pub trait Float: Core {
         self.data_space().compile_f64(v);
     }
 
+    /// Run-time: ( "<spaces>name" -- ) ( F: r -- )
+    ///
+    /// Define `name ( F: -- r )` returning `r` until changed by `to`.
+    fn fvalue(&mut self) {
+        let v = self.f_stack().pop();
+        self.define(Core::p_fvalue, Core::compile_word);
+        if self.last_error().is_none() {
+            self.data_space().align_f64();
+            self.data_space().compile_f64(v);
+        }
+    }
+
     /// Run-time: ( a-addr1 -- a-addr2 )
     ///
     /// Add the size in address units of a float to `a-addr1`, giving `a-addr2`.
@@ -0,0 +1,442 @@
+//! Finite state machine word set
+//!
+//! ```text
+//! fsm: light
+//!   state: red
+//!     0 on  ." go" => green
+//!   state: green
+//!     0 on  => yellow
+//!     1 on  ." stop" => red
+//!   state: yellow
+//!     0 on  => red
+//! ;fsm
+//!
+//! 0 light fsm-step
+//! ```
+//!
+//! `light ( -- handle )` starts in its first state `red`. Actions between
+//! `on` and `=>` are compiled as nameless definitions. `;fsm` compiles the
+//! transitions into a table indexed by state and event, so that
+//! `fsm-step` dispatches in constant time.
+//!
+//! ```text
+//! handle: | current state | table |
+//! table:  | states | events | action | next state + 1 | ... |
+//! ```
+//!
+//! Next state 0 in the table means that the event is ignored in the state.
+
+use core::Core;
+use exception::{
+    CONTROL_STRUCTURE_MISMATCH, INVALID_MEMORY_ADDRESS, INVALID_NUMERIC_ARGUMENT, UNDEFINED_WORD,
+    UNEXPECTED_END_OF_FILE,
+};
+use memory::Memory;
+use std::mem;
+
+/// Upper limit of event numbers.
+pub const MAX_FSM_EVENTS: usize = 256;
+
+struct Transition {
+    state: usize,
+    event: usize,
+    action: usize,
+    target: String,
+}
+
+/// Finite state machine being defined by `fsm:` ... `;fsm`.
+pub struct FsmDefinition {
+    handle: usize,
+    states: Vec<String>,
+    transitions: Vec<Transition>,
+    /// Event and action of the transition being compiled.
+    pending: Option<(usize, usize)>,
+}
+
+impl FsmDefinition {
+    fn state(&self, name: &str) -> Option<usize> {
+        self.states
+            .iter()
+            .position(|s| s.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Stack effects of the primitives of `Fsm`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("fsm:", "( \"name\" -- )"),
+    ("state:", "( \"name\" -- )"),
+    ("on", "( event -- )"),
+    ("=>", "( \"name\" -- )"),
+    (";fsm", "( -- )"),
+    ("fsm-step", "( i*x event handle -- j*x )"),
+    ("fsm-state", "( handle -- i )"),
+    ("fsm-state!", "( i handle -- )"),
+];
+
+pub trait Fsm: Core {
+    /// Add finite state machine words.
+    fn add_fsm(&mut self) {
+        self.add_primitive("fsm:", Fsm::fsm_colon);
+        self.add_primitive("state:", Fsm::state_colon);
+        self.add_primitive("on", Fsm::on);
+        self.add_immediate_and_compile_only("=>", Fsm::imm_transition);
+        self.add_primitive(";fsm", Fsm::semicolon_fsm);
+        self.add_primitive("fsm-step", Fsm::fsm_step);
+        self.add_primitive("fsm-state", Fsm::fsm_state);
+        self.add_primitive("fsm-state!", Fsm::set_fsm_state);
+        self.declare_effects(STACK_EFFECTS);
+    }
+
+    /// Parse a name, abort with `UNEXPECTED_END_OF_FILE` if there is none.
+    fn parse_fsm_name(&mut self) -> Option<String> {
+        self.parse_word();
+        let name = self.last_token().clone().unwrap_or_default();
+        if name.is_empty() {
+            self.abort_with(UNEXPECTED_END_OF_FILE);
+            None
+        } else {
+            Some(name)
+        }
+    }
+
+    /// Address of the transition table of `handle`.
+    fn fsm_table(&mut self, handle: usize) -> Option<usize> {
+        let cell = mem::size_of::<usize>();
+        let (start, here) = (self.data_space().start(), self.data_space().here());
+        if start <= handle && handle.saturating_add(2 * cell) <= here {
+            let table = unsafe { self.data_space().get_usize(handle + cell) };
+            if start <= table && table.saturating_add(2 * cell) <= here {
+                return Some(table);
+            }
+        }
+        self.abort_with(INVALID_MEMORY_ADDRESS);
+        None
+    }
+
+    /// Address of the transition of `table` for `state` and `event`.
+    /// Aborts with `INVALID_NUMERIC_ARGUMENT` if `state` or `event` is out
+    /// of the table, or `INVALID_MEMORY_ADDRESS` if the transition is not
+    /// in data space.
+    fn fsm_entry(&mut self, table: usize, state: usize, event: usize) -> Option<usize> {
+        let cell = mem::size_of::<usize>();
+        let (states, events) = unsafe {
+            (
+                self.data_space().get_usize(table),
+                self.data_space().get_usize(table + cell),
+            )
+        };
+        if state >= states || event >= events {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+            return None;
+        }
+        let here = self.data_space().here();
+        let entry = state
+            .checked_mul(events)
+            .and_then(|i| i.checked_add(event))
+            .and_then(|i| i.checked_mul(2))
+            .and_then(|i| i.checked_add(2))
+            .and_then(|i| i.checked_mul(cell))
+            .and_then(|offset| table.checked_add(offset))
+            .filter(|entry| entry.saturating_add(2 * cell) <= here);
+        if entry.is_none() {
+            self.abort_with(INVALID_MEMORY_ADDRESS);
+        }
+        entry
+    }
+
+    /// Interpretation: ( "name" -- )
+    ///
+    /// Begin the definition of finite state machine `name ( -- handle )`.
+    fn fsm_colon(&mut self) {
+        if self.state().fsm.is_some() {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+            return;
+        }
+        self.define(Core::p_var, Core::compile_var);
+        if self.last_error().is_some() {
+            return;
+        }
+        let handle = self.data_space().here();
+        self.data_space().compile_usize(0);
+        self.data_space().compile_usize(0);
+        self.state().fsm = Some(FsmDefinition {
+            handle,
+            states: Vec::new(),
+            transitions: Vec::new(),
+            pending: None,
+        });
+    }
+
+    /// Interpretation: ( "name" -- )
+    ///
+    /// Begin state `name` of the finite state machine being defined. States
+    /// are numbered from 0 in the order of definition.
+    fn state_colon(&mut self) {
+        let name = match self.parse_fsm_name() {
+            Some(name) => name,
+            None => return,
+        };
+        let duplicated = match self.state().fsm {
+            Some(ref fsm) if fsm.pending.is_none() => fsm.state(&name).is_some(),
+            _ => true,
+        };
+        if duplicated {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        } else if let Some(ref mut fsm) = self.state().fsm {
+            fsm.states.push(name);
+        }
+    }
+
+    /// Interpretation: ( event -- )
+    ///
+    /// Begin the action upon `event` in the current state, ended by `=>`.
+    ///
+    /// Outside of the definition of a machine, or when compiled, the
+    /// usual `on ( a-addr -- )`.
+    fn on(&mut self) {
+        if self.state().fsm.is_none() || self.state().is_compiling {
+            self.p_on();
+            return;
+        }
+        let event = self.s_stack().pop() as usize;
+        let valid = match self.state().fsm {
+            Some(ref fsm) => !fsm.states.is_empty(),
+            None => false,
+        };
+        if !valid {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+        } else if event >= MAX_FSM_EVENTS {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        } else {
+            let action = self.noname();
+            if let Some(ref mut fsm) = self.state().fsm {
+                fsm.pending = Some((event, action));
+            }
+        }
+    }
+
+    /// Compilation: ( "name" -- )
+    ///
+    /// End the action begun by `on` and go to state `name` after the
+    /// action. `name` may be defined later in the same machine.
+    fn imm_transition(&mut self) {
+        let pending = match self.state().fsm {
+            Some(ref fsm) => fsm.pending,
+            None => None,
+        };
+        let (event, action) = match pending {
+            Some(pending) => pending,
+            None => {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                return;
+            }
+        };
+        let target = match self.parse_fsm_name() {
+            Some(name) => name,
+            None => return,
+        };
+        self.semicolon();
+        if self.last_error().is_some() {
+            return;
+        }
+        self.wordlist_mut()[action].set_hidden(true);
+        if let Some(ref mut fsm) = self.state().fsm {
+            let state = fsm.states.len() - 1;
+            fsm.pending = None;
+            fsm.transitions.push(Transition {
+                state,
+                event,
+                action,
+                target,
+            });
+        }
+    }
+
+    /// Interpretation: ( -- )
+    ///
+    /// End the definition of the finite state machine and compile its
+    /// transition table. Aborts with `UNDEFINED_WORD` if a target state is
+    /// not defined. A later transition for the same state and event
+    /// replaces the earlier one.
+    fn semicolon_fsm(&mut self) {
+        let fsm = match self.state().fsm.take() {
+            Some(ref fsm) if fsm.pending.is_some() || fsm.states.is_empty() => None,
+            fsm => fsm,
+        };
+        let fsm = match fsm {
+            Some(fsm) => fsm,
+            None => {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                return;
+            }
+        };
+        let mut targets = Vec::with_capacity(fsm.transitions.len());
+        for t in &fsm.transitions {
+            match fsm.state(&t.target) {
+                Some(target) => targets.push(target),
+                None => {
+                    self.set_last_token(t.target.clone());
+                    self.abort_with(UNDEFINED_WORD);
+                    return;
+                }
+            }
+        }
+        let states = fsm.states.len();
+        let events = fsm
+            .transitions
+            .iter()
+            .map(|t| t.event + 1)
+            .max()
+            .unwrap_or(0);
+        let table = self.data_space().here();
+        self.data_space().compile_usize(states);
+        self.data_space().compile_usize(events);
+        for _ in 0..states * events {
+            self.data_space().compile_usize(0);
+            self.data_space().compile_usize(0);
+        }
+        for (t, target) in fsm.transitions.iter().zip(targets) {
+            let entry = table + (2 + 2 * (t.state * events + t.event)) * mem::size_of::<usize>();
+            unsafe {
+                self.data_space().put_usize(t.action, entry);
+                self.data_space()
+                    .put_usize(target + 1, entry + mem::size_of::<usize>());
+            }
+        }
+        unsafe {
+            self.data_space()
+                .put_usize(table, fsm.handle + mem::size_of::<usize>());
+        }
+    }
+
+    /// Run-time: ( i*x event handle -- j*x )
+    ///
+    /// Go to the next state of the machine `handle` upon `event`, then
+    /// execute the action of the transition. Nothing is done if `event` is
+    /// not handled in the current state. Aborts with
+    /// `INVALID_NUMERIC_ARGUMENT` if the current state is not one of the
+    /// machine.
+    fn fsm_step(&mut self) {
+        let (event, handle) = self.s_stack().pop2();
+        let handle = handle as usize;
+        let table = match self.fsm_table(handle) {
+            Some(table) => table,
+            None => return,
+        };
+        let events = unsafe { self.data_space().get_usize(table + mem::size_of::<usize>()) };
+        let event = event as usize;
+        if event < events {
+            let state = unsafe { self.data_space().get_usize(handle) };
+            let entry = match self.fsm_entry(table, state, event) {
+                Some(entry) => entry,
+                None => return,
+            };
+            let (action, next) = unsafe {
+                (
+                    self.data_space().get_usize(entry),
+                    self.data_space().get_usize(entry + mem::size_of::<usize>()),
+                )
+            };
+            if next != 0 {
+                unsafe {
+                    self.data_space().put_usize(next - 1, handle);
+                }
+                self.execute_word(action);
+            }
+        }
+    }
+
+    /// Run-time: ( handle -- i )
+    ///
+    /// Current state of the machine `handle`.
+    fn fsm_state(&mut self) {
+        let handle = self.s_stack().pop() as usize;
+        if self.fsm_table(handle).is_some() {
+            let state = unsafe { self.data_space().get_usize(handle) };
+            self.s_stack().push(state as isize);
+        }
+    }
+
+    /// Run-time: ( i handle -- )
+    ///
+    /// Set the current state of the machine `handle` to `i`.
+    fn set_fsm_state(&mut self) {
+        let (state, handle) = self.s_stack().pop2();
+        let handle = handle as usize;
+        if let Some(table) = self.fsm_table(handle) {
+            let states = unsafe { self.data_space().get_usize(table) };
+            if 0 <= state && (state as usize) < states {
+                unsafe {
+                    self.data_space().put_usize(state as usize, handle);
+                }
+            } else {
+                self.abort_with(INVALID_NUMERIC_ARGUMENT);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::Core;
+    use exception::{
+        CONTROL_STRUCTURE_MISMATCH, INVALID_MEMORY_ADDRESS, INVALID_NUMERIC_ARGUMENT,
+        UNDEFINED_WORD,
+    };
+    use mock_vm::VM;
+
+    #[test]
+    fn test_fsm() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "
+            variable log
+            fsm: light
+              state: red
+                0 on  1 log +! => green
+              state: green
+                0 on  10 log +! => yellow
+                1 on  100 log +! => red
+              state: yellow
+                0 on  => red
+            ;fsm
+            light fsm-state
+            0 light fsm-step  light fsm-state
+            0 light fsm-step  light fsm-state
+            1 light fsm-step  light fsm-state
+            0 light fsm-step  light fsm-state
+            5 light fsm-step  light fsm-state
+            : go   0 light fsm-step ;  go  light fsm-state
+            log @
+            2 light fsm-state!  light fsm-state
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [0, 1, 2, 2, 0, 0, 1, 12, 2]);
+    }
+
+    #[test]
+    fn test_fsm_errors() {
+        let vm = &mut VM::new();
+        vm.set_source("fsm: m  state: a  0 on => b  ;fsm");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNDEFINED_WORD));
+        let vm = &mut VM::new();
+        vm.set_source("fsm: m  0 on");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        let vm = &mut VM::new();
+        vm.set_source("fsm: m  state: a  state: a");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        let vm = &mut VM::new();
+        vm.set_source("fsm: m  state: a  0 on => a  ;fsm  100000000 m !  0 m fsm-step");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_NUMERIC_ARGUMENT));
+        let vm = &mut VM::new();
+        vm.set_source("fsm: m  state: a  0 on => a  ;fsm  here m cell+ !  0 m fsm-step");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_MEMORY_ADDRESS));
+    }
+}
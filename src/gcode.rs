@@ -0,0 +1,420 @@
+//! G-code interpreter word set
+//!
+//! ```text
+//! : rapid ( -- )   'X' gcode-param if  ." X " f.  then ;
+//! : feed ( -- )    'F' gcode-param if  ." feed " f.  then ;
+//! ' rapid 0 g-handler
+//! ' feed 1 g-handler
+//!
+//! s" N10 G90 G1 X10.5 F300 (cut)" gcode-line
+//! s" X20" gcode-line
+//! ```
+//!
+//! `gcode-line` parses a line into words, a letter followed by a number,
+//! ignoring spaces, comments in parentheses and everything after `;`.
+//! Parameters, that is, words other than `G`, `M` and `N`, are stored
+//! first, then the handler of each `G` and `M` word is executed in the
+//! order of the line. Handlers are found in a dispatch table indexed by
+//! the integer part of the code, so that `G38.2` is handled by the
+//! handler of `G38`, which may read the exact code with `gcode-code`.
+//!
+//! Codes belonging to a modal group are recorded in the modal state, see
+//! `gcode-modal`, and are accepted without a handler. A line with axis
+//! words but no motion code repeats the current motion mode, so that
+//! `X20` above moves with `G1` again.
+//!
+//! ```text
+//! table: | G handlers | M handlers | modal groups | given | parameters | code |
+//! ```
+//!
+//! Each handler is an execution token, 0 if none. `given` is a bit mask of
+//! the letters of the parameters in the current line, and the parameters
+//! and the current code are floats.
+
+use core::Core;
+use exception::{INVALID_NUMERIC_ARGUMENT, UNSUPPORTED_OPERATION};
+use memory::Memory;
+use parser::{self, IResult};
+use std::mem;
+
+/// Number of codes in the dispatch tables of `G` and of `M` codes.
+pub const GCODE_CODES: usize = 200;
+
+/// Modal groups and the codes in them, the default first.
+pub const MODAL_GROUPS: [(char, &[usize]); 7] = [
+    // Motion
+    (
+        'G',
+        &[
+            0, 1, 2, 3, 33, 38, 73, 76, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89,
+        ],
+    ),
+    // Plane selection
+    ('G', &[17, 18, 19]),
+    // Distance mode
+    ('G', &[90, 91]),
+    // Feed rate mode
+    ('G', &[94, 93, 95]),
+    // Units
+    ('G', &[21, 20]),
+    // Spindle
+    ('M', &[5, 3, 4]),
+    // Coolant
+    ('M', &[9, 7, 8]),
+];
+
+/// Modal group of the motion mode.
+pub const MOTION_GROUP: usize = 0;
+
+/// Letters of axis words, which repeat the motion mode.
+const AXES: &str = "XYZABCUVW";
+
+/// Modal group of code `n` of `letter`.
+pub fn modal_group(letter: char, n: usize) -> Option<usize> {
+    MODAL_GROUPS
+        .iter()
+        .position(|&(l, codes)| l == letter && codes.contains(&n))
+}
+
+/// Words of a line of G-code, letters in upper case, or the index of the
+/// first malformed character.
+pub fn parse_line(line: &str) -> Result<Vec<(char, f64)>, usize> {
+    let mut words = Vec::new();
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if c == b'(' {
+            match line[i..].find(')') {
+                Some(n) => i += n + 1,
+                None => return Err(i),
+            }
+        } else if c == b';' {
+            break;
+        } else if c.is_ascii_alphabetic() {
+            let letter = c.to_ascii_uppercase() as char;
+            i += 1;
+            while i < bytes.len() && (bytes[i] == b' ' || bytes[i] == b'\t') {
+                i += 1;
+            }
+            match number(&bytes[i..]) {
+                Some((rest, value)) => {
+                    i = bytes.len() - rest.len();
+                    words.push((letter, value));
+                }
+                None => return Err(i),
+            }
+        } else {
+            return Err(i);
+        }
+    }
+    Ok(words)
+}
+
+/// Decimal number with optional sign and fraction at the start of `input`.
+fn number(input: &[u8]) -> Option<(&[u8], f64)> {
+    let (rest, sign) = match parser::sign(input) {
+        IResult::Done(rest, sign) => (rest, sign),
+        IResult::Err(_) => return None,
+    };
+    let (after_int, int) = match parser::uint(rest) {
+        IResult::Done(bytes, int) => (bytes, int),
+        IResult::Err(_) => return None,
+    };
+    let (after_fraction, fraction) = match parser::fraction(after_int) {
+        IResult::Done(bytes, fraction) => (bytes, fraction),
+        IResult::Err(_) => return None,
+    };
+    let int_digits = rest.len() - after_int.len();
+    let fraction_digits = after_int.len() - after_fraction.len();
+    if int_digits == 0 && fraction_digits <= 1 {
+        return None;
+    }
+    Some((after_fraction, sign as f64 * (int as f64 + fraction)))
+}
+
+/// Stack effects of the primitives of `Gcode`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("g-handler", "( xt n -- )"),
+    ("m-handler", "( xt n -- )"),
+    ("gcode-line", "( c-addr u -- )"),
+    ("gcode-param", "( char -- flag ) ( F: -- r | )"),
+    ("gcode-code", "( F: -- r )"),
+    ("gcode-modal", "( group -- n )"),
+    ("gcode-reset", "( -- )"),
+];
+
+pub trait Gcode: Core {
+    /// Add G-code words and allocate their tables in data space.
+    fn add_gcode(&mut self) {
+        self.add_primitive("g-handler", Gcode::g_handler);
+        self.add_primitive("m-handler", Gcode::m_handler);
+        self.add_primitive("gcode-line", Gcode::gcode_line);
+        self.add_primitive("gcode-param", Gcode::gcode_param);
+        self.add_primitive("gcode-code", Gcode::gcode_code);
+        self.add_primitive("gcode-modal", Gcode::gcode_modal);
+        self.add_primitive("gcode-reset", Gcode::gcode_reset);
+
+        self.data_space().align();
+        let table = self.data_space().here();
+        let cells = 2 * GCODE_CODES + MODAL_GROUPS.len() + 1;
+        for _ in 0..cells {
+            self.data_space().compile_usize(0);
+        }
+        for _ in 0..27 {
+            self.data_space().compile_f64(0.0);
+        }
+        self.wordlist_mut().set_gcode_table(table);
+        self.gcode_reset();
+        self.declare_effects(STACK_EFFECTS);
+    }
+
+    /// Address of the handler of code `n` of `letter` in the dispatch table.
+    fn gcode_handler_addr(&self, letter: char, n: usize) -> usize {
+        let table = self.wordlist().gcode_table();
+        let offset = if letter == 'M' { GCODE_CODES } else { 0 };
+        table + (offset + n) * mem::size_of::<usize>()
+    }
+
+    /// Address of the current code of modal group `group`.
+    fn gcode_modal_addr(&self, group: usize) -> usize {
+        self.gcode_handler_addr('G', 2 * GCODE_CODES + group)
+    }
+
+    /// Address of the bit mask of the letters of parameters given in the
+    /// current line.
+    fn gcode_given_addr(&self) -> usize {
+        self.gcode_modal_addr(MODAL_GROUPS.len())
+    }
+
+    /// Address of the parameter `letter`, `A` to `Z`, or of the current
+    /// code if `letter` is none of them.
+    fn gcode_param_addr(&self, letter: char) -> usize {
+        let index = if letter.is_ascii_uppercase() {
+            letter as usize - 'A' as usize
+        } else {
+            26
+        };
+        self.gcode_given_addr() + mem::size_of::<usize>() + index * mem::size_of::<f64>()
+    }
+
+    fn register_handler(&mut self, letter: char) {
+        let (xt, n) = self.s_stack().pop2();
+        match self.wordlist().untag_xt(xt) {
+            Some(xt) if n >= 0 && (n as usize) < GCODE_CODES => {
+                let addr = self.gcode_handler_addr(letter, n as usize);
+                unsafe { self.data_space().put_usize(xt, addr) };
+            }
+            _ => self.abort_with(INVALID_NUMERIC_ARGUMENT),
+        }
+    }
+
+    /// Run-time: ( xt n -- )
+    ///
+    /// Execute `xt` upon code `Gn`.
+    fn g_handler(&mut self) {
+        self.register_handler('G');
+    }
+
+    /// Run-time: ( xt n -- )
+    ///
+    /// Execute `xt` upon code `Mn`.
+    fn m_handler(&mut self) {
+        self.register_handler('M');
+    }
+
+    /// Run-time: ( c-addr u -- )
+    ///
+    /// Interpret the line of G-code `c-addr u`. Aborts with
+    /// `INVALID_NUMERIC_ARGUMENT` if the line is malformed, and with
+    /// `UNSUPPORTED_OPERATION` upon a code neither modal nor handled.
+    fn gcode_line(&mut self) {
+        let (caddr, u) = self.s_stack().pop2();
+        let line = unsafe {
+            self.data_space()
+                .str_from_raw_parts(caddr as usize, u as usize)
+        }
+        .to_string();
+        let words = match parse_line(&line) {
+            Ok(words) => words,
+            Err(_) => return self.abort_with(INVALID_NUMERIC_ARGUMENT),
+        };
+        let mut given = 0usize;
+        let mut codes = Vec::new();
+        let mut has_motion = false;
+        let mut has_axes = false;
+        for &(letter, value) in &words {
+            match letter {
+                'G' | 'M' => {
+                    if value < 0.0 || value >= GCODE_CODES as f64 {
+                        return self.abort_with(UNSUPPORTED_OPERATION);
+                    }
+                    if letter == 'G' && modal_group('G', value as usize) == Some(MOTION_GROUP) {
+                        has_motion = true;
+                    }
+                    codes.push((letter, value));
+                }
+                'N' => {}
+                _ => {
+                    given |= 1 << (letter as usize - 'A' as usize);
+                    has_axes |= AXES.contains(letter);
+                    let addr = self.gcode_param_addr(letter);
+                    unsafe { self.data_space().put_f64(value, addr) };
+                }
+            }
+        }
+        let addr = self.gcode_given_addr();
+        unsafe { self.data_space().put_usize(given, addr) };
+        if has_axes && !has_motion {
+            let addr = self.gcode_modal_addr(MOTION_GROUP);
+            let motion = unsafe { self.data_space().get_usize(addr) };
+            codes.push(('G', motion as f64));
+        }
+        for (letter, value) in codes {
+            let n = value as usize;
+            let group = modal_group(letter, n);
+            if let Some(group) = group {
+                let addr = self.gcode_modal_addr(group);
+                unsafe { self.data_space().put_usize(n, addr) };
+            }
+            let xt = unsafe {
+                let addr = self.gcode_handler_addr(letter, n);
+                self.data_space().get_usize(addr)
+            };
+            if xt != 0 {
+                let addr = self.gcode_param_addr(' ');
+                unsafe { self.data_space().put_f64(value, addr) };
+                self.execute_xt(xt);
+                if self.last_error().is_some() {
+                    return;
+                }
+            } else if group.is_none() {
+                return self.abort_with(UNSUPPORTED_OPERATION);
+            }
+        }
+    }
+
+    /// Run-time: ( char -- flag ) ( F: -- r | )
+    ///
+    /// Parameter `char` of the current line, `flag` false if the line does
+    /// not give it.
+    fn gcode_param(&mut self) {
+        let letter = (self.s_stack().pop() as u8 as char).to_ascii_uppercase();
+        if !letter.is_ascii_uppercase() {
+            return self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
+        let given = unsafe {
+            let addr = self.gcode_given_addr();
+            self.data_space().get_usize(addr)
+        };
+        if given & (1 << (letter as usize - 'A' as usize)) != 0 {
+            let value = unsafe {
+                let addr = self.gcode_param_addr(letter);
+                self.data_space().get_f64(addr)
+            };
+            self.f_stack().push(value);
+            self.s_stack().push(-1);
+        } else {
+            self.s_stack().push(0);
+        }
+    }
+
+    /// Run-time: ( F: -- r )
+    ///
+    /// Code being handled, for example 38.2 for `G38.2`.
+    fn gcode_code(&mut self) {
+        let value = unsafe {
+            let addr = self.gcode_param_addr(' ');
+            self.data_space().get_f64(addr)
+        };
+        self.f_stack().push(value);
+    }
+
+    /// Run-time: ( group -- n )
+    ///
+    /// Current code of modal group `group`: 0 motion, 1 plane selection,
+    /// 2 distance mode, 3 feed rate mode, 4 units, 5 spindle (`M`) and
+    /// 6 coolant (`M`).
+    fn gcode_modal(&mut self) {
+        let group = self.s_stack().pop();
+        if group < 0 || group as usize >= MODAL_GROUPS.len() {
+            return self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
+        let n = unsafe {
+            let addr = self.gcode_modal_addr(group as usize);
+            self.data_space().get_usize(addr)
+        };
+        self.s_stack().push(n as isize);
+    }
+
+    /// Run-time: ( -- )
+    ///
+    /// Set all modal groups to their defaults: `G0 G17 G90 G94 G21 M5 M9`.
+    fn gcode_reset(&mut self) {
+        for (group, &(_, codes)) in MODAL_GROUPS.iter().enumerate() {
+            let addr = self.gcode_modal_addr(group);
+            unsafe { self.data_space().put_usize(codes[0], addr) };
+        }
+        let addr = self.gcode_given_addr();
+        unsafe { self.data_space().put_usize(0, addr) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_line;
+    use core::Core;
+    use exception::{INVALID_NUMERIC_ARGUMENT, UNSUPPORTED_OPERATION};
+    use mock_vm::VM;
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse_line("n10 G1 X-1.5 y .25 (comment) Z3. ; rest"),
+            Ok(vec![
+                ('N', 10.0),
+                ('G', 1.0),
+                ('X', -1.5),
+                ('Y', 0.25),
+                ('Z', 3.0)
+            ])
+        );
+        assert_eq!(parse_line("G1 X"), Err(4));
+        assert_eq!(parse_line("G1 (open"), Err(3));
+        assert_eq!(parse_line("G1 X."), Err(4));
+    }
+
+    #[test]
+    fn test_gcode_line() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "
+            variable moves
+            : move   1 moves +!  'X' gcode-param if f>s else 0 then ;
+            : probe   gcode-code 10e f* f>s ;
+            ' move 1 g-handler  ' probe 38 g-handler
+            : t1   s\" N10 G91 G1 X10.5 F300 (cut)\" gcode-line ;
+            : t2   s\" x20 ; modal\" gcode-line ;
+            : t3   s\" G38.2 Z-5\" gcode-line ;
+            t1 t2 t3  moves @  0 gcode-modal  2 gcode-modal  5 gcode-modal
+            'F' gcode-param
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [10, 20, 382, 2, 38, 91, 5, 0]);
+        vm.set_source(": t4   s\" G4 P1\" gcode-line ;  t4");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNSUPPORTED_OPERATION));
+        vm.reset();
+        vm.set_source(": t5   s\" G1 X1.2.3\" gcode-line ;  t5");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_NUMERIC_ARGUMENT));
+        vm.reset();
+        vm.set_source("gcode-reset  0 gcode-modal");
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().as_slice(), [0]);
+    }
+}
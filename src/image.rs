@@ -0,0 +1,376 @@
+//! Process images for PLC-style data exchange
+//!
+//! A process image is a contiguous region of the data space described by a
+//! schema, so that other threads, processes sharing the data space, or C
+//! code can copy it each cycle.
+//!
+//! ```text
+//! 1 image: io
+//!   cell-field: counter
+//!   float-field: setpoint
+//!   byte-field: ready
+//! ;image
+//!
+//! io begin-update  1 counter +!  true ready c!  io end-update
+//! ```
+//!
+//! `io ( -- a-addr )` is the address of the image and each field
+//! `name ( -- addr )` the address of the field in it. The first cell of the
+//! image is a generation counter, odd while an update between
+//! `begin-update` and `end-update` is in progress. Readers detect torn reads
+//! by comparing the generation before and after the copy, see
+//! `ImageSchema::read`.
+//!
+//! ```text
+//! image: | generation | fields ... |
+//! ```
+
+use core::Core;
+use exception::{CONTROL_STRUCTURE_MISMATCH, DICTIONARY_OVERFLOW, INVALID_MEMORY_ADDRESS};
+use memory::Memory;
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+
+/// Type of a field of a process image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    Byte,
+    Cell,
+    Float,
+}
+
+impl FieldType {
+    /// Size and alignment in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            FieldType::Byte => 1,
+            FieldType::Cell => mem::size_of::<isize>(),
+            FieldType::Float => mem::size_of::<f64>(),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            FieldType::Byte => "byte",
+            FieldType::Cell => "cell",
+            FieldType::Float => "float",
+        }
+    }
+}
+
+/// Field of a process image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageField {
+    pub name: String,
+    /// Offset in bytes from the start of the image.
+    pub offset: usize,
+    pub kind: FieldType,
+}
+
+/// Schema of a process image, see `Wordlist::images`.
+///
+/// Displayed as one line `image <name> <version> <size>` followed by a line
+/// `<offset> <type> <name>` for each field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSchema {
+    pub name: String,
+    /// Version of the schema given to `image:`.
+    pub version: usize,
+    /// Address of the image, where the generation counter is.
+    pub start: usize,
+    /// Size of the image in bytes, including the generation counter.
+    pub size: usize,
+    pub fields: Vec<ImageField>,
+    /// Word `name` of the image.
+    pub(crate) xt: usize,
+}
+
+impl ImageSchema {
+    /// Copy the image into `buf` and return its generation, or `None` if
+    /// an update was in progress or happened during the copy. `buf` must
+    /// have at least `size` bytes.
+    ///
+    /// # Safety
+    ///
+    /// The image must still be in the data space of a living virtual
+    /// machine, that is, not forgotten.
+    pub unsafe fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        assert!(buf.len() >= self.size);
+        let generation = &*(self.start as *const AtomicUsize);
+        let before = generation.load(Ordering::Acquire);
+        if before & 1 != 0 {
+            return None;
+        }
+        ptr::copy_nonoverlapping(self.start as *const u8, buf.as_mut_ptr(), self.size);
+        fence(Ordering::Acquire);
+        if generation.load(Ordering::Relaxed) == before {
+            Some(before)
+        } else {
+            None
+        }
+    }
+
+    /// Field `name`.
+    pub fn field(&self, name: &str) -> Option<&ImageField> {
+        self.fields
+            .iter()
+            .find(|f| f.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for ImageSchema {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "image {} {} {}", self.name, self.version, self.size)?;
+        for field in &self.fields {
+            writeln!(f, "{} {} {}", field.offset, field.kind.name(), field.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Process image being defined by `image:` ... `;image`.
+pub struct ImageDefinition {
+    schema: ImageSchema,
+    /// Data fields of the image word and of the field words, whose
+    /// addresses are known at `;image`.
+    dfas: Vec<usize>,
+}
+
+/// Stack effects of the primitives of `ProcessImage`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("image:", "( version \"name\" -- )"),
+    ("byte-field:", "( \"name\" -- )"),
+    ("cell-field:", "( \"name\" -- )"),
+    ("float-field:", "( \"name\" -- )"),
+    (";image", "( -- )"),
+    ("begin-update", "( a-addr -- )"),
+    ("end-update", "( a-addr -- )"),
+];
+
+pub trait ProcessImage: Core {
+    /// Add process image words.
+    fn add_process_image(&mut self) {
+        self.add_primitive("image:", ProcessImage::image_colon);
+        self.add_primitive("byte-field:", ProcessImage::byte_field_colon);
+        self.add_primitive("cell-field:", ProcessImage::cell_field_colon);
+        self.add_primitive("float-field:", ProcessImage::float_field_colon);
+        self.add_primitive(";image", ProcessImage::semicolon_image);
+        self.add_primitive("begin-update", ProcessImage::begin_update);
+        self.add_primitive("end-update", ProcessImage::end_update);
+        self.declare_effects(STACK_EFFECTS);
+    }
+
+    /// Define a constant whose value is set by `;image`, return its data
+    /// field.
+    fn define_image_word(&mut self) -> Option<usize> {
+        self.define(Core::p_const, Core::compile_const);
+        if self.last_error().is_some() {
+            return None;
+        }
+        let dfa = self.data_space().here();
+        self.data_space().compile_usize(0);
+        Some(dfa)
+    }
+
+    /// Interpretation: ( version "name" -- )
+    ///
+    /// Begin the definition of process image `name ( -- a-addr )` with
+    /// schema version `version`.
+    fn image_colon(&mut self) {
+        let version = self.s_stack().pop() as usize;
+        if self.state().image.is_some() {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+            return;
+        }
+        if let Some(dfa) = self.define_image_word() {
+            let xt = self.wordlist().len() - 1;
+            let name = self.word_name(xt);
+            self.state().image = Some(ImageDefinition {
+                schema: ImageSchema {
+                    name,
+                    version,
+                    start: 0,
+                    size: mem::size_of::<usize>(),
+                    fields: Vec::new(),
+                    xt,
+                },
+                dfas: vec![dfa],
+            });
+        }
+    }
+
+    /// Add field `name ( -- addr )` of type `kind`, aligned to its size, to
+    /// the process image being defined.
+    fn image_field(&mut self, kind: FieldType) {
+        if self.state().image.is_none() {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+            return;
+        }
+        if let Some(dfa) = self.define_image_word() {
+            let name = self.word_name(self.wordlist().len() - 1);
+            if let Some(ref mut image) = self.state().image {
+                let align = kind.size();
+                let offset = (image.schema.size + align - 1) & align.wrapping_neg();
+                image.schema.size = offset + kind.size();
+                image.schema.fields.push(ImageField { name, offset, kind });
+                image.dfas.push(dfa);
+            }
+        }
+    }
+
+    /// Interpretation: ( "name" -- )
+    ///
+    /// Add byte field `name ( -- c-addr )`.
+    fn byte_field_colon(&mut self) {
+        self.image_field(FieldType::Byte);
+    }
+
+    /// Interpretation: ( "name" -- )
+    ///
+    /// Add cell field `name ( -- a-addr )`.
+    fn cell_field_colon(&mut self) {
+        self.image_field(FieldType::Cell);
+    }
+
+    /// Interpretation: ( "name" -- )
+    ///
+    /// Add float field `name ( -- f-addr )`.
+    fn float_field_colon(&mut self) {
+        self.image_field(FieldType::Float);
+    }
+
+    /// Interpretation: ( -- )
+    ///
+    /// End the definition of the process image, allot it zero-filled and
+    /// publish its schema. Aborts with `DICTIONARY_OVERFLOW` if there is not
+    /// enough data space.
+    fn semicolon_image(&mut self) {
+        let mut image = match self.state().image.take() {
+            Some(image) => image,
+            None => {
+                self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+                return;
+            }
+        };
+        self.data_space().align_f64();
+        let start = self.data_space().here();
+        let size = image.schema.size;
+        if self.data_space().limit() - start < size {
+            self.abort_with(DICTIONARY_OVERFLOW);
+            return;
+        }
+        self.data_space().allot(size as isize);
+        unsafe {
+            self.data_space()
+                .buffer_from_raw_parts_mut(start, size)
+                .fill(0);
+            self.data_space().put_usize(start, image.dfas[0]);
+            for (field, &dfa) in image.schema.fields.iter().zip(&image.dfas[1..]) {
+                self.data_space().put_usize(start + field.offset, dfa);
+            }
+        }
+        image.schema.start = start;
+        self.wordlist_mut().add_image(image.schema);
+    }
+
+    /// Increment the generation counter of the process image at `a-addr`,
+    /// aborting with `INVALID_MEMORY_ADDRESS` if there is no image there.
+    fn next_generation(&mut self, odd: bool) {
+        let start = self.s_stack().pop() as usize;
+        if !self.wordlist().images().iter().any(|i| i.start == start) {
+            self.abort_with(INVALID_MEMORY_ADDRESS);
+            return;
+        }
+        let generation = unsafe { &*(start as *const AtomicUsize) };
+        let g = generation.load(Ordering::Relaxed);
+        if (g & 1 != 0) == odd {
+            self.abort_with(CONTROL_STRUCTURE_MISMATCH);
+            return;
+        }
+        if odd {
+            generation.store(g.wrapping_add(1), Ordering::Relaxed);
+            fence(Ordering::Release);
+        } else {
+            generation.store(g.wrapping_add(1), Ordering::Release);
+        }
+    }
+
+    /// Run-time: ( a-addr -- )
+    ///
+    /// Begin an update of the process image at `a-addr`.
+    fn begin_update(&mut self) {
+        self.next_generation(true);
+    }
+
+    /// Run-time: ( a-addr -- )
+    ///
+    /// End an update of the process image at `a-addr`, begun by
+    /// `begin-update`.
+    fn end_update(&mut self) {
+        self.next_generation(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FieldType;
+    use core::Core;
+    use exception::{CONTROL_STRUCTURE_MISMATCH, INVALID_MEMORY_ADDRESS, UNDEFINED_WORD};
+    use mock_vm::VM;
+
+    #[test]
+    fn test_process_image() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "
+            3 image: io
+              byte-field: ready
+              cell-field: counter
+              float-field: setpoint
+            ;image
+            counter io -  setpoint io -  ready io -
+            io @  io begin-update  io @
+            5 counter !  true ready c!  io end-update  io @
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [16, 24, 8, 0, 1, 2]);
+        let image = vm.wordlist().image("IO").unwrap().clone();
+        assert_eq!(image.version, 3);
+        assert_eq!(image.size, 32);
+        assert_eq!(image.field("counter").unwrap().kind, FieldType::Cell);
+        assert_eq!(
+            image.to_string(),
+            "image io 3 32\n8 byte ready\n16 cell counter\n24 float setpoint\n"
+        );
+        let mut buf = vec![0; image.size];
+        assert_eq!(unsafe { image.read(&mut buf) }, Some(2));
+        assert_eq!(buf[8], 0xff);
+        assert_eq!(buf[16], 5);
+        vm.set_source("io begin-update");
+        vm.evaluate_input();
+        assert_eq!(unsafe { image.read(&mut buf) }, None);
+        vm.set_source("io begin-update");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        vm.clear_error();
+        vm.set_source("counter begin-update");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(INVALID_MEMORY_ADDRESS));
+        vm.clear_error();
+        vm.set_source("cell-field: x");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(CONTROL_STRUCTURE_MISMATCH));
+        vm.reset();
+        vm.set_source("1 image: broken  cell-field: x  oops");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNDEFINED_WORD));
+        vm.reset();
+        vm.set_source("2 image: other  cell-field: y  ;image");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+    }
+}
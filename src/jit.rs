@@ -0,0 +1,500 @@
+//! Native compilation of colon definitions
+//!
+//! With cargo feature `jit`, a colon definition is compiled upon its first
+//! call into machine code in the code space of `code` definitions, if it is
+//! straight-line code of literals, the stack and arithmetic primitives
+//! below, and calls of such definitions, which are inlined:
+//!
+//! ```text
+//! dup drop swap over nip rot + - and or xor 1+ 1- negate invert
+//! ```
+//!
+//! Definitions with other words, such as branches, stay interpreted. The
+//! stack effect of compiled code is known when compiling it, so that the
+//! data stack is accessed at fixed offsets and its depth is only updated
+//! upon return. Compiled code is deoptimized back to the interpreter for a
+//! call when the data stack holds too few items for it or could overflow,
+//! so that the interpreter reports the error, while breakpoints are set,
+//! and while `strict-math-on` lets `+` and `-` abort on overflow if it uses
+//! them.
+//!
+//! `jit-off` deoptimizes all compiled definitions and stops compiling new
+//! ones until `jit-on`. `jit-stats` shows the counts of definitions
+//! compiled and interpreted, calls of compiled code, deoptimizations, and
+//! the dispatches of the inner interpreter avoided by the compiled code.
+//!
+//! Only x86-64 is supported, definitions stay interpreted on other
+//! architectures.
+
+use core::{Core, Operand};
+use std::fmt::Write;
+
+/// Maximum nesting of inlined calls.
+const MAX_INLINE_DEPTH: usize = 8;
+
+/// Maximum number of operations in a compiled definition.
+const MAX_OPS: usize = 256;
+
+/// Machine code of a compiled colon definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NativeCode {
+    /// Offset in the code space.
+    offset: usize,
+    /// Items on the data stack consumed.
+    need: u8,
+    /// Highest depth of the data stack above the depth upon call.
+    grow: u8,
+    /// Operations compiled, that is, dispatches avoided per call.
+    ops: usize,
+    /// True if `+` or `-` is compiled, which wrap around on overflow.
+    wraps: bool,
+    calls: usize,
+}
+
+/// State of the compiler, shared by all tasks.
+pub struct JitState {
+    enabled: bool,
+    deopts: usize,
+}
+
+impl JitState {
+    pub fn new() -> JitState {
+        JitState {
+            enabled: true,
+            deopts: 0,
+        }
+    }
+
+    /// True if definitions are compiled upon their first call.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+impl Default for JitState {
+    fn default() -> Self {
+        JitState::new()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Op {
+    Lit(isize),
+    Dup,
+    Drop,
+    Swap,
+    Over,
+    Nip,
+    Rot,
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    OnePlus,
+    OneMinus,
+    Negate,
+    Invert,
+}
+
+/// Action of colon definitions not called yet, set by `;` while the
+/// compiler is enabled.
+pub fn first_call<T: Core>(vm: &mut T) {
+    let wp = vm.state().word_pointer();
+    let action = if vm.wordlist().jit().enabled {
+        match compile(vm, wp) {
+            Some(code) => {
+                vm.wordlist_mut()[wp].native = Some(Box::new(code));
+                native::<T> as fn(&mut T)
+            }
+            None => T::nest,
+        }
+    } else {
+        T::nest
+    };
+    vm.wordlist_mut()[wp].set_action(action);
+    action(vm);
+}
+
+/// Action of compiled colon definitions.
+pub fn native<T: Core>(vm: &mut T) {
+    let wp = vm.state().word_pointer();
+    let (offset, need, grow, wraps) = match vm.wordlist()[wp].native {
+        Some(ref code) => (code.offset, code.need, code.grow, code.wraps),
+        None => (usize::MAX, 0, 0, false),
+    };
+    let depth = vm.s_stack().len();
+    let entry = vm.wordlist().code_space().entry(offset);
+    match entry {
+        Some(f)
+            if !vm.wordlist().has_breakpoints()
+                && !(wraps && vm.wordlist().strict_math())
+                && depth >= need
+                && depth as usize + grow as usize <= i8::MAX as usize =>
+        {
+            if let Some(ref mut code) = vm.wordlist_mut()[wp].native {
+                code.calls += 1;
+            }
+            let stack = vm.s_stack();
+            let result = f(stack.inner.as_mut_ptr(), depth as usize);
+            stack.set_len(depth - need);
+            stack.set_len(depth + grow);
+            stack.set_len(result as u8);
+        }
+        _ => {
+            vm.wordlist_mut().jit_mut().deopts += 1;
+            vm.nest();
+        }
+    }
+}
+
+/// True if `action` is the action of a colon definition compiled or to
+/// be compiled.
+pub fn is_colon_action<T: Core>(action: fn(&mut T)) -> bool {
+    let action = action as usize;
+    action == first_call::<T> as fn(&mut T) as usize || action == native::<T> as fn(&mut T) as usize
+}
+
+/// Operations of colon definition `xt` appended to `ops`, `false` if it
+/// cannot be compiled.
+fn translate<T: Core>(vm: &mut T, xt: usize, ops: &mut Vec<Op>, depth: usize) -> bool {
+    let code = match vm.decode(xt) {
+        Some(code) => code,
+        None => return false,
+    };
+    let (lit, exit) = (vm.references().idx_lit, vm.references().idx_exit);
+    for i in code {
+        if ops.len() >= MAX_OPS {
+            return false;
+        }
+        if i.xt == exit {
+            return true;
+        }
+        if i.xt == lit {
+            match i.operand {
+                Operand::Literal(n) => ops.push(Op::Lit(n)),
+                _ => return false,
+            }
+            continue;
+        }
+        let action = vm.wordlist()[i.xt].action() as usize;
+        let primitives = [
+            (T::dup as fn(&mut T), Op::Dup),
+            (T::p_drop, Op::Drop),
+            (T::swap, Op::Swap),
+            (T::over, Op::Over),
+            (T::nip, Op::Nip),
+            (T::rot, Op::Rot),
+            (T::plus, Op::Add),
+            (T::minus, Op::Sub),
+            (T::and, Op::And),
+            (T::or, Op::Or),
+            (T::xor, Op::Xor),
+            (T::one_plus, Op::OnePlus),
+            (T::one_minus, Op::OneMinus),
+            (T::negate, Op::Negate),
+            (T::invert, Op::Invert),
+        ];
+        match primitives.iter().find(|(f, _)| *f as usize == action) {
+            Some(&(_, op)) => ops.push(op),
+            None => {
+                if depth >= MAX_INLINE_DEPTH || !translate(vm, i.xt, ops, depth + 1) {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Compile colon definition `xt`, `None` if it stays interpreted.
+fn compile<T: Core>(vm: &mut T, xt: usize) -> Option<NativeCode> {
+    let mut ops = Vec::new();
+    if !translate(vm, xt, &mut ops, 0) {
+        return None;
+    }
+    let (bytes, need, grow) = x86_64::emit(&ops)?;
+    let offset = vm.wordlist_mut().code_space_mut().add_function(&bytes)?;
+    Some(NativeCode {
+        offset,
+        need,
+        grow,
+        ops: ops.len(),
+        wraps: ops.iter().any(|op| *op == Op::Add || *op == Op::Sub),
+        calls: 0,
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86_64 {
+    use super::Op;
+
+    const RAX: u8 = 0;
+    const RCX: u8 = 1;
+    const RDX: u8 = 2;
+
+    /// Instruction `op` with register `reg` and operand `[rdi+rsi*8+p*8]`,
+    /// the cell `p` above the top of the data stack upon call.
+    fn mem(code: &mut Vec<u8>, op: u8, reg: u8, p: isize) {
+        code.extend_from_slice(&[0x48, op, 0x84 | reg << 3, 0xf7]);
+        code.extend_from_slice(&((p * 8) as i32).to_le_bytes());
+    }
+
+    fn load(code: &mut Vec<u8>, reg: u8, p: isize) {
+        mem(code, 0x8b, reg, p);
+    }
+
+    fn store(code: &mut Vec<u8>, p: isize, reg: u8) {
+        mem(code, 0x89, reg, p);
+    }
+
+    /// Machine code of `ops`, items consumed and highest depth reached.
+    ///
+    /// The data stack is addressed by rdi and its depth is in rsi. Only
+    /// rax, rcx and rdx are used.
+    pub fn emit(ops: &[Op]) -> Option<(Vec<u8>, u8, u8)> {
+        let mut code = Vec::new();
+        // Depth relative to the depth upon call.
+        let mut d: isize = 0;
+        let (mut low, mut high) = (0, 0);
+        for op in ops {
+            match *op {
+                Op::Lit(n) => {
+                    code.extend_from_slice(&[0x48, 0xb8]);
+                    code.extend_from_slice(&(n as i64).to_le_bytes());
+                    store(&mut code, d, RAX);
+                    d += 1;
+                }
+                Op::Dup => {
+                    load(&mut code, RAX, d - 1);
+                    store(&mut code, d, RAX);
+                    low = low.min(d - 1);
+                    d += 1;
+                }
+                Op::Drop => {
+                    d -= 1;
+                }
+                Op::Swap => {
+                    load(&mut code, RAX, d - 1);
+                    load(&mut code, RCX, d - 2);
+                    store(&mut code, d - 1, RCX);
+                    store(&mut code, d - 2, RAX);
+                    low = low.min(d - 2);
+                }
+                Op::Over => {
+                    load(&mut code, RAX, d - 2);
+                    store(&mut code, d, RAX);
+                    low = low.min(d - 2);
+                    d += 1;
+                }
+                Op::Nip => {
+                    load(&mut code, RAX, d - 1);
+                    store(&mut code, d - 2, RAX);
+                    low = low.min(d - 2);
+                    d -= 1;
+                }
+                Op::Rot => {
+                    load(&mut code, RAX, d - 3);
+                    load(&mut code, RCX, d - 2);
+                    load(&mut code, RDX, d - 1);
+                    store(&mut code, d - 3, RCX);
+                    store(&mut code, d - 2, RDX);
+                    store(&mut code, d - 1, RAX);
+                    low = low.min(d - 3);
+                }
+                Op::Add | Op::Sub | Op::And | Op::Or | Op::Xor => {
+                    let opcode = match *op {
+                        Op::Add => 0x01,
+                        Op::Sub => 0x29,
+                        Op::And => 0x21,
+                        Op::Or => 0x09,
+                        _ => 0x31,
+                    };
+                    load(&mut code, RAX, d - 1);
+                    mem(&mut code, opcode, RAX, d - 2);
+                    low = low.min(d - 2);
+                    d -= 1;
+                }
+                Op::OnePlus | Op::OneMinus => {
+                    // add/sub qword [..], 1
+                    let ext = if *op == Op::OnePlus { 0 } else { 5 };
+                    mem(&mut code, 0x83, ext, d - 1);
+                    code.push(1);
+                    low = low.min(d - 1);
+                }
+                Op::Negate | Op::Invert => {
+                    // neg/not qword [..]
+                    let ext = if *op == Op::Negate { 3 } else { 2 };
+                    mem(&mut code, 0xf7, ext, d - 1);
+                    low = low.min(d - 1);
+                }
+            }
+            low = low.min(d);
+            high = high.max(d);
+        }
+        // lea rax, [rsi+d]; ret
+        code.extend_from_slice(&[0x48, 0x8d, 0x86]);
+        code.extend_from_slice(&(d as i32).to_le_bytes());
+        code.push(0xc3);
+        if -low > i8::MAX as isize || high > i8::MAX as isize {
+            return None;
+        }
+        Some((code, -low as u8, high as u8))
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod x86_64 {
+    use super::Op;
+
+    pub fn emit(_: &[Op]) -> Option<(Vec<u8>, u8, u8)> {
+        None
+    }
+}
+
+/// Stack effects of the primitives of `Jit`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("jit-on", "( -- )"),
+    ("jit-off", "( -- )"),
+    ("jit-stats", "( -- )"),
+];
+
+pub trait Jit: Core {
+    /// Add compiler words.
+    fn add_jit(&mut self) {
+        self.add_primitive("jit-on", Jit::jit_on);
+        self.add_primitive("jit-off", Jit::jit_off);
+        self.add_primitive("jit-stats", Jit::jit_stats);
+        self.declare_effects(STACK_EFFECTS);
+    }
+
+    /// ( -- )
+    ///
+    /// Compile colon definitions defined from now on upon their first call.
+    fn jit_on(&mut self) {
+        self.wordlist_mut().jit_mut().enabled = true;
+    }
+
+    /// ( -- )
+    ///
+    /// Deoptimize all compiled definitions and stop compiling.
+    fn jit_off(&mut self) {
+        self.wordlist_mut().jit_mut().enabled = false;
+        for xt in 1..self.wordlist().len() {
+            if is_colon_action(self.wordlist()[xt].action()) {
+                self.wordlist_mut()[xt].native = None;
+                self.wordlist_mut()[xt].set_action(Self::nest);
+            }
+        }
+    }
+
+    /// ( -- )
+    ///
+    /// Show the counts of colon definitions compiled, interpreted and not
+    /// called yet, calls of compiled definitions, deoptimizations and
+    /// dispatches avoided.
+    fn jit_stats(&mut self) {
+        let (mut compiled, mut interpreted, mut pending) = (0, 0, 0);
+        let (mut calls, mut avoided) = (0, 0);
+        let nest = Self::nest as fn(&mut Self) as usize;
+        let first_call = first_call::<Self> as fn(&mut Self) as usize;
+        for xt in 1..self.wordlist().len() {
+            let w = &self.wordlist()[xt];
+            if let Some(ref code) = w.native {
+                compiled += 1;
+                calls += code.calls;
+                avoided += code.calls * code.ops;
+            } else if w.action() as usize == nest {
+                interpreted += 1;
+            } else if w.action() as usize == first_call {
+                pending += 1;
+            }
+        }
+        let deopts = self.wordlist().jit().deopts;
+        if let Some(mut buf) = self.output_buffer().take() {
+            writeln!(
+                buf,
+                "compiled {} interpreted {} pending {}",
+                compiled, interpreted, pending
+            )
+            .unwrap();
+            writeln!(
+                buf,
+                "calls {} deopts {} dispatches avoided {}",
+                calls, deopts, avoided
+            )
+            .unwrap();
+            self.set_output_buffer(buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::Core;
+    use exception::STACK_UNDERFLOW;
+    use mock_vm::VM;
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_jit() {
+        let source = "
+            : sq  dup dup xor over + ;
+            : f  1+ swap 2 - sq rot negate invert nip ;
+            : g  3 0 do f loop ;
+            1 2 3 f  10 20 30 g
+            ";
+        let interpreted = &mut VM::new();
+        interpreted.set_source("jit-off");
+        interpreted.evaluate_input();
+        interpreted.set_source(source);
+        interpreted.evaluate_input();
+        let vm = &mut VM::new();
+        vm.set_source(source);
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [1, 0, 3, 10, 14, 30]);
+        assert_eq!(vm.s_stack().as_slice(), interpreted.s_stack().as_slice());
+        let f = vm.find("f").unwrap();
+        let g = vm.find("g").unwrap();
+        assert!(vm.wordlist()[f].native.is_some());
+        assert!(vm.wordlist()[g].native.is_none());
+        assert!(vm.decode(f).is_some());
+        vm.s_stack().reset();
+        vm.set_source("f");
+        vm.evaluate_input();
+        vm.check_stacks();
+        assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW));
+        vm.clear_error();
+        vm.set_source("jit-off  1 2 3 f");
+        vm.evaluate_input();
+        assert!(vm.wordlist()[f].native.is_none());
+        assert_eq!(vm.s_stack().as_slice(), [1, 0, 3]);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_jit_underflow() {
+        for &(source, call) in &[
+            (": t + ;", "1 t"),
+            (": t xor ;", "1 t"),
+            (": t nip ;", "1 t"),
+            (": t dup ;", "t"),
+            (": t 1 + + ;", "1 t"),
+        ] {
+            let vm = &mut VM::new();
+            vm.set_source(source);
+            vm.evaluate_input();
+            vm.set_source("1 2 3 t drop drop");
+            vm.evaluate_input();
+            assert_eq!(vm.last_error(), None, "{}", source);
+            let t = vm.find("t").unwrap();
+            assert!(vm.wordlist()[t].native.is_some(), "{}", source);
+            vm.s_stack().reset();
+            vm.set_source(call);
+            vm.evaluate_input();
+            vm.check_stacks();
+            assert_eq!(vm.last_error(), Some(STACK_UNDERFLOW), "{}", source);
+        }
+    }
+}
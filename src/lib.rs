@@ -5,28 +5,62 @@
 //! using this library.
 //!
 //! [rtf]: https://crates.io/crates/rtf
+//!
+//! The word sets `facility`, `file_access`, `loader`, `memory_allocation`
+//! and `units` are enabled by cargo features of the same names, all on by
+//! default. Cargo
+//! feature `user-dirs`, also on by default, adds `home-path` and
+//! `config-path` to `file_access`.
+//!
+//! Cargo feature `code`, off by default, adds machine code words `code` and
+//! `end-code`. Features `asm-x86-64` and `asm-aarch64` add an assembler for
+//! the architecture to them. Feature `jit` compiles colon definitions to
+//! machine code upon their first call.
+//!
+//! Cargo feature `gcode`, off by default, adds a G-code interpreter whose
+//! codes are dispatched to Forth words.
+//!
+//! Cargo feature `check-alignment`, off by default, makes debug builds panic
+//! upon fetching or storing a cell at an unaligned address.
 
 extern crate approx;
+#[cfg(feature = "user-dirs")]
+extern crate directories;
 pub extern crate hibitset;
+#[cfg(feature = "units")]
 extern crate uom;
 
+pub mod builder;
+#[cfg(feature = "code")]
+pub mod code;
 pub mod core;
 pub mod env;
 pub mod exception;
+#[cfg(feature = "facility")]
 pub mod facility;
+#[cfg(feature = "file-access")]
 pub mod file_access;
 pub mod float;
+pub mod fsm;
+#[cfg(feature = "gcode")]
+pub mod gcode;
+pub mod image;
+#[cfg(feature = "jit")]
+pub mod jit;
+#[cfg(feature = "loader")]
 pub mod loader;
 pub mod memory;
+#[cfg(feature = "memory-allocation")]
+pub mod memory_allocation;
 mod mock_vm;
 pub mod output;
 pub(crate) mod parser;
+pub mod poll;
 pub mod tools;
+#[cfg(feature = "units")]
 pub mod units;
 
-use core::Core;
 use exception::Exception;
-use memory::Memory;
 use std::result;
 
 pub const TRUE: isize = -1;
@@ -1,25 +1,318 @@
 //! Source input
+//!
+//! `included` opens its source from the `SourceProvider`s registered with
+//! `HasLoader::add_source_provider` in the order of registration, then
+//! from the file system. Embedders serve code from bundled archives, flash
+//! partitions or generated strings this way.
+//!
+//! ```text
+//! let mut sources = MemorySources::new();
+//! sources.add("app/init.fs", include_str!("init.fs"));
+//! vm.add_source_provider(Box::new(sources));
+//! vm.evaluate_str("include app/init.fs");
+//! ```
+//!
+//! Reading a line from a source opened from a file, such as a FIFO or a
+//! file on a network file system, may be given a timeout by
+//! `source-timeout!`, so that a stalled source aborts with
+//! `FILE_IO_TIMEOUT` instead of freezing all tasks. The part of the line
+//! read before the timeout is kept for the next `load-line`. Timeouts are
+//! supported on Unix.
 
-use core::Core;
-use exception::{Exception, FILE_IO_EXCEPTION, INVALID_NUMERIC_ARGUMENT};
+use core::{Core, SourcePosition};
+use exception::{
+    Exception, FILE_IO_EXCEPTION, FILE_IO_TIMEOUT, INVALID_NUMERIC_ARGUMENT, UNSUPPORTED_OPERATION,
+};
+use file_access::FileAccess;
 use memory::Memory;
 use output::Output;
-use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
+use std::cell::Cell;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Cursor, ErrorKind, Read};
+use std::rc::Rc;
+use std::time::Duration;
+use NUM_TASKS;
+
+/// Provider of input sources other than the file system.
+pub trait SourceProvider {
+    /// Reader of the source named `name`, `None` if not provided.
+    fn open(&self, name: &str) -> Option<Box<dyn Read>>;
+}
+
+/// Sources kept in memory, such as those embedded with `include_str!`.
+#[derive(Default)]
+pub struct MemorySources {
+    sources: Vec<(String, String)>,
+}
+
+impl MemorySources {
+    pub fn new() -> MemorySources {
+        MemorySources::default()
+    }
+
+    /// Provide `text` as source `name`, replacing an earlier one.
+    pub fn add(&mut self, name: &str, text: &str) {
+        self.sources.retain(|(n, _)| n != name);
+        self.sources.push((name.to_string(), text.to_string()));
+    }
+}
+
+impl SourceProvider for MemorySources {
+    fn open(&self, name: &str) -> Option<Box<dyn Read>> {
+        self.sources
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, text)| Box::new(Cursor::new(text.clone().into_bytes())) as Box<dyn Read>)
+    }
+}
+
+/// Reader of a file failing with `ErrorKind::TimedOut` if no data arrive
+/// within the timeout shared with its `Source`.
+struct TimedReader {
+    file: File,
+    timeout: Rc<Cell<Option<Duration>>>,
+}
+
+impl Read for TimedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(timeout) = self.timeout.get() {
+            wait_readable(&self.file, timeout)?;
+        }
+        self.file.read(buf)
+    }
+}
+
+/// Wait at most `timeout` for `file` to become readable.
+#[cfg(unix)]
+fn wait_readable(file: &File, timeout: Duration) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    let mut fd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+    loop {
+        match unsafe { libc::poll(&mut fd, 1, ms) } {
+            0 => return Err(io::Error::new(ErrorKind::TimedOut, "read timed out")),
+            n if n > 0 => return Ok(()),
+            _ => {
+                let e = io::Error::last_os_error();
+                if e.kind() != ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+/// Timeouts are not supported, see `source-timeout!`.
+#[cfg(not(unix))]
+fn wait_readable(_file: &File, _timeout: Duration) -> io::Result<()> {
+    Ok(())
+}
 
 pub struct Source {
-    reader: BufReader<File>,
+    reader: Box<dyn BufRead>,
+    /// Path as given when opened.
     path: String,
+    /// Canonical path, or the given path if it cannot be canonicalized or
+    /// the source is from a `SourceProvider`.
+    canonical_path: String,
+    /// Index of the path in `Wordlist::source_file`.
+    file: usize,
+    /// Number of lines loaded.
+    line: usize,
+    /// Timeout of reading a line, shared with the reader.
+    timeout: Rc<Cell<Option<Duration>>>,
+    /// Part of a line read before a timeout.
+    pending: String,
+    /// Size in bytes, 0 if unknown.
+    size: u64,
+    /// Number of bytes loaded.
+    read: u64,
+}
+
+impl Source {
+    /// Path as given when opened.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Absolute path with all symbolic links resolved.
+    pub fn canonical_path(&self) -> &str {
+        &self.canonical_path
+    }
+
+    /// Number of lines loaded.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Timeout of reading a line, `None` if waiting indefinitely.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.get()
+    }
+
+    /// Percentage of the source loaded, 0 if its size is unknown.
+    pub fn progress(&self) -> usize {
+        match (self.read.min(self.size) * 100).checked_div(self.size) {
+            Some(percent) => percent as usize,
+            None => 0,
+        }
+    }
 }
 
-pub trait HasLoader: Core + Output {
+/// Canonical form of `path`, or `path` itself if it cannot be
+/// canonicalized.
+fn canonicalize(path: &str) -> String {
+    match fs::canonicalize(path) {
+        Ok(p) => p.to_string_lossy().into_owned(),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// Stack effects of the primitives of `HasLoader`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("open-source", "( c-addr u file-id -- source-id )"),
+    ("open-provided", "( c-addr u -- source-id | 0 )"),
+    ("close-source", "( source-id -- )"),
+    (".source-path", "( source-id -- )"),
+    ("load-line", "( source-id -- count not-eof? )"),
+    (".source-line", "( source-id -- )"),
+    ("refill", "( -- flag )"),
+    ("source-file?", "( c-addr u -- flag )"),
+    ("source-id!", "( source-id -- )"),
+    ("(close-sources)", "( -- )"),
+    ("(unwind-sources)", "( -- )"),
+    ("source-timeout!", "( n source-id -- )"),
+    ("(loading)", "( source-id i -- )"),
+    ("loading?", "( -- percent flag )"),
+];
+
+pub trait HasLoader: Core + Output + FileAccess {
+    /// Input sources of the current task, source id `i` at index `i - 1`.
+    fn sources(&self) -> &Vec<Option<Source>> {
+        &self.file_storage().sources
+    }
+    fn sources_mut(&mut self) -> &mut Vec<Option<Source>> {
+        &mut self.file_storage_mut().sources
+    }
+    /// Input buffers of the input sources of the current task.
+    fn lines(&self) -> &Vec<Option<String>> {
+        &self.file_storage().lines
+    }
+    fn lines_mut(&mut self) -> &mut Vec<Option<String>> {
+        &mut self.file_storage_mut().lines
+    }
+
     fn add_loader(&mut self) {
         self.add_primitive("open-source", HasLoader::open_source);
+        self.add_primitive("open-provided", HasLoader::open_provided);
         self.add_primitive("close-source", HasLoader::close_source);
         self.add_primitive(".source-path", HasLoader::dot_source_path);
         self.add_primitive("load-line", HasLoader::p_load_line);
         self.add_primitive(".source-line", HasLoader::dot_source_line);
+        self.add_primitive("refill", HasLoader::refill);
+        self.add_primitive("source-file?", HasLoader::source_file_q);
+        self.add_primitive("source-id!", HasLoader::p_set_source_id);
+        self.add_primitive("source-timeout!", HasLoader::source_timeout_store);
+        self.add_primitive("(loading)", HasLoader::p_loading);
+        self.add_primitive("loading?", HasLoader::loading_q);
+        self.add_primitive("(close-sources)", HasLoader::close_sources);
+        let close_sources = self
+            .find("(close-sources)")
+            .expect("(close-sources) undefined");
+        self.wordlist_mut().at_exit(close_sources);
+        self.add_primitive("(unwind-sources)", HasLoader::unwind_sources);
+        let unwind_sources = self
+            .find("(unwind-sources)")
+            .expect("(unwind-sources) undefined");
+        self.wordlist_mut().at_reset(unwind_sources);
+        self.declare_effects(STACK_EFFECTS);
+    }
+
+    /// ( -- )
+    ///
+    /// Close all input sources of the current task and clear their input
+    /// buffers if `Wordlist::close_sources_on_reset`. Executed by `reset`.
+    fn unwind_sources(&mut self) {
+        if self.wordlist().close_sources_on_reset() {
+            for source in self.sources_mut().iter_mut() {
+                *source = None;
+            }
+            for line in self.lines_mut().iter_mut().flatten() {
+                line.clear();
+            }
+        }
+    }
+
+    /// ( source-id -- )
+    ///
+    /// Set source id, also accepting the id of an opened input source.
+    fn p_set_source_id(&mut self) {
+        let id = self.s_stack().pop();
+        if id > 0 {
+            if id - 1 < self.sources().len() as isize
+                && self.sources()[id as usize - 1].is_some()
+                && self.lines()[id as usize - 1].is_some()
+            {
+                self.state().source_id = id;
+            } else {
+                self.abort_with(INVALID_NUMERIC_ARGUMENT);
+            }
+        } else {
+            self.set_source_id(id);
+        }
+    }
+
+    /// ( n source-id -- )
+    ///
+    /// Abort with `FILE_IO_TIMEOUT` if reading a line from input source
+    /// `source-id` waits for more than `n` milliseconds, 0 to wait
+    /// indefinitely. Source id 0 sets the timeout of the sources opened
+    /// from files afterwards, see `Wordlist::source_timeout`.
+    ///
+    /// Timeouts are supported on Unix, elsewhere a non-zero `n` aborts
+    /// with `UNSUPPORTED_OPERATION`. Sources from a `SourceProvider` never
+    /// time out.
+    fn source_timeout_store(&mut self) {
+        let (n, id) = self.s_stack().pop2();
+        if n < 0 {
+            return self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
+        if n > 0 && !cfg!(unix) {
+            return self.abort_with(UNSUPPORTED_OPERATION);
+        }
+        let timeout = if n == 0 {
+            None
+        } else {
+            Some(Duration::from_millis(n as u64))
+        };
+        if id == 0 {
+            self.wordlist_mut().set_source_timeout(timeout);
+        } else if id > 0 && id - 1 < self.sources().len() as isize {
+            match self.sources()[id as usize - 1] {
+                Some(ref source) => source.timeout.set(timeout),
+                None => self.abort_with(INVALID_NUMERIC_ARGUMENT),
+            }
+        } else {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
+    }
+
+    /// ( -- )
+    ///
+    /// Close input sources of all tasks. Executed by `bye`.
+    fn close_sources(&mut self) {
+        let current_task = self.current_task();
+        for i in 0..NUM_TASKS {
+            self.set_current_task(i);
+            for source in self.sources_mut().iter_mut() {
+                *source = None;
+            }
+        }
+        self.set_current_task(current_task);
     }
 
     /// ( c-addr u file-id -- source-id )
@@ -41,23 +334,24 @@ pub trait HasLoader: Core + Output {
         if id > 0 && id - 1 < self.files().len() as isize {
             match self.files_mut()[id as usize - 1].take() {
                 Some(file) => {
-                    let position = self.sources().iter().position(|x| x.is_none());
-                    let reader = BufReader::new(file);
+                    let size = file.metadata().map(|m| m.len()).unwrap_or(0);
                     let path = String::from(unsafe {
                         self.data_space().str_from_raw_parts(caddr as _, u as _)
                     });
-                    match position {
-                        Some(sid) => {
-                            self.sources_mut()[sid] = Some(Source { reader, path });
-                            self.s_stack().push(sid as isize + 1);
-                        }
-                        None => {
-                            let sid = self.sources().len() as isize;
-                            self.s_stack().push(sid as isize + 1);
-                            self.sources_mut().push(Some(Source { reader, path }));
-                            self.lines_mut().push(Some(String::with_capacity(128)));
-                        }
-                    }
+                    let canonical_path = canonicalize(&path);
+                    let timeout = Rc::new(Cell::new(self.wordlist().source_timeout()));
+                    let reader = TimedReader {
+                        file,
+                        timeout: timeout.clone(),
+                    };
+                    let sid = self.insert_source(
+                        Box::new(BufReader::new(reader)),
+                        timeout,
+                        path,
+                        canonical_path,
+                        size,
+                    );
+                    self.s_stack().push(sid as isize);
                 }
                 None => {
                     self.abort_with(INVALID_NUMERIC_ARGUMENT);
@@ -68,6 +362,77 @@ pub trait HasLoader: Core + Output {
         }
     }
 
+    /// ( c-addr u -- source-id | 0 )
+    ///
+    /// Open input source named c-addr u from the first `SourceProvider`
+    /// providing it, 0 if none does.
+    fn open_provided(&mut self) {
+        let (caddr, u) = self.s_stack().pop2();
+        let name =
+            String::from(unsafe { self.data_space().str_from_raw_parts(caddr as _, u as _) });
+        let reader = self
+            .wordlist()
+            .source_providers()
+            .iter()
+            .filter_map(|p| p.open(&name))
+            .next();
+        match reader {
+            Some(reader) => {
+                let sid = self.insert_source(
+                    Box::new(BufReader::new(reader)),
+                    Rc::new(Cell::new(None)),
+                    name.clone(),
+                    name,
+                    0,
+                );
+                self.s_stack().push(sid as isize);
+            }
+            None => self.s_stack().push(0),
+        }
+    }
+
+    /// Put an input source of `size` bytes reading from `reader` with
+    /// `timeout` in the first free slot and return its source id.
+    fn insert_source(
+        &mut self,
+        reader: Box<dyn BufRead>,
+        timeout: Rc<Cell<Option<Duration>>>,
+        path: String,
+        canonical_path: String,
+        size: u64,
+    ) -> usize {
+        self.wordlist_mut().add_loaded_file(&canonical_path);
+        let file = self.wordlist_mut().source_file_index(&canonical_path);
+        let source = Source {
+            reader,
+            path,
+            canonical_path,
+            file,
+            line: 0,
+            timeout,
+            pending: String::new(),
+            size,
+            read: 0,
+        };
+        match self.sources().iter().position(|x| x.is_none()) {
+            Some(i) => {
+                self.sources_mut()[i] = Some(source);
+                i + 1
+            }
+            None => {
+                self.sources_mut().push(Some(source));
+                self.lines_mut().push(Some(String::with_capacity(128)));
+                self.sources().len()
+            }
+        }
+    }
+
+    /// Register `provider` of input sources, consulted by `included` after
+    /// those registered earlier and before the file system.
+    fn add_source_provider(&mut self, provider: Box<dyn SourceProvider>) {
+        self.wordlist_mut().add_source_provider(provider);
+    }
+
     /// ( source-id -- )
     ///
     /// Close input source.
@@ -92,13 +457,15 @@ pub trait HasLoader: Core + Output {
     }
 
     /// ( source-id -- )
+    ///
+    /// Print the canonical path of input source.
     fn dot_source_path(&mut self) {
         let id = self.s_stack().pop();
         if id > 0 && id - 1 < self.sources().len() as isize {
             let source = self.sources_mut()[id as usize - 1].take();
             match source {
                 Some(s) => {
-                    self.push_output(&s.path);
+                    self.push_output(&s.canonical_path);
                     self.sources_mut()[id as usize - 1] = Some(s);
                 }
                 None => self.abort_with(INVALID_NUMERIC_ARGUMENT),
@@ -139,9 +506,89 @@ pub trait HasLoader: Core + Output {
         }
     }
 
+    /// ( source-id i -- )
+    ///
+    /// Let `loading?` report the progress of loading input source
+    /// `source-id` of task `i`, 0 if not opened yet, or nothing if `i` is
+    /// 0. Executed by `included-async`.
+    fn p_loading(&mut self) {
+        let (id, i) = self.s_stack().pop2();
+        if i == 0 {
+            self.wordlist_mut().set_background_load(None);
+        } else if i > 0 && i as usize <= NUM_TASKS && id >= 0 {
+            self.wordlist_mut()
+                .set_background_load(Some((i as usize - 1, id as usize)));
+        } else {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
+    }
+
+    /// ( -- percent flag )
+    ///
+    /// `flag` is true while a file is loaded by `include-async`, with
+    /// `percent` of its bytes loaded so far, 0 if the size of the file is
+    /// unknown. Otherwise `percent` is 100 and `flag` false, also if the
+    /// loading has been aborted.
+    fn loading_q(&mut self) {
+        let progress = match self.wordlist().background_load() {
+            Some((_, 0)) => Some(0),
+            Some((task, id)) => match self
+                .task_file_storage(task)
+                .and_then(|files| files.sources.get(id - 1))
+            {
+                Some(Some(source)) => Some(source.progress()),
+                _ => None,
+            },
+            None => None,
+        };
+        match progress {
+            Some(percent) => self.s_stack().push2(percent as isize, -1),
+            None => {
+                self.wordlist_mut().set_background_load(None);
+                self.s_stack().push2(100, 0);
+            }
+        }
+    }
+
+    /// ( c-addr u -- flag )
+    ///
+    /// True if the source file with path `c-addr u` has been loaded and not
+    /// forgotten by a marker. Paths are compared in canonical form.
+    fn source_file_q(&mut self) {
+        let (caddr, u) = self.s_stack().pop2();
+        let path =
+            canonicalize(unsafe { self.data_space().str_from_raw_parts(caddr as _, u as _) });
+        let loaded = self.wordlist().is_loaded_file(&path);
+        self.s_stack().push(if loaded { -1 } else { 0 });
+    }
+
+    /// ( -- flag )
+    ///
+    /// Load the next line of the input source. `flag` is false if the
+    /// input source is a string from `evaluate`, the terminal, or the end of
+    /// file is reached.
+    ///
+    /// Receiving lines from the terminal is left to the embedder.
+    fn refill(&mut self) {
+        let id = self.source_id();
+        if id > 0 {
+            match self.load_line(id as usize) {
+                Err(e) => self.abort_with(e),
+                Ok((_, not_eof)) => {
+                    self.state().source_index = 0;
+                    self.s_stack().push(if not_eof { -1 } else { 0 });
+                }
+            }
+        } else {
+            self.s_stack().push(0);
+        }
+    }
+
     /// Load a line from file into input buffer.
     ///
-    /// Returns Ok((length, not-eof)) if successful.
+    /// Returns Ok((length, not-eof)) if successful, `FILE_IO_TIMEOUT` if
+    /// the timeout of the source expires first, keeping the part of the
+    /// line read for the next call.
     fn load_line(&mut self, source_id: usize) -> Result<(usize, bool), Exception> {
         // Read line
         if !(source_id > 0 && source_id - 1 < self.sources().len()) {
@@ -161,8 +608,12 @@ pub trait HasLoader: Core + Output {
             }
         };
         line.clear();
+        line.push_str(&source.pending);
+        source.pending.clear();
         let result = match source.reader.read_line(&mut line) {
-            Ok(len) => {
+            Ok(_) => {
+                let len = line.len();
+                source.read += len as u64;
                 let not_eof = !(len == 0);
                 if line.ends_with('\n') {
                     line.truncate(len - 1);
@@ -176,31 +627,22 @@ pub trait HasLoader: Core + Output {
                     Ok((len, not_eof))
                 }
             }
+            Err(ref e) if e.kind() == ErrorKind::TimedOut => {
+                source.pending.push_str(&line);
+                line.clear();
+                Err(FILE_IO_TIMEOUT)
+            }
             Err(_) => Err(FILE_IO_EXCEPTION),
         };
+        if result != Err(FILE_IO_TIMEOUT) {
+            source.line += 1;
+            self.state().source_position = SourcePosition {
+                file: source.file,
+                line: source.line,
+            };
+        }
         self.lines_mut()[source_id - 1] = Some(line);
         self.sources_mut()[source_id - 1] = Some(source);
         result
     }
-
-    fn load_str(&mut self, script: &str) {
-        let mut input_buffer = self.input_buffer().take().unwrap();
-        input_buffer.clear();
-        input_buffer.push_str(script);
-        self.state().source_index = 0;
-        self.set_input_buffer(input_buffer);
-        self.evaluate_input();
-    }
-
-    fn load_core_fth(&mut self) {
-        let libfs = include_str!("../core.fth");
-        self.load_str(libfs);
-        if self.last_error().is_some() {
-            panic!(
-                "Error {:?} {:?}",
-                self.last_error().unwrap(),
-                self.last_token()
-            );
-        }
-    }
 }
@@ -1,28 +1,218 @@
 //! Memory access
 
-use exception::{Exception, INVALID_MEMORY_ADDRESS};
+use exception::{Exception, ALLOCATE, INVALID_MEMORY_ADDRESS};
+#[cfg(not(unix))]
 use std::alloc::{alloc_zeroed, dealloc, Layout};
 use std::marker;
 use std::mem;
+#[cfg(unix)]
+use std::ptr;
 use std::slice;
+use NUM_TASKS;
+
+/// Maximum number of task switches scripted by `schedule"`.
+pub const SCHEDULE_CAPACITY: usize = 64;
+
+/// Priority class of tasks, see `priority!`.
+pub const NORMAL_PRIORITY: u8 = 0;
+pub const HIGH_PRIORITY: u8 = 1;
+
+/// Condition a task waits for, so that `pause` skips it meanwhile.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Wait {
+    None,
+    /// Semaphore at the address to be released, that is, become 0.
+    Semaphore(usize),
+    /// System time in nanoseconds to be reached.
+    Time(u64),
+    /// Completion of the I/O operation with the token, see `PollHandle`.
+    Io(usize),
+}
 
 pub struct SystemVariables {
     null: isize,
     base: isize,
+    solo: isize,
+    low_water: isize,
+    low_water_reached: bool,
+    /// Separator of digit groups in number output, see `group_separator`.
+    group_digits: isize,
+    /// True if floats are output with a decimal comma.
+    decimal_comma: isize,
+    /// True if `flush-output` waits for a key after each page.
+    pager: isize,
+    /// Lines per page of `flush-output`, the height of the terminal less
+    /// one if 0.
+    page_lines: isize,
+    schedule_len: usize,
+    schedule_idx: usize,
+    schedule: [u8; SCHEDULE_CAPACITY],
+    priorities: [u8; NUM_TASKS],
+    /// True if the next task switch is offered to high-priority tasks.
+    high_turn: bool,
+    /// Task which last ran in each priority class.
+    last_run: [u8; 2],
+    waits: [Wait; NUM_TASKS],
 }
 
 impl SystemVariables {
     pub fn base_addr(&self) -> usize {
         &self.base as *const _ as usize
     }
+
+    pub fn solo_addr(&self) -> usize {
+        &self.solo as *const _ as usize
+    }
+
+    pub fn low_water_addr(&self) -> usize {
+        &self.low_water as *const _ as usize
+    }
+
+    pub fn group_digits_addr(&self) -> usize {
+        &self.group_digits as *const _ as usize
+    }
+
+    pub fn decimal_comma_addr(&self) -> usize {
+        &self.decimal_comma as *const _ as usize
+    }
+
+    pub fn pager_addr(&self) -> usize {
+        &self.pager as *const _ as usize
+    }
+
+    pub fn page_lines_addr(&self) -> usize {
+        &self.page_lines as *const _ as usize
+    }
+
+    /// Lines per page of output if the pager is on, for a terminal of
+    /// `rows` rows.
+    pub fn page_lines(&self, rows: usize) -> Option<usize> {
+        match (self.pager, self.page_lines) {
+            (0, _) => None,
+            (_, n) if n > 0 => Some(n as usize),
+            _ => Some(rows.saturating_sub(1).max(1)),
+        }
+    }
+
+    /// Separator of digit groups in number output, `None` if not grouped.
+    ///
+    /// `group-digits on` groups with `,`, or with `.` if `decimal-comma` is
+    /// on. Any other character stored in `group-digits`, such as `_`, is
+    /// used as is.
+    pub fn group_separator(&self) -> Option<char> {
+        match self.group_digits {
+            0 => None,
+            -1 if self.decimal_comma() => Some('.'),
+            -1 => Some(','),
+            c => Some(::std::char::from_u32(c as u32).unwrap_or(',')),
+        }
+    }
+
+    /// True if floats are output with a decimal comma.
+    pub fn decimal_comma(&self) -> bool {
+        self.decimal_comma != 0
+    }
+
+    /// True if unused data space has just dropped below the threshold set
+    /// in `low-water`, which is disabled if 0.
+    ///
+    /// Only reported once until unused space rises above the threshold
+    /// again.
+    pub fn low_water_reached(&mut self, unused: usize) -> bool {
+        let reached = (unused as isize) < self.low_water;
+        let result = reached && !self.low_water_reached;
+        self.low_water_reached = reached;
+        result
+    }
+
+    /// True if only the current task runs.
+    pub fn solo(&self) -> bool {
+        self.solo != 0
+    }
+
+    /// Replace the scripted task switches with `tasks`, 0-based task ids.
+    ///
+    /// Extra tasks beyond `SCHEDULE_CAPACITY` are ignored.
+    pub fn set_schedule(&mut self, tasks: &[u8]) {
+        let len = tasks.len().min(SCHEDULE_CAPACITY);
+        self.schedule[..len].copy_from_slice(&tasks[..len]);
+        self.schedule_len = len;
+        self.schedule_idx = 0;
+    }
+
+    /// Priority class of task `i`, 0-based task id.
+    pub fn priority(&self, i: usize) -> u8 {
+        self.priorities[i]
+    }
+
+    /// Set the priority class of task `i`, 0-based task id.
+    pub fn set_priority(&mut self, i: usize, priority: u8) {
+        self.priorities[i] = priority;
+    }
+
+    /// Priority class to which the next task switch is offered first.
+    /// Alternates between high and normal priority upon each call.
+    pub fn next_turn(&mut self) -> u8 {
+        self.high_turn = !self.high_turn;
+        if self.high_turn {
+            HIGH_PRIORITY
+        } else {
+            NORMAL_PRIORITY
+        }
+    }
+
+    /// Task which last ran in priority class `priority`, 0-based task id.
+    pub fn last_run(&self, priority: u8) -> usize {
+        self.last_run[priority as usize] as usize
+    }
+
+    pub fn set_last_run(&mut self, priority: u8, i: usize) {
+        self.last_run[priority as usize] = i as u8;
+    }
+
+    /// Condition task `i` waits for, 0-based task id.
+    pub fn wait(&self, i: usize) -> Wait {
+        self.waits[i]
+    }
+
+    pub fn set_wait(&mut self, i: usize, wait: Wait) {
+        self.waits[i] = wait;
+    }
+
+    /// Next scripted task switch, 0-based task id.
+    pub fn next_scheduled(&mut self) -> Option<usize> {
+        if self.schedule_idx < self.schedule_len {
+            self.schedule_idx += 1;
+            Some(self.schedule[self.schedule_idx - 1] as usize)
+        } else {
+            None
+        }
+    }
 }
 
+/// Size suggested for data space to grow into, see
+/// `VmBuilder::max_data_capacity`.
+#[cfg(target_pointer_width = "64")]
+pub const DATA_SPACE_RESERVE: usize = 256 * 1024 * 1024;
+#[cfg(not(target_pointer_width = "64"))]
+pub const DATA_SPACE_RESERVE: usize = 16 * 1024 * 1024;
+
+/// Data space of the virtual machine.
+///
+/// Compiled code and data refer to it by absolute addresses, so it never
+/// moves. It is given `reserved` bytes up front, of which only the first
+/// `cap` are accessible, so that `grow` extends it in place. On unix only
+/// address space is reserved and pages are committed as it grows;
+/// elsewhere all `reserved` bytes are allocated at once.
 #[allow(dead_code)]
 pub struct DataSpace {
     pub inner: *mut u8,
-    layout: Layout,
     cap: usize,
+    reserved: usize,
     len: usize,
+    /// Cells where data-space addresses have been stored since
+    /// `record_addresses`, see `Core::load_core_image`.
+    addresses: Option<Vec<usize>>,
     marker: marker::PhantomData<SystemVariables>,
 }
 
@@ -32,24 +222,41 @@ impl DataSpace {
         Self::with_capacity(cap)
     }
 
+    /// Data space of `cap` bytes which cannot grow.
     pub fn with_capacity(cap: usize) -> Self {
-        let ptr: *mut u8;
-        let layout = Layout::from_size_align(cap, page_size::get()).unwrap();
-        unsafe {
-            ptr = alloc_zeroed(layout);
-            if ptr.is_null() {
-                panic!("Cannot allocate data space");
-            }
+        Self::with_reserve(cap, cap)
+    }
+
+    /// Data space of `cap` bytes able to grow up to `reserved` bytes, or
+    /// not at all if `reserved` is not larger than `cap`.
+    pub fn with_reserve(cap: usize, reserved: usize) -> Self {
+        let reserved = round_up_to_page(reserved.max(cap));
+        let ptr = reserve(cap, reserved);
+        if ptr.is_null() {
+            panic!("Cannot allocate data space");
         }
         let mut result = DataSpace {
             inner: ptr,
-            layout,
             cap,
+            reserved,
             len: mem::size_of::<SystemVariables>(),
+            addresses: None,
             marker: marker::PhantomData,
         };
         result.system_variables_mut().null = 0;
         result.system_variables_mut().base = 10;
+        result.system_variables_mut().solo = 0;
+        result.system_variables_mut().low_water = 0;
+        result.system_variables_mut().low_water_reached = false;
+        result.system_variables_mut().group_digits = 0;
+        result.system_variables_mut().decimal_comma = 0;
+        result.system_variables_mut().pager = -1;
+        result.system_variables_mut().page_lines = 0;
+        result.system_variables_mut().set_schedule(&[]);
+        result.system_variables_mut().priorities = [NORMAL_PRIORITY; NUM_TASKS];
+        result.system_variables_mut().high_turn = false;
+        result.system_variables_mut().last_run = [0; 2];
+        result.system_variables_mut().waits = [Wait::None; NUM_TASKS];
         result
     }
 
@@ -62,16 +269,135 @@ impl DataSpace {
     pub fn system_variables_mut(&mut self) -> &mut SystemVariables {
         unsafe { &mut *(self.inner.offset(0) as *mut SystemVariables) }
     }
+
+    /// Record the cells where `put_address` and `compile_address` store
+    /// addresses from now on, until `take_addresses`.
+    pub(crate) fn record_addresses(&mut self) {
+        self.addresses = Some(Vec::new());
+    }
+
+    /// Cells below `here` recorded since `record_addresses`, and stop
+    /// recording.
+    pub(crate) fn take_addresses(&mut self) -> Vec<usize> {
+        self.addresses.take().unwrap_or_default()
+    }
+
+    /// Store data-space address `addr` at `pos`, recording `pos` if
+    /// `record_addresses` is in effect.
+    pub(crate) unsafe fn put_address(&mut self, addr: usize, pos: usize) {
+        self.put_usize(addr, pos);
+        if let Some(ref mut addresses) = self.addresses {
+            addresses.push(pos);
+        }
+    }
+
+    /// Compile data-space address `addr`, see `put_address`.
+    pub(crate) fn compile_address(&mut self, addr: usize) {
+        let pos = self.here();
+        self.compile_usize(addr);
+        if let Some(ref mut addresses) = self.addresses {
+            addresses.push(pos);
+        }
+    }
+
+    /// Bytes the data space can grow to without moving.
+    pub fn reserved(&self) -> usize {
+        self.reserved
+    }
+
+    /// Extend the data space by `bytes`, zeroed, keeping its addresses.
+    ///
+    /// `ALLOCATE` if it would exceed the reserved address space.
+    pub fn grow(&mut self, bytes: usize) -> Result<(), Exception> {
+        let cap = match self.cap.checked_add(bytes) {
+            Some(cap) if cap <= self.reserved => cap,
+            _ => return Err(ALLOCATE),
+        };
+        if commit(self.inner, self.cap, cap) {
+            self.cap = cap;
+            Ok(())
+        } else {
+            Err(ALLOCATE)
+        }
+    }
 }
 
 impl Drop for DataSpace {
     fn drop(&mut self) {
-        unsafe {
-            dealloc(self.inner, self.layout);
+        release(self.inner, self.reserved);
+    }
+}
+
+fn round_up_to_page(bytes: usize) -> usize {
+    let page = page_size::get();
+    bytes.saturating_add(page - 1) / page * page
+}
+
+/// Address space of `reserved` bytes, the first `cap` accessible.
+#[cfg(unix)]
+fn reserve(cap: usize, reserved: usize) -> *mut u8 {
+    let p = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            reserved,
+            libc::PROT_NONE,
+            libc::MAP_PRIVATE | libc::MAP_ANON,
+            -1,
+            0,
+        )
+    };
+    if p == libc::MAP_FAILED {
+        ptr::null_mut()
+    } else if !commit(p as *mut u8, 0, cap) {
+        release(p as *mut u8, reserved);
+        ptr::null_mut()
+    } else {
+        p as *mut u8
+    }
+}
+
+/// Make the reserved bytes from `old_cap` to `cap` accessible. Pages
+/// mapped anonymously read as zeros.
+#[cfg(unix)]
+fn commit(p: *mut u8, old_cap: usize, cap: usize) -> bool {
+    let start = round_up_to_page(old_cap);
+    let end = round_up_to_page(cap);
+    end <= start
+        || unsafe {
+            libc::mprotect(
+                p.add(start) as *mut libc::c_void,
+                end - start,
+                libc::PROT_READ | libc::PROT_WRITE,
+            ) == 0
         }
+}
+
+#[cfg(unix)]
+fn release(p: *mut u8, reserved: usize) {
+    unsafe {
+        libc::munmap(p as *mut libc::c_void, reserved);
     }
 }
 
+/// Without virtual memory primitives all `reserved` bytes are allocated,
+/// zeroed, so that growing only moves `cap`.
+#[cfg(not(unix))]
+fn reserve(_: usize, reserved: usize) -> *mut u8 {
+    let layout = Layout::from_size_align(reserved, page_size::get()).unwrap();
+    unsafe { alloc_zeroed(layout) }
+}
+
+#[cfg(not(unix))]
+fn commit(_: *mut u8, _: usize, _: usize) -> bool {
+    true
+}
+
+#[cfg(not(unix))]
+fn release(p: *mut u8, reserved: usize) {
+    let layout = Layout::from_size_align(reserved, page_size::get()).unwrap();
+    unsafe { dealloc(p, layout) }
+}
+
 impl Memory for DataSpace {
     fn start(&self) -> usize {
         unsafe { self.inner.offset(0) as usize }
@@ -94,6 +420,9 @@ impl Memory for DataSpace {
         if self.start() <= pos && pos <= self.limit() {
             let len = pos as isize - self.start() as isize;
             self.len = len as usize;
+            if let Some(ref mut addresses) = self.addresses {
+                addresses.retain(|&p| p < pos);
+            }
             Ok(())
         } else {
             Err(INVALID_MEMORY_ADDRESS)
@@ -101,6 +430,42 @@ impl Memory for DataSpace {
     }
 }
 
+/// Value of type `T` at `addr`.
+///
+/// Forth allows cells at any address, so that `here 1+ @` is legal. Aligned
+/// addresses, the usual case, are read with a plain load, others with an
+/// unaligned one. With cargo feature `check-alignment`, debug builds panic
+/// upon an unaligned cell access instead, to find programs which are slow
+/// on targets without unaligned loads.
+#[inline(always)]
+unsafe fn load<T: Copy>(addr: usize) -> T {
+    if addr & (mem::align_of::<T>() - 1) == 0 {
+        *(addr as *const T)
+    } else {
+        unaligned(addr);
+        (addr as *const T).read_unaligned()
+    }
+}
+
+/// Store `v` at `addr`, see `load`.
+#[inline(always)]
+unsafe fn store<T: Copy>(v: T, addr: usize) {
+    if addr & (mem::align_of::<T>() - 1) == 0 {
+        *(addr as *mut T) = v;
+    } else {
+        unaligned(addr);
+        (addr as *mut T).write_unaligned(v);
+    }
+}
+
+/// Called upon an unaligned cell access at `addr`.
+#[cold]
+#[allow(unused_variables)]
+fn unaligned(addr: usize) {
+    #[cfg(all(feature = "check-alignment", debug_assertions))]
+    panic!("unaligned cell access at {:#x}", addr);
+}
+
 pub(crate) trait Memory {
     /// Start address
     fn start(&self) -> usize;
@@ -129,19 +494,23 @@ pub(crate) trait Memory {
     }
 
     unsafe fn get_usize(&self, addr: usize) -> usize {
-        *(addr as *mut usize)
+        load(addr)
     }
 
     unsafe fn get_isize(&self, addr: usize) -> isize {
-        *(addr as *mut isize)
+        load(addr)
     }
 
+    /// Float at `addr`, which need not be float-aligned, see
+    /// `Core::compile_float`.
     unsafe fn get_f64(&self, addr: usize) -> f64 {
-        *(addr as *mut f64)
+        (addr as *const f64).read_unaligned()
     }
 
+    /// String compiled by `compile_str` at `addr`, whose length cell need
+    /// not be aligned.
     unsafe fn get_str(&self, addr: usize) -> &str {
-        let len = self.get_usize(addr);
+        let len = (addr as *const usize).read_unaligned();
         let a = addr + mem::size_of::<usize>();
         self.str_from_raw_parts(a, len)
     }
@@ -178,7 +547,7 @@ pub(crate) trait Memory {
     }
 
     unsafe fn put_usize(&mut self, v: usize, pos: usize) {
-        *(pos as *mut usize) = v;
+        store(v, pos);
     }
 
     fn compile_usize(&mut self, v: usize) {
@@ -200,7 +569,7 @@ pub(crate) trait Memory {
     }
 
     unsafe fn put_isize(&mut self, v: isize, pos: usize) {
-        *(pos as *mut isize) = v;
+        store(v, pos);
     }
 
     fn compile_isize(&mut self, v: isize) {
@@ -215,7 +584,7 @@ pub(crate) trait Memory {
         }
     }
     unsafe fn put_f64(&mut self, v: f64, pos: usize) {
-        *(pos as *mut f64) = v;
+        (pos as *mut f64).write_unaligned(v);
     }
 
     fn compile_f64(&mut self, v: f64) {
@@ -255,7 +624,11 @@ pub(crate) trait Memory {
         let here = self.here();
         let len = bytes.len();
         if here + len + mem::size_of::<usize>() <= self.limit() {
-            self.compile_usize(len);
+            // The length is part of the string, stored at any address.
+            unsafe {
+                (here as *mut usize).write_unaligned(len);
+            }
+            self.allot(mem::size_of::<usize>() as isize);
             for byte in bytes {
                 self.compile_u8(*byte);
             }
@@ -0,0 +1,367 @@
+//! Memory-Allocation word set
+//!
+//! `allocate`, `free` and `resize` manage a heap in data space, allotted
+//! when the word set is added, so that the usual `@` and `!` work on the
+//! blocks. Its size is set by `VmBuilder::heap_size`, `HEAP_SIZE` by
+//! default. The bookkeeping is kept outside data space in `Heap`, owned by
+//! the virtual machine, see `MemoryAllocation::heap`.
+//!
+//! Each block records the word calling `allocate` or `resize`, so that
+//! leaks of long-running programs can be found:
+//!
+//! ```text
+//! heap-mark  run-for-a-while  heap-leaks.
+//! ```
+
+use core::Core;
+use exception::{Exception, ALLOCATE, FREE, RESIZE};
+use memory::Memory;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::mem;
+use std::ptr;
+
+/// Default size of the heap in bytes.
+pub const HEAP_SIZE: usize = 16 * 1024;
+
+/// Block returned by `allocate` or `resize`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    pub addr: usize,
+    /// Size in bytes, rounded up to cells.
+    pub size: usize,
+    /// Word calling `allocate` or `resize`, 0 if interpreted.
+    pub xt: usize,
+    /// Number of allocations before this one.
+    pub seq: usize,
+}
+
+/// First-fit allocator of the heap.
+pub struct Heap {
+    start: usize,
+    size: usize,
+    /// Free blocks `(addr, size)` in the order of addresses, adjacent ones
+    /// merged.
+    free: Vec<(usize, usize)>,
+    allocations: BTreeMap<usize, Allocation>,
+    count: usize,
+    mark: usize,
+}
+
+impl Heap {
+    /// Heap of `size` bytes, placed by `place`.
+    pub fn new(size: usize) -> Heap {
+        Heap {
+            start: 0,
+            size,
+            free: Vec::new(),
+            allocations: BTreeMap::new(),
+            count: 0,
+            mark: 0,
+        }
+    }
+
+    /// Manage the `size` bytes at `start`, all free.
+    pub(crate) fn place(&mut self, start: usize) {
+        self.start = start;
+        self.free = vec![(start, self.size)];
+        self.allocations.clear();
+    }
+
+    /// Address of the heap in data space, 0 if not placed.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Size of the heap in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Bytes in allocated blocks.
+    pub fn used(&self) -> usize {
+        self.allocations.values().map(|a| a.size).sum()
+    }
+
+    /// Size of the largest free block.
+    pub fn largest_free(&self) -> usize {
+        self.free.iter().map(|&(_, size)| size).max().unwrap_or(0)
+    }
+
+    /// Blocks allocated and not freed, in the order of addresses.
+    pub fn allocations(&self) -> impl Iterator<Item = &Allocation> {
+        self.allocations.values()
+    }
+
+    /// Number of successful `allocate`s and `resize`s so far.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Let `leaks` report the blocks allocated from now on.
+    pub fn mark(&mut self) {
+        self.mark = self.count;
+    }
+
+    /// Blocks allocated since the last `mark` and not freed, the oldest
+    /// first.
+    pub fn leaks(&self) -> Vec<Allocation> {
+        let mut leaks: Vec<Allocation> = self
+            .allocations
+            .values()
+            .filter(|a| a.seq >= self.mark)
+            .cloned()
+            .collect();
+        leaks.sort_by_key(|a| a.seq);
+        leaks
+    }
+
+    /// Block of at least `size` bytes allocated by word `xt`, `None` if no
+    /// free block is large enough.
+    pub fn allocate(&mut self, size: usize, xt: usize) -> Option<usize> {
+        let cell = mem::size_of::<usize>();
+        let size = size.checked_add(cell - 1)?.max(cell) & !(cell - 1);
+        let i = self.free.iter().position(|&(_, len)| len >= size)?;
+        let (addr, len) = self.free[i];
+        if len == size {
+            self.free.remove(i);
+        } else {
+            self.free[i] = (addr + size, len - size);
+        }
+        let seq = self.count;
+        self.count += 1;
+        self.allocations.insert(
+            addr,
+            Allocation {
+                addr,
+                size,
+                xt,
+                seq,
+            },
+        );
+        Some(addr)
+    }
+
+    /// Free the block at `addr`, false if there is none.
+    pub fn free(&mut self, addr: usize) -> bool {
+        let size = match self.allocations.remove(&addr) {
+            Some(a) => a.size,
+            None => return false,
+        };
+        let i = self
+            .free
+            .iter()
+            .position(|&(a, _)| a > addr)
+            .unwrap_or(self.free.len());
+        self.free.insert(i, (addr, size));
+        if i + 1 < self.free.len() && addr + size == self.free[i + 1].0 {
+            self.free[i].1 += self.free[i + 1].1;
+            self.free.remove(i + 1);
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == addr {
+            self.free[i - 1].1 += self.free[i].1;
+            self.free.remove(i);
+        }
+        true
+    }
+}
+
+/// Stack effects of the primitives of `MemoryAllocation`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("allocate", "( u -- a-addr ior )"),
+    ("free", "( a-addr -- ior )"),
+    ("resize", "( a-addr1 u -- a-addr2 ior )"),
+    ("heap-stats.", "( -- )"),
+    ("heap-mark", "( -- )"),
+    ("heap-leaks.", "( -- )"),
+];
+
+pub trait MemoryAllocation: Core {
+    /// Heap of `allocate` and `free`.
+    fn heap(&self) -> &Heap;
+    fn heap_mut(&mut self) -> &mut Heap;
+
+    /// Add memory allocation words and allot the heap in data space.
+    fn add_memory_allocation(&mut self) {
+        self.add_primitive("allocate", MemoryAllocation::p_allocate);
+        self.add_primitive("free", MemoryAllocation::p_free);
+        self.add_primitive("resize", MemoryAllocation::p_resize);
+        self.add_primitive("heap-stats.", MemoryAllocation::dot_heap_stats);
+        self.add_primitive("heap-mark", MemoryAllocation::heap_mark);
+        self.add_primitive("heap-leaks.", MemoryAllocation::dot_heap_leaks);
+
+        self.data_space().align();
+        let start = self.data_space().here();
+        let size = self.heap().size();
+        self.data_space().allot(size as isize);
+        self.heap_mut().place(start);
+        self.declare_effects(STACK_EFFECTS);
+    }
+
+    /// Word calling the current primitive, 0 if interpreted.
+    fn caller(&mut self) -> usize {
+        let ip = self.state().instruction_pointer;
+        self.wordlist().find_xt(ip).unwrap_or(0)
+    }
+
+    /// Allocate `u` bytes of data space. `allocate ( u -- a-addr ior )`
+    ///
+    /// `ior` is `ALLOCATE` if there is no free block large enough.
+    fn p_allocate(&mut self) {
+        let u = self.s_stack().pop();
+        let xt = self.caller();
+        let addr = if u < 0 {
+            None
+        } else {
+            self.heap_mut().allocate(u as usize, xt)
+        };
+        match addr {
+            Some(addr) => self.s_stack().push2(addr as isize, 0),
+            None => self.s_stack().push2(0, isize::from(ALLOCATE)),
+        }
+    }
+
+    /// Return the block at `a-addr` to the heap. `free ( a-addr -- ior )`
+    ///
+    /// `ior` is `FREE` if `a-addr` is not an allocated block.
+    fn p_free(&mut self) {
+        let addr = self.s_stack().pop() as usize;
+        let ior = if self.heap_mut().free(addr) {
+            0
+        } else {
+            isize::from(FREE)
+        };
+        self.s_stack().push(ior);
+    }
+
+    /// Change the size of the block at `a-addr1` to `u` bytes, moving its
+    /// contents to `a-addr2`. `resize ( a-addr1 u -- a-addr2 ior )`
+    ///
+    /// The new block is allocated before the old one is freed. If it
+    /// fails, `ior` is `RESIZE` and `a-addr2` is `a-addr1`, still allocated.
+    fn p_resize(&mut self) {
+        let (addr, u) = self.s_stack().pop2();
+        match self.resize(addr as usize, u) {
+            Ok(new_addr) => self.s_stack().push2(new_addr as isize, 0),
+            Err(e) => self.s_stack().push2(addr, isize::from(e)),
+        }
+    }
+
+    fn resize(&mut self, addr: usize, u: isize) -> Result<usize, Exception> {
+        let old_size = match self.heap().allocations.get(&addr) {
+            Some(a) => a.size,
+            None => return Err(RESIZE),
+        };
+        if u < 0 {
+            return Err(RESIZE);
+        }
+        let xt = self.caller();
+        let heap = self.heap_mut();
+        let new_addr = heap.allocate(u as usize, xt).ok_or(RESIZE)?;
+        let len = old_size.min(u as usize);
+        unsafe { ptr::copy_nonoverlapping(addr as *const u8, new_addr as *mut u8, len) };
+        heap.free(addr);
+        Ok(new_addr)
+    }
+
+    /// Print the size of the heap, the bytes used and free, the largest
+    /// free block and the number of blocks allocated. `heap-stats. ( -- )`
+    fn dot_heap_stats(&mut self) {
+        if let Some(mut buf) = self.output_buffer().take() {
+            let heap = self.heap();
+            let used = heap.used();
+            writeln!(
+                buf,
+                "total {} used {} free {} largest {} allocations {}",
+                heap.size(),
+                used,
+                heap.size() - used,
+                heap.largest_free(),
+                heap.allocations.len()
+            )
+            .unwrap();
+            self.set_output_buffer(buf);
+        }
+    }
+
+    /// Let `heap-leaks.` report the blocks allocated from now on.
+    /// `heap-mark ( -- )`
+    fn heap_mark(&mut self) {
+        self.heap_mut().mark();
+    }
+
+    /// Print the blocks allocated since `heap-mark` and not freed, one per
+    /// line with its address, size and the word allocating it.
+    /// `heap-leaks. ( -- )`
+    fn dot_heap_leaks(&mut self) {
+        let leaks = self.heap().leaks();
+        if let Some(mut buf) = self.output_buffer().take() {
+            for a in leaks {
+                let name = if a.xt == 0 {
+                    "(interpreted)"
+                } else {
+                    let nfa = self.wordlist()[a.xt].nfa();
+                    unsafe { self.data_space_const().get_str(nfa) }
+                };
+                writeln!(buf, "{:#x} {} {}", a.addr, a.size, name).unwrap();
+            }
+            self.set_output_buffer(buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Heap;
+    use core::Core;
+    use mock_vm::VM;
+
+    #[test]
+    fn test_heap() {
+        let mut heap = Heap::new(64);
+        heap.place(0x1000);
+        let a = heap.allocate(10, 0).expect("a");
+        let b = heap.allocate(16, 0).expect("b");
+        let c = heap.allocate(0, 0).expect("c");
+        assert_eq!((a, b, c), (0x1000, 0x1010, 0x1020));
+        assert_eq!(heap.allocate(40, 0), None);
+        assert!(heap.free(b));
+        assert!(!heap.free(b));
+        assert_eq!(heap.largest_free(), 24);
+        assert!(heap.free(a));
+        assert_eq!(heap.largest_free(), 32);
+        assert!(heap.free(c));
+        assert_eq!(heap.largest_free(), 64);
+        assert_eq!(heap.used(), 0);
+    }
+
+    #[test]
+    fn test_allocate() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "
+            variable p
+            : keep   100 allocate drop ;
+            : t   42 p !  8 allocate drop dup p !  7 swap !
+                  p @ 64 resize drop dup p !  @ ;
+            heap-mark  t  keep drop  p @ free  p @ free  1 -1 resize nip
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [7, 0, -60, -61]);
+        vm.s_stack().reset();
+        vm.set_output_buffer(String::new());
+        vm.set_source("heap-leaks.  heap-stats.  1000000 allocate nip");
+        vm.evaluate_input();
+        assert_eq!(vm.s_stack().as_slice(), [-59]);
+        let output = vm.output_buffer().take().expect("output");
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" 104 keep"));
+        assert_eq!(
+            lines[1],
+            "total 16384 used 104 free 16280 largest 16208 allocations 1"
+        );
+    }
+}
@@ -1,22 +1,31 @@
+use builder::VmBuilder;
+#[cfg(feature = "code")]
+use code::Code;
 use core::{Control, Core, ForwardReferences, Stack, State, Wordlist};
 use env::Environment;
 use exception::Exception;
-use facility::Facility;
-use file_access::FileAccess;
+#[cfg(feature = "facility")]
+use facility::{Alarms, Facility};
+#[cfg(feature = "file-access")]
+use file_access::{FileAccess, FileStorage, Transcript};
 use float::Float;
-use hibitset::BitSet;
+use fsm::Fsm;
+#[cfg(feature = "gcode")]
+use gcode::Gcode;
+use image::ProcessImage;
+#[cfg(feature = "jit")]
+use jit::Jit;
+#[cfg(feature = "loader")]
 use loader::HasLoader;
-use loader::Source;
 use memory::DataSpace;
+#[cfg(feature = "memory-allocation")]
+use memory_allocation::{Heap, MemoryAllocation};
 use output::Output;
-use std::fs::File;
 use tools::Tools;
+#[cfg(feature = "units")]
 use units::Units;
 use NUM_TASKS;
 
-const BUFFER_SIZE: usize = 0x400;
-const LABEL_COUNT: u32 = 1000;
-
 /// Task
 ///
 /// Each task has its own input buffer but shares the
@@ -29,9 +38,8 @@ pub struct Task {
     c_stk: Stack<Control>,
     f_stk: Stack<f64>,
     inbuf: Option<String>,
-    files: Vec<Option<File>>,
-    sources: Vec<Option<Source>>,
-    lines: Vec<Option<String>>,
+    #[cfg(feature = "file-access")]
+    files: FileStorage,
 }
 
 impl Task {
@@ -45,16 +53,15 @@ impl Task {
             c_stk: Stack::new(Control::Canary),
             f_stk: Stack::new(1.234567890),
             inbuf: None,
-            files: Vec::new(),
-            sources: Vec::new(),
-            lines: Vec::new(),
+            #[cfg(feature = "file-access")]
+            files: FileStorage::new(),
         }
     }
 
     /// Create a task with input buffer.
-    pub fn new_terminal() -> Task {
+    pub fn new_terminal(inbuf: String) -> Task {
         let mut task = Task::new_background();
-        task.inbuf = Some(String::with_capacity(BUFFER_SIZE));
+        task.inbuf = Some(inbuf);
         task
     }
 }
@@ -72,22 +79,27 @@ pub struct VM {
     hldbuf: String,
     references: ForwardReferences,
     now: u64,
-    forward_bitset: BitSet,
-    resolved_bitset: BitSet,
-    labels: Vec<usize>,
+    #[cfg(feature = "facility")]
+    alarms: Alarms,
+    #[cfg(feature = "file-access")]
+    transcript: Transcript,
+    #[cfg(feature = "memory-allocation")]
+    heap: Heap,
 }
 
 impl VM {
     /// Create VM
     pub fn new() -> VM {
-        let data_capacity = 64 * 1024;
-        let mut labels = Vec::with_capacity(LABEL_COUNT as _);
-        labels.resize(LABEL_COUNT as _, 0);
+        VM::with_builder(&VmBuilder::new())
+    }
+
+    /// Create VM configured by `builder`.
+    pub fn with_builder(builder: &VmBuilder) -> VM {
         let mut vm = VM {
             current_task: 0,
             tasks: [
                 // Only operator task has its own input buffer.
-                Task::new_terminal(),
+                Task::new_terminal(builder.input_buffer()),
                 Task::new_background(),
                 Task::new_background(),
                 Task::new_background(),
@@ -95,29 +107,21 @@ impl VM {
             ],
             last_error: None,
             handler: 0,
-            wordlist: Wordlist::with_capacity(1000),
-            data_space: DataSpace::with_capacity(data_capacity),
+            wordlist: builder.wordlist(),
+            data_space: builder.data_space(),
             tkn: Some(String::with_capacity(64)),
-            outbuf: Some(String::with_capacity(128)),
+            outbuf: Some(builder.output_buffer()),
             hldbuf: String::with_capacity(128),
             references: ForwardReferences::new(),
             now: 0,
-            forward_bitset: BitSet::with_capacity(LABEL_COUNT),
-            resolved_bitset: BitSet::with_capacity(LABEL_COUNT),
-            labels,
+            #[cfg(feature = "facility")]
+            alarms: Alarms::new(),
+            #[cfg(feature = "file-access")]
+            transcript: Transcript::new(),
+            #[cfg(feature = "memory-allocation")]
+            heap: builder.heap(),
         };
-        vm.add_core();
-        vm.add_output();
-        vm.add_tools();
-        vm.add_environment();
-        vm.add_facility();
-        vm.add_float();
-        vm.add_units();
-        vm.add_file_access();
-        vm.add_loader();
-
-        vm.load_core_fth();
-
+        builder.install(&mut vm);
         vm
     }
 
@@ -159,35 +163,19 @@ impl Core for VM {
         self.tasks[self.current_task].state.source_id
     }
     fn input_buffer(&mut self) -> &mut Option<String> {
-        let source_id = self.source_id();
-        if source_id > 0 {
-            &mut self.lines_mut()[source_id as usize - 1]
-        } else {
-            &mut self.tasks[self.current_task].inbuf
+        #[cfg(feature = "loader")]
+        {
+            let source_id = self.source_id();
+            if source_id > 0 {
+                return &mut self.lines_mut()[source_id as usize - 1];
+            }
         }
+        &mut self.tasks[self.current_task].inbuf
     }
     fn set_input_buffer(&mut self, buffer: String) {
         // self.tasks[self.current_task].inbuf = Some(buffer);
         *self.input_buffer() = Some(buffer);
     }
-    fn files(&self) -> &Vec<Option<File>> {
-        &self.tasks[self.current_task].files
-    }
-    fn files_mut(&mut self) -> &mut Vec<Option<File>> {
-        &mut self.tasks[self.current_task].files
-    }
-    fn sources(&self) -> &Vec<Option<Source>> {
-        &self.tasks[self.current_task].sources
-    }
-    fn sources_mut(&mut self) -> &mut Vec<Option<Source>> {
-        &mut self.tasks[self.current_task].sources
-    }
-    fn lines(&self) -> &Vec<Option<String>> {
-        &self.tasks[self.current_task].lines
-    }
-    fn lines_mut(&mut self) -> &mut Vec<Option<String>> {
-        &mut self.tasks[self.current_task].lines
-    }
     fn last_token(&mut self) -> &mut Option<String> {
         &mut self.tkn
     }
@@ -218,7 +206,15 @@ impl Core for VM {
     fn references(&mut self) -> &mut ForwardReferences {
         &mut self.references
     }
-    fn system_time_ns(&self) -> u64 {
+    #[cfg(feature = "file-access")]
+    fn transcription(&self) -> &Transcript {
+        &self.transcript
+    }
+    #[cfg(feature = "file-access")]
+    fn transcription_mut(&mut self) -> &mut Transcript {
+        &mut self.transcript
+    }
+    fn monotonic_ns(&self) -> u64 {
         self.now
     }
     fn current_task(&self) -> usize {
@@ -245,31 +241,51 @@ impl Core for VM {
             // Do nothing.
         }
     }
-    fn forward_bitset(&self) -> &BitSet {
-        &self.forward_bitset
+}
+
+#[cfg(feature = "code")]
+impl Code for VM {}
+impl Environment for VM {}
+#[cfg(feature = "facility")]
+impl Facility for VM {
+    fn alarms(&self) -> &Alarms {
+        &self.alarms
     }
-    fn forward_bitset_mut(&mut self) -> &mut BitSet {
-        &mut self.forward_bitset
+    fn alarms_mut(&mut self) -> &mut Alarms {
+        &mut self.alarms
     }
-    fn resolved_bitset(&self) -> &BitSet {
-        &self.resolved_bitset
+}
+impl Float for VM {}
+impl Fsm for VM {}
+impl ProcessImage for VM {}
+#[cfg(feature = "jit")]
+impl Jit for VM {}
+#[cfg(feature = "gcode")]
+impl Gcode for VM {}
+#[cfg(feature = "memory-allocation")]
+impl MemoryAllocation for VM {
+    fn heap(&self) -> &Heap {
+        &self.heap
+    }
+    fn heap_mut(&mut self) -> &mut Heap {
+        &mut self.heap
     }
-    fn resolved_bitset_mut(&mut self) -> &mut BitSet {
-        &mut self.resolved_bitset
+}
+#[cfg(feature = "units")]
+impl Units for VM {}
+#[cfg(feature = "file-access")]
+impl FileAccess for VM {
+    fn file_storage(&self) -> &FileStorage {
+        &self.tasks[self.current_task].files
     }
-    fn labels(&self) -> &Vec<usize> {
-        &self.labels
+    fn file_storage_mut(&mut self) -> &mut FileStorage {
+        &mut self.tasks[self.current_task].files
     }
-    fn labels_mut(&mut self) -> &mut Vec<usize> {
-        &mut self.labels
+    fn task_file_storage(&self, i: usize) -> Option<&FileStorage> {
+        self.tasks.get(i).map(|task| &task.files)
     }
 }
-
-impl Environment for VM {}
-impl Facility for VM {}
-impl Float for VM {}
-impl Units for VM {}
-impl FileAccess for VM {}
+#[cfg(feature = "loader")]
 impl HasLoader for VM {}
 impl Output for VM {}
 impl Tools for VM {}
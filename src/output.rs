@@ -1,9 +1,86 @@
 //! Words output to console
 
-use core::Core;
-use exception::{STACK_UNDERFLOW, UNSUPPORTED_OPERATION};
+use core::{Core, OutputOverflow};
+use exception::{
+    INVALID_MEMORY_ADDRESS, INVALID_NUMERIC_ARGUMENT, STACK_UNDERFLOW, UNEXPECTED_END_OF_FILE,
+    UNSUPPORTED_OPERATION,
+};
+#[cfg(feature = "facility")]
+use facility::{Key, KeyEvent};
 use memory::Memory;
 use std::fmt::Write;
+use std::io::{self, Write as IoWrite};
+use std::mem;
+#[cfg(feature = "facility")]
+use std::thread;
+#[cfg(feature = "facility")]
+use std::time::Duration;
+
+/// `number`, the text of a number with an optional sign and fraction,
+/// with the digits of its integer part grouped by `size` with `separator`,
+/// and a decimal comma if `comma`.
+fn localize(number: &str, separator: Option<char>, size: usize, comma: bool) -> String {
+    let (sign, digits) = match number.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", number),
+    };
+    let (integer, fraction) = match digits.find('.') {
+        Some(i) => (&digits[..i], &digits[i + 1..]),
+        None => (digits, ""),
+    };
+    let mut s = String::with_capacity(number.len() * 2);
+    s.push_str(sign);
+    for (i, c) in integer.chars().enumerate() {
+        if let Some(separator) = separator {
+            if i > 0 && (integer.len() - i) % size == 0 {
+                s.push(separator);
+            }
+        }
+        s.push(c);
+    }
+    if digits.len() > integer.len() {
+        s.push(if comma { ',' } else { '.' });
+        s.push_str(fraction);
+    }
+    s
+}
+
+/// Rows of `text` on a terminal `cols` wide, longer lines wrapped, each
+/// with true if it ends a line.
+fn screen_rows(text: &str, cols: usize) -> Vec<(&str, bool)> {
+    let mut rows = Vec::new();
+    for line in text.split('\n') {
+        let mut rest = line;
+        while rest.chars().count() > cols {
+            let (i, _) = rest.char_indices().nth(cols).expect("char");
+            rows.push((&rest[..i], false));
+            rest = &rest[i..];
+        }
+        rows.push((rest, true));
+    }
+    rows
+}
+
+/// Stack effects of the primitives of `Output`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("emit", "( x -- )"),
+    ("type", "( c-addr u -- )"),
+    ("bemit", "( byte -- )"),
+    ("btype", "( c-addr u -- )"),
+    ("s\"", "( \"ccc<quote>\" -- )"),
+    (".\"", "( \"ccc<quote>\" -- )"),
+    (".(", "( \"ccc<paren>\" -- )"),
+    ("text:", "( \"delimiter\" \"lines\" -- )"),
+    (".r", "( n1 n2 -- )"),
+    ("f.r", "( n1 n2 -- ) ( F: r -- )"),
+    ("group-digits", "( -- a-addr )"),
+    ("decimal-comma", "( -- a-addr )"),
+    ("flush-output", "( -- )"),
+    ("pager", "( -- a-addr )"),
+    ("page-lines", "( -- a-addr )"),
+    ("output-limit", "( u n -- )"),
+    ("(.banner)", "( -- )"),
+];
 
 /// Types that can output to console.
 pub trait Output: Core {
@@ -11,14 +88,60 @@ pub trait Output: Core {
     fn add_output(&mut self) {
         self.add_primitive("emit", Output::emit);
         self.add_primitive("type", Output::p_type);
+        self.add_primitive("bemit", Output::bemit);
+        self.add_primitive("btype", Output::btype);
         self.add_immediate_and_compile_only("s\"", Output::s_quote);
         self.add_immediate_and_compile_only(".\"", Output::dot_quote);
         self.add_immediate(".(", Output::dot_paren);
+        self.add_immediate("text:", Output::text_colon);
         self.add_primitive(".r", Output::dot_r);
         self.add_primitive("f.r", Output::fdot_r);
+        self.add_primitive("group-digits", Output::group_digits);
+        self.add_primitive("decimal-comma", Output::decimal_comma);
         self.add_primitive("flush-output", Output::flush_output);
+        self.add_primitive("pager", Output::pager);
+        self.add_primitive("page-lines", Output::page_lines);
+        self.add_primitive("output-limit", Output::output_limit);
+        self.add_primitive("(.banner)", Output::default_banner);
+        let flush_output = self.find("flush-output").expect("flush-output undefined");
+        self.wordlist_mut().at_exit(flush_output);
         self.references().idx_s_quote = self.find("_s\"").expect("_s\" undefined");
         self.references().idx_type = self.find("type").expect("type undefined");
+        self.declare_effects(STACK_EFFECTS);
+    }
+
+    /// Run-time: ( -- )
+    ///
+    /// Default behavior of `.banner`, print name and version of rtForth.
+    fn default_banner(&mut self) {
+        self.push_output(concat!("rtForth v", env!("CARGO_PKG_VERSION")));
+    }
+
+    /// Re-vector `.prompt` to print `prompt`.
+    ///
+    /// `.prompt` is printed by the REPL when not compiling, its default
+    /// is ` ok`.
+    fn set_prompt(&mut self, prompt: &str) {
+        self.revector_to_text(".prompt", prompt);
+    }
+
+    /// Re-vector `.banner` to print `banner`.
+    fn set_banner(&mut self, banner: &str) {
+        self.revector_to_text(".banner", banner);
+    }
+
+    /// Define a word printing `text` and make it the behavior of the
+    /// deferred word `deferred`.
+    fn revector_to_text(&mut self, deferred: &str, text: &str) {
+        self.evaluate_str(&format!(": ({}-text)", deferred));
+        let idx = self.references().idx_s_quote;
+        let compilation_semantics = self.wordlist()[idx].compilation_semantics;
+        compilation_semantics(self, idx);
+        self.data_space().compile_str(text);
+        self.data_space().align();
+        let idx_type = self.references().idx_type;
+        self.compile_word(idx_type);
+        self.evaluate_str(&format!("; ' ({}-text) ' {} defer!", deferred, deferred));
     }
 
     fn push_output(&mut self, text: &str) {
@@ -70,6 +193,47 @@ pub trait Output: Core {
         }
     }
 
+    /// Move the output buffer to the end of the raw output, so that raw
+    /// bytes are output in order with the text before them.
+    fn text_to_raw_output(&mut self) {
+        let text = match self.output_buffer().as_mut() {
+            Some(buf) if !buf.is_empty() => buf.clone(),
+            _ => return,
+        };
+        self.wordlist_mut()
+            .raw_output_mut()
+            .extend_from_slice(text.as_bytes());
+        if let Some(buf) = self.output_buffer().as_mut() {
+            buf.clear();
+        }
+    }
+
+    /// Run-time: ( byte -- )
+    ///
+    /// Output the low 8 bits of `byte` as is, without the UTF-8 encoding
+    /// done by `emit` for characters above 127. For binary protocols.
+    fn bemit(&mut self) {
+        let byte = self.s_stack().pop();
+        self.text_to_raw_output();
+        self.wordlist_mut().raw_output_mut().push(byte as u8);
+    }
+
+    /// Run-time: ( c-addr u -- )
+    ///
+    /// Output the `u` bytes at `c-addr` as is, which need not be valid
+    /// UTF-8.
+    fn btype(&mut self) {
+        let (addr, len) = self.s_stack().pop2();
+        let (addr, len) = (addr as usize, len as usize);
+        if self.data_space().start() <= addr && addr + len <= self.data_space().limit() {
+            self.text_to_raw_output();
+            let bytes = unsafe { self.data_space().buffer_from_raw_parts(addr, len) }.to_vec();
+            self.wordlist_mut().raw_output_mut().extend(bytes);
+        } else {
+            self.abort_with(INVALID_MEMORY_ADDRESS);
+        }
+    }
+
     /// Compilation: ( "ccc<quote>" -- )
     ///
     /// Parse ccc delimited by " (double-quote). Append the run-time semantics given below to the
@@ -101,6 +265,88 @@ pub trait Output: Core {
         self.set_input_buffer(input_buffer);
     }
 
+    /// Compilation: ( "delimiter" "lines" -- )
+    ///
+    /// Parse `delimiter`, then take the following lines of the input source
+    /// up to a line consisting of `delimiter` alone, and compile them like
+    /// `s"`. The rest of the line of `text:` is ignored.
+    ///
+    /// Interpretation: ( "delimiter" "lines" -- c-addr u )
+    ///
+    /// Place the lines in data space.
+    ///
+    /// Each line of the text ends with a newline. Lines are taken raw,
+    /// without escapes, refilling a file input source as needed.
+    ///
+    /// ```text
+    /// : greeting ( -- c-addr u )
+    ///   text: END
+    /// <p>Hello, "world"</p>
+    /// END
+    /// ;
+    /// ```
+    fn text_colon(&mut self) {
+        self.parse_word();
+        let delimiter = self.last_token().clone().unwrap_or_default();
+        if delimiter.is_empty() {
+            return self.abort_with(UNEXPECTED_END_OF_FILE);
+        }
+        self.s_stack().push('\n' as isize);
+        self.parse();
+        let mut text = String::new();
+        let mut refilled = false;
+        loop {
+            let len = self.input_buffer().as_ref().map_or(0, |b| b.len());
+            if self.state().source_index >= len && !refilled {
+                if !self.refill_source() {
+                    return self.abort_with(UNEXPECTED_END_OF_FILE);
+                }
+                refilled = true;
+                continue;
+            }
+            refilled = false;
+            self.s_stack().push('\n' as isize);
+            self.parse();
+            let line = self.last_token().clone().unwrap_or_default();
+            let line = line.trim_end_matches('\r');
+            if line.trim() == delimiter {
+                break;
+            }
+            text.push_str(line);
+            text.push('\n');
+        }
+        if self.state().is_compiling {
+            let idx = self.references().idx_s_quote;
+            let compilation_semantics = self.wordlist()[idx].compilation_semantics;
+            compilation_semantics(self, idx);
+            self.data_space().compile_str(&text);
+            self.data_space().align();
+        } else {
+            self.data_space().align();
+            let addr = self.data_space().compile_str(&text);
+            self.data_space().align();
+            self.s_stack().push2(
+                (addr + mem::size_of::<usize>()) as isize,
+                text.len() as isize,
+            );
+        }
+    }
+
+    /// Load the next line of a file input source with `refill`, false if
+    /// there is none.
+    fn refill_source(&mut self) -> bool {
+        if self.source_id() <= 0 {
+            return false;
+        }
+        match self.find("refill") {
+            Some(refill) => {
+                self.execute_xt(refill);
+                self.last_error().is_none() && self.s_stack().pop() != 0
+            }
+            None => false,
+        }
+    }
+
     /// Compilation: ( "ccc<quote>" -- )
     ///
     /// Parse ccc delimited by " (double-quote). Append the run-time semantics given below to the
@@ -156,6 +402,12 @@ pub trait Output: Core {
                 }
             }
             if valid_base {
+                let separator = self.data_space().system_variables().group_separator();
+                if separator.is_some() {
+                    let size = if base == 10 { 3 } else { 4 };
+                    let grouped = localize(self.hold_buffer(), separator, size, false);
+                    *self.hold_buffer() = grouped;
+                }
                 for _ in 0..(n2 - self.hold_buffer().len() as isize) {
                     buf.push(' ');
                 }
@@ -199,6 +451,12 @@ pub trait Output: Core {
                 16 => write!(self.hold_buffer(), "{:.16}", r).unwrap(),
                 _ => write!(self.hold_buffer(), "{:.17}", r).unwrap(),
             }
+            let separator = self.data_space().system_variables().group_separator();
+            let comma = self.data_space().system_variables().decimal_comma();
+            if (separator.is_some() || comma) && r.is_finite() {
+                let localized = localize(self.hold_buffer(), separator, 3, comma);
+                *self.hold_buffer() = localized;
+            }
             for _ in 0..(n1 - self.hold_buffer().len() as isize) {
                 buf.push(' ');
             }
@@ -207,22 +465,176 @@ pub trait Output: Core {
         }
     }
 
+    /// Run-time: ( -- a-addr )
+    ///
+    /// Variable grouping the digits of numbers output by `.r`, `.`, `f.r`
+    /// and `f.` when on, by thousands in decimal and by four digits in other
+    /// bases. The separator is `,`, or `.` with `decimal-comma` on, unless
+    /// another character is stored, as in `[char] _ group-digits !`.
+    fn group_digits(&mut self) {
+        let addr = self.data_space().system_variables().group_digits_addr();
+        self.s_stack().push(addr as isize);
+    }
+
+    /// Run-time: ( -- a-addr )
+    ///
+    /// Variable letting `f.r` and `f.` output a decimal comma when on.
+    fn decimal_comma(&mut self) {
+        let addr = self.data_space().system_variables().decimal_comma_addr();
+        self.s_stack().push(addr as isize);
+    }
+
+    /// Run-time: ( -- a-addr )
+    ///
+    /// Variable letting `flush-output` prompt `more?` after each page of
+    /// output and wait for a key, on by default. `q` or Escape discards
+    /// the rest of the output. Only output to a terminal of the host of
+    /// known size is paged.
+    fn pager(&mut self) {
+        let addr = self.data_space().system_variables().pager_addr();
+        self.s_stack().push(addr as isize);
+    }
+
+    /// Run-time: ( -- a-addr )
+    ///
+    /// Variable holding the lines per page of `pager`, the height of the
+    /// terminal less one if 0.
+    fn page_lines(&mut self) {
+        let addr = self.data_space().system_variables().page_lines_addr();
+        self.s_stack().push(addr as isize);
+    }
+
+    /// Run-time: ( u n -- )
+    ///
+    /// Limit the output buffer to `u` bytes, no limit if `u` is 0. Output
+    /// beyond the limit is handled according to `n`:
+    ///
+    /// * 0: discard the oldest output,
+    /// * 1: discard the new output,
+    /// * 2: discard the new output and abort.
+    fn output_limit(&mut self) {
+        let (u, n) = self.s_stack().pop2();
+        let policy = match n {
+            0 => OutputOverflow::DropOldest,
+            1 => OutputOverflow::DropNewest,
+            2 => OutputOverflow::Error,
+            _ => {
+                self.abort_with(INVALID_NUMERIC_ARGUMENT);
+                return;
+            }
+        };
+        let max_len = if u == 0 { usize::MAX } else { u as usize };
+        self.set_output_limit(max_len, policy);
+    }
+
+    /// Print raw output then the output buffer to standard output.
     fn flush_output(&mut self) {
-        match self.output_buffer().as_mut() {
-            Some(buf) => {
-                if buf.len() > 0 {
-                    println!("{}", buf);
-                    buf.clear();
+        if !self.wordlist().raw_output().is_empty() {
+            let raw = mem::take(self.wordlist_mut().raw_output_mut());
+            let mut stdout = io::stdout();
+            stdout.write_all(&raw).ok();
+            stdout.flush().ok();
+            self.transcribe(&raw);
+        }
+        if let Some(mut buf) = self.output_buffer().take() {
+            if !buf.is_empty() {
+                self.print_paged(&buf);
+                buf.push('\n');
+                self.transcribe(buf.as_bytes());
+                buf.clear();
+            }
+            self.set_output_buffer(buf);
+        }
+    }
+
+    /// Print `text` and a newline to standard output, a page at a time
+    /// if `pager` is on, see `Output::pager`.
+    fn print_paged(&mut self, text: &str) {
+        let (cols, lines) = match self.page_size() {
+            Some(size) => size,
+            None => {
+                println!("{}", text);
+                return;
+            }
+        };
+        let mut stdout = io::stdout();
+        let mut row = 0;
+        for (s, end) in screen_rows(text, cols) {
+            if row == lines {
+                if !self.more() {
+                    return;
                 }
+                row = 0;
             }
-            None => {}
+            if end {
+                writeln!(stdout, "{}", s).ok();
+            } else {
+                write!(stdout, "{}", s).ok();
+            }
+            row += 1;
         }
     }
+
+    /// Columns of the terminal of the host and lines per page, `None` if
+    /// output is not paged.
+    #[cfg(feature = "facility")]
+    fn page_size(&mut self) -> Option<(usize, usize)> {
+        let (cols, rows) = self.wordlist_mut().terminal_mut()?.size()?;
+        let lines = self.data_space().system_variables().page_lines(rows)?;
+        Some((cols.max(1), lines))
+    }
+
+    #[cfg(not(feature = "facility"))]
+    fn page_size(&mut self) -> Option<(usize, usize)> {
+        None
+    }
+
+    /// Prompt `more?` and wait for a key from the terminal of the host,
+    /// false if it is `q`, Escape or Ctrl-C.
+    #[cfg(feature = "facility")]
+    fn more(&mut self) -> bool {
+        let mut stdout = io::stdout();
+        write!(stdout, "more? ").ok();
+        stdout.flush().ok();
+        let event = match self.wordlist_mut().terminal_mut() {
+            Some(terminal) => {
+                terminal.set_raw_mode(true);
+                let event = loop {
+                    match terminal.poll_key() {
+                        Some(KeyEvent {
+                            key: Key::Resize, ..
+                        }) => {}
+                        Some(event) => break Some(event),
+                        None => thread::sleep(Duration::from_millis(10)),
+                    }
+                };
+                terminal.set_raw_mode(false);
+                event
+            }
+            None => None,
+        };
+        write!(stdout, "\r      \r").ok();
+        match event {
+            Some(e) => {
+                !(e.key == Key::Char('q')
+                    || e.key == Key::Escape
+                    || (e.ctrl && e.key == Key::Char('c')))
+            }
+            None => true,
+        }
+    }
+
+    #[cfg(not(feature = "facility"))]
+    fn more(&mut self) -> bool {
+        true
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use core::Core;
+    use super::{screen_rows, Output};
+    use core::{Core, OutputOverflow};
+    use exception::{EXCEPTION_IN_SENDING_OR_RECEIVING_A_CHARACTER, UNEXPECTED_END_OF_FILE};
     use mock_vm::VM;
 
     #[test]
@@ -235,6 +647,62 @@ mod tests {
         assert_eq!(vm.output_buffer().clone().unwrap(), "Hi, how are you");
     }
 
+    #[test]
+    fn test_text_colon() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            ": page   text: END ignored
+<p>\"hi\"</p>
+  END
+; page type  text: EOT
+a
+EOT
+type",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), []);
+        assert_eq!(vm.output_buffer().clone().unwrap(), "<p>\"hi\"</p>\na\n");
+        vm.set_source("text: END\nno end");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), Some(UNEXPECTED_END_OF_FILE));
+    }
+
+    #[test]
+    fn test_group_digits() {
+        let vm = &mut VM::new();
+        vm.set_source(
+            "
+            group-digits on  1234567 .  -999 .  -1000 .  1234567.891e 0 3 f.r
+            decimal-comma on  1234567 .  -1234.5e 0 2 f.r
+            group-digits off  0.25e f.
+            char _ group-digits !  decimal-comma off  hex 12345 .  decimal
+            ",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(
+            vm.output_buffer().clone().unwrap(),
+            "1,234,567 -999 -1,000 1,234,567.891\
+             1.234.567 -1.234,50\
+             0,2500000 \
+             1_2345 "
+        );
+    }
+
+    #[cfg(feature = "loader")]
+    #[test]
+    fn test_text_colon_in_file() {
+        let path = ::std::env::temp_dir().join("rtforth-test-text-colon.fs");
+        ::std::fs::write(&path, ": t   text: END\nline 1\n\nline 3\nEND\n;\n")
+            .expect("write source file");
+        let vm = &mut VM::new();
+        vm.set_source(&format!("include {}  t type", path.to_str().expect("path")));
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.output_buffer().clone().unwrap(), "line 1\n\nline 3\n");
+    }
+
     #[test]
     fn test_emit() {
         let vm = &mut VM::new();
@@ -244,4 +712,137 @@ mod tests {
         assert_eq!(vm.s_stack().as_slice(), []);
         assert_eq!(vm.output_buffer().clone().unwrap(), "*+");
     }
+
+    #[test]
+    fn test_prompt() {
+        let vm = &mut VM::new();
+        vm.set_source(".prompt");
+        vm.evaluate_input();
+        assert_eq!(vm.output_buffer().clone().unwrap(), " ok");
+        vm.output_buffer().as_mut().unwrap().clear();
+        vm.set_prompt(" \"ready\"");
+        vm.set_source(".prompt");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), []);
+        assert_eq!(vm.output_buffer().clone().unwrap(), " \"ready\"");
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let vm = &mut VM::new();
+        vm.set_source("8 1 output-limit  1 . 2 . 3 . 4 . 5 .");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.output_buffer().clone().unwrap(), "1 2 3 4 ");
+        assert!(vm.output_overflowed());
+        vm.clear_output_overflowed();
+        vm.output_buffer().as_mut().unwrap().clear();
+        vm.set_output_limit(4, OutputOverflow::DropOldest);
+        vm.set_source("1 . 2 . 3 .");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.output_buffer().clone().unwrap(), "2 3 ");
+        assert!(vm.output_overflowed());
+        vm.output_buffer().as_mut().unwrap().clear();
+        vm.set_source("0 2 output-limit  4 2 output-limit  1 . 2 . 3 .");
+        vm.evaluate_input();
+        assert_eq!(
+            vm.last_error(),
+            Some(EXCEPTION_IN_SENDING_OR_RECEIVING_A_CHARACTER)
+        );
+        assert_eq!(vm.output_buffer().clone().unwrap(), "1 2 ");
+        vm.reset();
+        for source in &["1 2 3 .s .s .s .s", "words", ": t   1 2 3 .s .s .s .s ;  t"] {
+            vm.output_buffer().as_mut().unwrap().clear();
+            vm.set_source("10 1 output-limit");
+            vm.evaluate_input();
+            vm.set_source(source);
+            vm.evaluate_input();
+            assert_eq!(vm.last_error(), None, "{}", source);
+            assert_eq!(vm.output_buffer().as_ref().unwrap().len(), 10, "{}", source);
+            vm.s_stack().reset();
+        }
+    }
+
+    #[test]
+    fn test_bemit_and_btype() {
+        let vm = &mut VM::new();
+        vm.set_source(": bytes   s\" ab\" btype ;  65 emit 233 bemit bytes 66 emit");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.wordlist().raw_output().as_slice(), b"A\xe9ab");
+        assert_eq!(vm.output_buffer().clone().unwrap(), "B");
+        vm.wordlist_mut().raw_output_mut().clear();
+        vm.output_buffer().as_mut().unwrap().clear();
+        vm.set_source("hex 1 fg 7 bg ff bemit normal");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(
+            vm.wordlist().raw_output().as_slice(),
+            b"\x1b[31m\x1b[47m\xff"
+        );
+        assert_eq!(vm.output_buffer().clone().unwrap(), "\x1b[0m");
+    }
+
+    #[test]
+    fn test_screen_rows() {
+        assert_eq!(
+            screen_rows("abcdefg\n\nhé", 3),
+            [
+                ("abc", false),
+                ("def", false),
+                ("g", true),
+                ("", true),
+                ("hé", true)
+            ]
+        );
+    }
+
+    #[cfg(feature = "facility")]
+    #[test]
+    fn test_pager() {
+        use facility::{Key, KeyEvent, Terminal};
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        /// Terminal of 10 by 4 recording the keys read.
+        struct Screen(Rc<RefCell<Vec<char>>>, Vec<char>);
+
+        impl Terminal for Screen {
+            fn set_raw_mode(&mut self, _raw: bool) -> bool {
+                true
+            }
+            fn poll_key(&mut self) -> Option<KeyEvent> {
+                let c = self.1.pop()?;
+                self.0.borrow_mut().push(c);
+                Some(KeyEvent {
+                    key: Key::Char(c),
+                    shift: false,
+                    ctrl: false,
+                    alt: false,
+                })
+            }
+            fn size(&mut self) -> Option<(usize, usize)> {
+                Some((10, 4))
+            }
+        }
+
+        let vm = &mut VM::new();
+        let read = Rc::new(RefCell::new(Vec::new()));
+        vm.wordlist_mut()
+            .set_terminal(Box::new(Screen(read.clone(), vec!['x', 'q', ' '])));
+        vm.set_output_buffer(String::new());
+        vm.set_source(
+            "pager @  page-lines @  : lines   9 0 do i . cr loop  9 . ;  lines flush-output",
+        );
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(vm.s_stack().as_slice(), [-1, 0]);
+        assert_eq!(*read.borrow(), [' ', 'q']);
+        vm.set_source("5 page-lines !  lines flush-output  pager off  lines flush-output");
+        vm.evaluate_input();
+        assert_eq!(vm.last_error(), None);
+        assert_eq!(*read.borrow(), [' ', 'q', 'x']);
+    }
 }
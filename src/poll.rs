@@ -0,0 +1,75 @@
+//! Completion of asynchronous I/O by the host
+//!
+//! rtForth tasks are cooperative and cannot block in an I/O call. Hosts
+//! driving sockets or serial ports with an event loop such as mio or tokio
+//! start the operation on behalf of a task and post its completion through
+//! a `PollHandle`, which can be sent to other threads. The task waits with
+//! `io-wait` and is skipped by `pause` until then.
+//!
+//! ```text
+//! \ Forth: ( token ) is handed to the host by a primitive of the embedder.
+//! io-token dup start-read  io-wait ( result )
+//! ```
+//!
+//! ```text
+//! // Host, in the primitive `start-read ( token -- )`:
+//! let token = vm.s_stack().pop() as usize;
+//! let handle = vm.wordlist().poll_handle();
+//! tokio::spawn(async move {
+//!     let n = socket.read(&mut buf).await.map_or(-1, |n| n as isize);
+//!     handle.complete(token, n);
+//! });
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct Completions {
+    next_token: usize,
+    /// Results of the completed operations not yet taken.
+    done: Vec<(usize, isize)>,
+}
+
+/// Handle to post completions of I/O operations into a virtual machine.
+///
+/// All clones refer to the same virtual machine, see
+/// `Wordlist::poll_handle`.
+#[derive(Clone, Default)]
+pub struct PollHandle {
+    inner: Arc<Mutex<Completions>>,
+}
+
+impl PollHandle {
+    pub fn new() -> PollHandle {
+        PollHandle::default()
+    }
+
+    /// New token identifying an I/O operation, never 0.
+    pub fn new_token(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_token = inner.next_token.wrapping_add(1).max(1);
+        inner.next_token
+    }
+
+    /// Post the completion of operation `token` with `result`, waking the
+    /// task waiting for it.
+    pub fn complete(&self, token: usize, result: isize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.done.retain(|&(t, _)| t != token);
+        inner.done.push((token, result));
+    }
+
+    /// True if operation `token` has completed and its result is not yet
+    /// taken.
+    pub fn is_complete(&self, token: usize) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.done.iter().any(|&(t, _)| t == token)
+    }
+
+    /// Take the result of operation `token`, `None` if not completed.
+    pub fn take(&self, token: usize) -> Option<isize> {
+        let mut inner = self.inner.lock().unwrap();
+        let i = inner.done.iter().position(|&(t, _)| t == token)?;
+        Some(inner.done.swap_remove(i).1)
+    }
+}
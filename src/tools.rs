@@ -1,8 +1,746 @@
 //! Tools to inspect the rtforth system
 
+use core::{Core, Instruction, Operand, SourcePosition, Stack, Word};
+use exception::{
+    FLOATING_POINT_STACK_UNDERFLOW, INVALID_NUMERIC_ARGUMENT, INVALID_XT, STACK_UNDERFLOW,
+    UNDEFINED_WORD, UNEXPECTED_END_OF_FILE, UNSUPPORTED_OPERATION,
+};
+#[cfg(feature = "jit")]
+use jit;
 use memory::Memory;
 use output::Output;
+use std::collections::HashMap;
+use std::f64;
 use std::fmt::Write;
+use std::hint;
+use std::mem;
+
+/// Maximum number of suggestions for an undefined word.
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Failures reported by `post`.
+pub const POST_DICTIONARY: isize = 1;
+pub const POST_DATA_SPACE: isize = 2;
+pub const POST_STACKS: isize = 4;
+pub const POST_FLOAT: isize = 8;
+pub const POST_TIMER: isize = 16;
+
+/// Number of events kept by the trace ring.
+pub const TRACE_CAPACITY: usize = 4096;
+
+/// Event recorded by `trace!`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// System time in nanoseconds.
+    pub timestamp: u64,
+    pub task: usize,
+    pub id: isize,
+}
+
+/// Ring buffer of the latest `TRACE_CAPACITY` trace events.
+pub struct TraceRing {
+    events: Vec<TraceEvent>,
+    /// Index of the oldest event once the ring is full.
+    next: usize,
+}
+
+impl TraceRing {
+    pub fn new() -> TraceRing {
+        TraceRing {
+            events: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// Record `event`, overwriting the oldest one if the ring is full.
+    pub fn record(&mut self, event: TraceEvent) {
+        if self.events.len() < TRACE_CAPACITY {
+            if self.events.capacity() == 0 {
+                self.events.reserve_exact(TRACE_CAPACITY);
+            }
+            self.events.push(event);
+        } else {
+            self.events[self.next] = event;
+            self.next = (self.next + 1) % TRACE_CAPACITY;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.next = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Recorded events, the oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &TraceEvent> {
+        self.events[self.next..]
+            .iter()
+            .chain(self.events[..self.next].iter())
+    }
+
+    /// Recorded events in Chrome trace-event format, as instant events
+    /// named by their ids on the threads of their tasks.
+    pub fn to_chrome_json(&self) -> String {
+        let mut json = String::from("{\"traceEvents\":[");
+        for (i, e) in self.iter().enumerate() {
+            if i != 0 {
+                json.push(',');
+            }
+            write!(
+                json,
+                "\n{{\"name\":\"{}\",\"ph\":\"i\",\"s\":\"t\",\"ts\":{}.{:03},\"pid\":0,\"tid\":{}}}",
+                e.id,
+                e.timestamp / 1_000,
+                e.timestamp % 1_000,
+                e.task
+            )
+            .unwrap();
+        }
+        json.push_str("\n]}\n");
+        json
+    }
+}
+
+impl Default for TraceRing {
+    fn default() -> Self {
+        TraceRing::new()
+    }
+}
+
+/// Number of results kept by the cache of a memoized word. The cache is
+/// emptied when full.
+pub const MEMO_CAPACITY: usize = 256;
+
+/// Cache of a word memoized by `memoize` or `fmemoize`, keyed by the bit
+/// patterns of its inputs.
+pub struct Memo<Target> {
+    pub xt: usize,
+    /// True if inputs and outputs are on the floating-point stack.
+    pub float: bool,
+    pub inputs: usize,
+    pub outputs: usize,
+    /// Action of the word before it was memoized.
+    action: fn(&mut Target),
+    cache: HashMap<Vec<u64>, Vec<u64>>,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl<Target> Memo<Target> {
+    /// Number of cached results.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Empty the cache and clear the statistics.
+    pub fn clear(&mut self) {
+        self.cache.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+}
+
+/// Control flow graph of a colon definition, see `cfg-export`.
+///
+/// Instructions are grouped into basic blocks, which are entered only at
+/// their first instruction and left only after their last one.
+pub struct ControlFlowGraph {
+    name: String,
+    /// Text of the instructions of each block.
+    blocks: Vec<Vec<String>>,
+    /// Edges from a block to a block, or to the exit of the definition if
+    /// `None`, with labels.
+    edges: Vec<(usize, Option<usize>, String)>,
+}
+
+impl ControlFlowGraph {
+    /// Graph of definition `name` decoded into `code` by `Core::decode`.
+    pub fn new(name: &str, code: &[Instruction]) -> ControlFlowGraph {
+        let index = |addr: usize| code.iter().position(|i| i.addr == addr);
+        // Loops as ranges from `_do` or `_qdo` to the address after the loop.
+        let loops: Vec<(usize, usize)> = code
+            .iter()
+            .filter_map(|i| match (i.name.as_str(), &i.operand) {
+                ("_do", &Operand::Branch(exit)) | ("_qdo", &Operand::Branch(exit)) => {
+                    Some((i.addr, exit))
+                }
+                _ => None,
+            })
+            .collect();
+        let leave_target = |addr: usize| {
+            loops
+                .iter()
+                .filter(|&&(start, exit)| start < addr && addr < exit)
+                .map(|&(_, exit)| exit)
+                .min()
+        };
+        // Targets of each instruction, with labels, and whether it falls
+        // through to the next one.
+        let jumps: Vec<(Vec<(usize, String)>, bool)> = code
+            .iter()
+            .map(|i| match (i.name.as_str(), &i.operand) {
+                ("branch", &Operand::Branch(t)) => (vec![(t, String::new())], false),
+                ("0branch", &Operand::Branch(t)) => (vec![(t, "0".to_string())], true),
+                ("_qdo", &Operand::Branch(t)) => (vec![(t, "skip".to_string())], true),
+                ("_loop", &Operand::Branch(t)) | ("_+loop", &Operand::Branch(t)) => {
+                    (vec![(t, "loop".to_string())], true)
+                }
+                ("_jump-table", Operand::JumpTable(dests)) => (
+                    dests
+                        .iter()
+                        .enumerate()
+                        .map(|(n, &t)| (t, n.to_string()))
+                        .collect(),
+                    true,
+                ),
+                ("leave", _) => match leave_target(i.addr) {
+                    Some(t) => (vec![(t, "leave".to_string())], false),
+                    None => (vec![], false),
+                },
+                ("exit", _) => (vec![], false),
+                _ => (vec![], true),
+            })
+            .collect();
+        let mut leader = vec![false; code.len()];
+        if !code.is_empty() {
+            leader[0] = true;
+        }
+        for (n, (targets, _)) in jumps.iter().enumerate() {
+            for &(t, _) in targets {
+                if let Some(k) = index(t) {
+                    leader[k] = true;
+                }
+            }
+            let ends_block = !targets.is_empty() || !jumps[n].1 || code[n].name == "_do";
+            if ends_block && n + 1 < code.len() {
+                leader[n + 1] = true;
+            }
+        }
+        let mut block_of = vec![0; code.len()];
+        let mut blocks: Vec<Vec<String>> = Vec::new();
+        for (n, i) in code.iter().enumerate() {
+            if leader[n] {
+                blocks.push(Vec::new());
+            }
+            block_of[n] = blocks.len() - 1;
+            let text = match i.operand {
+                Operand::Literal(x) => format!("{} {}", i.name, x),
+                Operand::FLiteral(r) => format!("{} {:e}", i.name, r),
+                Operand::Str(_, len) => format!("{} ({} chars)", i.name, len),
+                _ => i.name.clone(),
+            };
+            blocks[block_of[n]].push(text);
+        }
+        let mut edges = Vec::new();
+        for (n, (targets, falls_through)) in jumps.into_iter().enumerate() {
+            let last = n + 1 == code.len() || leader[n + 1];
+            if !last {
+                continue;
+            }
+            let from = block_of[n];
+            for (t, label) in targets {
+                edges.push((from, index(t).map(|k| block_of[k]), label));
+            }
+            if falls_through {
+                let to = if n + 1 < code.len() {
+                    Some(block_of[n + 1])
+                } else {
+                    None
+                };
+                edges.push((from, to, String::new()));
+            } else if code[n].name == "exit" {
+                edges.push((from, None, String::new()));
+            }
+        }
+        ControlFlowGraph {
+            name: name.to_string(),
+            blocks,
+            edges,
+        }
+    }
+
+    /// Number of basic blocks.
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Graph in Graphviz dot format, the exit of the definition as node
+    /// `exit`.
+    pub fn to_dot(&self) -> String {
+        fn escape(s: &str) -> String {
+            s.replace('\\', "\\\\").replace('"', "\\\"")
+        }
+        let mut dot = String::new();
+        writeln!(dot, "digraph \"{}\" {{", escape(&self.name)).unwrap();
+        writeln!(dot, "  node [shape=box, fontname=monospace];").unwrap();
+        for (n, block) in self.blocks.iter().enumerate() {
+            let mut label = String::new();
+            for text in block {
+                label.push_str(&escape(text));
+                label.push_str("\\l");
+            }
+            writeln!(dot, "  b{} [label=\"{}\"];", n, label).unwrap();
+        }
+        writeln!(dot, "  exit [shape=doublecircle];").unwrap();
+        for &(from, to, ref label) in &self.edges {
+            let to = match to {
+                Some(to) => format!("b{}", to),
+                None => "exit".to_string(),
+            };
+            if label.is_empty() {
+                writeln!(dot, "  b{} -> {};", from, to).unwrap();
+            } else {
+                writeln!(dot, "  b{} -> {} [label=\"{}\"];", from, to, label).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Suspicious pattern found by `Tools::lint`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LintKind {
+    /// Branch to a label which is not defined.
+    UnresolvedLabel,
+    /// Branch into another definition.
+    ForeignBranch,
+    /// `exit` inside a do-loop without `unloop`.
+    ExitInLoop,
+    /// Code which cannot be reached.
+    Unreachable,
+}
+
+impl LintKind {
+    pub fn description(self) -> &'static str {
+        match self {
+            LintKind::UnresolvedLabel => "unresolved label",
+            LintKind::ForeignBranch => "branch into another definition",
+            LintKind::ExitInLoop => "exit inside do-loop without unloop",
+            LintKind::Unreachable => "unreachable code",
+        }
+    }
+}
+
+/// Finding of `Tools::lint`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LintWarning {
+    pub kind: LintKind,
+    /// Definition where found.
+    pub xt: usize,
+    /// Address of the instruction.
+    pub addr: usize,
+    /// Where the definition was defined.
+    pub position: SourcePosition,
+}
+
+/// Check the instructions `code` of a colon definition, see `Tools::lint`.
+fn lint_code(code: &[Instruction]) -> Vec<(LintKind, usize)> {
+    let mut found = Vec::new();
+    if code.is_empty() {
+        return found;
+    }
+    let (first, last) = (code[0].addr, code[code.len() - 1].addr);
+    let index = |addr: usize| code.iter().position(|i| i.addr == addr);
+    let loops: Vec<(usize, usize)> = code
+        .iter()
+        .filter_map(|i| match (i.name.as_str(), &i.operand) {
+            ("_do", &Operand::Branch(exit)) | ("_qdo", &Operand::Branch(exit)) => {
+                Some((i.addr, exit))
+            }
+            _ => None,
+        })
+        .collect();
+    let enclosing = |addr: usize| {
+        loops
+            .iter()
+            .filter(move |&&(start, exit)| start < addr && addr < exit)
+            .map(|&(_, exit)| exit)
+    };
+    // Successors of each instruction, as addresses.
+    let mut successors: Vec<Vec<usize>> = Vec::with_capacity(code.len());
+    let mut roots = vec![first];
+    for (n, i) in code.iter().enumerate() {
+        let next = code.get(n + 1).map(|i| i.addr);
+        let mut targets = Vec::new();
+        let mut falls_through = true;
+        match (i.name.as_str(), &i.operand) {
+            ("branch", &Operand::Branch(t)) => {
+                targets.push(t);
+                falls_through = false;
+            }
+            (_, &Operand::Branch(t)) if i.name != "_do" => targets.push(t),
+            (_, Operand::JumpTable(dests)) => targets.extend(dests),
+            // Return address of `call`.
+            ("lit", &Operand::Literal(t))
+                if code.get(n + 1).map(|i| i.name.as_str()) == Some(">r") =>
+            {
+                targets.push(t as usize)
+            }
+            ("leave", _) => {
+                targets.extend(enclosing(i.addr).min());
+                falls_through = false;
+            }
+            ("exit", _) => {
+                falls_through = false;
+                if n > 0 && code[n - 1].name == "_does" {
+                    // Code executed by the words created.
+                    roots.extend(next);
+                }
+                let unloops = code[..n]
+                    .iter()
+                    .rev()
+                    .take_while(|i| i.name == "unloop")
+                    .count();
+                if enclosing(i.addr).count() > unloops {
+                    found.push((LintKind::ExitInLoop, i.addr));
+                }
+            }
+            _ => {}
+        }
+        for &t in &targets {
+            if t == 0 || (first <= t && t <= last && index(t).is_none()) {
+                found.push((LintKind::UnresolvedLabel, i.addr));
+            } else if t < first || t > last {
+                found.push((LintKind::ForeignBranch, i.addr));
+            }
+        }
+        if falls_through {
+            targets.extend(next);
+        }
+        successors.push(targets);
+    }
+    let mut reached = vec![false; code.len()];
+    let mut pending: Vec<usize> = roots.into_iter().filter_map(index).collect();
+    while let Some(n) = pending.pop() {
+        if !reached[n] {
+            reached[n] = true;
+            pending.extend(successors[n].iter().filter_map(|&t| index(t)));
+        }
+    }
+    for n in 0..code.len() {
+        // The `exit` compiled by `;` after an endless loop is not reported.
+        let trailing_exit = n + 1 == code.len() && code[n].name == "exit";
+        if !reached[n] && (n == 0 || reached[n - 1]) && !trailing_exit {
+            found.push((LintKind::Unreachable, code[n].addr));
+        }
+    }
+    found.sort_by_key(|&(_, addr)| addr);
+    found
+}
+
+/// Name and body hash of a word in a dictionary digest.
+///
+/// Digests saved by `save-digest` are compared by `diff_digests` to audit
+/// the changes between two builds of an application.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordDigest {
+    pub name: String,
+    pub hash: u64,
+}
+
+/// Difference between two dictionary digests, see `diff_digests`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WordChange {
+    Added(String),
+    Removed(String),
+    /// Same name, different body.
+    Changed(String),
+}
+
+/// FNV-1a hash, stable across builds and platforms unlike `DefaultHasher`.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_i64(&mut self, v: i64) {
+        self.write(&v.to_le_bytes());
+    }
+}
+
+/// Digest of the visible words in the order of definition.
+///
+/// The hash of a colon definition covers the names of the words compiled
+/// and their operands, with branch destinations relative to the
+/// definition, so that it does not depend on where the definition is. The
+/// hash of a constant covers its value, and that of other words only their
+/// kind and flags.
+pub fn dictionary_digest<T: Core>(vm: &mut T) -> Vec<WordDigest> {
+    let mut names: Vec<(usize, String)> = Vec::new();
+    for w in (1..vm.wordlist().len()).rev() {
+        if !vm.wordlist()[w].is_hidden() {
+            let name = vm.word_name(w);
+            if !name.is_empty() && !names.iter().any(|(_, n)| n.eq_ignore_ascii_case(&name)) {
+                names.push((w, name));
+            }
+        }
+    }
+    let mut digest = Vec::with_capacity(names.len());
+    for (xt, name) in names.into_iter().rev() {
+        let mut h = Fnv::new();
+        let word = &vm.wordlist()[xt];
+        h.write(&[word.is_immediate() as u8, word.is_compile_only() as u8]);
+        let action = word.action() as usize;
+        let dfa = word.dfa();
+        if let Some(code) = vm.decode(xt) {
+            h.write(b"colon");
+            let relative = |d: usize| d as i64 - dfa as i64;
+            for i in &code {
+                h.write(i.name.as_bytes());
+                match i.operand {
+                    Operand::None => {}
+                    Operand::Literal(n) => h.write_i64(n as i64),
+                    Operand::FLiteral(f) => h.write_i64(f.to_bits() as i64),
+                    Operand::Str(p, len) => {
+                        h.write(unsafe { vm.data_space().buffer_from_raw_parts(p, len) })
+                    }
+                    Operand::Branch(d) => h.write_i64(relative(d)),
+                    Operand::JumpTable(ref dests) => {
+                        for &d in dests {
+                            h.write_i64(relative(d));
+                        }
+                    }
+                }
+                h.write(&[0]);
+            }
+        } else if action == T::p_const as fn(&mut T) as usize {
+            h.write(b"constant");
+            h.write_i64(unsafe { vm.data_space().get_isize(dfa) } as i64);
+        } else {
+            h.write(b"word");
+        }
+        digest.push(WordDigest { name, hash: h.0 });
+    }
+    digest
+}
+
+/// Hash of the names and bodies of the visible words, combining their
+/// hashes in `dictionary_digest`. See `seal-dict`.
+pub fn dictionary_hash<T: Core>(vm: &mut T) -> u64 {
+    let mut h = Fnv::new();
+    for w in dictionary_digest(vm) {
+        h.write(w.name.as_bytes());
+        h.write(&[0]);
+        h.write_i64(w.hash as i64);
+    }
+    h.0
+}
+
+/// Text of `digest` saved by `save-digest`, one line `<hash> <name>` per
+/// word with the hash in 16 hexadecimal digits.
+pub fn format_digest(digest: &[WordDigest]) -> String {
+    let mut text = String::new();
+    for w in digest {
+        writeln!(text, "{:016x} {}", w.hash, w.name).unwrap();
+    }
+    text
+}
+
+/// Text of the build log of `vm` saved by `build-manifest`, one line
+/// `<timestamp> <start> <end> <file>:<line> <name>` per definition, the
+/// oldest first, with the wall-clock time in nanoseconds since the Unix
+/// epoch, the data space in hexadecimal and `-` as file if not loaded
+/// from a file.
+pub fn format_manifest<T: Core>(vm: &mut T) -> String {
+    let here = vm.data_space().here();
+    vm.wordlist_mut().build_log_mut().close(here);
+    let wordlist = vm.wordlist();
+    let mut text = String::new();
+    for e in wordlist.build_log().iter() {
+        writeln!(
+            text,
+            "{} {:#x} {:#x} {}:{} {}",
+            e.timestamp,
+            e.start,
+            e.end,
+            wordlist.source_file(e.position.file).unwrap_or("-"),
+            e.position.line,
+            e.name
+        )
+        .unwrap();
+    }
+    text
+}
+
+/// Digest in `text` written by `format_digest`, ignoring malformed lines.
+pub fn parse_digest(text: &str) -> Vec<WordDigest> {
+    text.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ' ');
+            let hash = u64::from_str_radix(parts.next()?, 16).ok()?;
+            let name = parts.next()?;
+            Some(WordDigest {
+                name: name.to_string(),
+                hash,
+            })
+        })
+        .collect()
+}
+
+/// Words added, removed or changed from `old` to `new`: first those
+/// removed in the order of `old`, then those added or changed in the order
+/// of `new`. Names are compared without regard to case.
+pub fn diff_digests(old: &[WordDigest], new: &[WordDigest]) -> Vec<WordChange> {
+    let find = |digest: &[WordDigest], name: &str| {
+        digest
+            .iter()
+            .find(|w| w.name.eq_ignore_ascii_case(name))
+            .map(|w| w.hash)
+    };
+    let mut changes: Vec<WordChange> = old
+        .iter()
+        .filter(|w| find(new, &w.name).is_none())
+        .map(|w| WordChange::Removed(w.name.clone()))
+        .collect();
+    for w in new {
+        match find(old, &w.name) {
+            None => changes.push(WordChange::Added(w.name.clone())),
+            Some(hash) if hash != w.hash => changes.push(WordChange::Changed(w.name.clone())),
+            Some(_) => {}
+        }
+    }
+    changes
+}
+
+/// True if floating-point arithmetic behaves as IEEE 754 double
+/// precision with rounding to nearest, see `Tools::post`.
+///
+/// Operands go through `black_box` so that the checks are done by the
+/// target at run time, not folded by the compiler.
+fn post_float() -> bool {
+    let (tenth, fifth, three_tenths) = hint::black_box((0.1f64, 0.2f64, 0.3f64));
+    let (zero, one, two, two_and_half) = hint::black_box((0.0f64, 1.0f64, 2.0f64, 2.5f64));
+    let divisor = hint::black_box(0.0f64);
+    let checks = [
+        tenth + fifth > three_tenths,
+        tenth + fifth - three_tenths < 4.0 * f64::EPSILON,
+        one / 3.0 * 3.0 == one,
+        one + f64::EPSILON / 2.0 == one,
+        one + f64::EPSILON > one,
+        two_and_half.round() == 3.0,
+        (-two_and_half).round() == -3.0,
+        (f64::MAX * two).is_infinite(),
+        (zero / divisor).is_nan(),
+        (two.sqrt() * two.sqrt() - two).abs() < 4.0 * f64::EPSILON,
+    ];
+    checks.iter().all(|&passed| passed)
+}
+
+/// Case-insensitive edit distance between `a` and `b`, or `None` if it is
+/// greater than `max`.
+///
+/// Insertion, deletion, substitution and transposition of adjacent
+/// characters cost one edit each.
+fn edit_distance(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let b: Vec<char> = b.chars().map(|c| c.to_ascii_lowercase()).collect();
+    if a.len().max(b.len()) - a.len().min(b.len()) > max {
+        return None;
+    }
+    let mut prev2 = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        cur[0] = i;
+        let mut row_min = cur[0];
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            cur[j] = (prev[j] + 1).min(cur[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                cur[j] = cur[j].min(prev2[j - 2] + 1);
+            }
+            row_min = row_min.min(cur[j]);
+        }
+        if row_min > max {
+            return None;
+        }
+        std::mem::swap(&mut prev2, &mut prev);
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    if prev[b.len()] <= max {
+        Some(prev[b.len()])
+    } else {
+        None
+    }
+}
+
+/// Case-insensitive match of `name` against `pattern`, where `*` matches
+/// any sequence of characters and `?` matches any one character.
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((&'*', rest)) => (0..=name.len()).any(|i| glob_match(rest, &name[i..])),
+        Some((&'?', rest)) => !name.is_empty() && glob_match(rest, &name[1..]),
+        Some((&c, rest)) => {
+            !name.is_empty() && name[0].eq_ignore_ascii_case(&c) && glob_match(rest, &name[1..])
+        }
+    }
+}
+
+/// Stack effects of the primitives of `Tools`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("words", "( -- )"),
+    ("effect", "( \"<spaces>name\" -- )"),
+    (".word", "( xt -- )"),
+    (".backtrace", "( -- )"),
+    (".s", "( -- )"),
+    (".memory", "( -- )"),
+    ("(xtime)", "( t0 xt -- )"),
+    (".xtime", "( -- )"),
+    ("0xtime", "( -- )"),
+    ("elapsed", "( xt -- ns )"),
+    ("benchmark", "( xt n -- min avg max )"),
+    (".input", "( -- )"),
+    ("flush-to-err", "( -- )"),
+    (".suggestions", "( -- )"),
+    ("apropos", "( \"substr\" -- )"),
+    ("words-like", "( \"pattern\" -- )"),
+    ("trace!", "( id -- )"),
+    ("0trace", "( -- )"),
+    ("errors.", "( -- )"),
+    (".deadlock", "( -- )"),
+    (".timers", "( -- )"),
+    ("post", "( -- mask )"),
+    ("cfg-export", "( \"name\" -- )"),
+    ("lint", "( -- n )"),
+    ("0errors", "( -- )"),
+    ("error-history", "( u -- )"),
+    ("memoize", "( n-in n-out \"name\" -- )"),
+    ("fmemoize", "( n-in n-out \"name\" -- )"),
+    ("forget-cache", "( -- )"),
+    (".memo", "( -- )"),
+    ("hash-stats.", "( -- )"),
+    ("rehash", "( -- )"),
+    ("dict-hash", "( -- u )"),
+    ("seal-dict", "( -- )"),
+    ("verify-dict", "( -- flag )"),
+    ("word-size", "( xt -- u )"),
+    (".dict", "( n -- )"),
+];
 
 pub trait Tools: Output {
     /// Add programming-tools primitives.
@@ -15,8 +753,95 @@ pub trait Tools: Output {
         self.add_primitive("(xtime)", Tools::set_execution_times);
         self.add_primitive(".xtime", Tools::dot_xtime);
         self.add_primitive("0xtime", Tools::clear_xtime);
+        self.add_primitive("elapsed", Tools::elapsed);
+        self.add_primitive("benchmark", Tools::benchmark);
         self.add_primitive(".input", Tools::dot_input);
         self.add_primitive("flush-to-err", Tools::flush_to_err);
+        self.add_primitive(".suggestions", Tools::dot_suggestions);
+        self.add_primitive("apropos", Tools::apropos);
+        self.add_primitive("words-like", Tools::words_like);
+        self.add_primitive("trace!", Tools::trace_store);
+        self.add_primitive("0trace", Tools::clear_trace);
+        self.add_primitive("errors.", Tools::dot_errors);
+        self.add_primitive(".deadlock", Tools::dot_deadlock);
+        self.add_primitive(".timers", Tools::dot_timers);
+        self.add_primitive("post", Tools::post);
+        self.add_primitive("cfg-export", Tools::cfg_export);
+        self.add_primitive("lint", Tools::p_lint);
+        self.add_primitive("0errors", Tools::clear_errors);
+        self.add_primitive("error-history", Tools::error_history);
+        self.add_primitive("memoize", Tools::memoize);
+        self.add_primitive("fmemoize", Tools::fmemoize);
+        self.add_primitive("forget-cache", Tools::forget_cache);
+        self.add_primitive(".memo", Tools::dot_memo);
+        self.add_primitive("hash-stats.", Tools::dot_hash_stats);
+        self.add_primitive("rehash", Tools::p_rehash);
+        self.add_primitive("dict-hash", Tools::dict_hash);
+        self.add_primitive("seal-dict", Tools::seal_dict);
+        self.add_primitive("verify-dict", Tools::verify_dict);
+        self.add_primitive("word-size", Tools::p_word_size);
+        self.add_primitive(".dict", Tools::dot_dict);
+        self.add_primitive("effect", Tools::effect);
+        self.declare_effects(STACK_EFFECTS);
+    }
+
+    /// Execution tokens and names of visible words, the latest defined
+    /// first. Redefined names are listed only once.
+    fn visible_words(&mut self) -> Vec<(usize, String)> {
+        let mut result: Vec<(usize, String)> = Vec::new();
+        for w in (1..self.wordlist().len()).rev() {
+            if !self.wordlist()[w].is_hidden() {
+                let nfa = self.wordlist()[w].nfa();
+                let name = unsafe { self.data_space().get_str(nfa) };
+                if !name.is_empty() && !result.iter().any(|(_, n)| n.eq_ignore_ascii_case(name)) {
+                    result.push((w, name.to_string()));
+                }
+            }
+        }
+        result
+    }
+
+    /// Names of words similar to `token`, the most similar first.
+    ///
+    /// Words within a small edit distance or starting with `token` are
+    /// similar.
+    fn suggestions(&mut self, token: &str) -> Vec<String> {
+        let max = if token.chars().count() <= 3 { 1 } else { 2 };
+        let lower = token.to_ascii_lowercase();
+        let mut candidates: Vec<(usize, String)> = Vec::new();
+        for (_, name) in self.visible_words() {
+            let score = match edit_distance(token, &name, max) {
+                Some(d) => d,
+                None if lower.len() >= 2 && name.to_ascii_lowercase().starts_with(&lower) => {
+                    max + 1
+                }
+                None => continue,
+            };
+            candidates.push((score, name));
+        }
+        candidates.sort();
+        candidates
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, name)| name)
+            .collect()
+    }
+
+    /// Run-time: ( -- )
+    ///
+    /// Print names of words similar to the last token, used to report
+    /// undefined words.
+    fn dot_suggestions(&mut self) {
+        let token = match self.last_token().as_ref() {
+            Some(t) if !t.is_empty() => t.clone(),
+            _ => return,
+        };
+        let suggestions = self.suggestions(&token);
+        if !suggestions.is_empty() {
+            if let Some(buf) = self.output_buffer().as_mut() {
+                write!(buf, ", did you mean: {}", suggestions.join(" ")).unwrap();
+            }
+        }
     }
 
     /// Run-time: ( -- )
@@ -42,7 +867,8 @@ pub trait Tools: Output {
 
     /// Run-time: ( -- )
     ///
-    /// List definition names in word list.
+    /// List definition names in word list, each followed by its stack
+    /// effect if declared.
     fn words(&mut self) {
         if let Some(mut buf) = self.output_buffer().take() {
             for w in (1..self.wordlist().len()).rev() {
@@ -50,19 +876,106 @@ pub trait Tools: Output {
                     let nfa = self.wordlist()[w].nfa();
                     let name = unsafe { self.data_space().get_str(nfa) };
                     write!(buf, "{} ", name).unwrap();
+                    if let Some(effect) = self.word_effect(w) {
+                        write!(buf, "{} ", effect).unwrap();
+                    }
                 }
             }
             self.set_output_buffer(buf);
         }
     }
 
+    /// Print names of visible words for which `f` returns true, with their
+    /// stack effects if declared.
+    fn list_words(&mut self, f: &dyn Fn(&str) -> bool) {
+        let words: Vec<(String, Option<String>)> = self
+            .visible_words()
+            .into_iter()
+            .filter(|(_, name)| f(name))
+            .map(|(xt, name)| (name, self.word_effect(xt)))
+            .collect();
+        if let Some(buf) = self.output_buffer().as_mut() {
+            for (name, effect) in words {
+                write!(buf, "{} ", name).unwrap();
+                if let Some(effect) = effect {
+                    write!(buf, "{} ", effect).unwrap();
+                }
+            }
+        }
+    }
+
+    /// Run-time: ( "substr" -- )
+    ///
+    /// List words whose names contain `substr`, case-insensitive.
+    ///
+    /// Only names are searched, not the stack effects shown.
+    fn apropos(&mut self) {
+        self.parse_word();
+        let substr = match self.last_token().as_ref() {
+            Some(t) if !t.is_empty() => t.to_ascii_lowercase(),
+            _ => return self.abort_with(UNEXPECTED_END_OF_FILE),
+        };
+        self.list_words(&|name| name.to_ascii_lowercase().contains(&substr));
+    }
+
+    /// Run-time: ( "pattern" -- )
+    ///
+    /// List words whose names match `pattern` case-insensitively, where `*`
+    /// matches any sequence of characters and `?` matches any one
+    /// character.
+    ///
+    /// Example:
+    /// ```forth
+    /// words-like f*
+    /// words-like ?dup
+    /// ```
+    fn words_like(&mut self) {
+        self.parse_word();
+        let pattern: Vec<char> = match self.last_token().as_ref() {
+            Some(t) if !t.is_empty() => t.chars().collect(),
+            _ => return self.abort_with(UNEXPECTED_END_OF_FILE),
+        };
+        self.list_words(&|name| {
+            let name: Vec<char> = name.chars().collect();
+            glob_match(&pattern, &name)
+        });
+    }
+
+    /// Run-time: ( "<spaces>name" -- )
+    ///
+    /// Print the stack effect declared for word `name`, `( ? )` if none.
+    ///
+    /// Example:
+    /// ```forth
+    /// : sq ( n -- n*n ) dup * ;
+    /// effect sq
+    /// ```
+    fn effect(&mut self) {
+        self.parse_word();
+        let name = self.last_token().take().expect("token");
+        if name.is_empty() {
+            self.set_last_token(name);
+            return self.abort_with(UNEXPECTED_END_OF_FILE);
+        }
+        let found = self.find(&name);
+        self.set_last_token(name);
+        let xt = match found {
+            Some(xt) => xt,
+            None => return self.abort_with(UNDEFINED_WORD),
+        };
+        let effect = self.word_effect(xt);
+        if let Some(buf) = self.output_buffer().as_mut() {
+            write!(buf, "{} ", effect.as_ref().map_or("( ? )", |e| e)).unwrap();
+        }
+    }
+
     /// Run-time: ( xt -- )
     ///
     /// Print name of execution token xt.
     fn dot_word(&mut self) {
         if let Some(mut buf) = self.output_buffer().take() {
-            let xt = self.s_stack().pop() as usize;
-            if xt < self.wordlist().len() {
+            let xt = self.s_stack().pop();
+            if let Some(xt) = self.wordlist().untag_xt(xt) {
                 let nfa = self.wordlist()[xt].nfa();
                 let name = unsafe { self.data_space().get_str(nfa) };
                 write!(buf, "{} ", name).unwrap();
@@ -151,8 +1064,12 @@ pub trait Tools: Output {
     /// ```
     fn set_execution_times(&mut self) {
         let (t0, xt) = self.s_stack().pop2();
-        let t = (self.system_time_ns() / 1_000) as usize - t0 as usize;
-        let word = &mut self.wordlist_mut()[xt as usize];
+        let xt = match self.wordlist().untag_xt(xt) {
+            Some(xt) => xt,
+            None => return self.abort_with(INVALID_XT),
+        };
+        let t = (self.monotonic_ns() / 1_000) as usize - t0 as usize;
+        let word = &mut self.wordlist_mut()[xt];
         if word.min_execution_time != 0 {
             word.min_execution_time = word.min_execution_time.min(t);
         } else {
@@ -161,6 +1078,65 @@ pub trait Tools: Output {
         word.max_execution_time = word.max_execution_time.max(t);
     }
 
+    /// Execute `xt` and return its execution time. `elapsed ( xt -- ns )`
+    ///
+    /// Example:
+    /// ```forth
+    /// ' words elapsed .
+    /// ```
+    fn elapsed(&mut self) {
+        let xt = self.s_stack().pop();
+        let xt = match self.wordlist().untag_xt(xt) {
+            Some(xt) => xt,
+            None => return self.abort_with(INVALID_XT),
+        };
+        let t0 = self.monotonic_ns();
+        self.execute_xt(xt);
+        if self.last_error().is_none() {
+            let t = self.monotonic_ns() - t0;
+            self.s_stack().push(t as isize);
+        }
+    }
+
+    /// Execute `xt` `n` times and return the minimum, average and maximum
+    /// execution times. `benchmark ( xt n -- min avg max )`
+    ///
+    /// Unit of the results is nanoseconds.
+    ///
+    /// Example:
+    /// ```forth
+    /// : 1k   1000 0 do loop ;
+    /// ' 1k 100 benchmark . . .
+    /// ```
+    fn benchmark(&mut self) {
+        let (xt, n) = self.s_stack().pop2();
+        if n <= 0 {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+            return;
+        }
+        let xt = match self.wordlist().untag_xt(xt) {
+            Some(xt) => xt,
+            None => return self.abort_with(INVALID_XT),
+        };
+        let mut min = u64::MAX;
+        let mut max = 0;
+        let mut total = 0;
+        for _ in 0..n {
+            let t0 = self.monotonic_ns();
+            self.execute_xt(xt);
+            if self.last_error().is_some() {
+                return;
+            }
+            let t = self.monotonic_ns() - t0;
+            min = min.min(t);
+            max = max.max(t);
+            total += t;
+        }
+        self.s_stack().push(min as isize);
+        self.s_stack().push((total / n as u64) as isize);
+        self.s_stack().push(max as isize);
+    }
+
     /// Display measured execution time. `.xtime ( -- )`
     fn dot_xtime(&mut self) {
         if let Some(mut buf) = self.output_buffer().take() {
@@ -192,6 +1168,564 @@ pub trait Tools: Output {
         }
     }
 
+    /// Record event `id` of the current task in the trace ring.
+    /// `trace! ( id -- )`
+    ///
+    /// Only the latest `TRACE_CAPACITY` events are kept. See
+    /// `trace-export`.
+    fn trace_store(&mut self) {
+        let id = self.s_stack().pop();
+        let event = TraceEvent {
+            timestamp: self.monotonic_ns(),
+            task: self.current_task(),
+            id,
+        };
+        self.wordlist_mut().trace_mut().record(event);
+    }
+
+    /// Clear the trace ring. `0trace ( -- )`
+    fn clear_trace(&mut self) {
+        self.wordlist_mut().trace_mut().clear();
+    }
+
+    /// Print the control flow graph of colon definition `name` in Graphviz
+    /// dot format. `cfg-export ( "name" -- )`
+    ///
+    /// Aborts with `UNDEFINED_WORD` if `name` is not defined, and with
+    /// `INVALID_NUMERIC_ARGUMENT` if it is not a colon definition.
+    fn cfg_export(&mut self) {
+        self.parse_word();
+        let name = self.last_token().clone().unwrap_or_default();
+        if name.is_empty() {
+            self.abort_with(UNEXPECTED_END_OF_FILE);
+            return;
+        }
+        let xt = match self.find(&name) {
+            Some(xt) => xt,
+            None => {
+                self.abort_with(UNDEFINED_WORD);
+                return;
+            }
+        };
+        match self.decode(xt) {
+            Some(code) => {
+                let dot = ControlFlowGraph::new(&name, &code).to_dot();
+                if let Some(ref mut buf) = *self.output_buffer() {
+                    buf.push_str(&dot);
+                }
+            }
+            None => self.abort_with(INVALID_NUMERIC_ARGUMENT),
+        }
+    }
+
+    /// Check all colon definitions for unresolved labels, branches into
+    /// other definitions, `exit` inside do-loops without `unloop`, and
+    /// unreachable code.
+    fn lint(&mut self) -> Vec<LintWarning> {
+        let mut warnings = Vec::new();
+        for xt in 1..self.wordlist().len() {
+            if let Some(code) = self.decode(xt) {
+                let position = self.wordlist()[xt].position();
+                for (kind, addr) in lint_code(&code) {
+                    warnings.push(LintWarning {
+                        kind,
+                        xt,
+                        addr,
+                        position,
+                    });
+                }
+            }
+        }
+        warnings
+    }
+
+    /// Print the findings of `Tools::lint`, one per line, and return their
+    /// number. `lint ( -- n )`
+    ///
+    /// ```text
+    /// /path/to/file.fs:12: foo: exit inside do-loop without unloop
+    /// ```
+    fn p_lint(&mut self) {
+        let warnings = self.lint();
+        let mut report = String::new();
+        for w in &warnings {
+            let name = self.word_name(w.xt);
+            match self.wordlist().source_file(w.position.file) {
+                Some(path) => write!(report, "{}:{}: ", path, w.position.line).unwrap(),
+                None => report.push_str("-: "),
+            }
+            writeln!(report, "{}: {}", name, w.kind.description()).unwrap();
+        }
+        self.push_output(&report);
+        self.s_stack().push(warnings.len() as isize);
+    }
+
+    /// Power-on self test. `post ( -- mask )`
+    ///
+    /// Check the invariants the kernel relies on, and return the bitwise
+    /// or of the checks failed, 0 if all passed:
+    ///
+    /// * `POST_DICTIONARY`: every word is reachable through the hash
+    ///   buckets.
+    /// * `POST_DATA_SPACE`: `here` is within data space and addresses
+    ///   outside it are rejected.
+    /// * `POST_STACKS`: no stack of the current task is beyond its
+    ///   bounds, and underflows and overflows are detected.
+    /// * `POST_FLOAT`: floating-point arithmetic and rounding follow
+    ///   IEEE 754 double precision.
+    /// * `POST_TIMER`: system time does not go backwards.
+    ///
+    /// Meant to be run at boot before user code is loaded.
+    fn post(&mut self) {
+        let mut failures = 0;
+        if !self.dictionary_intact() {
+            failures |= POST_DICTIONARY;
+        }
+        if !self.post_data_space() {
+            failures |= POST_DATA_SPACE;
+        }
+        if !self.post_stacks() {
+            failures |= POST_STACKS;
+        }
+        if !post_float() {
+            failures |= POST_FLOAT;
+        }
+        let t0 = self.monotonic_ns();
+        let t1 = self.monotonic_ns();
+        let t2 = self.monotonic_ns();
+        if t1 < t0 || t2 < t1 {
+            failures |= POST_TIMER;
+        }
+        self.s_stack().push(failures);
+    }
+
+    fn post_data_space(&mut self) -> bool {
+        let ds = self.data_space();
+        let (start, here, limit) = (ds.start(), ds.here(), ds.limit());
+        let valid = start < limit
+            && start <= here
+            && here <= limit
+            && ds.has(start)
+            && !ds.has(limit)
+            && ds.set_here(limit + 1).is_err()
+            && ds.set_here(start.wrapping_sub(1)).is_err()
+            && ds.here() == here
+            && ds.set_here(limit).is_ok();
+        let _ = self.data_space().set_here(here);
+        valid && self.data_space().here() == here
+    }
+
+    fn post_stacks(&mut self) -> bool {
+        let live = !self.s_stack().underflow()
+            && !self.s_stack().overflow()
+            && !self.r_stack().underflow()
+            && !self.r_stack().overflow()
+            && !self.c_stack().underflow()
+            && !self.c_stack().overflow()
+            && !self.f_stack().underflow()
+            && !self.f_stack().overflow();
+        let mut stack = Stack::new(0isize);
+        stack.pop();
+        let underflow = stack.underflow() && stack.watermarks() == (-1, 0);
+        stack.reset();
+        for i in 0..=stack.limit() {
+            stack.push(i as isize);
+        }
+        let overflow = stack.overflow() && !stack.underflow();
+        stack.reset();
+        live && underflow && overflow && !stack.underflow() && !stack.overflow()
+    }
+
+    /// Print the tasks waiting for semaphores when the last deadlock was
+    /// detected, one per line. `.deadlock ( -- )`
+    fn dot_deadlock(&mut self) {
+        if let Some(mut buf) = self.output_buffer().take() {
+            for w in self.wordlist().deadlock() {
+                write!(
+                    buf,
+                    "task {} waits for semaphore {}",
+                    w.task + 1,
+                    w.semaphore
+                )
+                .unwrap();
+                match w.holder {
+                    Some(holder) => writeln!(buf, " held by task {}", holder + 1).unwrap(),
+                    None => writeln!(buf).unwrap(),
+                }
+            }
+            self.set_output_buffer(buf);
+        }
+    }
+
+    /// Print the pending timers set by `after`, the earliest first, then
+    /// the number of timers dispatched late and the latest lateness in
+    /// microseconds. `.timers ( -- )`
+    fn dot_timers(&mut self) {
+        let now = self.monotonic_ns();
+        if let Some(mut buf) = self.output_buffer().take() {
+            for t in self.wordlist().timers() {
+                let nfa = self.wordlist()[t.xt].nfa();
+                let name = unsafe { self.data_space_const().get_str(nfa) };
+                writeln!(
+                    buf,
+                    "timer {} {} in {} us",
+                    t.id,
+                    name,
+                    t.deadline.saturating_sub(now) / 1_000
+                )
+                .unwrap();
+            }
+            writeln!(
+                buf,
+                "overdue {} lateness {} us",
+                self.wordlist().timers_overdue(),
+                self.wordlist().timer_lateness() / 1_000
+            )
+            .unwrap();
+            self.set_output_buffer(buf);
+        }
+    }
+
+    /// Print the latest errors, the oldest first. `errors. ( -- )`
+    ///
+    /// Each line shows the UTC time of day, the task, the exception,
+    /// the word being executed, the last token and the source position
+    /// `source-id:offset`.
+    fn dot_errors(&mut self) {
+        if let Some(mut buf) = self.output_buffer().take() {
+            for r in self.wordlist().errors().iter() {
+                writeln!(
+                    buf,
+                    "{:02}:{:02}:{:02}.{:03} task {} {} {} in {} at {} {}:{}",
+                    r.timestamp / 3_600_000_000_000 % 24,
+                    r.timestamp / 60_000_000_000 % 60,
+                    r.timestamp / 1_000_000_000 % 60,
+                    r.timestamp / 1_000_000 % 1_000,
+                    r.task + 1,
+                    isize::from(r.error),
+                    self.wordlist().exception_description(r.error),
+                    r.word,
+                    r.token,
+                    r.source_id,
+                    r.source_index
+                )
+                .unwrap();
+            }
+            self.set_output_buffer(buf);
+        }
+    }
+
+    /// Clear the error history. `0errors ( -- )`
+    fn clear_errors(&mut self) {
+        self.wordlist_mut().errors_mut().clear();
+    }
+
+    /// Keep the latest `u` errors in the error history, 0 to disable it.
+    /// `error-history ( u -- )`
+    fn error_history(&mut self) {
+        let u = self.s_stack().pop();
+        if u < 0 {
+            self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        } else {
+            self.wordlist_mut().errors_mut().set_capacity(u as usize);
+        }
+    }
+
+    /// Cache the results of word `name` by its `n-in` topmost cells, which
+    /// it replaces with `n-out` cells. `memoize ( n-in n-out "name" -- )`
+    ///
+    /// `name` must be pure, its results depending only on its inputs.
+    /// Results are not cached if the depth of the stack does not change as
+    /// declared.
+    ///
+    /// Example:
+    /// ```forth
+    /// : hypot ( x y -- n )  dup * swap dup * + ;
+    /// 2 1 memoize hypot
+    /// ```
+    fn memoize(&mut self) {
+        self.memoize_word(false);
+    }
+
+    /// Like `memoize`, with inputs and outputs on the floating-point stack.
+    /// `fmemoize ( n-in n-out "name" -- )`
+    fn fmemoize(&mut self) {
+        self.memoize_word(true);
+    }
+
+    fn memoize_word(&mut self, float: bool) {
+        let (inputs, outputs) = self.s_stack().pop2();
+        self.parse_word();
+        let name = self.last_token().clone().unwrap_or_default();
+        if name.is_empty() {
+            return self.abort_with(UNEXPECTED_END_OF_FILE);
+        }
+        let xt = match self.find(&name) {
+            Some(xt) => xt,
+            None => return self.abort_with(UNDEFINED_WORD),
+        };
+        let max = i8::MAX as isize;
+        if inputs < 0 || inputs > max || outputs < 0 || outputs > max {
+            return self.abort_with(INVALID_NUMERIC_ARGUMENT);
+        }
+        let action = match self.wordlist().memos().iter().find(|m| m.xt == xt) {
+            Some(memo) => memo.action,
+            None => self.wordlist()[xt].action(),
+        };
+        // A colon definition compiled by the JIT would have its action
+        // replaced upon the next call.
+        #[cfg(feature = "jit")]
+        let action = if jit::is_colon_action::<Self>(action) {
+            self.wordlist_mut()[xt].native = None;
+            Self::nest
+        } else {
+            action
+        };
+        let memos = self.wordlist_mut().memos_mut();
+        memos.retain(|m| m.xt != xt);
+        memos.push(Memo {
+            xt,
+            float,
+            inputs: inputs as usize,
+            outputs: outputs as usize,
+            action,
+            cache: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        });
+        self.wordlist_mut()[xt].set_action(Tools::p_memoized);
+    }
+
+    /// Run-time of memoized words.
+    fn p_memoized(&mut self) {
+        let xt = self.state().word_pointer();
+        let i = match self.wordlist().memos().iter().position(|m| m.xt == xt) {
+            Some(i) => i,
+            None => return self.abort_with(UNSUPPORTED_OPERATION),
+        };
+        let (float, inputs, outputs, action) = {
+            let memo = &self.wordlist().memos()[i];
+            (memo.float, memo.inputs, memo.outputs, memo.action)
+        };
+        let key = match self.memo_items(float, inputs) {
+            Some(key) => key,
+            None if float => return self.abort_with(FLOATING_POINT_STACK_UNDERFLOW),
+            None => return self.abort_with(STACK_UNDERFLOW),
+        };
+        let cached = self.wordlist().memos()[i].cache.get(&key).cloned();
+        if let Some(results) = cached {
+            self.wordlist_mut().memos_mut()[i].hits += 1;
+            if float {
+                let len = self.f_stack().len() - inputs as u8;
+                self.f_stack().set_len(len);
+                for &r in &results {
+                    self.f_stack().push(f64::from_bits(r));
+                }
+            } else {
+                let len = self.s_stack().len() - inputs as u8;
+                self.s_stack().set_len(len);
+                for &r in &results {
+                    self.s_stack().push(r as isize);
+                }
+            }
+            return;
+        }
+        self.wordlist_mut().memos_mut()[i].misses += 1;
+        let depth = self.memo_depth(float) - inputs;
+        let ip = self.state().instruction_pointer;
+        self.state().instruction_pointer = 0;
+        action(self);
+        self.run();
+        if self.last_error().is_some() {
+            return;
+        }
+        self.state().instruction_pointer = ip;
+        if self.memo_depth(float) != depth + outputs {
+            return;
+        }
+        if let Some(results) = self.memo_items(float, outputs) {
+            let memo = &mut self.wordlist_mut().memos_mut()[i];
+            if memo.cache.len() >= MEMO_CAPACITY {
+                memo.cache.clear();
+            }
+            memo.cache.insert(key, results);
+        }
+    }
+
+    /// Number of items on the stack used by memoized words.
+    fn memo_depth(&mut self, float: bool) -> usize {
+        if float {
+            self.f_stack().as_slice().len()
+        } else {
+            self.s_stack().as_slice().len()
+        }
+    }
+
+    /// Bit patterns of the `n` topmost items on the stack used by memoized
+    /// words, `None` if there are less items.
+    fn memo_items(&mut self, float: bool, n: usize) -> Option<Vec<u64>> {
+        if float {
+            let items = self.f_stack().as_slice();
+            if items.len() < n {
+                return None;
+            }
+            Some(
+                items[items.len() - n..]
+                    .iter()
+                    .map(|f| f.to_bits())
+                    .collect(),
+            )
+        } else {
+            let items = self.s_stack().as_slice();
+            if items.len() < n {
+                return None;
+            }
+            Some(items[items.len() - n..].iter().map(|&v| v as u64).collect())
+        }
+    }
+
+    /// Empty the caches of all memoized words and clear their statistics.
+    /// `forget-cache ( -- )`
+    fn forget_cache(&mut self) {
+        for memo in self.wordlist_mut().memos_mut() {
+            memo.clear();
+        }
+    }
+
+    /// Print hits, misses and number of cached results of memoized words.
+    /// `.memo ( -- )`
+    fn dot_memo(&mut self) {
+        if let Some(mut buf) = self.output_buffer().take() {
+            for memo in self.wordlist().memos() {
+                let nfa = self.wordlist()[memo.xt].nfa();
+                let name = unsafe { self.data_space_const().get_str(nfa) };
+                writeln!(
+                    buf,
+                    "{} hits {} misses {} cached {}",
+                    name,
+                    memo.hits,
+                    memo.misses,
+                    memo.len()
+                )
+                .unwrap();
+            }
+            self.set_output_buffer(buf);
+        }
+    }
+
+    /// Print the number of words, of hash buckets, of buckets in use, and
+    /// the longest and the average length of the chains of used buckets.
+    /// `hash-stats. ( -- )`
+    fn dot_hash_stats(&mut self) {
+        let lengths = self.wordlist().bucket_lengths();
+        let used = lengths.iter().filter(|&&len| len > 0).count();
+        let linked: usize = lengths.iter().sum();
+        let longest = lengths.iter().cloned().max().unwrap_or(0);
+        let average = if used > 0 {
+            linked as f64 / used as f64
+        } else {
+            0.0
+        };
+        let words = self.wordlist().len() - 1;
+        if let Some(mut buf) = self.output_buffer().take() {
+            writeln!(
+                buf,
+                "words {} buckets {} used {} longest {} average {:.2}",
+                words,
+                lengths.len(),
+                used,
+                longest,
+                average
+            )
+            .unwrap();
+            self.set_output_buffer(buf);
+        }
+    }
+
+    /// Rebuild the hash buckets of the dictionary from the names of all
+    /// words. `rehash ( -- )`
+    ///
+    /// Use it when `post` reports `POST_DICTIONARY`, for example after
+    /// markers have been executed out of order in a long session.
+    fn p_rehash(&mut self) {
+        self.rehash();
+    }
+
+    /// Hash of the names and bodies of the visible words, see
+    /// `dictionary_digest`. `dict-hash ( -- u )`
+    ///
+    /// Values of variables are not covered, those of constants are.
+    fn dict_hash(&mut self) {
+        let hash = dictionary_hash(self);
+        self.s_stack().push(hash as isize);
+    }
+
+    /// Record the hash of the dictionary for `verify-dict`.
+    /// `seal-dict ( -- )`
+    ///
+    /// Meant to be run once the application is loaded.
+    fn seal_dict(&mut self) {
+        let hash = dictionary_hash(self);
+        self.wordlist_mut().set_dict_seal(Some(hash));
+    }
+
+    /// Hash the dictionary again and compare with the hash recorded by
+    /// `seal-dict`. `verify-dict ( -- flag )`
+    ///
+    /// `flag` is false if words have been defined, forgotten or modified
+    /// since, or if the dictionary has never been sealed. Run it before
+    /// starting the machine cycle to detect corrupted or tampered code.
+    fn verify_dict(&mut self) {
+        let hash = dictionary_hash(self);
+        let intact = self.wordlist().dict_seal() == Some(hash);
+        self.s_stack().push(if intact { -1 } else { 0 });
+    }
+
+    /// Bytes occupied by word `xt`: its header, and its name and body in
+    /// data space up to the start of the next word, or `here` for the last
+    /// word. Shared names of primitives are not counted.
+    fn word_size(&mut self, xt: usize) -> usize {
+        let start = self.wordlist()[xt].start();
+        let end = if xt + 1 < self.wordlist().len() {
+            self.wordlist()[xt + 1].start()
+        } else {
+            self.data_space().here()
+        };
+        mem::size_of::<Word<Self>>() + end.saturating_sub(start)
+    }
+
+    /// Bytes occupied by word `xt`, see `word_size`. `word-size ( xt -- u )`
+    fn p_word_size(&mut self) {
+        let xt = self.s_stack().pop();
+        match self.wordlist().untag_xt(xt) {
+            Some(xt) if xt != 0 => {
+                let size = self.word_size(xt);
+                self.s_stack().push(size as isize);
+            }
+            _ => self.abort_with(INVALID_NUMERIC_ARGUMENT),
+        }
+    }
+
+    /// Print the `n` largest words, one per line with size and name, and
+    /// the number of words and the bytes they occupy. `.dict ( n -- )`
+    fn dot_dict(&mut self) {
+        let n = self.s_stack().pop().max(0) as usize;
+        let mut sizes: Vec<(usize, usize)> = (1..self.wordlist().len())
+            .map(|xt| (self.word_size(xt), xt))
+            .collect();
+        let total: usize = sizes.iter().map(|&(size, _)| size).sum();
+        sizes.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        if let Some(mut buf) = self.output_buffer().take() {
+            for &(size, xt) in sizes.iter().take(n) {
+                let nfa = self.wordlist()[xt].nfa();
+                let name = unsafe { self.data_space().get_str(nfa) };
+                writeln!(buf, "{} {}", size, name).unwrap();
+            }
+            writeln!(buf, "words {} total {}", sizes.len(), total).unwrap();
+            self.set_output_buffer(buf);
+        }
+    }
+
     /// Print content of the input buffer. `.input ( -- )`
     fn dot_input(&mut self) {
         match self.input_buffer().take() {
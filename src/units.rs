@@ -8,6 +8,26 @@ use uom::si::time::{hour, microsecond, millisecond, minute, second};
 
 const RPM: f64 = 2.0 * PI / 60.0;
 
+/// Stack effects of the primitives of `Units`, see `Core::declare_effects`.
+const STACK_EFFECTS: &[(&str, &str)] = &[
+    ("meter", "( F: r1 -- r2 )"),
+    ("mm", "( F: r1 -- r2 )"),
+    ("um", "( F: r1 -- r2 )"),
+    ("deg", "( F: r1 -- r2 )"),
+    ("rad", "( F: r1 -- r2 )"),
+    ("hr", "( F: r1 -- r2 )"),
+    ("minute", "( F: r1 -- r2 )"),
+    ("sec", "( F: r1 -- r2 )"),
+    ("msec", "( F: r1 -- r2 )"),
+    ("usec", "( F: r1 -- r2 )"),
+    ("mm/min", "( F: r1 -- r2 )"),
+    ("mm/sec", "( F: r1 -- r2 )"),
+    ("um/msec", "( F: r1 -- r2 )"),
+    ("rpm", "( F: r1 -- r2 )"),
+    ("hz", "( F: r1 -- r2 )"),
+    ("1/sec", "( F: r1 -- r2 )"),
+];
+
 pub trait Units: Core {
     fn add_units(&mut self) {
         self.add_primitive("meter", Units::from_meter);
@@ -30,6 +50,7 @@ pub trait Units: Core {
         self.add_primitive("rpm", Units::rpm);
         self.add_primitive("hz", Units::hertz);
         self.add_primitive("1/sec", Units::hertz);
+        self.declare_effects(STACK_EFFECTS);
     }
 
     fn from_meter(&mut self) {